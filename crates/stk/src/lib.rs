@@ -0,0 +1,26 @@
+//! the whole simulator behind one dependency.
+//!
+//! each device crate is re-exported as a module, and [`prelude`] collects the types most
+//! programs need. paths through this crate are kept stable across releases, while the layout
+//! of the crates underneath may change.
+
+#[doc(inline)]
+pub use stk_dc_motor_vm as dc_motor;
+#[doc(inline)]
+pub use stk_diag as diag;
+#[doc(inline)]
+pub use stk_hd44780_vm as hd44780;
+#[doc(inline)]
+pub use stk_led_matrix_vm as led_matrix;
+#[doc(inline)]
+pub use stk_pic_vm as pic;
+
+pub mod prelude {
+    //! `use stk::prelude::*;`
+
+    pub use stk_dc_motor_vm::DcMotor;
+    pub use stk_diag::{Diagnostic, DiagnosticSink, Severity};
+    pub use stk_hd44780_vm::{Hd44780, PinObserver};
+    pub use stk_led_matrix_vm::LedMatrix;
+    pub use stk_pic_vm::prelude::*;
+}
@@ -0,0 +1,289 @@
+//! 24LCxx serial EEPROM on an I2C bus
+//!
+//! a write sends the word address, one byte of it on the small parts and two from the 24LC32
+//! up, then data that goes into a page buffer. the address wraps inside the page, so writing past
+//! its end overwrites the start of it. the STOP after a whole byte starts the write cycle, which
+//! takes [`WRITE_CYCLE`] and only then changes the memory. the chip doesn't acknowledge its
+//! address until the cycle is over, which firmware polls for. a read returns bytes from the
+//! address on, wrapping at the end of the memory. WP high makes writes do nothing, though the
+//! chip still acknowledges them.
+//!
+//! the parts with one address byte and more than 256 bytes take the upper bits of the address
+//! from the block select bits of the control byte and ignore A2..A0. the others answer only to
+//! the control byte with A2..A0 as wired.
+//!
+//! it goes on an [`I2cBus`](stk_i2c_vm::I2cBus) as an [`I2cDevice`]. a STOP in the middle of a
+//! byte can't be told from one after it there, so it starts the write cycle all the same.
+
+use std::time::Duration;
+
+use stk_i2c_vm::I2cDevice;
+
+/// the control code, the upper four bits of the 7-bit address
+pub const CONTROL_CODE: u8 = 0x50;
+/// max. time a write takes
+pub const WRITE_CYCLE: Duration = Duration::from_millis(5);
+
+/// size and page size of a part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part {
+    pub bytes: usize,
+    pub page: usize,
+}
+
+impl Part {
+    pub const LC01: Part = Part { bytes: 128, page: 8 };
+    pub const LC02: Part = Part { bytes: 256, page: 8 };
+    pub const LC04: Part = Part { bytes: 512, page: 16 };
+    pub const LC08: Part = Part { bytes: 1024, page: 16 };
+    pub const LC16: Part = Part { bytes: 2048, page: 16 };
+    pub const LC32: Part = Part { bytes: 4096, page: 32 };
+    pub const LC64: Part = Part { bytes: 8192, page: 32 };
+    pub const LC128: Part = Part { bytes: 16384, page: 64 };
+    pub const LC256: Part = Part { bytes: 32768, page: 64 };
+    pub const LC512: Part = Part { bytes: 65536, page: 128 };
+
+    /// bytes of word address a write starts with
+    fn address_bytes(self) -> u8 {
+        if self.bytes > 2048 {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Eeprom {
+    part: Part,
+    memory: Vec<u8>,
+    /// A2..A0
+    chip_select: u8,
+    write_protect: bool,
+    pointer: usize,
+    /// bytes of the word address yet to come in this write
+    address_left: u8,
+    /// the page being written and the bytes loaded into it
+    page_base: usize,
+    page: Vec<Option<u8>>,
+    /// left of the write cycle
+    writing: Duration,
+}
+
+impl Eeprom {
+    /// erased, with A2..A0 tied low
+    pub fn new(part: Part) -> Self {
+        Self {
+            part,
+            memory: vec![0xff; part.bytes],
+            chip_select: 0,
+            write_protect: false,
+            pointer: 0,
+            address_left: 0,
+            page_base: 0,
+            page: vec![None; part.page],
+            writing: Duration::ZERO,
+        }
+    }
+
+    /// the levels A2..A0 are wired to, in bits 2..0
+    pub fn chip_select(mut self, a: u8) -> Self {
+        self.chip_select = a & 7;
+        self
+    }
+
+    pub fn part(&self) -> Part {
+        self.part
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// for loading the memory as if programmed beforehand
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// the level of WP
+    pub fn set_write_protect(&mut self, wp: bool) {
+        self.write_protect = wp;
+    }
+
+    /// whether a write cycle is going on
+    pub fn busy(&self) -> bool {
+        !self.writing.is_zero()
+    }
+
+    /// lets the write cycle go on
+    pub fn advance(&mut self, elapsed: Duration) {
+        if !self.busy() {
+            return;
+        }
+        self.writing = self.writing.saturating_sub(elapsed);
+        if self.writing.is_zero() {
+            for (i, byte) in self.page.iter_mut().enumerate() {
+                if let Some(byte) = byte.take() {
+                    self.memory[self.page_base + i] = byte;
+                }
+            }
+        }
+    }
+
+    /// whether the 7-bit `address` is this chip's
+    fn addressed(&self, address: u8) -> bool {
+        if address & 0x78 != CONTROL_CODE {
+            return false;
+        }
+        let blocks = self.part.address_bytes() == 1 && self.part.bytes > 256;
+        blocks || address & 7 == self.chip_select
+    }
+
+    /// a byte written after the control byte
+    fn receive(&mut self, byte: u8) {
+        if self.address_left > 0 {
+            self.address_left -= 1;
+            let pointer = match self.part.address_bytes() {
+                // the block select bits stay
+                1 => self.pointer & !0xff | byte as usize,
+                _ => (self.pointer << 8 | byte as usize) & 0xffff,
+            };
+            self.pointer = pointer % self.part.bytes;
+            self.page_base = self.pointer / self.part.page * self.part.page;
+            return;
+        }
+        let offset = self.pointer - self.page_base;
+        self.page[offset] = Some(byte);
+        self.pointer = self.page_base + (offset + 1) % self.part.page;
+    }
+}
+
+impl I2cDevice for Eeprom {
+    /// a write not ended by a STOP doesn't happen
+    fn start(&mut self) {
+        if !self.busy() {
+            self.page.fill(None);
+        }
+        self.address_left = 0;
+    }
+
+    fn stop(&mut self) {
+        let loaded = self.page.iter().any(Option::is_some);
+        if loaded && self.address_left == 0 && !self.write_protect {
+            self.writing = WRITE_CYCLE;
+        } else {
+            self.page.fill(None);
+        }
+    }
+
+    fn select(&mut self, address: u8, read: bool) -> bool {
+        if self.busy() || !self.addressed(address) {
+            return false;
+        }
+        if !read {
+            self.address_left = self.part.address_bytes();
+            if self.part.address_bytes() == 1 {
+                self.pointer = (address as usize & 7) << 8;
+            }
+        }
+        true
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        self.receive(byte);
+        true
+    }
+
+    fn read(&mut self) -> u8 {
+        let byte = self.memory[self.pointer];
+        self.pointer = (self.pointer + 1) % self.part.bytes;
+        byte
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        Eeprom::advance(self, elapsed)
+    }
+}
+
+#[test]
+fn writes_pages_and_reads_back() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use stk_i2c_vm::I2cBus;
+
+    let read_from = |bus: &mut I2cBus, control: u8, address: &[u8], n: usize| {
+        bus.start();
+        bus.write(control);
+        for &x in address {
+            bus.write(x);
+        }
+        bus.start();
+        bus.write(control | 1);
+        let bytes: Vec<_> = (0..n).map(|i| bus.read(i + 1 < n)).collect();
+        bus.stop();
+        bytes
+    };
+
+    let control = (CONTROL_CODE | 0b010) << 1;
+    let chip = Rc::new(RefCell::new(Eeprom::new(Part::LC256).chip_select(0b010)));
+    let mut bus = I2cBus::new();
+    bus.attach(chip.clone());
+
+    // 0x3e and 0x3f, then the start of the same page, not 0x40
+    bus.start();
+    assert!(bus.write(control));
+    for byte in [0x00, 0x3e, 1, 2, 3, 4] {
+        assert!(bus.write(byte));
+    }
+    bus.stop();
+    assert!(chip.borrow().busy());
+    assert_eq!(chip.borrow().memory()[0x3e], 0xff);
+
+    // acknowledge polling
+    bus.start();
+    assert!(!bus.write(control));
+    chip.borrow_mut().advance(WRITE_CYCLE);
+    assert!(!chip.borrow().busy());
+    bus.start();
+    assert!(bus.write(control));
+    bus.stop();
+    assert_eq!(&chip.borrow().memory()[0x3e..0x41], [1, 2, 0xff]);
+    assert_eq!(&chip.borrow().memory()[0x00..0x03], [3, 4, 0xff]);
+    assert_eq!(
+        read_from(&mut bus, control, &[0x00, 0x3d], 4),
+        [0xff, 1, 2, 0xff]
+    );
+
+    // not this chip
+    bus.start();
+    assert!(!bus.write(CONTROL_CODE << 1));
+    bus.stop();
+
+    // sequential reads wrap at the end of the memory
+    chip.borrow_mut().memory_mut()[0x7fff] = 0x42;
+    assert_eq!(read_from(&mut bus, control, &[0x7f, 0xff], 2), [0x42, 3]);
+
+    // write protected: acknowledged, not written
+    chip.borrow_mut().set_write_protect(true);
+    bus.start();
+    assert!(bus.write(control));
+    for byte in [0x00, 0x00, 9] {
+        assert!(bus.write(byte));
+    }
+    bus.stop();
+    assert!(!chip.borrow().busy());
+    assert_eq!(chip.borrow().memory()[0], 3);
+
+    // the block select bits of a 24LC16 are the upper address bits
+    let chip = Rc::new(RefCell::new(Eeprom::new(Part::LC16)));
+    let mut bus = I2cBus::new();
+    bus.attach(chip.clone());
+    bus.start();
+    assert!(bus.write((CONTROL_CODE | 5) << 1));
+    bus.write(0x10);
+    bus.write(0xaa);
+    bus.stop();
+    chip.borrow_mut().advance(WRITE_CYCLE);
+    assert_eq!(chip.borrow().memory()[0x510], 0xaa);
+}
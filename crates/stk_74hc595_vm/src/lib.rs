@@ -0,0 +1,135 @@
+//! 74HC595 shift registers, one or more chained
+//!
+//! each rising edge of SRCLK shifts SER into QA of the first chip and moves every bit one place
+//! on, QH of a chip going into QA of the next one (through QH'). a rising edge of RCLK copies the
+//! shift registers into the storage registers, which drive QA..QH while OE is low. SRCLR low
+//! clears the shift registers but not the storage. floating inputs read as low.
+
+#[derive(Debug, Clone)]
+pub struct ShiftRegister {
+    /// per chip from the one SER goes into, QA in bit 0
+    shift: Vec<u8>,
+    storage: Vec<u8>,
+    ser: bool,
+    srclk: bool,
+    rclk: bool,
+    /// active low
+    srclr: bool,
+    /// active low
+    oe: bool,
+}
+
+impl Default for ShiftRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShiftRegister {
+    /// a single chip
+    pub fn new() -> Self {
+        Self::chained(1)
+    }
+
+    /// `chips` chips, QH' of each to SER of the next and all sharing the clocks, SRCLR and OE
+    pub fn chained(chips: usize) -> Self {
+        assert!(chips > 0);
+        Self {
+            shift: vec![0; chips],
+            storage: vec![0; chips],
+            ser: false,
+            srclk: false,
+            rclk: false,
+            srclr: true,
+            oe: false,
+        }
+    }
+
+    /// levels of SER, SRCLK and RCLK. `None` if floating
+    pub fn set_inputs(&mut self, ser: Option<bool>, srclk: Option<bool>, rclk: Option<bool>) {
+        let (srclk, rclk) = (srclk == Some(true), rclk == Some(true));
+        self.ser = ser == Some(true);
+        // with the clocks tied together, the storage gets what was shifted in before this edge
+        if rclk && !self.rclk {
+            self.storage.clone_from(&self.shift);
+        }
+        if srclk && !self.srclk && self.srclr {
+            let mut carry = self.ser as u8;
+            for chip in &mut self.shift {
+                let out = *chip >> 7;
+                *chip = *chip << 1 | carry;
+                carry = out;
+            }
+        }
+        self.srclk = srclk;
+        self.rclk = rclk;
+    }
+
+    /// level of SRCLR, which clears the shift registers while low. `None` if floating
+    pub fn set_clear(&mut self, srclr: Option<bool>) {
+        self.srclr = srclr == Some(true);
+        if !self.srclr {
+            self.shift.fill(0);
+        }
+    }
+
+    /// level of OE, which turns the outputs off while high. `None` if floating
+    pub fn set_output_enable(&mut self, oe: Option<bool>) {
+        self.oe = oe == Some(true);
+    }
+
+    pub fn chips(&self) -> usize {
+        self.shift.len()
+    }
+
+    /// the storage registers per chip, QA in bit 0, whether the outputs are on or not
+    pub fn latched(&self) -> &[u8] {
+        &self.storage
+    }
+
+    /// QA..QH of `chip` as `0..8`. `None` while OE is high and the outputs are off
+    pub fn output(&self, chip: usize, pin: usize) -> Option<bool> {
+        (!self.oe).then(|| self.storage[chip] >> pin & 1 != 0)
+    }
+
+    /// QH' of the last chip, for chaining to something else
+    pub fn serial_out(&self) -> bool {
+        self.shift[self.shift.len() - 1] >> 7 != 0
+    }
+}
+
+#[test]
+fn shifts_through_the_chain_and_latches() {
+    let mut chain = ShiftRegister::chained(2);
+    let send = |chain: &mut ShiftRegister, word: u16, tied: bool| {
+        // QH of the last chip first
+        for i in (0..16).rev() {
+            let ser = Some(word >> i & 1 != 0);
+            chain.set_inputs(ser, Some(false), Some(false));
+            chain.set_inputs(ser, Some(true), Some(tied));
+        }
+        chain.set_inputs(None, Some(false), Some(false));
+    };
+
+    send(&mut chain, 0xA55A, false);
+    assert_eq!(chain.latched(), [0, 0]);
+    assert!(chain.serial_out());
+    chain.set_inputs(None, None, Some(true));
+    assert_eq!(chain.latched(), [0x5A, 0xA5]);
+    assert_eq!(chain.output(0, 1), Some(true));
+    assert_eq!(chain.output(1, 1), Some(false));
+
+    chain.set_output_enable(Some(true));
+    assert_eq!(chain.output(0, 1), None);
+    chain.set_output_enable(Some(false));
+
+    // clearing leaves the storage alone
+    chain.set_clear(Some(false));
+    chain.set_clear(Some(true));
+    assert!(!chain.serial_out());
+    assert_eq!(chain.latched(), [0x5A, 0xA5]);
+
+    // RCLK with SRCLK latches one bit behind
+    send(&mut chain, 0xFFFF, true);
+    assert_eq!(chain.latched(), [0xFF, 0x7F]);
+}
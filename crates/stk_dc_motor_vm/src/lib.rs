@@ -0,0 +1,141 @@
+//! brushed DC motor behind an H-bridge
+//!
+//! the two bridge inputs select the drive: one high and the other low turns the motor one way,
+//! both at the same level shorts it (brake), and a floating input leaves it coasting. speed
+//! follows the drive through a first-order lag, so PWM on an input averages out into a partial
+//! speed like the inertia of a real motor does.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Stopped,
+    Forward,
+    Reverse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Drive {
+    Forward,
+    Reverse,
+    Brake,
+    Coast,
+}
+
+#[derive(Debug, Clone)]
+pub struct DcMotor {
+    a: Option<bool>,
+    b: Option<bool>,
+    max_rpm: f64,
+    /// time to reach ~63% of the target speed while driven or braked
+    time_constant_s: f64,
+    /// same, for slowing down by friction alone
+    coast_time_constant_s: f64,
+    /// signed. positive is forward
+    rpm: f64,
+    /// shaft angle in degrees, 0 to 360
+    angle: f64,
+}
+
+impl Default for DcMotor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DcMotor {
+    /// below this, [`Self::direction`] reports [`Direction::Stopped`]
+    pub const STOPPED_RPM: f64 = 1.0;
+
+    /// a small hobby motor: 6000 rpm, 50 ms to spin up
+    pub fn new() -> Self {
+        Self::with_params(6000.0, 0.05)
+    }
+
+    pub fn with_params(max_rpm: f64, time_constant_s: f64) -> Self {
+        assert!(max_rpm > 0.0 && time_constant_s > 0.0);
+        Self {
+            a: None,
+            b: None,
+            max_rpm,
+            time_constant_s,
+            coast_time_constant_s: time_constant_s * 4.0,
+            rpm: 0.0,
+            angle: 0.0,
+        }
+    }
+
+    /// levels of the two H-bridge inputs. `None` if floating
+    pub fn set_inputs(&mut self, a: Option<bool>, b: Option<bool>) {
+        self.a = a;
+        self.b = b;
+    }
+
+    fn drive(&self) -> Drive {
+        match (self.a, self.b) {
+            (Some(true), Some(false)) => Drive::Forward,
+            (Some(false), Some(true)) => Drive::Reverse,
+            (Some(_), Some(_)) => Drive::Brake,
+            _ => Drive::Coast,
+        }
+    }
+
+    pub fn simulate(&mut self, ns: u64) {
+        let dt = ns as f64 / 1_000_000_000.0;
+        let (target, tau) = match self.drive() {
+            Drive::Forward => (self.max_rpm, self.time_constant_s),
+            Drive::Reverse => (-self.max_rpm, self.time_constant_s),
+            Drive::Brake => (0.0, self.time_constant_s),
+            Drive::Coast => (0.0, self.coast_time_constant_s),
+        };
+        let before = self.rpm;
+        self.rpm += (target - self.rpm) * (1.0 - (-dt / tau).exp());
+
+        // trapezoid: the speed changes smoothly within the step
+        let revolutions = (before + self.rpm) / 2.0 / 60.0 * dt;
+        self.angle = (self.angle + revolutions * 360.0).rem_euclid(360.0);
+    }
+
+    /// signed speed. positive is forward
+    pub fn rpm(&self) -> f64 {
+        self.rpm
+    }
+
+    pub fn direction(&self) -> Direction {
+        if self.rpm >= Self::STOPPED_RPM {
+            Direction::Forward
+        } else if self.rpm <= -Self::STOPPED_RPM {
+            Direction::Reverse
+        } else {
+            Direction::Stopped
+        }
+    }
+
+    /// shaft angle in degrees, for drawing
+    pub fn angle(&self) -> f64 {
+        self.angle
+    }
+}
+
+#[test]
+fn spins_up_brakes_and_averages_pwm() {
+    const MS: u64 = 1_000_000;
+
+    let mut m = DcMotor::new();
+    m.set_inputs(Some(true), Some(false));
+    m.simulate(500 * MS);
+    assert!(m.rpm() > 5990.0);
+    assert_eq!(m.direction(), Direction::Forward);
+
+    m.set_inputs(Some(true), Some(true));
+    m.simulate(500 * MS);
+    assert_eq!(m.direction(), Direction::Stopped);
+
+    // 25% duty at 1 kHz, far faster than the motor reacts
+    for _ in 0..1000 {
+        m.set_inputs(Some(false), Some(true));
+        m.simulate(MS / 4);
+        m.set_inputs(Some(false), Some(false));
+        m.simulate(MS * 3 / 4);
+    }
+    assert!((m.rpm() + 1500.0).abs() < 100.0, "{}", m.rpm());
+    assert_eq!(m.direction(), Direction::Reverse);
+}
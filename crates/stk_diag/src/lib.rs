@@ -0,0 +1,302 @@
+//! diagnostics shared by the VM, devices and frontends.
+//!
+//! producers build a [`Diagnostic`] and hand it to a [`DiagnosticSink`]; each frontend decides how
+//! to show it (plain text, JSON lines, the web log panel, ...).
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io::Write;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// what emitted this, e.g. `vm` or the name of a device
+    pub source: String,
+    /// instruction cycle of the emitter, if it has one
+    pub cycle: Option<u64>,
+    pub pc: Option<u16>,
+    pub message: String,
+    /// machine readable details. `null` if none
+    pub data: serde_json::Value,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, source: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            source: source.into(),
+            cycle: None,
+            pc: None,
+            message: message.into(),
+            data: serde_json::Value::Null,
+        }
+    }
+
+    pub fn info(source: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Info, source, message)
+    }
+
+    pub fn warning(source: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, source, message)
+    }
+
+    pub fn error(source: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, source, message)
+    }
+
+    pub fn with_cycle(mut self, cycle: u64) -> Self {
+        self.cycle = Some(cycle);
+        self
+    }
+
+    pub fn with_pc(mut self, pc: u16) -> Self {
+        self.pc = Some(pc);
+        self
+    }
+
+    pub fn with_data(mut self, data: impl Serialize) -> Self {
+        self.data = serde_json::to_value(data).expect("diagnostic data must be serializable");
+        self
+    }
+}
+
+/// `error[vm] cycle 42 pc 0x0123: message {data}`
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]", self.severity, self.source)?;
+        if let Some(cycle) = self.cycle {
+            write!(f, " cycle {cycle}")?;
+        }
+        if let Some(pc) = self.pc {
+            write!(f, " pc {pc:#06x}")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if !self.data.is_null() {
+            write!(f, " {}", self.data)?;
+        }
+        Ok(())
+    }
+}
+
+pub trait DiagnosticSink {
+    fn emit(&mut self, diagnostic: Diagnostic);
+}
+
+impl DiagnosticSink for Vec<Diagnostic> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        self.push(diagnostic);
+    }
+}
+
+impl<S: DiagnosticSink + ?Sized> DiagnosticSink for &mut S {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        (**self).emit(diagnostic);
+    }
+}
+
+impl<S: DiagnosticSink + ?Sized> DiagnosticSink for Box<S> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        (**self).emit(diagnostic);
+    }
+}
+
+/// one human readable line per diagnostic
+pub struct PrintSink<W>(pub W);
+
+impl<W: Write> DiagnosticSink for PrintSink<W> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        // diagnostics must not take the emitter down
+        let _ = writeln!(self.0, "{diagnostic}");
+    }
+}
+
+/// one JSON object per line
+pub struct JsonSink<W>(pub W);
+
+impl<W: Write> DiagnosticSink for JsonSink<W> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        let _ = serde_json::to_writer(&mut self.0, &diagnostic);
+        let _ = writeln!(self.0);
+    }
+}
+
+/// passes the first `limit` (1 by default) of each distinct diagnostic (same source, severity and
+/// message) on to `S` and only counts the rest, so a noisy source can stay enabled.
+/// [`Self::flush`] reports the counts as one summary per source.
+#[derive(Debug)]
+pub struct Dedup<S> {
+    inner: S,
+    limit: u64,
+    /// occurrences since the start, and since the last flush
+    seen: BTreeMap<(String, Severity, String), (u64, u64)>,
+}
+
+impl<S: DiagnosticSink> Dedup<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner, limit: 1, seen: BTreeMap::new() }
+    }
+
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// emits a summary for each source with suppressed diagnostics since the last flush, at the
+    /// severity of the worst of them. their counts per message go in `data`.
+    pub fn flush(&mut self) {
+        let mut summaries = BTreeMap::<&str, (Severity, u64, BTreeMap<&str, u64>)>::new();
+        for ((source, severity, message), (total, since_flush)) in &mut self.seen {
+            let suppressed = (*total - self.limit.min(*total)).min(*since_flush);
+            *since_flush = 0;
+            if suppressed == 0 {
+                continue;
+            }
+            let summary = summaries
+                .entry(source)
+                .or_insert((*severity, 0, BTreeMap::new()));
+            summary.0 = summary.0.max(*severity);
+            summary.1 += suppressed;
+            *summary.2.entry(message).or_default() += suppressed;
+        }
+        for (source, (severity, suppressed, counts)) in summaries {
+            let message = format!("{suppressed} repeated diagnostics suppressed");
+            self.inner
+                .emit(Diagnostic::new(severity, source, message).with_data(counts));
+        }
+    }
+
+    /// [`Self::flush`]es and gives the inner sink back
+    pub fn into_inner(mut self) -> S {
+        self.flush();
+        self.inner
+    }
+}
+
+impl<S: DiagnosticSink> DiagnosticSink for Dedup<S> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        let key = (
+            diagnostic.source.clone(),
+            diagnostic.severity,
+            diagnostic.message.clone(),
+        );
+        let (total, since_flush) = self.seen.entry(key).or_default();
+        *total += 1;
+        *since_flush += 1;
+        if *total <= self.limit {
+            self.inner.emit(diagnostic);
+        }
+    }
+}
+
+/// passes everything on to `S`, counting by severity
+#[derive(Debug)]
+pub struct Counted<S> {
+    inner: S,
+    counts: BTreeMap<Severity, u64>,
+}
+
+impl<S: DiagnosticSink> Counted<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner, counts: BTreeMap::new() }
+    }
+
+    /// severities that haven't been seen are left out
+    pub fn counts(&self) -> &BTreeMap<Severity, u64> {
+        &self.counts
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: DiagnosticSink> DiagnosticSink for Counted<S> {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        *self.counts.entry(diagnostic.severity).or_default() += 1;
+        self.inner.emit(diagnostic);
+    }
+}
+
+#[test]
+fn sinks_format() {
+    let d = Diagnostic::error("vm", "callstack overflow")
+        .with_cycle(42)
+        .with_pc(0x123)
+        .with_data(
+            [("depth", 8)]
+                .into_iter()
+                .collect::<std::collections::BTreeMap<_, _>>(),
+        );
+
+    let mut text = PrintSink(vec![]);
+    text.emit(d.clone());
+    assert_eq!(
+        String::from_utf8(text.0).unwrap(),
+        "error[vm] cycle 42 pc 0x0123: callstack overflow {\"depth\":8}\n"
+    );
+
+    let mut json = JsonSink(vec![]);
+    json.emit(d);
+    assert_eq!(
+        String::from_utf8(json.0).unwrap(),
+        r#"{"severity":"error","source":"vm","cycle":42,"pc":291,"message":"callstack overflow","data":{"depth":8}}"#.to_owned() + "\n"
+    );
+}
+
+#[test]
+fn dedup_summarizes_repeats() {
+    let mut dedup = Dedup::new(vec![]);
+    for _ in 0..3 {
+        dedup.emit(Diagnostic::warning("vm", "TMR1L: read stub"));
+        dedup.emit(Diagnostic::warning("vm", "T1CON: write stub"));
+    }
+    dedup.emit(Diagnostic::error("loader", "program is too large"));
+    dedup.flush();
+    dedup.emit(Diagnostic::warning("vm", "TMR1L: read stub"));
+    let log = dedup.into_inner();
+
+    let lines = log.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "warning[vm]: TMR1L: read stub",
+            "warning[vm]: T1CON: write stub",
+            "error[loader]: program is too large",
+            r#"warning[vm]: 4 repeated diagnostics suppressed {"T1CON: write stub":2,"TMR1L: read stub":2}"#,
+            r#"warning[vm]: 1 repeated diagnostics suppressed {"TMR1L: read stub":1}"#,
+        ]
+    );
+}
+
+#[test]
+fn counted_counts_by_severity() {
+    let mut counted = Counted::new(vec![]);
+    counted.emit(Diagnostic::warning("vm", "TMR1L: read stub"));
+    counted.emit(Diagnostic::error("vm", "callstack overflow"));
+    counted.emit(Diagnostic::warning("loader", "program is too large"));
+
+    let counts = counted.counts().clone().into_iter().collect::<Vec<_>>();
+    assert_eq!(counts, [(Severity::Warning, 2), (Severity::Error, 1)]);
+    assert_eq!(counted.into_inner().len(), 3);
+}
@@ -0,0 +1,306 @@
+//! DS1307 real-time clock on an I2C bus
+//!
+//! the clock keeps BCD seconds, minutes, hours, day, date, month and year in registers 0x00 to
+//! 0x06, the SQW/OUT control in 0x07 and 56 bytes of RAM after. a write sets the register
+//! pointer with its first byte and fills registers from there, a read returns them from the
+//! pointer on, both moving the pointer on and wrapping from 0x3f to 0x00. reads see the time as it
+//! was at the START, as on the chip. it counts while CH (bit 7 of the seconds) is clear, which a
+//! fresh chip has set.
+//!
+//! it goes on an [`I2cBus`](stk_i2c_vm::I2cBus) as an [`I2cDevice`].
+//! datasheet: <https://www.analog.com/media/en/technical-documentation/data-sheets/DS1307.pdf>
+
+use std::time::Duration;
+
+use stk_i2c_vm::I2cDevice;
+
+/// the 7-bit I2C address
+pub const ADDRESS: u8 = 0x68;
+
+const SECONDS: usize = 0x00;
+const HOURS: usize = 0x02;
+const CONTROL: usize = 0x07;
+/// clock halt, in the seconds
+const CH: u8 = 0x80;
+
+/// a time and date, in binary. hours are 0 to 23 and years 0 to 99 from 2000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    pub year: u8,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub date: u8,
+    /// 1 to 7, what 1 is is up to the firmware
+    pub day: u8,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ds1307 {
+    registers: [u8; 64],
+    /// the clock registers as they were at the last START
+    latched: [u8; 7],
+    pointer: u8,
+    /// into the current second
+    fraction: Duration,
+    /// the first byte written sets the pointer
+    pointer_next: bool,
+}
+
+impl Default for Ds1307 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ds1307 {
+    /// halted at 2000-01-01 00:00:00, day 1
+    pub fn new() -> Self {
+        let mut registers = [0; 64];
+        registers[SECONDS] = CH;
+        registers[3..6].fill(1);
+        Self {
+            registers,
+            latched: [0; 7],
+            pointer: 0,
+            fraction: Duration::ZERO,
+            pointer_next: false,
+        }
+    }
+
+    /// sets the clock in 24-hour mode and starts it
+    pub fn set_time(&mut self, time: Time) {
+        let fields = [
+            time.seconds,
+            time.minutes,
+            time.hours,
+            time.day,
+            time.date,
+            time.month,
+            time.year,
+        ];
+        for (register, x) in self.registers.iter_mut().zip(fields) {
+            *register = bcd(x);
+        }
+        self.fraction = Duration::ZERO;
+    }
+
+    /// the time the registers hold, whatever the mode of the hours
+    pub fn time(&self) -> Time {
+        let r = &self.registers;
+        Time {
+            year: binary(r[6]),
+            month: binary(r[5] & 0x1f),
+            date: binary(r[4] & 0x3f),
+            day: r[3] & 0x07,
+            hours: hours_24(r[HOURS]),
+            minutes: binary(r[1] & 0x7f),
+            seconds: binary(r[SECONDS] & 0x7f),
+        }
+    }
+
+    pub fn halted(&self) -> bool {
+        self.registers[SECONDS] & CH != 0
+    }
+
+    /// register `addr`, 0x00 to 0x3f
+    pub fn register(&self, addr: u8) -> u8 {
+        self.registers[addr as usize & 0x3f]
+    }
+
+    /// lets the clock count
+    pub fn advance(&mut self, elapsed: Duration) {
+        if self.halted() {
+            return;
+        }
+        self.fraction += elapsed;
+        while self.fraction >= Duration::from_secs(1) {
+            self.fraction -= Duration::from_secs(1);
+            self.tick();
+        }
+    }
+
+    /// one second on, carrying into the minutes, ...
+    fn tick(&mut self) {
+        let r = &mut self.registers;
+        let seconds = binary(r[SECONDS] & 0x7f) + 1;
+        r[SECONDS] = bcd(seconds % 60);
+        if seconds < 60 {
+            return;
+        }
+        let minutes = binary(r[1] & 0x7f) + 1;
+        r[1] = bcd(minutes % 60);
+        if minutes < 60 {
+            return;
+        }
+        let hours = hours_24(r[HOURS]) + 1;
+        r[HOURS] = match r[HOURS] & 0x40 != 0 {
+            // 12-hour mode: 12, 1, ... 11 with bit 5 for PM
+            true => {
+                let h = hours % 24;
+                let pm = if h >= 12 { 0x20 } else { 0 };
+                0x40 | pm | bcd(if h % 12 == 0 { 12 } else { h % 12 })
+            }
+            false => bcd(hours % 24),
+        };
+        if hours < 24 {
+            return;
+        }
+        r[3] = r[3] % 7 + 1;
+        let (year, month) = (binary(r[6]), binary(r[5] & 0x1f));
+        let days = match month {
+            2 if year % 4 == 0 => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        let date = binary(r[4] & 0x3f) + 1;
+        if date <= days {
+            r[4] = bcd(date);
+            return;
+        }
+        r[4] = 0x01;
+        if month < 12 {
+            r[5] = bcd(month + 1);
+            return;
+        }
+        r[5] = 0x01;
+        r[6] = bcd((year + 1) % 100);
+    }
+
+    /// the level of SQW/OUT: OUT, or the square wave RS1:RS0 select while SQWE is set. it's open
+    /// drain, so high is the pull-up
+    pub fn sqw_out(&self) -> bool {
+        let control = self.registers[CONTROL];
+        if control & 0x10 == 0 || self.halted() {
+            return control & 0x80 != 0;
+        }
+        let hz: u128 = [1, 4096, 8192, 32768][control as usize & 3];
+        // high for the first half of every period
+        self.fraction.as_nanos() * hz * 2 / 1_000_000_000 % 2 == 0
+    }
+
+    fn write_register(&mut self, byte: u8) {
+        let at = self.pointer as usize;
+        // setting the seconds restarts the second
+        if at == SECONDS {
+            self.fraction = Duration::ZERO;
+        }
+        self.registers[at] = byte;
+        self.pointer = (self.pointer + 1) & 0x3f;
+    }
+}
+
+impl I2cDevice for Ds1307 {
+    fn start(&mut self) {
+        self.latched.copy_from_slice(&self.registers[..7]);
+    }
+
+    fn select(&mut self, address: u8, read: bool) -> bool {
+        self.pointer_next = !read;
+        address == ADDRESS
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        if std::mem::take(&mut self.pointer_next) {
+            self.pointer = byte & 0x3f;
+        } else {
+            self.write_register(byte);
+        }
+        true
+    }
+
+    fn read(&mut self) -> u8 {
+        let at = self.pointer as usize;
+        self.pointer = (self.pointer + 1) & 0x3f;
+        match at {
+            0..=6 => self.latched[at],
+            _ => self.registers[at],
+        }
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        Ds1307::advance(self, elapsed)
+    }
+}
+
+fn bcd(x: u8) -> u8 {
+    ((x / 10) << 4) | (x % 10)
+}
+
+fn binary(x: u8) -> u8 {
+    (x >> 4) * 10 + (x & 0x0f)
+}
+
+/// the hours register, in either mode, as 0 to 23
+fn hours_24(register: u8) -> u8 {
+    if register & 0x40 == 0 {
+        return binary(register & 0x3f);
+    }
+    let h = binary(register & 0x1f) % 12;
+    if register & 0x20 != 0 {
+        h + 12
+    } else {
+        h
+    }
+}
+
+#[test]
+fn keeps_time_over_the_bus() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use stk_i2c_vm::I2cBus;
+
+    let rtc = Rc::new(RefCell::new(Ds1307::new()));
+    let mut bus = I2cBus::new();
+    bus.attach(rtc.clone());
+    assert!(rtc.borrow().halted());
+
+    // 23:59:58 on 2024-02-28, a Wednesday, and the 1Hz square wave
+    bus.start();
+    assert!(bus.write(ADDRESS << 1));
+    for byte in [0x00, 0x58, 0x59, 0x23, 0x04, 0x28, 0x02, 0x24, 0x10] {
+        assert!(bus.write(byte));
+    }
+    bus.stop();
+    assert!(!rtc.borrow().halted());
+    assert!(rtc.borrow().sqw_out());
+    rtc.borrow_mut().advance(Duration::from_millis(600));
+    assert!(!rtc.borrow().sqw_out());
+
+    // nobody at 0x50
+    bus.start();
+    assert!(!bus.write(0x50 << 1));
+    bus.stop();
+
+    // leap day, then the registers back over the bus
+    rtc.borrow_mut().advance(Duration::from_millis(1400));
+    let time = rtc.borrow().time();
+    assert_eq!(
+        (time.date, time.day, time.hours, time.seconds),
+        (29, 5, 0, 0)
+    );
+    bus.start();
+    bus.write(ADDRESS << 1);
+    bus.write(0x00);
+    bus.start();
+    bus.write(ADDRESS << 1 | 1);
+    // the time stays as it was at the START while reading
+    rtc.borrow_mut().advance(Duration::from_secs(1));
+    let read = [true, true, true, true, true, true, false].map(|ack| bus.read(ack));
+    bus.stop();
+    assert_eq!(read, [0x00, 0x00, 0x00, 0x05, 0x29, 0x02, 0x24]);
+    assert_eq!(rtc.borrow().time().seconds, 1);
+
+    // 12-hour mode: 11:59:59 PM goes to 12 AM
+    rtc.borrow_mut().registers[HOURS] = 0x40 | 0x20 | 0x11;
+    rtc.borrow_mut().registers[1] = 0x59;
+    rtc.borrow_mut().registers[SECONDS] = 0x59;
+    rtc.borrow_mut().advance(Duration::from_secs(1));
+    assert_eq!(rtc.borrow().register(HOURS as u8), 0x40 | 0x12);
+    assert_eq!(rtc.borrow().time().hours, 0);
+}
@@ -0,0 +1,527 @@
+//! DS18B20 1-Wire digital thermometer, with the temperature set by the host
+//!
+//! 1-Wire is a single open-drain line with a pull-up, and the master starts every time slot by
+//! pulling it low. held low for 480 µs or more it's a reset, and the devices answer with a
+//! presence pulse 30 µs after the master lets go. otherwise a device takes the slot as a 1 if the
+//! master let go within 30 µs, where the chip samples, and as a 0 if it held on. to send a 0 a
+//! device keeps the line low for 30 µs from the master's falling edge, and the master samples
+//! before then. bits go LSB first. the devices watch the level the master leaves the line at, not
+//! the line itself, so several on a [`OneWire`] don't mistake each other's pulses for slots.
+//!
+//! the chip takes the ROM commands (READ, MATCH, SKIP, SEARCH and ALARM SEARCH) and the function
+//! commands. a conversion takes 93.75 ms to 750 ms by the resolution and latches the temperature
+//! the host set, and read slots read 0 until it's done. it's powered externally, with no parasite
+//! power.
+//!
+//! datasheet: <https://www.analog.com/media/en/technical-documentation/data-sheets/DS18B20.pdf>
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// the first byte of the ROM code
+pub const FAMILY: u8 = 0x28;
+
+/// the shortest low the devices take as a reset
+pub const RESET_NS: u64 = 480_000;
+/// from the end of the reset to the presence pulse
+const PRESENCE_WAIT_NS: u64 = 30_000;
+const PRESENCE_NS: u64 = 120_000;
+/// into a slot, where the devices read the bit of the master
+const SAMPLE_NS: u64 = 30_000;
+/// a 0 sent by a device, from the falling edge of the master
+const HOLD_NS: u64 = 30_000;
+/// COPY SCRATCHPAD writing the EEPROM
+const COPY_NS: u64 = 10_000_000;
+
+/// the Dallas/Maxim CRC-8 of the ROM code and the scratchpad. it's 0 over bytes ending in their
+/// own CRC
+pub fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0;
+    for &byte in bytes {
+        let mut byte = byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 1;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8c;
+            }
+            byte >>= 1;
+        }
+    }
+    crc
+}
+
+/// where a device is in the commands after a reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// not taking part until the next reset
+    Idle,
+    /// taking the ROM command
+    Rom,
+    /// taking the ROM code of MATCH ROM
+    Match,
+    /// at bit `bit` of the ROM code in SEARCH ROM. step 0 sends the bit, 1 its complement and 2
+    /// reads the one the master goes on with
+    Search { bit: u8, step: u8 },
+    /// taking the function command
+    Function,
+    /// taking TH, TL and the configuration
+    WriteScratchpad,
+    /// sending the queued bytes and 1s after them. `rom` for READ ROM, which a function command
+    /// follows
+    Send { rom: bool },
+    /// read slots read 0 while a conversion or a copy goes on, 1 after
+    Status,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ds18b20 {
+    rom: [u8; 8],
+    /// set by the host, in 1/16 °C
+    temperature: i16,
+    /// the temperature register, in 1/16 °C
+    register: i16,
+    th: u8,
+    tl: u8,
+    config: u8,
+    /// TH, TL and the configuration in the EEPROM
+    eeprom: [u8; 3],
+    alarm: bool,
+    state: State,
+    /// the byte coming in, LSB first
+    input: u8,
+    bits_in: u8,
+    /// the bytes after a command
+    received: Vec<u8>,
+    output: VecDeque<u8>,
+    bits_out: u8,
+    /// the level the master leaves the line at
+    master: bool,
+    /// whether the slot going on is one the device sends in
+    sending: bool,
+    now_ns: u64,
+    /// when the master last pulled the line low
+    low_since_ns: u64,
+    /// when the device pulls the line low
+    pull_ns: Range<u64>,
+    /// a conversion or a copy goes on until then
+    busy_until_ns: u64,
+    converting: bool,
+}
+
+impl Default for Ds18b20 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ds18b20 {
+    /// serial number 1 at 25 °C, powered up: 85 °C in the temperature register, TH 75 °C, TL
+    /// 70 °C and 12 bits
+    pub fn new() -> Self {
+        let eeprom = [0x4b, 0x46, 0x7f];
+        Self {
+            rom: [0; 8],
+            temperature: 25 * 16,
+            register: 85 * 16,
+            th: eeprom[0],
+            tl: eeprom[1],
+            config: eeprom[2],
+            eeprom,
+            alarm: false,
+            state: State::Idle,
+            input: 0,
+            bits_in: 0,
+            received: vec![],
+            output: VecDeque::new(),
+            bits_out: 0,
+            master: true,
+            sending: false,
+            now_ns: 0,
+            low_since_ns: 0,
+            pull_ns: 0..0,
+            busy_until_ns: 0,
+            converting: false,
+        }
+        .serial(1)
+    }
+
+    /// the 48-bit serial number in the ROM code
+    pub fn serial(mut self, serial: u64) -> Self {
+        assert!(serial >> 48 == 0, "a 48-bit serial number");
+        self.rom[0] = FAMILY;
+        self.rom[1..7].copy_from_slice(&serial.to_le_bytes()[..6]);
+        self.rom[7] = crc8(&self.rom[..7]);
+        self
+    }
+
+    /// the family code, the serial number and the CRC, in the order they go on the line
+    pub fn rom(&self) -> [u8; 8] {
+        self.rom
+    }
+
+    /// what the next conversion reads, within the -55 °C to 125 °C of the chip
+    pub fn set_temperature(&mut self, celsius: f64) {
+        self.temperature = (celsius.clamp(-55.0, 125.0) * 16.0).round() as i16;
+    }
+
+    pub fn temperature(&self) -> f64 {
+        self.temperature as f64 / 16.0
+    }
+
+    /// the temperature register, from the last conversion
+    pub fn reading(&self) -> f64 {
+        self.register as f64 / 16.0
+    }
+
+    /// 9 to 12 bits
+    pub fn resolution(&self) -> u8 {
+        9 + (self.config >> 5 & 3)
+    }
+
+    /// the 9 bytes READ SCRATCHPAD sends, the CRC last
+    pub fn scratchpad(&self) -> [u8; 9] {
+        let [lsb, msb] = self.register.to_le_bytes();
+        let mut bytes = [lsb, msb, self.th, self.tl, self.config, 0xff, 0x0c, 0x10, 0];
+        bytes[8] = crc8(&bytes[..8]);
+        bytes
+    }
+
+    /// whether the last conversion was at TH or above or at TL or below, for ALARM SEARCH
+    pub fn alarm(&self) -> bool {
+        self.alarm
+    }
+
+    /// whether a conversion or a copy to the EEPROM goes on
+    pub fn busy(&self) -> bool {
+        self.now_ns < self.busy_until_ns
+    }
+
+    /// whether the device holds the line low
+    pub fn pulling(&self) -> bool {
+        self.pull_ns.contains(&self.now_ns)
+    }
+
+    /// lets `ns` go by
+    pub fn simulate(&mut self, ns: u64) {
+        self.now_ns += ns;
+        if self.converting && !self.busy() {
+            self.converting = false;
+            self.convert();
+        }
+    }
+
+    /// the level the master leaves the line at, high when it lets go
+    pub fn input(&mut self, level: bool) {
+        if level == std::mem::replace(&mut self.master, level) {
+            return;
+        }
+        if !level {
+            self.low_since_ns = self.now_ns;
+            let bit = self.send_bit();
+            self.sending = bit.is_some();
+            if bit == Some(false) {
+                self.pull_ns = self.now_ns..self.now_ns + HOLD_NS;
+            }
+            return;
+        }
+        let low_ns = self.now_ns - self.low_since_ns;
+        if low_ns >= RESET_NS {
+            self.reset();
+        } else if !self.sending {
+            self.receive_bit(low_ns < SAMPLE_NS);
+        }
+    }
+
+    fn reset(&mut self) {
+        let at = self.now_ns + PRESENCE_WAIT_NS;
+        self.pull_ns = at..at + PRESENCE_NS;
+        self.state = State::Rom;
+        self.bits_in = 0;
+        self.received.clear();
+        self.output.clear();
+        self.bits_out = 0;
+    }
+
+    fn rom_bit(&self, bit: u8) -> bool {
+        self.rom[bit as usize / 8] >> (bit % 8) & 1 != 0
+    }
+
+    /// the bit for the slot the master just started, `None` if the device doesn't send in it
+    fn send_bit(&mut self) -> Option<bool> {
+        match self.state {
+            State::Send { rom } => {
+                let Some(&byte) = self.output.front() else {
+                    return Some(true);
+                };
+                let bit = byte >> self.bits_out & 1 != 0;
+                self.bits_out += 1;
+                if self.bits_out == 8 {
+                    self.bits_out = 0;
+                    self.output.pop_front();
+                    if rom && self.output.is_empty() {
+                        self.state = State::Function;
+                    }
+                }
+                Some(bit)
+            }
+            State::Status => Some(!self.busy()),
+            State::Search { bit, step } if step < 2 => {
+                self.state = State::Search { bit, step: step + 1 };
+                Some(self.rom_bit(bit) != (step == 1))
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_bit(&mut self, bit: bool) {
+        match self.state {
+            State::Search { bit: at, step: 2 } => {
+                self.state = match at {
+                    _ if bit != self.rom_bit(at) => State::Idle,
+                    63 => State::Function,
+                    _ => State::Search { bit: at + 1, step: 0 },
+                };
+            }
+            State::Rom | State::Match | State::Function | State::WriteScratchpad => {
+                self.input = self.input >> 1 | (bit as u8) << 7;
+                self.bits_in += 1;
+                if self.bits_in == 8 {
+                    self.bits_in = 0;
+                    self.state = self.receive(self.input);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// the state after a byte from the master
+    fn receive(&mut self, byte: u8) -> State {
+        match self.state {
+            State::Rom => match byte {
+                // READ ROM
+                0x33 => {
+                    self.output.extend(self.rom);
+                    State::Send { rom: true }
+                }
+                // MATCH ROM
+                0x55 => State::Match,
+                // SKIP ROM
+                0xcc => State::Function,
+                // SEARCH ROM, and ALARM SEARCH for the devices in alarm
+                0xf0 => State::Search { bit: 0, step: 0 },
+                0xec if self.alarm => State::Search { bit: 0, step: 0 },
+                _ => State::Idle,
+            },
+            State::Match => {
+                self.received.push(byte);
+                if self.received.len() < 8 {
+                    return State::Match;
+                }
+                match std::mem::take(&mut self.received) == self.rom {
+                    true => State::Function,
+                    false => State::Idle,
+                }
+            }
+            State::Function => match byte {
+                // CONVERT T
+                0x44 => {
+                    self.converting = true;
+                    self.busy_until_ns = self.now_ns + (750_000_000 >> (12 - self.resolution()));
+                    State::Status
+                }
+                // WRITE SCRATCHPAD
+                0x4e => State::WriteScratchpad,
+                // READ SCRATCHPAD
+                0xbe => {
+                    self.output.extend(self.scratchpad());
+                    State::Send { rom: false }
+                }
+                // COPY SCRATCHPAD
+                0x48 => {
+                    self.eeprom = [self.th, self.tl, self.config];
+                    self.busy_until_ns = self.now_ns + COPY_NS;
+                    State::Status
+                }
+                // RECALL E2
+                0xb8 => {
+                    [self.th, self.tl, self.config] = self.eeprom;
+                    State::Status
+                }
+                // READ POWER SUPPLY: 1s, for external power
+                0xb4 => State::Send { rom: false },
+                _ => State::Idle,
+            },
+            State::WriteScratchpad => {
+                self.received.push(byte);
+                let [th, tl, config] = self.received[..] else {
+                    return State::WriteScratchpad;
+                };
+                self.th = th;
+                self.tl = tl;
+                // only R1 and R0 take writes
+                self.config = config & 0x60 | 0x1f;
+                State::Idle
+            }
+            state => state,
+        }
+    }
+
+    fn convert(&mut self) {
+        // the bits below the resolution read 0
+        self.register = self.temperature & !0 << (12 - self.resolution());
+        // TH and TL are compared with the whole degrees
+        let whole = self.register >> 4;
+        self.alarm = whole >= self.th as i8 as i16 || whole <= self.tl as i8 as i16;
+    }
+}
+
+/// the line with the devices on it. testbenches without an MCU drive it as the master with
+/// [`OneWire::reset`], [`OneWire::write`] and [`OneWire::read`]
+#[derive(Debug, Clone)]
+pub struct OneWire {
+    devices: Vec<Ds18b20>,
+    /// the level the master leaves the line at
+    master: bool,
+}
+
+impl Default for OneWire {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OneWire {
+    /// nothing on it, the line high
+    pub fn new() -> Self {
+        Self { devices: vec![], master: true }
+    }
+
+    /// puts `device` on the line. returns its index, for [`Self::device`]
+    pub fn attach(&mut self, device: Ds18b20) -> usize {
+        self.devices.push(device);
+        self.devices.len() - 1
+    }
+
+    pub fn device(&self, i: usize) -> &Ds18b20 {
+        &self.devices[i]
+    }
+
+    pub fn device_mut(&mut self, i: usize) -> &mut Ds18b20 {
+        &mut self.devices[i]
+    }
+
+    /// lets `ns` go by
+    pub fn simulate(&mut self, ns: u64) {
+        for device in &mut self.devices {
+            device.simulate(ns);
+        }
+    }
+
+    /// the level the master leaves the line at, high when it lets go
+    pub fn input(&mut self, level: bool) {
+        self.master = level;
+        for device in &mut self.devices {
+            device.input(level);
+        }
+    }
+
+    /// level of the line, low if the master or a device pulls it
+    pub fn line(&self) -> bool {
+        self.master && !self.devices.iter().any(Ds18b20::pulling)
+    }
+
+    /// a reset as the master. true if a device answered with a presence pulse
+    pub fn reset(&mut self) -> bool {
+        self.input(false);
+        self.simulate(RESET_NS);
+        self.input(true);
+        self.simulate(70_000);
+        let presence = !self.line();
+        self.simulate(410_000);
+        presence
+    }
+
+    /// a slot as the master, writing a 0 or a 1 or, with a 1, reading. the line is sampled 15 µs
+    /// in
+    pub fn bit(&mut self, bit: bool) -> bool {
+        self.input(false);
+        self.simulate(6_000);
+        self.input(bit);
+        self.simulate(9_000);
+        let read = self.line();
+        self.simulate(45_000);
+        self.input(true);
+        self.simulate(10_000);
+        read
+    }
+
+    /// writes a byte as the master
+    pub fn write(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.bit(byte >> i & 1 != 0);
+        }
+    }
+
+    /// reads a byte as the master
+    pub fn read(&mut self) -> u8 {
+        (0..8).fold(0, |acc, i| acc | (self.bit(true) as u8) << i)
+    }
+}
+
+#[test]
+fn converts_and_searches() {
+    let mut bus = OneWire::new();
+    assert!(!bus.reset());
+    let a = bus.attach(Ds18b20::new());
+    let b = bus.attach(Ds18b20::new().serial(0xabcdef));
+    bus.device_mut(a).set_temperature(21.3125);
+    bus.device_mut(b).set_temperature(-10.5);
+    let rom_a = bus.device(a).rom();
+    assert_eq!(rom_a[0], FAMILY);
+    assert_eq!(crc8(&rom_a), 0);
+
+    // 9 bits, TH 25 °C and TL 10 °C on the first
+    assert!(bus.reset());
+    bus.write(0x55);
+    rom_a.iter().for_each(|&x| bus.write(x));
+    bus.write(0x4e);
+    [0x19, 0x0a, 0x00].into_iter().for_each(|x| bus.write(x));
+    assert_eq!(bus.device(a).resolution(), 9);
+    assert_eq!(bus.device(b).resolution(), 12);
+
+    // both convert, read slots reading 0 until the 12-bit one is done
+    bus.reset();
+    bus.write(0xcc);
+    bus.write(0x44);
+    let mut slots = 0;
+    while !bus.bit(true) {
+        slots += 1;
+    }
+    let us = slots * 70;
+    assert!((749_900..=750_100).contains(&us), "{us} µs");
+
+    bus.reset();
+    bus.write(0x55);
+    rom_a.iter().for_each(|&x| bus.write(x));
+    bus.write(0xbe);
+    let scratchpad = [(); 9].map(|_| bus.read());
+    assert_eq!(scratchpad, bus.device(a).scratchpad());
+    assert_eq!(crc8(&scratchpad), 0);
+    // 21.3125 °C to 9 bits
+    assert_eq!(&scratchpad[..5], [0x50, 0x01, 0x19, 0x0a, 0x1f]);
+    assert_eq!(bus.device(b).reading(), -10.5);
+
+    // only the second is below its TL
+    assert!(!bus.device(a).alarm());
+    bus.reset();
+    bus.write(0xec);
+    let mut rom = [0; 8];
+    for i in 0..64 {
+        let (bit, complement) = (bus.bit(true), bus.bit(true));
+        assert_ne!(bit, complement, "bit {i}");
+        rom[i / 8] |= (bit as u8) << (i % 8);
+        bus.bit(bit);
+    }
+    assert_eq!(rom, bus.device(b).rom());
+}
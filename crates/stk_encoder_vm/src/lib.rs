@@ -0,0 +1,200 @@
+//! quadrature rotary encoder with a push switch, turned by the host
+//!
+//! A and B are contacts to ground, read through pull-ups, and both open at a detent. a detent
+//! clockwise goes through four quarter steps, A closing first: (A, B) = HH, LH, LL, HL, HH.
+//! counter-clockwise is the same backwards. the host asks for whole detents with
+//! [`Encoder::turn`] and the steps come out one per step time, so firmware sees every edge. a
+//! contact can bounce after it changes, opening and closing a few times before it settles.
+
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    /// quarter steps from the start, clockwise positive. the phase is this mod 4
+    steps: i64,
+    /// quarter steps yet to make
+    pending: i64,
+    /// the last detent reached
+    detent: i64,
+    step_ns: u64,
+    /// time since the last step
+    since_ns: u64,
+    bounce_ns: u64,
+    /// left of the bouncing of A and B after they last changed
+    bouncing_ns: [u64; 2],
+    pressed: bool,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder {
+    /// 1 ms, a detent in 4 ms as a brisk turn
+    pub const DEFAULT_STEP_NS: u64 = 1_000_000;
+    /// times a contact opens and closes again while it bounces
+    const BOUNCES: u64 = 3;
+
+    /// contacts that never bounce
+    pub fn new() -> Self {
+        Self::with_bounce(0)
+    }
+
+    pub fn with_bounce(bounce_ns: u64) -> Self {
+        Self {
+            steps: 0,
+            pending: 0,
+            detent: 0,
+            step_ns: Self::DEFAULT_STEP_NS,
+            since_ns: 0,
+            bounce_ns,
+            bouncing_ns: [0; 2],
+            pressed: false,
+        }
+    }
+
+    /// time between quarter steps while turning
+    pub fn step_ns(mut self, step_ns: u64) -> Self {
+        assert!(step_ns > 0);
+        self.step_ns = step_ns;
+        self
+    }
+
+    /// `detents` more detents, clockwise if positive. turning back cancels steps not made yet
+    pub fn turn(&mut self, detents: i64) {
+        self.pending += 4 * detents;
+    }
+
+    /// whether a turn is still going on
+    pub fn turning(&self) -> bool {
+        self.pending != 0
+    }
+
+    /// detents made from the start, clockwise positive. a detent counts once it's reached
+    pub fn position(&self) -> i64 {
+        self.detent
+    }
+
+    pub fn press(&mut self) {
+        self.pressed = true;
+    }
+
+    pub fn release(&mut self) {
+        self.pressed = false;
+    }
+
+    /// level of A
+    pub fn a(&self) -> bool {
+        self.level(0)
+    }
+
+    /// level of B
+    pub fn b(&self) -> bool {
+        self.level(1)
+    }
+
+    /// level of the push switch, low while pressed
+    pub fn switch(&self) -> bool {
+        !self.pressed
+    }
+
+    /// of A as 0 and B as 1, where the turning has left them
+    fn settled(&self, line: usize) -> bool {
+        let (a, b) = match self.steps.rem_euclid(4) {
+            0 => (true, true),
+            1 => (false, true),
+            2 => (false, false),
+            _ => (true, false),
+        };
+        [a, b][line]
+    }
+
+    fn level(&self, line: usize) -> bool {
+        let settled = self.settled(line);
+        let left = self.bouncing_ns[line];
+        if left == 0 {
+            return settled;
+        }
+        // flipping, and at the settled level at the start of every other phase
+        let phase = left / (self.bounce_ns / (2 * Self::BOUNCES)).max(1);
+        settled == (phase % 2 == 0)
+    }
+
+    fn step(&mut self) {
+        let before = [self.settled(0), self.settled(1)];
+        let dir = self.pending.signum();
+        self.steps += dir;
+        self.pending -= dir;
+        if self.steps % 4 == 0 {
+            self.detent = self.steps / 4;
+        }
+        for (line, before) in before.into_iter().enumerate() {
+            if self.settled(line) != before {
+                self.bouncing_ns[line] = self.bounce_ns;
+            }
+        }
+    }
+
+    /// lets the turning and the bouncing go on
+    pub fn simulate(&mut self, ns: u64) {
+        let mut remain = ns;
+        while remain > 0 {
+            let slice = match self.pending {
+                0 => remain,
+                _ => remain.min(self.step_ns - self.since_ns),
+            };
+            for left in &mut self.bouncing_ns {
+                *left = left.saturating_sub(slice);
+            }
+            remain -= slice;
+            if self.pending == 0 {
+                self.since_ns = 0;
+                continue;
+            }
+            self.since_ns += slice;
+            if self.since_ns == self.step_ns {
+                self.since_ns = 0;
+                self.step();
+            }
+        }
+    }
+}
+
+#[test]
+fn turns_through_the_phases() {
+    const MS: u64 = 1_000_000;
+
+    let mut knob = Encoder::new();
+    let mut levels = vec![];
+    for detents in [1, -2] {
+        knob.turn(detents);
+        assert!(knob.turning());
+        while knob.turning() {
+            knob.simulate(MS);
+            levels.push((knob.a(), knob.b()));
+        }
+    }
+    let (h, l) = (true, false);
+    let cw = [(l, h), (l, l), (h, l), (h, h)];
+    let ccw = [(h, l), (l, l), (l, h), (h, h)];
+    assert_eq!(levels, [&cw[..], &ccw, &ccw].concat());
+    assert_eq!(knob.position(), -1);
+
+    // A bounces after it closes, B stays put
+    let mut knob = Encoder::with_bounce(MS).step_ns(10 * MS);
+    knob.turn(1);
+    knob.simulate(10 * MS);
+    assert_eq!(knob.position(), 0);
+    let mut seen = vec![];
+    for _ in 0..8 {
+        seen.push(knob.a());
+        assert!(knob.b());
+        knob.simulate(MS / 6);
+    }
+    assert_eq!(seen, [false, true, false, true, false, true, false, false]);
+
+    knob.press();
+    assert!(!knob.switch());
+    knob.simulate(100 * MS);
+    assert_eq!(knob.position(), 1);
+}
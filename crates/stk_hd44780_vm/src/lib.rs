@@ -2,6 +2,11 @@
 //! datasheet: <https://cdn-shop.adafruit.com/datasheets/HD44780.pdf>
 
 use std::fmt::Debug;
+use std::time::Duration;
+
+use crate::font::FONT;
+
+mod font;
 
 // generated by src/cgrom.py
 #[rustfmt::skip]
@@ -38,11 +43,219 @@ pub struct Hd44780 {
     /// address counter for cgram
     ac_cgram: u8,
 
-    /// display content
+    /// display content, 40 characters per line. see [`ddram_index`]
     ddram: [u8; 80],
+    /// how many characters the display is shifted left by, 0 to 39
+    display_shift: u8,
+    /// the 5x8 patterns of the 8 custom characters, a row per byte
+    cgram: [u8; 64],
 
     config: Config,
     bus_state: BusState,
+
+    /// whether instructions take time, see [`Hd44780::timing`]
+    timing: bool,
+    /// until the running instruction finishes
+    busy: Duration,
+    /// see [`DisplayState::changes`]
+    changes: u64,
+    /// time passed, for the blinking of the cursor
+    elapsed: Duration,
+    /// see [`Hd44780::subscribe`]
+    observers: Observers,
+    geometry: Geometry,
+}
+
+/// the characters the panel has, see [`Hd44780::geometry`]. the controller keeps two lines of 40
+/// characters. a 4-line panel shows the first and the second half of each, so its lines start at
+/// DDRAM addresses 0x00, 0x40, then 0x00 and 0x40 plus the width
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    columns: usize,
+    lines: usize,
+}
+
+impl Geometry {
+    pub const G16X2: Self = Self { columns: 16, lines: 2 };
+    pub const G20X4: Self = Self { columns: 20, lines: 4 };
+    pub const G40X2: Self = Self { columns: 40, lines: 2 };
+
+    /// `None` unless 2 lines of up to 40 or 4 lines of up to 20 characters
+    pub fn new(columns: usize, lines: usize) -> Option<Self> {
+        let fits = match lines {
+            2 => columns <= 40,
+            4 => columns <= 20,
+            _ => false,
+        };
+        (fits && columns > 0).then_some(Self { columns, lines })
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// the DDRAM line and the offset into it that `line` of the panel starts at
+    fn start(&self, line: usize) -> (usize, usize) {
+        (line % 2, line / 2 * self.columns)
+    }
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Self::G16X2
+    }
+}
+
+/// as `16x2`
+impl std::str::FromStr for Geometry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = s
+            .split_once(['x', 'X'])
+            .and_then(|(c, l)| Some((c.parse().ok()?, l.parse().ok()?)));
+        let Some((columns, lines)) = size else {
+            return Err(format!("expected COLUMNSxLINES, e.g. 16x2, not `{s}`"));
+        };
+        Self::new(columns, lines).ok_or_else(|| {
+            format!("no {columns}x{lines} panel: 2 lines of up to 40 or 4 of up to 20")
+        })
+    }
+}
+
+/// what an instruction did to the display, see [`Hd44780::subscribe`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayEvent {
+    /// `code` went into DDRAM at `column` 0 to 39 of `line`, shown or not
+    Write {
+        line: usize,
+        column: usize,
+        code: u8,
+    },
+    /// a row of custom character `code` 0 to 7 was redefined
+    Pattern {
+        code: u8,
+    },
+    Clear,
+    /// the cursor and the display went back to the start
+    Home,
+    /// the display shifted, now by `shift` characters left, see [`Hd44780::display_shift`]
+    Shift {
+        shift: u8,
+    },
+    DisplayControl {
+        display_on: bool,
+        cursor_shown: bool,
+        cursor_blink: bool,
+    },
+}
+
+type Observer = Box<dyn FnMut(&DisplayEvent) + Send>;
+
+#[derive(Default)]
+struct Observers(Vec<Observer>);
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} observers", self.0.len())
+    }
+}
+
+/// how long instructions run at the nominal 270kHz. refer to datasheet p24
+const CLEAR_OR_HOME_TIME: Duration = Duration::from_micros(1520);
+const INSTRUCTION_TIME: Duration = Duration::from_micros(37);
+/// the cursor blinks on and off for this long each. refer to datasheet p28
+const BLINK_TIME: Duration = Duration::from_micros(409_600);
+
+/// the cursor on the screen, see [`Hd44780::cursor`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    /// from 0, as are the columns
+    pub line: usize,
+    pub column: usize,
+    /// C: an underline below the character
+    pub underline: bool,
+    /// B: the whole character blinks, about every 400ms
+    pub blink: bool,
+}
+
+/// what the controller shows and how it's set up, see [`Hd44780::display_state`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayState {
+    /// all of DDRAM, the 40 characters of each line from address 0x00 and 0x40
+    pub ddram: [[u8; 40]; 2],
+    /// the characters of each line of the panel as shown, see [`Hd44780::codes`]
+    pub visible: Vec<Vec<u8>>,
+    /// see [`Hd44780::display_shift`]
+    pub display_shift: u8,
+    /// the DDRAM address counter
+    pub address: u8,
+    /// see [`Hd44780::cursor`]
+    pub cursor: Option<Cursor>,
+    /// D
+    pub display_on: bool,
+    /// C
+    pub cursor_shown: bool,
+    /// B
+    pub cursor_blink: bool,
+    /// N
+    pub two_lines: bool,
+    /// DL
+    pub eight_bit: bool,
+    /// how many times any of the above has changed. frontends redraw when it's not what they last
+    /// drew
+    pub changes: u64,
+}
+
+/// the dots of the panel, see [`Hd44780::render_pixels`]. characters are 5x8 dots with a
+/// blank dot between them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pixels {
+    pub width: usize,
+    pub height: usize,
+    /// row by row from the top left, true where a dot is on
+    pub dots: Vec<bool>,
+}
+
+impl Pixels {
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.dots[y * self.width + x]
+    }
+}
+
+/// `#` for the dots on and `.` for the others, a line per row
+impl std::fmt::Display for Pixels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in self.dots.chunks(self.width) {
+            for &on in row {
+                f.write_str(if on { "#" } else { "." })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// where DDRAM address `addr` is kept. the addresses of the lines are 0x00-0x27 and 0x40-0x67
+fn ddram_index(addr: u8) -> usize {
+    let line = (addr & 0x40 != 0) as usize;
+    line * 40 + (addr & 0x3f) as usize % 40
+}
+
+/// the 5x8 dots of character `code`, rows from the top with the leftmost dot in bit 4. codes the
+/// ROM has as blank are blank, and the ones without a pattern yet (the kana and most symbols above
+/// 0x7f) are a box
+pub fn glyph(code: u8) -> [u8; 8] {
+    match code {
+        0x20..=0x7f => FONT[code as usize - 0x20],
+        0xff => [0x1f; 8],
+        _ if CGROM[code as usize] == ' ' => [0; 8],
+        _ => [0x1f, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1f, 0x00],
+    }
 }
 
 // FIXME: move this to interface crate
@@ -151,17 +364,26 @@ impl PinObserver for Hd44780 {
     fn update(&mut self, pin: Self::PinState) {
         let signal: Hd44780Signal = pin.into();
 
-        let should_trigger = match (self.bus_state.prev_e, signal.e) {
-            // falling edge
-            (true, false) => true,
-            // rising edge
-            (false, true) => false,
+        let (rising, falling) = match (self.bus_state.prev_e, signal.e) {
+            (false, true) => (true, false),
+            (true, false) => (false, true),
             // no state changes
-            (true, true) | (false, false) => false,
+            (true, true) | (false, false) => (false, false),
         };
         self.bus_state.prev_e = signal.e;
+        if !signal.rw {
+            self.bus_state.output = None;
+        }
 
-        if !should_trigger {
+        if signal.rw {
+            if rising {
+                self.start_read(signal.rs);
+            } else if falling {
+                self.finish_read(signal.rs);
+            }
+            return;
+        }
+        if !falling {
             return;
         }
 
@@ -178,14 +400,23 @@ impl PinObserver for Hd44780 {
             self.ir = signal.db;
         }
 
-        let Some(inst) = Instruction::decode(signal.rs, signal.rw, self.ir) else {
+        let Some(inst) = Instruction::decode(signal.rs, self.ir) else {
             tracing::warn!("unknown instruction: {:#010b}", self.ir);
             return;
         };
 
+        if self.busy() {
+            tracing::warn!("{inst:?} while busy, ignored");
+            return;
+        }
         tracing::info!("{inst:?}\n");
 
+        if self.timing {
+            self.busy = inst.time();
+        }
+        let before = self.display_state();
         self.exec(inst);
+        self.count_change(before);
     }
 }
 
@@ -197,30 +428,277 @@ impl Hd44780 {
             ac_ddram: 0,
             ac_cgram: 0,
             ddram: [0; 80],
+            display_shift: 0,
+            cgram: [0; 64],
             config: Config::new(),
             bus_state: BusState::new(),
+            timing: false,
+            busy: Duration::ZERO,
+            changes: 0,
+            elapsed: Duration::ZERO,
+            observers: Observers::default(),
+            geometry: Geometry::default(),
         }
     }
 
-    fn debug_print_ddram(&self) {
-        println!("################");
-        for i in 0..16 {
-            if self.ac_ddram == i {
-                print!("█");
+    /// the panel the controller drives, 16x2 unless set
+    pub fn geometry(mut self, geometry: Geometry) -> Self {
+        self.geometry = geometry;
+        self
+    }
+
+    /// calls `observer` with what every instruction from now on does to the display, after it's
+    /// done
+    pub fn subscribe(&mut self, observer: impl FnMut(&DisplayEvent) + Send + 'static) {
+        self.observers.0.push(Box::new(observer));
+    }
+
+    fn emit(&mut self, event: DisplayEvent) {
+        for observer in &mut self.observers.0 {
+            observer(&event);
+        }
+    }
+
+    /// make instructions take as long as on the real controller, setting the busy flag while they
+    /// run and losing the ones written before it clears. [`Hd44780::advance`] lets the time pass.
+    /// off, the controller is always ready, which is faster to simulate
+    pub fn timing(mut self, on: bool) -> Self {
+        self.timing = on;
+        self
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.busy = self.busy.saturating_sub(elapsed);
+        self.elapsed += elapsed;
+    }
+
+    /// the busy flag (BF): an instruction is still running
+    pub fn busy(&self) -> bool {
+        !self.busy.is_zero()
+    }
+
+    /// what the controller drives onto DB7..0 while E is high on a read (RW high). in 4-bit mode
+    /// each half of the byte comes on DB7..4 in turn
+    pub fn output(&self) -> Option<u8> {
+        self.bus_state.output
+    }
+
+    fn start_read(&mut self, rs: bool) {
+        let first_half = self.config._8bit_mode || !self.bus_state.received_4bit_half;
+        if first_half {
+            self.bus_state.read = if rs {
+                match self.bus_state.prev_addr_set_was_ddram {
+                    true => self.ddram[ddram_index(self.ac_ddram)],
+                    false => self.cgram[self.ac_cgram as usize],
+                }
             } else {
-                print!("{}", CGROM[self.ddram[i as usize] as usize]);
+                let ac = match self.bus_state.prev_addr_set_was_ddram {
+                    true => self.ac_ddram,
+                    false => self.ac_cgram,
+                };
+                (self.busy() as u8) << 7 | (ac & 0b0111_1111)
+            };
+        }
+        self.bus_state.output = Some(match (self.config._8bit_mode, first_half) {
+            (true, _) => self.bus_state.read,
+            (false, true) => self.bus_state.read & 0b1111_0000,
+            (false, false) => self.bus_state.read << 4,
+        });
+    }
+
+    fn finish_read(&mut self, rs: bool) {
+        self.bus_state.output = None;
+        if !self.config._8bit_mode {
+            self.bus_state.received_4bit_half = !self.bus_state.received_4bit_half;
+            if self.bus_state.received_4bit_half {
+                return;
             }
         }
-        println!();
-        for i in 0..16 {
-            let i = 0x40 + i;
-            if self.ac_ddram == i {
-                print!("█");
-            } else {
-                print!("{}", CGROM[self.ddram[i as usize] as usize]);
+        // reading data moves the address counter like writing does
+        if rs {
+            let before = self.display_state();
+            self.step_ac();
+            self.count_change(before);
+            if self.timing {
+                self.busy = INSTRUCTION_TIME;
             }
         }
-        println!("\n################");
+    }
+
+    /// after reading or writing data, as the entry mode says
+    fn step_ac(&mut self) {
+        if !self.bus_state.prev_addr_set_was_ddram {
+            self.ac_cgram = match self.config.increment {
+                true => self.ac_cgram.wrapping_add(1),
+                false => self.ac_cgram.wrapping_sub(1),
+            } & 0b11_1111;
+            return;
+        }
+        self.move_cursor(self.config.increment);
+    }
+
+    /// from the end of one line to the start of the other
+    fn move_cursor(&mut self, right: bool) {
+        self.ac_ddram = match (right, self.ac_ddram) {
+            (true, 0x27) => 0x40,
+            (true, 0x67) => 0x00,
+            (true, x) => x.wrapping_add(1),
+            (false, 0x00) => 0x67,
+            (false, 0x40) => 0x27,
+            (false, x) => x.wrapping_sub(1),
+        };
+    }
+
+    /// both lines together, the characters moving left or right
+    fn shift_display(&mut self, right: bool) {
+        self.display_shift = match right {
+            true => (self.display_shift + 39) % 40,
+            false => (self.display_shift + 1) % 40,
+        };
+        self.emit(DisplayEvent::Shift { shift: self.display_shift });
+    }
+
+    /// where the address counter is on the screen, if it is
+    fn cursor_position(&self) -> Option<(usize, usize)> {
+        if !self.bus_state.prev_addr_set_was_ddram {
+            return None;
+        }
+        let index = ddram_index(self.ac_ddram);
+        let offset = (index % 40 + 40 - self.display_shift as usize) % 40;
+        (0..self.geometry.lines).find_map(|line| {
+            let (ddram_line, start) = self.geometry.start(line);
+            let column = offset.checked_sub(start)?;
+            (ddram_line == index / 40 && column < self.geometry.columns).then_some((line, column))
+        })
+    }
+
+    /// the cursor as shown, `None` if it's off or off the panel
+    pub fn cursor(&self) -> Option<Cursor> {
+        let (underline, blink) = (self.config.cursor_shown, self.config.cursor_blink);
+        if !self.config.display_on || !(underline || blink) {
+            return None;
+        }
+        let (line, column) = self.cursor_position()?;
+        Some(Cursor { line, column, underline, blink })
+    }
+
+    /// how many characters the display is shifted left by, 0 to 39. [`Hd44780::lines`] and
+    /// [`Hd44780::codes`] are already shifted
+    pub fn display_shift(&self) -> u8 {
+        self.display_shift
+    }
+
+    /// the text of each line of the panel, as shown
+    pub fn lines(&self) -> Vec<String> {
+        self.codes()
+            .iter()
+            .map(|line| line.iter().map(|&x| CGROM[x as usize]).collect())
+            .collect()
+    }
+
+    /// the character codes of each line of the panel, as shown. see [`glyph`] for what they look
+    /// like
+    pub fn codes(&self) -> Vec<Vec<u8>> {
+        (0..self.geometry.lines)
+            .map(|line| {
+                let (ddram_line, start) = self.geometry.start(line);
+                (0..self.geometry.columns)
+                    .map(|column| {
+                        let offset = (start + column + self.display_shift as usize) % 40;
+                        self.ddram[ddram_line * 40 + offset]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// when off, nothing is shown but the contents are kept
+    pub fn display_on(&self) -> bool {
+        self.config.display_on
+    }
+
+    pub fn display_state(&self) -> DisplayState {
+        DisplayState {
+            ddram: [0, 1].map(|line| std::array::from_fn(|x| self.ddram[line * 40 + x])),
+            visible: self.codes(),
+            display_shift: self.display_shift,
+            address: self.ac_ddram,
+            cursor: self.cursor(),
+            display_on: self.config.display_on,
+            cursor_shown: self.config.cursor_shown,
+            cursor_blink: self.config.cursor_blink,
+            two_lines: self.config._2lines_display,
+            eight_bit: self.config._8bit_mode,
+            changes: self.changes,
+        }
+    }
+
+    /// the dots of character `code`, as [`glyph`] but with the custom characters of CGRAM at 0x00
+    /// to 0x0f
+    pub fn pattern(&self, code: u8) -> [u8; 8] {
+        match code {
+            0x00..=0x0f => {
+                let at = (code as usize & 7) * 8;
+                self.cgram[at..at + 8].try_into().unwrap()
+            }
+            _ => glyph(code),
+        }
+    }
+
+    /// the panel as shown, the cursor and all. a blinking cursor is on for the first 409.6ms of
+    /// every 819.2ms that [`Hd44780::advance`] has let pass. all dots are off while the display
+    /// is off
+    pub fn render_pixels(&self) -> Pixels {
+        let width = self.geometry.columns * 6 - 1;
+        let height = self.geometry.lines * 9 - 1;
+        let mut dots = vec![false; width * height];
+        if !self.config.display_on {
+            return Pixels { width, height, dots };
+        }
+        let cursor = self.cursor();
+        let blink_on = (self.elapsed.as_micros() / BLINK_TIME.as_micros()) % 2 == 0;
+        for (line, codes) in self.codes().iter().enumerate() {
+            for (column, &code) in codes.iter().enumerate() {
+                let mut pattern = self.pattern(code);
+                if let Some(cursor) = cursor.filter(|x| (x.line, x.column) == (line, column)) {
+                    if cursor.underline {
+                        pattern[7] = 0x1f;
+                    }
+                    if cursor.blink && blink_on {
+                        pattern = [0x1f; 8];
+                    }
+                }
+                for (y, bits) in pattern.iter().enumerate() {
+                    for x in 0..5 {
+                        let at = (line * 9 + y) * width + column * 6 + x;
+                        dots[at] = bits >> (4 - x) & 1 != 0;
+                    }
+                }
+            }
+        }
+        Pixels { width, height, dots }
+    }
+
+    fn count_change(&mut self, before: DisplayState) {
+        if self.display_state() != before {
+            self.changes += 1;
+        }
+    }
+
+    fn debug_print_ddram(&self) {
+        println!("################");
+        let cursor = self.cursor_position();
+        for (line, codes) in self.codes().iter().enumerate() {
+            for (column, &code) in codes.iter().enumerate() {
+                if cursor == Some((line, column)) {
+                    print!("█");
+                } else {
+                    print!("{}", CGROM[code as usize]);
+                }
+            }
+            println!();
+        }
+        println!("################");
     }
 
     fn exec(&mut self, inst: Instruction) {
@@ -228,11 +706,17 @@ impl Hd44780 {
             Instruction::ClearDisplay => {
                 self.ac_ddram = 0;
                 self.ddram.fill(0);
+                self.display_shift = 0;
+                self.config.increment = true;
+                self.bus_state.prev_addr_set_was_ddram = true;
+                self.emit(DisplayEvent::Clear);
             }
 
             Instruction::ReturnHome => {
                 self.ac_ddram = 0;
-                // TODO: > returns display from being shifted to original position
+                self.display_shift = 0;
+                self.bus_state.prev_addr_set_was_ddram = true;
+                self.emit(DisplayEvent::Home);
             }
 
             Instruction::EntryModeSet { id, s } => {
@@ -244,11 +728,21 @@ impl Hd44780 {
                 self.config.display_on = d;
                 self.config.cursor_shown = c;
                 self.config.cursor_blink = b;
+                self.emit(DisplayEvent::DisplayControl {
+                    display_on: d,
+                    cursor_shown: c,
+                    cursor_blink: b,
+                });
             }
 
             Instruction::Shift { sc, rl } => {
                 self.config.cursor_follows_shift = sc;
                 self.config.shift_to_right = rl;
+                if sc {
+                    self.shift_display(rl);
+                } else {
+                    self.move_cursor(rl);
+                }
             }
 
             Instruction::FunctionSet { dl, n, f } => {
@@ -269,15 +763,21 @@ impl Hd44780 {
 
             Instruction::Write { data } => {
                 if !self.bus_state.prev_addr_set_was_ddram {
-                    unimplemented!("custom characters are not implemented")
+                    // only the 5 dots of a row are kept
+                    self.cgram[self.ac_cgram as usize] = data & 0b1_1111;
+                    let code = self.ac_cgram / 8;
+                    self.step_ac();
+                    self.emit(DisplayEvent::Pattern { code });
+                    return;
                 }
 
-                self.ddram[self.ac_ddram as usize] = data;
-
-                if self.config.increment {
-                    self.ac_ddram += 1;
-                } else {
-                    self.ac_ddram -= 1;
+                let index = ddram_index(self.ac_ddram);
+                self.ddram[index] = data;
+                self.step_ac();
+                self.emit(DisplayEvent::Write { line: index / 40, column: index % 40, code: data });
+                // the display moves with the cursor, so that it stays put on the screen
+                if self.config.shift {
+                    self.shift_display(!self.config.increment);
                 }
 
                 self.debug_print_ddram();
@@ -300,6 +800,11 @@ struct BusState {
     received_4bit_half: bool,
 
     prev_addr_set_was_ddram: bool,
+
+    /// the byte being read, latched on its first half in 4-bit mode
+    read: u8,
+    /// driven onto DB7..0 during a read
+    output: Option<u8>,
 }
 
 impl BusState {
@@ -308,6 +813,8 @@ impl BusState {
             prev_e: false,
             received_4bit_half: false,
             prev_addr_set_was_ddram: true,
+            read: 0,
+            output: None,
         }
     }
 }
@@ -375,8 +882,9 @@ impl Debug for Instruction {
 }
 
 impl Instruction {
-    fn decode(rs: bool, rw: bool, x: u8) -> Option<Self> {
-        if !rs && !rw {
+    /// an instruction written, RW low
+    fn decode(rs: bool, x: u8) -> Option<Self> {
+        if !rs {
             stk_macro::bitmaskeq! {
                 match x {
                     0b0000_0001 => return Some(Self::ClearDisplay),
@@ -406,15 +914,357 @@ impl Instruction {
             }
         }
 
-        // TODO: 実験ではちゃんと rw 落としてる？
-        if rs && !rw {
+        if rs {
             return Some(Self::Write { data: x });
         }
 
-        if rs && rw {
-            unimplemented!("read instruction is not implemented yet")
+        None
+    }
+
+    fn time(&self) -> Duration {
+        match self {
+            Self::ClearDisplay | Self::ReturnHome => CLEAR_OR_HOME_TIME,
+            _ => INSTRUCTION_TIME,
+        }
+    }
+}
+
+#[test]
+fn busy_flag_follows_the_instruction_times() {
+    let pins = |rs, rw, e, db: u8| {
+        let bit = |n: u8| Some(db & 1 << n != 0);
+        Hd44780PinState {
+            rs: Some(rs),
+            rw: Some(rw),
+            e: Some(e),
+            db7: bit(7),
+            db6: bit(6),
+            db5: bit(5),
+            db4: bit(4),
+            db3: bit(3),
+            db2: bit(2),
+            db1: bit(1),
+            db0: bit(0),
         }
+    };
+    let write = |lcd: &mut Hd44780, rs, db| {
+        lcd.update(pins(rs, false, true, db));
+        lcd.update(pins(rs, false, false, db));
+    };
+    let read = |lcd: &mut Hd44780, rs| {
+        lcd.update(pins(rs, true, true, 0xff));
+        let output = lcd.output();
+        lcd.update(pins(rs, true, false, 0xff));
+        assert_eq!(lcd.output(), None);
+        output
+    };
 
-        None
+    let mut lcd = Hd44780::new().timing(true);
+    write(&mut lcd, false, 0x01);
+    assert_eq!(read(&mut lcd, false), Some(0x80));
+    // lost while busy
+    write(&mut lcd, true, b'A');
+    lcd.advance(Duration::from_micros(1519));
+    assert!(lcd.busy());
+    lcd.advance(Duration::from_micros(1));
+    assert_eq!(read(&mut lcd, false), Some(0x00));
+
+    write(&mut lcd, true, b'A');
+    assert_eq!(read(&mut lcd, false), Some(0x81));
+    lcd.advance(Duration::from_micros(37));
+    write(&mut lcd, false, 0x80);
+    lcd.advance(Duration::from_micros(37));
+    assert_eq!(read(&mut lcd, true), Some(b'A'));
+    assert!(lcd.busy());
+    lcd.advance(Duration::from_micros(37));
+    assert_eq!(read(&mut lcd, false), Some(0x01));
+    assert_eq!(lcd.codes()[0][..2], [b'A', 0]);
+
+    // 4-bit mode reads come a half at a time
+    write(&mut lcd, false, 0x20);
+    lcd.advance(Duration::from_micros(37));
+    assert_eq!(read(&mut lcd, false), Some(0x00));
+    assert_eq!(read(&mut lcd, false), Some(0x10));
+
+    let mut lcd = Hd44780::new();
+    write(&mut lcd, false, 0x01);
+    assert!(!lcd.busy());
+    write(&mut lcd, true, b'A');
+    assert_eq!(lcd.codes()[0][0], b'A');
+
+    // CGRAM reads back what was written, 5 dots a row
+    write(&mut lcd, false, 0x40 | 0x08);
+    write(&mut lcd, true, 0xff);
+    write(&mut lcd, true, 0x11);
+    write(&mut lcd, false, 0x40 | 0x08);
+    assert_eq!(read(&mut lcd, true), Some(0x1f));
+    assert_eq!(read(&mut lcd, true), Some(0x11));
+    assert_eq!(read(&mut lcd, false), Some(0x0a));
+}
+
+#[test]
+fn shifts_the_display_and_moves_the_cursor() {
+    // the cursor and the display shift after the write
+    let write = |lcd: &mut Hd44780, rs, db: u8| {
+        for e in [true, false] {
+            let bit = |n: u8| Some(db & 1 << n != 0);
+            lcd.update(Hd44780PinState {
+                rs: Some(rs),
+                rw: Some(false),
+                e: Some(e),
+                db7: bit(7),
+                db6: bit(6),
+                db5: bit(5),
+                db4: bit(4),
+                db3: bit(3),
+                db2: bit(2),
+                db1: bit(1),
+                db0: bit(0),
+            });
+        }
+        let cursor = lcd.cursor().map(|x| (x.line, x.column));
+        (cursor, lcd.display_shift())
+    };
+    let mut lcd = Hd44780::new();
+
+    // display, cursor and blink on
+    assert_eq!(write(&mut lcd, false, 0x0F), (Some((0, 0)), 0));
+    write(&mut lcd, true, b'A');
+    assert_eq!(write(&mut lcd, true, b'B'), (Some((0, 2)), 0));
+    // the cursor left, then the display
+    assert_eq!(write(&mut lcd, false, 0x10), (Some((0, 1)), 0));
+    assert_eq!(write(&mut lcd, false, 0x18), (Some((0, 0)), 1));
+    assert_eq!(lcd.codes()[0][..2], [b'B', 0]);
+    assert_eq!(write(&mut lcd, false, 0x02), (Some((0, 0)), 0));
+
+    // the end of the first line goes on to the second
+    write(&mut lcd, false, 0x80 | 0x27);
+    assert_eq!(write(&mut lcd, true, b'Z'), (Some((1, 0)), 0));
+    // the display right, the first line now starting from its last character
+    assert_eq!(write(&mut lcd, false, 0x1C), (Some((1, 1)), 39));
+
+    // decrementing, the display following the cursor
+    write(&mut lcd, false, 0x02);
+    write(&mut lcd, false, 0x05);
+    write(&mut lcd, false, 0x80 | 0x05);
+    assert_eq!(write(&mut lcd, true, b'C'), (Some((0, 5)), 39));
+    assert_eq!(write(&mut lcd, true, b'D'), (Some((0, 5)), 38));
+    assert_eq!(write(&mut lcd, false, 0x08), (None, 38));
+    assert_eq!(lcd.codes()[0][..8], [0, b'Z', b'A', b'B', 0, 0, b'D', b'C']);
+}
+
+#[test]
+fn counts_changes_to_the_display_state() {
+    let mut lcd = Hd44780::new();
+    let mut write = |rs, db: u8| {
+        for e in [true, false] {
+            let bit = |n: u8| Some(db & 1 << n != 0);
+            lcd.update(Hd44780PinState {
+                rs: Some(rs),
+                rw: Some(false),
+                e: Some(e),
+                db7: bit(7),
+                db6: bit(6),
+                db5: bit(5),
+                db4: bit(4),
+                db3: bit(3),
+                db2: bit(2),
+                db1: bit(1),
+                db0: bit(0),
+            });
+        }
+        lcd.display_state()
+    };
+
+    // 8-bit, 2 lines, then the display and cursor on
+    assert_eq!(write(false, 0x38).changes, 1);
+    let state = write(false, 0x0E);
+    assert!(state.display_on && state.cursor_shown && !state.cursor_blink);
+    assert!(state.two_lines && state.eight_bit);
+    assert_eq!(state.changes, 2);
+    // the same again changes nothing
+    assert_eq!(write(false, 0x0E).changes, 2);
+
+    write(false, 0x80 | 0x40 | 20);
+    let state = write(true, b'X');
+    assert_eq!(state.changes, 4);
+    assert_eq!(state.ddram[1][20], b'X');
+    assert_eq!(state.address, 0x40 | 21);
+    // past the 16 columns: in DDRAM but not shown, and nor is the cursor
+    assert!(!state.visible[1].contains(&b'X'));
+    assert_eq!(state.cursor, None);
+
+    let state = write(false, 0x80 | 0x40 | 3);
+    let cursor = state.cursor.unwrap();
+    assert_eq!((cursor.line, cursor.column), (1, 3));
+    assert_eq!(state.changes, 5);
+}
+
+#[test]
+fn renders_custom_characters_and_the_cursor() {
+    let mut lcd = Hd44780::new();
+    let write = |lcd: &mut Hd44780, rs, db: u8| {
+        for e in [true, false] {
+            let bit = |n: u8| Some(db & 1 << n != 0);
+            lcd.update(Hd44780PinState {
+                rs: Some(rs),
+                rw: Some(false),
+                e: Some(e),
+                db7: bit(7),
+                db6: bit(6),
+                db5: bit(5),
+                db4: bit(4),
+                db3: bit(3),
+                db2: bit(2),
+                db1: bit(1),
+                db0: bit(0),
+            });
+        }
+    };
+
+    // character 1 as a checkerboard, shown after an 'A'
+    write(&mut lcd, false, 0x40 | 8);
+    for row in 0..8 {
+        write(&mut lcd, true, [0x15, 0x0a][row % 2]);
     }
+    write(&mut lcd, false, 0x80);
+    write(&mut lcd, true, b'A');
+    write(&mut lcd, true, 0x09);
+    assert!(lcd.render_pixels().dots.iter().all(|&x| !x));
+    // display and cursor on, blinking
+    write(&mut lcd, false, 0x0F);
+
+    let pixels = lcd.render_pixels();
+    assert_eq!((pixels.width, pixels.height), (95, 17));
+    let rows = pixels.to_string();
+    let rows = rows.lines().map(|x| &x[..18]).collect::<Vec<_>>();
+    assert_eq!(
+        rows[..8],
+        [
+            "......#.#.#.#####.",
+            ".##....#.#..#####.",
+            "#..#..#.#.#.#####.",
+            "#..#...#.#..#####.",
+            "####..#.#.#.#####.",
+            "#..#...#.#..#####.",
+            "#..#..#.#.#.#####.",
+            ".......#.#..#####.",
+        ]
+    );
+
+    // the blink off, leaving the underline
+    lcd.advance(BLINK_TIME);
+    let pixels = lcd.render_pixels();
+    assert!(!pixels.get(12, 0) && pixels.get(12, 7) && pixels.get(16, 7));
+    assert!(!pixels.get(17, 7));
+}
+
+#[test]
+fn tells_observers_what_changed() {
+    use std::sync::{Arc, Mutex};
+
+    let write = |lcd: &mut Hd44780, rs, db: u8| {
+        for e in [true, false] {
+            let bit = |n: u8| Some(db & 1 << n != 0);
+            lcd.update(Hd44780PinState {
+                rs: Some(rs),
+                rw: Some(false),
+                e: Some(e),
+                db7: bit(7),
+                db6: bit(6),
+                db5: bit(5),
+                db4: bit(4),
+                db3: bit(3),
+                db2: bit(2),
+                db1: bit(1),
+                db0: bit(0),
+            });
+        }
+    };
+    let mut lcd = Hd44780::new();
+    let events = Arc::new(Mutex::new(vec![]));
+    let sink = events.clone();
+    lcd.subscribe(move |x| sink.lock().unwrap().push(*x));
+
+    write(&mut lcd, false, 0x01);
+    write(&mut lcd, false, 0x0C);
+    write(&mut lcd, false, 0x80 | 0x41);
+    write(&mut lcd, true, b'H');
+    write(&mut lcd, false, 0x18);
+    write(&mut lcd, false, 0x40 | 0x09);
+    write(&mut lcd, true, 0x1f);
+    write(&mut lcd, false, 0x02);
+    // moving the cursor isn't an event
+    write(&mut lcd, false, 0x14);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            DisplayEvent::Clear,
+            DisplayEvent::DisplayControl {
+                display_on: true,
+                cursor_shown: false,
+                cursor_blink: false,
+            },
+            DisplayEvent::Write { line: 1, column: 1, code: b'H' },
+            DisplayEvent::Shift { shift: 1 },
+            DisplayEvent::Pattern { code: 1 },
+            DisplayEvent::Home,
+        ]
+    );
+}
+
+#[test]
+fn lays_out_20x4_panels() {
+    let write = |lcd: &mut Hd44780, rs, db: u8| {
+        for e in [true, false] {
+            let bit = |n: u8| Some(db & 1 << n != 0);
+            lcd.update(Hd44780PinState {
+                rs: Some(rs),
+                rw: Some(false),
+                e: Some(e),
+                db7: bit(7),
+                db6: bit(6),
+                db5: bit(5),
+                db4: bit(4),
+                db3: bit(3),
+                db2: bit(2),
+                db1: bit(1),
+                db0: bit(0),
+            });
+        }
+        lcd.cursor().map(|x| (x.line, x.column))
+    };
+    assert_eq!("20x4".parse(), Ok(Geometry::G20X4));
+    assert!("40x4".parse::<Geometry>().is_err());
+    assert!("16".parse::<Geometry>().is_err());
+
+    let mut lcd = Hd44780::new().geometry(Geometry::G20X4);
+    write(&mut lcd, false, 0x0E);
+    // the first line runs on into the third
+    write(&mut lcd, false, 0x80 | 19);
+    write(&mut lcd, true, b'a');
+    assert_eq!(write(&mut lcd, true, b'b'), Some((2, 1)));
+    // and the second into the fourth
+    write(&mut lcd, false, 0x80 | 0x40 | 19);
+    write(&mut lcd, true, b'c');
+    assert_eq!(write(&mut lcd, true, b'd'), Some((3, 1)));
+    // from the end of the third to the start of the second
+    write(&mut lcd, false, 0x80 | 0x27);
+    assert_eq!(write(&mut lcd, true, b'e'), Some((1, 0)));
+
+    let lines = lcd.lines();
+    assert_eq!(lines.len(), 4);
+    assert!(lines.iter().all(|x| x.chars().count() == 20));
+    assert!(lines[0].ends_with('a') && lines[1].ends_with('c'));
+    assert!(lines[2].starts_with('b') && lines[2].ends_with('e'));
+    assert!(lines[3].starts_with('d'));
+    let pixels = lcd.render_pixels();
+    assert_eq!((pixels.width, pixels.height), (119, 35));
+
+    // shifted left by one, the first character of the third line moves to the end of the first
+    write(&mut lcd, false, 0x18);
+    let lines = lcd.lines();
+    assert!(lines[0].ends_with('b') && lines[0].chars().nth(18) == Some('a'));
+    assert_eq!(write(&mut lcd, false, 0x80 | 20), Some((0, 19)));
 }
@@ -0,0 +1,380 @@
+//! I2C bus shared by the device models, watched at the pin level
+//!
+//! SCL and SDA are open drain: a line is high unless the master or a device pulls it low. the bus
+//! follows the levels the master leaves the lines at with [`I2cBus::update`], finds START, STOP
+//! and the bits, and hands the devices whole bytes through [`I2cDevice`]. every device that
+//! acknowledges the address byte takes part in the transfer, the ACKs and the bits they send
+//! ANDed on SDA as on the wire. a device can hold SCL low to stretch the clock, and the bus sees
+//! no rising edge until it lets go.
+//!
+//! testbenches without an MCU drive the bus as the master with [`I2cBus::start`],
+//! [`I2cBus::write`], [`I2cBus::read`] and [`I2cBus::stop`].
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// a chip on the bus, seen a byte at a time
+pub trait I2cDevice {
+    /// a START or a repeated START, whether this device is addressed afterwards or not
+    fn start(&mut self) {}
+
+    /// a STOP
+    fn stop(&mut self) {}
+
+    /// the master sent the 7-bit `address`, to read from it if `read`. true to acknowledge and
+    /// take part in the transfer
+    fn select(&mut self, address: u8, read: bool) -> bool;
+
+    /// a byte written by the master. true to acknowledge
+    fn write(&mut self, byte: u8) -> bool;
+
+    /// the next byte to send the master
+    fn read(&mut self) -> u8;
+
+    /// whether the device holds SCL low
+    fn holding_scl(&self) -> bool {
+        false
+    }
+
+    /// lets time go on, for clocks and write cycles
+    fn advance(&mut self, _elapsed: Duration) {}
+}
+
+/// for keeping a handle to the device's state from outside
+impl<T: I2cDevice> I2cDevice for Rc<RefCell<T>> {
+    fn start(&mut self) {
+        self.borrow_mut().start()
+    }
+
+    fn stop(&mut self) {
+        self.borrow_mut().stop()
+    }
+
+    fn select(&mut self, address: u8, read: bool) -> bool {
+        self.borrow_mut().select(address, read)
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        self.borrow_mut().write(byte)
+    }
+
+    fn read(&mut self) -> u8 {
+        self.borrow_mut().read()
+    }
+
+    fn holding_scl(&self) -> bool {
+        self.borrow().holding_scl()
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        self.borrow_mut().advance(elapsed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// nobody addressed, waiting for a START
+    Idle,
+    /// shifting in the address byte or a written byte
+    Receive { bits: u8, byte: u8, address: bool },
+    /// the devices holding SDA low for the 9th clock
+    Ack { read: bool },
+    /// shifting out a byte, MSB first
+    Transmit { bits: u8, byte: u8 },
+    /// the 9th clock of a read, the master acknowledging or not
+    MasterAck,
+}
+
+pub struct I2cBus {
+    devices: Vec<Box<dyn I2cDevice>>,
+    /// the devices that acknowledged the address
+    selected: Vec<usize>,
+    state: State,
+    /// the levels the master leaves SCL and SDA at
+    master: (bool, bool),
+    /// the levels on the lines as last seen
+    scl: bool,
+    sda: bool,
+    sda_pulled: bool,
+}
+
+impl std::fmt::Debug for I2cBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("I2cBus")
+            .field("devices", &self.devices.len())
+            .field("selected", &self.selected)
+            .field("state", &self.state)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for I2cBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl I2cBus {
+    /// nothing on it, both lines high
+    pub fn new() -> Self {
+        Self {
+            devices: vec![],
+            selected: vec![],
+            state: State::Idle,
+            master: (true, true),
+            scl: true,
+            sda: true,
+            sda_pulled: false,
+        }
+    }
+
+    /// puts `device` on the bus
+    pub fn attach(&mut self, device: impl I2cDevice + 'static) {
+        self.devices.push(Box::new(device));
+    }
+
+    /// level of SCL, low if the master or a device pulls it
+    pub fn scl(&self) -> bool {
+        self.master.0 && !self.scl_held()
+    }
+
+    /// level of SDA, low if the master or a device pulls it
+    pub fn sda(&self) -> bool {
+        self.master.1 && !self.sda_pulled
+    }
+
+    /// whether a device stretches the clock
+    pub fn scl_held(&self) -> bool {
+        self.devices.iter().any(|x| x.holding_scl())
+    }
+
+    /// whether a device holds SDA low, to acknowledge or to send a 0
+    pub fn sda_pulled(&self) -> bool {
+        self.sda_pulled
+    }
+
+    /// lets time go on for the devices. one letting go of SCL can make the clock edge the master
+    /// has been waiting for
+    pub fn advance(&mut self, elapsed: Duration) {
+        for device in &mut self.devices {
+            device.advance(elapsed);
+        }
+        self.settle();
+    }
+
+    /// the levels the master leaves the lines at, high to release them
+    pub fn update(&mut self, scl: bool, sda: bool) {
+        self.master = (scl, sda);
+        self.settle();
+    }
+
+    fn settle(&mut self) {
+        let (scl_before, sda_before) = (self.scl, self.sda);
+        let (scl, sda) = (self.scl(), self.sda());
+        self.scl = scl;
+        self.sda = sda;
+        if scl && scl_before && sda != sda_before {
+            if !sda {
+                // START, or a repeated one
+                for device in &mut self.devices {
+                    device.start();
+                }
+                self.state = State::Receive { bits: 0, byte: 0, address: true };
+            } else {
+                for device in &mut self.devices {
+                    device.stop();
+                }
+                self.state = State::Idle;
+            }
+            self.selected.clear();
+            self.sda_pulled = false;
+            return;
+        }
+        if scl && !scl_before {
+            self.rising(sda);
+        } else if !scl && scl_before {
+            self.falling();
+            self.sda = self.sda();
+        }
+    }
+
+    /// the master's bits are read while SCL is high
+    fn rising(&mut self, sda: bool) {
+        self.state = match self.state {
+            State::Receive { bits, byte, address } => State::Receive {
+                bits: bits + 1,
+                byte: byte << 1 | sda as u8,
+                address,
+            },
+            // NACK: the master has read enough
+            State::MasterAck if sda => State::Idle,
+            state => state,
+        };
+    }
+
+    /// the devices change SDA while SCL is low
+    fn falling(&mut self) {
+        self.state = match self.state {
+            State::Receive { bits: 8, byte, address: true } => {
+                let read = byte & 1 != 0;
+                self.selected = (0..self.devices.len())
+                    .filter(|&i| self.devices[i].select(byte >> 1, read))
+                    .collect();
+                match self.selected.is_empty() {
+                    true => State::Idle,
+                    false => State::Ack { read },
+                }
+            }
+            State::Receive { bits: 8, byte, address: false } => {
+                let mut ack = false;
+                for &i in &self.selected {
+                    ack |= self.devices[i].write(byte);
+                }
+                match ack {
+                    true => State::Ack { read: false },
+                    false => State::Idle,
+                }
+            }
+            State::Ack { read: false } => State::Receive { bits: 0, byte: 0, address: false },
+            State::Ack { read: true } | State::MasterAck => {
+                let mut byte = 0xff;
+                for &i in &self.selected {
+                    byte &= self.devices[i].read();
+                }
+                State::Transmit { bits: 0, byte }
+            }
+            State::Transmit { bits: 7, .. } => State::MasterAck,
+            State::Transmit { bits, byte } => State::Transmit { bits: bits + 1, byte },
+            state => state,
+        };
+        self.sda_pulled = match self.state {
+            State::Ack { .. } => true,
+            State::Transmit { bits, byte } => byte << bits & 0x80 == 0,
+            _ => false,
+        };
+    }
+
+    /// drives the lines as the master, waiting out clock stretching on every rising edge
+    fn drive(&mut self, scl: bool, sda: bool) -> bool {
+        self.update(scl, sda);
+        while scl && self.scl_held() {
+            self.advance(Duration::from_micros(1));
+        }
+        self.sda()
+    }
+
+    /// a START, or a repeated one, as the master
+    pub fn start(&mut self) {
+        self.drive(false, true);
+        self.drive(true, true);
+        self.drive(true, false);
+        self.drive(false, false);
+    }
+
+    /// a STOP as the master
+    pub fn stop(&mut self) {
+        self.drive(false, false);
+        self.drive(true, false);
+        self.drive(true, true);
+    }
+
+    fn bit(&mut self, bit: bool) -> bool {
+        self.drive(false, bit);
+        let read = self.drive(true, bit);
+        self.drive(false, bit);
+        read
+    }
+
+    /// writes a byte as the master. true if acknowledged
+    pub fn write(&mut self, byte: u8) -> bool {
+        for i in (0..8).rev() {
+            self.bit(byte >> i & 1 != 0);
+        }
+        !self.bit(true)
+    }
+
+    /// reads a byte as the master, acknowledging it if more are to come
+    pub fn read(&mut self, ack: bool) -> u8 {
+        let byte = (0..8).fold(0, |acc, _| acc << 1 | self.bit(true) as u8);
+        self.bit(!ack);
+        byte
+    }
+}
+
+#[test]
+fn routes_bytes_by_address() {
+    /// registers behind a pointer, taking a while after each write
+    #[derive(Default)]
+    struct Chip {
+        address: u8,
+        registers: [u8; 4],
+        pointer: usize,
+        /// the first byte of a write sets the pointer
+        pointer_next: bool,
+        busy: Duration,
+        stretched: usize,
+    }
+    impl I2cDevice for Chip {
+        fn select(&mut self, address: u8, read: bool) -> bool {
+            self.pointer_next = !read;
+            address == self.address
+        }
+        fn write(&mut self, byte: u8) -> bool {
+            if std::mem::take(&mut self.pointer_next) {
+                self.pointer = byte as usize % 4;
+            } else {
+                self.registers[self.pointer] = byte;
+                self.pointer = (self.pointer + 1) % 4;
+                self.busy = Duration::from_micros(5);
+            }
+            true
+        }
+        fn read(&mut self) -> u8 {
+            let byte = self.registers[self.pointer];
+            self.pointer = (self.pointer + 1) % 4;
+            byte
+        }
+        fn holding_scl(&self) -> bool {
+            !self.busy.is_zero()
+        }
+        fn advance(&mut self, elapsed: Duration) {
+            if self.holding_scl() {
+                self.stretched += 1;
+            }
+            self.busy = self.busy.saturating_sub(elapsed);
+        }
+    }
+
+    let a = Rc::new(RefCell::new(Chip { address: 0x20, ..Chip::default() }));
+    let b = Rc::new(RefCell::new(Chip { address: 0x21, ..Chip::default() }));
+    let mut bus = I2cBus::new();
+    bus.attach(a.clone());
+    bus.attach(b.clone());
+
+    bus.start();
+    assert!(bus.write(0x21 << 1));
+    assert!(bus.write(0x01));
+    assert!(bus.write(0x5a));
+    assert!(bus.write(0xa5));
+    bus.stop();
+    assert_eq!(b.borrow().registers, [0, 0x5a, 0xa5, 0]);
+    assert_eq!(a.borrow().registers, [0; 4]);
+    // the clock held after each byte written
+    assert_eq!(b.borrow().stretched, 10);
+
+    // nobody at 0x22
+    bus.start();
+    assert!(!bus.write(0x22 << 1));
+    bus.stop();
+
+    // a repeated START to read back
+    bus.start();
+    bus.write(0x21 << 1);
+    bus.write(0x01);
+    bus.start();
+    bus.write(0x21 << 1 | 1);
+    assert_eq!([bus.read(true), bus.read(false)], [0x5a, 0xa5]);
+    bus.stop();
+    assert!(bus.scl() && bus.sda());
+}
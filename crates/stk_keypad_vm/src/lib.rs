@@ -0,0 +1,206 @@
+//! 4x4 matrix keypad scanned over its row and column lines
+//!
+//! a key closed connects its row to its column and nothing else, so with several keys down the
+//! lines connect through each other as on the real matrix, ghost keys and all. a line reads what
+//! something on it drives: its own driver, else whatever the closed keys connect it to, low
+//! winning over high. a line nobody drives floats and the pull-ups decide. keys bounce for a
+//! while after they're pressed or released, opening and closing a few times before they settle.
+
+pub const SIZE: usize = 4;
+
+/// the keys by `[row][col]`
+pub const LAYOUT: [[char; SIZE]; SIZE] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+#[derive(Debug, Clone)]
+pub struct Keypad {
+    rows: [Option<bool>; SIZE],
+    cols: [Option<bool>; SIZE],
+    /// held down by the host
+    pressed: [[bool; SIZE]; SIZE],
+    bounce_ns: u64,
+    /// left of the bouncing after the last press or release
+    bouncing_ns: [[u64; SIZE]; SIZE],
+}
+
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Keypad {
+    /// 5 ms, as a cheap tactile switch
+    pub const DEFAULT_BOUNCE_NS: u64 = 5_000_000;
+    /// times a key opens and closes again while it bounces
+    const BOUNCES: u64 = 4;
+
+    pub fn new() -> Self {
+        Self::with_bounce(Self::DEFAULT_BOUNCE_NS)
+    }
+
+    /// 0 for keys that never bounce
+    pub fn with_bounce(bounce_ns: u64) -> Self {
+        Self {
+            rows: [None; SIZE],
+            cols: [None; SIZE],
+            pressed: [[false; SIZE]; SIZE],
+            bounce_ns,
+            bouncing_ns: [[0; SIZE]; SIZE],
+        }
+    }
+
+    /// `[row, col]` of `key`. panics if it isn't on the keypad
+    fn position(key: char) -> (usize, usize) {
+        (0..SIZE)
+            .flat_map(|r| (0..SIZE).map(move |c| (r, c)))
+            .find(|&(r, c)| LAYOUT[r][c] == key)
+            .unwrap_or_else(|| panic!("no key `{key}` on the keypad"))
+    }
+
+    pub fn press(&mut self, key: char) {
+        self.set_pressed(key, true);
+    }
+
+    pub fn release(&mut self, key: char) {
+        self.set_pressed(key, false);
+    }
+
+    fn set_pressed(&mut self, key: char, pressed: bool) {
+        let (r, c) = Self::position(key);
+        if self.pressed[r][c] != pressed {
+            self.pressed[r][c] = pressed;
+            self.bouncing_ns[r][c] = self.bounce_ns;
+        }
+    }
+
+    pub fn is_pressed(&self, key: char) -> bool {
+        let (r, c) = Self::position(key);
+        self.pressed[r][c]
+    }
+
+    /// whether the contacts of the key at `[row][col]` touch, bouncing or not
+    pub fn is_closed(&self, row: usize, col: usize) -> bool {
+        let left = self.bouncing_ns[row][col];
+        if left == 0 {
+            return self.pressed[row][col];
+        }
+        // starting as pressed or released and then flipping
+        let phase = left / (self.bounce_ns / (2 * Self::BOUNCES)).max(1);
+        self.pressed[row][col] == (phase % 2 == 0)
+    }
+
+    /// what drives the row, `None` if nothing does
+    pub fn set_row(&mut self, row: usize, level: Option<bool>) {
+        self.rows[row] = level;
+    }
+
+    /// what drives the column, `None` if nothing does
+    pub fn set_col(&mut self, col: usize, level: Option<bool>) {
+        self.cols[col] = level;
+    }
+
+    /// the level on the row, `None` if it floats
+    pub fn row(&self, row: usize) -> Option<bool> {
+        self.level(row)
+    }
+
+    /// the level on the column, `None` if it floats
+    pub fn col(&self, col: usize) -> Option<bool> {
+        self.level(SIZE + col)
+    }
+
+    /// of line `line`: the rows, then the columns
+    fn level(&self, line: usize) -> Option<bool> {
+        let drivers = [self.rows, self.cols].concat();
+        if let Some(level) = drivers[line] {
+            return Some(level);
+        }
+        // the lines connected to this one through closed keys
+        let mut connected = [false; 2 * SIZE];
+        connected[line] = true;
+        let mut grew = true;
+        while grew {
+            grew = false;
+            for r in 0..SIZE {
+                for c in 0..SIZE {
+                    let (a, b) = (r, SIZE + c);
+                    if self.is_closed(r, c) && connected[a] != connected[b] {
+                        connected[a] = true;
+                        connected[b] = true;
+                        grew = true;
+                    }
+                }
+            }
+        }
+        let levels = (0..2 * SIZE)
+            .filter(|&x| connected[x])
+            .filter_map(|x| drivers[x]);
+        levels.reduce(|a, b| a && b)
+    }
+
+    /// lets the bouncing go on
+    pub fn simulate(&mut self, ns: u64) {
+        for left in self.bouncing_ns.iter_mut().flatten() {
+            *left = left.saturating_sub(ns);
+        }
+    }
+}
+
+#[test]
+fn scans_keys_through_the_matrix() {
+    const MS: u64 = 1_000_000;
+
+    // rows driven low one at a time, the columns read with pull-ups
+    let scan = |pad: &mut Keypad| {
+        let mut keys = vec![];
+        for (r, row) in LAYOUT.iter().enumerate() {
+            for i in 0..SIZE {
+                pad.set_row(i, (i == r).then_some(false));
+            }
+            for (c, &key) in row.iter().enumerate() {
+                if pad.col(c) == Some(false) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys
+    };
+    let mut pad = Keypad::new();
+    assert_eq!(scan(&mut pad), []);
+
+    pad.press('5');
+    assert!(pad.is_pressed('5'));
+    // bouncing: closed, open, closed, ... until it settles closed
+    let mut seen = vec![];
+    for _ in 0..10 {
+        seen.push(pad.is_closed(1, 1));
+        pad.simulate(MS / 2);
+    }
+    assert_eq!(
+        seen,
+        [true, false, true, false, true, true, false, true, false, true]
+    );
+    assert_eq!(scan(&mut pad), ['5']);
+
+    // 1, 2 and 4 down make 5 show up too, through 4-1-2
+    pad.press('1');
+    pad.press('2');
+    pad.press('4');
+    pad.release('5');
+    pad.simulate(10 * MS);
+    assert_eq!(scan(&mut pad), ['1', '2', '4', '5']);
+
+    // high and low connected read low
+    pad.set_row(0, Some(true));
+    pad.set_row(1, Some(false));
+    assert_eq!(pad.col(0), Some(false));
+    pad.set_row(1, None);
+    assert_eq!(pad.col(1), Some(true));
+    assert_eq!(pad.row(1), Some(true));
+    assert_eq!(pad.col(3), None);
+}
@@ -0,0 +1,127 @@
+//! 8x8 LED matrix driven by row/column multiplexing
+//!
+//! rows are anodes and columns are cathodes: the LED at (row, col) is on while its row is high
+//! and its column is low. firmware scans one row at a time, so every LED is only on for a part
+//! of the time. the matrix integrates on-time over a refresh period and reports the fraction as
+//! the brightness the eye would perceive.
+
+pub const SIZE: usize = 8;
+
+/// brightness (0.0 to 1.0) of every LED over one refresh period, indexed by `[row][col]`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Frame(pub [[f32; SIZE]; SIZE]);
+
+impl Frame {
+    pub fn brightness(&self, row: usize, col: usize) -> f32 {
+        self.0[row][col]
+    }
+
+    /// per row, bit `n` is set if column `n` is at least `threshold` bright
+    pub fn lit(&self, threshold: f32) -> [u8; SIZE] {
+        self.0.map(|row| {
+            row.iter()
+                .enumerate()
+                .filter(|(_, b)| **b >= threshold)
+                .fold(0, |acc, (c, _)| acc | (1 << c))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LedMatrix {
+    rows: [Option<bool>; SIZE],
+    cols: [Option<bool>; SIZE],
+    period_ns: u64,
+    /// time into the current period
+    elapsed_ns: u64,
+    on_ns: [[u64; SIZE]; SIZE],
+    /// last completed period
+    frame: Frame,
+}
+
+impl Default for LedMatrix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LedMatrix {
+    /// 50 Hz. scanning faster than this looks steady to the eye
+    pub const DEFAULT_PERIOD_NS: u64 = 20_000_000;
+
+    pub fn new() -> Self {
+        Self::with_period(Self::DEFAULT_PERIOD_NS)
+    }
+
+    pub fn with_period(period_ns: u64) -> Self {
+        assert!(period_ns > 0);
+        Self {
+            rows: [None; SIZE],
+            cols: [None; SIZE],
+            period_ns,
+            elapsed_ns: 0,
+            on_ns: [[0; SIZE]; SIZE],
+            frame: Frame::default(),
+        }
+    }
+
+    /// `None` if the row is floating
+    pub fn set_row(&mut self, row: usize, level: Option<bool>) {
+        self.rows[row] = level;
+    }
+
+    /// `None` if the column is floating
+    pub fn set_col(&mut self, col: usize, level: Option<bool>) {
+        self.cols[col] = level;
+    }
+
+    pub fn is_on(&self, row: usize, col: usize) -> bool {
+        self.rows[row] == Some(true) && self.cols[col] == Some(false)
+    }
+
+    /// advances time with the current row and column levels
+    pub fn simulate(&mut self, ns: u64) {
+        let mut remain = ns;
+        while remain > 0 {
+            let slice = remain.min(self.period_ns - self.elapsed_ns);
+            for r in 0..SIZE {
+                for c in 0..SIZE {
+                    if self.is_on(r, c) {
+                        self.on_ns[r][c] += slice;
+                    }
+                }
+            }
+            self.elapsed_ns += slice;
+            remain -= slice;
+
+            if self.elapsed_ns == self.period_ns {
+                let period = self.period_ns as f32;
+                self.frame = Frame(self.on_ns.map(|row| row.map(|on| on as f32 / period)));
+                self.on_ns = [[0; SIZE]; SIZE];
+                self.elapsed_ns = 0;
+            }
+        }
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+}
+
+#[test]
+fn scanned_diagonal() {
+    let mut m = LedMatrix::new();
+    let slot = LedMatrix::DEFAULT_PERIOD_NS / SIZE as u64;
+    for r in 0..SIZE {
+        for i in 0..SIZE {
+            m.set_row(i, Some(i == r));
+            m.set_col(i, Some(i != r));
+        }
+        m.simulate(slot);
+    }
+
+    let frame = m.frame();
+    assert_eq!(frame.brightness(3, 3), 0.125);
+    assert_eq!(frame.brightness(3, 4), 0.0);
+    assert_eq!(frame.lit(0.1), [1, 2, 4, 8, 16, 32, 64, 128]);
+}
@@ -1,12 +1,11 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
-use proc_macro::TokenStream;
 use proc_macro2::{Ident, TokenStream as TokenStream2};
 use quote::quote;
 use syn::parse::Parse;
 use syn::punctuated::Punctuated;
-use syn::{braced, parse_macro_input, token, Expr, LitInt, Token};
+use syn::{braced, token, Expr, LitInt, Token};
 
 struct BitmaskMatch {
     _match: Token![match],
@@ -19,12 +18,10 @@ impl Parse for BitmaskMatch {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         let preds;
         Ok(Self {
-            _match: input.parse().expect("a"),
+            _match: input.parse()?,
             match_var: Expr::parse_without_eager_brace(input)?,
             _brace_token: braced!(preds in input),
-            arms: preds
-                .parse_terminated(MatchArm::parse, Token![,])
-                .expect("c"),
+            arms: preds.parse_terminated(MatchArm::parse, Token![,])?,
         })
     }
 }
@@ -38,9 +35,9 @@ struct MatchArm {
 impl Parse for MatchArm {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
         Ok(MatchArm {
-            predicate: input.parse().expect("ma"),
-            _fat_arrow: input.parse().expect("mb"),
-            body: input.parse().expect("mc"),
+            predicate: input.parse()?,
+            _fat_arrow: input.parse()?,
+            body: input.parse()?,
         })
     }
 }
@@ -64,8 +61,8 @@ impl Parse for BitmaskMatchPredicate {
     }
 }
 
-pub(crate) fn bitmaskeq(input: TokenStream) -> TokenStream {
-    let BitmaskMatch { match_var, arms, .. } = parse_macro_input!(input as _);
+pub(crate) fn bitmaskeq(input: TokenStream2) -> syn::Result<TokenStream2> {
+    let BitmaskMatch { match_var, arms, .. } = syn::parse2(input)?;
 
     let mut body = quote!();
     for arm in arms {
@@ -85,18 +82,22 @@ pub(crate) fn bitmaskeq(input: TokenStream) -> TokenStream {
                 }
             }
 
-            BitmaskMatchPredicate::Complex(pred) => {
-                let pred = pred.to_string();
-                if !pred.starts_with("m_") {
-                    panic!("mask predicate must start with 'm_'");
-                }
-
-                let mut captures = HashMap::new();
+            BitmaskMatchPredicate::Complex(ident) => {
+                let pred = ident.to_string();
+                let Some(pred) = pred.strip_prefix("m_") else {
+                    return Err(syn::Error::new(
+                        ident.span(),
+                        "mask predicate must start with 'm_'",
+                    ));
+                };
+
+                // BTreeMap to keep the expansion stable
+                let mut captures = BTreeMap::new();
                 let mut mask = "0b".to_owned();
                 let mut value = "0b".to_owned();
                 let mut empty_mask = "0b".to_owned();
 
-                for p in pred.chars().skip("m_".len()) {
+                for p in pred.chars() {
                     #[rustfmt::skip]
                     let (maskc, valuec, emptyc, capture) = match p {
                         '0'             => ('1', '0', '0', None),
@@ -104,7 +105,12 @@ pub(crate) fn bitmaskeq(input: TokenStream) -> TokenStream {
                         'x'             => ('0', '0', '0', None),
                         '_'             => ('_', '_', '_', None), // separater
                         cap @ 'a'..='z' => ('0', '0', '0', Some(cap)),
-                        _ => panic!("invalid mask predicate {p}"),
+                        _ => {
+                            return Err(syn::Error::new(
+                                ident.span(),
+                                format!("invalid character '{p}' in mask predicate; expected one of '0', '1', 'x', '_' or a lower-case capture name"),
+                            ))
+                        }
                     };
 
                     if let Some(capture) = capture {
@@ -149,10 +155,52 @@ pub(crate) fn bitmaskeq(input: TokenStream) -> TokenStream {
         }
     }
 
-    quote! {
+    Ok(quote! {
         match #match_var {
             #body
         }
-    }
-    .into()
+    })
+}
+
+#[test]
+fn expands_mask_predicates() {
+    let expanded = bitmaskeq(quote! {
+        match x {
+            0b0000_0001 => 1,
+            m_01ab_xx10 => a | b,
+            _ => 0,
+        }
+    })
+    .unwrap();
+
+    let expected = quote! {
+        match x {
+            0b0000_0001 => 1,
+            __i if (__i & 0b1100_0011) == 0b0100_0010 => {
+                let a = __i & 0b0010_0000;
+                let b = __i & 0b0001_0000;
+                a | b
+            }
+            _ => 0,
+        }
+    };
+
+    assert_eq!(expanded.to_string(), expected.to_string());
+}
+
+#[test]
+fn reports_invalid_predicates() {
+    let err = |input| bitmaskeq(input).err().unwrap().to_string();
+
+    assert_eq!(
+        err(quote!(match x {
+            x_0101 => 1,
+        })),
+        "mask predicate must start with 'm_'"
+    );
+    assert_eq!(
+        err(quote!(match x { m_01A1 => 1 })),
+        "invalid character 'A' in mask predicate; expected one of '0', '1', 'x', '_' or a lower-case capture name"
+    );
+    assert!(bitmaskeq(quote!(match x { m_0101 1 })).is_err());
 }
@@ -2,7 +2,31 @@ mod bitmaskeq;
 
 use proc_macro::TokenStream;
 
+/// `match` on bit patterns.
+///
+/// Besides plain literals and `_`, an arm can be a mask predicate: an identifier starting with
+/// `m_` followed by one character per bit, most significant first.
+///
+/// - `0` / `1`: the bit must be 0 / 1
+/// - `x`: don't care
+/// - `_`: separator, ignored
+/// - other lower-case letters: don't care, but the bits are bound to a variable of that name.
+///   The captured value is masked in place, not shifted down.
+///
+/// Arms are tested from top to bottom.
+///
+/// ```ignore
+/// bitmaskeq! {
+///     match i {
+///         0b0000_0000_0000_1000 => Some(ControlInstruction::Return),
+///         m_xx10_1aaa_aaaa_aaaa => Some(ControlInstruction::Goto { addr: ProgramAddr::new(a) }),
+///         _ => None,
+///     }
+/// }
+/// ```
 #[proc_macro]
 pub fn bitmaskeq(input: TokenStream) -> TokenStream {
-    bitmaskeq::bitmaskeq(input)
+    bitmaskeq::bitmaskeq(input.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
 }
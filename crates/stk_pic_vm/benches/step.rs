@@ -0,0 +1,57 @@
+//! `cargo bench -p stk-pic-vm`. compares stepping through the decode cache with decoding every
+//! word on each step, which is what the vm did before.
+
+#![feature(test)]
+
+extern crate test;
+
+use stk_pic_vm::inst::Instruction;
+use stk_pic_vm::vm::p16f88::P16F88;
+use test::Bencher;
+
+const WORDS: [u16; 4] = [
+    0b11_0000_1111_1111, // 0x0000: movlw 0xff
+    0b00_0000_1010_0000, // 0x0001: movwf 0x20
+    0b00_1011_1010_0000, // 0x0002: decfsz 0x20, f
+    0b10_1000_0000_0010, // 0x0003: goto 0x0002
+];
+
+fn delay_loop() -> P16F88 {
+    let mut flash = [0; 7168];
+    for (i, w) in WORDS.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    // 0x0004: goto 0x0000
+    flash[8..10].copy_from_slice(&0b10_1000_0000_0000u16.to_le_bytes());
+    P16F88::new(flash)
+}
+
+#[bench]
+fn step_cached(b: &mut Bencher) {
+    let mut vm = delay_loop();
+    b.iter(|| vm.run_for_cycles(10_000, &mut ()));
+}
+
+#[bench]
+fn step_decoding(b: &mut Bencher) {
+    let mut vm = delay_loop();
+    b.iter(|| {
+        let mut cycles = 0;
+        while cycles < 10_000 {
+            let at = vm.pc as usize * 2;
+            let code = u16::from_le_bytes([vm.flash[at], vm.flash[at + 1]]);
+            let inst = Instruction::from_code(test::black_box(code)).unwrap();
+            vm.exec(inst, &mut ()).unwrap();
+            cycles += inst.cycle_cost(true) as u64;
+        }
+    });
+}
+
+#[bench]
+fn decode_all_opcodes(b: &mut Bencher) {
+    b.iter(|| {
+        (0..0x4000u16)
+            .filter(|&code| Instruction::from_code(test::black_box(code)).is_some())
+            .count()
+    });
+}
@@ -0,0 +1,145 @@
+//! `cargo bench -p stk-pic-vm --bench throughput`. instructions per second on a few workloads,
+//! for performance work on the vm to have numbers.
+//!
+//! fails if a workload got slower than the baseline it's compared with by more than
+//! `STK_BENCH_THRESHOLD` percent (10 by default):
+//!
+//! ```sh
+//! git checkout main && cargo bench --bench throughput -- --save-baseline main
+//! git checkout - && cargo bench --bench throughput -- --baseline main
+//! ```
+//!
+//! without `--baseline`, the comparison is with the previous run.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::SystemTime;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use stk_pic_vm::vm::p16f88::P16F88;
+
+/// instructions run per iteration
+const STEPS: u64 = 10_000;
+
+const GROUP: &str = "throughput";
+
+const WORKLOADS: [(&str, &[u16]); 3] = [
+    (
+        "tight_loop",
+        &[
+            0b11_0000_1111_1111, // 0x0000: movlw 0xff
+            0b00_0000_1010_0000, // 0x0001: movwf 0x20
+            0b00_1011_1010_0000, // 0x0002: decfsz 0x20, f
+            0b10_1000_0000_0010, // 0x0003: goto 0x0002
+            0b10_1000_0000_0000, // 0x0004: goto 0x0000
+        ],
+    ),
+    (
+        "call_heavy",
+        &[
+            0b10_0000_0000_0011, // 0x0000: call 0x0003
+            0b10_0000_0000_0101, // 0x0001: call 0x0005
+            0b10_1000_0000_0000, // 0x0002: goto 0x0000
+            0b10_0000_0000_0101, // 0x0003: call 0x0005
+            0b00_0000_0000_1000, // 0x0004: return
+            0b11_0100_0010_1010, // 0x0005: retlw 0x2a
+        ],
+    ),
+    (
+        "bank_switch_heavy",
+        &[
+            0b01_0110_1000_0011, // 0x0000: bsf STATUS, RP0
+            0b00_0000_1000_0110, // 0x0001: movwf TRISB
+            0b01_0010_1000_0011, // 0x0002: bcf STATUS, RP0
+            0b00_0000_1000_0110, // 0x0003: movwf PORTB
+            0b01_0111_0000_0011, // 0x0004: bsf STATUS, RP1
+            0b00_1010_1001_0000, // 0x0005: incf 0x110, f
+            0b01_0011_0000_0011, // 0x0006: bcf STATUS, RP1
+            0b10_1000_0000_0000, // 0x0007: goto 0x0000
+        ],
+    ),
+];
+
+fn vm(words: &[u16]) -> P16F88 {
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    P16F88::new(flash)
+}
+
+fn throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group(GROUP);
+    group.throughput(Throughput::Elements(STEPS));
+    for (name, words) in WORKLOADS {
+        let mut vm = vm(words);
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                for _ in 0..STEPS {
+                    vm.step(&mut ()).unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// where criterion keeps its results and baselines
+fn output_dir() -> PathBuf {
+    match std::env::var_os("CRITERION_HOME") {
+        Some(dir) => dir.into(),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("../../target/criterion"),
+    }
+}
+
+/// workloads of this run that got slower than the threshold, with how much
+fn regressions(dir: &Path, since: SystemTime, threshold: f64) -> Vec<(&'static str, f64)> {
+    let mut slower = vec![];
+    for (name, _) in WORKLOADS {
+        let path = dir.join(GROUP).join(name).join("change/estimates.json");
+        // a run that wasn't compared with anything leaves the last comparison there
+        let fresh = path
+            .metadata()
+            .and_then(|x| x.modified())
+            .is_ok_and(|x| x >= since);
+        let Some(change) = fresh
+            .then(|| std::fs::read(&path).ok())
+            .flatten()
+            .and_then(|x| serde_json::from_slice::<serde_json::Value>(&x).ok())
+            .and_then(|x| x["mean"]["point_estimate"].as_f64())
+        else {
+            continue;
+        };
+        // relative change of the time per iteration
+        if change * 100.0 > threshold {
+            slower.push((name, change * 100.0));
+        }
+    }
+    slower
+}
+
+fn main() -> ExitCode {
+    let started = SystemTime::now();
+    let threshold = match std::env::var("STK_BENCH_THRESHOLD") {
+        Ok(x) => x
+            .parse()
+            .expect("STK_BENCH_THRESHOLD should be a percentage"),
+        Err(_) => 10.0,
+    };
+
+    let dir = output_dir();
+    let mut criterion = Criterion::default()
+        .output_directory(&dir)
+        .configure_from_args();
+    throughput(&mut criterion);
+    criterion.final_summary();
+
+    let slower = regressions(&dir, started, threshold);
+    for (name, percent) in &slower {
+        eprintln!("{GROUP}/{name} is {percent:.1}% slower, more than {threshold}%");
+    }
+    match slower.is_empty() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
@@ -0,0 +1,17 @@
+//! `cargo fuzz run decode` from `crates/stk_pic_vm`. any word decodes without panicking, and what
+//! it decodes to encodes back to a word that decodes the same.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use stk_pic_vm::inst::Instruction;
+
+fuzz_target!(|code: u16| {
+    let Some(inst) = Instruction::from_code(code) else {
+        return;
+    };
+    let again = inst.to_code();
+    assert!(again < 0x4000, "{code:#06x} encodes to {again:#06x}");
+    assert_eq!(Instruction::from_code(again), Some(inst), "{code:#06x}");
+    let _ = format!("{inst:?}");
+});
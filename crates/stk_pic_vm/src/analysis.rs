@@ -0,0 +1,793 @@
+//! static control flow analysis over the decoded flash.
+//!
+//! the graph is built by following control flow from the reset and interrupt vectors, so data
+//! placed in the flash (e.g. after a `retlw` table) is not mistaken for code.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Write};
+use std::ops::Range;
+
+use crate::inst::{
+    BitOrientedOperation, ByteOrientedOperation, ControlInstruction, Instruction,
+    LiteralOrientedOperation, ProgramAddr, RegisterFileAddr,
+};
+use crate::profile::format_chain;
+use crate::vm::p16f88::{Ticker, P16F88};
+
+const RESET_VECTOR: ProgramAddr = ProgramAddr(0x0000);
+const INTERRUPT_VECTOR: ProgramAddr = ProgramAddr(0x0004);
+const PCL: RegisterFileAddr = RegisterFileAddr(0x02);
+const INTCON: RegisterFileAddr = RegisterFileAddr(0x0B);
+
+/// levels of the hardware return stack
+const STACK_LEVELS: usize = 8;
+
+/// how control leaves an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Next,
+    /// either the next instruction or the one after it
+    Skip,
+    Jump(ProgramAddr),
+    /// calls `ProgramAddr`, then continues with the next instruction
+    Call(ProgramAddr),
+    Return,
+    /// the destination is unknown statically (write to PCL, undecodable word)
+    Unknown,
+}
+
+fn flow(pc: ProgramAddr, inst: Option<Instruction>) -> Flow {
+    use ControlInstruction::*;
+
+    let Some(inst) = inst else {
+        return Flow::Unknown;
+    };
+    // goto/call only carry 11 bits. assume PCLATH<4:3> points at the current page,
+    // which is what the compiler emits for local jumps.
+    let page = |addr: ProgramAddr| ProgramAddr((pc.0 & 0b0001_1000_0000_0000) | addr.0);
+
+    match inst {
+        Instruction::Control(Goto { addr }) => Flow::Jump(page(addr)),
+        Instruction::Control(Call { addr }) => Flow::Call(page(addr)),
+        Instruction::Control(Return | ReturnFromInterrupt) => Flow::Return,
+        Instruction::LiteralOriented(l)
+            if l.op == LiteralOrientedOperation::ReturnWithLiteralInW =>
+        {
+            Flow::Return
+        }
+        Instruction::ByteOriented(y)
+            if matches!(
+                y.op,
+                ByteOrientedOperation::DecrementFSkipIfZ | ByteOrientedOperation::IncrementFSkipIfZ
+            ) =>
+        {
+            Flow::Skip
+        }
+        Instruction::BitOriented(b)
+            if matches!(
+                b.op,
+                BitOrientedOperation::SkipIfFBitClear | BitOrientedOperation::SkipIfFBitSet
+            ) =>
+        {
+            Flow::Skip
+        }
+        i if i.writes_register() == Some(PCL) => Flow::Unknown,
+        _ => Flow::Next,
+    }
+}
+
+/// whether `inst` may set INTCON.GIE
+fn may_enable_interrupts(inst: Instruction) -> bool {
+    match inst {
+        Instruction::BitOriented(b) => {
+            b.op == BitOrientedOperation::BitSetF && b.f == INTCON && b.b.0 == 7
+        }
+        Instruction::Control(ControlInstruction::ClearF { .. }) => false,
+        i => i.writes_register() == Some(INTCON),
+    }
+}
+
+/// straight-line run of instructions entered only at `start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: ProgramAddr,
+    /// address of the last instruction
+    pub last: ProgramAddr,
+    /// blocks control may continue to, not counting called functions
+    pub successors: Vec<ProgramAddr>,
+    /// function called by the last instruction
+    pub calls: Option<ProgramAddr>,
+}
+
+impl BasicBlock {
+    pub fn contains(&self, pc: ProgramAddr) -> bool {
+        (self.start..=self.last).contains(&pc)
+    }
+}
+
+/// natural loop formed by a back edge `latch -> header`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: ProgramAddr,
+    /// block that jumps back to the header
+    pub latch: ProgramAddr,
+    /// start addresses of the blocks in the loop, including the header and the latch
+    pub body: BTreeSet<ProgramAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub entry: ProgramAddr,
+    /// start addresses of the blocks reachable from the entry without following calls
+    pub blocks: BTreeSet<ProgramAddr>,
+}
+
+/// an edge of the call graph. `tail` for a goto into another function's entry instead of a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CallEdge {
+    pub caller: ProgramAddr,
+    pub callee: ProgramAddr,
+    pub tail: bool,
+}
+
+/// a reachable call or goto at `from`, as seen from where it lands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Reference {
+    pub from: ProgramAddr,
+    pub call: bool,
+}
+
+/// call depth worked out from the call graph by [`ControlFlowGraph::stack_usage`]. chains are
+/// the entry addresses of the functions on the stack, outermost first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackUsage {
+    /// deepest chain from the reset vector
+    pub deepest: Vec<ProgramAddr>,
+    /// deepest chain from the interrupt vector. empty if no instruction may set INTCON.GIE
+    pub interrupt: Vec<ProgramAddr>,
+    /// functions calling themselves, directly or not, back to the first one. the depth through
+    /// them has no bound and is left out of the other chains
+    pub recursion: Vec<Vec<ProgramAddr>>,
+    /// chains from the reset vector that overflow the 8-level hardware stack, with an
+    /// interrupt at the deepest point. cut at the call that overflows
+    pub overflows: Vec<Vec<ProgramAddr>>,
+}
+
+impl StackUsage {
+    /// levels in use at the deepest point, counting an interrupt on top
+    pub fn max_depth(&self) -> usize {
+        self.deepest.len().saturating_sub(1) + self.interrupt.len()
+    }
+}
+
+impl Display for StackUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "max stack depth: {}", self.max_depth())?;
+        writeln!(f, "  reset: {}", format_chain(&self.deepest))?;
+        if self.interrupt.is_empty() {
+            writeln!(f, "  interrupt: never enabled")?;
+        } else {
+            writeln!(f, "  interrupt: {}", format_chain(&self.interrupt))?;
+        }
+        for chain in &self.recursion {
+            writeln!(f, "unbounded recursion: {}", format_chain(chain))?;
+        }
+        for chain in &self.overflows {
+            writeln!(
+                f,
+                "overflows the {STACK_LEVELS}-level stack: {}",
+                format_chain(chain)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ControlFlowGraph {
+    insts: BTreeMap<ProgramAddr, Option<Instruction>>,
+    blocks: BTreeMap<ProgramAddr, BasicBlock>,
+    functions: BTreeMap<ProgramAddr, Function>,
+    loops: Vec<Loop>,
+}
+
+impl ControlFlowGraph {
+    /// analyses `flash`, laid out the same way as [`P16F88::flash`].
+    pub fn build(flash: &[u8]) -> Self {
+        let words = flash.len() / 2;
+        let decode = |pc: ProgramAddr| {
+            let at = pc.0 as usize * 2;
+            let code = ((flash[at + 1] as u16) << 8) | flash[at] as u16;
+            Instruction::from_code(code)
+        };
+        let in_flash = |pc: &ProgramAddr| (pc.0 as usize) < words;
+        let next = |pc: ProgramAddr, n: u16| Some(ProgramAddr(pc.0 + n)).filter(in_flash);
+
+        let mut cfg = Self::default();
+
+        // pass 1: find reachable instructions and where blocks begin
+        let mut entries = [RESET_VECTOR, INTERRUPT_VECTOR]
+            .into_iter()
+            .filter(in_flash)
+            .collect::<BTreeSet<_>>();
+        let mut leaders = entries.clone();
+        let mut work = entries.iter().copied().collect::<Vec<_>>();
+        while let Some(pc) = work.pop() {
+            if cfg.insts.contains_key(&pc) {
+                continue;
+            }
+            let inst = decode(pc);
+            cfg.insts.insert(pc, inst);
+
+            let succs = match flow(pc, inst) {
+                Flow::Next => vec![next(pc, 1)],
+                Flow::Skip => vec![next(pc, 1), next(pc, 2)],
+                Flow::Jump(to) => vec![Some(to).filter(in_flash)],
+                Flow::Call(to) => {
+                    if in_flash(&to) {
+                        entries.insert(to);
+                        leaders.insert(to);
+                        work.push(to);
+                    }
+                    vec![next(pc, 1)]
+                }
+                Flow::Return | Flow::Unknown => vec![],
+            };
+            let ends_block = flow(pc, inst) != Flow::Next;
+            for s in succs.into_iter().flatten() {
+                if ends_block {
+                    leaders.insert(s);
+                }
+                work.push(s);
+            }
+        }
+
+        // pass 2: cut blocks at the leaders
+        for &start in &leaders {
+            let mut last = start;
+            loop {
+                let inst = cfg.insts[&last];
+                if flow(last, inst) != Flow::Next {
+                    break;
+                }
+                match next(last, 1) {
+                    Some(n) if !leaders.contains(&n) && cfg.insts.contains_key(&n) => last = n,
+                    _ => break,
+                }
+            }
+
+            let inst = cfg.insts[&last];
+            let (successors, calls) = match flow(last, inst) {
+                Flow::Next => (vec![next(last, 1)], None),
+                Flow::Skip => (vec![next(last, 1), next(last, 2)], None),
+                Flow::Jump(to) => (vec![Some(to).filter(in_flash)], None),
+                Flow::Call(to) => (vec![next(last, 1)], Some(to).filter(in_flash)),
+                Flow::Return | Flow::Unknown => (vec![], None),
+            };
+            let successors = successors.into_iter().flatten().collect();
+            cfg.blocks
+                .insert(start, BasicBlock { start, last, successors, calls });
+        }
+
+        for entry in entries {
+            let mut blocks = BTreeSet::new();
+            let mut work = vec![entry];
+            while let Some(b) = work.pop() {
+                if blocks.insert(b) {
+                    work.extend(&cfg.blocks[&b].successors);
+                }
+            }
+            cfg.functions.insert(entry, Function { entry, blocks });
+        }
+
+        cfg.loops = cfg.find_loops();
+        cfg
+    }
+
+    fn find_loops(&self) -> Vec<Loop> {
+        let mut preds = BTreeMap::<ProgramAddr, Vec<ProgramAddr>>::new();
+        for b in self.blocks.values() {
+            for &s in &b.successors {
+                preds.entry(s).or_default().push(b.start);
+            }
+        }
+
+        // back edges are edges into a block that is still on the dfs stack
+        let mut back_edges = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        for &entry in self.functions.keys() {
+            let mut on_stack = BTreeSet::new();
+            // (block, index of the next successor to visit)
+            let mut stack = vec![(entry, 0)];
+            if !visited.insert(entry) {
+                continue;
+            }
+            on_stack.insert(entry);
+            while let Some((b, i)) = stack.last_mut() {
+                let b = *b;
+                let Some(&s) = self.blocks[&b].successors.get(*i) else {
+                    on_stack.remove(&b);
+                    stack.pop();
+                    continue;
+                };
+                *i += 1;
+                if on_stack.contains(&s) {
+                    back_edges.insert((b, s));
+                } else if visited.insert(s) {
+                    on_stack.insert(s);
+                    stack.push((s, 0));
+                }
+            }
+        }
+
+        back_edges
+            .into_iter()
+            .map(|(latch, header)| {
+                let mut body = BTreeSet::from([header]);
+                let mut work = vec![latch];
+                while let Some(b) = work.pop() {
+                    if body.insert(b) {
+                        work.extend(preds.get(&b).into_iter().flatten());
+                    }
+                }
+                Loop { header, latch, body }
+            })
+            .collect()
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// block containing the instruction at `pc`, if it is reachable
+    pub fn block_at(&self, pc: ProgramAddr) -> Option<&BasicBlock> {
+        self.blocks
+            .range(..=pc)
+            .next_back()
+            .map(|(_, b)| b)
+            .filter(|b| b.contains(pc))
+    }
+
+    /// whether control can get to the instruction at `pc` from the vectors
+    pub fn is_reachable(&self, pc: ProgramAddr) -> bool {
+        self.insts.contains_key(&pc)
+    }
+
+    /// reachable calls and gotos by where they land, for cross references
+    pub fn references(&self) -> BTreeMap<ProgramAddr, Vec<Reference>> {
+        let mut references = BTreeMap::<_, Vec<_>>::new();
+        for (&from, &inst) in &self.insts {
+            let (to, call) = match flow(from, inst) {
+                Flow::Jump(to) => (to, false),
+                Flow::Call(to) => (to, true),
+                _ => continue,
+            };
+            references
+                .entry(to)
+                .or_default()
+                .push(Reference { from, call });
+        }
+        references
+    }
+
+    /// reset vector, interrupt vector and every call target
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.functions.values()
+    }
+
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    /// who calls whom, between the functions of [`Self::functions`]
+    pub fn call_graph(&self) -> Vec<CallEdge> {
+        let mut edges = BTreeSet::new();
+        for f in self.functions.values() {
+            for b in f.blocks.iter().map(|b| &self.blocks[b]) {
+                if let Some(callee) = b.calls {
+                    edges.insert(CallEdge { caller: f.entry, callee, tail: false });
+                }
+                if let Flow::Jump(to) = flow(b.last, self.insts[&b.last]) {
+                    if to != f.entry && self.functions.contains_key(&to) {
+                        edges.insert(CallEdge { caller: f.entry, callee: to, tail: true });
+                    }
+                }
+            }
+        }
+        edges.into_iter().collect()
+    }
+
+    /// graphviz source of [`Self::call_graph`]. tail calls are drawn dashed.
+    pub fn call_graph_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph callgraph {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=monospace];").unwrap();
+        for &entry in self.functions.keys() {
+            let label = match entry {
+                RESET_VECTOR => " (reset)",
+                INTERRUPT_VECTOR => " (interrupt)",
+                _ => "",
+            };
+            writeln!(
+                dot,
+                "    f{:04x} [label=\"{:#06x}{label}\"];",
+                entry.0, entry.0
+            )
+            .unwrap();
+        }
+        for e in self.call_graph() {
+            let style = if e.tail { " [style=dashed]" } else { "" };
+            writeln!(
+                dot,
+                "    f{:04x} -> f{:04x}{style};",
+                e.caller.0, e.callee.0
+            )
+            .unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    /// functions called from the function starting at `entry`
+    fn callees(&self, entry: ProgramAddr) -> BTreeSet<ProgramAddr> {
+        self.functions.get(&entry).map_or_else(BTreeSet::new, |f| {
+            f.blocks
+                .iter()
+                .filter_map(|b| self.blocks[b].calls)
+                .collect()
+        })
+    }
+
+    /// deepest call chain from `entry`. records the cycles met on the way in `recursion`.
+    fn deepest_chain(
+        &self,
+        entry: ProgramAddr,
+        path: &mut Vec<ProgramAddr>,
+        memo: &mut BTreeMap<ProgramAddr, Vec<ProgramAddr>>,
+        recursion: &mut BTreeSet<Vec<ProgramAddr>>,
+    ) -> Vec<ProgramAddr> {
+        if let Some(chain) = memo.get(&entry) {
+            return chain.clone();
+        }
+        path.push(entry);
+        let mut deepest = vec![entry];
+        for callee in self.callees(entry) {
+            if let Some(i) = path.iter().position(|&f| f == callee) {
+                recursion.insert([&path[i..], &[callee]].concat());
+                continue;
+            }
+            let chain = self.deepest_chain(callee, path, memo, recursion);
+            if chain.len() + 1 > deepest.len() {
+                deepest = [&[entry], chain.as_slice()].concat();
+            }
+        }
+        path.pop();
+        memo.insert(entry, deepest.clone());
+        deepest
+    }
+
+    /// how deep the call stack can get, following every call from the reset and interrupt
+    /// vectors. indirect jumps (writes to PCL) are not followed.
+    pub fn stack_usage(&self) -> StackUsage {
+        let mut memo = BTreeMap::new();
+        let mut recursion = BTreeSet::new();
+        let mut deepest = |entry: ProgramAddr| {
+            if !self.functions.contains_key(&entry) {
+                return vec![];
+            }
+            self.deepest_chain(entry, &mut vec![], &mut memo, &mut recursion)
+        };
+
+        let main = deepest(RESET_VECTOR);
+        let enabled = self
+            .insts
+            .values()
+            .flatten()
+            .any(|i| may_enable_interrupts(*i));
+        let interrupt = if enabled {
+            deepest(INTERRUPT_VECTOR)
+        } else {
+            vec![]
+        };
+
+        // walk the chains that may go too deep, skipping those that can't
+        let budget = STACK_LEVELS.saturating_sub(interrupt.len());
+        let mut overflows = vec![];
+        let mut work = main
+            .first()
+            .map(|&f| vec![f])
+            .into_iter()
+            .collect::<Vec<_>>();
+        while let Some(path) = work.pop() {
+            let depth = path.len() - 1;
+            if depth > budget {
+                overflows.push(path);
+                continue;
+            }
+            let f = *path.last().unwrap();
+            if depth + memo[&f].len() - 1 <= budget {
+                continue;
+            }
+            for callee in self.callees(f).into_iter().rev() {
+                if !path.contains(&callee) {
+                    work.push([path.as_slice(), &[callee]].concat());
+                }
+            }
+        }
+
+        StackUsage {
+            deepest: main,
+            interrupt,
+            recursion: recursion.into_iter().collect(),
+            overflows,
+        }
+    }
+
+    /// graphviz source of the function starting at `entry`. back edges are drawn dashed.
+    pub fn to_dot(&self, entry: ProgramAddr) -> Option<String> {
+        let function = self.functions.get(&entry)?;
+        let is_back_edge = |from, to| self.loops.iter().any(|l| l.latch == from && l.header == to);
+
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"fn_{:04x}\" {{", entry.0).unwrap();
+        writeln!(dot, "    node [shape=box, fontname=monospace];").unwrap();
+        for b in function.blocks.iter().map(|b| &self.blocks[b]) {
+            write!(dot, "    b{:04x} [label=\"", b.start.0).unwrap();
+            for pc in (b.start.0..=b.last.0).map(ProgramAddr) {
+                match self.insts[&pc] {
+                    Some(inst) => write!(dot, "{:04x}: {inst:?}\\l", pc.0).unwrap(),
+                    None => write!(dot, "{:04x}: ???\\l", pc.0).unwrap(),
+                }
+            }
+            writeln!(dot, "\"];").unwrap();
+            for &s in &b.successors {
+                let style = if is_back_edge(b.start, s) {
+                    " [style=dashed]"
+                } else {
+                    ""
+                };
+                writeln!(dot, "    b{:04x} -> b{:04x}{style};", b.start.0, s.0).unwrap();
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        Some(dot)
+    }
+}
+
+/// runs of `retlw` in `flash` that look like lookup tables rather than code: 4 or more in a row,
+/// or 2 or more right after a computed goto (a write to PCL)
+pub fn find_tables(flash: &[u8]) -> Vec<Range<ProgramAddr>> {
+    let decode =
+        |at: usize| Instruction::from_code(u16::from_le_bytes([flash[at * 2], flash[at * 2 + 1]]));
+    let is_retlw = |at: usize| {
+        matches!(
+            decode(at),
+            Some(Instruction::LiteralOriented(l))
+                if l.op == LiteralOrientedOperation::ReturnWithLiteralInW
+        )
+    };
+
+    let words = flash.len() / 2;
+    let mut tables = vec![];
+    let mut at = 0;
+    while at < words {
+        if !is_retlw(at) {
+            at += 1;
+            continue;
+        }
+        let start = at;
+        while at < words && is_retlw(at) {
+            at += 1;
+        }
+        let computed =
+            start > 0 && decode(start - 1).is_some_and(|x| x.writes_register() == Some(PCL));
+        if at - start >= 4 || (computed && at - start >= 2) {
+            tables.push(ProgramAddr(start as u16)..ProgramAddr(at as u16));
+        }
+    }
+    tables
+}
+
+/// counts how many times each loop jumped back to its header while the vm ran
+#[derive(Debug, Clone, Default)]
+pub struct LoopTrips {
+    /// (address of the jumping instruction, header)
+    back_edges: BTreeSet<(u16, u16)>,
+    prev_pc: Option<u16>,
+    trips: BTreeMap<ProgramAddr, u64>,
+}
+
+impl LoopTrips {
+    pub fn new(cfg: &ControlFlowGraph) -> Self {
+        let back_edges = cfg
+            .loops()
+            .iter()
+            .map(|l| (cfg.blocks[&l.latch].last.0, l.header.0))
+            .collect();
+        Self { back_edges, ..Default::default() }
+    }
+
+    /// trip counts keyed by loop header
+    pub fn trips(&self) -> &BTreeMap<ProgramAddr, u64> {
+        &self.trips
+    }
+}
+
+impl Ticker for LoopTrips {
+    fn tick(&mut self, vm: &P16F88, _cycles: u8) {
+        let pc = vm.pc();
+        if let Some(prev) = self.prev_pc.replace(pc) {
+            if self.back_edges.contains(&(prev, pc)) {
+                *self.trips.entry(ProgramAddr(pc)).or_default() += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn assemble(words: &[u16]) -> Vec<u8> {
+    let mut flash = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect::<Vec<_>>();
+    flash.resize(7168, 0);
+    flash
+}
+
+#[test]
+fn finds_blocks_and_loops() {
+    #[rustfmt::skip]
+    let flash = assemble(&[
+        /* 0x0000 */ 0b10_1000_0000_0101, // goto 0x0005
+        /* 0x0001 */ 0b11_0100_0000_0000, // retlw 0 (never reached)
+        /* 0x0002 */ 0,
+        /* 0x0003 */ 0,
+        /* 0x0004 */ 0b00_0000_0000_1001, // retfie
+        /* 0x0005 */ 0b11_0000_0000_0011, // movlw 3
+        /* 0x0006 */ 0b00_0000_1010_0000, // movwf 0x20
+        /* 0x0007 */ 0b10_0000_0000_1011, // call 0x000b
+        /* 0x0008 */ 0b00_1011_1010_0000, // decfsz 0x20, f
+        /* 0x0009 */ 0b10_1000_0000_0111, // goto 0x0007
+        /* 0x000a */ 0b00_0000_0110_0011, // sleep
+        /* 0x000b */ 0b00_0000_0000_1000, // return
+    ]);
+    let cfg = ControlFlowGraph::build(&flash);
+    let a = ProgramAddr;
+
+    let starts = cfg.blocks().map(|b| b.start).collect::<Vec<_>>();
+    #[rustfmt::skip]
+    assert_eq!(
+        starts,
+        [a(0x00), a(0x04), a(0x05), a(0x07), a(0x08), a(0x09), a(0x0a), a(0x0b)]
+    );
+    assert!(cfg.block_at(a(0x01)).is_none());
+    assert!(!cfg.is_reachable(a(0x01)) && cfg.is_reachable(a(0x06)));
+    assert_eq!(cfg.block_at(a(0x06)).unwrap().start, a(0x05));
+    assert_eq!(cfg.block_at(a(0x07)).unwrap().calls, Some(a(0x0b)));
+    assert_eq!(cfg.block_at(a(0x0a)).unwrap().successors, [a(0x0b)]);
+
+    let entries = cfg.functions().map(|f| f.entry).collect::<Vec<_>>();
+    assert_eq!(entries, [a(0x00), a(0x04), a(0x0b)]);
+
+    assert_eq!(
+        cfg.loops(),
+        [Loop {
+            header: a(0x07),
+            latch: a(0x09),
+            body: [a(0x07), a(0x08), a(0x09)].into()
+        }]
+    );
+
+    let references = cfg.references();
+    let reference = |from, call| Reference { from: a(from), call };
+    assert_eq!(references[&a(0x05)], [reference(0x00, false)]);
+    assert_eq!(references[&a(0x07)], [reference(0x09, false)]);
+    assert_eq!(references[&a(0x0b)], [reference(0x07, true)]);
+    assert_eq!(references.len(), 3);
+
+    let dot = cfg.to_dot(a(0x00)).unwrap();
+    assert!(dot.contains("b0009 -> b0007 [style=dashed];"), "{dot}");
+}
+
+#[test]
+fn finds_stack_overflows() {
+    // 0x0020 + 2k calls 0x0020 + 2(k + 1), 8 functions deep
+    let mut words = vec![0; 0x42];
+    words[0x00] = 0x2810; // goto 0x0010
+    words[0x04] = 0x2030; // call 0x0030
+    words[0x05] = 0x0009; // retfie
+    words[0x10] = 0x178B; // bsf INTCON, GIE
+    words[0x11] = 0x2020; // call 0x0020
+    words[0x12] = 0x2040; // call 0x0040
+    words[0x13] = 0x2813; // goto 0x0013
+    for k in 0..8 {
+        let at = 0x20 + 2 * k;
+        words[at] = if k < 7 {
+            0x2000 | (at as u16 + 2)
+        } else {
+            0x0008
+        };
+        words[at + 1] = 0x0008; // return
+    }
+    words[0x30] = 0x0008; // return
+    words[0x40] = 0x2040; // call 0x0040
+    words[0x41] = 0x0008; // return
+    let cfg = ControlFlowGraph::build(&assemble(&words));
+    let a = ProgramAddr;
+    let nested = |n: u16| (0..n).map(|k| a(0x20 + 2 * k));
+
+    let usage = cfg.stack_usage();
+    assert_eq!(
+        usage.deepest,
+        [a(0x00)].into_iter().chain(nested(8)).collect::<Vec<_>>()
+    );
+    assert_eq!(usage.interrupt, [a(0x04), a(0x30)]);
+    assert_eq!(usage.max_depth(), 10);
+    assert_eq!(usage.recursion, [vec![a(0x40), a(0x40)]]);
+    // the interrupt takes 2 levels, so 7 calls deep is too deep
+    assert_eq!(
+        usage.overflows,
+        [[a(0x00)].into_iter().chain(nested(7)).collect::<Vec<_>>()]
+    );
+    assert!(usage.to_string().contains(
+        "overflows the 8-level stack: 0x0000 > 0x0020 > 0x0022 > 0x0024 > 0x0026 > 0x0028 > \
+         0x002a > 0x002c\n"
+    ));
+
+    // without GIE nothing overflows
+    words[0x10] = 0x0000;
+    let usage = ControlFlowGraph::build(&assemble(&words)).stack_usage();
+    assert_eq!((usage.max_depth(), usage.overflows.len()), (8, 0));
+    assert!(usage.to_string().contains("  interrupt: never enabled\n"));
+}
+
+#[test]
+fn builds_the_call_graph() {
+    #[rustfmt::skip]
+    let flash = assemble(&[
+        /* 0x0000 */ 0b10_0000_0000_0101, // call 0x0005
+        /* 0x0001 */ 0b10_0000_0000_0111, // call 0x0007
+        /* 0x0002 */ 0b10_1000_0000_0010, // goto 0x0002
+        /* 0x0003 */ 0,
+        /* 0x0004 */ 0b00_0000_0000_1001, // retfie
+        /* 0x0005 */ 0b00_0001_1010_0000, // clrf 0x20
+        /* 0x0006 */ 0b10_1000_0000_0111, // goto 0x0007
+        /* 0x0007 */ 0b00_0000_0000_1000, // return
+    ]);
+    let cfg = ControlFlowGraph::build(&flash);
+    let a = ProgramAddr;
+    let edge = |caller, callee, tail| CallEdge { caller: a(caller), callee: a(callee), tail };
+
+    assert_eq!(
+        cfg.call_graph(),
+        [
+            edge(0x00, 0x05, false),
+            edge(0x00, 0x07, false),
+            edge(0x05, 0x07, true)
+        ]
+    );
+    let dot = cfg.call_graph_dot();
+    assert!(dot.contains("f0000 [label=\"0x0000 (reset)\"];"), "{dot}");
+    assert!(dot.contains("f0005 -> f0007 [style=dashed];"), "{dot}");
+}
+
+#[test]
+fn finds_retlw_tables() {
+    #[rustfmt::skip]
+    let flash = assemble(&[
+        /* 0x0000 */ 0x0782, // addwf PCL, f
+        /* 0x0001 */ 0x3401, // retlw 1
+        /* 0x0002 */ 0x3402, // retlw 2
+        /* 0x0003 */ 0x0008, // return
+        /* 0x0004 */ 0x3401, // retlw 1
+        /* 0x0005 */ 0x3402, // retlw 2
+        /* 0x0006 */ 0x3400, // retlw 0
+        /* 0x0007 */ 0x0000,
+        /* 0x0008 */ 0x3448, // retlw 'H'
+        /* 0x0009 */ 0x3449, // retlw 'I'
+        /* 0x000a */ 0x3421, // retlw '!'
+        /* 0x000b */ 0x3400, // retlw 0
+    ]);
+    let a = ProgramAddr;
+    assert_eq!(find_tables(&flash), [a(0x01)..a(0x03), a(0x08)..a(0x0c)]);
+}
@@ -0,0 +1,221 @@
+//! what an HD44780 shows over a run, as an animated GIF or APNG for bug reports and docs.
+//! [`LcdRecording::record`] the LCD as the run goes and write the frames out when it stops. the
+//! animation plays at the speed of the simulation.
+
+use std::io::Write;
+use std::time::Duration;
+
+use stk_hd44780_vm::{glyph, Cursor, Hd44780};
+
+/// what the LCD showed from `at` until the next frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// simulated time since the recording started
+    pub at: Duration,
+    /// character codes of each line
+    pub codes: Vec<Vec<u8>>,
+    pub display_on: bool,
+    /// drawn as its underline. the blinking isn't animated
+    pub cursor: Option<Cursor>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("gif: {0}")]
+    Gif(#[from] gif::EncodingError),
+
+    #[error("png: {0}")]
+    Png(#[from] png::EncodingError),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LcdRecording {
+    /// oldest first, each differing from the one before
+    pub frames: Vec<Frame>,
+}
+
+/// dots of the image per dot of the LCD
+const SCALE: usize = 3;
+/// around the characters, in LCD dots
+const MARGIN: usize = 3;
+
+/// backlight, dot off, dot on
+const PALETTE: [u8; 9] = [0x9c, 0xc8, 0x3c, 0x8c, 0xb8, 0x30, 0x20, 0x30, 0x10];
+const BACKLIGHT: u8 = 0;
+const OFF: u8 = 1;
+const ON: u8 = 2;
+
+impl LcdRecording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds what `lcd` shows at `at`, unless it's the same as the last frame
+    pub fn record(&mut self, at: Duration, lcd: &Hd44780) {
+        let (codes, display_on, cursor) = (lcd.codes(), lcd.display_on(), lcd.cursor());
+        let same = |x: &Frame| x.codes == codes && x.display_on == display_on && x.cursor == cursor;
+        if !self.frames.last().is_some_and(same) {
+            self.frames.push(Frame { at, codes, display_on, cursor });
+        }
+    }
+
+    /// the frames as an endlessly looping GIF, the last one shown until `end`
+    pub fn write_gif(&self, end: Duration, out: impl Write) -> Result<(), Error> {
+        let (width, height) = self.size();
+        let mut encoder = gif::Encoder::new(out, width as u16, height as u16, &PALETTE)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for (frame, delay) in self.timeline(end) {
+            let mut image =
+                gif::Frame::from_indexed_pixels(width as u16, height as u16, render(frame), None);
+            image.delay = delay;
+            encoder.write_frame(&image)?;
+        }
+        Ok(())
+    }
+
+    /// the frames as an endlessly looping APNG, the last one shown until `end`
+    pub fn write_apng(&self, end: Duration, out: impl Write) -> Result<(), Error> {
+        let timeline = self.timeline(end);
+        let (width, height) = self.size();
+        let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_palette(&PALETTE[..]);
+        encoder.set_animated(timeline.len() as u32, 0)?;
+        let mut writer = encoder.write_header()?;
+        for (frame, delay) in timeline {
+            writer.set_frame_delay(delay, 100)?;
+            writer.write_image_data(&render(frame))?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// of the images, for the panel of the first frame
+    fn size(&self) -> (usize, usize) {
+        self.frames
+            .first()
+            .map_or(size(16, 2), |x| size(x.codes[0].len(), x.codes.len()))
+    }
+
+    /// the frames with how long each is shown, in hundredths of a second as both formats can have
+    /// it. changes closer together than that only show the last of them, and a frame longer than
+    /// a delay can be is repeated
+    fn timeline(&self, end: Duration) -> Vec<(&Frame, u16)> {
+        let centis = |t: Duration| (t.as_millis() / 10) as u64;
+        let mut timeline = vec![];
+        for (i, frame) in self.frames.iter().enumerate() {
+            let next = self.frames.get(i + 1).map_or(end, |x| x.at);
+            let mut delay = centis(next).saturating_sub(centis(frame.at));
+            // the last frame stays up for a moment even if the run ended right after it
+            if i + 1 == self.frames.len() {
+                delay = delay.max(1);
+            }
+            while delay > 0 {
+                let d = delay.min(u16::MAX as u64);
+                timeline.push((frame, d as u16));
+                delay -= d;
+            }
+        }
+        timeline
+    }
+}
+
+/// of the image of a panel of `columns` by `lines` characters
+fn size(columns: usize, lines: usize) -> (usize, usize) {
+    let width = columns * 6 - 1 + 2 * MARGIN;
+    let height = lines * 9 - 1 + 2 * MARGIN;
+    (width * SCALE, height * SCALE)
+}
+
+/// the LCD as palette indices, row by row
+fn render(frame: &Frame) -> Vec<u8> {
+    let (width, height) = size(frame.codes[0].len(), frame.codes.len());
+    let mut dots = vec![BACKLIGHT; (width / SCALE) * (height / SCALE)];
+    for (row, codes) in frame.codes.iter().enumerate() {
+        for (col, &code) in codes.iter().enumerate() {
+            let mut glyph = glyph(code);
+            // the cursor takes the 8th row
+            if frame
+                .cursor
+                .is_some_and(|x| x.underline && (x.line, x.column) == (row, col))
+            {
+                glyph[7] = 0x1f;
+            }
+            for (y, bits) in glyph.iter().enumerate() {
+                for x in 0..5 {
+                    let on = frame.display_on && bits >> (4 - x) & 1 != 0;
+                    let dot = (MARGIN + row * 9 + y) * (width / SCALE) + MARGIN + col * 6 + x;
+                    dots[dot] = if on { ON } else { OFF };
+                }
+            }
+        }
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for line in dots.chunks(width / SCALE) {
+        let line = line.iter().flat_map(|&x| [x; SCALE]).collect::<Vec<_>>();
+        for _ in 0..SCALE {
+            pixels.extend_from_slice(&line);
+        }
+    }
+    pixels
+}
+
+#[test]
+fn keeps_changes_apart_by_a_hundredth() {
+    use stk_hd44780_vm::{Hd44780PinState, PinObserver};
+
+    let mut lcd = Hd44780::new();
+    let mut recording = LcdRecording::new();
+    recording.record(Duration::ZERO, &lcd);
+    recording.record(Duration::from_millis(5), &lcd);
+    assert_eq!(recording.frames.len(), 1);
+
+    // display on, then 'A' a millisecond later. the LCD is in 8-bit mode out of reset
+    let send = |lcd: &mut Hd44780, rs: bool, byte: u8| {
+        let bit = |i: u8| Some(byte >> i & 1 != 0);
+        for e in [true, false] {
+            lcd.update(Hd44780PinState {
+                rs: Some(rs),
+                rw: Some(false),
+                e: Some(e),
+                db7: bit(7),
+                db6: bit(6),
+                db5: bit(5),
+                db4: bit(4),
+                db3: bit(3),
+                db2: bit(2),
+                db1: bit(1),
+                db0: bit(0),
+            });
+        }
+    };
+    send(&mut lcd, false, 0b0000_1100);
+    recording.record(Duration::from_millis(20), &lcd);
+    send(&mut lcd, true, b'A');
+    recording.record(Duration::from_millis(21), &lcd);
+    assert_eq!(recording.frames.len(), 3);
+    assert_eq!(recording.frames[2].codes[0][0], b'A');
+
+    let timeline = recording.timeline(Duration::from_secs(1));
+    let delays = timeline
+        .iter()
+        .map(|&(frame, delay)| (frame.at, delay))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        delays,
+        [(Duration::ZERO, 2), (Duration::from_millis(21), 98)]
+    );
+
+    let mut gif = vec![];
+    recording
+        .write_gif(Duration::from_secs(1), &mut gif)
+        .unwrap();
+    assert!(gif.starts_with(b"GIF89a"));
+    let mut apng = vec![];
+    recording
+        .write_apng(Duration::from_secs(1), &mut apng)
+        .unwrap();
+    assert!(apng.starts_with(b"\x89PNG"));
+}
@@ -0,0 +1,511 @@
+//! assembler for pic-as style source, so small test programs can be written without a
+//! toolchain and fed to the VM, or through [`encode_intel_hex`](crate::hex::encode_intel_hex)
+//! to anything that takes a hex.
+//!
+//! one statement per line: an optional `label:`, then an instruction or a directive. `;` starts
+//! a comment. operands are numbers (`42`, `0x2A`, `2Ah`, `0b101010`, `'A'`), symbols and `$`
+//! (the address of the statement), joined with `+` and `-`. `w` and `f` are 0 and 1, and the
+//! special function registers of the PIC16F88 are predefined by their upper-case names.
+//!
+//! directives: `org address`, `name equ value`, `dw value, ...`, `dt "text", value, ...` (a
+//! `retlw` for each byte) and `end`.
+
+use std::collections::HashMap;
+
+use crate::hex::MemoryImage;
+use crate::inst::{
+    BitIndex, BitOrientedInstruction, ByteOrientedInstruction, ControlInstruction, Destination,
+    Instruction, LiteralOrientedInstruction, LiteralOrientedOperation, ProgramAddr,
+    RegisterFileAddr,
+};
+use crate::symbols::SymbolTable;
+use crate::vm::device::{self, Slot};
+
+#[derive(Debug, thiserror::Error)]
+#[error("line {line}: {kind}")]
+pub struct Error {
+    /// from 1
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    #[error("unknown instruction or directive `{0}`")]
+    UnknownMnemonic(String),
+
+    #[error("undefined symbol `{0}`")]
+    UndefinedSymbol(String),
+
+    #[error("`{0}` is already defined")]
+    Redefined(String),
+
+    #[error("invalid expression `{0}`")]
+    InvalidExpression(String),
+
+    #[error("expected {expected} operands, found {found}")]
+    OperandCount {
+        expected: &'static str,
+        found: usize,
+    },
+
+    #[error("{value} is out of range for {what}")]
+    OutOfRange { value: i64, what: &'static str },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    /// words at their byte addresses, like a decoded hex
+    pub memory: MemoryImage,
+    /// the labels
+    pub symbols: SymbolTable,
+}
+
+struct Statement<'a> {
+    line: usize,
+    label: Option<&'a str>,
+    /// lower-cased mnemonic or directive
+    op: Option<String>,
+    operands: Vec<&'a str>,
+}
+
+struct Assembler {
+    /// mnemonic -> an instruction of that kind, with operands to be replaced
+    templates: HashMap<&'static str, Instruction>,
+    symbols: HashMap<String, i64>,
+    predefined: HashMap<String, i64>,
+}
+
+pub fn assemble(source: &str) -> Result<Assembly> {
+    let statements = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse_line(i + 1, line))
+        .collect::<Vec<_>>();
+
+    let mut asm = Assembler::new();
+    let mut labels = SymbolTable::new();
+
+    // addresses of the labels
+    let mut here = 0u16;
+    for s in &statements {
+        let error = |kind| Error { line: s.line, kind };
+        if let Some(label) = s.label {
+            asm.define(label, here as i64).map_err(error)?;
+            labels.insert_code(ProgramAddr(here), label);
+        }
+        let Some(op) = s.op.as_deref() else {
+            continue;
+        };
+        match op {
+            "end" => break,
+            "org" => here = asm.address(&s.operands, here).map_err(error)?,
+            "equ" => {
+                let value = asm.eval(s.operands[1], here).map_err(error)?;
+                asm.define(s.operands[0], value).map_err(error)?;
+            }
+            "dw" => here = advance(here, s.operands.len()).map_err(error)?,
+            "dt" => here = advance(here, table_len(&s.operands)).map_err(error)?,
+            _ if asm.templates.contains_key(op) => here = advance(here, 1).map_err(error)?,
+            _ => return Err(error(ErrorKind::UnknownMnemonic(op.to_owned()))),
+        }
+    }
+
+    let mut memory = MemoryImage::new();
+    let mut here = 0u16;
+    for s in &statements {
+        let error = |kind| Error { line: s.line, kind };
+        let Some(op) = s.op.as_deref() else {
+            continue;
+        };
+        let words = match op {
+            "end" => break,
+            "org" => {
+                here = asm.address(&s.operands, here).map_err(error)?;
+                continue;
+            }
+            "equ" => continue,
+            "dw" => s
+                .operands
+                .iter()
+                .map(|x| {
+                    asm.eval(x, here)
+                        .and_then(|x| in_range(x, 0, 0x3FFF, "a word"))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(error)?,
+            "dt" => asm.table(&s.operands, here).map_err(error)?,
+            _ => vec![asm
+                .instruction(op, &s.operands, here)
+                .map_err(error)?
+                .to_code()],
+        };
+        for word in &words {
+            memory.write(here as u32 * 2, &word.to_le_bytes());
+            here += 1;
+        }
+    }
+
+    Ok(Assembly { memory, symbols: labels })
+}
+
+fn parse_line(line: usize, text: &str) -> Statement<'_> {
+    let text = split_unquoted(text, ';')[0].trim();
+    let (label, rest) = match text.split_once(':') {
+        Some((label, rest)) if is_symbol(label) => (Some(label), rest.trim()),
+        _ => (None, text),
+    };
+
+    let mut statement = Statement { line, label, op: None, operands: vec![] };
+    if rest.is_empty() {
+        return statement;
+    }
+    let (first, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let operands = operands.trim();
+    match operands.split_once(char::is_whitespace) {
+        // `name equ value`
+        Some((equ, value)) if equ.eq_ignore_ascii_case("equ") => {
+            statement.op = Some("equ".to_owned());
+            statement.operands = vec![first, value.trim()];
+        }
+        _ => {
+            statement.op = Some(first.to_ascii_lowercase());
+            if !operands.is_empty() {
+                statement.operands = split_unquoted(operands, ',')
+                    .into_iter()
+                    .map(|x| x.trim())
+                    .collect();
+            }
+        }
+    }
+    statement
+}
+
+/// splits at `sep` outside of `"..."` and `'...'`
+pub(crate) fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == sep => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            None => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+pub(crate) fn string_operand(operand: &str) -> Option<&str> {
+    operand.strip_prefix('"')?.strip_suffix('"')
+}
+
+/// words a `dt` takes
+fn table_len(operands: &[&str]) -> usize {
+    operands
+        .iter()
+        .map(|x| string_operand(x).map_or(1, |x| x.len()))
+        .sum()
+}
+
+/// whether `s` can name a label or a constant
+pub fn is_symbol(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|x| x.is_ascii_alphabetic() || x == '_')
+        && chars.all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+fn advance(here: u16, words: usize) -> Result<u16, ErrorKind> {
+    let next = here as i64 + words as i64;
+    in_range(next, 0, 0x7FFF, "a program address")
+}
+
+fn in_range(value: i64, min: i64, max: i64, what: &'static str) -> Result<u16, ErrorKind> {
+    if (min..=max).contains(&value) {
+        Ok(value as u16)
+    } else {
+        Err(ErrorKind::OutOfRange { value, what })
+    }
+}
+
+impl Assembler {
+    fn new() -> Self {
+        let mut templates = HashMap::new();
+        for code in 0..0x4000 {
+            if let Some(inst) = Instruction::from_code(code) {
+                templates.entry(inst.mnemonic()).or_insert(inst);
+            }
+        }
+
+        let mut predefined = HashMap::from([("w".to_owned(), 0), ("f".to_owned(), 1)]);
+        for (bank, slots) in device::P16F88.map.iter().enumerate() {
+            for (addr, slot) in slots.iter().enumerate() {
+                let Slot::Special(sfr) = slot else {
+                    continue;
+                };
+                let name = match sfr.name() {
+                    "unimpl" | "reserv" => continue,
+                    "iaddr" => "indf",
+                    name => name,
+                };
+                predefined
+                    .entry(name.to_ascii_uppercase())
+                    .or_insert((bank * 0x80 + addr) as i64);
+            }
+        }
+
+        Self { templates, symbols: HashMap::new(), predefined }
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), ErrorKind> {
+        if self.symbols.insert(name.to_owned(), value).is_some() {
+            return Err(ErrorKind::Redefined(name.to_owned()));
+        }
+        Ok(())
+    }
+
+    fn address(&self, operands: &[&str], here: u16) -> Result<u16, ErrorKind> {
+        let [address] = operands else {
+            return Err(ErrorKind::OperandCount { expected: "1", found: operands.len() });
+        };
+        in_range(self.eval(address, here)?, 0, 0x7FFF, "a program address")
+    }
+
+    /// terms joined with `+` and `-`
+    fn eval(&self, expr: &str, here: u16) -> Result<i64, ErrorKind> {
+        let invalid = || ErrorKind::InvalidExpression(expr.to_owned());
+        let spaced = expr.replace('-', "+-");
+        let mut terms = spaced.split('+').map(|x| x.trim());
+        // a leading sign
+        if expr.trim_start().starts_with(['+', '-']) {
+            terms.next();
+        }
+
+        let mut value = 0;
+        for term in terms {
+            let (negative, term) = match term.strip_prefix('-') {
+                Some(term) => (true, term.trim()),
+                None => (false, term),
+            };
+            let v = if term == "$" {
+                here as i64
+            } else if term.starts_with(|x: char| x.is_ascii_digit()) {
+                parse_number(term).ok_or_else(invalid)?
+            } else if let Some(c) = term.strip_prefix('\'').and_then(|x| x.strip_suffix('\'')) {
+                let mut chars = c.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii() => c as i64,
+                    _ => return Err(invalid()),
+                }
+            } else if is_symbol(term) {
+                *self
+                    .symbols
+                    .get(term)
+                    .or_else(|| self.predefined.get(term))
+                    .ok_or_else(|| ErrorKind::UndefinedSymbol(term.to_owned()))?
+            } else {
+                return Err(invalid());
+            };
+            value += if negative { -v } else { v };
+        }
+        Ok(value)
+    }
+
+    /// a `retlw` for each byte of the strings and each value
+    fn table(&self, operands: &[&str], here: u16) -> Result<Vec<u16>, ErrorKind> {
+        let mut bytes = vec![];
+        for operand in operands {
+            match string_operand(operand) {
+                Some(text) => bytes.extend(text.bytes()),
+                None => {
+                    let k = self.eval(operand, here)?;
+                    bytes.push(in_range(k, -128, 255, "a literal")? as u8);
+                }
+            }
+        }
+        let op = LiteralOrientedOperation::ReturnWithLiteralInW;
+        Ok(bytes
+            .into_iter()
+            .map(|k| Instruction::LiteralOriented(LiteralOrientedInstruction { op, k }).to_code())
+            .collect())
+    }
+
+    fn instruction(
+        &self,
+        op: &str,
+        operands: &[&str],
+        here: u16,
+    ) -> Result<Instruction, ErrorKind> {
+        let template = self.templates[op];
+        let values = operands
+            .iter()
+            .map(|x| self.eval(x, here))
+            .collect::<Result<Vec<_>, _>>()?;
+        let count = |expected| ErrorKind::OperandCount { expected, found: values.len() };
+        let file =
+            |f| in_range(f, 0, 0x1FF, "a register").map(|x| RegisterFileAddr(x as u8 & 0x7F));
+        let program =
+            |k| in_range(k, 0, 0x1FFF, "a program address").map(|x| ProgramAddr(x & 0x7FF));
+
+        Ok(match template {
+            Instruction::ByteOriented(ByteOrientedInstruction { op, .. }) => {
+                let (f, d) = match values[..] {
+                    [f] => (f, 1),
+                    [f, d] => (f, d),
+                    _ => return Err(count("1 or 2")),
+                };
+                let dest = match in_range(d, 0, 1, "a destination")? {
+                    0 => Destination::W,
+                    _ => Destination::F,
+                };
+                Instruction::ByteOriented(ByteOrientedInstruction { op, f: file(f)?, dest })
+            }
+            Instruction::BitOriented(BitOrientedInstruction { op, .. }) => {
+                let [f, b] = values[..] else {
+                    return Err(count("2"));
+                };
+                let b = BitIndex(in_range(b, 0, 7, "a bit")? as u8);
+                Instruction::BitOriented(BitOrientedInstruction { op, b, f: file(f)? })
+            }
+            Instruction::LiteralOriented(LiteralOrientedInstruction { op, .. }) => {
+                let [k] = values[..] else {
+                    return Err(count("1"));
+                };
+                let k = in_range(k, -128, 255, "a literal")? as u8;
+                Instruction::LiteralOriented(LiteralOrientedInstruction { op, k })
+            }
+            Instruction::Control(c) => {
+                let c = match (c, &values[..]) {
+                    (ControlInstruction::Goto { .. }, &[k]) => {
+                        ControlInstruction::Goto { addr: program(k)? }
+                    }
+                    (ControlInstruction::Call { .. }, &[k]) => {
+                        ControlInstruction::Call { addr: program(k)? }
+                    }
+                    (ControlInstruction::ClearF { .. }, &[f]) => {
+                        ControlInstruction::ClearF { f: file(f)? }
+                    }
+                    (ControlInstruction::MoveWtoF { .. }, &[f]) => {
+                        ControlInstruction::MoveWtoF { f: file(f)? }
+                    }
+                    (
+                        ControlInstruction::Goto { .. }
+                        | ControlInstruction::Call { .. }
+                        | ControlInstruction::ClearF { .. }
+                        | ControlInstruction::MoveWtoF { .. },
+                        _,
+                    ) => return Err(count("1")),
+                    (c, []) => c,
+                    _ => return Err(count("0")),
+                };
+                Instruction::Control(c)
+            }
+        })
+    }
+}
+
+pub(crate) fn parse_number(s: &str) -> Option<i64> {
+    let s = s.to_ascii_lowercase();
+    if let Some(hex) = s.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else if let Some(hex) = s.strip_suffix('h') {
+        i64::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+#[test]
+fn assembles_a_program() {
+    let source = "
+counter equ 0x20        ; a variable
+        org 0
+        goto start
+        org 4
+        retfie
+start:  movlw 5
+        movwf counter
+loop:   decfsz counter, f
+        goto $-1
+        bsf STATUS, 5   ; bank 1
+        clrf TRISB
+        addlw -1
+        call loop + 1
+        end
+        nop
+";
+    let asm = assemble(source).unwrap();
+    let words = (0..14).map(|i| asm.memory.word(i)).collect::<Vec<_>>();
+    #[rustfmt::skip]
+    assert_eq!(
+        words,
+        [
+            Some(0x2805), None, None, None,
+            Some(0x0009), Some(0x3005), Some(0x00A0), Some(0x0BA0), Some(0x2807),
+            Some(0x1683), Some(0x0186), Some(0x3EFF), Some(0x2008), None,
+        ]
+    );
+    assert_eq!(asm.symbols.code_name(ProgramAddr(7)), Some("loop"));
+
+    let table = assemble("table: addwf PCL, f\n dt \"a;b, c\", 0, ';' ; text\n").unwrap();
+    let words = (0..8).map(|i| table.memory.word(i)).collect::<Vec<_>>();
+    #[rustfmt::skip]
+    assert_eq!(
+        words,
+        [
+            Some(0x0782), Some(0x3461), Some(0x343B), Some(0x3462), Some(0x342C),
+            Some(0x3420), Some(0x3463), Some(0x3400),
+        ]
+    );
+    assert_eq!(table.memory.word(8), Some(0x343B));
+
+    let config = assemble("org 0x2007\ndw 0x3F38, 0b11\n").unwrap();
+    assert_eq!(config.memory.word(0x2007), Some(0x3F38));
+    assert_eq!(config.memory.word(0x2008), Some(0x0003));
+}
+
+#[test]
+fn reports_errors_by_line() {
+    let error = |source| assemble(source).unwrap_err().to_string();
+    assert_eq!(
+        error("nop\nmovlf 1"),
+        "line 2: unknown instruction or directive `movlf`"
+    );
+    assert_eq!(error("goto nowhere"), "line 1: undefined symbol `nowhere`");
+    assert_eq!(error("a: nop\na: nop"), "line 2: `a` is already defined");
+    assert_eq!(
+        error("movlw 256"),
+        "line 1: 256 is out of range for a literal"
+    );
+    assert_eq!(error("bcf 0x20"), "line 1: expected 2 operands, found 1");
+    assert_eq!(error("movlw 1 ** 2"), "line 1: invalid expression `1 ** 2`");
+}
+
+#[test]
+fn reads_what_instructions_display() {
+    let codes = (0..0x4000)
+        .filter(|&x| Instruction::from_code(x).is_some_and(|i| i.to_code() == x))
+        .collect::<Vec<_>>();
+    let source = codes
+        .iter()
+        .map(|&x| Instruction::from_code(x).unwrap().to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let asm = assemble(&source).unwrap();
+    for (i, &code) in codes.iter().enumerate() {
+        assert_eq!(asm.memory.word(i as u16), Some(code), "{code:#06x}");
+    }
+}
@@ -1,46 +1,118 @@
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::PathBuf;
 
 use clap::Parser;
-use stk_pic_vm::inst::{
-    BitOrientedInstruction, ByteOrientedInstruction, ControlInstruction, Instruction,
-};
+use stk_pic_vm::analysis::{find_tables, ControlFlowGraph};
+use stk_pic_vm::asm::is_symbol;
+use stk_pic_vm::coff::SourceLines;
+use stk_pic_vm::hex::PROGRAM_END;
+use stk_pic_vm::inst::{ControlInstruction, Instruction, ProgramAddr, RegisterFileAddr};
+use stk_pic_vm::symbols::SymbolTable;
 use stk_pic_vm::vm::p16f88;
 
 #[derive(Parser, Debug)]
 struct Args {
+    /// Intel HEX, or ELF if it ends in `.elf`
     file: PathBuf,
+
+    /// pic-as `.map` file of the firmware, to label functions and variables. replaces the symbols
+    /// of an ELF
+    #[arg(long, value_name = "PATH")]
+    map: Option<PathBuf>,
+
+    /// COFF (`.cof`) file of the firmware, to note where each source line starts
+    #[arg(long, value_name = "PATH")]
+    cof: Option<PathBuf>,
+
+    /// print the control flow graph of each function as graphviz instead
+    #[arg(long)]
+    cfg: bool,
+
+    /// print the call graph as graphviz instead
+    #[arg(long)]
+    callgraph: bool,
+
+    /// print how deep the call stack can get and the call chains that overflow it instead
+    #[arg(long)]
+    stack: bool,
 }
 
-fn format_instruction(inst: Instruction) -> String {
-    match inst {
-        Instruction::ByteOriented(ByteOrientedInstruction { op, f, dest }) => {
-            let name = p16f88::register_name_at(f).join(", ");
-            format!("{:?}: 0x{:02x}({name}) into {:?}", op, f.0, dest)
-        }
+/// names of the register at `f`, followed by the variable there if there is one
+fn register_name(f: RegisterFileAddr, symbols: &SymbolTable) -> String {
+    let mut names = p16f88::register_name_at(f);
+    names.extend(symbols.data_name(f).map(|x| x.to_owned()));
+    names.join(", ")
+}
 
-        Instruction::BitOriented(BitOrientedInstruction { op, b, f }) => {
-            let name = p16f88::register_name_at(f).join(", ");
-            format!("{:?}(0x{:02x}({})<{}>)", op, f.0, name, b.0)
+/// where a call or goto at `at` lands, assuming PCLATH points at the page it's in
+fn branch_target(at: u16, addr: ProgramAddr) -> u16 {
+    (at & 0x1800) | addr.0
+}
+
+/// labels for the listing: code symbols the assembler can read back, then `sub_0123` for the
+/// targets of calls and `loc_0123` for the targets of gotos
+fn labels(program: &BTreeMap<u16, u16>, symbols: &SymbolTable) -> BTreeMap<u16, String> {
+    let mut labels = BTreeMap::new();
+    let mut used = HashSet::new();
+    for &at in program.keys() {
+        let Some(name) = symbols.code_name(ProgramAddr(at)) else {
+            continue;
+        };
+        let register = ["w", "f"].iter().any(|x| name.eq_ignore_ascii_case(x));
+        if is_symbol(name) && !register && used.insert(name) {
+            labels.insert(at, name.to_owned());
         }
+    }
+    for (&at, &code) in program {
+        let Some(inst) = Instruction::from_code(code) else {
+            continue;
+        };
+        let prefix = match inst {
+            Instruction::Control(ControlInstruction::Call { .. }) => "sub",
+            Instruction::Control(ControlInstruction::Goto { .. }) => "loc",
+            _ => continue,
+        };
+        let target = branch_target(at, inst.branch_target().unwrap());
+        if program.contains_key(&target) {
+            labels
+                .entry(target)
+                .or_insert_with(|| format!("{prefix}_{target:04x}"));
+        }
+    }
+    labels
+}
 
-        l @ Instruction::LiteralOriented(_) => format!("{l:?}"),
+/// `"HELLO", 0x00`
+fn table_operands(bytes: &[u8]) -> String {
+    let mut operands = vec![];
+    let mut text = String::new();
+    for &b in bytes {
+        if (b' '..=b'~').contains(&b) && b != b'"' {
+            text.push(b as char);
+            continue;
+        }
+        if !text.is_empty() {
+            operands.push(format!("\"{text}\""));
+            text.clear();
+        }
+        operands.push(format!("0x{b:02x}"));
+    }
+    if !text.is_empty() {
+        operands.push(format!("\"{text}\""));
+    }
+    operands.join(", ")
+}
 
-        o @ Instruction::Control(c) => match c {
-            ControlInstruction::ClearF { f } => format!(
-                "ClearF(0x{:02x}({}))",
-                f.0,
-                p16f88::register_name_at(f).join(", ")
-            ),
-            ControlInstruction::MoveWtoF { f } => format!(
-                "MoveWtoF(0x{:02x}({}))",
-                f.0,
-                p16f88::register_name_at(f).join(", ")
-            ),
-            _ => format!("{o:?}"),
-        },
+fn format_instruction(inst: Instruction, at: u16, labels: &BTreeMap<u16, String>) -> String {
+    let label = inst
+        .branch_target()
+        .and_then(|addr| labels.get(&branch_target(at, addr)));
+    match label {
+        Some(label) => format!("{} {label}", inst.mnemonic()),
+        None => inst.to_string(),
     }
 }
 
@@ -50,42 +122,146 @@ fn main() {
         .init();
 
     let args = Args::parse();
-    let flash =
-        stk_pic_vm::hex::decode_intel_hex(BufReader::new(File::open(args.file).unwrap())).unwrap();
+    let (memory, elf_symbols) = if args.file.extension().is_some_and(|x| x == "elf") {
+        let image = stk_pic_vm::elf::load_elf(&std::fs::read(&args.file).unwrap()).unwrap();
+        (image.memory, image.symbols)
+    } else {
+        let memory =
+            stk_pic_vm::hex::decode_intel_hex(BufReader::new(File::open(&args.file).unwrap()))
+                .unwrap();
+        (memory, SymbolTable::new())
+    };
+    let flash = memory.program_flash(memory.program_len());
+    let symbols = match &args.map {
+        Some(path) => SymbolTable::from_map(BufReader::new(File::open(path).unwrap())).unwrap(),
+        None => elf_symbols,
+    };
+    let lines = match &args.cof {
+        Some(path) => SourceLines::from_coff(&std::fs::read(path).unwrap()).unwrap(),
+        None => SourceLines::new(),
+    };
 
-    let mut noop = None;
+    if args.cfg {
+        let cfg = ControlFlowGraph::build(&flash);
+        for function in cfg.functions() {
+            print!("{}", cfg.to_dot(function.entry).unwrap());
+        }
+        return;
+    }
+
+    if args.callgraph {
+        print!("{}", ControlFlowGraph::build(&flash).call_graph_dot());
+        return;
+    }
 
-    for (i, instruction) in flash.chunks(2).enumerate() {
-        let &[a, b] = instruction else { unreachable!() };
+    if args.stack {
+        print!("{}", ControlFlowGraph::build(&flash).stack_usage());
+        return;
+    }
 
-        let instruction = ((b as u16) << 8) | (a as u16);
-        let decoded = stk_pic_vm::inst::Instruction::from_code(instruction);
+    // every word the image sets, by word address
+    let mut words = BTreeMap::new();
+    for (at, bytes) in memory.segments() {
+        for addr in at / 2..(at + bytes.len() as u32).div_ceil(2) {
+            words.insert(addr as u16, memory.word(addr as u16).unwrap());
+        }
+    }
+    let program = words
+        .range(..(PROGRAM_END / 2) as u16)
+        .map(|(&at, &code)| (at, code))
+        .collect::<BTreeMap<_, _>>();
+    let labels = labels(&program, &symbols);
+    let cfg = ControlFlowGraph::build(&flash);
+    let references = cfg.references();
 
-        match decoded {
-            Some(Instruction::Control(ControlInstruction::Noop)) => {
-                if noop.is_none() {
-                    noop = Some(i);
-                }
+    // `retlw` tables become `dt` lines, cut wherever a label or a comment has to go
+    let mut tables = BTreeMap::<u16, Vec<u8>>::new();
+    for table in find_tables(&flash) {
+        let mut start = table.start.0;
+        for at in table.start.0..table.end.0 {
+            let cut = at == table.start.0
+                || labels.contains_key(&at)
+                || references.contains_key(&ProgramAddr(at))
+                || lines.locate(ProgramAddr(at)) != lines.locate(ProgramAddr(at - 1))
+                || at - start == 16;
+            if cut {
+                start = at;
             }
+            tables.entry(start).or_default().push(program[&at] as u8);
+        }
+    }
+    let in_table = |at: u16| {
+        tables
+            .range(..=at)
+            .next_back()
+            .is_some_and(|(&start, bytes)| at < start + bytes.len() as u16)
+    };
 
-            Some(d) => {
-                if let Some(oi) = noop.take() {
-                    let diff = oi.abs_diff(i);
-                    let count = diff / 2;
-
-                    if count > 4 {
-                        println!("0x{:x}..0x{:x}: {} Noops", oi, i, diff / 2);
-                    } else {
-                        for i in (0..count + 1).map(|x| oi + x) {
-                            println!("0x{i:04x}(0x00): Noop");
-                        }
-                    }
-                }
-
-                println!("0x{:04x}({instruction:04x}): {}", i, format_instruction(d));
+    let mut last_location = None;
+    let mut next = None;
+    let mut in_dead_code = false;
+    for (&at, &code) in &words {
+        if next != Some(at) {
+            println!("        org 0x{at:04x}");
+        }
+        next = Some(at + 1);
+        if in_table(at) && !tables.contains_key(&at) {
+            continue;
+        }
+
+        let dead = |at: u16| {
+            program.contains_key(&at) && !cfg.is_reachable(ProgramAddr(at)) && !in_table(at)
+        };
+        if dead(at) && !in_dead_code {
+            let end = (at..).take_while(|&x| dead(x)).last().unwrap();
+            println!("; unreachable 0x{at:04x}..=0x{end:04x}");
+        }
+        in_dead_code = dead(at);
+        if let Some(label) = labels.get(&at) {
+            println!("{label}:");
+        }
+        let incoming = references.get(&ProgramAddr(at)).map(Vec::as_slice);
+        for (call, what) in [(true, "called"), (false, "jumped to")] {
+            let from = incoming
+                .unwrap_or_default()
+                .iter()
+                .filter(|x| x.call == call)
+                .map(|x| format!("0x{:04x}", x.from.0))
+                .collect::<Vec<_>>();
+            if !from.is_empty() {
+                println!("; {what} from {}", from.join(", "));
             }
+        }
+        let location = lines.locate(ProgramAddr(at));
+        match location {
+            Some(location) if Some(location) != last_location => println!("; {location}"),
+            _ => {}
+        }
+        last_location = location;
 
-            None => {}
+        if let Some(bytes) = tables.get(&at) {
+            let text = format!("dt {}", table_operands(bytes));
+            println!(
+                "        {text:<23} ; 0x{at:04x} \"{}\"",
+                bytes.escape_ascii()
+            );
+            continue;
         }
+
+        // words that don't decode, or decode to something that encodes differently (ignored
+        // bits set), are kept as they are
+        let inst = Instruction::from_code(code).filter(|x| x.to_code() == code);
+        let (text, register) = match inst {
+            Some(inst) if program.contains_key(&at) => (
+                format_instruction(inst, at, &labels),
+                inst.reads_register().or(inst.writes_register()),
+            ),
+            _ => (format!("dw 0x{code:04x}"), None),
+        };
+        let register = register
+            .map(|f| format!(" {}", register_name(f, &symbols)))
+            .unwrap_or_default();
+        println!("        {text:<23} ; 0x{at:04x} {code:04x}{register}");
     }
+    println!("        end");
 }
@@ -0,0 +1,219 @@
+//! line number records of Microchip COFF (`.cof`) files, as written by MPLAB and gputils, so a
+//! pc can be shown as `main.c:42`.
+//!
+//! only what's needed for that is read: section headers, their line numbers, and the `.file`
+//! symbols they point at. the flash image still comes from the hex.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use crate::inst::ProgramAddr;
+
+const MAGIC_V1: u16 = 0x1234;
+const MAGIC_V2: u16 = 0x1240;
+
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const LINENO_SIZE: usize = 16;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("not a Microchip COFF file (magic {found:#06x})")]
+    InvalidMagic { found: u16 },
+
+    #[error("file ends in the middle of a record at {offset:#x}")]
+    Truncated { offset: usize },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// where in the source a program address came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    pub file: &'a str,
+    pub line: u32,
+}
+
+/// `main.c:42`
+impl Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceLines {
+    files: Vec<String>,
+    /// index into `files`, and the line
+    lines: BTreeMap<ProgramAddr, (usize, u32)>,
+}
+
+impl SourceLines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// reads the line numbers of every section in a `.cof` file
+    pub fn from_coff(data: &[u8]) -> Result<Self> {
+        let r = Reader(data);
+        let magic = r.u16(0)?;
+        let symbol_size = match magic {
+            MAGIC_V1 => 18,
+            MAGIC_V2 => 20,
+            found => return Err(Error::InvalidMagic { found }),
+        };
+        let sections = r.u16(2)? as usize;
+        let symbols_at = r.u32(8)? as usize;
+        let symbols = r.u32(12)? as usize;
+        let optional_header_size = r.u16(16)? as usize;
+        let strings_at = symbols_at + symbols * symbol_size;
+
+        let mut table = Self::new();
+        // symbol index of a `.file` to its index in `files`
+        let mut files = BTreeMap::new();
+        for i in 0..sections {
+            let header = FILE_HEADER_SIZE + optional_header_size + i * SECTION_HEADER_SIZE;
+            let linenos_at = r.u32(header + 28)? as usize;
+            let linenos = r.u16(header + 34)? as usize;
+            for j in 0..linenos {
+                let at = linenos_at + j * LINENO_SIZE;
+                let symbol = r.u32(at)? as usize;
+                let line = r.u16(at + 4)? as u32;
+                // byte address
+                let addr = r.u32(at + 6)?;
+
+                let file = match files.get(&symbol) {
+                    Some(&file) => file,
+                    None => {
+                        // the first aux entry of `.file` has the offset of the name
+                        let aux = symbols_at + (symbol + 1) * symbol_size;
+                        let name = r.string(strings_at + r.u32(aux)? as usize)?;
+                        table.files.push(name.to_owned());
+                        files.insert(symbol, table.files.len() - 1);
+                        table.files.len() - 1
+                    }
+                };
+                table
+                    .lines
+                    .insert(ProgramAddr((addr / 2) as u16), (file, line));
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// the line of the closest record at or before `addr`. compilers only mark the first
+    /// instruction of each line.
+    pub fn locate(&self, addr: ProgramAddr) -> Option<SourceLocation<'_>> {
+        let (_, &(file, line)) = self.lines.range(..=addr).next_back()?;
+        Some(SourceLocation { file: &self.files[file], line })
+    }
+
+    /// the first address of `line` in `file`, or of the next line with code if it has none, and
+    /// that line. files match by name, so the path an editor has finds the one the toolchain
+    /// wrote
+    pub fn addr_of(&self, file: &str, line: u32) -> Option<(ProgramAddr, u32)> {
+        let name = |path: &str| path.rsplit(['/', '\\']).next().unwrap_or(path).to_owned();
+        let file = name(file);
+        self.lines
+            .iter()
+            .filter(|(_, &(f, l))| l >= line && name(&self.files[f]) == file)
+            .map(|(&addr, &(_, l))| (addr, l))
+            .min_by_key(|&(addr, l)| (l, addr))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+        self.0
+            .get(offset..offset + N)
+            .map(|x| x.try_into().unwrap())
+            .ok_or(Error::Truncated { offset })
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    /// NUL terminated
+    fn string(&self, offset: usize) -> Result<&str> {
+        let rest = self.0.get(offset..).ok_or(Error::Truncated { offset })?;
+        let len = rest
+            .iter()
+            .position(|&x| x == 0)
+            .ok_or(Error::Truncated { offset })?;
+        Ok(std::str::from_utf8(&rest[..len]).unwrap_or("?"))
+    }
+}
+
+#[test]
+fn reads_line_numbers() {
+    // one section with three lines from two files. no optional header.
+    let mut cof = vec![];
+    let symbols_at = FILE_HEADER_SIZE + SECTION_HEADER_SIZE + 3 * LINENO_SIZE;
+    cof.extend(MAGIC_V2.to_le_bytes());
+    cof.extend(1u16.to_le_bytes()); // sections
+    cof.extend(0u32.to_le_bytes()); // time stamp
+    cof.extend((symbols_at as u32).to_le_bytes());
+    cof.extend(4u32.to_le_bytes()); // symbols
+    cof.extend(0u16.to_le_bytes()); // optional header size
+    cof.extend(0u16.to_le_bytes()); // flags
+
+    cof.extend(b".text\0\0\0");
+    cof.extend([0; 20]); // addresses, size, data, relocations
+    cof.extend(((FILE_HEADER_SIZE + SECTION_HEADER_SIZE) as u32).to_le_bytes());
+    cof.extend(0u16.to_le_bytes()); // relocations
+    cof.extend(3u16.to_le_bytes()); // line numbers
+    cof.extend(0u32.to_le_bytes()); // flags
+
+    for (file, line, addr) in [(0u32, 42u16, 0x0018u32), (0, 43, 0x001c), (2, 7, 0x0028)] {
+        cof.extend(file.to_le_bytes());
+        cof.extend(line.to_le_bytes());
+        cof.extend(addr.to_le_bytes());
+        cof.extend(0u16.to_le_bytes()); // flags
+        cof.extend(0u32.to_le_bytes()); // function
+    }
+
+    // `.file` and its aux entry, twice
+    for name_at in [4u32, 11] {
+        cof.extend(b".file\0\0\0");
+        cof.extend([0; 12]);
+        cof.extend(name_at.to_le_bytes());
+        cof.extend([0; 16]);
+    }
+    cof.extend(17u32.to_le_bytes());
+    cof.extend(b"main.c\0lcd.c\0");
+
+    let lines = SourceLines::from_coff(&cof).unwrap();
+    let at = |addr| lines.locate(ProgramAddr(addr)).map(|x| x.to_string());
+    assert_eq!(at(0x000b), None);
+    assert_eq!(at(0x000c).as_deref(), Some("main.c:42"));
+    assert_eq!(at(0x000d).as_deref(), Some("main.c:42"));
+    assert_eq!(at(0x000e).as_deref(), Some("main.c:43"));
+    assert_eq!(at(0x0014).as_deref(), Some("lcd.c:7"));
+    assert_eq!(
+        lines.addr_of("/home/me/fw/main.c", 43),
+        Some((ProgramAddr(0x000e), 43))
+    );
+    assert_eq!(lines.addr_of("lcd.c", 1), Some((ProgramAddr(0x0014), 7)));
+    assert_eq!(lines.addr_of("main.c", 44), None);
+
+    assert!(matches!(
+        SourceLines::from_coff(&cof[..100]),
+        Err(Error::Truncated { .. })
+    ));
+    assert!(matches!(
+        SourceLines::from_coff(&[0; 20]),
+        Err(Error::InvalidMagic { found: 0 })
+    ));
+}
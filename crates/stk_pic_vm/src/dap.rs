@@ -0,0 +1,490 @@
+//! `stk-pic-vm dap ...`: the debugger over the Debug Adapter Protocol, so an editor such as VS
+//! Code can set breakpoints in the source, step and show the registers. the editor connects to a
+//! TCP port, with a launch configuration of `"debugServer": 4711`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use clap::Args;
+use serde_json::{json, Value};
+use stk_pic_vm::coff::SourceLines;
+use stk_pic_vm::inst::ProgramAddr;
+use stk_pic_vm::symbols::SymbolTable;
+use stk_pic_vm::vm::device::Slot;
+use stk_pic_vm::vm::p16f88::{RunExit, Stopped, FLASH_BYTES, P16F88};
+
+use crate::{load_firmware, register_addr};
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+/// instructions to run between looking for requests
+const STEPS_PER_SLICE: u32 = 20_000;
+
+/// `variablesReference`s of the scopes
+const REGISTERS: u64 = 1;
+const VARIABLES: u64 = 2;
+
+#[derive(Args, Debug)]
+pub struct DapArgs {
+    /// firmware to debug, as Intel HEX or ELF (`.elf`)
+    file: PathBuf,
+
+    /// pic-as `.map` file of the firmware. replaces the symbols of an ELF
+    #[arg(long, value_name = "PATH")]
+    map: Option<PathBuf>,
+
+    /// COFF (`.cof`) file of the firmware, for breakpoints and stepping in the source
+    #[arg(long, value_name = "PATH")]
+    cof: Option<PathBuf>,
+
+    /// TCP port to listen on, on localhost
+    #[arg(long, default_value_t = 4711)]
+    port: u16,
+}
+
+pub fn run(args: DapArgs) -> Result<()> {
+    let (memory, symbols) = load_firmware(&args.file, args.map.as_deref())?;
+    let (lines, source_dir) = match &args.cof {
+        Some(path) => {
+            let lines = (|| -> Result<_> { Ok(SourceLines::from_coff(&std::fs::read(path)?)?) })()
+                .map_err(|e| format!("{}: {e}", path.display()))?;
+            let dir = path.parent().unwrap_or(Path::new("")).to_owned();
+            (lines, dir)
+        }
+        None => (SourceLines::new(), PathBuf::new()),
+    };
+    let vm = P16F88::new(memory.program_flash(FLASH_BYTES).try_into().unwrap());
+
+    let listener = TcpListener::bind(("127.0.0.1", args.port))?;
+    eprintln!("listening on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    eprintln!("{peer} connected");
+
+    let requests = spawn_reader(stream.try_clone()?);
+    let mut session = Session {
+        out: stream,
+        seq: 0,
+        vm,
+        symbols,
+        lines,
+        source_dir,
+        source_breakpoints: HashMap::new(),
+        function_breakpoints: vec![],
+        stop_on_entry: false,
+        running: None,
+    };
+    session.serve(requests)
+}
+
+/// reads the requests on a thread of its own, so a run can go on until the next one comes
+fn spawn_reader(stream: TcpStream) -> Receiver<Value> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// a `Content-Length` framed JSON message, or `None` at the end of the stream
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+    let length = length.ok_or("message without Content-Length")?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+/// where a run stops, other than at breakpoints and errors
+#[derive(Debug, Clone)]
+enum Running {
+    Free,
+    /// once the call stack is no deeper than this
+    Depth(usize),
+    /// at another source line, once the call stack is no deeper than `depth`
+    Line {
+        depth: Option<usize>,
+        from: (String, u32),
+    },
+}
+
+struct Session {
+    out: TcpStream,
+    seq: u64,
+    vm: P16F88,
+    symbols: SymbolTable,
+    lines: SourceLines,
+    /// where relative paths of the COFF are from
+    source_dir: PathBuf,
+    /// by the path the editor has for the source
+    source_breakpoints: HashMap<String, Vec<ProgramAddr>>,
+    function_breakpoints: Vec<ProgramAddr>,
+    stop_on_entry: bool,
+    running: Option<Running>,
+}
+
+impl Session {
+    fn serve(&mut self, requests: Receiver<Value>) -> Result<()> {
+        loop {
+            let request = match self.running {
+                Some(_) => match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                },
+                None => match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                },
+            };
+            if let Some(request) = request {
+                if !self.request(&request)? {
+                    return Ok(());
+                }
+            }
+            self.advance()?;
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let body = message.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.out.flush()
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = message.into(),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) -> io::Result<()> {
+        self.running = None;
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "text": text, "threadId": 1, "allThreadsStopped": true }),
+        )
+    }
+
+    /// false once the editor is done
+    fn request(&mut self, request: &Value) -> io::Result<bool> {
+        let args = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or_default();
+        let result = match command {
+            "initialize" => {
+                self.respond(
+                    request,
+                    Ok(json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsFunctionBreakpoints": true,
+                        "supportsEvaluateForHovers": true,
+                        "supportsSteppingGranularity": true,
+                    })),
+                )?;
+                return self.event("initialized", json!({})).map(|_| true);
+            }
+            "launch" | "attach" => {
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+                Ok(json!({}))
+            }
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(args)),
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})))?;
+                match self.stop_on_entry {
+                    true => self.stopped("entry", None)?,
+                    false => self.running = Some(Running::Free),
+                }
+                return Ok(true);
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": 1, "name": self.vm.device().name }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Variables", "variablesReference": VARIABLES, "expensive": false },
+            ] })),
+            "variables" => Ok(self.variables(args["variablesReference"].as_u64())),
+            "evaluate" => {
+                let expr = args["expression"].as_str().unwrap_or_default().trim();
+                register_addr(expr, &self.symbols, self.vm.device())
+                    .map(|addr| {
+                        let value = self.vm.peek(addr);
+                        json!({ "result": format!("0x{value:02x} ({value})"), "variablesReference": 0 })
+                    })
+                    .ok_or_else(|| format!("no address, variable or SFR `{expr}`"))
+            }
+            "continue" => {
+                self.running = Some(Running::Free);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.respond(request, Ok(json!({})))?;
+                let instruction = args["granularity"] == "instruction";
+                self.start_step(command, instruction)?;
+                return Ok(true);
+            }
+            "pause" => {
+                if self.running.is_some() {
+                    self.respond(request, Ok(json!({})))?;
+                    self.stopped("pause", None)?;
+                    return Ok(true);
+                }
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})))?;
+                self.event("terminated", json!({}))?;
+                return Ok(false);
+            }
+            _ => Err(format!("`{command}` isn't supported")),
+        };
+        self.respond(request, result)?;
+        Ok(true)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let path = args["source"]["path"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+        let mut addrs = vec![];
+        let breakpoints = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|x| {
+                let line = x["line"].as_u64().unwrap_or(0) as u32;
+                match self.lines.addr_of(&path, line) {
+                    Some((addr, line)) => {
+                        addrs.push(addr);
+                        json!({ "verified": true, "line": line })
+                    }
+                    None => {
+                        json!({ "verified": false, "message": "no code at or after this line" })
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        self.source_breakpoints.insert(path, addrs);
+        self.sync_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn set_function_breakpoints(&mut self, args: &Value) -> Value {
+        self.function_breakpoints.clear();
+        let breakpoints = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|x| {
+                let name = x["name"].as_str().unwrap_or_default();
+                match self.symbols.code_addr(name) {
+                    Some(addr) => {
+                        self.function_breakpoints.push(addr);
+                        json!({ "verified": true })
+                    }
+                    None => json!({ "verified": false, "message": "no such code symbol" }),
+                }
+            })
+            .collect::<Vec<_>>();
+        self.sync_breakpoints();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// the vm's breakpoints are those of every source and the functions
+    fn sync_breakpoints(&mut self) {
+        for addr in self.vm.breakpoints().collect::<Vec<_>>() {
+            self.vm.remove_breakpoint(addr);
+        }
+        let all = self.source_breakpoints.values().flatten();
+        for &addr in all.chain(&self.function_breakpoints) {
+            self.vm.add_breakpoint(addr);
+        }
+    }
+
+    fn stack_trace(&self) -> Value {
+        let pc = ProgramAddr(self.vm.pc());
+        let frames = [pc]
+            .into_iter()
+            .chain(self.vm.call_stack.iter().rev().map(|&x| ProgramAddr(x)))
+            .enumerate()
+            .map(|(id, addr)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": self.symbols.describe(addr),
+                    "instructionPointerReference": format!("0x{:04x}", addr.0),
+                    "line": 0,
+                    "column": 0,
+                });
+                if let Some(location) = self.lines.locate(addr) {
+                    let name = location.file.rsplit(['/', '\\']).next();
+                    let path = self.source_dir.join(location.file);
+                    frame["source"] = json!({ "name": name, "path": path });
+                    frame["line"] = location.line.into();
+                    frame["column"] = 1.into();
+                }
+                frame
+            })
+            .collect::<Vec<_>>();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&self, reference: Option<u64>) -> Value {
+        let variable = |name: String, value: u8| json!({ "name": name, "value": format!("0x{value:02x}"), "variablesReference": 0 });
+        let variables = match reference {
+            Some(REGISTERS) => {
+                let mut names = vec![];
+                let mut variables = vec![
+                    variable("W".to_owned(), self.vm.w),
+                    json!({
+                        "name": "PC",
+                        "value": format!("0x{:04x}", self.vm.pc()),
+                        "variablesReference": 0,
+                    }),
+                ];
+                let slots = self.vm.device().map.iter().flatten().enumerate();
+                for (addr, &slot) in slots {
+                    let Slot::Special(sfr) = slot else { continue };
+                    let name = sfr.name().to_ascii_uppercase();
+                    // not registers, and IADDR (INDF) would read through FSR
+                    if ["UNIMPL", "RESERV", "IADDR"].contains(&name.as_str())
+                        || names.contains(&name)
+                    {
+                        continue;
+                    }
+                    names.push(name.clone());
+                    variables.push(variable(name, self.vm.peek(addr as u16)));
+                }
+                variables
+            }
+            Some(VARIABLES) => self
+                .symbols
+                .data()
+                .map(|(addr, name)| variable(name.to_owned(), self.vm.peek(addr.0 as u16)))
+                .collect(),
+            _ => vec![],
+        };
+        json!({ "variables": variables })
+    }
+
+    /// the source line at pc, if the COFF has one
+    fn line(&self) -> Option<(String, u32)> {
+        let location = self.lines.locate(ProgramAddr(self.vm.pc()))?;
+        Some((location.file.to_owned(), location.line))
+    }
+
+    fn start_step(&mut self, command: &str, instruction: bool) -> io::Result<()> {
+        let depth = self.vm.call_stack.len();
+        let line = self.line().filter(|_| !instruction);
+        if command == "stepOut" {
+            self.running = Some(match depth.checked_sub(1) {
+                Some(depth) => Running::Depth(depth),
+                None => Running::Free,
+            });
+            return Ok(());
+        }
+        if let Some(from) = line {
+            let depth = (command == "next").then_some(depth);
+            self.running = Some(Running::Line { depth, from });
+            return Ok(());
+        }
+
+        // an instruction, and over a call for `next`
+        match self.vm.step(&mut ()) {
+            Ok(None) if command == "next" && self.vm.call_stack.len() > depth => {
+                self.running = Some(Running::Depth(depth));
+                Ok(())
+            }
+            Ok(None) => self.stopped("step", None),
+            Ok(Some(stopped)) => self.stop(stopped),
+            Err(e) => self.stopped("exception", Some(e.to_string())),
+        }
+    }
+
+    /// runs a slice of the current run, so requests are still read while it goes
+    fn advance(&mut self) -> io::Result<()> {
+        let Some(running) = self.running.clone() else {
+            return Ok(());
+        };
+        let mut steps = 0;
+        let run = self.vm.run_until(
+            |vm| {
+                steps += 1;
+                steps > STEPS_PER_SLICE
+                    || match &running {
+                        Running::Free => false,
+                        Running::Depth(depth) => vm.call_stack.len() <= *depth,
+                        Running::Line { depth, from } => {
+                            depth.map_or(true, |d| vm.call_stack.len() <= d)
+                                && self
+                                    .lines
+                                    .locate(ProgramAddr(vm.pc()))
+                                    .is_some_and(|x| (x.file, x.line) != (from.0.as_str(), from.1))
+                        }
+                    }
+            },
+            &mut (),
+        );
+        match run.exit {
+            RunExit::Condition if steps > STEPS_PER_SLICE => Ok(()),
+            // a step that ends on a breakpoint is a stop at it
+            RunExit::Condition => {
+                let pc = ProgramAddr(self.vm.pc());
+                if self.vm.breakpoints().any(|x| x == pc) {
+                    // stops at it without running it, so continuing goes past it
+                    let _ = self.vm.step(&mut ());
+                    return self.stopped("breakpoint", None);
+                }
+                self.stopped("step", None)
+            }
+            RunExit::Stopped(stopped) => self.stop(stopped),
+            RunExit::Error(e) => self.stopped("exception", Some(e.to_string())),
+            exit => self.stopped("exception", Some(format!("{exit:?}"))),
+        }
+    }
+
+    fn stop(&mut self, stopped: Stopped) -> io::Result<()> {
+        match stopped {
+            Stopped::Breakpoint(_) => self.stopped("breakpoint", None),
+            _ => self.stopped("step", None),
+        }
+    }
+}
@@ -0,0 +1,597 @@
+//! `stk-pic-vm debug ...`: a terminal UI to step through a firmware, instead of adding prints to
+//! the runner and recompiling
+
+use std::error::Error;
+use std::io::{self, Stdout};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::Args;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{Frame, Terminal};
+use stk_hd44780_vm::{Hd44780, PinObserver};
+use stk_pic_vm::coff::SourceLines;
+use stk_pic_vm::expr::{Expr, ParseError, Watches};
+use stk_pic_vm::inst::{Instruction, ProgramAddr};
+use stk_pic_vm::lcd::LcdWiring;
+use stk_pic_vm::symbols::SymbolTable;
+use stk_pic_vm::vm::p16f88::{RunExit, Stopped, Ticker, FLASH_BYTES, P16F88};
+
+use crate::{load_firmware, parse_addr, parse_number};
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+/// instructions to run between looking for key presses
+const STEPS_PER_FRAME: u32 = 20_000;
+
+#[derive(Args, Debug)]
+pub struct DebugArgs {
+    /// firmware to debug, as Intel HEX or ELF (`.elf`)
+    file: PathBuf,
+
+    /// pic-as `.map` file of the firmware. replaces the symbols of an ELF
+    #[arg(long, value_name = "PATH")]
+    map: Option<PathBuf>,
+
+    /// COFF (`.cof`) file of the firmware, to show source lines
+    #[arg(long, value_name = "PATH")]
+    cof: Option<PathBuf>,
+
+    /// start with a breakpoint at this address, stopping only when the condition after `if`
+    /// holds if there is one (`0x1a4 if W == 0x3f && bank == 1`, see `stk_pic_vm::expr`). can be
+    /// given multiple times
+    #[arg(long = "break", value_name = "ADDR [if COND]")]
+    breakpoints: Vec<String>,
+
+    /// start with this watched: a register as an address (`0x20`, `0xA0` for bank 1), an SFR
+    /// name or a variable, or an expression on them like `counter * 2`. can be given multiple
+    /// times
+    #[arg(long = "watch", value_name = "EXPR")]
+    watches: Vec<String>,
+}
+
+pub fn run(args: DebugArgs) -> Result<()> {
+    let debugger = Debugger::load(args)?;
+
+    terminal::enable_raw_mode()?;
+    io::stdout().execute(EnterAlternateScreen)?;
+    // leave the terminal usable for the panic message
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        hook(info);
+    }));
+
+    let result = Terminal::new(CrosstermBackend::new(io::stdout()))
+        .map_err(Into::into)
+        .and_then(|mut terminal| debugger.event_loop(&mut terminal));
+    restore_terminal()?;
+    result
+}
+
+fn restore_terminal() -> io::Result<()> {
+    terminal::disable_raw_mode()?;
+    io::stdout().execute(LeaveAlternateScreen)?;
+    Ok(())
+}
+
+struct LcdTicker {
+    lcd: Hd44780,
+    cycles: u64,
+}
+
+impl Ticker for LcdTicker {
+    fn tick(&mut self, vm: &P16F88, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.lcd.update(LcdWiring::board().pins(vm));
+    }
+}
+
+/// what is being typed
+#[derive(Debug, Clone, Copy)]
+enum Prompt {
+    Watch,
+    /// of the breakpoint there
+    Condition(ProgramAddr),
+}
+
+/// where a run started by a key stops, other than at breakpoints and errors
+#[derive(Debug, Clone, Copy)]
+enum Running {
+    Free,
+    /// once the call stack is no deeper than this. step over and step out
+    Depth(usize),
+    /// run to cursor
+    To(ProgramAddr),
+}
+
+struct Debugger {
+    vm: P16F88,
+    ticker: LcdTicker,
+    /// program memory by word address
+    words: Vec<u16>,
+    symbols: SymbolTable,
+    lines: SourceLines,
+    /// selected address of the disassembly
+    cursor: u16,
+    watches: Watches,
+    /// a watch or breakpoint condition being typed
+    input: Option<(Prompt, String)>,
+    running: Option<Running>,
+    /// why it last stopped, or what went wrong
+    message: String,
+}
+
+impl Debugger {
+    fn load(args: DebugArgs) -> Result<Self> {
+        fn context<T>(path: &Path, r: Result<T>) -> Result<T> {
+            r.map_err(|e| format!("{}: {e}", path.display()).into())
+        }
+
+        let (memory, symbols) = load_firmware(&args.file, args.map.as_deref())?;
+        let lines = match &args.cof {
+            Some(path) => context(
+                path,
+                (|| Ok(SourceLines::from_coff(&std::fs::read(path)?)?))(),
+            )?,
+            None => SourceLines::new(),
+        };
+
+        let flash = memory.program_flash(FLASH_BYTES);
+        let words = flash
+            .chunks(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .collect();
+        let mut vm = P16F88::new(flash.try_into().unwrap());
+        for spec in &args.breakpoints {
+            let (addr, condition) = match spec.split_once(" if ") {
+                Some((addr, condition)) => (addr, Some(condition)),
+                None => (spec.as_str(), None),
+            };
+            let addr = parse_addr(addr.trim())?;
+            match condition {
+                Some(condition) => {
+                    let condition = Expr::parse(condition, &symbols, vm.device())
+                        .map_err(|e| format!("{spec}: {e}"))?;
+                    vm.add_breakpoint_if(addr, condition);
+                }
+                None => vm.add_breakpoint(addr),
+            }
+        }
+
+        let mut debugger = Self {
+            vm,
+            ticker: LcdTicker { lcd: Hd44780::new(), cycles: 0 },
+            words,
+            symbols,
+            lines,
+            cursor: 0,
+            watches: Watches::new(),
+            input: None,
+            running: None,
+            message: String::new(),
+        };
+        for text in &args.watches {
+            let expr = debugger
+                .watch_expr(text)
+                .map_err(|e| format!("{text}: {e}"))?;
+            debugger.watches.push(expr);
+        }
+        debugger.watches.update(&debugger.vm);
+        Ok(debugger)
+    }
+
+    fn event_loop(mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let timeout = match self.running {
+                Some(_) => Duration::ZERO,
+                None => Duration::from_millis(250),
+            };
+            if event::poll(timeout)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.key(key.code) {
+                        return Ok(());
+                    }
+                }
+            }
+            self.advance();
+        }
+    }
+
+    /// false to quit
+    fn key(&mut self, key: KeyCode) -> bool {
+        if let Some((prompt, input)) = &mut self.input {
+            match key {
+                KeyCode::Enter => {
+                    let (prompt, text) = (*prompt, input.trim().to_owned());
+                    self.input = None;
+                    self.entered(prompt, &text);
+                }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return true;
+        }
+
+        if self.running.is_some() {
+            match key {
+                KeyCode::Char('q') => return false,
+                KeyCode::Esc | KeyCode::Char(' ' | 'p') => self.stop("paused".to_owned()),
+                _ => {}
+            }
+            return true;
+        }
+
+        let last = self.words.len() as u16 - 1;
+        match key {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('s') => {
+                self.step();
+            }
+            KeyCode::Char('n') => {
+                let depth = self.vm.call_stack.len();
+                if self.step() && self.vm.call_stack.len() > depth {
+                    self.running = Some(Running::Depth(depth));
+                }
+            }
+            KeyCode::Char('o') => {
+                self.message.clear();
+                self.running = Some(match self.vm.call_stack.len().checked_sub(1) {
+                    Some(depth) => Running::Depth(depth),
+                    None => Running::Free,
+                });
+            }
+            KeyCode::Char('c') => {
+                self.message.clear();
+                self.running = Some(Running::Free);
+            }
+            KeyCode::Char('r') => {
+                let to = ProgramAddr(self.cursor);
+                if self.step() && self.vm.pc() != to.0 {
+                    self.running = Some(Running::To(to));
+                }
+            }
+            KeyCode::Char('b') => {
+                let addr = ProgramAddr(self.cursor);
+                if self.vm.breakpoints().any(|x| x == addr) {
+                    self.vm.remove_breakpoint(addr);
+                } else {
+                    self.vm.add_breakpoint(addr);
+                }
+            }
+            KeyCode::Char('i') => {
+                let addr = ProgramAddr(self.cursor);
+                let condition = self.vm.breakpoint_condition(addr);
+                let text = condition.map(|x| x.to_string()).unwrap_or_default();
+                self.input = Some((Prompt::Condition(addr), text));
+            }
+            KeyCode::Char('w') => self.input = Some((Prompt::Watch, String::new())),
+            KeyCode::Char('x') => {
+                if !self.watches.is_empty() {
+                    self.watches.remove(self.watches.len() - 1);
+                }
+            }
+            KeyCode::Char('.') => self.cursor = self.vm.pc(),
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down => self.cursor = (self.cursor + 1).min(last),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(16),
+            KeyCode::PageDown => self.cursor = (self.cursor + 16).min(last),
+            _ => {}
+        }
+        true
+    }
+
+    fn entered(&mut self, prompt: Prompt, text: &str) {
+        match prompt {
+            Prompt::Watch if text.is_empty() => {}
+            Prompt::Watch => match self.watch_expr(text) {
+                Ok(expr) => {
+                    self.watches.push(expr);
+                    self.watches.update(&self.vm);
+                }
+                Err(e) => self.message = format!("{text}: {e}"),
+            },
+            // an empty condition makes it unconditional
+            Prompt::Condition(addr) if text.is_empty() => self.vm.add_breakpoint(addr),
+            Prompt::Condition(addr) => match Expr::parse(text, &self.symbols, self.vm.device()) {
+                Ok(condition) => self.vm.add_breakpoint_if(addr, condition),
+                Err(e) => self.message = format!("{text}: {e}"),
+            },
+        }
+    }
+
+    /// a watch, where a plain number is the register at that address rather than the number
+    fn watch_expr(&self, text: &str) -> Result<Expr, ParseError> {
+        let text = match parse_number(text) {
+            Ok(_) => format!("[{text}]"),
+            Err(_) => text.to_owned(),
+        };
+        Expr::parse(&text, &self.symbols, self.vm.device())
+    }
+
+    /// true if an instruction ran and nothing stopped it
+    fn step(&mut self) -> bool {
+        self.message.clear();
+        let result = self.vm.step(&mut self.ticker);
+        self.cursor = self.vm.pc();
+        self.watches.update(&self.vm);
+        match result {
+            Ok(None) => true,
+            Ok(Some(stopped)) => {
+                self.message = self.describe_stop(stopped);
+                false
+            }
+            Err(e) => {
+                self.message = e.to_string();
+                false
+            }
+        }
+    }
+
+    /// runs a slice of the current run, so keys are still read while it goes
+    fn advance(&mut self) {
+        let Some(running) = self.running else {
+            return;
+        };
+        let mut steps = 0;
+        let run = self.vm.run_until(
+            |vm| {
+                steps += 1;
+                steps > STEPS_PER_FRAME
+                    || match running {
+                        Running::Free => false,
+                        Running::Depth(depth) => vm.call_stack.len() <= depth,
+                        Running::To(addr) => vm.pc() == addr.0,
+                    }
+            },
+            &mut self.ticker,
+        );
+        match run.exit {
+            RunExit::Condition if steps > STEPS_PER_FRAME => {}
+            RunExit::Condition => self.stop(String::new()),
+            RunExit::Stopped(stopped) => self.stop(self.describe_stop(stopped)),
+            RunExit::Error(e) => self.stop(e.to_string()),
+            exit => self.stop(format!("{exit:?}")),
+        }
+    }
+
+    fn stop(&mut self, message: String) {
+        self.running = None;
+        self.message = message;
+        self.cursor = self.vm.pc();
+        self.watches.update(&self.vm);
+    }
+
+    fn describe_stop(&self, stopped: Stopped) -> String {
+        match stopped {
+            Stopped::Breakpoint(addr) => match self.vm.breakpoint_condition(addr) {
+                Some(condition) => format!(
+                    "breakpoint at {} ({condition})",
+                    self.symbols.describe(addr)
+                ),
+                None => format!("breakpoint at {}", self.symbols.describe(addr)),
+            },
+            Stopped::Reached(addr) => format!("reached {}", self.symbols.describe(addr)),
+            Stopped::Stepped => String::new(),
+            stopped => format!("{stopped:?}"),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(4),
+                Constraint::Length(2),
+            ])
+            .split(frame.size());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(rows[0]);
+        let side = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(9),
+                Constraint::Length(11),
+                Constraint::Min(0),
+            ])
+            .split(columns[1]);
+
+        self.draw_code(frame, columns[0]);
+        self.draw_registers(frame, side[0]);
+        self.draw_call_stack(frame, side[1]);
+        self.draw_watches(frame, side[2]);
+
+        let cursor = self.ticker.lcd.cursor();
+        let lcd = self
+            .ticker
+            .lcd
+            .lines()
+            .iter()
+            .enumerate()
+            .map(|(line, text)| {
+                let chars = text.chars().enumerate().map(|(column, c)| {
+                    let mut style = Style::default();
+                    if let Some(cursor) = cursor.filter(|x| (x.line, x.column) == (line, column)) {
+                        if cursor.underline {
+                            style = style.add_modifier(Modifier::UNDERLINED);
+                        }
+                        if cursor.blink {
+                            style = style.add_modifier(Modifier::SLOW_BLINK);
+                        }
+                    }
+                    Span::styled(c.to_string(), style)
+                });
+                Line::from(chars.collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(lcd).block(block("lcd")), rows[1]);
+
+        let state = match self.running {
+            Some(_) => "running. esc/space: pause  q: quit",
+            None => {
+                "s: step  n: over  o: out  c: continue  r: to cursor  b: breakpoint  \
+                 i: condition  w/x: watch/unwatch  arrows: move  .: pc  q: quit"
+            }
+        };
+        let status = vec![
+            Line::styled(&self.message, Style::default().fg(Color::Yellow)),
+            Line::from(state),
+        ];
+        frame.render_widget(Paragraph::new(status), rows[2]);
+    }
+
+    fn draw_code(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2);
+        let last = self.words.len() as u16;
+        let start = self
+            .cursor
+            .saturating_sub(height / 2)
+            .min(last.saturating_sub(height));
+        let pc = self.vm.pc();
+
+        let code = (start..(start + height).min(last))
+            .map(|at| {
+                let addr = ProgramAddr(at);
+                let word = self.words[at as usize];
+                let text = match Instruction::from_code(word) {
+                    Some(inst) => {
+                        let target = inst
+                            .branch_target()
+                            .map(|x| ProgramAddr((at & 0x1800) | x.0));
+                        match target.and_then(|x| self.symbols.code_name(x)) {
+                            Some(name) => format!("{inst} <{name}>"),
+                            None => inst.to_string(),
+                        }
+                    }
+                    None => format!("dw 0x{word:04x}"),
+                };
+                let label = self.symbols.code_name(addr).unwrap_or_default();
+                let breakpoint = match self.vm.breakpoints().any(|x| x == addr) {
+                    true if self.vm.breakpoint_condition(addr).is_some() => "◆",
+                    true => "●",
+                    false => " ",
+                };
+                let here = if at == pc { "▶" } else { " " };
+
+                let mut style = Style::default();
+                if at == pc {
+                    style = style.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+                }
+                if at == self.cursor {
+                    style = style.bg(Color::DarkGray);
+                }
+                Line::from(vec![
+                    Span::styled(breakpoint, Style::default().fg(Color::Red)),
+                    Span::raw(here),
+                    Span::raw(format!(" {at:04x} {label:>14} {text}")),
+                ])
+                .style(style)
+            })
+            .collect::<Vec<_>>();
+
+        let title = match self.lines.locate(ProgramAddr(self.cursor)) {
+            Some(location) => format!("code ({location})"),
+            None => "code".to_owned(),
+        };
+        frame.render_widget(Paragraph::new(code).block(block(&title)), area);
+    }
+
+    fn draw_registers(&self, frame: &mut Frame, area: Rect) {
+        let vm = &self.vm;
+        let status = vm.peek(0x03);
+        let flags = ["irp", "rp1", "rp0", "to", "pd", "z", "dc", "c"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| match status & (0x80 >> i) != 0 {
+                true => name.to_ascii_uppercase(),
+                false => name.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        let seconds = (self.ticker.cycles * 4) as f64 / vm.clock_hz() as f64;
+
+        let registers = [
+            format!("W      0x{:02x}", vm.w),
+            format!("STATUS 0x{status:02x}  {flags}"),
+            format!(
+                "PCLATH 0x{:02x}  FSR   0x{:02x}",
+                vm.peek(0x0A),
+                vm.peek(0x04)
+            ),
+            format!("INTCON 0x{:02x}", vm.peek(0x0B)),
+            format!(
+                "PORTA  0x{:02x}  TRISA 0x{:02x}",
+                vm.peek(0x05),
+                vm.peek(0x85)
+            ),
+            format!(
+                "PORTB  0x{:02x}  TRISB 0x{:02x}",
+                vm.peek(0x06),
+                vm.peek(0x86)
+            ),
+            format!("cycles {} ({:.3} ms)", self.ticker.cycles, seconds * 1000.0),
+        ]
+        .map(Line::from)
+        .to_vec();
+        frame.render_widget(Paragraph::new(registers).block(block("registers")), area);
+    }
+
+    fn draw_call_stack(&self, frame: &mut Frame, area: Rect) {
+        let pc = ProgramAddr(self.vm.pc());
+        let stack = [pc]
+            .into_iter()
+            .chain(self.vm.call_stack.iter().rev().map(|&x| ProgramAddr(x)))
+            .map(|x| Line::from(format!("0x{:04x} {}", x.0, self.symbols.describe(x))))
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(stack).block(block("call stack")), area);
+    }
+
+    fn draw_watches(&self, frame: &mut Frame, area: Rect) {
+        let mut watches = self
+            .watches
+            .iter()
+            .map(|watch| {
+                let (expr, value) = (&watch.expr, watch.value.unwrap_or_default());
+                let line = Line::from(match expr.register() {
+                    Some(addr) => format!("{expr:<12} [0x{addr:03x}] 0x{value:02x} {value:>3}"),
+                    None => format!("{expr:<12} = {value:#x} {value}"),
+                });
+                match watch.changed {
+                    true => line.style(Style::default().fg(Color::Yellow)),
+                    false => line,
+                }
+            })
+            .collect::<Vec<_>>();
+        if let Some((prompt, input)) = &self.input {
+            let prompt = match prompt {
+                Prompt::Watch => String::new(),
+                Prompt::Condition(addr) => format!("break {:#06x} if ", addr.0),
+            };
+            watches.push(Line::styled(
+                format!("> {prompt}{input}_"),
+                Style::default().fg(Color::Cyan),
+            ));
+        }
+        frame.render_widget(Paragraph::new(watches).block(block("watches")), area);
+    }
+}
+
+fn block(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
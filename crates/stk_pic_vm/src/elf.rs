@@ -0,0 +1,208 @@
+//! program loading from 32 bit little endian ELF files (XC8 can write these instead of a hex).
+//!
+//! addresses are the byte addresses of the hex, so the image is the same as what
+//! [`decode_intel_hex`](crate::hex::decode_intel_hex) gives for the same program.
+
+use crate::hex::MemoryImage;
+use crate::inst::{ProgramAddr, RegisterFileAddr};
+use crate::symbols::{source_name, SymbolTable};
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHF_WRITE: u32 = 0x1;
+const SHF_EXECINSTR: u32 = 0x4;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("not an ELF file")]
+    InvalidMagic,
+
+    #[error("only 32 bit little endian ELF is supported")]
+    Unsupported,
+
+    #[error("file ends in the middle of a record at {offset:#x}")]
+    Truncated { offset: usize },
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfImage {
+    /// program and config words, like a decoded hex
+    pub memory: MemoryImage,
+    /// functions and variables from `.symtab`, if it wasn't stripped
+    pub symbols: SymbolTable,
+}
+
+/// copies the loadable segments with contents in the file into the image. RAM has none: the
+/// startup code copies initial values over from program memory.
+pub fn load_elf(data: &[u8]) -> Result<ElfImage> {
+    if data.get(..4) != Some(b"\x7fELF") {
+        return Err(Error::InvalidMagic);
+    }
+    // 32 bit, little endian
+    if data.get(4..6) != Some(&[1, 1]) {
+        return Err(Error::Unsupported);
+    }
+    let r = Reader(data);
+
+    let mut memory = MemoryImage::new();
+    let segments_at = r.u32(28)? as usize;
+    let segment_size = r.u16(42)? as usize;
+    for i in 0..r.u16(44)? as usize {
+        let header = segments_at + i * segment_size;
+        if r.u32(header)? != PT_LOAD {
+            continue;
+        }
+        let offset = r.u32(header + 4)? as usize;
+        let addr = r.u32(header + 12)?;
+        let size = r.u32(header + 16)? as usize;
+        let contents = data
+            .get(offset..offset + size)
+            .ok_or(Error::Truncated { offset })?;
+        memory.write(addr, contents);
+    }
+
+    let sections_at = r.u32(32)? as usize;
+    let section_size = r.u16(46)? as usize;
+    let sections = r.u16(48)? as usize;
+    let section = |i: usize| sections_at + i * section_size;
+    let mut symbols = SymbolTable::new();
+    for i in 0..sections {
+        if r.u32(section(i) + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let symbols_at = r.u32(section(i) + 16)? as usize;
+        let count = r.u32(section(i) + 20)? as usize / 16;
+        let names_at = r.u32(section(r.u32(section(i) + 24)? as usize) + 16)? as usize;
+        for j in 0..count {
+            let symbol = symbols_at + j * 16;
+            let kind = r.u8(symbol + 12)? & 0xf;
+            if kind != STT_FUNC && kind != STT_OBJECT {
+                continue;
+            }
+            let name = r.string(names_at + r.u32(symbol)? as usize)?;
+            let Some(name) = source_name(name) else {
+                continue;
+            };
+            let value = r.u32(symbol + 4)?;
+            let in_section = r.u16(symbol + 14)? as usize;
+            if in_section == 0 || in_section >= sections {
+                continue;
+            }
+            let flags = r.u32(section(in_section) + 8)?;
+            if flags & SHF_EXECINSTR != 0 {
+                symbols.insert_code(ProgramAddr((value / 2) as u16), name);
+            } else if flags & SHF_WRITE != 0 {
+                if let Ok(addr) = u8::try_from(value) {
+                    symbols.insert_data(RegisterFileAddr(addr), name);
+                }
+            }
+        }
+    }
+
+    Ok(ElfImage { memory, symbols })
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Result<[u8; N]> {
+        self.0
+            .get(offset..offset + N)
+            .map(|x| x.try_into().unwrap())
+            .ok_or(Error::Truncated { offset })
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8> {
+        self.bytes(offset).map(u8::from_le_bytes)
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    /// NUL terminated
+    fn string(&self, offset: usize) -> Result<&str> {
+        let rest = self.0.get(offset..).ok_or(Error::Truncated { offset })?;
+        let len = rest
+            .iter()
+            .position(|&x| x == 0)
+            .ok_or(Error::Truncated { offset })?;
+        Ok(std::str::from_utf8(&rest[..len]).unwrap_or("?"))
+    }
+}
+
+#[test]
+fn loads_segments_and_symbols() {
+    fn u16s(x: &[u16]) -> Vec<u8> {
+        x.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+    fn u32s(x: &[u32]) -> Vec<u8> {
+        x.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    let code = u16s(&[0x3005, 0x2800]);
+    let config = u16s(&[0x3F38]);
+    let names = b"\0_main\0_counter\0__end_of_main\0.symtab\0.strtab\0";
+    let symtab = [
+        vec![0; 16],
+        // name, value, size, info, other, section
+        [u32s(&[1, 0x0002, 2]), vec![STT_FUNC, 0], u16s(&[3])].concat(),
+        [u32s(&[7, 0x0020, 1]), vec![STT_OBJECT, 0], u16s(&[4])].concat(),
+        [u32s(&[16, 0x0004, 0]), vec![STT_FUNC, 0], u16s(&[3])].concat(),
+    ]
+    .concat();
+
+    // header, 2 program headers, then contents, then 5 section headers
+    let code_at = 52 + 2 * 32;
+    let config_at = code_at + code.len();
+    let symtab_at = config_at + config.len();
+    let names_at = symtab_at + symtab.len();
+    let sections_at = names_at + names.len();
+
+    let mut elf = b"\x7fELF\x01\x01\x01".to_vec();
+    elf.resize(16, 0);
+    elf.extend(u16s(&[2, 0])); // type, machine
+    elf.extend(u32s(&[1, 0, 52, sections_at as u32, 0]));
+    elf.extend(u16s(&[52, 32, 2, 40, 5, 0]));
+    for (at, addr, size) in [(code_at, 0, code.len()), (config_at, 0x400E, config.len())] {
+        let (at, size) = (at as u32, size as u32);
+        elf.extend(u32s(&[PT_LOAD, at, addr, addr, size, size, 0, 2]));
+    }
+    elf.extend(&code);
+    elf.extend(&config);
+    elf.extend(&symtab);
+    elf.extend(names);
+    let section = |name, kind, flags, at: usize, size: usize, link| {
+        u32s(&[name, kind, flags, 0, at as u32, size as u32, link, 0, 0, 0])
+    };
+    elf.extend(vec![0; 40]);
+    elf.extend(section(30, SHT_SYMTAB, 0, symtab_at, symtab.len(), 2));
+    elf.extend(section(38, 3, 0, names_at, names.len(), 0));
+    elf.extend(section(0, 1, 0x2 | SHF_EXECINSTR, code_at, code.len(), 0));
+    elf.extend(section(0, 8, 0x2 | SHF_WRITE, 0, 0, 0));
+
+    let image = load_elf(&elf).unwrap();
+    assert_eq!(image.memory.program_flash(4), code);
+    assert_eq!(image.memory.word(0x2007), Some(0x3F38));
+    assert_eq!(image.symbols.code_name(ProgramAddr(0x0001)), Some("main"));
+    assert_eq!(image.symbols.code_name(ProgramAddr(0x0002)), None);
+    assert_eq!(
+        image.symbols.data_name(RegisterFileAddr(0x20)),
+        Some("counter")
+    );
+
+    assert!(matches!(load_elf(b"hello"), Err(Error::InvalidMagic)));
+    assert!(matches!(
+        load_elf(&elf[..100]),
+        Err(Error::Truncated { .. })
+    ));
+}
@@ -0,0 +1,64 @@
+//! a rotary encoder on the pins of an MCU, A, B and the push switch driving them as inputs. the
+//! host turns it through [`Knob::encoder_mut`] while the simulation runs.
+
+use std::time::Duration;
+
+use stk_encoder_vm::Encoder;
+
+use crate::sim::Peripheral;
+use crate::vm::cosim::CoSim;
+use crate::vm::pic14::Pin;
+
+/// the encoder on the pins of one MCU
+#[derive(Debug)]
+pub struct Knob {
+    encoder: Encoder,
+    a: Pin,
+    b: Pin,
+    switch: Option<Pin>,
+    mcu: usize,
+    /// the time of the last update
+    now: Duration,
+}
+
+impl Knob {
+    /// A and B on `a` and `b` of MCU `mcu`, the index in the [`CoSim`]. the switch isn't wired
+    pub fn new(encoder: Encoder, a: Pin, b: Pin, mcu: usize) -> Self {
+        Self {
+            encoder,
+            a,
+            b,
+            switch: None,
+            mcu,
+            now: Duration::ZERO,
+        }
+    }
+
+    /// the push switch on `pin`
+    pub fn switch(mut self, pin: Pin) -> Self {
+        self.switch = Some(pin);
+        self
+    }
+
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    pub fn encoder_mut(&mut self) -> &mut Encoder {
+        &mut self.encoder
+    }
+}
+
+impl Peripheral for Knob {
+    fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+        let elapsed = now.saturating_sub(self.now);
+        self.encoder.simulate(elapsed.as_nanos() as u64);
+        self.now = now;
+        let vm = mcus.mcu_mut(self.mcu);
+        vm.set_pin_input(self.a, self.encoder.a());
+        vm.set_pin_input(self.b, self.encoder.b());
+        if let Some(pin) = self.switch {
+            vm.set_pin_input(pin, self.encoder.switch());
+        }
+    }
+}
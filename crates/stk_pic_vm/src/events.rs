@@ -0,0 +1,262 @@
+//! machine readable record of what the firmware did, one JSON object per line (NDJSON), for CI
+//! jobs and scripts to assert on. plug [`EventLog`] in as a [`Ticker`] and [`EventLog::emit`]
+//! whatever the host sees on top of the pins, like the bytes an attached LCD latches. hosts that
+//! act on the events themselves take them from an [`EventQueue`] instead.
+
+use std::fmt::Display;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vm::device::Slot;
+use crate::vm::pic14::reg::Sfr;
+use crate::vm::pic14::{MemoryAccess, Pic14, Pin, Ticker, CLOCKS_PER_CYCLE};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// instruction cycles since the log started
+    pub cycle: u64,
+    /// simulated time since the log started, at the clock of the vm
+    pub time_ns: u64,
+    /// address of the instruction that caused it, see [`Pic14::executing`]
+    pub pc: u16,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// `{"event": "pin", "pin": "RB0", "level": true}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum EventKind {
+    /// level driven by the MCU changed. `None` when the pin became an input
+    Pin {
+        pin: String,
+        level: Option<bool>,
+    },
+    /// an LCD latched `data` on the falling edge of E
+    Lcd {
+        rs: bool,
+        data: u8,
+    },
+    /// a byte written to TXREG
+    Uart {
+        byte: u8,
+    },
+    /// a device on an SPI bus was selected and deselected again, with the bytes shifted each way
+    Spi {
+        device: usize,
+        mosi: Vec<u8>,
+        miso: Vec<u8>,
+    },
+    /// a write to the register at `addr` of the linear register file (bank * 0x80 + offset).
+    /// only in logs that [`EventQueue::log_writes`]
+    Write {
+        addr: u16,
+        value: u8,
+    },
+    Breakpoint {
+        addr: u16,
+    },
+    /// the run ended on a [`StopCondition`](crate::stop::StopCondition), as it displays
+    Stop {
+        condition: String,
+    },
+    /// the run stopped on an error
+    Error {
+        message: String,
+    },
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventKind::Pin { pin, level: Some(level) } => {
+                write!(f, "{pin} {}", if *level { "high" } else { "low" })
+            }
+            EventKind::Pin { pin, level: None } => write!(f, "{pin} input"),
+            EventKind::Lcd { rs, data } => write!(f, "lcd rs={} data={data:#04x}", *rs as u8),
+            EventKind::Uart { byte } => write!(f, "uart {byte:#04x} {:?}", *byte as char),
+            EventKind::Spi { device, mosi, miso } => {
+                let hex =
+                    |bytes: &[u8]| bytes.iter().map(|x| format!("{x:02x}")).collect::<Vec<_>>();
+                let (mosi, miso) = (hex(mosi).join(" "), hex(miso).join(" "));
+                write!(f, "spi {device}: {mosi} -> {miso}")
+            }
+            EventKind::Write { addr, value } => write!(f, "write {addr:#05x} = {value:#04x}"),
+            EventKind::Breakpoint { addr } => write!(f, "breakpoint at {addr:#06x}"),
+            EventKind::Stop { condition } => write!(f, "stopped: {condition}"),
+            EventKind::Error { message } => write!(f, "error: {message}"),
+        }
+    }
+}
+
+/// the events of a run, kept until the host takes them. [`EventLog`] writes them out as they
+/// come
+#[derive(Debug, Default)]
+pub struct EventQueue {
+    cycle: u64,
+    /// every pin of the device with the level it was last reported at
+    pins: Vec<(Pin, Option<bool>)>,
+    /// writes of the instruction that hasn't ticked yet
+    writes: Vec<MemoryAccess>,
+    /// whether they become events
+    log_writes: bool,
+    events: Vec<Event>,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// also queue every register write as an [`EventKind::Write`]. a lot of events, for
+    /// comparing runs closely
+    pub fn log_writes(mut self, on: bool) -> Self {
+        self.log_writes = on;
+        self
+    }
+
+    /// instruction cycles since the queue started
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// queues `kind` as happening now. outside of a tick, e.g. once the run has stopped, the pc is
+    /// still that of the last instruction run
+    pub fn push(&mut self, vm: &Pic14, kind: EventKind) {
+        let clocks = self.cycle as u128 * CLOCKS_PER_CYCLE as u128;
+        self.events.push(Event {
+            cycle: self.cycle,
+            time_ns: (clocks * 1_000_000_000 / vm.clock_hz() as u128) as u64,
+            pc: vm.executing().map_or(vm.pc(), |x| x.0),
+            kind,
+        });
+    }
+
+    /// the events so far, oldest first
+    pub fn take(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl Ticker for EventQueue {
+    fn tick(&mut self, vm: &Pic14, cycles: u8) {
+        self.cycle += cycles as u64;
+
+        // all pins are inputs out of reset
+        if self.pins.is_empty() {
+            self.pins = vm.pins().into_iter().map(|pin| (pin, None)).collect();
+        }
+        for i in 0..self.pins.len() {
+            let (pin, last) = self.pins[i];
+            let level = vm.pin_output(pin);
+            if level != last {
+                self.pins[i].1 = level;
+                let pin = format!("{pin:?}");
+                self.push(vm, EventKind::Pin { pin, level });
+            }
+        }
+
+        for access in std::mem::take(&mut self.writes) {
+            if self.log_writes {
+                let addr = access.bank as u16 * 0x80 + access.addr.0 as u16;
+                self.push(vm, EventKind::Write { addr, value: access.new });
+            }
+            let slot = vm.device().map[access.bank as usize][access.addr.0 as usize];
+            if slot == Slot::Special(Sfr::TXREG) {
+                self.push(vm, EventKind::Uart { byte: access.new });
+            }
+        }
+    }
+
+    fn on_write(&mut self, access: MemoryAccess) {
+        self.writes.push(access);
+    }
+}
+
+#[derive(Debug)]
+pub struct EventLog<W> {
+    out: W,
+    queue: EventQueue,
+}
+
+impl<W: Write> EventLog<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, queue: EventQueue::new() }
+    }
+
+    /// see [`EventQueue::log_writes`]
+    pub fn log_writes(mut self, on: bool) -> Self {
+        self.queue = self.queue.log_writes(on);
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// writes `kind` as happening now, see [`EventQueue::push`]
+    pub fn emit(&mut self, vm: &Pic14, kind: EventKind) {
+        self.queue.push(vm, kind);
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        for event in self.queue.take() {
+            // the log is for watching the run, it shouldn't be able to stop it
+            let _ = serde_json::to_writer(&mut self.out, &event);
+            let _ = writeln!(self.out);
+        }
+    }
+}
+
+impl<W: Write> Ticker for EventLog<W> {
+    fn tick(&mut self, vm: &Pic14, cycles: u8) {
+        self.queue.tick(vm, cycles);
+        self.flush();
+    }
+
+    fn on_write(&mut self, access: MemoryAccess) {
+        self.queue.on_write(access);
+    }
+}
+
+#[test]
+fn logs_pins_and_uart() {
+    use crate::vm::p16f88::P16F88;
+
+    let words: [u16; 6] = [
+        0b01_0110_1000_0011, // 0x0000: bsf STATUS, RP0
+        0b01_0000_0000_0110, // 0x0001: bcf TRISB, 0
+        0b01_0010_1000_0011, // 0x0002: bcf STATUS, RP0
+        0b01_0100_0000_0110, // 0x0003: bsf PORTB, 0
+        0b11_0000_0100_0001, // 0x0004: movlw 'A'
+        0b00_0000_1001_1001, // 0x0005: movwf TXREG
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = P16F88::new(flash);
+    let mut log = EventLog::new(vec![]);
+    for _ in 0..words.len() {
+        vm.step(&mut log).unwrap();
+    }
+    log.emit(&vm, EventKind::Breakpoint { addr: vm.pc() });
+
+    let out = String::from_utf8(log.into_inner()).unwrap();
+    let lines = out.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            r#"{"cycle":2,"time_ns":400,"pc":1,"event":"pin","pin":"RB0","level":false}"#,
+            r#"{"cycle":4,"time_ns":800,"pc":3,"event":"pin","pin":"RB0","level":true}"#,
+            r#"{"cycle":6,"time_ns":1200,"pc":5,"event":"uart","byte":65}"#,
+            r#"{"cycle":6,"time_ns":1200,"pc":5,"event":"breakpoint","addr":6}"#,
+        ]
+    );
+    let event = serde_json::from_str::<Event>(lines[1]).unwrap();
+    assert_eq!(event.kind.to_string(), "RB0 high");
+}
@@ -0,0 +1,359 @@
+//! expressions on the machine state, e.g. `W == 0x3f && bank == 1`, for conditional breakpoints
+//! ([`Pic14::add_breakpoint_if`]) and [`Watches`].
+//!
+//! the operators are Rust's, with the same precedence. `!` is a logical not. the operands are
+//! numbers (`42`, `0x2a`, `0b101010`), `W`, `PC`, `bank` (STATUS.RP1:RP0), variables, SFR names
+//! and `[addr]` for the register at a linear address. values are `i64`; dividing by 0 gives 0.
+
+use std::fmt::Display;
+
+use crate::symbols::SymbolTable;
+use crate::vm::device::Device;
+use crate::vm::pic14::Pic14;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message} at column {column}")]
+pub struct ParseError {
+    pub message: String,
+    /// from 1
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    node: Node,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Literal(i64),
+    W,
+    Pc,
+    Bank,
+    /// linear address
+    Register(u16),
+    /// the register at the linear address the operand evaluates to
+    Indirect(Box<Node>),
+    Unary(char, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+/// loosest first, as in Rust
+const BINARY: [&[&str]; 9] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<=", ">=", "<", ">"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+impl Expr {
+    /// names are looked up as variables in `symbols`, then as SFRs of `device`
+    pub fn parse(source: &str, symbols: &SymbolTable, device: &Device) -> Result<Self, ParseError> {
+        let mut parser = Parser { source, at: 0, symbols, device };
+        let node = parser.binary(0)?;
+        parser.skip_spaces();
+        if parser.at < source.len() {
+            return Err(parser.error("unexpected input"));
+        }
+        Ok(Self { source: source.trim().to_owned(), node })
+    }
+
+    pub fn eval(&self, vm: &Pic14) -> i64 {
+        eval(&self.node, vm)
+    }
+
+    /// not 0
+    pub fn holds(&self, vm: &Pic14) -> bool {
+        self.eval(vm) != 0
+    }
+
+    /// the linear address if the expression is just a register
+    pub fn register(&self) -> Option<u16> {
+        match self.node {
+            Node::Register(addr) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn eval(node: &Node, vm: &Pic14) -> i64 {
+    let register = |addr: i64| u16::try_from(addr).map_or(0, |addr| vm.peek(addr)) as i64;
+    match node {
+        Node::Literal(x) => *x,
+        Node::W => vm.w as i64,
+        Node::Pc => vm.pc() as i64,
+        Node::Bank => (vm.register.special.status().bits() as i64 >> 5) & 0b11,
+        Node::Register(addr) => register(*addr as i64),
+        Node::Indirect(addr) => register(eval(addr, vm)),
+        Node::Unary(op, x) => {
+            let x = eval(x, vm);
+            match op {
+                '-' => x.wrapping_neg(),
+                '~' => !x,
+                _ => (x == 0) as i64,
+            }
+        }
+        Node::Binary(op, a, b) => {
+            let a = eval(a, vm);
+            // short-circuit, like the rest of the language
+            match *op {
+                "&&" => return (a != 0 && eval(b, vm) != 0) as i64,
+                "||" => return (a != 0 || eval(b, vm) != 0) as i64,
+                _ => {}
+            }
+            let b = eval(b, vm);
+            match *op {
+                "==" => (a == b) as i64,
+                "!=" => (a != b) as i64,
+                "<=" => (a <= b) as i64,
+                ">=" => (a >= b) as i64,
+                "<" => (a < b) as i64,
+                ">" => (a > b) as i64,
+                "|" => a | b,
+                "^" => a ^ b,
+                "&" => a & b,
+                "<<" => a.checked_shl(b as u32).unwrap_or(0),
+                ">>" => a.checked_shr(b as u32).unwrap_or(0),
+                "+" => a.wrapping_add(b),
+                "-" => a.wrapping_sub(b),
+                "*" => a.wrapping_mul(b),
+                "/" => a.checked_div(b).unwrap_or(0),
+                _ => a.checked_rem(b).unwrap_or(0),
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    /// byte offset into `source`
+    at: usize,
+    symbols: &'a SymbolTable,
+    device: &'a Device,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        let column = self.source[..self.at].chars().count() + 1;
+        ParseError { message: message.into(), column }
+    }
+
+    fn rest(&self) -> &str {
+        &self.source[self.at..]
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.at += rest.len() - rest.trim_start().len();
+    }
+
+    /// takes `token` if it's next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.at += token.len();
+        }
+        found
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node, ParseError> {
+        let Some(ops) = BINARY.get(level) else {
+            return self.unary();
+        };
+        let mut node = self.binary(level + 1)?;
+        'operators: loop {
+            self.skip_spaces();
+            for &op in *ops {
+                // `&` isn't the start of `&&`, nor `|` of `||`, `<` of `<<` or `<=`, ...
+                let longer = BINARY
+                    .iter()
+                    .flat_map(|x| x.iter())
+                    .any(|x| x.len() > op.len() && x.starts_with(op) && self.rest().starts_with(x));
+                if !longer && self.eat(op) {
+                    let rhs = self.binary(level + 1)?;
+                    node = Node::Binary(op, Box::new(node), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(node);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, ParseError> {
+        for op in ['-', '~', '!'] {
+            // `!=` is never at the start of an operand
+            if self.eat(&op.to_string()) {
+                return Ok(Node::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        self.operand()
+    }
+
+    fn operand(&mut self) -> Result<Node, ParseError> {
+        if self.eat("(") {
+            let node = self.binary(0)?;
+            return match self.eat(")") {
+                true => Ok(node),
+                false => Err(self.error("expected `)`")),
+            };
+        }
+        if self.eat("[") {
+            let node = self.binary(0)?;
+            return match self.eat("]") {
+                true => Ok(Node::Indirect(Box::new(node))),
+                false => Err(self.error("expected `]`")),
+            };
+        }
+
+        let word = self
+            .rest()
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or_default();
+        if word.is_empty() {
+            return Err(self.error("expected an operand"));
+        }
+        let node = if word.starts_with(|c: char| c.is_ascii_digit()) {
+            let (digits, radix) = match word.get(..2) {
+                Some("0x" | "0X") => (&word[2..], 16),
+                Some("0b" | "0B") => (&word[2..], 2),
+                _ => (word, 10),
+            };
+            let value = i64::from_str_radix(digits, radix)
+                .map_err(|_| self.error(format!("invalid number `{word}`")))?;
+            Node::Literal(value)
+        } else if word.eq_ignore_ascii_case("w") {
+            Node::W
+        } else if word.eq_ignore_ascii_case("pc") {
+            Node::Pc
+        } else if word.eq_ignore_ascii_case("bank") {
+            Node::Bank
+        } else if let Some(addr) = self.symbols.data_addr(word) {
+            Node::Register(addr.0 as u16)
+        } else if let Some(addr) = self.device.sfr_addr(word) {
+            Node::Register(addr)
+        } else {
+            return Err(self.error(format!("unknown name `{word}`")));
+        };
+        self.at += word.len();
+        Ok(node)
+    }
+}
+
+/// expressions a debugger shows the values of, re-evaluated with [`Self::update`] after each step
+#[derive(Debug, Clone, Default)]
+pub struct Watches {
+    watches: Vec<Watch>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Watch {
+    pub expr: Expr,
+    /// at the last update, `None` before the first one
+    pub value: Option<i64>,
+    /// the last update changed the value
+    pub changed: bool,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, expr: Expr) {
+        self.watches
+            .push(Watch { expr, value: None, changed: false });
+    }
+
+    pub fn remove(&mut self, index: usize) -> Watch {
+        self.watches.remove(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.watches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watch> + '_ {
+        self.watches.iter()
+    }
+
+    /// evaluates every watch against `vm`. true if any value changed
+    pub fn update(&mut self, vm: &Pic14) -> bool {
+        let mut any = false;
+        for watch in &mut self.watches {
+            let value = watch.expr.eval(vm);
+            watch.changed = watch.value.is_some_and(|x| x != value);
+            watch.value = Some(value);
+            any |= watch.changed;
+        }
+        any
+    }
+}
+
+#[test]
+fn evaluates_against_the_machine_state() {
+    use crate::inst::RegisterFileAddr;
+    use crate::vm::device::P16F88;
+    use crate::vm::pic14::reg::STATUS;
+
+    let mut symbols = SymbolTable::new();
+    symbols.insert_data(RegisterFileAddr(0x20), "counter");
+    let parse = |s: &str| Expr::parse(s, &symbols, &P16F88);
+
+    let mut vm = Pic14::new([0; 7168]);
+    vm.w = 0x3f;
+    vm.register.gpr[0].0 = 5;
+    vm.register.special.status_mut().insert(STATUS::RP0);
+
+    let value = |s: &str| parse(s).unwrap().eval(&vm);
+    assert_eq!(value("1 + 2 * 3 - -4"), 11);
+    assert_eq!(value("(1 + 2) * 3 % 4"), 1);
+    assert_eq!(value("1 << 4 | 0b11 & 0x2 ^ 1"), 0x13);
+    assert_eq!(value("7 / 0 + !0 + ~0"), 0);
+    assert_eq!(value("W == 0x3F && bank == 1"), 1);
+    assert_eq!(value("w != 0x3f || bank < 1"), 0);
+    assert_eq!(value("counter + [0x20] + [0x1f + 1]"), 15);
+    assert_eq!(value("status & 0x20"), 0x20);
+    assert_eq!(value("STATUS >= 0x20 && pc <= 0"), 1);
+
+    assert_eq!(parse("counter").unwrap().register(), Some(0x20));
+    assert_eq!(parse("TRISB").unwrap().register(), Some(0x86));
+    assert_eq!(parse("counter + 1").unwrap().register(), None);
+    assert_eq!(parse(" W==1 ").unwrap().to_string(), "W==1");
+
+    let error = |s: &str| parse(s).unwrap_err().to_string();
+    assert_eq!(error("W == nothing"), "unknown name `nothing` at column 6");
+    assert_eq!(error("(1 + 2"), "expected `)` at column 7");
+    assert_eq!(error("1 +"), "expected an operand at column 4");
+    assert_eq!(error("0x1g"), "invalid number `0x1g` at column 1");
+    assert_eq!(error("1 2"), "unexpected input at column 3");
+
+    let mut watches = Watches::new();
+    watches.push(parse("counter * 2").unwrap());
+    watches.push(parse("W").unwrap());
+    assert!(!watches.update(&vm));
+    vm.register.gpr[0].0 = 6;
+    assert!(watches.update(&vm));
+    let changed = watches
+        .iter()
+        .map(|x| (x.value, x.changed))
+        .collect::<Vec<_>>();
+    assert_eq!(changed, [(Some(12), true), (Some(0x3f), false)]);
+}
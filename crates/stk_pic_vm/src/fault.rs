@@ -0,0 +1,237 @@
+//! fault injection for robustness testing. schedule [`Fault`]s at given cycles, or let
+//! [`FaultInjector::random`] pick them from a seed, then run the firmware through
+//! [`FaultInjector::run_for_cycles`] to see whether its checksums and watchdog recovery notice.
+
+use std::ops::Range;
+
+use crate::vm::device::Slot;
+use crate::vm::pic14::{Pic14, Pin, PortId, Run, RunExit, Ticker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// inverts bit `bit` of flash byte `byte`, as a disturbed cell would
+    FlashBitFlip { byte: usize, bit: u8 },
+    /// overwrites [`Registers::gpr`](crate::vm::pic14::reg::Registers::gpr)`[index]`
+    CorruptGpr { index: u16, value: u8 },
+    /// drives `pin` to `level` for `cycles` instruction cycles, then gives it back to whatever
+    /// drove it before
+    PinGlitch { pin: Pin, level: bool, cycles: u64 },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Inject(Fault),
+    /// end of a [`Fault::PinGlitch`]. `None` if the pin wasn't driven from outside.
+    Restore {
+        pin: Pin,
+        level: Option<bool>,
+    },
+}
+
+/// longest [`Fault::PinGlitch`] [`FaultInjector::random`] makes
+const MAX_RANDOM_GLITCH_CYCLES: u64 = 100;
+
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    /// latest first, so the next one is at the end
+    pending: Vec<(u64, Action)>,
+    /// cycles run through the injector so far
+    cycles: u64,
+    injected: Vec<(u64, Fault)>,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `count` faults of random kinds at random cycles in `cycles` (not empty), all drawn from
+    /// `seed`. the same seed and device give the same campaign.
+    pub fn random(seed: u64, count: usize, cycles: Range<u64>, vm: &Pic14) -> Self {
+        let mut rng = SplitMix64(seed);
+        let gprs = mapped_gprs(vm);
+        let pins = vm.pins();
+
+        let mut me = Self::new();
+        for _ in 0..count {
+            let at = cycles.start + rng.below(cycles.end - cycles.start);
+            let fault = match rng.below(3) {
+                0 => Fault::FlashBitFlip {
+                    byte: rng.below(vm.flash.len() as u64) as usize,
+                    bit: rng.below(8) as u8,
+                },
+                1 => Fault::CorruptGpr {
+                    index: gprs[rng.below(gprs.len() as u64) as usize],
+                    value: rng.next_u64() as u8,
+                },
+                _ => Fault::PinGlitch {
+                    pin: pins[rng.below(pins.len() as u64) as usize],
+                    level: rng.next_u64() & 1 != 0,
+                    cycles: 1 + rng.below(MAX_RANDOM_GLITCH_CYCLES),
+                },
+            };
+            me.schedule(at, fault);
+        }
+        me
+    }
+
+    /// injects `fault` at the first instruction boundary at or after `cycle`, counted from the
+    /// first run through this injector
+    pub fn schedule(&mut self, cycle: u64, fault: Fault) {
+        self.push(cycle, Action::Inject(fault));
+    }
+
+    fn push(&mut self, cycle: u64, action: Action) {
+        let at = self.pending.partition_point(|(c, _)| *c > cycle);
+        self.pending.insert(at, (cycle, action));
+    }
+
+    /// faults injected so far, with the cycle they went in at
+    pub fn injected(&self) -> &[(u64, Fault)] {
+        &self.injected
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// [`Pic14::run_for_cycles`], stopping on the way to inject the faults that are due
+    pub fn run_for_cycles(&mut self, vm: &mut Pic14, cycles: u64, ticker: &mut impl Ticker) -> Run {
+        let start = self.cycles;
+        let end = start + cycles;
+        loop {
+            while let Some(&(at, action)) = self.pending.last() {
+                if at > self.cycles {
+                    break;
+                }
+                self.pending.pop();
+                match action {
+                    Action::Inject(fault) => self.inject(vm, fault),
+                    Action::Restore { pin, level: Some(level) } => vm.set_pin_input(pin, level),
+                    Action::Restore { pin, level: None } => vm.release_pin_input(pin),
+                }
+            }
+            if self.cycles >= end {
+                return Run { cycles: self.cycles - start, exit: RunExit::Budget };
+            }
+
+            let until = self.pending.last().map_or(end, |(at, _)| (*at).min(end));
+            let run = vm.run_for_cycles(until - self.cycles, ticker);
+            self.cycles += run.cycles;
+            if run.exit != RunExit::Budget {
+                return Run { cycles: self.cycles - start, exit: run.exit };
+            }
+        }
+    }
+
+    fn inject(&mut self, vm: &mut Pic14, fault: Fault) {
+        tracing::debug!("injecting {fault:?} at cycle {}", self.cycles);
+        match fault {
+            Fault::FlashBitFlip { byte, bit } => vm.flash[byte] ^= 1 << bit,
+            Fault::CorruptGpr { index, value } => vm.register.gpr[index as usize].0 = value,
+            Fault::PinGlitch { pin, level, cycles } => {
+                let before = external_level(vm, pin);
+                vm.set_pin_input(pin, level);
+                self.push(self.cycles + cycles, Action::Restore { pin, level: before });
+            }
+        }
+        self.injected.push((self.cycles, fault));
+    }
+}
+
+/// level `pin` is driven to from outside, if it is
+fn external_level(vm: &Pic14, pin: Pin) -> Option<bool> {
+    let sp = &vm.register.special;
+    let (input, driven) = match pin.port {
+        PortId::A => (sp.porta().input, sp.porta().driven),
+        PortId::B => (sp.portb().input, sp.portb().driven),
+        PortId::Gpio => (sp.gpio().input, sp.gpio().driven),
+    };
+    let mask = 1 << pin.bit;
+    (driven & mask != 0).then_some(input & mask != 0)
+}
+
+/// every GPR index the device maps somewhere, once
+fn mapped_gprs(vm: &Pic14) -> Vec<u16> {
+    let mut gprs = vm
+        .device()
+        .map
+        .iter()
+        .flatten()
+        .filter_map(|slot| match slot {
+            Slot::Gpr(index) => Some(*index),
+            Slot::Special(_) => None,
+        })
+        .collect::<Vec<_>>();
+    gprs.sort_unstable();
+    gprs.dedup();
+    gprs
+}
+
+/// small seeded generator, so campaigns are reproducible without depending on `rand`
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// in `0..n`
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+#[cfg(test)]
+fn looping_vm() -> Pic14 {
+    let mut flash = [0; 7168];
+    // 0x0000: goto 0x0000
+    flash[..2].copy_from_slice(&0b10_1000_0000_0000u16.to_le_bytes());
+    Pic14::new(flash)
+}
+
+#[test]
+fn injects_scheduled_faults() {
+    let mut vm = looping_vm();
+    let mut injector = FaultInjector::new();
+    injector.schedule(10, Fault::CorruptGpr { index: 0, value: 0x5A });
+    let glitch = Fault::PinGlitch { pin: Pin::rb(0), level: true, cycles: 4 };
+    injector.schedule(20, glitch);
+    injector.schedule(30, Fault::FlashBitFlip { byte: 0, bit: 0 });
+
+    injector.run_for_cycles(&mut vm, 16, &mut ());
+    assert_eq!(vm.register.gpr[0].0, 0x5A);
+    assert_eq!(injector.injected().len(), 1);
+
+    injector.run_for_cycles(&mut vm, 6, &mut ());
+    assert_eq!(external_level(&vm, Pin::rb(0)), Some(true));
+    injector.run_for_cycles(&mut vm, 6, &mut ());
+    assert_eq!(external_level(&vm, Pin::rb(0)), None);
+
+    injector.run_for_cycles(&mut vm, 4, &mut ());
+    // goto 0x0001
+    assert_eq!(vm.flash[0], 0b0000_0001);
+    assert_eq!(injector.injected()[1], (20, glitch));
+    assert_eq!(injector.injected().len(), 3);
+}
+
+#[test]
+fn random_campaigns_are_reproducible() {
+    let campaign = |seed| {
+        let mut vm = looping_vm();
+        let mut injector = FaultInjector::random(seed, 20, 100..1000, &vm);
+        injector.run_for_cycles(&mut vm, 2000, &mut ());
+        injector.injected().to_vec()
+    };
+
+    // a flipped bit can send the firmware off the end of the flash, ending the run early
+    let a = campaign(1);
+    assert!(!a.is_empty());
+    assert!(a.iter().all(|(at, _)| (100..1001).contains(at)));
+    assert_eq!(a, campaign(1));
+    assert_ne!(a, campaign(2));
+}
@@ -1,11 +1,17 @@
-use std::io::{self, Read};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::ops::Range;
 
 /// decoder for <https://ja.wikipedia.org/wiki/Intel_HEX>
 pub struct IntelHexDecoder<R> {
     reader: R,
+    /// lines read so far
+    line: usize,
+    lenient: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("io error")]
     Io(#[from] io::Error),
@@ -21,13 +27,27 @@ pub enum Error {
 
     #[error("expected '\\r\\n' or '\\n', found {found:?}")]
     InvalidNewLine { found: char },
+
+    #[error("checksum mismatch on line {line}; expected {expected:#04x}, found {found:#04x}")]
+    ChecksumMismatch {
+        line: usize,
+        expected: u8,
+        found: u8,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
 impl<R: Read> IntelHexDecoder<R> {
     pub fn new(reader: R) -> Self {
-        Self { reader }
+        Self { reader, line: 0, lenient: false }
+    }
+
+    /// if set, lines that don't decode are skipped with a warning instead of failing.
+    /// io errors and a missing EOF record still fail.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
     }
 
     fn decode_hex_char(&mut self) -> Result<u8> {
@@ -53,64 +73,581 @@ impl<R: Read> IntelHexDecoder<R> {
         Ok(c0 << 8 | c1) // Big-Endian
     }
 
-    pub fn decode(mut self) -> Result<Vec<u8>> {
-        let mut decoded = vec![];
-
-        let mut upper_address = 0u16;
+    /// up to and including the '\n', or to the end of the file
+    fn read_line(&mut self) -> Result<Vec<u8>> {
+        let mut line = vec![];
+        let mut buf = [0; 1];
+        loop {
+            match self.reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Error::Io(e)),
+            }
+            line.push(buf[0]);
+            if buf == [b'\n'] {
+                break;
+            }
+        }
+        if line.is_empty() {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(line)
+    }
 
+    fn read_record(&mut self) -> Result<Record> {
         loop {
-            let mut buf = [0; 1];
+            let line = self.read_line()?;
+            self.line += 1;
+            if line.iter().all(|x| x.is_ascii_whitespace()) {
+                continue;
+            }
+            match IntelHexDecoder::new(line.as_slice()).parse_record(self.line) {
+                Err(e) if self.lenient => tracing::warn!("line {}: {e}; skipped", self.line),
+                r => return r,
+            }
+        }
+    }
+
+    /// `line` is the line number for errors
+    fn parse_record(&mut self, line: usize) -> Result<Record> {
+        let mut buf = [0; 1];
+        self.reader.read_exact(&mut buf).map_err(Error::Io)?;
+
+        if buf != [b':'] {
+            return Err(Error::InvalidLineStart { found: buf[0] as char });
+        }
+
+        let byte_count = self.decode_hex_u8()?;
+        let address = self.decode_hex_u16()?;
+        let record_type = self.decode_hex_u8()?;
+        let kind = RecordKind::from_u8(record_type)
+            .ok_or(Error::UnknownRecordType { found: record_type })?;
+        let data = (0..byte_count)
+            .map(|_| self.decode_hex_u8())
+            .collect::<Result<Vec<_>>>()?;
+
+        let record = Record { address, kind, data };
+        let checksum = self.decode_hex_u8()?;
+        if checksum != record.checksum() {
+            return Err(Error::ChecksumMismatch {
+                line,
+                expected: record.checksum(),
+                found: checksum,
+            });
+        }
+
+        // the file may end right after the EOF record
+        if kind == RecordKind::EndOfFile {
+            return Ok(record);
+        }
+
+        self.reader.read_exact(&mut buf).map_err(Error::Io)?;
+        if buf == [b'\r'] {
             self.reader.read_exact(&mut buf).map_err(Error::Io)?;
+        }
+        if buf != [b'\n'] {
+            return Err(Error::InvalidNewLine { found: buf[0] as char });
+        }
 
-            if buf != [b':'] {
-                return Err(Error::InvalidLineStart { found: buf[0] as char });
-            }
+        Ok(record)
+    }
 
-            let byte_count = self.decode_hex_u8()?;
-            let address = ((upper_address as u32) << 16) | self.decode_hex_u16()? as u32;
-
-            let record_type = self.decode_hex_u8()?;
-
-            match record_type {
-                // data record
-                0 => {
-                    tracing::debug!("addr=0x{address:x}, bytes={byte_count}");
-                    for i in 0..byte_count {
-                        let b = self.decode_hex_u8()?;
-                        let pos = (address + i as u32) as usize;
-                        decoded.resize(pos + 1, 0);
-                        decoded[pos] = b;
-                    }
-                }
+    /// records in the file, up to and including the EOF record, read as they are asked for.
+    /// iteration stops after the first error.
+    pub fn records(self) -> Records<R> {
+        Records { decoder: self, done: false }
+    }
 
-                // EOF
-                1 => break,
+    /// collects the data records into a memory image.
+    pub fn decode(self) -> Result<MemoryImage> {
+        let mut decoded = MemoryImage::new();
 
-                4 => {
-                    upper_address = self.decode_hex_u16()?;
+        for record in self.records().with_addresses() {
+            let (address, record) = record?;
+            match record.kind {
+                RecordKind::Data => {
+                    tracing::debug!("addr=0x{address:x}, bytes={}", record.data.len());
+                    decoded.write(address, &record.data);
                 }
 
-                i @ 2..=5 => unimplemented!("record type {i}"),
+                RecordKind::EndOfFile => break,
+
+                RecordKind::StartSegmentAddress | RecordKind::StartLinearAddress => {
+                    decoded.start = record.start_address();
+                }
 
-                _ => return Err(Error::UnknownRecordType { found: record_type }),
+                RecordKind::ExtendedSegmentAddress | RecordKind::ExtendedLinearAddress => {}
             }
+        }
 
-            // FIXME: verify this
-            let _checksum = self.decode_hex_u8();
+        Ok(decoded)
+    }
+}
 
-            self.reader.read_exact(&mut buf).map_err(Error::Io)?;
-            if buf == [b'\r'] {
-                self.reader.read_exact(&mut buf).map_err(Error::Io)?;
+/// byte address in a mid-range hex where program memory ends and the IDs and config words begin
+pub const PROGRAM_END: u32 = 0x4000;
+/// byte address of the first EEPROM byte in a mid-range hex. each byte takes a word.
+pub const EEPROM_START: u32 = 0x4200;
+
+/// the bytes a hex file (or ELF) sets, in contiguous segments, so program memory, config words
+/// and EEPROM don't have to be told apart from a flat array with holes filled in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryImage {
+    /// start address -> bytes. segments never touch or overlap.
+    segments: BTreeMap<u32, Vec<u8>>,
+    start: Option<StartAddress>,
+}
+
+impl MemoryImage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// later writes win where they overlap earlier ones
+    pub fn write(&mut self, addr: u32, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let end = addr + data.len() as u32;
+        let touching = self
+            .segments
+            .range(..=end)
+            .filter(|(&start, bytes)| start + bytes.len() as u32 >= addr)
+            .map(|(&start, _)| start)
+            .collect::<Vec<_>>();
+        let touching = touching
+            .into_iter()
+            .map(|start| (start, self.segments.remove(&start).unwrap()))
+            .collect::<Vec<_>>();
+
+        let start = touching.first().map_or(addr, |x| x.0.min(addr));
+        let end = touching
+            .last()
+            .map_or(end, |x| end.max(x.0 + x.1.len() as u32));
+        let mut merged = vec![0; (end - start) as usize];
+        for (at, bytes) in touching
+            .iter()
+            .map(|(s, b)| (s, b.as_slice()))
+            .chain([(&addr, data)])
+        {
+            let at = (at - start) as usize;
+            merged[at..at + bytes.len()].copy_from_slice(bytes);
+        }
+        self.segments.insert(start, merged);
+    }
+
+    /// contiguous runs of set bytes, by address
+    pub fn segments(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.segments
+            .iter()
+            .map(|(&at, bytes)| (at, bytes.as_slice()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn get(&self, addr: u32) -> Option<u8> {
+        let (&start, bytes) = self.segments.range(..=addr).next_back()?;
+        bytes.get((addr - start) as usize).copied()
+    }
+
+    /// the bytes in `range`, with `fill` where nothing was set
+    pub fn read(&self, range: Range<u32>, fill: u8) -> Vec<u8> {
+        range.map(|x| self.get(x).unwrap_or(fill)).collect()
+    }
+
+    /// one past the last set byte
+    pub fn end(&self) -> u32 {
+        self.segments
+            .iter()
+            .next_back()
+            .map_or(0, |(&at, bytes)| at + bytes.len() as u32)
+    }
+
+    /// everything up to [`Self::end`], with zeros in the holes
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.read(0..self.end(), 0)
+    }
+
+    /// bytes of program memory up to the last word set, so a program that doesn't fit the
+    /// device can be noticed
+    pub fn program_len(&self) -> usize {
+        let end = self
+            .segments()
+            .filter(|(at, _)| *at < PROGRAM_END)
+            .map(|(at, bytes)| (at + bytes.len() as u32).min(PROGRAM_END))
+            .max()
+            .unwrap_or(0) as usize;
+        end.next_multiple_of(2)
+    }
+
+    /// the first `bytes` of program memory, for [`Pic14Builder::flash`]. unset words are 0
+    /// (`nop`), like the emulator has always loaded them.
+    ///
+    /// [`Pic14Builder::flash`]: crate::vm::p16f88::Pic14Builder::flash
+    pub fn program_flash(&self, bytes: usize) -> Vec<u8> {
+        self.read(0..bytes as u32, 0)
+    }
+
+    /// the 14 bit word at word address `addr`, e.g. a config word at 0x2007
+    pub fn word(&self, addr: u16) -> Option<u16> {
+        let at = addr as u32 * 2;
+        match (self.get(at), self.get(at + 1)) {
+            (None, None) => None,
+            (lo, hi) => Some(u16::from_le_bytes([lo.unwrap_or(0), hi.unwrap_or(0)])),
+        }
+    }
+
+    /// initial EEPROM contents, erased (0xFF) where unset
+    pub fn eeprom(&self, bytes: usize) -> Vec<u8> {
+        (0..bytes as u32)
+            .map(|i| self.get(EEPROM_START + i * 2).unwrap_or(0xFF))
+            .collect()
+    }
+
+    /// from the last start address record, if any. PIC toolchains don't write one; the reset
+    /// vector is where execution starts.
+    pub fn start(&self) -> Option<StartAddress> {
+        self.start
+    }
+}
+
+/// entry point given by a start address record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StartAddress {
+    /// CS:IP of record type 03
+    Segment { cs: u16, ip: u16 },
+    /// EIP of record type 05
+    Linear(u32),
+}
+
+pub struct Records<R> {
+    decoder: IntelHexDecoder<R>,
+    done: bool,
+}
+
+impl<R> Records<R> {
+    /// pairs each record with the full address of its first byte, following the extended
+    /// segment and linear address records before it
+    pub fn with_addresses(self) -> Addressed<R> {
+        Addressed { records: self, base: 0 }
+    }
+}
+
+impl<R: Read> Iterator for Records<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let record = self.decoder.read_record();
+        self.done = record
+            .as_ref()
+            .map_or(true, |r| r.kind == RecordKind::EndOfFile);
+        Some(record)
+    }
+}
+
+pub struct Addressed<R> {
+    records: Records<R>,
+    base: u32,
+}
+
+impl<R: Read> Iterator for Addressed<R> {
+    type Item = Result<(u32, Record)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.records.next()? {
+            Ok(x) => x,
+            Err(e) => return Some(Err(e)),
+        };
+        match record.kind {
+            RecordKind::ExtendedSegmentAddress => {
+                self.base = (record.upper_address() as u32) << 4;
             }
-            if buf != [b'\n'] {
-                return Err(Error::InvalidNewLine { found: buf[0] as char });
+            RecordKind::ExtendedLinearAddress => {
+                self.base = (record.upper_address() as u32) << 16;
             }
+            _ => {}
         }
+        Some(Ok((self.base + record.address as u32, record)))
+    }
+}
 
-        Ok(decoded)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum RecordKind {
+    Data = 0,
+    EndOfFile = 1,
+    ExtendedSegmentAddress = 2,
+    StartSegmentAddress = 3,
+    ExtendedLinearAddress = 4,
+    StartLinearAddress = 5,
+}
+
+impl RecordKind {
+    fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0 => Self::Data,
+            1 => Self::EndOfFile,
+            2 => Self::ExtendedSegmentAddress,
+            3 => Self::StartSegmentAddress,
+            4 => Self::ExtendedLinearAddress,
+            5 => Self::StartLinearAddress,
+            _ => return None,
+        })
+    }
+}
+
+/// a line of the hex file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Record {
+    /// lower 16 bits of the address. only meaningful for data records
+    pub address: u16,
+    pub kind: RecordKind,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    pub fn data(address: u16, data: Vec<u8>) -> Self {
+        assert!(data.len() <= 0xFF, "too many bytes for a record");
+        Self { address, kind: RecordKind::Data, data }
+    }
+
+    pub fn end_of_file() -> Self {
+        Self {
+            address: 0,
+            kind: RecordKind::EndOfFile,
+            data: vec![],
+        }
+    }
+
+    pub fn extended_linear_address(upper: u16) -> Self {
+        Self {
+            address: 0,
+            kind: RecordKind::ExtendedLinearAddress,
+            data: upper.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// upper 16 bits of the address set by an extended linear address record, or the segment
+    /// of an extended segment address record
+    pub fn upper_address(&self) -> u16 {
+        let mut b = [0; 2];
+        for (b, d) in b.iter_mut().zip(&self.data) {
+            *b = *d;
+        }
+        u16::from_be_bytes(b)
+    }
+
+    /// `None` for records of the other kinds or of the wrong length
+    pub fn start_address(&self) -> Option<StartAddress> {
+        let data: [u8; 4] = self.data.as_slice().try_into().ok()?;
+        match self.kind {
+            RecordKind::StartSegmentAddress => Some(StartAddress::Segment {
+                cs: u16::from_be_bytes([data[0], data[1]]),
+                ip: u16::from_be_bytes([data[2], data[3]]),
+            }),
+            RecordKind::StartLinearAddress => Some(StartAddress::Linear(u32::from_be_bytes(data))),
+            _ => None,
+        }
+    }
+
+    pub fn checksum(&self) -> u8 {
+        let sum = [
+            self.data.len() as u8,
+            (self.address >> 8) as u8,
+            self.address as u8,
+            self.kind as u8,
+        ]
+        .iter()
+        .chain(&self.data)
+        .fold(0u8, |a, b| a.wrapping_add(*b));
+        sum.wrapping_neg()
     }
 }
 
-pub fn decode_intel_hex<R: Read>(r: R) -> Result<Vec<u8>> {
+pub fn decode_intel_hex<R: Read>(r: R) -> Result<MemoryImage> {
     IntelHexDecoder::new(r).decode()
 }
+
+pub struct IntelHexEncoder<W> {
+    writer: W,
+}
+
+impl<W: Write> IntelHexEncoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        write!(
+            self.writer,
+            ":{:02X}{:04X}{:02X}",
+            record.data.len(),
+            record.address,
+            record.kind as u8
+        )?;
+        for b in &record.data {
+            write!(self.writer, "{b:02X}")?;
+        }
+        writeln!(self.writer, "{:02X}", record.checksum())
+    }
+
+    /// writes `image` as data records, followed by the EOF record.
+    /// addresses above 64KiB are emitted with extended linear address records.
+    pub fn encode(mut self, image: &[u8]) -> io::Result<()> {
+        const BYTES_PER_RECORD: usize = 16;
+
+        let mut upper_address = 0;
+        for (i, chunk) in image.chunks(BYTES_PER_RECORD).enumerate() {
+            let address = i * BYTES_PER_RECORD;
+            if (address >> 16) as u16 != upper_address {
+                upper_address = (address >> 16) as u16;
+                self.write_record(&Record::extended_linear_address(upper_address))?;
+            }
+            self.write_record(&Record::data(address as u16, chunk.to_vec()))?;
+        }
+        self.write_record(&Record::end_of_file())
+    }
+}
+
+pub fn encode_intel_hex<W: Write>(w: W, image: &[u8]) -> io::Result<()> {
+    IntelHexEncoder::new(w).encode(image)
+}
+
+#[test]
+fn records_roundtrip() {
+    let image = (0..0x10020).map(|x| x as u8).collect::<Vec<_>>();
+
+    let mut hex = vec![];
+    encode_intel_hex(&mut hex, &image).unwrap();
+
+    let records = IntelHexDecoder::new(hex.as_slice())
+        .records()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(records[0], Record::data(0, (0..16).collect()));
+    assert_eq!(records[4096], Record::extended_linear_address(1));
+    assert_eq!(records.last(), Some(&Record::end_of_file()));
+
+    let mut rewritten = vec![];
+    let mut encoder = IntelHexEncoder::new(&mut rewritten);
+    for r in &records {
+        encoder.write_record(r).unwrap();
+    }
+    assert_eq!(rewritten, hex);
+
+    let addresses = IntelHexDecoder::new(hex.as_slice())
+        .records()
+        .with_addresses()
+        .map(|x| x.unwrap().0)
+        .collect::<Vec<_>>();
+    assert_eq!(addresses[4095..4098], [0xFFF0, 0x10000, 0x10000]);
+    assert_eq!(addresses[4098], 0x10010);
+
+    assert_eq!(decode_intel_hex(hex.as_slice()).unwrap().to_vec(), image);
+}
+
+#[test]
+fn checksums_are_verified() {
+    let hex = ":0400000005308A003D\n:0400040082000800FF\n:00000001FF\n";
+
+    let err = decode_intel_hex(hex.as_bytes()).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::ChecksumMismatch { line: 2, expected: 0x6E, found: 0xFF }
+        ),
+        "{err:?}"
+    );
+
+    let image = IntelHexDecoder::new(hex.as_bytes())
+        .lenient(true)
+        .decode()
+        .unwrap();
+    assert_eq!(image.to_vec(), [0x05, 0x30, 0x8A, 0x00]);
+
+    // a broken EOF record means the end is never found
+    let hex = ":0400000005308A003D\n:00000001FE\n";
+    let err = IntelHexDecoder::new(hex.as_bytes())
+        .lenient(true)
+        .decode()
+        .unwrap_err();
+    assert!(matches!(err, Error::Io(_)), "{err:?}");
+}
+
+#[test]
+fn decodes_segment_and_start_records() {
+    let records = [
+        Record {
+            address: 0,
+            kind: RecordKind::ExtendedSegmentAddress,
+            data: vec![0x00, 0x10],
+        },
+        Record::data(0x0002, vec![0xAA, 0xBB]),
+        Record {
+            address: 0,
+            kind: RecordKind::StartSegmentAddress,
+            data: vec![0x00, 0x10, 0x00, 0x02],
+        },
+        Record {
+            address: 0,
+            kind: RecordKind::StartLinearAddress,
+            data: vec![0x00, 0x00, 0x01, 0x02],
+        },
+        Record::end_of_file(),
+    ];
+    let mut hex = vec![];
+    for (i, r) in records.iter().enumerate() {
+        IntelHexEncoder::new(&mut hex).write_record(r).unwrap();
+        if i == 1 {
+            hex.extend(b"\r\n");
+        }
+    }
+    // no newline at the end, or blank lines after it
+    assert_eq!(hex.pop(), Some(b'\n'));
+
+    let image = decode_intel_hex(hex.as_slice()).unwrap();
+    assert_eq!(
+        image.segments().collect::<Vec<_>>(),
+        [(0x102, &[0xAA, 0xBB][..])]
+    );
+    assert_eq!(image.start(), Some(StartAddress::Linear(0x0102)));
+    assert_eq!(
+        records[2].start_address(),
+        Some(StartAddress::Segment { cs: 0x0010, ip: 0x0002 })
+    );
+
+    hex.extend(b"\n\n\r\n");
+    assert_eq!(decode_intel_hex(hex.as_slice()).unwrap(), image);
+}
+
+#[test]
+fn memory_image_keeps_regions_apart() {
+    let mut image = MemoryImage::new();
+    image.write(0x0000, &[0x05, 0x30]);
+    image.write(0x0004, &[0x08, 0x00]);
+    image.write(0x0002, &[0x8A, 0x00]);
+    image.write(0x0003, &[0x01]);
+    image.write(0x400E, &0x3F38u16.to_le_bytes());
+    image.write(EEPROM_START + 2, &[0x42, 0x00]);
+
+    let segments = image
+        .segments()
+        .map(|(at, b)| (at, b.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(segments, [(0x0000, 6), (0x400E, 2), (0x4202, 2)]);
+    assert_eq!(image.program_len(), 6);
+    assert_eq!(
+        image.program_flash(8),
+        [0x05, 0x30, 0x8A, 0x01, 0x08, 0x00, 0, 0]
+    );
+    assert_eq!(image.word(0x2007), Some(0x3F38));
+    assert_eq!(image.word(0x2008), None);
+    assert_eq!(image.eeprom(3), [0xFF, 0x42, 0xFF]);
+    assert_eq!(image.to_vec().len(), 0x4204);
+}
@@ -0,0 +1,201 @@
+//! `stk-pic-vm hex ...`: firmware image surgery on top of the record-level hex API
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Subcommand};
+use stk_pic_vm::hex::{IntelHexDecoder, IntelHexEncoder, Record, RecordKind};
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+/// address -> byte. hex files may have holes, so images are kept sparse.
+type Image = BTreeMap<u32, u8>;
+
+#[derive(Subcommand, Debug)]
+pub enum HexCommand {
+    /// merge images. overlapping bytes must agree
+    Merge {
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        out: Output,
+    },
+
+    /// keep only the bytes inside the range
+    Crop {
+        file: PathBuf,
+        /// e.g. `0x0000..0x1000`
+        #[arg(long, value_parser = parse_range)]
+        range: Range<u32>,
+        #[command(flatten)]
+        out: Output,
+    },
+
+    /// overwrite bytes starting at an address
+    Patch {
+        file: PathBuf,
+        #[arg(long, value_parser = parse_u32)]
+        at: u32,
+        /// hex bytes separated by spaces or commas, e.g. `"8a 01"`
+        #[arg(long, value_parser = parse_bytes)]
+        bytes: Bytes,
+        #[command(flatten)]
+        out: Output,
+    },
+
+    /// list the addresses where two images differ. exits with 1 if any
+    Diff { a: PathBuf, b: PathBuf },
+}
+
+#[derive(Args, Debug)]
+pub struct Output {
+    /// write to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Bytes(Vec<u8>);
+
+fn parse_u32(s: &str) -> Result<u32, String> {
+    let r = match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    r.map_err(|e| format!("{s}: {e}"))
+}
+
+fn parse_range(s: &str) -> Result<Range<u32>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected `start..end`, found {s}"))?;
+    Ok(parse_u32(start)?..parse_u32(end)?)
+}
+
+fn parse_bytes(s: &str) -> Result<Bytes, String> {
+    s.split([' ', ','])
+        .filter(|x| !x.is_empty())
+        .map(|x| {
+            u8::from_str_radix(x.trim_start_matches("0x"), 16).map_err(|e| format!("{x}: {e}"))
+        })
+        .collect::<Result<_, _>>()
+        .map(Bytes)
+}
+
+fn load(path: &Path) -> Result<Image> {
+    let file = File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    let mut image = Image::new();
+    for record in IntelHexDecoder::new(BufReader::new(file))
+        .records()
+        .with_addresses()
+    {
+        let (address, record) = record.map_err(|e| format!("{}: {e}", path.display()))?;
+        match record.kind {
+            RecordKind::Data => {
+                for (i, b) in record.data.iter().enumerate() {
+                    image.insert(address + i as u32, *b);
+                }
+            }
+            RecordKind::EndOfFile => break,
+            // PIC programs start at the reset vector
+            RecordKind::StartSegmentAddress | RecordKind::StartLinearAddress => {}
+            RecordKind::ExtendedSegmentAddress | RecordKind::ExtendedLinearAddress => {}
+        }
+    }
+    Ok(image)
+}
+
+fn store(image: &Image, out: &Output) -> Result<()> {
+    const BYTES_PER_RECORD: usize = 16;
+
+    let writer: Box<dyn Write> = match &out.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut encoder = IntelHexEncoder::new(writer);
+
+    let mut upper_address = 0;
+    let mut record: Option<(u32, Vec<u8>)> = None;
+    for (&addr, &b) in image {
+        if let Some((start, data)) = &mut record {
+            let contiguous = *start + data.len() as u32 == addr;
+            if contiguous && data.len() < BYTES_PER_RECORD && addr >> 16 == *start >> 16 {
+                data.push(b);
+                continue;
+            }
+            encoder.write_record(&Record::data(*start as u16, std::mem::take(data)))?;
+        }
+        if (addr >> 16) as u16 != upper_address {
+            upper_address = (addr >> 16) as u16;
+            encoder.write_record(&Record::extended_linear_address(upper_address))?;
+        }
+        record = Some((addr, vec![b]));
+    }
+    if let Some((start, data)) = record {
+        encoder.write_record(&Record::data(start as u16, data))?;
+    }
+    encoder.write_record(&Record::end_of_file())?;
+    Ok(())
+}
+
+pub fn run(cmd: HexCommand) -> Result<ExitCode> {
+    match cmd {
+        HexCommand::Merge { files, out } => {
+            let mut merged = Image::new();
+            for path in &files {
+                for (addr, b) in load(path)? {
+                    match merged.insert(addr, b) {
+                        Some(prev) if prev != b => {
+                            return Err(format!(
+                                "{}: {addr:#06x} is already {prev:#04x}, found {b:#04x}",
+                                path.display()
+                            )
+                            .into())
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            store(&merged, &out)?;
+        }
+
+        HexCommand::Crop { file, range, out } => {
+            let image = load(&file)?;
+            let cropped = image.range(range).map(|(a, b)| (*a, *b)).collect();
+            store(&cropped, &out)?;
+        }
+
+        HexCommand::Patch { file, at, bytes, out } => {
+            let mut image = load(&file)?;
+            for (i, b) in bytes.0.into_iter().enumerate() {
+                image.insert(at + i as u32, b);
+            }
+            store(&image, &out)?;
+        }
+
+        HexCommand::Diff { a, b } => {
+            let (a, b) = (load(&a)?, load(&b)?);
+            let show = |x: Option<&u8>| x.map_or("--".to_owned(), |x| format!("{x:02x}"));
+
+            let mut differs = false;
+            let addrs = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+            for addr in addrs {
+                let (x, y) = (a.get(addr), b.get(addr));
+                if x != y {
+                    differs = true;
+                    println!("{addr:#06x}: {} -> {}", show(x), show(y));
+                }
+            }
+            if differs {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,56 @@
+//! an I2C bus on the pins of an MCU, with the devices attached to the [`I2cBus`]. the SSP is still
+//! a stub, so the firmware bit-bangs SCL and SDA as open-drain lines: TRIS set lets a line go high
+//! through its pull-up, TRIS cleared with the latch at 0 pulls it low.
+
+use std::time::Duration;
+
+use stk_i2c_vm::I2cBus;
+
+use crate::sim::Peripheral;
+use crate::vm::cosim::CoSim;
+use crate::vm::pic14::Pin;
+
+/// the bus on the pins of one MCU, the MCU as the master
+#[derive(Debug)]
+pub struct I2cPins {
+    bus: I2cBus,
+    scl: Pin,
+    sda: Pin,
+    mcu: usize,
+    /// the time of the last update
+    now: Duration,
+}
+
+impl I2cPins {
+    /// on the SSP pins of the PIC16F88: SCL on RB4 and SDA on RB1. `mcu` is the index in the
+    /// [`CoSim`]
+    pub fn new(bus: I2cBus, mcu: usize) -> Self {
+        Self::wired(bus, Pin::rb(4), Pin::rb(1), mcu)
+    }
+
+    pub fn wired(bus: I2cBus, scl: Pin, sda: Pin, mcu: usize) -> Self {
+        Self { bus, scl, sda, mcu, now: Duration::ZERO }
+    }
+
+    pub fn bus(&self) -> &I2cBus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut I2cBus {
+        &mut self.bus
+    }
+}
+
+impl Peripheral for I2cPins {
+    fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+        self.bus.advance(now.saturating_sub(self.now));
+        self.now = now;
+        let vm = mcus.mcu_mut(self.mcu);
+        // high unless something pulls the line low
+        let scl = vm.pin_output(self.scl) != Some(false);
+        let sda = vm.pin_output(self.sda) != Some(false);
+        self.bus.update(scl, sda);
+        vm.set_pin_input(self.scl, self.bus.scl());
+        vm.set_pin_input(self.sda, self.bus.sda());
+    }
+}
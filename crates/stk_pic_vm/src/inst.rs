@@ -2,7 +2,9 @@ use std::fmt::Debug;
 
 use stk_macro::bitmaskeq;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+use crate::vm::p16f88::reg::STATUS;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct RegisterFileAddr(pub u8);
 impl std::fmt::Debug for RegisterFileAddr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -15,7 +17,7 @@ impl RegisterFileAddr {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub struct ProgramAddr(pub u16);
 impl std::fmt::Debug for ProgramAddr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -64,14 +66,353 @@ impl Debug for Instruction {
     }
 }
 
+/// assembler syntax that [`crate::asm`] reads back, e.g. `movlw 0x13` or `addwf 0x20, f`
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.mnemonic();
+        match *self {
+            Instruction::ByteOriented(ByteOrientedInstruction { f, dest, .. }) => {
+                let d = match dest {
+                    Destination::W => "w",
+                    Destination::F => "f",
+                };
+                write!(fmt, "{mnemonic} 0x{:02x}, {d}", f.0)
+            }
+            Instruction::BitOriented(BitOrientedInstruction { b, f, .. }) => {
+                write!(fmt, "{mnemonic} 0x{:02x}, {}", f.0, b.0)
+            }
+            Instruction::LiteralOriented(LiteralOrientedInstruction { k, .. }) => {
+                write!(fmt, "{mnemonic} 0x{k:02x}")
+            }
+            Instruction::Control(c) => match c {
+                ControlInstruction::Goto { addr } | ControlInstruction::Call { addr } => {
+                    write!(fmt, "{mnemonic} 0x{:04x}", addr.0)
+                }
+                ControlInstruction::ClearF { f } | ControlInstruction::MoveWtoF { f } => {
+                    write!(fmt, "{mnemonic} 0x{:02x}", f.0)
+                }
+                _ => write!(fmt, "{mnemonic}"),
+            },
+        }
+    }
+}
+
+/// how to decode an opcode, selected by its bits 13..8
+#[derive(Clone, Copy)]
+enum Form {
+    Byte(ByteOrientedOperation),
+    Bit(BitOrientedOperation),
+    Literal(LiteralOrientedOperation),
+    Call,
+    Goto,
+    /// rows 0x00 and 0x01 pack several instructions. left to [`ControlInstruction::from_code`].
+    Misc,
+    Invalid,
+}
+
+#[rustfmt::skip]
+const FORMS: [Form; 64] = {
+    use BitOrientedOperation::*;
+    use ByteOrientedOperation::*;
+    use Form::{Bit, Byte, Call, Goto, Invalid, Literal, Misc};
+    use LiteralOrientedOperation::*;
+
+    [
+        // 0x00
+        Misc,                           Misc,
+        Byte(SubtractWfromF),           Byte(DecrementF),
+        Byte(OrWf),                     Byte(AndWf),
+        Byte(XorWwithF),                Byte(AddWf),
+        Byte(MoveF),                    Byte(ComplementF),
+        Byte(IncrementF),               Byte(DecrementFSkipIfZ),
+        Byte(RotateRightFThroughCarry), Byte(RotateLeftFThroughCarry),
+        Byte(SwapF),                    Byte(IncrementFSkipIfZ),
+        // 0x10
+        Bit(BitClearF),                 Bit(BitClearF),
+        Bit(BitClearF),                 Bit(BitClearF),
+        Bit(BitSetF),                   Bit(BitSetF),
+        Bit(BitSetF),                   Bit(BitSetF),
+        Bit(SkipIfFBitClear),           Bit(SkipIfFBitClear),
+        Bit(SkipIfFBitClear),           Bit(SkipIfFBitClear),
+        Bit(SkipIfFBitSet),             Bit(SkipIfFBitSet),
+        Bit(SkipIfFBitSet),             Bit(SkipIfFBitSet),
+        // 0x20
+        Call, Call, Call, Call, Call, Call, Call, Call,
+        Goto, Goto, Goto, Goto, Goto, Goto, Goto, Goto,
+        // 0x30
+        Literal(MoveLiteralToW),        Literal(MoveLiteralToW),
+        Literal(MoveLiteralToW),        Literal(MoveLiteralToW),
+        Literal(ReturnWithLiteralInW),  Literal(ReturnWithLiteralInW),
+        Literal(ReturnWithLiteralInW),  Literal(ReturnWithLiteralInW),
+        Literal(OrLiteralWithW),        Literal(AndLiteralWithW),
+        Literal(XorLiteralWithW),       Invalid,
+        Literal(SubtractWFromLiteral),  Literal(SubtractWFromLiteral),
+        Literal(AddLiteralToW),         Literal(AddLiteralToW),
+    ]
+};
+
 impl Instruction {
+    /// decodes through a table indexed by the opcode's upper bits. the per-kind `from_code`s
+    /// are kept for decoding a known kind.
     pub fn from_code(i: u16) -> Option<Instruction> {
-        ByteOrientedInstruction::from_code(i)
-            .map(Instruction::ByteOriented)
-            .or(BitOrientedInstruction::from_code(i).map(Instruction::BitOriented))
-            .or(LiteralOrientedInstruction::from_code(i).map(Instruction::LiteralOriented))
-            .or(ControlInstruction::from_code(i).map(Instruction::Control))
+        let f = RegisterFileAddr((i & 0b0000_0000_0111_1111) as u8);
+        let inst = match FORMS[((i >> 8) & 0b0011_1111) as usize] {
+            Form::Byte(op) => Instruction::ByteOriented(ByteOrientedInstruction {
+                op,
+                f,
+                dest: if (i & 0b0000_0000_1000_0000) == 0 {
+                    Destination::W
+                } else {
+                    Destination::F
+                },
+            }),
+            Form::Bit(op) => Instruction::BitOriented(BitOrientedInstruction {
+                op,
+                b: BitIndex(((i & 0b0000_0011_1000_0000) >> 7) as u8),
+                f,
+            }),
+            Form::Literal(op) => {
+                Instruction::LiteralOriented(LiteralOrientedInstruction { op, k: i as u8 })
+            }
+            Form::Call => Instruction::Control(ControlInstruction::Call {
+                addr: ProgramAddr(i & 0b0000_0111_1111_1111),
+            }),
+            Form::Goto => Instruction::Control(ControlInstruction::Goto {
+                addr: ProgramAddr(i & 0b0000_0111_1111_1111),
+            }),
+            Form::Misc => return ControlInstruction::from_code(i).map(Instruction::Control),
+            Form::Invalid => return None,
+        };
+        Some(inst)
+    }
+
+    fn meta(&self) -> Meta {
+        use BitOrientedOperation::*;
+        use ByteOrientedOperation::*;
+        use ControlInstruction::*;
+        use LiteralOrientedOperation::*;
+        use {
+            BitOrientedInstruction as B, ByteOrientedInstruction as Y,
+            LiteralOrientedInstruction as L,
+        };
+
+        const NONE: STATUS = STATUS::empty();
+        const Z: STATUS = STATUS::Z;
+        const C: STATUS = STATUS::C;
+        const CDCZ: STATUS = STATUS::C.union(STATUS::DC).union(STATUS::Z);
+        const TOPD: STATUS = STATUS::TO.union(STATUS::PD);
+
+        let (f, dest_f) = match *self {
+            Instruction::ByteOriented(Y { f, dest, .. }) => (Some(f), dest == Destination::F),
+            Instruction::BitOriented(B { f, .. }) => (Some(f), false),
+            Instruction::Control(ClearF { f } | MoveWtoF { f }) => (Some(f), false),
+            _ => (None, false),
+        };
+
+        #[rustfmt::skip]
+        let (reads_f, writes_f, flags, cycles, taken_cycles) = match *self {
+            // instruction                               reads f writes f  flags cycles taken
+            Instruction::ByteOriented(Y { op, .. }) => match op {
+                AddWf | SubtractWfromF                 => (true,  dest_f, CDCZ, 1, 1),
+                AndWf | ComplementF | DecrementF
+                | IncrementF | OrWf | MoveF
+                | XorWwithF                            => (true,  dest_f, Z,    1, 1),
+                DecrementFSkipIfZ | IncrementFSkipIfZ  => (true,  dest_f, NONE, 1, 2),
+                RotateLeftFThroughCarry
+                | RotateRightFThroughCarry             => (true,  dest_f, C,    1, 1),
+                SwapF                                  => (true,  dest_f, NONE, 1, 1),
+            },
+            Instruction::BitOriented(B { op, .. }) => match op {
+                BitClearF | BitSetF                    => (true,  true,   NONE, 1, 1),
+                SkipIfFBitClear | SkipIfFBitSet        => (true,  false,  NONE, 1, 2),
+            },
+            Instruction::LiteralOriented(L { op, .. }) => match op {
+                AddLiteralToW | SubtractWFromLiteral   => (false, false,  CDCZ, 1, 1),
+                AndLiteralWithW | OrLiteralWithW
+                | XorLiteralWithW                      => (false, false,  Z,    1, 1),
+                MoveLiteralToW                         => (false, false,  NONE, 1, 1),
+                ReturnWithLiteralInW                   => (false, false,  NONE, 2, 2),
+            },
+            Instruction::Control(c) => match c {
+                ClearWatchDogTimer | Sleep             => (false, false,  TOPD, 1, 1),
+                ReturnFromInterrupt | Return
+                | Goto { .. } | Call { .. }            => (false, false,  NONE, 2, 2),
+                Noop                                   => (false, false,  NONE, 1, 1),
+                ClearF { .. }                          => (false, true,   Z,    1, 1),
+                ClearW                                 => (false, false,  Z,    1, 1),
+                MoveWtoF { .. }                        => (false, true,   NONE, 1, 1),
+            },
+        };
+
+        Meta {
+            reads: f.filter(|_| reads_f),
+            writes: f.filter(|_| writes_f),
+            flags,
+            cycles,
+            taken_cycles,
+        }
+    }
+
+    /// register file address read by this instruction
+    pub fn reads_register(&self) -> Option<RegisterFileAddr> {
+        self.meta().reads
+    }
+
+    /// register file address written by this instruction, not counting STATUS flags
+    pub fn writes_register(&self) -> Option<RegisterFileAddr> {
+        self.meta().writes
+    }
+
+    /// lower 11 bits of the jump destination. the rest comes from PCLATH at runtime.
+    pub fn branch_target(&self) -> Option<ProgramAddr> {
+        match *self {
+            Instruction::Control(ControlInstruction::Goto { addr })
+            | Instruction::Control(ControlInstruction::Call { addr }) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// STATUS bits this instruction may change
+    pub fn affects_flags(&self) -> STATUS {
+        self.meta().flags
+    }
+
+    /// instruction cycles to execute this. `taken` is whether a skip instruction skips.
+    pub fn cycle_cost(&self, taken: bool) -> u8 {
+        let meta = self.meta();
+        if taken {
+            meta.taken_cycles
+        } else {
+            meta.cycles
+        }
+    }
+
+    /// the opcode, the inverse of [`Self::from_code`]. bits the decoder ignores are 0.
+    pub fn to_code(&self) -> u16 {
+        use BitOrientedOperation::*;
+        use ByteOrientedOperation::*;
+        use ControlInstruction::*;
+        use LiteralOrientedOperation::*;
+
+        match *self {
+            Instruction::ByteOriented(ByteOrientedInstruction { op, f, dest }) => {
+                let op: u16 = match op {
+                    SubtractWfromF => 0x02,
+                    DecrementF => 0x03,
+                    OrWf => 0x04,
+                    AndWf => 0x05,
+                    XorWwithF => 0x06,
+                    AddWf => 0x07,
+                    MoveF => 0x08,
+                    ComplementF => 0x09,
+                    IncrementF => 0x0A,
+                    DecrementFSkipIfZ => 0x0B,
+                    RotateRightFThroughCarry => 0x0C,
+                    RotateLeftFThroughCarry => 0x0D,
+                    SwapF => 0x0E,
+                    IncrementFSkipIfZ => 0x0F,
+                };
+                let d = (dest == Destination::F) as u16;
+                op << 8 | d << 7 | (f.0 & 0x7F) as u16
+            }
+            Instruction::BitOriented(BitOrientedInstruction { op, b, f }) => {
+                let op: u16 = match op {
+                    BitClearF => 0x1000,
+                    BitSetF => 0x1400,
+                    SkipIfFBitClear => 0x1800,
+                    SkipIfFBitSet => 0x1C00,
+                };
+                op | ((b.0 & 0x7) as u16) << 7 | (f.0 & 0x7F) as u16
+            }
+            Instruction::LiteralOriented(LiteralOrientedInstruction { op, k }) => {
+                let op: u16 = match op {
+                    MoveLiteralToW => 0x3000,
+                    ReturnWithLiteralInW => 0x3400,
+                    OrLiteralWithW => 0x3800,
+                    AndLiteralWithW => 0x3900,
+                    XorLiteralWithW => 0x3A00,
+                    SubtractWFromLiteral => 0x3C00,
+                    AddLiteralToW => 0x3E00,
+                };
+                op | k as u16
+            }
+            Instruction::Control(c) => match c {
+                Noop => 0x0000,
+                Return => 0x0008,
+                ReturnFromInterrupt => 0x0009,
+                Sleep => 0x0063,
+                ClearWatchDogTimer => 0x0064,
+                ClearW => 0x0100,
+                MoveWtoF { f } => 0x0080 | (f.0 & 0x7F) as u16,
+                ClearF { f } => 0x0180 | (f.0 & 0x7F) as u16,
+                Call { addr } => 0x2000 | (addr.0 & 0x7FF),
+                Goto { addr } => 0x2800 | (addr.0 & 0x7FF),
+            },
+        }
     }
+
+    /// assembler name, e.g. `movf`
+    pub fn mnemonic(&self) -> &'static str {
+        use BitOrientedOperation::*;
+        use ByteOrientedOperation::*;
+        use ControlInstruction::*;
+        use LiteralOrientedOperation::*;
+
+        match *self {
+            Instruction::ByteOriented(ByteOrientedInstruction { op, .. }) => match op {
+                AddWf => "addwf",
+                AndWf => "andwf",
+                ComplementF => "comf",
+                DecrementF => "decf",
+                DecrementFSkipIfZ => "decfsz",
+                IncrementF => "incf",
+                IncrementFSkipIfZ => "incfsz",
+                OrWf => "iorwf",
+                MoveF => "movf",
+                RotateLeftFThroughCarry => "rlf",
+                RotateRightFThroughCarry => "rrf",
+                SubtractWfromF => "subwf",
+                SwapF => "swapf",
+                XorWwithF => "xorwf",
+            },
+            Instruction::BitOriented(BitOrientedInstruction { op, .. }) => match op {
+                BitClearF => "bcf",
+                BitSetF => "bsf",
+                SkipIfFBitClear => "btfsc",
+                SkipIfFBitSet => "btfss",
+            },
+            Instruction::LiteralOriented(LiteralOrientedInstruction { op, .. }) => match op {
+                AddLiteralToW => "addlw",
+                AndLiteralWithW => "andlw",
+                OrLiteralWithW => "iorlw",
+                MoveLiteralToW => "movlw",
+                ReturnWithLiteralInW => "retlw",
+                SubtractWFromLiteral => "sublw",
+                XorLiteralWithW => "xorlw",
+            },
+            Instruction::Control(c) => match c {
+                ClearWatchDogTimer => "clrwdt",
+                ReturnFromInterrupt => "retfie",
+                Return => "return",
+                Sleep => "sleep",
+                Noop => "nop",
+                Goto { .. } => "goto",
+                Call { .. } => "call",
+                ClearF { .. } => "clrf",
+                ClearW => "clrw",
+                MoveWtoF { .. } => "movwf",
+            },
+        }
+    }
+}
+
+/// static properties of an instruction, shared by the executor and tools
+struct Meta {
+    reads: Option<RegisterFileAddr>,
+    writes: Option<RegisterFileAddr>,
+    flags: STATUS,
+    cycles: u8,
+    taken_cycles: u8,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -406,14 +747,14 @@ pub enum LiteralOrientedOperation {
     /// ```ignore
     /// W + k -> W
     /// ```
-    /// - affects: None
+    /// - affects: C, DC, Z
     #[doc(alias = "addlw")]
     AddLiteralToW,
 
     /// ```ignore
     /// W & k -> W
     /// ```
-    /// - affects: None
+    /// - affects: Z
     #[doc(alias = "andlw")]
     AndLiteralWithW,
 }
@@ -535,3 +876,93 @@ impl ControlInstruction {
         }
     }
 }
+
+#[test]
+fn encodes_what_it_decodes() {
+    for code in 0..0x4000 {
+        if let Some(inst) = Instruction::from_code(code) {
+            assert_eq!(
+                Instruction::from_code(inst.to_code()),
+                Some(inst),
+                "{code:#06x}"
+            );
+        }
+    }
+    assert_eq!(Instruction::from_code(0x3EFB).unwrap().to_code(), 0x3EFB);
+}
+
+#[test]
+fn operand_introspection() {
+    let decode = |code| Instruction::from_code(code).unwrap();
+    let f = Some(RegisterFileAddr(0x20));
+
+    // addwf 0x20, f
+    let addwf = decode(0b00_0111_1010_0000);
+    assert_eq!(addwf.reads_register(), f);
+    assert_eq!(addwf.writes_register(), f);
+    assert_eq!(addwf.affects_flags(), STATUS::C | STATUS::DC | STATUS::Z);
+
+    // addwf 0x20, w
+    assert_eq!(decode(0b00_0111_0010_0000).writes_register(), None);
+
+    // movwf 0x20
+    let movwf = decode(0b00_0000_1010_0000);
+    assert_eq!(movwf.reads_register(), None);
+    assert_eq!(movwf.writes_register(), f);
+
+    // btfsc 0x20, 3
+    let btfsc = decode(0b01_1001_1010_0000);
+    assert_eq!(btfsc.reads_register(), f);
+    assert_eq!(btfsc.writes_register(), None);
+    assert_eq!((btfsc.cycle_cost(false), btfsc.cycle_cost(true)), (1, 2));
+
+    // call 0x123
+    let call = decode(0b10_0001_0010_0011);
+    assert_eq!(call.branch_target(), Some(ProgramAddr(0x123)));
+    assert_eq!(call.cycle_cost(false), 2);
+    assert!(call.affects_flags().is_empty());
+}
+
+#[test]
+fn table_decode_matches_decoders() {
+    for i in 0..=u16::MAX {
+        let chained = ByteOrientedInstruction::from_code(i)
+            .map(Instruction::ByteOriented)
+            .or(BitOrientedInstruction::from_code(i).map(Instruction::BitOriented))
+            .or(LiteralOrientedInstruction::from_code(i).map(Instruction::LiteralOriented))
+            .or(ControlInstruction::from_code(i).map(Instruction::Control));
+        assert_eq!(Instruction::from_code(i), chained, "{i:#06x}");
+    }
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn decoded_operands_fit_their_fields(code in 0..0x4000u16) {
+        use ControlInstruction::*;
+
+        match Instruction::from_code(code) {
+            Some(Instruction::ByteOriented(x)) => proptest::prop_assert!(x.f.0 < 0x80),
+            Some(Instruction::BitOriented(x)) => {
+                proptest::prop_assert!(x.f.0 < 0x80);
+                proptest::prop_assert!(x.b.0 < 8);
+            }
+            Some(Instruction::Control(ClearF { f } | MoveWtoF { f })) => {
+                proptest::prop_assert!(f.0 < 0x80)
+            }
+            Some(Instruction::Control(Call { addr } | Goto { addr })) => {
+                proptest::prop_assert!(addr.0 < 0x800)
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn encodes_any_word_it_decodes(code: u16) {
+        if let Some(inst) = Instruction::from_code(code) {
+            let again = inst.to_code();
+            proptest::prop_assert!(again < 0x4000, "{:#06x} encodes to {:#06x}", code, again);
+            proptest::prop_assert_eq!(Instruction::from_code(again), Some(inst));
+        }
+    }
+}
@@ -0,0 +1,578 @@
+//! PIC18 (16-bit instruction) encoding. `movff`, `lfsr`, `call` and `goto` take a second word,
+//! see [`Instruction::words`]. only the standard instruction set is decoded; the extended one
+//! (`addfsr`, `pushl`, ...) is left invalid.
+
+pub use crate::inst::Destination;
+
+/// where an 8-bit `f` operand points, selected by the `a` bit
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Ram {
+    /// the access bank: the bottom of bank 0 and the SFRs at the top of bank 15
+    Access,
+    /// the bank selected by BSR
+    Banked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// `op f, d, a`
+    Byte {
+        op: ByteOperation,
+        f: u8,
+        dest: Destination,
+        ram: Ram,
+    },
+    /// `op f, a`. the result, if any, goes back to f
+    File {
+        op: FileOperation,
+        f: u8,
+        ram: Ram,
+    },
+    /// `op f, b, a`
+    Bit {
+        op: BitOperation,
+        f: u8,
+        b: u8,
+        ram: Ram,
+    },
+    Literal {
+        op: LiteralOperation,
+        k: u8,
+    },
+    /// ```ignore
+    /// k -> BSR
+    /// ```
+    #[doc(alias = "movlb")]
+    MoveLiteralToBsr {
+        k: u8,
+    },
+    /// ```ignore
+    /// *src -> *dst
+    /// ```
+    /// both are full 12-bit addresses. two words, 2 cycles
+    #[doc(alias = "movff")]
+    MoveFf {
+        src: u16,
+        dst: u16,
+    },
+    /// ```ignore
+    /// k -> FSRn
+    /// ```
+    /// two words, 2 cycles
+    #[doc(alias = "lfsr")]
+    LoadFsr {
+        fsr: u8,
+        k: u16,
+    },
+    /// `offset` words from the next instruction if `cond` holds. 2 cycles if taken
+    Branch {
+        cond: Condition,
+        offset: i8,
+    },
+    /// `offset` words from the next instruction
+    #[doc(alias = "bra")]
+    BranchAlways {
+        offset: i16,
+    },
+    /// [`Instruction::BranchAlways`], pushing the return address
+    #[doc(alias = "rcall")]
+    RelativeCall {
+        offset: i16,
+    },
+    /// `addr` is a word address. `fast` also saves W, STATUS and BSR to the shadow registers.
+    /// two words, 2 cycles
+    Call {
+        addr: u32,
+        fast: bool,
+    },
+    /// `addr` is a word address. two words, 2 cycles
+    Goto {
+        addr: u32,
+    },
+    /// `fast` restores W, STATUS and BSR from the shadow registers
+    Return {
+        fast: bool,
+    },
+    /// [`Instruction::Return`], setting GIE
+    #[doc(alias = "retfie")]
+    ReturnFromInterrupt {
+        fast: bool,
+    },
+    /// `tblrd` / `tblwt`
+    Table {
+        op: TableOperation,
+    },
+    #[doc(alias = "clrwdt")]
+    ClearWatchDogTimer,
+    #[doc(alias = "daw")]
+    DecimalAdjustW,
+    #[doc(alias = "nop")]
+    Noop,
+    /// drops the top of the return stack
+    Pop,
+    /// pushes the address of the next instruction
+    Push,
+    Reset,
+    Sleep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOperation {
+    /// W + *f
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "addwf")]
+    AddWf,
+    /// W + *f + C
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "addwfc")]
+    AddWfWithCarry,
+    /// W & *f
+    /// - affects: Z, N
+    #[doc(alias = "andwf")]
+    AndWf,
+    /// !*f
+    /// - affects: Z, N
+    #[doc(alias = "comf")]
+    ComplementF,
+    /// *f - 1
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "decf")]
+    DecrementF,
+    /// *f - 1, skipping the next instruction if it is 0
+    #[doc(alias = "decfsz")]
+    DecrementFSkipIfZ,
+    /// *f - 1, skipping the next instruction unless it is 0
+    #[doc(alias = "dcfsnz")]
+    DecrementFSkipIfNotZ,
+    /// *f + 1
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "incf")]
+    IncrementF,
+    /// *f + 1, skipping the next instruction if it is 0
+    #[doc(alias = "incfsz")]
+    IncrementFSkipIfZ,
+    /// *f + 1, skipping the next instruction unless it is 0
+    #[doc(alias = "infsnz")]
+    IncrementFSkipIfNotZ,
+    /// W | *f
+    /// - affects: Z, N
+    #[doc(alias = "iorwf")]
+    OrWf,
+    /// *f
+    /// - affects: Z, N
+    #[doc(alias = "movf")]
+    MoveF,
+    /// <- C <- *f <-
+    /// - affects: C, Z, N
+    #[doc(alias = "rlcf")]
+    RotateLeftFThroughCarry,
+    /// <- *f <-
+    /// - affects: Z, N
+    #[doc(alias = "rlncf")]
+    RotateLeftF,
+    /// -> C -> *f ->
+    /// - affects: C, Z, N
+    #[doc(alias = "rrcf")]
+    RotateRightFThroughCarry,
+    /// -> *f ->
+    /// - affects: Z, N
+    #[doc(alias = "rrncf")]
+    RotateRightF,
+    /// W - *f - !C
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "subfwb")]
+    SubtractFFromWWithBorrow,
+    /// *f - W
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "subwf")]
+    SubtractWfromF,
+    /// *f - W - !C
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "subwfb")]
+    SubtractWfromFWithBorrow,
+    /// nibbles of *f swapped
+    #[doc(alias = "swapf")]
+    SwapF,
+    /// W ^ *f
+    /// - affects: Z, N
+    #[doc(alias = "xorwf")]
+    XorWwithF,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileOperation {
+    /// 0 -> *f, 1 -> Z
+    #[doc(alias = "clrf")]
+    ClearF,
+    /// skips the next instruction if *f == W
+    #[doc(alias = "cpfseq")]
+    CompareSkipIfEqual,
+    /// skips the next instruction if *f > W, unsigned
+    #[doc(alias = "cpfsgt")]
+    CompareSkipIfGreater,
+    /// skips the next instruction if *f < W, unsigned
+    #[doc(alias = "cpfslt")]
+    CompareSkipIfLess,
+    /// W -> *f
+    #[doc(alias = "movwf")]
+    MoveWtoF,
+    /// W * *f -> PRODH:PRODL
+    #[doc(alias = "mulwf")]
+    MultiplyWwithF,
+    /// -*f -> *f
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "negf")]
+    NegateF,
+    /// 0xFF -> *f
+    #[doc(alias = "setf")]
+    SetF,
+    /// skips the next instruction if *f == 0
+    #[doc(alias = "tstfsz")]
+    TestFSkipIfZ,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOperation {
+    #[doc(alias = "bcf")]
+    BitClearF,
+    #[doc(alias = "bsf")]
+    BitSetF,
+    #[doc(alias = "btfsc")]
+    SkipIfFBitClear,
+    #[doc(alias = "btfss")]
+    SkipIfFBitSet,
+    #[doc(alias = "btg")]
+    BitToggleF,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiteralOperation {
+    /// W + k -> W
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "addlw")]
+    AddLiteralToW,
+    /// W & k -> W
+    /// - affects: Z, N
+    #[doc(alias = "andlw")]
+    AndLiteralWithW,
+    /// W | k -> W
+    /// - affects: Z, N
+    #[doc(alias = "iorlw")]
+    OrLiteralWithW,
+    #[doc(alias = "movlw")]
+    MoveLiteralToW,
+    /// W * k -> PRODH:PRODL
+    #[doc(alias = "mullw")]
+    MultiplyLiteralWithW,
+    #[doc(alias = "retlw")]
+    ReturnWithLiteralInW,
+    /// k - W -> W
+    /// - affects: C, DC, Z, OV, N
+    #[doc(alias = "sublw")]
+    SubtractWFromLiteral,
+    /// W ^ k -> W
+    /// - affects: Z, N
+    #[doc(alias = "xorlw")]
+    XorLiteralWithW,
+}
+
+/// STATUS condition a [`Instruction::Branch`] tests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    #[doc(alias = "bz")]
+    Zero,
+    #[doc(alias = "bnz")]
+    NotZero,
+    #[doc(alias = "bc")]
+    Carry,
+    #[doc(alias = "bnc")]
+    NotCarry,
+    #[doc(alias = "bov")]
+    Overflow,
+    #[doc(alias = "bnov")]
+    NotOverflow,
+    #[doc(alias = "bn")]
+    Negative,
+    #[doc(alias = "bnn")]
+    NotNegative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableOperation {
+    /// `tblwt` if set, `tblrd` otherwise
+    pub write: bool,
+    pub pointer: TablePointer,
+}
+
+/// what happens to TBLPTR around a table access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TablePointer {
+    /// `*`
+    Unchanged,
+    /// `*+`
+    PostIncrement,
+    /// `*-`
+    PostDecrement,
+    /// `+*`
+    PreIncrement,
+}
+
+/// byte-oriented operations by bits 15..10, for opcodes 0x04xx-0x5Fxx
+#[rustfmt::skip]
+const BYTE_OPS: [Option<ByteOperation>; 24] = {
+    use ByteOperation::*;
+
+    [
+        None,                           Some(DecrementF),
+        None,                           None,
+        Some(OrWf),                     Some(AndWf),
+        Some(XorWwithF),                Some(ComplementF),
+        Some(AddWfWithCarry),           Some(AddWf),
+        Some(IncrementF),               Some(DecrementFSkipIfZ),
+        Some(RotateRightFThroughCarry), Some(RotateLeftFThroughCarry),
+        Some(SwapF),                    Some(IncrementFSkipIfZ),
+        Some(RotateRightF),             Some(RotateLeftF),
+        Some(IncrementFSkipIfNotZ),     Some(DecrementFSkipIfNotZ),
+        Some(MoveF),                    Some(SubtractFFromWWithBorrow),
+        Some(SubtractWfromFWithBorrow), Some(SubtractWfromF),
+    ]
+};
+
+/// by bits 11..9 of 0x6xxx
+const FILE_OPS: [FileOperation; 8] = {
+    use FileOperation::*;
+
+    [
+        CompareSkipIfLess,
+        CompareSkipIfEqual,
+        CompareSkipIfGreater,
+        TestFSkipIfZ,
+        SetF,
+        ClearF,
+        NegateF,
+        MoveWtoF,
+    ]
+};
+
+/// by bits 10..8 of 0xE0xx-0xE7xx
+const CONDITIONS: [Condition; 8] = {
+    use Condition::*;
+
+    [
+        Zero,
+        NotZero,
+        Carry,
+        NotCarry,
+        Overflow,
+        NotOverflow,
+        Negative,
+        NotNegative,
+    ]
+};
+
+impl Instruction {
+    /// `next` is the word after `code`, only looked at by two-word instructions
+    pub fn from_code(code: u16, next: u16) -> Option<Instruction> {
+        use LiteralOperation::*;
+
+        let f = code as u8;
+        let ram = if code & 0x0100 == 0 {
+            Ram::Access
+        } else {
+            Ram::Banked
+        };
+        let dest = if code & 0x0200 == 0 {
+            Destination::W
+        } else {
+            Destination::F
+        };
+        let literal = |op| Some(Instruction::Literal { op, k: code as u8 });
+        // 11-bit signed offset of bra/rcall
+        let long_offset = ((code << 5) as i16) >> 5;
+
+        match code >> 8 {
+            0x00 => Self::misc(code as u8),
+            0x01 if code & 0x00F0 == 0 => Some(Instruction::MoveLiteralToBsr { k: code as u8 }),
+            0x02 | 0x03 => Some(Instruction::File { op: FileOperation::MultiplyWwithF, f, ram }),
+            0x08 => literal(SubtractWFromLiteral),
+            0x09 => literal(OrLiteralWithW),
+            0x0A => literal(XorLiteralWithW),
+            0x0B => literal(AndLiteralWithW),
+            0x0C => literal(ReturnWithLiteralInW),
+            0x0D => literal(MultiplyLiteralWithW),
+            0x0E => literal(MoveLiteralToW),
+            0x0F => literal(AddLiteralToW),
+            0x04..=0x5F => {
+                let op = BYTE_OPS[(code >> 10) as usize]?;
+                Some(Instruction::Byte { op, f, dest, ram })
+            }
+            0x60..=0x6F => Some(Instruction::File {
+                op: FILE_OPS[((code >> 9) & 0b111) as usize],
+                f,
+                ram,
+            }),
+            0x70..=0xBF => {
+                let op = match code >> 12 {
+                    0x7 => BitOperation::BitToggleF,
+                    0x8 => BitOperation::BitSetF,
+                    0x9 => BitOperation::BitClearF,
+                    0xA => BitOperation::SkipIfFBitSet,
+                    _ => BitOperation::SkipIfFBitClear,
+                };
+                let b = ((code >> 9) & 0b111) as u8;
+                Some(Instruction::Bit { op, f, b, ram })
+            }
+            0xC0..=0xCF => Some(Instruction::MoveFf { src: code & 0x0FFF, dst: next & 0x0FFF }),
+            0xD0..=0xD7 => Some(Instruction::BranchAlways { offset: long_offset }),
+            0xD8..=0xDF => Some(Instruction::RelativeCall { offset: long_offset }),
+            0xE0..=0xE7 => Some(Instruction::Branch {
+                cond: CONDITIONS[((code >> 8) & 0b111) as usize],
+                offset: code as u8 as i8,
+            }),
+            0xEC | 0xED => Some(Instruction::Call {
+                addr: Self::long_addr(code, next),
+                fast: code & 0x0100 != 0,
+            }),
+            0xEE if code & 0x00F0 < 0x0030 => Some(Instruction::LoadFsr {
+                fsr: ((code >> 4) & 0b11) as u8,
+                k: ((code & 0x000F) << 8) | (next & 0x00FF),
+            }),
+            0xEF => Some(Instruction::Goto { addr: Self::long_addr(code, next) }),
+            // the second word of a two-word instruction runs as a nop
+            0xF0..=0xFF => Some(Instruction::Noop),
+            _ => None,
+        }
+    }
+
+    /// the 0x00xx row
+    fn misc(low: u8) -> Option<Instruction> {
+        use TablePointer::*;
+
+        let inst = match low {
+            0x00 => Instruction::Noop,
+            0x03 => Instruction::Sleep,
+            0x04 => Instruction::ClearWatchDogTimer,
+            0x05 => Instruction::Push,
+            0x06 => Instruction::Pop,
+            0x07 => Instruction::DecimalAdjustW,
+            0x08..=0x0F => Instruction::Table {
+                op: TableOperation {
+                    write: low & 0b100 != 0,
+                    pointer: [Unchanged, PostIncrement, PostDecrement, PreIncrement]
+                        [(low & 0b11) as usize],
+                },
+            },
+            0x10 | 0x11 => Instruction::ReturnFromInterrupt { fast: low & 1 != 0 },
+            0x12 | 0x13 => Instruction::Return { fast: low & 1 != 0 },
+            0xFF => Instruction::Reset,
+            _ => return None,
+        };
+        Some(inst)
+    }
+
+    /// k19..8 in the second word, k7..0 in the first
+    fn long_addr(code: u16, next: u16) -> u32 {
+        (((next & 0x0FFF) as u32) << 8) | (code & 0x00FF) as u32
+    }
+
+    /// program words this instruction takes
+    pub fn words(&self) -> u8 {
+        match self {
+            Instruction::MoveFf { .. }
+            | Instruction::LoadFsr { .. }
+            | Instruction::Call { .. }
+            | Instruction::Goto { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    /// instruction cycles to execute this. `taken` is whether a branch branches or a skip
+    /// skips; skipping a two-word instruction takes one more cycle than this.
+    pub fn cycle_cost(&self, taken: bool) -> u8 {
+        match self {
+            Instruction::MoveFf { .. }
+            | Instruction::LoadFsr { .. }
+            | Instruction::BranchAlways { .. }
+            | Instruction::RelativeCall { .. }
+            | Instruction::Call { .. }
+            | Instruction::Goto { .. }
+            | Instruction::Return { .. }
+            | Instruction::ReturnFromInterrupt { .. }
+            | Instruction::Table { .. }
+            | Instruction::Literal { op: LiteralOperation::ReturnWithLiteralInW, .. } => 2,
+            _ if taken => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[test]
+fn decodes_pic18_encodings() {
+    let decode = |code| Instruction::from_code(code, 0xF000).unwrap();
+
+    // addwf 0x20, f, b
+    assert_eq!(
+        decode(0x2720),
+        Instruction::Byte {
+            op: ByteOperation::AddWf,
+            f: 0x20,
+            dest: Destination::F,
+            ram: Ram::Banked
+        }
+    );
+    // mulwf 0x10, a
+    assert_eq!(
+        decode(0x0210),
+        Instruction::File {
+            op: FileOperation::MultiplyWwithF,
+            f: 0x10,
+            ram: Ram::Access
+        }
+    );
+    // btg 0x05, 7, a
+    assert_eq!(
+        decode(0x7E05),
+        Instruction::Bit {
+            op: BitOperation::BitToggleF,
+            f: 0x05,
+            b: 7,
+            ram: Ram::Access
+        }
+    );
+    // bra -1
+    assert_eq!(decode(0xD7FF), Instruction::BranchAlways { offset: -1 });
+    // bnz +4
+    assert_eq!(
+        decode(0xE104),
+        Instruction::Branch { cond: Condition::NotZero, offset: 4 }
+    );
+    // tblrd*+
+    let tblrd = decode(0x0009);
+    assert_eq!(
+        tblrd,
+        Instruction::Table {
+            op: TableOperation { write: false, pointer: TablePointer::PostIncrement }
+        }
+    );
+    assert_eq!(tblrd.cycle_cost(false), 2);
+
+    // call 0x12345, fast
+    let call = Instruction::from_code(0xED45, 0xF123).unwrap();
+    assert_eq!(call, Instruction::Call { addr: 0x12345, fast: true });
+    assert_eq!(call.words(), 2);
+    // lfsr 2, 0x3AB
+    assert_eq!(
+        Instruction::from_code(0xEE23, 0xF0AB),
+        Some(Instruction::LoadFsr { fsr: 2, k: 0x3AB })
+    );
+    // movff 0x123, 0xF8A
+    assert_eq!(
+        Instruction::from_code(0xC123, 0xFF8A),
+        Some(Instruction::MoveFf { src: 0x123, dst: 0xF8A })
+    );
+
+    // extended instruction set and holes
+    assert_eq!(Instruction::from_code(0xE800, 0), None);
+    assert_eq!(Instruction::from_code(0x0001, 0), None);
+    assert_eq!(Instruction::from_code(0x0120, 0), None);
+}
@@ -0,0 +1,176 @@
+//! an HD44780 on the pins of an MCU. [`LcdWiring`] says which pins go where, and [`Lcd`] puts the
+//! controller on a [`Simulation`](crate::sim::Simulation), reading the bus after every
+//! instruction and driving the data lines back while the firmware reads the display.
+
+use std::time::Duration;
+
+use stk_hd44780_vm::{Hd44780, Hd44780PinState, PinObserver};
+
+use crate::sim::Peripheral;
+use crate::vm::cosim::CoSim;
+use crate::vm::pic14::{Pic14, Pin};
+
+/// a 4-bit bus: DB3..0 of the controller aren't connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LcdWiring {
+    pub e: Pin,
+    pub rs: Pin,
+    /// `None` when RW is tied low and the firmware can only write
+    pub rw: Option<Pin>,
+    /// DB7..4
+    pub db: [Pin; 4],
+}
+
+impl LcdWiring {
+    /// as on the board: E on RA3, RS on RA4, DB7..4 on RB3..0 and RW tied low
+    pub fn board() -> Self {
+        Self {
+            e: Pin::ra(3),
+            rs: Pin::ra(4),
+            rw: None,
+            db: [3, 2, 1, 0].map(Pin::rb),
+        }
+    }
+
+    /// what the controller sees on its pins
+    pub fn pins(&self, vm: &Pic14) -> Hd44780PinState {
+        let level = |pin| Some(vm.pin_level(pin));
+        Hd44780PinState {
+            rs: level(self.rs),
+            rw: Some(self.rw.is_some_and(|x| vm.pin_level(x))),
+            e: level(self.e),
+            db7: level(self.db[0]),
+            db6: level(self.db[1]),
+            db5: level(self.db[2]),
+            db4: level(self.db[3]),
+            db3: None,
+            db2: None,
+            db1: None,
+            db0: None,
+        }
+    }
+
+    /// drives DB7..4 to DB7..4 of `output`, see [`Hd44780::output`]. `None` lets go of them
+    pub fn drive(&self, vm: &mut Pic14, output: Option<u8>) {
+        for (i, &pin) in self.db.iter().enumerate() {
+            match output {
+                Some(x) => vm.set_pin_input(pin, x & 0x80 >> i != 0),
+                None => vm.release_pin_input(pin),
+            }
+        }
+    }
+}
+
+impl Default for LcdWiring {
+    fn default() -> Self {
+        Self::board()
+    }
+}
+
+/// the controller on the pins of one MCU
+#[derive(Debug)]
+pub struct Lcd {
+    lcd: Hd44780,
+    wiring: LcdWiring,
+    mcu: usize,
+    /// what DB7..4 are driven with
+    driven: Option<u8>,
+    /// the time of the last update
+    now: Duration,
+}
+
+impl Lcd {
+    /// `mcu` is the index in the [`CoSim`]
+    pub fn new(lcd: Hd44780, wiring: LcdWiring, mcu: usize) -> Self {
+        Self {
+            lcd,
+            wiring,
+            mcu,
+            driven: None,
+            now: Duration::ZERO,
+        }
+    }
+
+    pub fn lcd(&self) -> &Hd44780 {
+        &self.lcd
+    }
+}
+
+impl Peripheral for Lcd {
+    fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+        self.lcd.advance(now.saturating_sub(self.now));
+        self.now = now;
+        let vm = mcus.mcu_mut(self.mcu);
+        self.lcd.update(self.wiring.pins(vm));
+        let output = self.lcd.output();
+        if output != self.driven {
+            self.driven = output;
+            self.wiring.drive(vm, output);
+        }
+    }
+}
+
+#[test]
+fn firmware_reads_the_address_counter() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::sim::Simulation;
+    use crate::vm::p16f88::P16F88;
+
+    let words: [u16; 12] = [
+        0x1683, // 0x0000: bsf STATUS, RP0
+        0x019B, // 0x0001: clrf ANSEL
+        0x0185, // 0x0002: clrf TRISA
+        0x300F, // 0x0003: movlw 0x0F
+        0x0086, // 0x0004: movwf TRISB
+        0x1283, // 0x0005: bcf STATUS, RP0
+        0x3004, // 0x0006: movlw 0x04
+        0x0085, // 0x0007: movwf PORTA
+        0x1585, // 0x0008: bsf PORTA, 3
+        0x0806, // 0x0009: movf PORTB, w
+        0x1185, // 0x000A: bcf PORTA, 3
+        0x2800, // 0x000B: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    // the address counter at 0x45, set over all 8 data lines
+    let mut lcd = Hd44780::new();
+    for e in [true, false] {
+        lcd.update(Hd44780PinState {
+            rs: Some(false),
+            rw: Some(false),
+            e: Some(e),
+            db7: Some(true),
+            db6: Some(true),
+            db5: Some(false),
+            db4: Some(false),
+            db3: Some(false),
+            db2: Some(true),
+            db1: Some(false),
+            db0: Some(true),
+        });
+    }
+    let wiring = LcdWiring { rw: Some(Pin::ra(2)), ..LcdWiring::board() };
+    let lcd = Rc::new(RefCell::new(Lcd::new(lcd, wiring, 0)));
+
+    let mut sim = Simulation::new();
+    sim.add_mcu(P16F88::builder().flash(&flash).build());
+    sim.add_peripheral(lcd.clone());
+    // through movf PORTB, w
+    sim.advance(Duration::from_nanos(200 * 10), &mut ())
+        .unwrap();
+    let vm = sim.mcus().mcu(0);
+    assert_eq!(vm.pc(), 0x000A);
+    // BF clear and the high half of the address
+    assert_eq!(vm.w, 0x04);
+    assert_eq!(lcd.borrow().lcd().output(), Some(0x45));
+
+    // E low again lets go of the bus
+    sim.advance(Duration::from_nanos(200), &mut ()).unwrap();
+    assert_eq!(lcd.borrow().lcd().output(), None);
+    assert!(!sim.mcus().mcu(0).pin_level(Pin::rb(2)));
+}
@@ -1,3 +1,56 @@
+//! mid-range PIC emulator, centered on the PIC16F88: instruction decoding, the VM, and tools built
+//! on top of it (Intel HEX and ELF, a small assembler, static analysis, profiling, fault injection,
+//! NDJSON event logs, an HD44780, I2C, SPI and 1-Wire devices and a rotary encoder on the pins, LCD
+//! animations, sigrok traces, a simulation clock for several MCUs and peripherals, devices in other
+//! processes, input record and replay, pin stimulus files, reverse execution, symbols and source
+//! lines from the toolchain, a vm on a worker thread for UIs, conditional breakpoints and watch
+//! expressions, recorders exporting NDJSON and VCD). a PIC18 core lives alongside in [`inst18`] and
+//! [`vm::pic18`].
+//!
+//! the modules are the full API and may be reorganized. the items re-exported here and in
+//! [`prelude`] are the stable surface.
+
+pub mod analysis;
+pub mod animation;
+pub mod asm;
+pub mod coff;
+pub mod elf;
+pub mod encoder;
+pub mod events;
+pub mod expr;
+pub mod fault;
 pub mod hex;
+pub mod i2c;
 pub mod inst;
+pub mod inst18;
+pub mod lcd;
+pub mod logic;
+pub mod onewire;
+pub mod prelude;
+pub mod profile;
+pub mod record;
+pub mod remote;
+pub mod replay;
+pub mod rewind;
+pub mod savefile;
+pub mod sim;
+pub mod spi;
+pub mod stimulus;
+pub mod stop;
+pub mod symbols;
+pub mod uart;
 pub mod vm;
+
+#[doc(inline)]
+pub use crate::hex::{decode_intel_hex, encode_intel_hex};
+#[doc(inline)]
+pub use crate::inst::{Instruction, ProgramAddr, RegisterFileAddr};
+#[doc(inline)]
+pub use crate::vm::cancel::CancelToken;
+#[doc(inline)]
+pub use crate::vm::device::Device;
+#[doc(inline)]
+pub use crate::vm::p16f88::{
+    MemoryAccess, Pic14, Pic14Builder, Pin, PortId, Run, RunExit, Snapshot, SnapshotError, Stopped,
+    Ticker, VmError, P16F88,
+};
@@ -0,0 +1,141 @@
+//! pin levels over a run, for a logic analyzer UI. plug [`PinTrace`] in as a [`Ticker`] and
+//! [`PinTrace::write_srzip`] a sigrok session (`.sr`) when the run stops: PulseView opens it with
+//! a channel per pin, ready for its UART, I²C, HD44780 and other protocol decoders.
+
+use std::io::{Seek, Write};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::vm::pic14::{Pic14, Pin, Ticker, CLOCKS_PER_CYCLE};
+
+#[derive(Debug, Clone, Default)]
+pub struct PinTrace {
+    /// every pin of the device, channel `i` is `pins[i]`
+    pins: Vec<Pin>,
+    clock_hz: u64,
+    cycle: u64,
+    /// levels with bit `i` for channel `i`, from the cycle on until the next change
+    changes: Vec<(u64, u32)>,
+}
+
+impl PinTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// instruction cycles traced so far
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    /// the cycles at which the levels changed, with the levels from then on
+    pub fn changes(&self) -> &[(u64, u32)] {
+        &self.changes
+    }
+
+    /// writes the trace as a sigrok session, one sample per instruction cycle
+    pub fn write_srzip(&self, out: impl Write + Seek) -> zip::result::ZipResult<()> {
+        let unit_size = self.pins.len().div_ceil(8).max(1);
+        let sample_bytes = self.cycle * unit_size as u64;
+
+        let mut zip = ZipWriter::new(out);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file("version", options)?;
+        zip.write_all(b"2")?;
+
+        zip.start_file("metadata", options)?;
+        let mut metadata = String::new();
+        metadata += "[global]\nsigrok version=0.5.2\n\n";
+        metadata += "[device 1]\ncapturefile=logic-1\n";
+        metadata += &format!("total probes={}\n", self.pins.len());
+        metadata += &format!("samplerate={}\n", self.clock_hz / CLOCKS_PER_CYCLE);
+        metadata += "total analog=0\n";
+        for (i, pin) in self.pins.iter().enumerate() {
+            metadata += &format!("probe{}={pin:?}\n", i + 1);
+        }
+        metadata += &format!("unitsize={unit_size}\n");
+        zip.write_all(metadata.as_bytes())?;
+
+        let large = sample_bytes > u32::MAX as u64;
+        zip.start_file("logic-1-1", options.large_file(large))?;
+        let mut chunk = vec![];
+        for (i, &(from, levels)) in self.changes.iter().enumerate() {
+            let until = self.changes.get(i + 1).map_or(self.cycle, |x| x.0);
+            let sample = &levels.to_le_bytes()[..unit_size];
+            for _ in from..until {
+                chunk.extend_from_slice(sample);
+                if chunk.len() >= 1 << 16 {
+                    zip.write_all(&chunk)?;
+                    chunk.clear();
+                }
+            }
+        }
+        zip.write_all(&chunk)?;
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+impl Ticker for PinTrace {
+    fn tick(&mut self, vm: &Pic14, cycles: u8) {
+        if self.pins.is_empty() {
+            self.pins = vm.pins();
+            self.clock_hz = vm.clock_hz();
+        }
+        let levels = self
+            .pins
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, &pin)| acc | (vm.pin_level(pin) as u32) << i);
+        // the levels are those after the instruction, they hold from its first cycle
+        if self.changes.last().map_or(true, |x| x.1 != levels) {
+            self.changes.push((self.cycle, levels));
+        }
+        self.cycle += cycles as u64;
+    }
+}
+
+#[test]
+fn writes_a_sigrok_session() {
+    use std::io::{Cursor, Read};
+
+    use crate::vm::p16f88::P16F88;
+
+    let words: [u16; 4] = [
+        0b01_0110_1000_0011, // 0x0000: bsf STATUS, RP0
+        0b01_0000_0000_0110, // 0x0001: bcf TRISB, 0
+        0b01_0010_1000_0011, // 0x0002: bcf STATUS, RP0
+        0b01_0100_0000_0110, // 0x0003: bsf PORTB, 0
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = P16F88::new(flash);
+    let mut trace = PinTrace::new();
+    for _ in 0..words.len() + 1 {
+        vm.step(&mut trace).unwrap();
+    }
+    // RB0 is channel 8
+    assert_eq!(trace.changes(), [(0, 0), (3, 1 << 8)]);
+
+    let mut out = Cursor::new(vec![]);
+    trace.write_srzip(&mut out).unwrap();
+    let mut zip = zip::ZipArchive::new(out).unwrap();
+    let mut metadata = String::new();
+    zip.by_name("metadata")
+        .unwrap()
+        .read_to_string(&mut metadata)
+        .unwrap();
+    assert!(metadata.contains("samplerate=5000000\n"));
+    assert!(metadata.contains("probe9=RB0\n"));
+    assert!(metadata.contains("unitsize=2\n"));
+    let mut samples = vec![];
+    zip.by_name("logic-1-1")
+        .unwrap()
+        .read_to_end(&mut samples)
+        .unwrap();
+    assert_eq!(samples, [0, 0, 0, 0, 0, 0, 0, 1, 0, 1]);
+}
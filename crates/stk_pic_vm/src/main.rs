@@ -1,172 +1,834 @@
-use std::fmt::Debug;
+use std::cell::Cell;
+use std::fmt::{Debug, Display};
 use std::fs::File;
-use std::io::BufReader;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::io::{self, BufReader};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use clap::Parser;
-use stk_hd44780_vm::{Hd44780, Hd44780PinState, PinObserver};
-use stk_pic_vm::hex::decode_intel_hex;
-use stk_pic_vm::vm::p16f88::reg::Registers;
-use stk_pic_vm::vm::p16f88::{Ticker, P16F88};
+use clap::{Parser, Subcommand, ValueEnum};
+use stk_diag::{Counted, Dedup, Diagnostic, DiagnosticSink, JsonSink, PrintSink};
+use stk_hd44780_vm::{Geometry, Hd44780, PinObserver};
+use stk_pic_vm::animation::LcdRecording;
+use stk_pic_vm::coff::SourceLines;
+use stk_pic_vm::elf::load_elf;
+use stk_pic_vm::events::{EventKind, EventLog};
+use stk_pic_vm::hex::{IntelHexDecoder, MemoryImage};
+use stk_pic_vm::inst::ProgramAddr;
+use stk_pic_vm::lcd::LcdWiring;
+use stk_pic_vm::logic::PinTrace;
+use stk_pic_vm::profile::{Profiler, RunStats, StackMonitor, StubMonitor, Trace};
+use stk_pic_vm::record::{Hd44780Edge, Record, Recorder};
+use stk_pic_vm::replay::Replayer;
+use stk_pic_vm::stimulus::Stimulus;
+use stk_pic_vm::stop::{parse_duration, StopCondition, StopConditions};
+use stk_pic_vm::symbols::SymbolTable;
+use stk_pic_vm::uart::{Host, UartBridge};
+use stk_pic_vm::vm::cancel::CancelToken;
+use stk_pic_vm::vm::device::{self, Device};
+use stk_pic_vm::vm::p16f88::{
+    MemoryAccess, Pin, Run, RunExit, Snapshot, Stopped, Ticker, FLASH_BYTES, P16F88,
+};
+
+use crate::dap::DapArgs;
+use crate::debug::DebugArgs;
+use crate::hex_cmd::HexCommand;
+use crate::script::ScriptArgs;
+use crate::trace_cmd::TraceCommand;
+
+mod dap;
+mod debug;
+mod hex_cmd;
+mod script;
+mod trace_cmd;
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// firmware to run, as Intel HEX or ELF (`.elf`)
+    #[arg(required = true)]
+    file: Option<PathBuf>,
+
+    /// skip lines of the hex that don't decode or fail the checksum, with a warning
+    #[arg(long)]
+    lenient_hex: bool,
+
+    /// stop when the pc reaches this address (e.g. `0x01a3`). can be given multiple times
+    #[arg(long = "break", value_parser = parse_addr)]
+    breakpoints: Vec<ProgramAddr>,
+
+    /// stop after this many instruction cycles
+    #[arg(long, value_name = "N")]
+    stop_cycles: Option<u64>,
+
+    /// stop after this much simulated time, e.g. `1.5s` or `10ms`
+    #[arg(long, value_name = "TIME", value_parser = parse_time)]
+    stop_time: Option<Duration>,
+
+    /// stop when the pc reaches this address or code symbol. can be given multiple times
+    #[arg(long, value_name = "ADDR")]
+    stop_at: Vec<String>,
+
+    /// stop when a register holds a value, e.g. `counter=5` or `0x20=0x05`. the register is an
+    /// address, a variable or an SFR name. can be given multiple times
+    #[arg(long, value_name = "REG=VALUE")]
+    stop_when: Vec<String>,
+
+    /// stop when the firmware executes SLEEP
+    #[arg(long)]
+    stop_on_sleep: bool,
+
+    /// pic-as `.map` file of the firmware, to print names instead of addresses. replaces the
+    /// symbols of an ELF
+    #[arg(long, value_name = "PATH")]
+    map: Option<PathBuf>,
+
+    /// COFF (`.cof`) file of the firmware, to show source lines in the trace
+    #[arg(long, value_name = "PATH")]
+    cof: Option<PathBuf>,
+
+    /// drive pins, A/D channels and software UART lines from this file while running, see
+    /// `stk_pic_vm::stimulus`
+    #[arg(long, value_name = "PATH")]
+    stimulus: Option<PathBuf>,
+
+    /// resume from a snapshot written by `--save-snapshot` instead of starting from reset
+    #[arg(long)]
+    load_snapshot: Option<PathBuf>,
+
+    /// write the machine state to this file (JSON) when the run stops
+    #[arg(long)]
+    save_snapshot: Option<PathBuf>,
+
+    /// keep the data EEPROM in this file, so what the firmware writes there is still there on
+    /// the next run. the EEPROM data of the firmware is only used until the file exists
+    #[arg(long, value_name = "PATH")]
+    eeprom: Option<PathBuf>,
+
+    /// print where the cycles went when the run stops
+    #[arg(long)]
+    profile: bool,
+
+    /// warn the first time the call stack gets deeper than this
+    #[arg(long, value_name = "DEPTH")]
+    stack_warn: Option<usize>,
+
+    /// warn about accesses to registers whose hardware isn't emulated, once per register with a
+    /// count of the repeats at the end
+    #[arg(long)]
+    warn_stubs: bool,
+
+    /// keep the last N executed instructions and print them if the run stops on a breakpoint or
+    /// an error, or the emulator panics
+    #[arg(long, value_name = "N")]
+    trace: Option<usize>,
+
+    /// print a summary of the run (cycles, coverage, diagnostics, ...) when it stops
+    #[arg(long)]
+    summary: bool,
+
+    /// also write the summary to this file, as Markdown if it ends in `.md` and JSON otherwise
+    #[arg(long, value_name = "PATH")]
+    summary_out: Option<PathBuf>,
+
+    /// print pin changes, LCD writes, UART bytes and where the run stopped to stdout, one JSON
+    /// object per line, instead of the LCD records. logs go to stderr
+    #[arg(long, conflicts_with_all = ["profile", "summary"])]
+    ndjson: bool,
+
+    /// with `--ndjson`, also print every register write. for comparing runs with `trace diff`
+    #[arg(long, requires = "ndjson")]
+    ndjson_writes: bool,
+
+    /// write what the LCD shows over the run to this file, as an animated GIF, or APNG if it ends
+    /// in `.png`
+    #[arg(long, value_name = "PATH")]
+    lcd_animation: Option<PathBuf>,
+
+    /// make LCD instructions take as long as on the real controller, losing the ones the firmware
+    /// writes too soon after the last
+    #[arg(long)]
+    lcd_timing: bool,
+
+    /// the characters of the LCD, as COLUMNSxLINES: 16x2, 20x4, 40x2, ...
+    #[arg(long, value_name = "SIZE", default_value = "16x2")]
+    lcd_size: Geometry,
+
+    /// the pin RW of the LCD is on, letting the firmware read the busy flag and the display back.
+    /// tied low when left out
+    #[arg(long, value_name = "PIN")]
+    lcd_rw: Option<Pin>,
+
+    /// write the levels of all pins over the run to this file as a sigrok session (`.sr`), for
+    /// PulseView and its protocol decoders
+    #[arg(long, value_name = "PATH")]
+    sigrok: Option<PathBuf>,
+
+    /// connect the AUSART to the host: `stdio`, `pty` (its path goes to stderr) or `tcp:ADDR` to
+    /// listen on, e.g. `tcp:127.0.0.1:4000`
+    #[arg(long, value_name = "HOST")]
+    uart: Option<Host>,
+
+    /// run again whenever the firmware, the stimulus, the map or the COFF file changes. a run
+    /// still going is cut short
+    #[arg(long)]
+    watch: bool,
+
+    /// how to print diagnostics (to stderr)
+    #[arg(long, value_enum, default_value_t = DiagnosticFormat::Text)]
+    diagnostics: DiagnosticFormat,
 }
 
-fn main() {
-    tracing_subscriber::fmt()
-        .with_ansi(std::env::var("NO_COLOR").is_err())
-        .init();
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum DiagnosticFormat {
+    Text,
+    /// one JSON object per line
+    Json,
+}
 
-    let args = Args::parse();
+fn parse_number(s: &str) -> Result<u16, String> {
+    let r = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    r.map_err(|e| format!("{s}: {e}"))
+}
+
+fn parse_addr(s: &str) -> Result<ProgramAddr, String> {
+    parse_number(s).map(ProgramAddr)
+}
 
-    let mut flash = decode_intel_hex(BufReader::new(File::open(args.file).unwrap())).unwrap();
+fn parse_time(s: &str) -> Result<Duration, String> {
+    parse_duration(s).ok_or_else(|| format!("invalid time `{s}`"))
+}
 
-    if flash.len() > 7168 {
-        tracing::warn!(
-            "program is too large; expected: {}, actual: {}",
-            7168,
-            flash.len()
-        );
+/// the `--stop-*` options, with names looked up in `symbols` and the SFRs of `vm`
+fn stop_conditions(
+    args: &Args,
+    symbols: &SymbolTable,
+    vm: &P16F88,
+) -> Result<StopConditions, String> {
+    // running off the end of the program into erased flash
+    let mut stop = StopConditions(vec![StopCondition::PcPast(ProgramAddr(3500))]);
+    if let Some(n) = args.stop_cycles {
+        stop.push(StopCondition::Cycles(n));
+    }
+    if let Some(t) = args.stop_time {
+        stop.push(StopCondition::Time(t));
+    }
+    for at in &args.stop_at {
+        let addr = parse_addr(at)
+            .ok()
+            .or_else(|| symbols.code_addr(at))
+            .ok_or_else(|| format!("no address or code symbol `{at}`"))?;
+        stop.push(StopCondition::Pc(addr));
+    }
+    for when in &args.stop_when {
+        let (reg, value) = when
+            .split_once('=')
+            .ok_or_else(|| format!("expected `REG=VALUE`, found `{when}`"))?;
+        let (reg, value) = (reg.trim(), value.trim());
+        let addr = register_addr(reg, symbols, vm.device())
+            .ok_or_else(|| format!("no address, variable or SFR `{reg}`"))?;
+        let value = parse_number(value)?
+            .try_into()
+            .map_err(|_| format!("{value} doesn't fit in a register"))?;
+        stop.push(StopCondition::Register { addr, value });
+    }
+    if args.stop_on_sleep {
+        stop.push(StopCondition::Sleep);
     }
-    flash.resize(7168, 0);
+    Ok(stop)
+}
 
-    const CLOCKS_PER_SEC: u128 = 20_000_000;
-    const CLOCKS_PER_CYCLE: u128 = 4;
+/// linear register file address of `expr`: an address (`0x20`, `0xA0` for bank 1), a variable
+/// or an SFR name
+fn register_addr(expr: &str, symbols: &SymbolTable, device: &Device) -> Option<u16> {
+    parse_number(expr)
+        .ok()
+        .or_else(|| symbols.data_addr(expr).map(|x| x.0 as u16))
+        .or_else(|| device.sfr_addr(expr))
+}
 
-    trait RecordPredicate {
-        type Record: Debug;
-        fn record(&mut self, vm: &P16F88) -> Option<Self::Record>;
-    }
-    struct HD44780Record {
-        e: bool,
-        rs: bool,
-        db: u8,
-        callstack: Vec<u16>,
-    }
-    impl Debug for HD44780Record {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            write!(
-                f,
-                "HD44780Record(e: {}, rs: {}, db: {:#010b}, cs: {})",
-                self.e,
-                self.rs,
-                self.db,
-                self.callstack
-                    .iter()
-                    .map(|x| format!("{x:#06x}"))
-                    .collect::<Vec<_>>()
-                    .join(", ")
-            )
-        }
-    }
-    struct HD44780DebugPredicate {
-        before_e: bool,
-    }
-    impl HD44780DebugPredicate {
-        fn new() -> Self {
-            Self { before_e: false }
-        }
-        fn e(reg: &Registers) -> bool {
-            (reg.special.porta().0 & 0b0000_1000) != 0
-        }
-        fn rs(reg: &Registers) -> bool {
-            (reg.special.porta().0 & 0b0001_0000) != 0
-        }
-        fn db(reg: &Registers) -> u8 {
-            reg.special.portb().0 << 4
-        }
-    }
-    impl RecordPredicate for HD44780DebugPredicate {
-        type Record = HD44780Record;
-        fn record(&mut self, vm: &P16F88) -> Option<HD44780Record> {
-            let reg = &vm.register;
-            let rec = match (self.before_e, Self::e(reg)) {
-                // 立ち下がりエッジ
-                (true, false) => Some(HD44780Record {
-                    e: Self::e(reg),
-                    rs: Self::rs(reg),
-                    db: Self::db(reg),
-                    callstack: vm.call_stack.to_vec(),
-                }),
-                // 立ち上がりエッジ
-                (false, true) => None,
-                // 状態変化なし
-                (true, true) | (false, false) => None,
-            };
-            self.before_e = Self::e(reg);
-            if let Some(rec) = rec.as_ref() {
-                tracing::info!("{rec:?}");
+/// `file` as Intel HEX, or ELF if it ends in `.elf`, with the symbols of `map` if given and of
+/// the ELF otherwise
+fn load_firmware(file: &Path, map: Option<&Path>) -> Result<(MemoryImage, SymbolTable), String> {
+    fn context<T, E: Display>(path: &Path, r: Result<T, E>) -> Result<T, String> {
+        r.map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    let (memory, symbols) = if file.extension().is_some_and(|x| x == "elf") {
+        let image = context(file, load_elf(&context(file, std::fs::read(file))?))?;
+        (image.memory, image.symbols)
+    } else {
+        let f = context(file, File::open(file))?;
+        let memory = context(file, IntelHexDecoder::new(BufReader::new(f)).decode())?;
+        (memory, SymbolTable::new())
+    };
+    let symbols = match map {
+        Some(path) => {
+            let f = context(path, File::open(path))?;
+            context(path, SymbolTable::from_map(BufReader::new(f)))?
+        }
+        None => symbols,
+    };
+    Ok((memory, symbols))
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// edit and compare hex files
+    #[command(subcommand)]
+    Hex(HexCommand),
+
+    /// step through the firmware in a terminal UI
+    Debug(DebugArgs),
+
+    /// serve the debugger over the Debug Adapter Protocol, for VS Code and other editors
+    Dap(DapArgs),
+
+    /// run the firmware under a rhai script that checks what it does. exits with 1 if a check
+    /// fails
+    Script(ScriptArgs),
+
+    /// work with the event logs of `--ndjson`
+    #[command(subcommand)]
+    Trace(TraceCommand),
+}
+
+fn main() -> ExitCode {
+    let mut args = Args::parse();
+
+    // the debugger has the terminal to itself
+    if matches!(args.command, Some(Command::Debug(_))) {
+        tracing_subscriber::fmt().with_writer(io::sink).init();
+    } else if args.ndjson || matches!(args.command, Some(Command::Script(_) | Command::Dap(_))) {
+        tracing_subscriber::fmt()
+            .with_ansi(std::env::var("NO_COLOR").is_err())
+            .with_writer(io::stderr)
+            .init();
+    } else {
+        tracing_subscriber::fmt()
+            .with_ansi(std::env::var("NO_COLOR").is_err())
+            .init();
+    }
+
+    match args.command.take() {
+        Some(Command::Hex(cmd)) => match hex_cmd::run(cmd) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(2)
             }
-            rec
+        },
+        Some(Command::Debug(args)) => match debug::run(args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(2)
+            }
+        },
+        Some(Command::Dap(args)) => match dap::run(args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(2)
+            }
+        },
+        Some(Command::Trace(cmd)) => match trace_cmd::run(cmd) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(2)
+            }
+        },
+        Some(Command::Script(args)) => match script::run(args) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::from(2)
+            }
+        },
+        None if args.watch => watch(&args),
+        None => {
+            run(&args, diag_sink(&args), None);
+            ExitCode::SUCCESS
         }
     }
+}
+
+fn diag_sink(args: &Args) -> Box<dyn DiagnosticSink> {
+    match args.diagnostics {
+        DiagnosticFormat::Text => Box::new(PrintSink(io::stderr())),
+        DiagnosticFormat::Json => Box::new(JsonSink(io::stderr())),
+    }
+}
 
-    #[derive(Debug)]
-    struct TickerRecord<R> {
-        clock: u128,
-        pc: u16,
-        record: R,
+/// modification times of the files a run reads, `None` for the ones that can't be read
+fn modified(files: &[&PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|x| std::fs::metadata(x).and_then(|x| x.modified()).ok())
+        .collect()
+}
+
+/// `--watch`: runs, and runs again from the start each time an input changes, until killed
+fn watch(args: &Args) -> ExitCode {
+    const POLL: Duration = Duration::from_millis(200);
+
+    let files = [&args.file, &args.stimulus, &args.map, &args.cof]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    loop {
+        let seen = modified(&files);
+        let cancel = CancelToken::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                while modified(&files) == seen {
+                    thread::sleep(POLL);
+                }
+                cancel.cancel();
+            });
+            run(args, diag_sink(args), Some(&cancel));
+            if !cancel.is_cancelled() {
+                eprintln!("waiting for changes");
+            }
+        });
+        // let the toolchain finish writing
+        thread::sleep(POLL);
+        eprintln!("\n--- input changed, running again ---\n");
     }
-    #[derive(Default, Debug)]
-    struct LocalTickerInner<R: RecordPredicate> {
+}
+
+/// runs the firmware until it stops, or until `cancel` is cancelled
+fn run(args: &Args, diag: impl DiagnosticSink, cancel: Option<&CancelToken>) {
+    let mut diag = Counted::new(diag);
+    let file = args.file.as_ref().unwrap();
+    let loaded = if file.extension().is_some_and(|x| x == "elf") {
+        std::fs::read(file)
+            .map_err(|e| e.to_string())
+            .and_then(|data| load_elf(&data).map_err(|e| e.to_string()))
+            .map(|image| (image.memory, image.symbols))
+    } else {
+        File::open(file)
+            .map_err(|e| e.to_string())
+            .and_then(|f| {
+                IntelHexDecoder::new(BufReader::new(f))
+                    .lenient(args.lenient_hex)
+                    .decode()
+                    .map_err(|e| e.to_string())
+            })
+            .map(|memory| (memory, SymbolTable::new()))
+    };
+    let (memory, elf_symbols) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            diag.emit(Diagnostic::error(
+                "loader",
+                format!("{}: {e}", file.display()),
+            ));
+            return;
+        }
+    };
+    let program_words = memory.program_len().min(FLASH_BYTES) / 2;
+
+    if memory.program_len() > FLASH_BYTES {
+        diag.emit(Diagnostic::warning(
+            "loader",
+            format!(
+                "program is too large; expected: {}, actual: {}",
+                FLASH_BYTES,
+                memory.program_len()
+            ),
+        ));
+    }
+    let flash = memory.program_flash(FLASH_BYTES);
+
+    let symbols = match &args.map {
+        Some(path) => {
+            match File::open(path).and_then(|f| SymbolTable::from_map(BufReader::new(f))) {
+                Ok(symbols) => symbols,
+                Err(e) => {
+                    diag.emit(Diagnostic::error(
+                        "loader",
+                        format!("{}: {e}", path.display()),
+                    ));
+                    return;
+                }
+            }
+        }
+        None => elf_symbols,
+    };
+    let lines = match &args.cof {
+        Some(path) => {
+            let lines = std::fs::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| SourceLines::from_coff(&data).map_err(|e| e.to_string()));
+            match lines {
+                Ok(lines) => lines,
+                Err(e) => {
+                    diag.emit(Diagnostic::error(
+                        "loader",
+                        format!("{}: {e}", path.display()),
+                    ));
+                    return;
+                }
+            }
+        }
+        None => SourceLines::new(),
+    };
+
+    const CLOCKS_PER_SEC: u128 = 20_000_000;
+    const CLOCKS_PER_CYCLE: u128 = 4;
+
+    #[derive(Debug)]
+    struct LocalTickerInner {
         clock: u128,
-        records: Vec<TickerRecord<R::Record>>,
-        pred: R,
+        recorder: Recorder,
         lcd: Hd44780,
+        lcd_wiring: LcdWiring,
+        /// what the LCD drives DB7..4 with, and whether that changed since the vm last saw it
+        lcd_driven: Option<u8>,
+        lcd_drive: Rc<Cell<bool>>,
+        profiler: Option<Profiler>,
+        stack: StackMonitor,
+        stubs: Option<StubMonitor<Dedup<Vec<Diagnostic>>>>,
+        trace: Option<Trace>,
+        stats: Option<RunStats>,
+        events: Option<EventLog<io::Stdout>>,
+        animation: Option<LcdRecording>,
+        pins: Option<PinTrace>,
+        uart: Option<UartBridge>,
+        uart_service: Rc<Cell<bool>>,
     }
-    impl<R: RecordPredicate> Ticker for LocalTickerInner<R> {
+    impl Ticker for LocalTickerInner {
         fn tick(&mut self, vm: &P16F88, cycles: u8) {
             self.clock += CLOCKS_PER_CYCLE * cycles as u128;
-            if let Some(record) = self.pred.record(vm) {
-                let record = TickerRecord { clock: self.clock, pc: vm.pc(), record };
-                self.records.push(record);
-            }
-            let reg = &vm.register;
-            let db = HD44780DebugPredicate::db(reg);
-            self.lcd.update(Hd44780PinState {
-                rs: Some(HD44780DebugPredicate::rs(reg)),
-                rw: Some(false), // TODO: 確認
-                e: Some(HD44780DebugPredicate::e(reg)),
-                db7: Some((db & (1 << 7)) != 0),
-                db6: Some((db & (1 << 6)) != 0),
-                db5: Some((db & (1 << 5)) != 0),
-                db4: Some((db & (1 << 4)) != 0),
-                db3: None,
-                db2: None,
-                db1: None,
-                db0: None,
-            })
+            if let Some(events) = &mut self.events {
+                events.tick(vm, cycles);
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.tick(vm, cycles);
+            }
+            self.stack.tick(vm, cycles);
+            if let Some(stubs) = &mut self.stubs {
+                stubs.tick(vm, cycles);
+            }
+            if let Some(trace) = &mut self.trace {
+                trace.tick(vm, cycles);
+            }
+            if let Some(stats) = &mut self.stats {
+                stats.tick(vm, cycles);
+            }
+            if let Some(pins) = &mut self.pins {
+                pins.tick(vm, cycles);
+            }
+            let recorded = self.recorder.recorded();
+            self.recorder.tick(vm, cycles);
+            let new = (self.recorder.recorded() - recorded) as usize;
+            for record in self.recorder.records().rev().take(new).rev() {
+                tracing::info!("{}", record.event.kind);
+                if let Some(events) = &mut self.events {
+                    events.emit(vm, record.event.kind.clone());
+                }
+            }
+            let elapsed = CLOCKS_PER_CYCLE * cycles as u128 * 1_000_000_000 / CLOCKS_PER_SEC;
+            self.lcd.advance(Duration::from_nanos(elapsed as u64));
+            self.lcd.update(self.lcd_wiring.pins(vm));
+            if self.lcd.output() != self.lcd_driven {
+                self.lcd_driven = self.lcd.output();
+                self.lcd_drive.set(true);
+            }
+            if let Some(animation) = &mut self.animation {
+                let at = Duration::from_nanos((self.clock * 1_000_000_000 / CLOCKS_PER_SEC) as u64);
+                animation.record(at, &self.lcd);
+            }
+            if let Some(uart) = &mut self.uart {
+                uart.tick(vm, cycles);
+                if uart.needs_service() {
+                    self.uart_service.set(true);
+                }
+            }
+        }
+
+        fn on_read(&mut self, access: MemoryAccess) {
+            if let Some(stubs) = &mut self.stubs {
+                stubs.on_read(access);
+            }
+        }
+
+        fn on_write(&mut self, access: MemoryAccess) {
+            if let Some(stubs) = &mut self.stubs {
+                stubs.on_write(access);
+            }
+            if let Some(events) = &mut self.events {
+                events.on_write(access);
+            }
+            if let Some(uart) = &mut self.uart {
+                uart.on_write(access);
+            }
+        }
+    }
+
+    if args.ndjson && args.uart == Some(Host::Stdio) {
+        diag.emit(Diagnostic::error(
+            "args",
+            "--uart stdio can't share stdout with --ndjson",
+        ));
+        return;
+    }
+    let uart = match args.uart.as_ref().map(UartBridge::open).transpose() {
+        Ok(uart) => uart,
+        Err(e) => {
+            diag.emit(Diagnostic::error(
+                "uart",
+                format!("{}: {e}", args.uart.as_ref().unwrap()),
+            ));
+            return;
         }
+    };
+    if let Some(path) = uart.as_ref().and_then(UartBridge::path) {
+        eprintln!("uart: {}", path.display());
     }
 
+    let lcd_drive = Rc::new(Cell::new(false));
+    let uart_service = Rc::new(Cell::new(false));
     let mut ticker = LocalTickerInner {
         clock: 0,
-        records: vec![],
-        pred: HD44780DebugPredicate::new(),
-        lcd: Hd44780::new(),
+        recorder: Recorder::new().with(Hd44780Edge::new()),
+        lcd: Hd44780::new()
+            .timing(args.lcd_timing)
+            .geometry(args.lcd_size),
+        lcd_wiring: LcdWiring { rw: args.lcd_rw, ..LcdWiring::board() },
+        lcd_driven: None,
+        lcd_drive: lcd_drive.clone(),
+        profiler: args.profile.then(Profiler::new),
+        stack: StackMonitor::new(args.stack_warn),
+        stubs: args
+            .warn_stubs
+            .then(|| StubMonitor::new(Dedup::new(vec![]))),
+        trace: args.trace.map(|n| {
+            Trace::new(n)
+                .with_symbols(symbols.clone())
+                .with_source_lines(lines.clone())
+        }),
+        stats: (args.summary || args.summary_out.is_some()).then(RunStats::new),
+        events: args
+            .ndjson
+            .then(|| EventLog::new(io::stdout()).log_writes(args.ndjson_writes)),
+        animation: args.lcd_animation.is_some().then(LcdRecording::new),
+        pins: args.sigrok.is_some().then(PinTrace::new),
+        uart,
+        uart_service: uart_service.clone(),
     };
 
-    let mut vm = P16F88::new(flash.try_into().unwrap());
-    loop {
-        vm.step(&mut ticker);
-        if vm.pc() * 2 > 7000 {
-            break;
+    let mut builder = P16F88::builder()
+        .flash(&flash)
+        .eeprom(&memory.eeprom(device::P16F88.eeprom_bytes));
+    if let Some(path) = &args.eeprom {
+        builder = builder.eeprom_file(path);
+    }
+    let mut vm = builder.build();
+    if let Some(path) = &args.load_snapshot {
+        let restored = Snapshot::load(BufReader::new(File::open(path).unwrap()))
+            .map_err(|e| e.to_string())
+            .and_then(|snapshot| vm.restore(snapshot).map_err(|e| e.to_string()));
+        if let Err(e) = restored {
+            diag.emit(Diagnostic::error(
+                "loader",
+                format!("{}: {e}", path.display()),
+            ));
+            return;
+        }
+    }
+    for &b in &args.breakpoints {
+        vm.add_breakpoint(b);
+    }
+    let mut replayer = None;
+    if let Some(path) = &args.stimulus {
+        let stimulus = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| Stimulus::parse(&source).map_err(|e| e.to_string()));
+        match stimulus {
+            Ok(stimulus) => replayer = Some(Replayer::new(stimulus.input_log(vm.clock_hz()))),
+            Err(e) => {
+                diag.emit(Diagnostic::error(
+                    "loader",
+                    format!("{}: {e}", path.display()),
+                ));
+                return;
+            }
+        }
+    }
+    let stop = match stop_conditions(args, &symbols, &vm) {
+        Ok(stop) => stop,
+        Err(e) => {
+            diag.emit(Diagnostic::error("args", e));
+            return;
+        }
+    };
+    if let Some(uart) = &mut ticker.uart {
+        uart.service(&mut vm);
+    }
+    let started = Instant::now();
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut budget = stop.budget(&vm).unwrap_or(u64::MAX);
+        let end =
+            |vm: &P16F88| stop.holds(vm).is_some() || cancel.is_some_and(CancelToken::is_cancelled);
+        let mut cycles = 0;
+        loop {
+            let until = |vm: &P16F88| end(vm) || lcd_drive.get() || uart_service.get();
+            let run = match &mut replayer {
+                Some(replayer) => replayer.run_until_within(&mut vm, budget, until, &mut ticker),
+                None => vm.run_until_within(budget, until, &mut ticker),
+            };
+            cycles += run.cycles;
+            budget = budget.saturating_sub(run.cycles);
+            // tickers only see the vm, so the LCD's bus is driven and the UART serviced between
+            // runs
+            let drive = lcd_drive.replace(false);
+            if drive {
+                ticker.lcd_wiring.drive(&mut vm, ticker.lcd_driven);
+            }
+            let serve = uart_service.replace(false);
+            if let (true, Some(uart)) = (serve, &mut ticker.uart) {
+                uart.service(&mut vm);
+            }
+            if (drive || serve) && run.exit == RunExit::Condition && !end(&vm) {
+                continue;
+            }
+            break Run { cycles, exit: run.exit };
+        }
+    }));
+    let wall_time = started.elapsed();
+    let run = match run {
+        Ok(run) => run,
+        Err(payload) => {
+            if let Some(trace) = &ticker.trace {
+                eprint!("last instructions before the panic:\n{trace}");
+            }
+            panic::resume_unwind(payload);
+        }
+    };
+    if let (RunExit::Stopped(_) | RunExit::Error(_), Some(trace)) = (&run.exit, &ticker.trace) {
+        eprint!("last instructions:\n{trace}");
+    }
+    let fired = stop.fired(&vm, &run);
+    if let Some(events) = &mut ticker.events {
+        if let Some(condition) = fired {
+            let condition = condition.to_string();
+            events.emit(&vm, EventKind::Stop { condition });
+        }
+        match &run.exit {
+            RunExit::Stopped(Stopped::Breakpoint(addr)) => {
+                events.emit(&vm, EventKind::Breakpoint { addr: addr.0 })
+            }
+            RunExit::Error(e) => events.emit(&vm, EventKind::Error { message: e.to_string() }),
+            _ => {}
+        }
+    }
+    let stopped = match run.exit {
+        RunExit::Stopped(stopped) => Some(Diagnostic::info("vm", format!("{stopped:?}"))),
+        RunExit::Error(e) => Some(Diagnostic::error("vm", e.to_string())),
+        _ => fired.map(|x| Diagnostic::info("vm", format!("stopped: {x}"))),
+    };
+    if let Some(d) = stopped {
+        let cycle = (ticker.clock / CLOCKS_PER_CYCLE) as u64;
+        diag.emit(d.with_cycle(cycle).with_pc(vm.pc()));
+    }
+
+    for d in ticker.stack.diagnostics() {
+        diag.emit(d);
+    }
+    if let Some(stubs) = ticker.stubs.take() {
+        for d in stubs.into_sink().into_inner() {
+            diag.emit(d);
+        }
+    }
+    if let Some(profiler) = &ticker.profiler {
+        print!("{}", profiler.report(10));
+    }
+
+    if let Some(path) = &args.save_snapshot {
+        vm.snapshot().save(File::create(path).unwrap()).unwrap();
+    }
+    if let (Some(path), Some(animation)) = (&args.lcd_animation, &ticker.animation) {
+        let end = Duration::from_nanos((ticker.clock * 1_000_000_000 / CLOCKS_PER_SEC) as u64);
+        let apng = path.extension().is_some_and(|x| x == "png");
+        let written = File::create(path).map_err(|e| e.to_string()).and_then(|f| {
+            let out = io::BufWriter::new(f);
+            let written = if apng {
+                animation.write_apng(end, out)
+            } else {
+                animation.write_gif(end, out)
+            };
+            written.map_err(|e| e.to_string())
+        });
+        if let Err(e) = written {
+            diag.emit(Diagnostic::error("lcd", format!("{}: {e}", path.display())));
+        }
+    }
+
+    if let (Some(path), Some(pins)) = (&args.sigrok, &ticker.pins) {
+        let written = File::create(path)
+            .map_err(|e| e.to_string())
+            .and_then(|f| pins.write_srzip(f).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            diag.emit(Diagnostic::error(
+                "sigrok",
+                format!("{}: {e}", path.display()),
+            ));
         }
     }
 
     let mut before = None;
-    for TickerRecord { clock, pc, record } in &ticker.records {
-        let duration = Duration::from_secs_f64(*clock as f64 / CLOCKS_PER_SEC as f64);
+    let records = if args.ndjson {
+        vec![]
+    } else {
+        ticker.recorder.take()
+    };
+    for Record { event, call_stack } in &records {
+        let (clock, pc) = (event.cycle as u128 * CLOCKS_PER_CYCLE, event.pc);
+        let duration = Duration::from_secs_f64(clock as f64 / CLOCKS_PER_SEC as f64);
         print!("{duration:04.02?} clk: {clock}, pc: {pc:#x}");
         if let Some(before) = before {
             let d = clock - before;
             let dh = Duration::from_secs_f64(d as f64 / CLOCKS_PER_SEC as f64);
             print!(" (diff: {dh:04.02?}({d}))");
         }
-        println!(": {record:?}");
+        let call_stack = call_stack
+            .iter()
+            .map(|&x| symbols.describe(ProgramAddr(x)))
+            .collect::<Vec<_>>();
+        println!(": {}, cs: {}", event.kind, call_stack.join(", "));
         before = Some(clock);
     }
+    if let Some(stats) = &ticker.stats {
+        let mut summary = stats.summary(program_words);
+        summary.wall_time_ms = wall_time.as_secs_f64() * 1000.0;
+        summary.max_stack_depth = ticker.stack.max_depth();
+        summary
+            .events
+            .insert("hd44780 writes".to_owned(), ticker.recorder.recorded());
+        summary.diagnostics = diag.counts().clone();
+
+        if args.summary {
+            print!("{summary}");
+        }
+        if let Some(path) = &args.summary_out {
+            let out = if path.extension().is_some_and(|x| x == "md") {
+                summary.markdown()
+            } else {
+                serde_json::to_string_pretty(&summary).unwrap()
+            };
+            std::fs::write(path, out).unwrap();
+        }
+    }
 }
@@ -0,0 +1,49 @@
+//! a 1-Wire line on a pin of an MCU, with DS18B20s on the [`OneWire`]. the firmware bit-bangs it
+//! as open drain: TRIS set lets the line go high through its pull-up, TRIS cleared with the latch
+//! at 0 pulls it low. the slots are timed in µs, so the pin is watched after every instruction.
+
+use std::time::Duration;
+
+use stk_ds18b20_vm::OneWire;
+
+use crate::sim::Peripheral;
+use crate::vm::cosim::CoSim;
+use crate::vm::pic14::Pin;
+
+/// the line on one pin of one MCU, the MCU as the master
+#[derive(Debug)]
+pub struct OneWirePin {
+    bus: OneWire,
+    pin: Pin,
+    mcu: usize,
+    /// the time of the last update
+    now: Duration,
+}
+
+impl OneWirePin {
+    /// the line on `pin` of MCU `mcu`, the index in the [`CoSim`]
+    pub fn new(bus: OneWire, pin: Pin, mcu: usize) -> Self {
+        Self { bus, pin, mcu, now: Duration::ZERO }
+    }
+
+    pub fn bus(&self) -> &OneWire {
+        &self.bus
+    }
+
+    /// for setting the temperatures while the simulation runs
+    pub fn bus_mut(&mut self) -> &mut OneWire {
+        &mut self.bus
+    }
+}
+
+impl Peripheral for OneWirePin {
+    fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+        self.bus
+            .simulate(now.saturating_sub(self.now).as_nanos() as u64);
+        self.now = now;
+        let vm = mcus.mcu_mut(self.mcu);
+        // high unless something pulls the line low
+        self.bus.input(vm.pin_output(self.pin) != Some(false));
+        vm.set_pin_input(self.pin, self.bus.line());
+    }
+}
@@ -0,0 +1,8 @@
+//! `use stk_pic_vm::prelude::*;` to get what driving the VM usually needs
+
+pub use crate::inst::{Instruction, ProgramAddr};
+pub use crate::profile::{Profiler, StackMonitor};
+pub use crate::sim::{Peripheral, Simulation};
+pub use crate::vm::cancel::CancelToken;
+pub use crate::vm::device::{self, Device};
+pub use crate::vm::p16f88::{Pic14, Pin, PortId, Run, RunExit, Stopped, Ticker, VmError, P16F88};
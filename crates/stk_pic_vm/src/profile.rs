@@ -0,0 +1,751 @@
+//! execution profiling. plug [`Profiler`], [`StackMonitor`], [`StubMonitor`], [`Trace`] or
+//! [`RunStats`] in as a [`Ticker`] and read the results after the run.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Display;
+
+use serde::Serialize;
+use stk_diag::{Diagnostic, DiagnosticSink, Severity};
+
+use crate::coff::SourceLines;
+use crate::inst::{Instruction, ProgramAddr};
+use crate::symbols::SymbolTable;
+use crate::vm::device::Slot;
+use crate::vm::p16f88::{MemoryAccess, Ticker, P16F88};
+
+/// entry addresses of the functions on the guest call stack, rebuilt from the depth changes
+/// seen by a ticker. the bottom one is the reset vector.
+#[derive(Debug, Clone)]
+struct CallFrames(Vec<ProgramAddr>);
+
+impl CallFrames {
+    fn new() -> Self {
+        Self(vec![ProgramAddr(0x0000)])
+    }
+
+    fn current(&self) -> ProgramAddr {
+        *self.0.last().unwrap()
+    }
+
+    /// call after the instruction has run. returns the function that has just been entered.
+    fn update(&mut self, vm: &P16F88) -> Option<ProgramAddr> {
+        // after call/interrupt, the new pc is the callee
+        let depth = vm.call_stack.len() + 1;
+        let entered = (depth > self.0.len()).then(|| ProgramAddr(vm.pc()));
+        self.0.extend(entered);
+        self.0.truncate(depth);
+        entered
+    }
+}
+
+pub(crate) fn format_chain(chain: &[ProgramAddr]) -> String {
+    chain
+        .iter()
+        .map(|x| format!("{:#06x}", x.0))
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PcStats {
+    /// times the instruction was executed
+    pub count: u64,
+    /// instruction cycles spent on it
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    /// times the function was entered
+    pub calls: u64,
+    /// cycles spent in the function itself
+    pub self_cycles: u64,
+    /// cycles spent in the function and everything it called
+    pub total_cycles: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    pcs: BTreeMap<ProgramAddr, PcStats>,
+    functions: BTreeMap<ProgramAddr, FunctionStats>,
+    frames: CallFrames,
+    /// cycles spent sleeping or entering interrupts
+    other_cycles: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            pcs: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            frames: CallFrames::new(),
+            other_cycles: 0,
+        }
+    }
+
+    /// per instruction, hottest (most cycles) first
+    pub fn hot_pcs(&self) -> Vec<(ProgramAddr, PcStats)> {
+        let mut pcs = self.pcs.iter().map(|(a, s)| (*a, *s)).collect::<Vec<_>>();
+        pcs.sort_by_key(|(a, s)| (std::cmp::Reverse(s.cycles), *a));
+        pcs
+    }
+
+    /// per function (keyed by entry address), most self cycles first
+    pub fn hot_functions(&self) -> Vec<(ProgramAddr, FunctionStats)> {
+        let mut fns = self
+            .functions
+            .iter()
+            .map(|(a, s)| (*a, *s))
+            .collect::<Vec<_>>();
+        fns.sort_by_key(|(a, s)| (std::cmp::Reverse(s.self_cycles), *a));
+        fns
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.pcs.values().map(|s| s.cycles).sum::<u64>() + self.other_cycles
+    }
+
+    /// text report with the `top` hottest functions and instructions
+    pub fn report(&self, top: usize) -> Report<'_> {
+        Report { profiler: self, top }
+    }
+}
+
+impl Ticker for Profiler {
+    fn tick(&mut self, vm: &P16F88, cycles: u8) {
+        let cycles = cycles as u64;
+
+        match vm.executing() {
+            Some(pc) => {
+                let s = self.pcs.entry(pc).or_default();
+                s.count += 1;
+                s.cycles += cycles;
+
+                let current = self.frames.current();
+                self.functions.entry(current).or_default().self_cycles += cycles;
+            }
+            None => self.other_cycles += cycles,
+        }
+        let frames = &self.frames.0;
+        for (i, f) in frames.iter().enumerate() {
+            // count recursive frames once
+            if !frames[..i].contains(f) {
+                self.functions.entry(*f).or_default().total_cycles += cycles;
+            }
+        }
+
+        if let Some(entry) = self.frames.update(vm) {
+            self.functions.entry(entry).or_default().calls += 1;
+        }
+    }
+}
+
+/// the first time the call stack got deeper than [`StackMonitor`]'s threshold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeepStack {
+    pub depth: usize,
+    pub cycle: u64,
+    pub pc: u16,
+    /// entry addresses of the functions on the stack, outermost first
+    pub chain: Vec<ProgramAddr>,
+}
+
+/// tracks how deep the guest call stack gets. the device only has 8 levels and overflowing
+/// them (typically by unbounded recursion) silently corrupts the return addresses on hardware.
+#[derive(Debug, Clone)]
+pub struct StackMonitor {
+    frames: CallFrames,
+    cycles: u64,
+    max_depth: usize,
+    /// call chain when `max_depth` was first reached
+    max_chain: Vec<ProgramAddr>,
+    threshold: Option<usize>,
+    exceeded: Option<DeepStack>,
+}
+
+impl Default for StackMonitor {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl StackMonitor {
+    /// `threshold`: remember the first time the depth goes above this
+    pub fn new(threshold: Option<usize>) -> Self {
+        Self {
+            frames: CallFrames::new(),
+            cycles: 0,
+            max_depth: 0,
+            max_chain: vec![],
+            threshold,
+            exceeded: None,
+        }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn exceeded(&self) -> Option<&DeepStack> {
+        self.exceeded.as_ref()
+    }
+
+    /// the maximum depth as info, and the threshold crossing as a warning
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diags = vec![Diagnostic::info(
+            "stack",
+            format!(
+                "max call stack depth: {} ({})",
+                self.max_depth,
+                format_chain(&self.max_chain)
+            ),
+        )];
+        if let Some(e) = &self.exceeded {
+            diags.push(
+                Diagnostic::warning(
+                    "stack",
+                    format!(
+                        "call stack depth reached {} ({})",
+                        e.depth,
+                        format_chain(&e.chain)
+                    ),
+                )
+                .with_cycle(e.cycle)
+                .with_pc(e.pc)
+                .with_data(e.chain.iter().map(|x| x.0).collect::<Vec<_>>()),
+            );
+        }
+        diags
+    }
+}
+
+impl Ticker for StackMonitor {
+    fn tick(&mut self, vm: &P16F88, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.frames.update(vm);
+
+        let depth = vm.call_stack.len();
+        if depth > self.max_depth {
+            self.max_depth = depth;
+            self.max_chain = self.frames.0.clone();
+        }
+        if self.exceeded.is_none() && self.threshold.is_some_and(|t| depth > t) {
+            self.exceeded = Some(DeepStack {
+                depth,
+                cycle: self.cycles,
+                pc: vm.pc(),
+                chain: self.frames.0.clone(),
+            });
+        }
+    }
+}
+
+/// warns about every access to a register the VM only stubs (the timers, the MSSP, ...), as a
+/// sign the firmware depends on hardware that isn't emulated. that is one warning per access, so
+/// `S` is usually a [`stk_diag::Dedup`]. accesses through INDF are not seen.
+#[derive(Debug, Clone)]
+pub struct StubMonitor<S> {
+    sink: S,
+    cycles: u64,
+    /// accesses of the current instruction, and whether they write
+    pending: Vec<(MemoryAccess, bool)>,
+}
+
+impl<S: DiagnosticSink> StubMonitor<S> {
+    pub fn new(sink: S) -> Self {
+        Self { sink, cycles: 0, pending: vec![] }
+    }
+
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+}
+
+impl<S: DiagnosticSink> Ticker for StubMonitor<S> {
+    fn tick(&mut self, vm: &P16F88, cycles: u8) {
+        for (access, write) in self.pending.drain(..) {
+            let slot = vm.device().map[access.bank as usize][access.addr.0 as usize];
+            let Slot::Special(sfr) = slot else {
+                continue;
+            };
+            if let Some(name) = vm.register.special.get(sfr).stub() {
+                let kind = if write { "write" } else { "read" };
+                self.sink.emit(
+                    Diagnostic::warning("vm", format!("{name}: {kind} stub"))
+                        .with_cycle(self.cycles)
+                        .with_pc(access.pc),
+                );
+            }
+        }
+        self.cycles += cycles as u64;
+    }
+
+    fn on_read(&mut self, access: MemoryAccess) {
+        self.pending.push((access, false));
+    }
+
+    fn on_write(&mut self, access: MemoryAccess) {
+        self.pending.push((access, true));
+    }
+}
+
+/// an instruction kept by [`Trace`], with the registers as it left them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: ProgramAddr,
+    /// `None` if the word at `pc` no longer decodes (the program rewrote it)
+    pub inst: Option<Instruction>,
+    pub w: u8,
+    pub status: u8,
+}
+
+impl TraceEntry {
+    /// `0x0236 <lcd_send_nibble+0x2> [lcd.c:42]: ...` with what is known about the pc
+    fn write(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        symbols: &SymbolTable,
+        lines: &SourceLines,
+    ) -> std::fmt::Result {
+        write!(f, "{:#06x}", self.pc.0)?;
+        if symbols.locate(self.pc).is_some() {
+            write!(f, " <{}>", symbols.describe(self.pc))?;
+        }
+        if let Some(location) = lines.locate(self.pc) {
+            write!(f, " [{location}]")?;
+        }
+        write!(f, ": ")?;
+        match self.inst {
+            Some(inst) => write!(f, "{inst:?}")?,
+            None => write!(f, "(invalid)")?,
+        }
+        write!(f, ", W={:#04x} STATUS={:#04x}", self.w, self.status)
+    }
+}
+
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write(f, &SymbolTable::new(), &SourceLines::new())
+    }
+}
+
+/// the last instructions executed, for seeing how the program got where it stopped or crashed.
+/// keeping them costs a copy per instruction, so it can stay plugged in.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+    symbols: SymbolTable,
+    lines: SourceLines,
+}
+
+impl Trace {
+    /// keeps the last `capacity` instructions
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            symbols: SymbolTable::new(),
+            lines: SourceLines::new(),
+        }
+    }
+
+    /// names the pcs when printed
+    pub fn with_symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// shows the source line of the pcs when printed
+    pub fn with_source_lines(mut self, lines: SourceLines) -> Self {
+        self.lines = lines;
+        self
+    }
+
+    /// oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+}
+
+impl Ticker for Trace {
+    fn tick(&mut self, vm: &P16F88, _cycles: u8) {
+        let Some(pc) = vm.executing() else {
+            return;
+        };
+        if self.capacity == 0 {
+            return;
+        }
+        let i = pc.0 as usize * 2;
+        let code = u16::from_le_bytes([vm.flash[i], vm.flash[i + 1]]);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TraceEntry {
+            pc,
+            inst: Instruction::from_code(code),
+            w: vm.w,
+            status: vm.register.special.status().bits(),
+        });
+    }
+}
+
+/// one entry per line, oldest first
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for e in &self.entries {
+            write!(f, "  ")?;
+            e.write(f, &self.symbols, &self.lines)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// counts for the end-of-run [`Summary`]
+#[derive(Debug, Clone, Default)]
+pub struct RunStats {
+    cycles: u64,
+    instructions: BTreeMap<&'static str, u64>,
+    covered: BTreeSet<ProgramAddr>,
+    interrupts: u64,
+}
+
+impl RunStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the parts of the summary seen from the VM. `program_words` is the size of the program
+    /// for the coverage; the rest of the flash is padding.
+    pub fn summary(&self, program_words: usize) -> Summary {
+        let covered = self
+            .covered
+            .range(..ProgramAddr(program_words.try_into().unwrap_or(u16::MAX)))
+            .count();
+        Summary {
+            cycles: self.cycles,
+            instructions: self.instructions.clone(),
+            coverage_percent: covered as f64 * 100.0 / program_words.max(1) as f64,
+            interrupts: self.interrupts,
+            ..Summary::default()
+        }
+    }
+}
+
+impl Ticker for RunStats {
+    fn tick(&mut self, vm: &P16F88, cycles: u8) {
+        self.cycles += cycles as u64;
+        let Some(pc) = vm.executing() else {
+            // the other cycles without an instruction are spent sleeping
+            if !vm.sleeping {
+                self.interrupts += 1;
+            }
+            return;
+        };
+        self.covered.insert(pc);
+        let i = pc.0 as usize * 2;
+        let code = u16::from_le_bytes([vm.flash[i], vm.flash[i + 1]]);
+        if let Some(inst) = Instruction::from_code(code) {
+            *self.instructions.entry(inst.mnemonic()).or_default() += 1;
+        }
+    }
+}
+
+/// what happened in a run, gathered from the subsystems by the frontend
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    /// instruction cycles
+    pub cycles: u64,
+    /// host time the run took
+    pub wall_time_ms: f64,
+    /// times executed per mnemonic
+    pub instructions: BTreeMap<&'static str, u64>,
+    /// share of the program's words executed at least once
+    pub coverage_percent: f64,
+    pub max_stack_depth: usize,
+    /// interrupts serviced
+    pub interrupts: u64,
+    /// per peripheral event, e.g. writes to an LCD
+    pub events: BTreeMap<String, u64>,
+    pub diagnostics: BTreeMap<Severity, u64>,
+}
+
+impl Summary {
+    /// a two-column table
+    pub fn markdown(&self) -> String {
+        let mut md = "| | |\n|---|---|\n".to_owned();
+        for (key, value) in self.rows() {
+            md += &format!("| {key} | {value} |\n");
+        }
+        md
+    }
+
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        let join = |items: Vec<String>| {
+            if items.is_empty() {
+                "none".to_owned()
+            } else {
+                items.join(", ")
+            }
+        };
+        let mut instructions = self.instructions.iter().collect::<Vec<_>>();
+        instructions.sort_by_key(|(name, count)| (std::cmp::Reverse(**count), **name));
+        let instructions = instructions
+            .iter()
+            .map(|(name, count)| format!("{name} {count}"))
+            .collect();
+        let events = self
+            .events
+            .iter()
+            .map(|(name, count)| format!("{name} {count}"))
+            .collect();
+        let diagnostics = self
+            .diagnostics
+            .iter()
+            .rev()
+            .map(|(severity, count)| format!("{severity} {count}"))
+            .collect();
+
+        vec![
+            ("cycles", self.cycles.to_string()),
+            ("wall time", format!("{:.1}ms", self.wall_time_ms)),
+            ("coverage", format!("{:.1}%", self.coverage_percent)),
+            ("max stack depth", self.max_stack_depth.to_string()),
+            ("interrupts", self.interrupts.to_string()),
+            ("instructions", join(instructions)),
+            ("events", join(events)),
+            ("diagnostics", join(diagnostics)),
+        ]
+    }
+}
+
+/// one `key: value` line per item
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value) in self.rows() {
+            writeln!(f, "{key}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Report<'a> {
+    profiler: &'a Profiler,
+    top: usize,
+}
+
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total = self.profiler.total_cycles().max(1);
+        let percent = |c: u64| c as f64 * 100.0 / total as f64;
+
+        writeln!(f, "total: {total} cycles")?;
+        writeln!(f, "functions:")?;
+        for (entry, s) in self.profiler.hot_functions().iter().take(self.top) {
+            writeln!(
+                f,
+                "  {:#06x}: {:5.1}% self, {:5.1}% total, {} calls",
+                entry.0,
+                percent(s.self_cycles),
+                percent(s.total_cycles),
+                s.calls
+            )?;
+        }
+        writeln!(f, "instructions:")?;
+        for (pc, s) in self.profiler.hot_pcs().iter().take(self.top) {
+            writeln!(
+                f,
+                "  {:#06x}: {:5.1}%, {} cycles, {} times",
+                pc.0,
+                percent(s.cycles),
+                s.cycles,
+                s.count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn profiles_delay_loop() {
+    let words: [u16; 6] = [
+        0b11_0000_0000_0011, // 0x0000: movlw 3
+        0b00_0000_1010_0000, // 0x0001: movwf 0x20
+        0b10_0000_0000_0100, // 0x0002: call 0x0004
+        0b00_0000_0110_0011, // 0x0003: sleep
+        0b00_1011_1010_0000, // 0x0004: decfsz 0x20, f
+        0b10_1000_0000_0100, // 0x0005: goto 0x0004
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    // 0x0006: return
+    flash[12] = 0b0000_1000;
+
+    let mut vm = P16F88::new(flash);
+    let mut profiler = Profiler::new();
+    while !vm.sleeping {
+        vm.step(&mut profiler).unwrap();
+    }
+
+    let hot = profiler.hot_pcs();
+    // decfsz runs 3 times, skipping (2 cycles) on the last one
+    assert_eq!(
+        hot[0],
+        (ProgramAddr(0x0004), PcStats { count: 3, cycles: 4 })
+    );
+    assert_eq!(
+        hot[1],
+        (ProgramAddr(0x0005), PcStats { count: 2, cycles: 4 })
+    );
+
+    let fns = profiler.hot_functions();
+    assert_eq!(
+        fns[0],
+        (
+            ProgramAddr(0x0004),
+            FunctionStats { calls: 1, self_cycles: 10, total_cycles: 10 }
+        )
+    );
+    assert_eq!(fns[1].1.total_cycles, profiler.total_cycles());
+}
+
+#[test]
+fn stack_monitor_catches_recursion() {
+    let mut flash = [0; 7168];
+    // 0x0000: call 0x0000
+    flash[..2].copy_from_slice(&0b10_0000_0000_0000u16.to_le_bytes());
+
+    let mut vm = P16F88::new(flash);
+    let mut monitor = StackMonitor::new(Some(6));
+    for _ in 0..8 {
+        vm.step(&mut monitor).unwrap();
+    }
+    assert!(vm.step(&mut monitor).is_err());
+
+    assert_eq!(monitor.max_depth(), 8);
+    let exceeded = monitor.exceeded().unwrap();
+    assert_eq!((exceeded.depth, exceeded.cycle), (7, 14));
+    assert_eq!(exceeded.chain, [ProgramAddr(0x0000); 8]);
+}
+
+#[test]
+fn stub_monitor_reports_stub_accesses() {
+    use stk_diag::Dedup;
+
+    let words: [u16; 3] = [
+        0b00_1000_0000_1110, // 0x0000: movf TMR1L, w
+        0b00_0000_1010_0000, // 0x0001: movwf 0x20
+        0b10_1000_0000_0000, // 0x0002: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = P16F88::new(flash);
+    let mut monitor = StubMonitor::new(Dedup::new(vec![]));
+    for _ in 0..9 {
+        vm.step(&mut monitor).unwrap();
+    }
+
+    let log = monitor.into_sink().into_inner();
+    let lines = log.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "warning[vm] cycle 0 pc 0x0000: TMR1L: read stub",
+            r#"warning[vm]: 2 repeated diagnostics suppressed {"TMR1L: read stub":2}"#,
+        ]
+    );
+}
+
+#[test]
+fn trace_keeps_the_last_instructions() {
+    let words: [u16; 3] = [
+        0x3005, // 0x0000: movlw 0x05
+        0x3EFB, // 0x0001: addlw 0xFB
+        0x2800, // 0x0002: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = P16F88::new(flash);
+    let mut trace = Trace::new(3);
+    for _ in 0..4 {
+        vm.step(&mut trace).unwrap();
+    }
+
+    let pcs = trace.entries().map(|e| e.pc.0).collect::<Vec<_>>();
+    assert_eq!(pcs, [0x0001, 0x0002, 0x0000]);
+    assert_eq!(
+        trace.to_string().lines().collect::<Vec<_>>(),
+        [
+            "  0x0001: AddLiteralToW(251), W=0x00 STATUS=0x1f",
+            "  0x0002: Goto(0x0000), W=0x00 STATUS=0x1f",
+            "  0x0000: MoveLiteralToW(5), W=0x05 STATUS=0x1f",
+        ]
+    );
+
+    let mut symbols = SymbolTable::new();
+    symbols.insert_code(ProgramAddr(0x0001), "add");
+    assert_eq!(
+        trace
+            .with_symbols(symbols)
+            .to_string()
+            .lines()
+            .collect::<Vec<_>>(),
+        [
+            "  0x0001 <add>: AddLiteralToW(251), W=0x00 STATUS=0x1f",
+            "  0x0002 <add+0x1>: Goto(0x0000), W=0x00 STATUS=0x1f",
+            "  0x0000: MoveLiteralToW(5), W=0x05 STATUS=0x1f",
+        ]
+    );
+}
+
+#[test]
+fn summarizes_a_run() {
+    let words: [u16; 4] = [
+        0x3005, // 0x0000: movlw 0x05
+        0x0BA0, // 0x0001: decfsz 0x20, f
+        0x2801, // 0x0002: goto 0x0001
+        0x2800, // 0x0003: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = P16F88::new(flash);
+    let mut stats = RunStats::new();
+    for _ in 0..5 {
+        vm.step(&mut stats).unwrap();
+    }
+
+    let mut summary = stats.summary(words.len());
+    summary.events.insert("lcd writes".to_owned(), 2);
+    summary.diagnostics.insert(Severity::Warning, 1);
+    // 0x20 starts at 0, so decfsz loops 255 times. goto 0x0000 isn't reached
+    assert_eq!(
+        summary.to_string(),
+        "cycles: 7\n\
+         wall time: 0.0ms\n\
+         coverage: 75.0%\n\
+         max stack depth: 0\n\
+         interrupts: 0\n\
+         instructions: decfsz 2, goto 2, movlw 1\n\
+         events: lcd writes 2\n\
+         diagnostics: warning 1\n"
+    );
+    assert!(summary.markdown().contains("| coverage | 75.0% |\n"));
+}
@@ -0,0 +1,317 @@
+//! records of what mattered in a run, for a frontend to list or export. a [`Recorder`] asks its
+//! [`Predicate`]s after every instruction whether there's something to record, timestamps what
+//! they return and keeps it, all of it or the latest [`Recorder::capacity`] records. the records
+//! write out as NDJSON, in the format of [`crate::events`], or as a VCD for waveform viewers.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use crate::events::{Event, EventKind};
+use crate::vm::pic14::{Pic14, Pin, Ticker, CLOCKS_PER_CYCLE};
+
+/// decides what to record. closures taking the vm are predicates too
+pub trait Predicate {
+    /// called after each instruction, with the state it left
+    fn record(&mut self, vm: &Pic14) -> Option<EventKind>;
+}
+
+impl<F: FnMut(&Pic14) -> Option<EventKind>> Predicate for F {
+    fn record(&mut self, vm: &Pic14) -> Option<EventKind> {
+        self(vm)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub event: Event,
+    /// return addresses when it happened, outermost first
+    pub call_stack: Vec<u16>,
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    predicates: Vec<Box<dyn Predicate + Send>>,
+    /// `None` keeps everything
+    capacity: Option<usize>,
+    records: VecDeque<Record>,
+    /// records made, including those dropped
+    recorded: u64,
+    cycle: u64,
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("predicates", &self.predicates.len())
+            .field("capacity", &self.capacity)
+            .field("records", &self.records)
+            .field("recorded", &self.recorded)
+            .field("cycle", &self.cycle)
+            .finish()
+    }
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// also record what `predicate` returns. predicates asked on the same instruction record in
+    /// the order they were added
+    pub fn with(mut self, predicate: impl Predicate + Send + 'static) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    /// keep only the latest `records`, dropping the oldest
+    pub fn capacity(mut self, records: usize) -> Self {
+        self.capacity = Some(records);
+        self
+    }
+
+    /// the records kept, oldest first
+    pub fn records(&self) -> impl DoubleEndedIterator<Item = &Record> + ExactSizeIterator + '_ {
+        self.records.iter()
+    }
+
+    /// records made so far, including the ones the capacity dropped
+    pub fn recorded(&self) -> u64 {
+        self.recorded
+    }
+
+    /// records the capacity dropped
+    pub fn dropped(&self) -> u64 {
+        self.recorded - self.records.len() as u64
+    }
+
+    /// the records kept, leaving none
+    pub fn take(&mut self) -> Vec<Record> {
+        self.records.drain(..).collect()
+    }
+
+    /// one [`Event`] per line, as an [`EventLog`](crate::events::EventLog) writes them. the call
+    /// stacks are left out
+    pub fn write_ndjson(&self, mut out: impl Write) -> io::Result<()> {
+        for record in &self.records {
+            serde_json::to_writer(&mut out, &record.event)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// a Value Change Dump at 1ns: a wire per pin, `lcd_rs` and `lcd_data` for what an LCD
+    /// latched, `uart` for TXREG and an 8-bit signal per register written. other records aren't
+    /// signals and are left out
+    pub fn write_vcd(&self, mut out: impl Write) -> io::Result<()> {
+        // (name, width), in the order they first appear
+        let mut signals: Vec<(String, u8)> = vec![];
+        let mut changes = vec![];
+        for record in &self.records {
+            let mut change = |name: String, width: u8, value: Option<u8>| {
+                let index = match signals.iter().position(|x| x.0 == name) {
+                    Some(index) => index,
+                    None => {
+                        signals.push((name, width));
+                        signals.len() - 1
+                    }
+                };
+                changes.push((record.event.time_ns, index, value));
+            };
+            match &record.event.kind {
+                EventKind::Pin { pin, level } => change(pin.clone(), 1, level.map(u8::from)),
+                EventKind::Lcd { rs, data } => {
+                    change("lcd_rs".to_owned(), 1, Some(*rs as u8));
+                    change("lcd_data".to_owned(), 8, Some(*data));
+                }
+                EventKind::Uart { byte } => change("uart".to_owned(), 8, Some(*byte)),
+                EventKind::Write { addr, value } => {
+                    change(format!("reg_{addr:03x}"), 8, Some(*value))
+                }
+                _ => {}
+            }
+        }
+
+        writeln!(out, "$timescale 1ns $end")?;
+        writeln!(out, "$scope module stk $end")?;
+        for (i, (name, width)) in signals.iter().enumerate() {
+            let kind = if *width == 1 { "wire" } else { "reg" };
+            writeln!(out, "$var {kind} {width} {} {name} $end", vcd_id(i))?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+        let mut time = None;
+        for (at, index, value) in changes {
+            if time != Some(at) {
+                writeln!(out, "#{at}")?;
+                time = Some(at);
+            }
+            let id = vcd_id(index);
+            match (signals[index].1, value) {
+                (1, Some(level)) => writeln!(out, "{level}{id}")?,
+                (1, None) => writeln!(out, "z{id}")?,
+                (_, Some(value)) => writeln!(out, "b{value:08b} {id}")?,
+                (_, None) => writeln!(out, "bz {id}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `!`, `"`, ... `~`, then `!!`, ...
+fn vcd_id(mut index: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return id;
+        }
+        index -= 1;
+    }
+}
+
+impl Ticker for Recorder {
+    fn tick(&mut self, vm: &Pic14, cycles: u8) {
+        self.cycle += cycles as u64;
+        let clocks = self.cycle as u128 * CLOCKS_PER_CYCLE as u128;
+        let time_ns = (clocks * 1_000_000_000 / vm.clock_hz() as u128) as u64;
+        for predicate in &mut self.predicates {
+            let Some(kind) = predicate.record(vm) else {
+                continue;
+            };
+            self.records.push_back(Record {
+                event: Event {
+                    cycle: self.cycle,
+                    time_ns,
+                    pc: vm.executing().map_or(vm.pc(), |x| x.0),
+                    kind,
+                },
+                call_stack: vm.call_stack.clone(),
+            });
+            self.recorded += 1;
+            if self.capacity.is_some_and(|x| self.records.len() > x) {
+                self.records.pop_front();
+            }
+        }
+    }
+}
+
+/// an HD44780 in 4-bit mode latching a nibble on the falling edge of E, as
+/// [`EventKind::Lcd`] with the nibble in the high half of `data`
+#[derive(Debug, Clone)]
+pub struct Hd44780Edge {
+    e: Pin,
+    rs: Pin,
+    /// DB7..4
+    db: [Pin; 4],
+    before_e: bool,
+}
+
+impl Hd44780Edge {
+    /// wired as on the board: E on RA3, RS on RA4 and DB7..4 on RB3..0
+    pub fn new() -> Self {
+        Self::wired(Pin::ra(3), Pin::ra(4), [3, 2, 1, 0].map(Pin::rb))
+    }
+
+    pub fn wired(e: Pin, rs: Pin, db: [Pin; 4]) -> Self {
+        Self { e, rs, db, before_e: false }
+    }
+}
+
+impl Default for Hd44780Edge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Predicate for Hd44780Edge {
+    fn record(&mut self, vm: &Pic14) -> Option<EventKind> {
+        let e = vm.pin_level(self.e);
+        let falling = self.before_e && !e;
+        self.before_e = e;
+        if !falling {
+            return None;
+        }
+        let data = self
+            .db
+            .iter()
+            .fold(0, |acc, &pin| acc << 1 | vm.pin_level(pin) as u8);
+        Some(EventKind::Lcd { rs: vm.pin_level(self.rs), data: data << 4 })
+    }
+}
+
+#[test]
+fn records_lcd_nibbles_and_writes_them_out() {
+    use crate::vm::p16f88::P16F88;
+
+    let words: [u16; 9] = [
+        0b01_0110_1000_0011, // 0x0000: bsf STATUS, RP0
+        0b00_0001_1000_0101, // 0x0001: clrf TRISA
+        0b00_0001_1000_0110, // 0x0002: clrf TRISB
+        0b01_0010_1000_0011, // 0x0003: bcf STATUS, RP0
+        0b11_0000_0000_0011, // 0x0004: movlw 0x03
+        0b00_0000_1000_0110, // 0x0005: movwf PORTB
+        0b11_0000_0001_1000, // 0x0006: movlw 0x18
+        0b00_0000_1000_0101, // 0x0007: movwf PORTA
+        0b01_0001_1000_0101, // 0x0008: bcf PORTA, 3
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    let mut vm = P16F88::builder().flash(&flash).build();
+    // ANSEL would have RA3 and RA4 read as 0
+    vm.register.special.ansel_mut().0 = 0;
+
+    let rb0 = Pin::rb(0);
+    let mut before = None;
+    let rb0_changes = move |vm: &Pic14| {
+        let level = vm.pin_output(rb0);
+        let changed = level != before;
+        before = level;
+        changed.then(|| EventKind::Pin { pin: "RB0".to_owned(), level })
+    };
+    let mut recorder = Recorder::new()
+        .with(Hd44780Edge::new())
+        .with(rb0_changes)
+        .capacity(2);
+    for _ in 0..words.len() {
+        vm.step(&mut recorder).unwrap();
+    }
+
+    // RB0 went low, then high, then the nibble
+    assert_eq!(recorder.recorded(), 3);
+    assert_eq!(recorder.dropped(), 1);
+    let events = recorder
+        .records()
+        .map(|x| (x.event.cycle, x.event.pc, x.event.kind.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        events,
+        [
+            (
+                6,
+                5,
+                EventKind::Pin { pin: "RB0".to_owned(), level: Some(true) }
+            ),
+            (9, 8, EventKind::Lcd { rs: true, data: 0x30 }),
+        ]
+    );
+
+    let mut ndjson = vec![];
+    recorder.write_ndjson(&mut ndjson).unwrap();
+    let ndjson = String::from_utf8(ndjson).unwrap();
+    assert_eq!(ndjson.lines().count(), 2);
+    assert!(ndjson.ends_with(
+        "{\"cycle\":9,\"time_ns\":1800,\"pc\":8,\"event\":\"lcd\",\"rs\":true,\"data\":48}\n"
+    ));
+
+    let mut vcd = vec![];
+    recorder.write_vcd(&mut vcd).unwrap();
+    let vcd = String::from_utf8(vcd).unwrap();
+    assert!(vcd.contains("$var wire 1 ! RB0 $end\n$var wire 1 \" lcd_rs $end\n"));
+    assert!(vcd.contains("$var reg 8 # lcd_data $end\n"));
+    assert!(vcd.ends_with("#1200\n1!\n#1800\n1\"\nb00110000 #\n"));
+    assert_eq!(vcd_id(93), "~");
+    assert_eq!(vcd_id(94), "!!");
+}
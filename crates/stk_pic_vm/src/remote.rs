@@ -0,0 +1,251 @@
+//! devices simulated by another process (a Verilator model, a Python mock, ...), attached to a
+//! [`Simulation`](crate::sim::Simulation) over a socket or pipe as a [`Peripheral`].
+//!
+//! the protocol is a stream of [`Message`]s. every [`RemoteDevice::new`]`(.., quantum)` of
+//! simulated time the host sends the levels of the wired MCU pins that have changed, then
+//! `Advance`. the device runs that long, sends the levels of its own outputs that have changed
+//! and finishes with `Done`:
+//!
+//! ```text
+//! host -> device   Pin { pin: 0, level: Some(true) }
+//! host -> device   Advance { ns: 1000 }
+//! device -> host   Pin { pin: 1, level: Some(false) }
+//! device -> host   Done
+//! ```
+//!
+//! frames are a tag byte and a fixed payload, integers little endian:
+//!
+//! | message   | tag    | payload                                        |
+//! |-----------|--------|------------------------------------------------|
+//! | `Advance` | `0x01` | `ns: u64`                                      |
+//! | `Pin`     | `0x02` | `pin: u8`, `level: u8` (0, 1, or 0xFF for none) |
+//! | `Done`    | `0x03` |                                                |
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use crate::sim::Peripheral;
+use crate::vm::cosim::{CoSim, McuPin};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// host to device: run `ns` nanoseconds of simulated time, then answer [`Message::Done`]
+    Advance { ns: u64 },
+    /// the level on pin `pin` of the device, either way. `None` when it isn't driven
+    Pin { pin: u8, level: Option<bool> },
+    /// device to host: the advance has finished
+    Done,
+}
+
+const ADVANCE: u8 = 0x01;
+const PIN: u8 = 0x02;
+const DONE: u8 = 0x03;
+const NOT_DRIVEN: u8 = 0xFF;
+
+impl Message {
+    pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+        match *self {
+            Message::Advance { ns } => {
+                w.write_all(&[ADVANCE])?;
+                w.write_all(&ns.to_le_bytes())
+            }
+            Message::Pin { pin, level } => {
+                let level = level.map_or(NOT_DRIVEN, u8::from);
+                w.write_all(&[PIN, pin, level])
+            }
+            Message::Done => w.write_all(&[DONE]),
+        }
+    }
+
+    pub fn read_from(r: &mut impl Read) -> io::Result<Self> {
+        let mut tag = [0];
+        r.read_exact(&mut tag)?;
+        match tag[0] {
+            ADVANCE => {
+                let mut ns = [0; 8];
+                r.read_exact(&mut ns)?;
+                Ok(Message::Advance { ns: u64::from_le_bytes(ns) })
+            }
+            PIN => {
+                let mut payload = [0; 2];
+                r.read_exact(&mut payload)?;
+                let level = match payload[1] {
+                    0 => Some(false),
+                    1 => Some(true),
+                    NOT_DRIVEN => None,
+                    x => return Err(invalid(format!("invalid pin level {x:#04x}"))),
+                };
+                Ok(Message::Pin { pin: payload[0], level })
+            }
+            DONE => Ok(Message::Done),
+            x => Err(invalid(format!("unknown message tag {x:#04x}"))),
+        }
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// the host side of the protocol. pin `i` of the device is wired to `pins[i]`: the device hears
+/// the MCU's output on it and drives the MCU's input with its own.
+pub struct RemoteDevice<S> {
+    stream: S,
+    pins: Vec<McuPin>,
+    quantum: Duration,
+    /// time the device has been advanced to
+    synced: Duration,
+    /// levels last sent per pin. `None` until the first exchange
+    sent: Vec<Option<Option<bool>>>,
+    /// the link broke. the device is left behind from then on
+    error: Option<io::Error>,
+}
+
+impl<S: Read + Write> RemoteDevice<S> {
+    /// `quantum`: simulated time between exchanges. a smaller one is more accurate and slower,
+    /// as every exchange is a round trip. `pins` must not have more than 256 entries.
+    pub fn new(stream: S, pins: Vec<McuPin>, quantum: Duration) -> Self {
+        assert!(pins.len() <= 256);
+        Self {
+            stream,
+            sent: vec![None; pins.len()],
+            pins,
+            quantum,
+            synced: Duration::ZERO,
+            error: None,
+        }
+    }
+
+    /// why the device stopped being updated, if it did
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    fn exchange(&mut self, now: Duration, mcus: &mut CoSim) -> io::Result<()> {
+        for (i, pin) in self.pins.iter().enumerate() {
+            let level = mcus.mcu(pin.mcu).pin_output(pin.pin);
+            if self.sent[i] != Some(level) {
+                Message::Pin { pin: i as u8, level }.write_to(&mut self.stream)?;
+                self.sent[i] = Some(level);
+            }
+        }
+        let ns = (now - self.synced)
+            .as_nanos()
+            .try_into()
+            .unwrap_or(u64::MAX);
+        Message::Advance { ns }.write_to(&mut self.stream)?;
+        self.stream.flush()?;
+        self.synced = now;
+
+        loop {
+            match Message::read_from(&mut self.stream)? {
+                Message::Pin { pin, level } => {
+                    let Some(to) = self.pins.get(pin as usize) else {
+                        return Err(invalid(format!("device drove unknown pin {pin}")));
+                    };
+                    let vm = mcus.mcu_mut(to.mcu);
+                    match level {
+                        Some(level) => vm.set_pin_input(to.pin, level),
+                        None => vm.release_pin_input(to.pin),
+                    }
+                }
+                Message::Done => return Ok(()),
+                Message::Advance { .. } => {
+                    return Err(invalid("device sent Advance".to_owned()));
+                }
+            }
+        }
+    }
+}
+
+impl<S: Read + Write> Peripheral for RemoteDevice<S> {
+    fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+        if self.error.is_some() || now < self.synced + self.quantum {
+            return;
+        }
+        if let Err(e) = self.exchange(now, mcus) {
+            tracing::error!("remote device disconnected: {e}");
+            self.error = Some(e);
+        }
+    }
+}
+
+#[test]
+fn messages_round_trip() {
+    let messages = [
+        Message::Advance { ns: 1_000_000 },
+        Message::Pin { pin: 3, level: Some(true) },
+        Message::Pin { pin: 0, level: None },
+        Message::Done,
+    ];
+    let mut buf = vec![];
+    for m in &messages {
+        m.write_to(&mut buf).unwrap();
+    }
+    assert_eq!(buf.len(), 9 + 3 + 3 + 1);
+
+    let mut r = buf.as_slice();
+    for m in &messages {
+        assert_eq!(Message::read_from(&mut r).unwrap(), *m);
+    }
+    assert!(Message::read_from(&mut [0x7Fu8].as_slice()).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn remote_device_mirrors_a_pin() {
+    use std::os::unix::net::UnixStream;
+
+    use crate::sim::Simulation;
+    use crate::vm::pic14::{Pic14, Pin};
+
+    let (host, mut device) = UnixStream::pair().unwrap();
+    // copies pin 0 to pin 1. returns the time it has been advanced by
+    let device = std::thread::spawn(move || {
+        let mut level = None;
+        let mut elapsed = 0;
+        while let Ok(m) = Message::read_from(&mut device) {
+            match m {
+                Message::Pin { pin: 0, level: l } => level = l,
+                Message::Advance { ns } => {
+                    elapsed += ns;
+                    Message::Pin { pin: 1, level }
+                        .write_to(&mut device)
+                        .unwrap();
+                    Message::Done.write_to(&mut device).unwrap();
+                }
+                _ => {}
+            }
+        }
+        elapsed
+    });
+
+    let mut flash = [0; 7168];
+    let words: [u16; 5] = [
+        0x1683, // bsf STATUS, RP0
+        0x1006, // bcf TRISB, 0
+        0x1283, // bcf STATUS, RP0
+        0x1406, // bsf PORTB, 0
+        0x2804, // goto 0x0004
+    ];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut sim = Simulation::new();
+    let mcu = sim.add_mcu(Pic14::new(flash));
+    let pins = vec![
+        McuPin { mcu, pin: Pin::rb(0) },
+        McuPin { mcu, pin: Pin::ra(2) },
+    ];
+    sim.add_peripheral(RemoteDevice::new(host, pins, Duration::from_micros(1)));
+    sim.advance(Duration::from_micros(10), &mut ()).unwrap();
+
+    let porta = sim.mcus().mcu(mcu).register.special.porta();
+    assert_eq!((porta.driven & 0b100, porta.input & 0b100), (0b100, 0b100));
+
+    // closing the stream ends the device. it lags by less than a quantum
+    drop(sim);
+    let elapsed = device.join().unwrap();
+    assert!((9_000..=10_000).contains(&elapsed), "{elapsed}");
+}
@@ -0,0 +1,212 @@
+//! deterministic record and replay of what the host drives into a vm. route the inputs through a
+//! [`Recorder`] while running, keep its [`InputLog`] (it serializes to JSON), and a [`Replayer`]
+//! applies them again at the same instruction boundaries. the vm itself is deterministic, so the
+//! replay reproduces the run exactly: an intermittent bug stays caught, and a log makes a
+//! regression fixture.
+
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::savefile::{Format, SaveFileError};
+use crate::vm::pic14::{Pic14, Pin, Run, RunExit, Ticker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Input {
+    /// [`Pic14::set_pin_input`], or [`Pic14::release_pin_input`] for `None`
+    Pin { pin: Pin, level: Option<bool> },
+    /// [`Pic14::set_analog_input`]
+    Analog { channel: u8, volts: f32 },
+}
+
+impl Input {
+    pub fn apply(&self, vm: &mut Pic14) {
+        match *self {
+            Input::Pin { pin, level: Some(level) } => vm.set_pin_input(pin, level),
+            Input::Pin { pin, level: None } => vm.release_pin_input(pin),
+            Input::Analog { channel, volts } => vm.set_analog_input(channel, volts),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputLog {
+    /// inputs with the cycle they went in at, in order
+    pub events: Vec<(u64, Input)>,
+}
+
+impl InputLog {
+    pub const FORMAT: Format = Format { name: "input log", migrations: &[] };
+
+    pub fn load(r: impl Read) -> Result<Self, SaveFileError> {
+        Self::FORMAT.load(r)
+    }
+
+    pub fn save(&self, w: impl Write) -> Result<(), SaveFileError> {
+        Self::FORMAT.save(w, self)
+    }
+}
+
+/// applies inputs to a vm and logs them with the cycle, counted from the first run through it
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    log: InputLog,
+    cycles: u64,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// applies `input` to `vm` now, between instructions
+    pub fn input(&mut self, vm: &mut Pic14, input: Input) {
+        input.apply(vm);
+        self.log.events.push((self.cycles, input));
+    }
+
+    /// [`Pic14::run_for_cycles`], counting the cycles for the log
+    pub fn run_for_cycles(&mut self, vm: &mut Pic14, cycles: u64, ticker: &mut impl Ticker) -> Run {
+        let run = vm.run_for_cycles(cycles, ticker);
+        self.cycles += run.cycles;
+        run
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn log(&self) -> &InputLog {
+        &self.log
+    }
+
+    pub fn into_log(self) -> InputLog {
+        self.log
+    }
+}
+
+/// feeds an [`InputLog`] to a vm in the state the recording started from
+#[derive(Debug, Clone)]
+pub struct Replayer {
+    log: InputLog,
+    /// index of the next event in `log`
+    next: usize,
+    cycles: u64,
+}
+
+impl Replayer {
+    pub fn new(log: InputLog) -> Self {
+        Self { log, next: 0, cycles: 0 }
+    }
+
+    /// whether every logged input has been applied
+    pub fn is_finished(&self) -> bool {
+        self.next == self.log.events.len()
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// [`Pic14::run_for_cycles`], stopping on the way to apply the inputs that are due
+    pub fn run_for_cycles(&mut self, vm: &mut Pic14, cycles: u64, ticker: &mut impl Ticker) -> Run {
+        self.run_with(vm, Some(cycles), |_| false, ticker)
+    }
+
+    /// [`Pic14::run_until`], stopping on the way to apply the inputs that are due
+    pub fn run_until(
+        &mut self,
+        vm: &mut Pic14,
+        cond: impl FnMut(&Pic14) -> bool,
+        ticker: &mut impl Ticker,
+    ) -> Run {
+        self.run_with(vm, None, cond, ticker)
+    }
+
+    /// [`Pic14::run_until_within`], stopping on the way to apply the inputs that are due
+    pub fn run_until_within(
+        &mut self,
+        vm: &mut Pic14,
+        cycles: u64,
+        cond: impl FnMut(&Pic14) -> bool,
+        ticker: &mut impl Ticker,
+    ) -> Run {
+        self.run_with(vm, Some(cycles), cond, ticker)
+    }
+
+    fn run_with(
+        &mut self,
+        vm: &mut Pic14,
+        budget: Option<u64>,
+        mut cond: impl FnMut(&Pic14) -> bool,
+        ticker: &mut impl Ticker,
+    ) -> Run {
+        let start = self.cycles;
+        let end = budget.map(|x| start.saturating_add(x));
+        loop {
+            while let Some(&(at, input)) = self.log.events.get(self.next) {
+                if at > self.cycles {
+                    break;
+                }
+                input.apply(vm);
+                self.next += 1;
+            }
+            if end.is_some_and(|end| self.cycles >= end) {
+                return Run { cycles: self.cycles - start, exit: RunExit::Budget };
+            }
+
+            let due = self.log.events.get(self.next).map(|(at, _)| *at);
+            let until = match (due, end) {
+                (Some(due), Some(end)) => Some(due.min(end)),
+                (due, end) => due.or(end),
+            };
+            let run = vm.run_with(until.map(|x| x - self.cycles), &mut cond, None, ticker);
+            self.cycles += run.cycles;
+            if run.exit != RunExit::Budget || until.is_none() {
+                return Run { cycles: self.cycles - start, exit: run.exit };
+            }
+        }
+    }
+}
+
+#[test]
+fn replay_reproduces_the_run() {
+    use crate::vm::pic14::HashScope;
+
+    let words: [u16; 3] = [
+        0x1806, // 0x0000: btfsc PORTB, 0
+        0x0AA0, // 0x0001: incf 0x20, f
+        0x2800, // 0x0002: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = Pic14::new(flash);
+    let mut recorder = Recorder::new();
+    recorder.run_for_cycles(&mut vm, 100, &mut ());
+    recorder.input(&mut vm, Input::Pin { pin: Pin::rb(0), level: Some(true) });
+    recorder.run_for_cycles(&mut vm, 51, &mut ());
+    recorder.input(&mut vm, Input::Analog { channel: 0, volts: 2.5 });
+    recorder.run_for_cycles(&mut vm, 30, &mut ());
+    recorder.input(&mut vm, Input::Pin { pin: Pin::rb(0), level: None });
+    recorder.run_for_cycles(&mut vm, 40, &mut ());
+    assert_ne!(vm.register.gpr[0].0, 0);
+
+    let mut json = vec![];
+    recorder.log().save(&mut json).unwrap();
+    let log = InputLog::load(json.as_slice()).unwrap();
+    assert_eq!(&log, recorder.log());
+
+    let mut replayed = Pic14::new(flash);
+    let mut replayer = Replayer::new(log);
+    replayer.run_for_cycles(&mut replayed, recorder.cycles(), &mut ());
+    assert!(replayer.is_finished());
+    assert_eq!(replayer.cycles(), recorder.cycles());
+    assert_eq!(
+        replayed.state_hash(HashScope::Full),
+        vm.state_hash(HashScope::Full)
+    );
+}
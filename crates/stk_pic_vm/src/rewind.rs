@@ -0,0 +1,225 @@
+//! stepping backwards. [`Rewinder`] keeps a copy of the whole vm every so many cycles and logs
+//! the inputs in between (as [`crate::replay`] does), so any earlier instruction boundary can be
+//! rebuilt by replaying from the checkpoint before it.
+
+use std::collections::VecDeque;
+
+use crate::inst::ProgramAddr;
+use crate::replay::Input;
+use crate::vm::pic14::{MemoryAccess, Pic14, Stopped, Ticker, VmError};
+
+/// counts cycles on the way to the user's ticker
+struct Counting<'a, T> {
+    inner: &'a mut T,
+    cycles: u64,
+}
+
+impl<T: Ticker> Ticker for Counting<'_, T> {
+    fn tick(&mut self, vm: &Pic14, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.inner.tick(vm, cycles);
+    }
+
+    fn on_read(&mut self, access: MemoryAccess) {
+        self.inner.on_read(access);
+    }
+
+    fn on_write(&mut self, access: MemoryAccess) {
+        self.inner.on_write(access);
+    }
+}
+
+pub struct Rewinder {
+    /// instruction cycles between checkpoints
+    interval: u64,
+    /// checkpoints kept. the oldest one is as far back as stepping back can go
+    capacity: usize,
+    /// oldest first, with the cycle each was taken at
+    checkpoints: VecDeque<(u64, Pic14)>,
+    /// inputs since the oldest checkpoint, with the cycle they went in at
+    inputs: Vec<(u64, Input)>,
+    cycles: u64,
+}
+
+impl Rewinder {
+    /// starts from `vm` as it is now. `capacity` copies of the vm are kept, so the history
+    /// reaches back `interval * (capacity - 1)` cycles at least.
+    pub fn new(vm: &Pic14, interval: u64, capacity: usize) -> Self {
+        assert!(interval > 0 && capacity > 0);
+        Self {
+            interval,
+            capacity,
+            checkpoints: VecDeque::from([(0, vm.clone())]),
+            inputs: vec![],
+            cycles: 0,
+        }
+    }
+
+    /// instruction cycles run through this rewinder, less those stepped back over
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// applies `input` to `vm` now, between instructions. inputs must go through here to be
+    /// replayed.
+    pub fn input(&mut self, vm: &mut Pic14, input: Input) {
+        input.apply(vm);
+        self.inputs.push((self.cycles, input));
+    }
+
+    /// [`Pic14::step`], taking a checkpoint when one is due
+    pub fn step(
+        &mut self,
+        vm: &mut Pic14,
+        ticker: &mut impl Ticker,
+    ) -> Result<Option<Stopped>, VmError> {
+        let mut ticker = Counting { inner: ticker, cycles: 0 };
+        let stepped = vm.step(&mut ticker);
+        self.cycles += ticker.cycles;
+
+        let last = self.checkpoints.back().map_or(0, |(at, _)| *at);
+        if self.cycles >= last + self.interval {
+            self.checkpoints.push_back((self.cycles, vm.clone()));
+            if self.checkpoints.len() > self.capacity {
+                self.checkpoints.pop_front();
+                let oldest = self.checkpoints[0].0;
+                self.inputs.retain(|(at, _)| *at >= oldest);
+            }
+        }
+        stepped
+    }
+
+    /// goes back to the instruction boundary before the current one. returns false, leaving
+    /// `vm` as it is, if that is older than the history.
+    pub fn step_back(&mut self, vm: &mut Pic14) -> bool {
+        self.rewind(vm, |_| true)
+    }
+
+    /// goes back to the last boundary where the pc was `addr`, not counting the current one.
+    /// returns false, leaving `vm` as it is, if there is none in the history.
+    pub fn run_back_to(&mut self, vm: &mut Pic14, addr: ProgramAddr) -> bool {
+        self.rewind(vm, |vm| vm.pc() == addr.0)
+    }
+
+    /// goes back to the latest boundary before now where `cond` holds
+    fn rewind(&mut self, vm: &mut Pic14, cond: impl Fn(&Pic14) -> bool) -> bool {
+        for i in (0..self.checkpoints.len()).rev() {
+            let start = self.checkpoints[i].0;
+            if start >= self.cycles {
+                continue;
+            }
+            let end = self
+                .checkpoints
+                .get(i + 1)
+                .map_or(self.cycles, |(at, _)| *at);
+
+            let mut found = None;
+            self.replay(i, end, |cycle, vm| {
+                if cond(vm) {
+                    found = Some(cycle);
+                }
+                false
+            });
+            if let Some(target) = found {
+                let (cycle, mut restored) = self.replay(i, target, |_, _| false);
+                // breakpoints are debugger state, not history
+                for addr in restored.breakpoints().collect::<Vec<_>>() {
+                    restored.remove_breakpoint(addr);
+                }
+                for addr in vm.breakpoints() {
+                    restored.add_breakpoint(addr);
+                }
+                *vm = restored;
+
+                self.cycles = cycle;
+                self.checkpoints.retain(|(at, _)| *at <= cycle);
+                self.inputs.retain(|(at, _)| *at <= cycle);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// a copy of checkpoint `i` run forward, with the logged inputs, up to the first boundary at
+    /// or after `until` or the first one `stop` returns true for. `stop` sees every boundary
+    /// before `until`, inputs applied.
+    fn replay(
+        &self,
+        i: usize,
+        until: u64,
+        mut stop: impl FnMut(u64, &Pic14) -> bool,
+    ) -> (u64, Pic14) {
+        let (mut cycle, ref checkpoint) = self.checkpoints[i];
+        let mut vm = checkpoint.clone();
+        let mut inputs = self
+            .inputs
+            .iter()
+            .skip_while(move |(at, _)| *at < cycle)
+            .peekable();
+        loop {
+            while let Some((_, input)) = inputs.next_if(|(at, _)| *at == cycle) {
+                input.apply(&mut vm);
+            }
+            if cycle >= until || stop(cycle, &vm) {
+                return (cycle, vm);
+            }
+            let mut ticker = Counting { inner: &mut (), cycles: 0 };
+            loop {
+                match vm.step(&mut ticker) {
+                    // stopping at a breakpoint doesn't execute it. go on
+                    Ok(Some(_)) => continue,
+                    Ok(None) => break,
+                    // the recorded run stopped here too
+                    Err(_) => return (cycle, vm),
+                }
+            }
+            cycle += ticker.cycles;
+        }
+    }
+}
+
+#[test]
+fn steps_back_through_history() {
+    use crate::vm::pic14::Pin;
+
+    let words: [u16; 3] = [
+        0x1806, // 0x0000: btfsc PORTB, 0
+        0x0AA0, // 0x0001: incf 0x20, f
+        0x2800, // 0x0002: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    let mut vm = Pic14::new(flash);
+    let mut rewinder = Rewinder::new(&vm, 16, 8);
+
+    let mut history = vec![(0, vm.snapshot())];
+    for i in 0..40 {
+        if i == 10 {
+            rewinder.input(&mut vm, Input::Pin { pin: Pin::rb(0), level: Some(true) });
+            // the boundary is rebuilt with the inputs given at it
+            history.last_mut().unwrap().1 = vm.snapshot();
+        }
+        rewinder.step(&mut vm, &mut ()).unwrap();
+        history.push((rewinder.cycles(), vm.snapshot()));
+    }
+    // the counter ran for the last 30 steps
+    assert_ne!(vm.register.gpr[0].0, 0);
+
+    let last_incf = history[..40]
+        .iter()
+        .rposition(|(_, snapshot)| snapshot.pc == 0x0001)
+        .unwrap();
+    assert!(rewinder.run_back_to(&mut vm, ProgramAddr(0x0001)));
+    assert_eq!((rewinder.cycles(), vm.snapshot()), history[last_incf]);
+
+    // and one instruction at a time from there, over the input, back to the start
+    for expected in history[..last_incf].iter().rev() {
+        assert!(rewinder.step_back(&mut vm));
+        assert_eq!((rewinder.cycles(), vm.snapshot()), *expected);
+    }
+    assert!(!rewinder.step_back(&mut vm));
+    // RB0 was low, so the incf was always skipped
+    assert!(!rewinder.run_back_to(&mut vm, ProgramAddr(0x0001)));
+}
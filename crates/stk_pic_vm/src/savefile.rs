@@ -0,0 +1,146 @@
+//! versioned save files. every format written to disk carries a `version` field, and its
+//! [`Format`] lists the migrations that bring older versions up to the current one, so files
+//! written by earlier releases keep loading as the schemas change.
+//!
+//! to change a schema, bump it by appending a migration that rewrites the JSON of the previous
+//! version, and test it against a file of that version.
+
+use std::io::{Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+/// rewrites a file of version N into version N + 1. the `version` field is taken care of.
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+pub struct Format {
+    /// for error messages
+    pub name: &'static str,
+    /// `migrations[i]` upgrades version `i + 1` to `i + 2`
+    pub migrations: &'static [Migration],
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum SaveFileError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("{format}: expected a JSON object with a positive version")]
+    Malformed { format: &'static str },
+
+    #[error("{format}: version {found} is newer than this build supports ({supported})")]
+    TooNew {
+        format: &'static str,
+        found: u64,
+        supported: u64,
+    },
+
+    #[error("{format}: can't migrate from version {from}: {message}")]
+    Migration {
+        format: &'static str,
+        from: u64,
+        message: String,
+    },
+}
+
+impl Format {
+    /// version files are written in
+    pub fn version(&self) -> u64 {
+        self.migrations.len() as u64 + 1
+    }
+
+    /// brings `value` up to the current version. a file without a `version` is version 1, from
+    /// before files were versioned.
+    pub fn migrate(&self, mut value: Value) -> Result<Value, SaveFileError> {
+        let malformed = || SaveFileError::Malformed { format: self.name };
+        let found = match value.as_object().ok_or_else(malformed)?.get("version") {
+            Some(v) => v.as_u64().filter(|v| *v > 0).ok_or_else(malformed)?,
+            None => 1,
+        };
+        if found > self.version() {
+            return Err(SaveFileError::TooNew {
+                format: self.name,
+                found,
+                supported: self.version(),
+            });
+        }
+        for (from, migration) in (found..).zip(&self.migrations[(found - 1) as usize..]) {
+            value = migration(value).map_err(|message| SaveFileError::Migration {
+                format: self.name,
+                from,
+                message,
+            })?;
+            let object = value.as_object_mut().ok_or_else(malformed)?;
+            object.insert("version".to_owned(), (from + 1).into());
+        }
+        Ok(value)
+    }
+
+    /// `data` as JSON, with the current version
+    pub fn to_value(&self, data: &impl Serialize) -> Result<Value, SaveFileError> {
+        let mut value = serde_json::to_value(data)?;
+        let object = value
+            .as_object_mut()
+            .ok_or(SaveFileError::Malformed { format: self.name })?;
+        object.insert("version".to_owned(), self.version().into());
+        Ok(value)
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, r: impl Read) -> Result<T, SaveFileError> {
+        let value = self.migrate(serde_json::from_reader(r)?)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn save(&self, w: impl Write, data: &impl Serialize) -> Result<(), SaveFileError> {
+        serde_json::to_writer(w, &self.to_value(data)?)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn migrates_old_versions() {
+    use serde_json::json;
+
+    // v2 renamed `x` to `pos`, v3 turned it into a pair
+    static POINT: Format = Format {
+        name: "point",
+        migrations: &[
+            |mut v| {
+                let x = v["x"].take();
+                v["pos"] = x;
+                v.as_object_mut().unwrap().remove("x");
+                Ok(v)
+            },
+            |mut v| {
+                let pos = v["pos"].as_i64().ok_or("pos is not a number")?;
+                v["pos"] = json!([pos, 0]);
+                Ok(v)
+            },
+        ],
+    };
+    let current = json!({ "version": 3, "pos": [5, 0] });
+
+    assert_eq!(POINT.migrate(json!({ "x": 5 })).unwrap(), current);
+    assert_eq!(
+        POINT.migrate(json!({ "version": 2, "pos": 5 })).unwrap(),
+        current
+    );
+    assert_eq!(POINT.migrate(current.clone()).unwrap(), current);
+
+    assert!(matches!(
+        POINT.migrate(json!({ "version": 4 })),
+        Err(SaveFileError::TooNew { found: 4, supported: 3, .. })
+    ));
+    assert!(matches!(
+        POINT.migrate(json!({ "x": "left" })),
+        Err(SaveFileError::Migration { from: 2, .. })
+    ));
+    for malformed in [json!([1]), json!({ "version": 0 })] {
+        assert!(matches!(
+            POINT.migrate(malformed),
+            Err(SaveFileError::Malformed { .. })
+        ));
+    }
+}
@@ -0,0 +1,359 @@
+//! `stk-pic-vm script ...`: firmware acceptance tests as [rhai](https://rhai.rs) scripts, so a
+//! new check doesn't mean a new predicate in the runner and a rebuild. the script drives the run
+//! and asserts on what it sees:
+//!
+//! ```text
+//! let blinks = 0;
+//! on_pin_change(|pin, level| if pin == "RA1" && level == true { blinks += 1 });
+//! set_pin("RB0", false);          // hold the button down
+//! run_for("1.2s");
+//! assert_eq(blinks, 2, "the led blinks twice");
+//! assert(wait_pin("RA1", false, "600ms"));
+//! ```
+//!
+//! - running: `run_for(cycles)`, `run_for("10ms")`, `step()`, `run_until(addr or code symbol,
+//!   "timeout")` and `wait_pin(pin, level, "timeout")`, the last two return whether they got there
+//! - state: `pc()`, `cycles()`, `time()` in seconds, `peek(addr, variable or SFR)`, `pin(pin)`
+//!   (`()` for an input) and `lcd()` with the two lines of the LCD
+//! - inputs: `set_pin(pin, level)`, `release_pin(pin)` and `set_analog(pin, volts)`
+//! - callbacks: `on_pin_change(|pin, level| ..)`, `on_lcd(|rs, data| ..)`, `on_uart(|byte| ..)`
+//!   and `on_cycle(|cycle| ..)` (after every instruction, slow)
+//! - checks: `assert(cond)` and `assert_eq(left, right)`, both with an optional message
+//!
+//! the command fails when a check does or the script has an error.
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::rc::Rc;
+
+use clap::Args;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FnPtr, NativeCallContext, FLOAT, INT};
+use stk_hd44780_vm::{Hd44780, PinObserver};
+use stk_pic_vm::events::{EventKind, EventQueue};
+use stk_pic_vm::lcd::LcdWiring;
+use stk_pic_vm::stop::parse_duration;
+use stk_pic_vm::symbols::SymbolTable;
+use stk_pic_vm::vm::p16f88::{MemoryAccess, Pin, Ticker, CLOCKS_PER_CYCLE, FLASH_BYTES, P16F88};
+
+use crate::load_firmware;
+
+type RhaiResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Args, Debug)]
+pub struct ScriptArgs {
+    /// firmware to test, as Intel HEX or ELF (`.elf`)
+    file: PathBuf,
+
+    /// rhai script that runs the firmware and checks what it does
+    script: PathBuf,
+
+    /// pic-as `.map` file of the firmware, for the script to use names. replaces the symbols of an
+    /// ELF
+    #[arg(long, value_name = "PATH")]
+    map: Option<PathBuf>,
+}
+
+pub fn run(args: ScriptArgs) -> Result<ExitCode, Box<dyn Error>> {
+    let (memory, symbols) = load_firmware(&args.file, args.map.as_deref())?;
+    let source = std::fs::read_to_string(&args.script)
+        .map_err(|e| format!("{}: {e}", args.script.display()))?;
+
+    let vm = P16F88::new(memory.program_flash(FLASH_BYTES).try_into().unwrap());
+    let session = Rc::new(RefCell::new(Session {
+        vm,
+        symbols,
+        ticker: ScriptTicker {
+            events: EventQueue::new(),
+            lcd: Hd44780::new(),
+            e: false,
+        },
+        callbacks: Rc::default(),
+    }));
+    match engine(&session).run(&source) {
+        Ok(()) => Ok(ExitCode::SUCCESS),
+        Err(e) => {
+            eprintln!("{}: {e}", args.script.display());
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+struct ScriptTicker {
+    events: EventQueue,
+    lcd: Hd44780,
+    /// E of the LCD at the last tick
+    e: bool,
+}
+
+impl Ticker for ScriptTicker {
+    fn tick(&mut self, vm: &P16F88, cycles: u8) {
+        self.events.tick(vm, cycles);
+        let pins = LcdWiring::board().pins(vm);
+        // the LCD latches on the falling edge of E
+        let e = pins.e == Some(true);
+        if self.e && !e {
+            let db = [pins.db7, pins.db6, pins.db5, pins.db4];
+            let data = db
+                .iter()
+                .fold(0, |acc, x| acc << 1 | u8::from(*x == Some(true)))
+                << 4;
+            let rs = pins.rs == Some(true);
+            self.events.push(vm, EventKind::Lcd { rs, data });
+        }
+        self.e = e;
+        self.lcd.update(pins);
+    }
+
+    fn on_write(&mut self, access: MemoryAccess) {
+        self.events.on_write(access);
+    }
+}
+
+#[derive(Default, Clone)]
+struct Callbacks {
+    pin: Vec<FnPtr>,
+    lcd: Vec<FnPtr>,
+    uart: Vec<FnPtr>,
+    cycle: Vec<FnPtr>,
+}
+
+struct Session {
+    vm: P16F88,
+    symbols: SymbolTable,
+    ticker: ScriptTicker,
+    /// shared, so a run can call them without holding the session
+    callbacks: Rc<Callbacks>,
+}
+
+impl Session {
+    fn cycles_in(&self, time: &str) -> RhaiResult<u64> {
+        let duration = parse_duration(time).ok_or_else(|| format!("invalid time `{time}`"))?;
+        Ok(self.vm.cycles_in(duration))
+    }
+
+    /// linear address of a variable or an SFR
+    fn register(&self, name: &str) -> RhaiResult<u16> {
+        self.symbols
+            .data_addr(name)
+            .map(|x| x.0 as u16)
+            .or_else(|| self.vm.device().sfr_addr(name))
+            .ok_or_else(|| format!("no variable or SFR `{name}`").into())
+    }
+
+    fn code(&self, name: &str) -> RhaiResult<u16> {
+        self.symbols
+            .code_addr(name)
+            .map(|x| x.0)
+            .ok_or_else(|| format!("no code symbol `{name}`").into())
+    }
+}
+
+fn pin(name: &str) -> RhaiResult<Pin> {
+    Ok(name.parse::<Pin>()?)
+}
+
+/// runs at most `budget` cycles, until `until` holds, calling the script back on the way.
+/// whether `until` held
+fn drive(
+    ctx: &NativeCallContext,
+    session: &RefCell<Session>,
+    budget: u64,
+    until: impl Fn(&P16F88) -> bool,
+) -> RhaiResult<bool> {
+    let start = session.borrow().ticker.events.cycle();
+    loop {
+        let (events, cycle, callbacks) = {
+            let s = &mut *session.borrow_mut();
+            if until(&s.vm) {
+                return Ok(true);
+            }
+            if s.ticker.events.cycle() - start >= budget {
+                return Ok(false);
+            }
+            s.vm.step(&mut s.ticker)
+                .map_err(|e| format!("{:#06x}: {e}", s.vm.pc()))?;
+            (
+                s.ticker.events.take(),
+                s.ticker.events.cycle(),
+                s.callbacks.clone(),
+            )
+        };
+
+        for event in events {
+            let (callbacks, args): (_, Vec<Dynamic>) = match event.kind {
+                EventKind::Pin { pin, level } => {
+                    let level = level.map_or(Dynamic::UNIT, Dynamic::from);
+                    (&callbacks.pin, vec![pin.into(), level])
+                }
+                EventKind::Lcd { rs, data } => {
+                    (&callbacks.lcd, vec![rs.into(), (data as INT).into()])
+                }
+                EventKind::Uart { byte } => (&callbacks.uart, vec![(byte as INT).into()]),
+                _ => continue,
+            };
+            // what the callbacks return is of no use
+            for f in callbacks {
+                let _ = f.call_raw(ctx, None, args.clone())?;
+            }
+        }
+        for f in &callbacks.cycle {
+            let _ = f.call_raw(ctx, None, [(cycle as INT).into()])?;
+        }
+    }
+}
+
+fn engine(session: &Rc<RefCell<Session>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let s = session.clone();
+    engine.register_fn("run_for", move |ctx: NativeCallContext, cycles: INT| {
+        drive(&ctx, &s, cycles.max(0) as u64, |_| false).map(drop)
+    });
+    let s = session.clone();
+    engine.register_fn("run_for", move |ctx: NativeCallContext, time: &str| {
+        let budget = s.borrow().cycles_in(time)?;
+        drive(&ctx, &s, budget, |_| false).map(drop)
+    });
+    let s = session.clone();
+    engine.register_fn("step", move |ctx: NativeCallContext| {
+        drive(&ctx, &s, 1, |_| false).map(drop)
+    });
+    let s = session.clone();
+    engine.register_fn(
+        "run_until",
+        move |ctx: NativeCallContext, addr: INT, timeout: &str| {
+            let budget = s.borrow().cycles_in(timeout)?;
+            drive(&ctx, &s, budget, |vm| vm.pc() as INT == addr)
+        },
+    );
+    let s = session.clone();
+    engine.register_fn(
+        "run_until",
+        move |ctx: NativeCallContext, symbol: &str, timeout: &str| {
+            let (addr, budget) = {
+                let s = s.borrow();
+                (s.code(symbol)?, s.cycles_in(timeout)?)
+            };
+            drive(&ctx, &s, budget, |vm| vm.pc() == addr)
+        },
+    );
+    let s = session.clone();
+    engine.register_fn(
+        "wait_pin",
+        move |ctx: NativeCallContext, name: &str, level: bool, timeout: &str| {
+            let (pin, budget) = (pin(name)?, s.borrow().cycles_in(timeout)?);
+            drive(&ctx, &s, budget, |vm| vm.pin_output(pin) == Some(level))
+        },
+    );
+
+    let s = session.clone();
+    engine.register_fn("pc", move || s.borrow().vm.pc() as INT);
+    let s = session.clone();
+    engine.register_fn("cycles", move || s.borrow().ticker.events.cycle() as INT);
+    let s = session.clone();
+    engine.register_fn("time", move || {
+        let s = s.borrow();
+        (s.ticker.events.cycle() * CLOCKS_PER_CYCLE) as FLOAT / s.vm.clock_hz() as FLOAT
+    });
+    let s = session.clone();
+    engine.register_fn("peek", move |addr: INT| -> RhaiResult<INT> {
+        let addr = u16::try_from(addr).map_err(|_| format!("invalid address {addr}"))?;
+        Ok(s.borrow().vm.peek(addr) as INT)
+    });
+    let s = session.clone();
+    engine.register_fn("peek", move |name: &str| -> RhaiResult<INT> {
+        let s = s.borrow();
+        Ok(s.vm.peek(s.register(name)?) as INT)
+    });
+    let s = session.clone();
+    engine.register_fn("pin", move |name: &str| -> RhaiResult<Dynamic> {
+        let level = s.borrow().vm.pin_output(pin(name)?);
+        Ok(level.map_or(Dynamic::UNIT, Dynamic::from))
+    });
+    let s = session.clone();
+    engine.register_fn("lcd", move || -> Array {
+        let lines = s.borrow().ticker.lcd.lines();
+        lines.into_iter().map(Dynamic::from).collect()
+    });
+
+    let s = session.clone();
+    engine.register_fn(
+        "set_pin",
+        move |name: &str, level: bool| -> RhaiResult<()> {
+            s.borrow_mut().vm.set_pin_input(pin(name)?, level);
+            Ok(())
+        },
+    );
+    let s = session.clone();
+    engine.register_fn("release_pin", move |name: &str| -> RhaiResult<()> {
+        s.borrow_mut().vm.release_pin_input(pin(name)?);
+        Ok(())
+    });
+    let s = session.clone();
+    engine.register_fn(
+        "set_analog",
+        move |name: &str, volts: FLOAT| -> RhaiResult<()> {
+            let pin = pin(name)?;
+            let channel = pin
+                .analog_channel()
+                .ok_or_else(|| format!("{pin:?} has no A/D channel"))?;
+            s.borrow_mut().vm.set_analog_input(channel, volts as f32);
+            Ok(())
+        },
+    );
+
+    on(&mut engine, session, "on_pin_change", |x| &mut x.pin);
+    on(&mut engine, session, "on_lcd", |x| &mut x.lcd);
+    on(&mut engine, session, "on_uart", |x| &mut x.uart);
+    on(&mut engine, session, "on_cycle", |x| &mut x.cycle);
+
+    engine.register_fn("assert", |cond: bool| {
+        check(cond, || "assertion failed".to_owned())
+    });
+    engine.register_fn("assert", |cond: bool, message: &str| {
+        check(cond, || format!("assertion failed: {message}"))
+    });
+    engine.register_fn("assert_eq", |left: Dynamic, right: Dynamic| {
+        check(same(&left, &right), || {
+            format!("assertion failed: left == right\n  left: {left:?}\n right: {right:?}")
+        })
+    });
+    engine.register_fn(
+        "assert_eq",
+        |left: Dynamic, right: Dynamic, message: &str| {
+            check(same(&left, &right), || {
+                format!("assertion failed: {message}\n  left: {left:?}\n right: {right:?}")
+            })
+        },
+    );
+
+    engine
+}
+
+/// registers `name` to add a callback to `list`
+fn on(
+    engine: &mut Engine,
+    session: &Rc<RefCell<Session>>,
+    name: &str,
+    list: fn(&mut Callbacks) -> &mut Vec<FnPtr>,
+) {
+    let s = session.clone();
+    engine.register_fn(name, move |f: FnPtr| {
+        let mut s = s.borrow_mut();
+        list(Rc::make_mut(&mut s.callbacks)).push(f);
+    });
+}
+
+fn check(cond: bool, message: impl FnOnce() -> String) -> RhaiResult<()> {
+    if cond {
+        Ok(())
+    } else {
+        Err(message().into())
+    }
+}
+
+fn same(left: &Dynamic, right: &Dynamic) -> bool {
+    left.type_name() == right.type_name() && left.to_string() == right.to_string()
+}
@@ -0,0 +1,171 @@
+//! one clock for the whole simulation. [`Simulation`] owns the MCUs (as a [`CoSim`]) and the
+//! peripherals around them, advances them all in time order and can be paused, so hosts don't
+//! each keep their own cycle budgets in tickers.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::vm::cosim::{CoSim, Halted};
+use crate::vm::pic14::{Pic14, Ticker};
+
+/// something outside the MCUs that lives on the simulation clock: an LCD watching pins, a signal
+/// generator driving them, ...
+pub trait Peripheral {
+    /// called after every instruction of any MCU, with the time all of them have reached
+    fn update(&mut self, now: Duration, mcus: &mut CoSim);
+}
+
+/// for keeping a handle to read the peripheral's state from outside
+impl<T: Peripheral> Peripheral for Rc<RefCell<T>> {
+    fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+        self.borrow_mut().update(now, mcus)
+    }
+}
+
+#[derive(Default)]
+pub struct Simulation {
+    mcus: CoSim,
+    peripherals: Vec<Box<dyn Peripheral>>,
+    /// time [`Self::advance`] has been asked to reach. the MCUs stop on the first instruction
+    /// boundary at or after it.
+    target: Duration,
+    paused: bool,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns the index of `vm` in [`Self::mcus`]
+    pub fn add_mcu(&mut self, vm: Pic14) -> usize {
+        self.mcus.add(vm)
+    }
+
+    pub fn add_peripheral(&mut self, peripheral: impl Peripheral + 'static) {
+        self.peripherals.push(Box::new(peripheral));
+    }
+
+    pub fn mcus(&self) -> &CoSim {
+        &self.mcus
+    }
+
+    /// for wiring pins and poking at the MCUs between advances
+    pub fn mcus_mut(&mut self) -> &mut CoSim {
+        &mut self.mcus
+    }
+
+    /// simulated time since the start, not counting time spent paused
+    pub fn now(&self) -> Duration {
+        self.target
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// runs everything `duration` further, or nothing while paused. when an MCU halts (error or
+    /// breakpoint) the simulation pauses there; resuming continues from that instruction.
+    pub fn advance(&mut self, duration: Duration, ticker: &mut impl Ticker) -> Result<(), Halted> {
+        if self.paused {
+            return Ok(());
+        }
+        self.target += duration;
+
+        if self.mcus.is_empty() {
+            for p in &mut self.peripherals {
+                p.update(self.target, &mut self.mcus);
+            }
+            return Ok(());
+        }
+        while self.mcus.now() < self.target {
+            self.step(ticker)?;
+        }
+        Ok(())
+    }
+
+    /// executes one instruction on the MCU furthest behind and updates the peripherals, for
+    /// hosts that schedule the MCUs themselves. does nothing while paused.
+    pub fn step(&mut self, ticker: &mut impl Ticker) -> Result<(), Halted> {
+        if self.paused || self.mcus.is_empty() {
+            return Ok(());
+        }
+        let stepped = self.mcus.step(ticker);
+        let now = self.mcus.now();
+        for p in &mut self.peripherals {
+            p.update(now, &mut self.mcus);
+        }
+        match stepped {
+            Ok(_) => {
+                self.target = self.target.max(now);
+                Ok(())
+            }
+            Err(halted) => {
+                self.target = now;
+                self.paused = true;
+                Err(halted)
+            }
+        }
+    }
+}
+
+#[test]
+fn advances_in_time_and_pauses() {
+    use crate::vm::pic14::Pin;
+
+    /// drives RB0 of MCU 0 high from 5 us on
+    #[derive(Default)]
+    struct Late {
+        calls: usize,
+        last: Duration,
+    }
+    impl Peripheral for Late {
+        fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+            self.calls += 1;
+            self.last = now;
+            mcus.mcu_mut(0)
+                .set_pin_input(Pin::rb(0), now >= Duration::from_micros(5));
+        }
+    }
+
+    let mut flash = [0; 7168];
+    // 0x0000: goto 0x0000
+    flash[..2].copy_from_slice(&0b10_1000_0000_0000u16.to_le_bytes());
+    let mut vm = Pic14::new(flash);
+    // 1 us per cycle
+    vm.set_clock_hz(4_000_000);
+
+    let mut sim = Simulation::new();
+    sim.add_mcu(vm);
+    let late = Rc::new(RefCell::new(Late::default()));
+    sim.add_peripheral(Rc::clone(&late));
+
+    sim.advance(Duration::from_micros(4), &mut ()).unwrap();
+    assert_eq!(sim.mcus().mcu(0).register.special.portb().input & 1, 0);
+    sim.advance(Duration::from_micros(6), &mut ()).unwrap();
+    assert_eq!(sim.mcus().mcu(0).register.special.portb().input & 1, 1);
+    assert_eq!((late.borrow().calls, late.borrow().last), (5, sim.now()));
+
+    sim.pause();
+    sim.advance(Duration::from_micros(10), &mut ()).unwrap();
+    assert_eq!(
+        (sim.now(), sim.mcus().cycles(0)),
+        (Duration::from_micros(10), 10)
+    );
+
+    sim.resume();
+    sim.advance(Duration::from_micros(10), &mut ()).unwrap();
+    assert_eq!(
+        (sim.now(), sim.mcus().cycles(0)),
+        (Duration::from_micros(20), 20)
+    );
+}
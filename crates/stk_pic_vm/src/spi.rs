@@ -0,0 +1,87 @@
+//! an SPI bus on the pins of an MCU, with the devices attached to the [`SpiBus`]. the SSP is still
+//! a stub, so the firmware bit-bangs SCK, SDO and the chip selects and reads SDI. a chip select
+//! left as an input reads high, through the pull-up the board has on it.
+
+use std::time::Duration;
+
+use stk_spi_vm::{SpiBus, Transaction};
+
+use crate::events::EventKind;
+use crate::sim::Peripheral;
+use crate::vm::cosim::CoSim;
+use crate::vm::pic14::Pin;
+
+/// the bus on the pins of one MCU, the MCU as the master
+#[derive(Debug)]
+pub struct SpiPins {
+    bus: SpiBus,
+    sck: Pin,
+    /// SDO of the MCU
+    mosi: Pin,
+    /// SDI of the MCU
+    miso: Pin,
+    /// per device, by the index the bus gave it
+    cs: Vec<Pin>,
+    mcu: usize,
+    /// finished, with when they finished
+    transactions: Vec<(Duration, Transaction)>,
+}
+
+impl SpiPins {
+    /// on the SSP pins of the PIC16F88: SCK on RB4, SDO on RB2 and SDI on RB1, with the chip
+    /// selects of the devices on `cs`. `mcu` is the index in the [`CoSim`]
+    pub fn new(bus: SpiBus, cs: Vec<Pin>, mcu: usize) -> Self {
+        Self::wired(bus, Pin::rb(4), Pin::rb(2), Pin::rb(1), cs, mcu)
+    }
+
+    pub fn wired(bus: SpiBus, sck: Pin, mosi: Pin, miso: Pin, cs: Vec<Pin>, mcu: usize) -> Self {
+        assert_eq!(cs.len(), bus.devices(), "a chip select per device");
+        Self {
+            bus,
+            sck,
+            mosi,
+            miso,
+            cs,
+            mcu,
+            transactions: vec![],
+        }
+    }
+
+    pub fn bus(&self) -> &SpiBus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut SpiBus {
+        &mut self.bus
+    }
+
+    /// the transactions finished since the last call with the time each ended, oldest first
+    pub fn take_transactions(&mut self) -> Vec<(Duration, Transaction)> {
+        std::mem::take(&mut self.transactions)
+    }
+}
+
+/// for the event log, e.g. with [`EventLog::emit`](crate::events::EventLog::emit)
+pub fn event(transaction: Transaction) -> EventKind {
+    let Transaction { device, mosi, miso } = transaction;
+    EventKind::Spi { device, mosi, miso }
+}
+
+impl Peripheral for SpiPins {
+    fn update(&mut self, now: Duration, mcus: &mut CoSim) {
+        let vm = mcus.mcu_mut(self.mcu);
+        for (i, &cs) in self.cs.iter().enumerate() {
+            self.bus.set_select(i, vm.pin_output(cs) != Some(false));
+        }
+        let sck = vm.pin_output(self.sck) == Some(true);
+        let mosi = vm.pin_output(self.mosi) == Some(true);
+        self.bus.update(sck, mosi);
+        match self.bus.miso() {
+            Some(level) => vm.set_pin_input(self.miso, level),
+            None => vm.release_pin_input(self.miso),
+        }
+        let finished = self.bus.take_transactions();
+        self.transactions
+            .extend(finished.into_iter().map(|x| (now, x)));
+    }
+}
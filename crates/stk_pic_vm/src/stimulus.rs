@@ -0,0 +1,211 @@
+//! what the outside world does to the pins, scheduled against the simulation clock. a stimulus
+//! file has one input per line, `#` starts a comment:
+//!
+//! ```text
+//! at 0ms    RB2 high                  # idle level of the UART line
+//! at 10ms   RB0 low                   # press the button
+//! at 60ms   RB0 release               # and let the pull-up have it back
+//! at 20ms   RA0 2.5V                  # A/D channel on the pin
+//! at 100ms  RB2 uart 9600 "hi", 0x0d  # 8N1 frames bit-banged on the pin
+//! ```
+//!
+//! times are in `s`, `ms`, `us` or `ns` from the start of the run and lines don't have to be in
+//! order. [`Stimulus::input_log`] turns the file into an [`InputLog`] for a
+//! [`Replayer`](crate::replay::Replayer).
+
+use std::time::Duration;
+
+use crate::asm::{parse_number, split_unquoted, string_operand};
+use crate::replay::{Input, InputLog};
+use crate::stop::parse_duration;
+use crate::vm::pic14::{Pin, CLOCKS_PER_CYCLE};
+
+#[derive(Debug, thiserror::Error)]
+#[error("line {line}: {kind}")]
+pub struct Error {
+    /// from 1
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    #[error("expected `at <time> <pin> <action>`")]
+    Syntax,
+
+    #[error("invalid time `{0}`")]
+    InvalidTime(String),
+
+    #[error("{0}")]
+    InvalidPin(String),
+
+    #[error("{0:?} has no A/D channel")]
+    NotAnalog(Pin),
+
+    #[error("unknown action `{0}`")]
+    UnknownAction(String),
+
+    #[error("invalid {what} `{value}`")]
+    InvalidOperand { value: String, what: &'static str },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stimulus {
+    /// inputs with when they go in, in the order of the file. a UART line is already broken down
+    /// into its bits
+    pub inputs: Vec<(Duration, Input)>,
+}
+
+impl Stimulus {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut inputs = vec![];
+        for (i, line) in source.lines().enumerate() {
+            parse_line(line, &mut inputs).map_err(|kind| Error { line: i + 1, kind })?;
+        }
+        Ok(Self { inputs })
+    }
+
+    /// the inputs at the cycles they fall on at `clock_hz`. inputs at the same cycle keep the
+    /// order of the file
+    pub fn input_log(&self, clock_hz: u64) -> InputLog {
+        let cycle = |at: Duration| {
+            (at.as_nanos() * clock_hz as u128 / CLOCKS_PER_CYCLE as u128 / 1_000_000_000) as u64
+        };
+        let mut events = self
+            .inputs
+            .iter()
+            .map(|&(at, input)| (cycle(at), input))
+            .collect::<Vec<_>>();
+        events.sort_by_key(|&(at, _)| at);
+        InputLog { events }
+    }
+}
+
+fn parse_line(line: &str, inputs: &mut Vec<(Duration, Input)>) -> Result<(), ErrorKind> {
+    let line = split_unquoted(line, '#')[0].trim();
+    if line.is_empty() {
+        return Ok(());
+    }
+    let ("at", rest) = next_word(line) else {
+        return Err(ErrorKind::Syntax);
+    };
+    let (time, rest) = next_word(rest);
+    let (pin, action) = next_word(rest);
+    let (verb, operands) = next_word(action);
+    if verb.is_empty() {
+        return Err(ErrorKind::Syntax);
+    }
+    let at = parse_duration(time).ok_or_else(|| ErrorKind::InvalidTime(time.to_owned()))?;
+    let pin = pin.parse::<Pin>().map_err(ErrorKind::InvalidPin)?;
+
+    let level = match verb {
+        "high" => Some(Some(true)),
+        "low" => Some(Some(false)),
+        "release" => Some(None),
+        _ => None,
+    };
+    if let (Some(level), "") = (level, operands) {
+        inputs.push((at, Input::Pin { pin, level }));
+        return Ok(());
+    }
+
+    if let (Some(volts), "") = (verb.strip_suffix(['V', 'v']), operands) {
+        let volts = volts
+            .parse()
+            .map_err(|_| ErrorKind::InvalidOperand { value: verb.to_owned(), what: "voltage" })?;
+        let channel = pin.analog_channel().ok_or(ErrorKind::NotAnalog(pin))?;
+        inputs.push((at, Input::Analog { channel, volts }));
+        return Ok(());
+    }
+
+    if verb == "uart" {
+        let (baud, bytes) = next_word(operands);
+        let baud = baud.parse::<u32>().ok().filter(|&x| x > 0).ok_or_else(|| {
+            ErrorKind::InvalidOperand { value: baud.to_owned(), what: "baud rate" }
+        })?;
+        let bit = Duration::from_secs(1) / baud;
+        let mut t = at;
+        for b in parse_bytes(bytes)? {
+            // start bit, 8 data bits from the LSB, stop bit
+            let frame = (0..10).map(|i| match i {
+                0 => false,
+                9 => true,
+                _ => b >> (i - 1) & 1 != 0,
+            });
+            for level in frame {
+                inputs.push((t, Input::Pin { pin, level: Some(level) }));
+                t += bit;
+            }
+        }
+        return Ok(());
+    }
+
+    Err(ErrorKind::UnknownAction(action.to_owned()))
+}
+
+/// the first word of `s` and what's left after it, both trimmed
+fn next_word(s: &str) -> (&str, &str) {
+    let s = s.trim();
+    let (word, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+    (word, rest.trim())
+}
+
+/// `"hi", 0x0d`
+fn parse_bytes(s: &str) -> Result<Vec<u8>, ErrorKind> {
+    let mut bytes = vec![];
+    for operand in split_unquoted(s, ',') {
+        let operand = operand.trim();
+        if let Some(text) = string_operand(operand) {
+            bytes.extend(text.bytes());
+            continue;
+        }
+        let b = parse_number(operand)
+            .and_then(|x| u8::try_from(x).ok())
+            .ok_or_else(|| ErrorKind::InvalidOperand { value: operand.to_owned(), what: "byte" })?;
+        bytes.push(b);
+    }
+    Ok(bytes)
+}
+
+#[test]
+fn schedules_inputs() {
+    let source = "
+at 2ms RB0 low      # press
+at 1ms RA0 2.5V
+at 2ms RB0 release
+at 0us RB2 uart 250000 \"A\"
+";
+    let stimulus = Stimulus::parse(source).unwrap();
+    let log = stimulus.input_log(20_000_000);
+
+    // a bit is 4us, 20 cycles at 20 MHz. 'A' is 0b0100_0001
+    let uart = log.events[..10]
+        .iter()
+        .map(|&(at, input)| match input {
+            Input::Pin { pin, level: Some(level) } if pin == Pin::rb(2) => (at, level),
+            _ => panic!("{input:?}"),
+        })
+        .collect::<Vec<_>>();
+    #[rustfmt::skip]
+    assert_eq!(
+        uart,
+        [
+            (0, false), (20, true), (40, false), (60, false), (80, false),
+            (100, false), (120, false), (140, true), (160, false), (180, true),
+        ]
+    );
+    assert_eq!(
+        log.events[10..],
+        [
+            (5000, Input::Analog { channel: 0, volts: 2.5 }),
+            (10000, Input::Pin { pin: Pin::rb(0), level: Some(false) }),
+            (10000, Input::Pin { pin: Pin::rb(0), level: None }),
+        ]
+    );
+
+    let err = Stimulus::parse("at 1ms RB1 1V\nat 2ms RB9 high\n").unwrap_err();
+    assert_eq!(err.to_string(), "line 1: RB1 has no A/D channel");
+    let err = Stimulus::parse("at 1ms RB0 high\nat soon RB0 low\n").unwrap_err();
+    assert_eq!(err.to_string(), "line 2: invalid time `soon`");
+}
@@ -0,0 +1,159 @@
+//! when a run should end, besides breakpoints and errors. [`StopConditions::run`] runs a vm until
+//! one of them holds and says which.
+
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::inst::ProgramAddr;
+use crate::vm::pic14::{Pic14, Run, RunExit, Ticker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    /// this many instruction cycles have run
+    Cycles(u64),
+    /// this much simulated time has passed, at the clock of the vm
+    Time(Duration),
+    /// the pc is about to run `addr`
+    Pc(ProgramAddr),
+    /// the pc is past `addr`, e.g. it ran off the end of the program into erased flash
+    PcPast(ProgramAddr),
+    /// the register at linear address `addr` reads `value`
+    Register { addr: u16, value: u8 },
+    /// the vm executed SLEEP
+    Sleep,
+}
+
+impl Display for StopCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopCondition::Cycles(n) => write!(f, "{n} cycles have run"),
+            StopCondition::Time(t) => write!(f, "{t:?} have passed"),
+            StopCondition::Pc(addr) => write!(f, "pc reached {:#06x}", addr.0),
+            StopCondition::PcPast(addr) => write!(f, "pc went past {:#06x}", addr.0),
+            StopCondition::Register { addr, value } => {
+                write!(f, "register {addr:#05x} is {value:#04x}")
+            }
+            StopCondition::Sleep => write!(f, "sleeping"),
+        }
+    }
+}
+
+/// any of the conditions ends the run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StopConditions(pub Vec<StopCondition>);
+
+impl StopConditions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, condition: StopCondition) {
+        self.0.push(condition);
+    }
+
+    /// cycles until the first cycle or time condition at the clock of `vm`, `None` without any
+    pub fn budget(&self, vm: &Pic14) -> Option<u64> {
+        self.0
+            .iter()
+            .filter_map(|c| match *c {
+                StopCondition::Cycles(n) => Some(n),
+                StopCondition::Time(t) => Some(vm.cycles_in(t)),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// the first condition on the state of `vm` that holds
+    pub fn holds(&self, vm: &Pic14) -> Option<StopCondition> {
+        self.0.iter().copied().find(|c| match *c {
+            StopCondition::Cycles(_) | StopCondition::Time(_) => false,
+            StopCondition::Pc(addr) => vm.pc() == addr.0,
+            StopCondition::PcPast(addr) => vm.pc() > addr.0,
+            StopCondition::Register { addr, value } => vm.peek(addr) == value,
+            StopCondition::Sleep => vm.sleeping,
+        })
+    }
+
+    /// [`Pic14::run_until_within`] the conditions. for runs made some other way, e.g. through a
+    /// [`Replayer`](crate::replay::Replayer), pass [`Self::budget`] and [`Self::holds`] and ask
+    /// [`Self::fired`] afterwards
+    pub fn run(&self, vm: &mut Pic14, ticker: &mut impl Ticker) -> (Run, Option<StopCondition>) {
+        let budget = self.budget(vm).unwrap_or(u64::MAX);
+        let run = vm.run_until_within(budget, |vm| self.holds(vm).is_some(), ticker);
+        let fired = self.fired(vm, &run);
+        (run, fired)
+    }
+
+    /// the condition that ended `run`, `None` if something else did
+    pub fn fired(&self, vm: &Pic14, run: &Run) -> Option<StopCondition> {
+        match run.exit {
+            RunExit::Budget => {
+                let budget = self.budget(vm)?;
+                self.0.iter().copied().find(|c| match *c {
+                    StopCondition::Cycles(n) => n == budget,
+                    StopCondition::Time(t) => vm.cycles_in(t) == budget,
+                    _ => false,
+                })
+            }
+            RunExit::Condition => self.holds(vm),
+            _ => None,
+        }
+    }
+}
+
+/// `10ms`, `1.5s`. units are `s`, `ms`, `us` and `ns`
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| c.is_ascii_alphabetic())?;
+    let (value, unit) = s.split_at(split);
+    let value = value.parse::<f64>().ok().filter(|x| *x >= 0.0)?;
+    let secs = match unit {
+        "s" => value,
+        "ms" => value / 1e3,
+        "us" => value / 1e6,
+        "ns" => value / 1e9,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[test]
+fn reports_the_condition_that_fired() {
+    let words: [u16; 4] = [
+        0x0AA0, // 0x0000: incf 0x20, f
+        0x2800, // 0x0001: goto 0x0000
+        0x0000, // 0x0002: nop
+        0x0063, // 0x0003: sleep
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = Pic14::new(flash);
+    let stop = StopConditions(vec![
+        StopCondition::Time(Duration::from_micros(100)),
+        StopCondition::Register { addr: 0x20, value: 5 },
+    ]);
+    let (run, fired) = stop.run(&mut vm, &mut ());
+    assert_eq!(
+        fired,
+        Some(StopCondition::Register { addr: 0x20, value: 5 })
+    );
+    assert_eq!((run.cycles, vm.pc()), (13, 1));
+
+    // 100us at 20 MHz
+    let stop = StopConditions(vec![
+        StopCondition::Cycles(1000),
+        StopCondition::Time(Duration::from_micros(100)),
+    ]);
+    let (run, fired) = stop.run(&mut vm, &mut ());
+    assert_eq!(fired, Some(StopCondition::Time(Duration::from_micros(100))));
+    assert_eq!(run.cycles, 500);
+
+    vm.pc = 2;
+    let stop = StopConditions(vec![StopCondition::Sleep, StopCondition::Cycles(10)]);
+    assert_eq!(stop.run(&mut vm, &mut ()).1, Some(StopCondition::Sleep));
+
+    assert_eq!(parse_duration("1.5ms"), Some(Duration::from_micros(1500)));
+    assert_eq!(parse_duration("10"), None);
+}
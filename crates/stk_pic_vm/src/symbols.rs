@@ -0,0 +1,205 @@
+//! names for program and data addresses, read from the files the toolchain writes next to the
+//! hex, so tools can print `lcd_send_nibble+0x2` instead of `0x0236`
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+
+use crate::inst::{ProgramAddr, RegisterFileAddr};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    code: BTreeMap<ProgramAddr, String>,
+    data: BTreeMap<RegisterFileAddr, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// reads the symbol table section of a pic-as (XC8) `.map` file.
+    ///
+    /// symbols in code psects become code symbols, ones in RAM psects data symbols. compiler
+    /// internals (`?_main`, `__end_of_main`, ...) and absolute values are skipped, and the leading
+    /// `_` of C names is dropped.
+    pub fn from_map(reader: impl BufRead) -> io::Result<Self> {
+        let mut table = Self::new();
+        let mut in_table = false;
+        for line in reader.lines() {
+            let line = line?;
+            if !in_table {
+                in_table = line.trim() == "Symbol Table";
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            // older versions put two symbols on a line
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let entries = words
+                .chunks(3)
+                .map(|w| match *w {
+                    [name, psect, value] => {
+                        Some((name, psect, u16::from_str_radix(value, 16).ok()?))
+                    }
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>();
+            // the next section
+            let Some(entries) = entries else {
+                break;
+            };
+            for (name, psect, value) in entries {
+                let Some(name) = source_name(name) else {
+                    continue;
+                };
+                match psect_kind(psect) {
+                    Some(PsectKind::Code) => table.insert_code(ProgramAddr(value), name),
+                    Some(PsectKind::Data) => {
+                        if let Ok(addr) = u8::try_from(value) {
+                            table.insert_data(RegisterFileAddr(addr), name);
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty() && self.data.is_empty()
+    }
+
+    /// the first name given to an address wins
+    pub fn insert_code(&mut self, addr: ProgramAddr, name: impl Into<String>) {
+        self.code.entry(addr).or_insert_with(|| name.into());
+    }
+
+    /// the first name given to an address wins
+    pub fn insert_data(&mut self, addr: RegisterFileAddr, name: impl Into<String>) {
+        self.data.entry(addr).or_insert_with(|| name.into());
+    }
+
+    /// name of the code at exactly `addr`
+    pub fn code_name(&self, addr: ProgramAddr) -> Option<&str> {
+        self.code.get(&addr).map(|x| x.as_str())
+    }
+
+    /// address of the code called `name`
+    pub fn code_addr(&self, name: &str) -> Option<ProgramAddr> {
+        self.code
+            .iter()
+            .find(|(_, x)| *x == name)
+            .map(|(&addr, _)| addr)
+    }
+
+    /// name of the variable at `addr`. data symbols are linear addresses, so this only finds
+    /// bank 0 and the common area when given the 7 bit operand of an instruction.
+    pub fn data_name(&self, addr: RegisterFileAddr) -> Option<&str> {
+        self.data.get(&addr).map(|x| x.as_str())
+    }
+
+    /// address of the variable called `name`
+    pub fn data_addr(&self, name: &str) -> Option<RegisterFileAddr> {
+        self.data
+            .iter()
+            .find(|(_, x)| *x == name)
+            .map(|(&addr, _)| addr)
+    }
+
+    /// every variable, by address
+    pub fn data(&self) -> impl Iterator<Item = (RegisterFileAddr, &str)> + '_ {
+        self.data.iter().map(|(&addr, name)| (addr, name.as_str()))
+    }
+
+    /// the closest code symbol at or before `addr`, and how far past it `addr` is
+    pub fn locate(&self, addr: ProgramAddr) -> Option<(&str, u16)> {
+        let (at, name) = self.code.range(..=addr).next_back()?;
+        Some((name, addr.0 - at.0))
+    }
+
+    /// `lcd_send_nibble`, `lcd_send_nibble+0x2`, or `0x0236` if nothing is before it
+    pub fn describe(&self, addr: ProgramAddr) -> String {
+        match self.locate(addr) {
+            Some((name, 0)) => name.to_owned(),
+            Some((name, offset)) => format!("{name}+{offset:#x}"),
+            None => format!("{:#06x}", addr.0),
+        }
+    }
+}
+
+/// the name a symbol of the toolchain has in the source, `None` for compiler internals
+pub(crate) fn source_name(symbol: &str) -> Option<&str> {
+    if symbol.starts_with('?') || symbol.starts_with("__") {
+        return None;
+    }
+    Some(symbol.strip_prefix('_').unwrap_or(symbol))
+}
+
+enum PsectKind {
+    Code,
+    Data,
+}
+
+fn psect_kind(psect: &str) -> Option<PsectKind> {
+    const CODE: &[&str] = &[
+        "init",
+        "end_init",
+        "reset_vec",
+        "intentry",
+        "cinit",
+        "powerup",
+        "functab",
+    ];
+    const DATA: &[&str] = &["bss", "cstack", "nv", "BANK", "COMMON"];
+
+    // initial values of data live in program memory
+    if psect.starts_with("idata") {
+        None
+    } else if psect.contains("text") || CODE.contains(&psect) {
+        Some(PsectKind::Code)
+    } else if psect.starts_with("data") || DATA.iter().any(|x| psect.contains(x)) {
+        Some(PsectKind::Data)
+    } else {
+        None
+    }
+}
+
+#[test]
+fn reads_pic_as_map() {
+    let map = "\
+Linker command line:
+
+-pmaintext=07E0h
+
+                                  Symbol Table
+
+?_lcd_send_nibble    cstackCOMMON 0070
+_lcd_send_nibble     text1        0234
+__end_of_main        maintext     07FF
+__Hspace_0           (abs)        0800
+_main                maintext     07E0
+_counter             bssCOMMON    0072
+__pidataBANK0        idataBANK0   0100
+start                init         0000
+
+Function Details:
+";
+    let table = SymbolTable::from_map(map.as_bytes()).unwrap();
+
+    assert_eq!(
+        table.code_name(ProgramAddr(0x0234)),
+        Some("lcd_send_nibble")
+    );
+    assert_eq!(table.code_name(ProgramAddr(0x0000)), Some("start"));
+    assert_eq!(table.code_name(ProgramAddr(0x07FF)), None);
+    assert_eq!(table.data_name(RegisterFileAddr(0x72)), Some("counter"));
+    assert_eq!(table.data_name(RegisterFileAddr(0x70)), None);
+    assert_eq!(table.data_addr("counter"), Some(RegisterFileAddr(0x72)));
+    assert_eq!(table.code.len() + table.data.len(), 4);
+
+    assert_eq!(table.describe(ProgramAddr(0x07E0)), "main");
+    assert_eq!(table.describe(ProgramAddr(0x0236)), "lcd_send_nibble+0x2");
+    assert_eq!(SymbolTable::new().describe(ProgramAddr(0x0236)), "0x0236");
+}
@@ -0,0 +1,92 @@
+//! `stk-pic-vm trace ...`: the NDJSON event logs written by `--ndjson`
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Subcommand;
+use stk_pic_vm::events::Event;
+
+type Result<T, E = Box<dyn Error>> = std::result::Result<T, E>;
+
+#[derive(Subcommand, Debug)]
+pub enum TraceCommand {
+    /// show where two logs part, e.g. a golden run and the current firmware or emulator. logs
+    /// written with `--ndjson-writes` are compared down to register writes. exits with 1 if they
+    /// differ
+    Diff { a: PathBuf, b: PathBuf },
+}
+
+fn load(path: &Path) -> Result<Vec<Event>> {
+    let file = File::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut events = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {e}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line)
+            .map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+fn describe(event: &Event) -> String {
+    let time = Duration::from_nanos(event.time_ns);
+    format!(
+        "cycle {} ({time:?}), pc {:#06x}: {}",
+        event.cycle, event.pc, event.kind
+    )
+}
+
+pub fn run(cmd: TraceCommand) -> Result<ExitCode> {
+    match cmd {
+        TraceCommand::Diff { a, b } => {
+            let (a, b) = (load(&a)?, load(&b)?);
+            let same = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+            if same == a.len() && same == b.len() {
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            println!("the first {same} events match");
+            match (a.get(same), b.get(same)) {
+                (Some(x), Some(y)) => {
+                    println!("a: {}", describe(x));
+                    println!("b: {}", describe(y));
+                    let why = if x.kind == y.kind {
+                        match y.cycle.cmp(&x.cycle) {
+                            std::cmp::Ordering::Greater => {
+                                format!("b is {} cycles late", y.cycle - x.cycle)
+                            }
+                            std::cmp::Ordering::Less => {
+                                format!("b is {} cycles early", x.cycle - y.cycle)
+                            }
+                            std::cmp::Ordering::Equal => "it comes from elsewhere in b".to_owned(),
+                        }
+                    } else if (x.cycle, x.pc) == (y.cycle, y.pc) {
+                        "they differ at the same point".to_owned()
+                    } else if x.cycle <= y.cycle {
+                        "a has an event b doesn't have there".to_owned()
+                    } else {
+                        "b has an event a doesn't have there".to_owned()
+                    };
+                    println!("{why}");
+                }
+                (Some(x), None) => {
+                    println!("b ends there, a goes on with");
+                    println!("a: {}", describe(x));
+                }
+                (None, Some(y)) => {
+                    println!("a ends there, b goes on with");
+                    println!("b: {}", describe(y));
+                }
+                (None, None) => unreachable!(),
+            }
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
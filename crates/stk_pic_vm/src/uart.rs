@@ -0,0 +1,326 @@
+//! the AUSART of the vm connected to the host: bytes the firmware writes to TXREG go out to a
+//! [`Write`] and bytes from a [`Read`] come in through RCREG. the AUSART itself is still a stub,
+//! so there's no baud timing. a byte goes out as soon as TXREG is written, TXIF is set again right
+//! after, and the next byte comes in once the firmware has read the last one and cleared RCIF.
+//! bytes only come in while RCSTA has SPEN and CREN set.
+//!
+//! the host end is stdin/stdout, a TCP port (one client at a time) or, on unix, a pseudo terminal
+//! for terminal programs to open. see [`Host`].
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::vm::device::Slot;
+use crate::vm::pic14::reg::{Sfr, PIR1};
+use crate::vm::pic14::{MemoryAccess, Pic14, Ticker};
+
+/// bits of RCSTA
+const SPEN: u8 = 1 << 7;
+const CREN: u8 = 1 << 4;
+
+/// where the other end of the UART is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Host {
+    /// stdin and stdout
+    Stdio,
+    /// a pseudo terminal, see [`UartBridge::path`]
+    Pty,
+    /// listening on this address, e.g. `127.0.0.1:4000`
+    Tcp(String),
+}
+
+impl FromStr for Host {
+    type Err = String;
+
+    /// `stdio`, `pty` or `tcp:ADDR`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdio" => Ok(Host::Stdio),
+            "pty" => Ok(Host::Pty),
+            _ => match s.strip_prefix("tcp:") {
+                Some(addr) => Ok(Host::Tcp(addr.to_owned())),
+                None => Err(format!("{s}: expected stdio, pty or tcp:ADDR")),
+            },
+        }
+    }
+}
+
+impl Display for Host {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Host::Stdio => write!(f, "stdio"),
+            Host::Pty => write!(f, "pty"),
+            Host::Tcp(addr) => write!(f, "tcp:{addr}"),
+        }
+    }
+}
+
+/// plug in as a [`Ticker`] and call [`Self::service`] before the run, to set TXIF, and between
+/// runs whenever [`Self::needs_service`], the way tickers can't change the vm themselves
+pub struct UartBridge {
+    input: Receiver<u8>,
+    /// arrived from the host, not taken by the firmware yet
+    received: VecDeque<u8>,
+    output: Box<dyn Write + Send>,
+    writes: Vec<MemoryAccess>,
+    /// TXREG was written, so TXIF wants setting again
+    sent: bool,
+    needs_service: bool,
+    /// where the pseudo terminal is, for [`Host::Pty`]
+    path: Option<PathBuf>,
+    /// the pty side terminal programs open, kept open so reads of the other side don't fail while
+    /// none has it open
+    _slave: Option<std::fs::File>,
+}
+
+impl std::fmt::Debug for UartBridge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UartBridge")
+            .field("received", &self.received)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl UartBridge {
+    /// bytes read from `input` on a thread of its own go to the firmware, the ones it sends to
+    /// `output`
+    pub fn new(input: impl Read + Send + 'static, output: impl Write + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                let Ok(byte) = byte else { break };
+                if tx.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            input: rx,
+            received: VecDeque::new(),
+            output: Box::new(output),
+            writes: vec![],
+            sent: false,
+            needs_service: false,
+            path: None,
+            _slave: None,
+        }
+    }
+
+    pub fn open(host: &Host) -> io::Result<Self> {
+        match host {
+            Host::Stdio => Ok(Self::new(io::stdin(), io::stdout())),
+            Host::Tcp(addr) => Self::tcp(addr),
+            Host::Pty => Self::pty(),
+        }
+    }
+
+    /// the first client to connect to `addr` gets the UART, the next one once it leaves. bytes
+    /// sent with nobody connected are dropped
+    pub fn tcp(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let client = Arc::new(Mutex::new(None::<TcpStream>));
+        let (tx, rx) = mpsc::channel();
+        let output = TcpOutput(client.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else { continue };
+                *client.lock().unwrap() = stream.try_clone().ok();
+                for byte in BufReader::new(stream).bytes() {
+                    let Ok(byte) = byte else { break };
+                    if tx.send(byte).is_err() {
+                        return;
+                    }
+                }
+                *client.lock().unwrap() = None;
+            }
+        });
+        let mut bridge = Self::new(io::empty(), output);
+        bridge.input = rx;
+        Ok(bridge)
+    }
+
+    /// a new pseudo terminal in raw mode, see [`Self::path`]
+    #[cfg(unix)]
+    pub fn pty() -> io::Result<Self> {
+        use std::ffi::CStr;
+        use std::fs::{File, OpenOptions};
+        use std::os::fd::{AsRawFd, FromRawFd};
+
+        let check = |r: libc::c_int| {
+            if r < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(r)
+            }
+        };
+        // SAFETY: plain calls on a descriptor this function owns
+        let (master, path) = unsafe {
+            let fd = check(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY))?;
+            let master = File::from_raw_fd(fd);
+            check(libc::grantpt(fd))?;
+            check(libc::unlockpt(fd))?;
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+            (master, path)
+        };
+        let slave = OpenOptions::new().read(true).write(true).open(&path)?;
+        // SAFETY: termios is plain data and filled in by tcgetattr before use
+        unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            check(libc::tcgetattr(slave.as_raw_fd(), &mut termios))?;
+            libc::cfmakeraw(&mut termios);
+            check(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios))?;
+        }
+        let mut bridge = Self::new(master.try_clone()?, master);
+        bridge.path = Some(path);
+        bridge._slave = Some(slave);
+        Ok(bridge)
+    }
+
+    #[cfg(not(unix))]
+    pub fn pty() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "pseudo terminals are only supported on unix",
+        ))
+    }
+
+    /// the pseudo terminal for terminal programs to open, for [`Host::Pty`]
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn needs_service(&self) -> bool {
+        self.needs_service
+    }
+
+    /// sets TXIF after a byte went out and hands the firmware the next byte from the host
+    pub fn service(&mut self, vm: &mut Pic14) {
+        self.needs_service = false;
+        self.sent = false;
+        vm.register.special.pir1_mut().0 |= PIR1::TXIF;
+        if self.can_receive(vm) {
+            if let Some(byte) = self.received.pop_front() {
+                let sp = &mut vm.register.special;
+                sp.rcreg_mut().0 = byte;
+                sp.pir1_mut().0 |= PIR1::RCIF;
+            }
+        }
+    }
+
+    fn can_receive(&self, vm: &Pic14) -> bool {
+        let sp = &vm.register.special;
+        sp.rcsta().0 & (SPEN | CREN) == SPEN | CREN && sp.pir1().0 & PIR1::RCIF == 0
+    }
+}
+
+impl Ticker for UartBridge {
+    fn tick(&mut self, vm: &Pic14, _cycles: u8) {
+        for access in std::mem::take(&mut self.writes) {
+            let slot = vm.device().map[access.bank as usize][access.addr.0 as usize];
+            if slot == Slot::Special(Sfr::TXREG) {
+                // the other end going away shouldn't stop the run
+                let _ = self.output.write_all(&[access.new]);
+                let _ = self.output.flush();
+                self.sent = true;
+            }
+        }
+        if self.received.is_empty() {
+            match self.input.try_recv() {
+                Ok(byte) => self.received.push_back(byte),
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => {}
+            }
+        }
+        let receive = !self.received.is_empty() && self.can_receive(vm);
+        self.needs_service |= self.sent || receive;
+    }
+
+    fn on_write(&mut self, access: MemoryAccess) {
+        self.writes.push(access);
+    }
+}
+
+/// writes to the TCP client, if there is one
+struct TcpOutput(Arc<Mutex<Option<TcpStream>>>);
+
+impl Write for TcpOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(stream) = &mut *self.0.lock().unwrap() {
+            // a client gone away is noticed by the reading side
+            let _ = stream.write_all(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn echoes_through_the_host() {
+    use crate::vm::p16f88::P16F88;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let words: [u16; 5] = [
+        0b01_1110_1000_1100, // 0x0000: btfss PIR1, RCIF
+        0b10_1000_0000_0000, // 0x0001: goto 0x0000
+        0b00_1000_0001_1010, // 0x0002: movf RCREG, w
+        0b00_0000_1001_1001, // 0x0003: movwf TXREG
+        0b10_1000_0000_0000, // 0x0004: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+
+    let mut vm = P16F88::new(flash);
+    let out = Shared::default();
+    let mut uart = UartBridge::new(&b"hi"[..], out.clone());
+    uart.service(&mut vm);
+    assert_eq!(vm.register.special.pir1().0, PIR1::TXIF);
+    // not receiving yet
+    for _ in 0..100 {
+        vm.step(&mut uart).unwrap();
+    }
+    assert!(!uart.needs_service());
+
+    vm.register.special.rcsta_mut().0 = SPEN | CREN;
+    for _ in 0..100_000 {
+        if out.0.lock().unwrap().len() == 2 {
+            break;
+        }
+        vm.step(&mut uart).unwrap();
+        if uart.needs_service() {
+            uart.service(&mut vm);
+        }
+        thread::yield_now();
+    }
+    assert_eq!(*out.0.lock().unwrap(), b"hi");
+    assert_eq!(
+        "tcp:127.0.0.1:4000".parse(),
+        Ok(Host::Tcp("127.0.0.1:4000".into()))
+    );
+}
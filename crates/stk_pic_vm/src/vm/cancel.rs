@@ -0,0 +1,40 @@
+//! cooperative cancellation of long runs. embedders (web worker, scripting hosts) hand a
+//! [`CancelToken`] to [`P16F88::run_until_cancellable`] so a firmware stuck in a loop can't hang
+//! them.
+//!
+//! [`P16F88::run_until_cancellable`]: super::p16f88::P16F88::run_until_cancellable
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// checked by the vm every [`CancelToken::CHECK_INTERVAL`] cycles. clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    /// cycles between checks. reading the host clock on every step is too slow.
+    pub const CHECK_INTERVAL: u64 = 4096;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// also cancels once `budget` of host time has passed since this call.
+    /// `Instant` panics on wasm32-unknown-unknown, so call [`Self::cancel`] from the host there.
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        self.deadline = Some(Instant::now() + budget);
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
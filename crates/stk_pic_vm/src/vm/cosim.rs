@@ -0,0 +1,203 @@
+//! several MCUs in one simulation with their pins wired together, e.g. a software UART TX of
+//! one into RX of another. [`CoSim`] always steps the MCU furthest behind in time, so a level
+//! change reaches the other side within one instruction of when it happened, whatever clocks
+//! they run at.
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use crate::vm::pic14::{Pic14, Pin, RunExit, Ticker, CLOCKS_PER_CYCLE};
+
+/// a pin of one of the MCUs in a [`CoSim`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct McuPin {
+    /// index returned by [`CoSim::add`]
+    pub mcu: usize,
+    pub pin: Pin,
+}
+
+/// why [`CoSim::step`] or [`CoSim::run_for`] stopped early. stepping again continues, as with a
+/// single vm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Halted {
+    pub mcu: usize,
+    /// [`RunExit::Stopped`] or [`RunExit::Error`]
+    pub exit: RunExit,
+}
+
+struct Node {
+    vm: Pic14,
+    /// instruction cycles run, at `vm`'s own clock
+    cycles: u64,
+}
+
+impl Node {
+    /// the time this node has reached is `cycles * CLOCKS_PER_CYCLE / clock_hz`. compare
+    /// without dividing so that it's exact.
+    fn cmp_time(&self, other: &Node) -> Ordering {
+        let a = self.cycles as u128 * other.vm.clock_hz() as u128;
+        let b = other.cycles as u128 * self.vm.clock_hz() as u128;
+        a.cmp(&b)
+    }
+
+    fn elapsed(&self) -> Duration {
+        let clocks = self.cycles as u128 * CLOCKS_PER_CYCLE as u128;
+        let nanos = clocks * 1_000_000_000 / self.vm.clock_hz() as u128;
+        Duration::from_nanos(nanos.try_into().unwrap_or(u64::MAX))
+    }
+}
+
+#[derive(Default)]
+pub struct CoSim {
+    nodes: Vec<Node>,
+    /// (driver, receiver)
+    wires: Vec<(McuPin, McuPin)>,
+}
+
+impl CoSim {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds `vm` at time zero, so add all of them before running. returns its index.
+    pub fn add(&mut self, vm: Pic14) -> usize {
+        self.nodes.push(Node { vm, cycles: 0 });
+        self.nodes.len() - 1
+    }
+
+    pub fn mcu(&self, mcu: usize) -> &Pic14 {
+        &self.nodes[mcu].vm
+    }
+
+    /// changing the clock of an MCU that has already run shifts its time; set it before running
+    pub fn mcu_mut(&mut self, mcu: usize) -> &mut Pic14 {
+        &mut self.nodes[mcu].vm
+    }
+
+    /// makes `to` follow `from`: driven to its level while `from` is an output, released while
+    /// it's an input. connect both ways for a line either side can drive.
+    pub fn connect(&mut self, from: McuPin, to: McuPin) {
+        self.wires.push((from, to));
+        self.propagate(from.mcu);
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// instruction cycles `mcu` has run
+    pub fn cycles(&self, mcu: usize) -> u64 {
+        self.nodes[mcu].cycles
+    }
+
+    /// simulated time `mcu` has reached
+    pub fn elapsed(&self, mcu: usize) -> Duration {
+        self.nodes[mcu].elapsed()
+    }
+
+    /// simulated time every MCU has reached
+    pub fn now(&self) -> Duration {
+        self.behind()
+            .map_or(Duration::ZERO, |i| self.nodes[i].elapsed())
+    }
+
+    /// the MCU furthest behind, the lowest index on ties
+    fn behind(&self) -> Option<usize> {
+        (0..self.nodes.len()).min_by(|a, b| self.nodes[*a].cmp_time(&self.nodes[*b]))
+    }
+
+    /// executes one instruction on the MCU furthest behind and updates the pins it drives.
+    /// returns which MCU stepped. `ticker` is shared by all of them.
+    pub fn step(&mut self, ticker: &mut impl Ticker) -> Result<usize, Halted> {
+        let mcu = self.behind().expect("no MCU to step");
+        let node = &mut self.nodes[mcu];
+        let run = node.vm.run_for_cycles(1, ticker);
+        node.cycles += run.cycles;
+        self.propagate(mcu);
+        match run.exit {
+            RunExit::Budget => Ok(mcu),
+            exit => Err(Halted { mcu, exit }),
+        }
+    }
+
+    /// steps until every MCU has reached `duration` from the start
+    pub fn run_for(&mut self, duration: Duration, ticker: &mut impl Ticker) -> Result<(), Halted> {
+        while self.now() < duration {
+            self.step(ticker)?;
+        }
+        Ok(())
+    }
+
+    fn propagate(&mut self, mcu: usize) {
+        let Self { nodes, wires } = self;
+        for (from, to) in wires.iter().filter(|(from, _)| from.mcu == mcu) {
+            let level = nodes[from.mcu].vm.pin_output(from.pin);
+            let receiver = &mut nodes[to.mcu].vm;
+            match level {
+                Some(level) => receiver.set_pin_input(to.pin, level),
+                None => receiver.release_pin_input(to.pin),
+            }
+        }
+    }
+}
+
+/// assembles `words` into a PIC16F88
+#[cfg(test)]
+fn test_vm(words: &[u16], clock_hz: u64) -> Pic14 {
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    let mut vm = Pic14::new(flash);
+    vm.set_clock_hz(clock_hz);
+    vm
+}
+
+#[test]
+fn wires_follow_the_driver_across_clocks() {
+    let master = test_vm(
+        &[
+            0x1683, // bsf STATUS, RP0
+            0x1006, // bcf TRISB, 0
+            0x1283, // bcf STATUS, RP0
+            0x1406, // bsf PORTB, 0
+            0x2804, // goto 0x0004
+        ],
+        20_000_000,
+    );
+    // goto 0x0000
+    let worker = test_vm(&[0x2800], 4_000_000);
+
+    let mut sim = CoSim::new();
+    let m = sim.add(master);
+    let w = sim.add(worker);
+    sim.connect(
+        McuPin { mcu: m, pin: Pin::rb(0) },
+        McuPin { mcu: w, pin: Pin::ra(2) },
+    );
+    let input = |sim: &CoSim| {
+        let porta = sim.mcu(w).register.special.porta();
+        (porta.driven & 0b100 != 0).then_some(porta.input & 0b100 != 0)
+    };
+    // RB0 is still an input
+    assert_eq!(input(&sim), None);
+
+    // at 200 ns per cycle, RB0 turns into a low output at 400 ns and goes high at 800 ns
+    sim.run_for(Duration::from_nanos(500), &mut ()).unwrap();
+    assert_eq!(input(&sim), Some(false));
+    sim.run_for(Duration::from_micros(10), &mut ()).unwrap();
+    assert_eq!(input(&sim), Some(true));
+
+    // both have reached 10 us, and neither is ahead by more than a 2-cycle instruction
+    for (mcu, cycle) in [
+        (m, Duration::from_nanos(200)),
+        (w, Duration::from_micros(1)),
+    ] {
+        let t = sim.elapsed(mcu);
+        assert!(Duration::from_micros(10) <= t && t <= Duration::from_micros(10) + 2 * cycle);
+    }
+}
@@ -0,0 +1,798 @@
+//! mid-range PIC devices [`Pic14`](super::pic14::Pic14) can run. they share the core and differ
+//! in flash size and in how the register file is laid out.
+
+use crate::inst::RegisterFileAddr;
+use crate::vm::pic14::reg::Sfr;
+
+// datasheets:
+//   - PIC16F84A: https://ww1.microchip.com/downloads/en/devicedoc/35007c.pdf
+//   - PIC16F877A: https://ww1.microchip.com/downloads/en/devicedoc/39582b.pdf
+//   - PIC12F675: DS41190 (PIC12F629/675)
+
+/// word address of the configuration word. devices with two have the second at 0x2008.
+pub const CONFIG_WORD_ADDR: u16 = 0x2007;
+
+#[derive(Debug)]
+pub struct Device {
+    pub name: &'static str,
+    /// size of the flash image, two bytes per instruction word
+    pub flash_bytes: usize,
+    pub map: &'static RegisterMap,
+    /// bytes of RAM, the `gpr[..]` of `map` index into them
+    pub gprs: usize,
+    /// return addresses the hardware stack holds
+    pub stack_depth: usize,
+    pub adc: AdcLayout,
+    /// implemented bits of the configuration word. an erased word has all of them set.
+    pub config_mask: u16,
+    /// PORTA outputs that can only pull low, RA4 on the older parts. they float while their latch
+    /// bit is set
+    pub porta_open_drain: u8,
+    /// bytes of data EEPROM
+    pub eeprom_bytes: usize,
+    /// the flag set when an EEPROM write finishes, EEIF, and the register it is in
+    pub eeif: (Sfr, u8),
+}
+
+/// where the A/D converter keeps its control bits. devices without one just leave ADCON0
+/// unmapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdcLayout {
+    /// ADCON0 holds ADCS1:0, CHS2:0, GO and ADON. ADCON1 holds ADFM and ADCS2.
+    Adcon1,
+    /// ADCON0 holds ADFM, CHS1:0, GO and ADON. ANSEL holds ADCS2:0.
+    Ansel,
+}
+
+/// what each address of each bank refers to
+pub type RegisterMap = [[Slot; 0x80]; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Special(Sfr),
+    /// index into [`Registers::gpr`](super::pic14::reg::Registers::gpr). the same index in
+    /// several places is a mirror.
+    Gpr(u16),
+}
+
+impl Slot {
+    pub fn name(self) -> String {
+        match self {
+            Slot::Special(sfr) => sfr.name().to_owned(),
+            Slot::Gpr(index) => format!("gpr[{index}]"),
+        }
+    }
+}
+
+impl Device {
+    /// what `addr` refers to in each bank, without duplicates
+    pub fn register_names(&self, addr: RegisterFileAddr) -> Vec<String> {
+        let mut names = vec![];
+        for bank in self.map {
+            let name = bank[addr.0 as usize].name();
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// linear address of the SFR called `name` (any case), the first one for a mirrored SFR
+    pub fn sfr_addr(&self, name: &str) -> Option<u16> {
+        let name = name.to_ascii_lowercase();
+        self.map
+            .iter()
+            .flatten()
+            .position(|slot| matches!(slot, Slot::Special(sfr) if sfr.name() == name))
+            .map(|at| at as u16)
+    }
+
+    /// configuration word programmed in a decoded hex `image`, if it has one
+    pub fn config_word_in(&self, image: &[u8]) -> Option<u16> {
+        let at = CONFIG_WORD_ADDR as usize * 2;
+        let bytes = image.get(at..at + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]) & self.config_mask)
+    }
+}
+
+macro_rules! register_map {
+    ($($addr:literal $bank0:ident$([$index0:literal])? $bank1:ident$([$index1:literal])? $bank2:ident$([$index2:literal])? $bank3:ident$([$index3:literal])?)+) => {{
+    let mut map = [[Slot::Special(Sfr::UNIMPL); 0x80]; 4];
+        $(
+            map[0][$addr] = register_map!(@slot $bank0$([$index0])?);
+            map[1][$addr] = register_map!(@slot $bank1$([$index1])?);
+            map[2][$addr] = register_map!(@slot $bank2$([$index2])?);
+            map[3][$addr] = register_map!(@slot $bank3$([$index3])?);
+        )+
+        map
+    }};
+
+    (@slot gpr[$index:literal]) => { Slot::Gpr($index) };
+    (@slot $name:ident) => { Slot::Special(Sfr::$name) };
+}
+
+/// 1K words of flash, 68 bytes of RAM and only two banks. RP1 is meant to be kept clear, so
+/// banks 2 and 3 just mirror 0 and 1.
+pub static P16F84A: Device = Device {
+    name: "PIC16F84A",
+    flash_bytes: 1024 * 2,
+    map: &P16F84A_MAP,
+    gprs: 68,
+    stack_depth: 8,
+    adc: AdcLayout::Adcon1,
+    config_mask: 0x3FFF,
+    porta_open_drain: 1 << 4,
+    eeprom_bytes: 64,
+    eeif: (Sfr::EECON1, 1 << 4),
+};
+
+pub static P16F88: Device = Device {
+    name: "PIC16F88",
+    flash_bytes: super::p16f88::FLASH_BYTES,
+    map: &P16F88_MAP,
+    gprs: 368,
+    stack_depth: 8,
+    adc: AdcLayout::Adcon1,
+    config_mask: 0x3FFF,
+    porta_open_drain: 0,
+    eeprom_bytes: 256,
+    eeif: (Sfr::PIR2, 1 << 4),
+};
+
+/// 8K words of flash and the same RAM layout as the PIC16F88, plus ports C to E.
+pub static P16F877A: Device = Device {
+    name: "PIC16F877A",
+    flash_bytes: 8192 * 2,
+    map: &P16F877A_MAP,
+    gprs: 368,
+    stack_depth: 8,
+    adc: AdcLayout::Adcon1,
+    config_mask: 0x2FCF,
+    porta_open_drain: 1 << 4,
+    eeprom_bytes: 256,
+    eeif: (Sfr::PIR2, 1 << 4),
+};
+
+/// 8-pin, 1K words of flash and 64 bytes of RAM shared by both banks. the only port is the
+/// 6-bit GPIO, and the A/D converter has 4 channels (see [`AdcLayout::Ansel`]).
+pub static P12F675: Device = Device {
+    name: "PIC12F675",
+    flash_bytes: 1024 * 2,
+    map: &P12F675_MAP,
+    gprs: 64,
+    stack_depth: 8,
+    adc: AdcLayout::Ansel,
+    config_mask: P12F675_CONFIG::all().bits(),
+    porta_open_drain: 0,
+    eeprom_bytes: 128,
+    eeif: (Sfr::PIR1, 1 << 7),
+};
+
+bitflags::bitflags! {
+    /// configuration word of the PIC12F675
+    #[allow(non_camel_case_types)]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct P12F675_CONFIG: u16 {
+        /// band gap calibration, programmed at the factory
+        const BG    = 0b11 << 12;
+        /// data EEPROM code protection, active low
+        const CPD   = 1 << 8;
+        /// program memory code protection, active low
+        const CP    = 1 << 7;
+        const BODEN = 1 << 6;
+        /// GP3 is MCLR instead of an input
+        const MCLRE = 1 << 5;
+        /// power-up timer, active low
+        const PWRTE = 1 << 4;
+        const WDTE  = 1 << 3;
+        /// oscillator selection. 0b100 and 0b101 are the internal oscillator, leaving GP4 and
+        /// GP5 as I/O
+        const FOSC  = 0b111;
+    }
+}
+
+#[rustfmt::skip]
+static P16F84A_MAP: RegisterMap = register_map! {
+    // bank 0    1          2       3
+    0x00 IADDR   IADDR      IADDR   IADDR
+    0x01 TMR0    OPTION_REG TMR0    OPTION_REG
+    0x02 PCL     PCL        PCL     PCL
+    0x03 STATUS  STATUS     STATUS  STATUS
+    0x04 FSR     FSR        FSR     FSR
+    0x05 PORTA   TRISA      PORTA   TRISA
+    0x06 PORTB   TRISB      PORTB   TRISB
+    0x07 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x08 EEDATA  EECON1     EEDATA  EECON1
+    0x09 EEADR   EECON2     EEADR   EECON2
+    0x0A PCLATH  PCLATH     PCLATH  PCLATH
+    0x0B INTCON  INTCON     INTCON  INTCON
+    0x0C gpr[0]  gpr[0]     gpr[0]  gpr[0]
+    0x0D gpr[1]  gpr[1]     gpr[1]  gpr[1]
+    0x0E gpr[2]  gpr[2]     gpr[2]  gpr[2]
+    0x0F gpr[3]  gpr[3]     gpr[3]  gpr[3]
+    0x10 gpr[4]  gpr[4]     gpr[4]  gpr[4]
+    0x11 gpr[5]  gpr[5]     gpr[5]  gpr[5]
+    0x12 gpr[6]  gpr[6]     gpr[6]  gpr[6]
+    0x13 gpr[7]  gpr[7]     gpr[7]  gpr[7]
+    0x14 gpr[8]  gpr[8]     gpr[8]  gpr[8]
+    0x15 gpr[9]  gpr[9]     gpr[9]  gpr[9]
+    0x16 gpr[10] gpr[10]    gpr[10] gpr[10]
+    0x17 gpr[11] gpr[11]    gpr[11] gpr[11]
+    0x18 gpr[12] gpr[12]    gpr[12] gpr[12]
+    0x19 gpr[13] gpr[13]    gpr[13] gpr[13]
+    0x1A gpr[14] gpr[14]    gpr[14] gpr[14]
+    0x1B gpr[15] gpr[15]    gpr[15] gpr[15]
+    0x1C gpr[16] gpr[16]    gpr[16] gpr[16]
+    0x1D gpr[17] gpr[17]    gpr[17] gpr[17]
+    0x1E gpr[18] gpr[18]    gpr[18] gpr[18]
+    0x1F gpr[19] gpr[19]    gpr[19] gpr[19]
+    0x20 gpr[20] gpr[20]    gpr[20] gpr[20]
+    0x21 gpr[21] gpr[21]    gpr[21] gpr[21]
+    0x22 gpr[22] gpr[22]    gpr[22] gpr[22]
+    0x23 gpr[23] gpr[23]    gpr[23] gpr[23]
+    0x24 gpr[24] gpr[24]    gpr[24] gpr[24]
+    0x25 gpr[25] gpr[25]    gpr[25] gpr[25]
+    0x26 gpr[26] gpr[26]    gpr[26] gpr[26]
+    0x27 gpr[27] gpr[27]    gpr[27] gpr[27]
+    0x28 gpr[28] gpr[28]    gpr[28] gpr[28]
+    0x29 gpr[29] gpr[29]    gpr[29] gpr[29]
+    0x2A gpr[30] gpr[30]    gpr[30] gpr[30]
+    0x2B gpr[31] gpr[31]    gpr[31] gpr[31]
+    0x2C gpr[32] gpr[32]    gpr[32] gpr[32]
+    0x2D gpr[33] gpr[33]    gpr[33] gpr[33]
+    0x2E gpr[34] gpr[34]    gpr[34] gpr[34]
+    0x2F gpr[35] gpr[35]    gpr[35] gpr[35]
+    0x30 gpr[36] gpr[36]    gpr[36] gpr[36]
+    0x31 gpr[37] gpr[37]    gpr[37] gpr[37]
+    0x32 gpr[38] gpr[38]    gpr[38] gpr[38]
+    0x33 gpr[39] gpr[39]    gpr[39] gpr[39]
+    0x34 gpr[40] gpr[40]    gpr[40] gpr[40]
+    0x35 gpr[41] gpr[41]    gpr[41] gpr[41]
+    0x36 gpr[42] gpr[42]    gpr[42] gpr[42]
+    0x37 gpr[43] gpr[43]    gpr[43] gpr[43]
+    0x38 gpr[44] gpr[44]    gpr[44] gpr[44]
+    0x39 gpr[45] gpr[45]    gpr[45] gpr[45]
+    0x3A gpr[46] gpr[46]    gpr[46] gpr[46]
+    0x3B gpr[47] gpr[47]    gpr[47] gpr[47]
+    0x3C gpr[48] gpr[48]    gpr[48] gpr[48]
+    0x3D gpr[49] gpr[49]    gpr[49] gpr[49]
+    0x3E gpr[50] gpr[50]    gpr[50] gpr[50]
+    0x3F gpr[51] gpr[51]    gpr[51] gpr[51]
+    0x40 gpr[52] gpr[52]    gpr[52] gpr[52]
+    0x41 gpr[53] gpr[53]    gpr[53] gpr[53]
+    0x42 gpr[54] gpr[54]    gpr[54] gpr[54]
+    0x43 gpr[55] gpr[55]    gpr[55] gpr[55]
+    0x44 gpr[56] gpr[56]    gpr[56] gpr[56]
+    0x45 gpr[57] gpr[57]    gpr[57] gpr[57]
+    0x46 gpr[58] gpr[58]    gpr[58] gpr[58]
+    0x47 gpr[59] gpr[59]    gpr[59] gpr[59]
+    0x48 gpr[60] gpr[60]    gpr[60] gpr[60]
+    0x49 gpr[61] gpr[61]    gpr[61] gpr[61]
+    0x4A gpr[62] gpr[62]    gpr[62] gpr[62]
+    0x4B gpr[63] gpr[63]    gpr[63] gpr[63]
+    0x4C gpr[64] gpr[64]    gpr[64] gpr[64]
+    0x4D gpr[65] gpr[65]    gpr[65] gpr[65]
+    0x4E gpr[66] gpr[66]    gpr[66] gpr[66]
+    0x4F gpr[67] gpr[67]    gpr[67] gpr[67]
+    0x50 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x51 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x52 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x53 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x54 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x55 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x56 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x57 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x58 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x59 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x5A UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x5B UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x5C UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x5D UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x5E UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x5F UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x60 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x61 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x62 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x63 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x64 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x65 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x66 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x67 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x68 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x69 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6A UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6B UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6C UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6D UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6E UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6F UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x70 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x71 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x72 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x73 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x74 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x75 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x76 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x77 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x78 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x79 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7A UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7B UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7C UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7D UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7E UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7F UNIMPL  UNIMPL     UNIMPL  UNIMPL
+};
+
+#[rustfmt::skip]
+static P16F88_MAP: RegisterMap = register_map! {
+    // bank 0    1          2        3
+    0x00 IADDR   IADDR      IADDR    IADDR
+    0x01 TMR0    OPTION_REG TMR0     OPTION_REG
+    0x02 PCL     PCL        PCL      PCL
+    0x03 STATUS  STATUS     STATUS   STATUS
+    0x04 FSR     FSR        FSR      FSR
+    0x05 PORTA   TRISA      WDTCON   UNIMPL
+    0x06 PORTB   TRISB      PORTB    TRISB
+    0x07 UNIMPL  UNIMPL     UNIMPL   UNIMPL
+    0x08 UNIMPL  UNIMPL     UNIMPL   UNIMPL
+    0x09 UNIMPL  UNIMPL     UNIMPL   UNIMPL
+    0x0A PCLATH  PCLATH     PCLATH   PCLATH
+    0x0B INTCON  INTCON     INTCON   INTCON
+    0x0C PIR1    PIE1       EEDATA   EECON1
+    0x0D PIR2    PIE2       EEADR    EECON2
+    0x0E TMR1L   PCON       EEDATH   RESERV
+    0x0F TMR1H   OSCCON     EEADRH   RESERV
+    0x10 T1CON   OSCTUNE    gpr[176] gpr[272]
+    0x11 TMR2    UNIMPL     gpr[177] gpr[273]
+    0x12 T2CON   PR2        gpr[178] gpr[274]
+    0x13 SSPBUF  SSPADD     gpr[179] gpr[275]
+    0x14 SSPCON  SSPSTAT    gpr[180] gpr[276]
+    0x15 CCPR1L  UNIMPL     gpr[181] gpr[277]
+    0x16 CCPR1H  UNIMPL     gpr[182] gpr[278]
+    0x17 CCP1CON UNIMPL     gpr[183] gpr[279]
+    0x18 RCSTA   TXSTA      gpr[184] gpr[280]
+    0x19 TXREG   SPBRG      gpr[185] gpr[281]
+    0x1A RCREG   UNIMPL     gpr[186] gpr[282]
+    0x1B UNIMPL  ANSEL      gpr[187] gpr[283]
+    0x1C UNIMPL  CMCON      gpr[188] gpr[284]
+    0x1D UNIMPL  CVRCON     gpr[189] gpr[285]
+    0x1E UNIMPL  UNIMPL     gpr[190] gpr[286]
+    0x1F UNIMPL  UNIMPL     gpr[191] gpr[287]
+    0x20 gpr[0]  gpr[96]    gpr[192] gpr[288]
+    0x21 gpr[1]  gpr[97]    gpr[193] gpr[289]
+    0x22 gpr[2]  gpr[98]    gpr[194] gpr[290]
+    0x23 gpr[3]  gpr[99]    gpr[195] gpr[291]
+    0x24 gpr[4]  gpr[100]   gpr[196] gpr[292]
+    0x25 gpr[5]  gpr[101]   gpr[197] gpr[293]
+    0x26 gpr[6]  gpr[102]   gpr[198] gpr[294]
+    0x27 gpr[7]  gpr[103]   gpr[199] gpr[295]
+    0x28 gpr[8]  gpr[104]   gpr[200] gpr[296]
+    0x29 gpr[9]  gpr[105]   gpr[201] gpr[297]
+    0x2A gpr[10] gpr[106]   gpr[202] gpr[298]
+    0x2B gpr[11] gpr[107]   gpr[203] gpr[299]
+    0x2C gpr[12] gpr[108]   gpr[204] gpr[300]
+    0x2D gpr[13] gpr[109]   gpr[205] gpr[301]
+    0x2E gpr[14] gpr[110]   gpr[206] gpr[302]
+    0x2F gpr[15] gpr[111]   gpr[207] gpr[303]
+    0x30 gpr[16] gpr[112]   gpr[208] gpr[304]
+    0x31 gpr[17] gpr[113]   gpr[209] gpr[305]
+    0x32 gpr[18] gpr[114]   gpr[210] gpr[306]
+    0x33 gpr[19] gpr[115]   gpr[211] gpr[307]
+    0x34 gpr[20] gpr[116]   gpr[212] gpr[308]
+    0x35 gpr[21] gpr[117]   gpr[213] gpr[309]
+    0x36 gpr[22] gpr[118]   gpr[214] gpr[310]
+    0x37 gpr[23] gpr[119]   gpr[215] gpr[311]
+    0x38 gpr[24] gpr[120]   gpr[216] gpr[312]
+    0x39 gpr[25] gpr[121]   gpr[217] gpr[313]
+    0x3A gpr[26] gpr[122]   gpr[218] gpr[314]
+    0x3B gpr[27] gpr[123]   gpr[219] gpr[315]
+    0x3C gpr[28] gpr[124]   gpr[220] gpr[316]
+    0x3D gpr[29] gpr[125]   gpr[221] gpr[317]
+    0x3E gpr[30] gpr[126]   gpr[222] gpr[318]
+    0x3F gpr[31] gpr[127]   gpr[223] gpr[319]
+    0x40 gpr[32] gpr[128]   gpr[224] gpr[320]
+    0x41 gpr[33] gpr[129]   gpr[225] gpr[321]
+    0x42 gpr[34] gpr[130]   gpr[226] gpr[322]
+    0x43 gpr[35] gpr[131]   gpr[227] gpr[323]
+    0x44 gpr[36] gpr[132]   gpr[228] gpr[324]
+    0x45 gpr[37] gpr[133]   gpr[229] gpr[325]
+    0x46 gpr[38] gpr[134]   gpr[230] gpr[326]
+    0x47 gpr[39] gpr[135]   gpr[231] gpr[327]
+    0x48 gpr[40] gpr[136]   gpr[232] gpr[328]
+    0x49 gpr[41] gpr[137]   gpr[233] gpr[329]
+    0x4A gpr[42] gpr[138]   gpr[234] gpr[330]
+    0x4B gpr[43] gpr[139]   gpr[235] gpr[331]
+    0x4C gpr[44] gpr[140]   gpr[236] gpr[332]
+    0x4D gpr[45] gpr[141]   gpr[237] gpr[333]
+    0x4E gpr[46] gpr[142]   gpr[238] gpr[334]
+    0x4F gpr[47] gpr[143]   gpr[239] gpr[335]
+    0x50 gpr[48] gpr[144]   gpr[240] gpr[336]
+    0x51 gpr[49] gpr[145]   gpr[241] gpr[337]
+    0x52 gpr[50] gpr[146]   gpr[242] gpr[338]
+    0x53 gpr[51] gpr[147]   gpr[243] gpr[339]
+    0x54 gpr[52] gpr[148]   gpr[244] gpr[340]
+    0x55 gpr[53] gpr[149]   gpr[245] gpr[341]
+    0x56 gpr[54] gpr[150]   gpr[246] gpr[342]
+    0x57 gpr[55] gpr[151]   gpr[247] gpr[343]
+    0x58 gpr[56] gpr[152]   gpr[248] gpr[344]
+    0x59 gpr[57] gpr[153]   gpr[249] gpr[345]
+    0x5A gpr[58] gpr[154]   gpr[250] gpr[346]
+    0x5B gpr[59] gpr[155]   gpr[251] gpr[347]
+    0x5C gpr[60] gpr[156]   gpr[252] gpr[348]
+    0x5D gpr[61] gpr[157]   gpr[253] gpr[349]
+    0x5E gpr[62] gpr[158]   gpr[254] gpr[350]
+    0x5F gpr[63] gpr[159]   gpr[255] gpr[351]
+    0x60 gpr[64] gpr[160]   gpr[256] gpr[352]
+    0x61 gpr[65] gpr[161]   gpr[257] gpr[353]
+    0x62 gpr[66] gpr[162]   gpr[258] gpr[354]
+    0x63 gpr[67] gpr[163]   gpr[259] gpr[355]
+    0x64 gpr[68] gpr[164]   gpr[260] gpr[356]
+    0x65 gpr[69] gpr[165]   gpr[261] gpr[357]
+    0x66 gpr[70] gpr[166]   gpr[262] gpr[358]
+    0x67 gpr[71] gpr[167]   gpr[263] gpr[359]
+    0x68 gpr[72] gpr[168]   gpr[264] gpr[360]
+    0x69 gpr[73] gpr[169]   gpr[265] gpr[361]
+    0x6A gpr[74] gpr[170]   gpr[266] gpr[362]
+    0x6B gpr[75] gpr[171]   gpr[267] gpr[363]
+    0x6C gpr[76] gpr[172]   gpr[268] gpr[364]
+    0x6D gpr[77] gpr[173]   gpr[269] gpr[365]
+    0x6E gpr[78] gpr[174]   gpr[270] gpr[366]
+    0x6F gpr[79] gpr[175]   gpr[271] gpr[367]
+    0x70 gpr[80] gpr[80]    gpr[80]  gpr[80]  // `accesses`
+    0x71 gpr[81] gpr[81]    gpr[81]  gpr[81]
+    0x72 gpr[82] gpr[82]    gpr[82]  gpr[82]
+    0x73 gpr[83] gpr[83]    gpr[83]  gpr[83]
+    0x74 gpr[84] gpr[84]    gpr[84]  gpr[84]
+    0x75 gpr[85] gpr[85]    gpr[85]  gpr[85]
+    0x76 gpr[86] gpr[86]    gpr[86]  gpr[86]
+    0x77 gpr[87] gpr[87]    gpr[87]  gpr[87]
+    0x78 gpr[88] gpr[88]    gpr[88]  gpr[88]
+    0x79 gpr[89] gpr[89]    gpr[89]  gpr[89]
+    0x7A gpr[90] gpr[90]    gpr[90]  gpr[90]
+    0x7B gpr[91] gpr[91]    gpr[91]  gpr[91]
+    0x7C gpr[92] gpr[92]    gpr[92]  gpr[92]
+    0x7D gpr[93] gpr[93]    gpr[93]  gpr[93]
+    0x7E gpr[94] gpr[94]    gpr[94]  gpr[94]
+    0x7F gpr[95] gpr[95]    gpr[95]  gpr[95]
+};
+
+#[rustfmt::skip]
+static P16F877A_MAP: RegisterMap = register_map! {
+    // bank 0    1          2        3
+    0x00 IADDR   IADDR      IADDR    IADDR
+    0x01 TMR0    OPTION_REG TMR0     OPTION_REG
+    0x02 PCL     PCL        PCL      PCL
+    0x03 STATUS  STATUS     STATUS   STATUS
+    0x04 FSR     FSR        FSR      FSR
+    0x05 PORTA   TRISA      UNIMPL   UNIMPL
+    0x06 PORTB   TRISB      PORTB    TRISB
+    0x07 PORTC   TRISC      UNIMPL   UNIMPL
+    0x08 PORTD   TRISD      UNIMPL   UNIMPL
+    0x09 PORTE   TRISE      UNIMPL   UNIMPL
+    0x0A PCLATH  PCLATH     PCLATH   PCLATH
+    0x0B INTCON  INTCON     INTCON   INTCON
+    0x0C PIR1    PIE1       EEDATA   EECON1
+    0x0D PIR2    PIE2       EEADR    EECON2
+    0x0E TMR1L   PCON       EEDATH   RESERV
+    0x0F TMR1H   UNIMPL     EEADRH   RESERV
+    0x10 T1CON   UNIMPL     gpr[176] gpr[272]
+    0x11 TMR2    SSPCON2    gpr[177] gpr[273]
+    0x12 T2CON   PR2        gpr[178] gpr[274]
+    0x13 SSPBUF  SSPADD     gpr[179] gpr[275]
+    0x14 SSPCON  SSPSTAT    gpr[180] gpr[276]
+    0x15 CCPR1L  UNIMPL     gpr[181] gpr[277]
+    0x16 CCPR1H  UNIMPL     gpr[182] gpr[278]
+    0x17 CCP1CON UNIMPL     gpr[183] gpr[279]
+    0x18 RCSTA   TXSTA      gpr[184] gpr[280]
+    0x19 TXREG   SPBRG      gpr[185] gpr[281]
+    0x1A RCREG   UNIMPL     gpr[186] gpr[282]
+    0x1B CCPR2L  UNIMPL     gpr[187] gpr[283]
+    0x1C CCPR2H  CMCON      gpr[188] gpr[284]
+    0x1D CCP2CON CVRCON     gpr[189] gpr[285]
+    0x1E ADRESH  ADRESL     gpr[190] gpr[286]
+    0x1F ADCON0  ADCON1     gpr[191] gpr[287]
+    0x20 gpr[0]  gpr[96]    gpr[192] gpr[288]
+    0x21 gpr[1]  gpr[97]    gpr[193] gpr[289]
+    0x22 gpr[2]  gpr[98]    gpr[194] gpr[290]
+    0x23 gpr[3]  gpr[99]    gpr[195] gpr[291]
+    0x24 gpr[4]  gpr[100]   gpr[196] gpr[292]
+    0x25 gpr[5]  gpr[101]   gpr[197] gpr[293]
+    0x26 gpr[6]  gpr[102]   gpr[198] gpr[294]
+    0x27 gpr[7]  gpr[103]   gpr[199] gpr[295]
+    0x28 gpr[8]  gpr[104]   gpr[200] gpr[296]
+    0x29 gpr[9]  gpr[105]   gpr[201] gpr[297]
+    0x2A gpr[10] gpr[106]   gpr[202] gpr[298]
+    0x2B gpr[11] gpr[107]   gpr[203] gpr[299]
+    0x2C gpr[12] gpr[108]   gpr[204] gpr[300]
+    0x2D gpr[13] gpr[109]   gpr[205] gpr[301]
+    0x2E gpr[14] gpr[110]   gpr[206] gpr[302]
+    0x2F gpr[15] gpr[111]   gpr[207] gpr[303]
+    0x30 gpr[16] gpr[112]   gpr[208] gpr[304]
+    0x31 gpr[17] gpr[113]   gpr[209] gpr[305]
+    0x32 gpr[18] gpr[114]   gpr[210] gpr[306]
+    0x33 gpr[19] gpr[115]   gpr[211] gpr[307]
+    0x34 gpr[20] gpr[116]   gpr[212] gpr[308]
+    0x35 gpr[21] gpr[117]   gpr[213] gpr[309]
+    0x36 gpr[22] gpr[118]   gpr[214] gpr[310]
+    0x37 gpr[23] gpr[119]   gpr[215] gpr[311]
+    0x38 gpr[24] gpr[120]   gpr[216] gpr[312]
+    0x39 gpr[25] gpr[121]   gpr[217] gpr[313]
+    0x3A gpr[26] gpr[122]   gpr[218] gpr[314]
+    0x3B gpr[27] gpr[123]   gpr[219] gpr[315]
+    0x3C gpr[28] gpr[124]   gpr[220] gpr[316]
+    0x3D gpr[29] gpr[125]   gpr[221] gpr[317]
+    0x3E gpr[30] gpr[126]   gpr[222] gpr[318]
+    0x3F gpr[31] gpr[127]   gpr[223] gpr[319]
+    0x40 gpr[32] gpr[128]   gpr[224] gpr[320]
+    0x41 gpr[33] gpr[129]   gpr[225] gpr[321]
+    0x42 gpr[34] gpr[130]   gpr[226] gpr[322]
+    0x43 gpr[35] gpr[131]   gpr[227] gpr[323]
+    0x44 gpr[36] gpr[132]   gpr[228] gpr[324]
+    0x45 gpr[37] gpr[133]   gpr[229] gpr[325]
+    0x46 gpr[38] gpr[134]   gpr[230] gpr[326]
+    0x47 gpr[39] gpr[135]   gpr[231] gpr[327]
+    0x48 gpr[40] gpr[136]   gpr[232] gpr[328]
+    0x49 gpr[41] gpr[137]   gpr[233] gpr[329]
+    0x4A gpr[42] gpr[138]   gpr[234] gpr[330]
+    0x4B gpr[43] gpr[139]   gpr[235] gpr[331]
+    0x4C gpr[44] gpr[140]   gpr[236] gpr[332]
+    0x4D gpr[45] gpr[141]   gpr[237] gpr[333]
+    0x4E gpr[46] gpr[142]   gpr[238] gpr[334]
+    0x4F gpr[47] gpr[143]   gpr[239] gpr[335]
+    0x50 gpr[48] gpr[144]   gpr[240] gpr[336]
+    0x51 gpr[49] gpr[145]   gpr[241] gpr[337]
+    0x52 gpr[50] gpr[146]   gpr[242] gpr[338]
+    0x53 gpr[51] gpr[147]   gpr[243] gpr[339]
+    0x54 gpr[52] gpr[148]   gpr[244] gpr[340]
+    0x55 gpr[53] gpr[149]   gpr[245] gpr[341]
+    0x56 gpr[54] gpr[150]   gpr[246] gpr[342]
+    0x57 gpr[55] gpr[151]   gpr[247] gpr[343]
+    0x58 gpr[56] gpr[152]   gpr[248] gpr[344]
+    0x59 gpr[57] gpr[153]   gpr[249] gpr[345]
+    0x5A gpr[58] gpr[154]   gpr[250] gpr[346]
+    0x5B gpr[59] gpr[155]   gpr[251] gpr[347]
+    0x5C gpr[60] gpr[156]   gpr[252] gpr[348]
+    0x5D gpr[61] gpr[157]   gpr[253] gpr[349]
+    0x5E gpr[62] gpr[158]   gpr[254] gpr[350]
+    0x5F gpr[63] gpr[159]   gpr[255] gpr[351]
+    0x60 gpr[64] gpr[160]   gpr[256] gpr[352]
+    0x61 gpr[65] gpr[161]   gpr[257] gpr[353]
+    0x62 gpr[66] gpr[162]   gpr[258] gpr[354]
+    0x63 gpr[67] gpr[163]   gpr[259] gpr[355]
+    0x64 gpr[68] gpr[164]   gpr[260] gpr[356]
+    0x65 gpr[69] gpr[165]   gpr[261] gpr[357]
+    0x66 gpr[70] gpr[166]   gpr[262] gpr[358]
+    0x67 gpr[71] gpr[167]   gpr[263] gpr[359]
+    0x68 gpr[72] gpr[168]   gpr[264] gpr[360]
+    0x69 gpr[73] gpr[169]   gpr[265] gpr[361]
+    0x6A gpr[74] gpr[170]   gpr[266] gpr[362]
+    0x6B gpr[75] gpr[171]   gpr[267] gpr[363]
+    0x6C gpr[76] gpr[172]   gpr[268] gpr[364]
+    0x6D gpr[77] gpr[173]   gpr[269] gpr[365]
+    0x6E gpr[78] gpr[174]   gpr[270] gpr[366]
+    0x6F gpr[79] gpr[175]   gpr[271] gpr[367]
+    0x70 gpr[80] gpr[80]    gpr[80]  gpr[80]
+    0x71 gpr[81] gpr[81]    gpr[81]  gpr[81]
+    0x72 gpr[82] gpr[82]    gpr[82]  gpr[82]
+    0x73 gpr[83] gpr[83]    gpr[83]  gpr[83]
+    0x74 gpr[84] gpr[84]    gpr[84]  gpr[84]
+    0x75 gpr[85] gpr[85]    gpr[85]  gpr[85]
+    0x76 gpr[86] gpr[86]    gpr[86]  gpr[86]
+    0x77 gpr[87] gpr[87]    gpr[87]  gpr[87]
+    0x78 gpr[88] gpr[88]    gpr[88]  gpr[88]
+    0x79 gpr[89] gpr[89]    gpr[89]  gpr[89]
+    0x7A gpr[90] gpr[90]    gpr[90]  gpr[90]
+    0x7B gpr[91] gpr[91]    gpr[91]  gpr[91]
+    0x7C gpr[92] gpr[92]    gpr[92]  gpr[92]
+    0x7D gpr[93] gpr[93]    gpr[93]  gpr[93]
+    0x7E gpr[94] gpr[94]    gpr[94]  gpr[94]
+    0x7F gpr[95] gpr[95]    gpr[95]  gpr[95]
+};
+
+#[rustfmt::skip]
+static P12F675_MAP: RegisterMap = register_map! {
+    // bank 0    1          2       3
+    0x00 IADDR   IADDR      IADDR   IADDR
+    0x01 TMR0    OPTION_REG TMR0    OPTION_REG
+    0x02 PCL     PCL        PCL     PCL
+    0x03 STATUS  STATUS     STATUS  STATUS
+    0x04 FSR     FSR        FSR     FSR
+    0x05 GPIO    TRISIO     GPIO    TRISIO
+    0x06 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x07 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x08 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x09 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x0A PCLATH  PCLATH     PCLATH  PCLATH
+    0x0B INTCON  INTCON     INTCON  INTCON
+    0x0C PIR1    PIE1       PIR1    PIE1
+    0x0D UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x0E TMR1L   PCON       TMR1L   PCON
+    0x0F TMR1H   UNIMPL     TMR1H   UNIMPL
+    0x10 T1CON   OSCCAL     T1CON   OSCCAL
+    0x11 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x12 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x13 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x14 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x15 UNIMPL  WPU        UNIMPL  WPU
+    0x16 UNIMPL  IOC        UNIMPL  IOC
+    0x17 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x18 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x19 CMCON   VRCON      CMCON   VRCON
+    0x1A UNIMPL  EEDATA     UNIMPL  EEDATA
+    0x1B UNIMPL  EEADR      UNIMPL  EEADR
+    0x1C UNIMPL  EECON1     UNIMPL  EECON1
+    0x1D UNIMPL  EECON2     UNIMPL  EECON2
+    0x1E ADRESH  ADRESL     ADRESH  ADRESL
+    0x1F ADCON0  ANSEL      ADCON0  ANSEL
+    0x20 gpr[0]  gpr[0]     gpr[0]  gpr[0]
+    0x21 gpr[1]  gpr[1]     gpr[1]  gpr[1]
+    0x22 gpr[2]  gpr[2]     gpr[2]  gpr[2]
+    0x23 gpr[3]  gpr[3]     gpr[3]  gpr[3]
+    0x24 gpr[4]  gpr[4]     gpr[4]  gpr[4]
+    0x25 gpr[5]  gpr[5]     gpr[5]  gpr[5]
+    0x26 gpr[6]  gpr[6]     gpr[6]  gpr[6]
+    0x27 gpr[7]  gpr[7]     gpr[7]  gpr[7]
+    0x28 gpr[8]  gpr[8]     gpr[8]  gpr[8]
+    0x29 gpr[9]  gpr[9]     gpr[9]  gpr[9]
+    0x2A gpr[10] gpr[10]    gpr[10] gpr[10]
+    0x2B gpr[11] gpr[11]    gpr[11] gpr[11]
+    0x2C gpr[12] gpr[12]    gpr[12] gpr[12]
+    0x2D gpr[13] gpr[13]    gpr[13] gpr[13]
+    0x2E gpr[14] gpr[14]    gpr[14] gpr[14]
+    0x2F gpr[15] gpr[15]    gpr[15] gpr[15]
+    0x30 gpr[16] gpr[16]    gpr[16] gpr[16]
+    0x31 gpr[17] gpr[17]    gpr[17] gpr[17]
+    0x32 gpr[18] gpr[18]    gpr[18] gpr[18]
+    0x33 gpr[19] gpr[19]    gpr[19] gpr[19]
+    0x34 gpr[20] gpr[20]    gpr[20] gpr[20]
+    0x35 gpr[21] gpr[21]    gpr[21] gpr[21]
+    0x36 gpr[22] gpr[22]    gpr[22] gpr[22]
+    0x37 gpr[23] gpr[23]    gpr[23] gpr[23]
+    0x38 gpr[24] gpr[24]    gpr[24] gpr[24]
+    0x39 gpr[25] gpr[25]    gpr[25] gpr[25]
+    0x3A gpr[26] gpr[26]    gpr[26] gpr[26]
+    0x3B gpr[27] gpr[27]    gpr[27] gpr[27]
+    0x3C gpr[28] gpr[28]    gpr[28] gpr[28]
+    0x3D gpr[29] gpr[29]    gpr[29] gpr[29]
+    0x3E gpr[30] gpr[30]    gpr[30] gpr[30]
+    0x3F gpr[31] gpr[31]    gpr[31] gpr[31]
+    0x40 gpr[32] gpr[32]    gpr[32] gpr[32]
+    0x41 gpr[33] gpr[33]    gpr[33] gpr[33]
+    0x42 gpr[34] gpr[34]    gpr[34] gpr[34]
+    0x43 gpr[35] gpr[35]    gpr[35] gpr[35]
+    0x44 gpr[36] gpr[36]    gpr[36] gpr[36]
+    0x45 gpr[37] gpr[37]    gpr[37] gpr[37]
+    0x46 gpr[38] gpr[38]    gpr[38] gpr[38]
+    0x47 gpr[39] gpr[39]    gpr[39] gpr[39]
+    0x48 gpr[40] gpr[40]    gpr[40] gpr[40]
+    0x49 gpr[41] gpr[41]    gpr[41] gpr[41]
+    0x4A gpr[42] gpr[42]    gpr[42] gpr[42]
+    0x4B gpr[43] gpr[43]    gpr[43] gpr[43]
+    0x4C gpr[44] gpr[44]    gpr[44] gpr[44]
+    0x4D gpr[45] gpr[45]    gpr[45] gpr[45]
+    0x4E gpr[46] gpr[46]    gpr[46] gpr[46]
+    0x4F gpr[47] gpr[47]    gpr[47] gpr[47]
+    0x50 gpr[48] gpr[48]    gpr[48] gpr[48]
+    0x51 gpr[49] gpr[49]    gpr[49] gpr[49]
+    0x52 gpr[50] gpr[50]    gpr[50] gpr[50]
+    0x53 gpr[51] gpr[51]    gpr[51] gpr[51]
+    0x54 gpr[52] gpr[52]    gpr[52] gpr[52]
+    0x55 gpr[53] gpr[53]    gpr[53] gpr[53]
+    0x56 gpr[54] gpr[54]    gpr[54] gpr[54]
+    0x57 gpr[55] gpr[55]    gpr[55] gpr[55]
+    0x58 gpr[56] gpr[56]    gpr[56] gpr[56]
+    0x59 gpr[57] gpr[57]    gpr[57] gpr[57]
+    0x5A gpr[58] gpr[58]    gpr[58] gpr[58]
+    0x5B gpr[59] gpr[59]    gpr[59] gpr[59]
+    0x5C gpr[60] gpr[60]    gpr[60] gpr[60]
+    0x5D gpr[61] gpr[61]    gpr[61] gpr[61]
+    0x5E gpr[62] gpr[62]    gpr[62] gpr[62]
+    0x5F gpr[63] gpr[63]    gpr[63] gpr[63]
+    0x60 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x61 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x62 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x63 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x64 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x65 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x66 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x67 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x68 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x69 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6A UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6B UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6C UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6D UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6E UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x6F UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x70 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x71 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x72 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x73 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x74 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x75 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x76 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x77 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x78 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x79 UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7A UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7B UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7C UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7D UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7E UNIMPL  UNIMPL     UNIMPL  UNIMPL
+    0x7F UNIMPL  UNIMPL     UNIMPL  UNIMPL
+};
+
+#[test]
+fn maps_use_all_of_the_ram() {
+    for device in [&P16F84A, &P16F88, &P16F877A, &P12F675] {
+        let mut used = vec![false; device.gprs];
+        for slot in device.map.iter().flatten() {
+            if let Slot::Gpr(index) = slot {
+                used[*index as usize] = true;
+            }
+        }
+        assert!(used.iter().all(|&x| x), "{}", device.name);
+    }
+}
+
+#[test]
+fn devices_lay_out_the_register_file() {
+    use crate::vm::pic14::reg::STATUS;
+    use crate::vm::pic14::Pic14;
+
+    let mut vm = Pic14::with_device(&P16F84A, &[0; 1024 * 2]);
+    vm.register.at(RegisterFileAddr(0x0C)).write(0x42);
+    vm.register.special.status_mut().insert(STATUS::RP0);
+    // bank 1 mirrors the RAM of bank 0
+    assert_eq!(vm.register.at(RegisterFileAddr(0x0C)).read(), 0x42);
+    assert_eq!(
+        P16F84A.register_names(RegisterFileAddr(0x05)),
+        ["porta", "trisa"]
+    );
+    assert_eq!(
+        P16F877A.register_names(RegisterFileAddr(0x07)),
+        ["portc", "trisc", "unimpl"]
+    );
+    assert_eq!(P16F88.register_names(RegisterFileAddr(0x70)), ["gpr[80]"]);
+
+    // the 16F877A has more flash than the 16F88
+    let mut vm = Pic14::with_device(&P16F877A, &[0; 8192 * 2]);
+    vm.pc = 0x1000;
+    vm.step(&mut ()).unwrap();
+    assert_eq!(vm.pc, 0x1001);
+}
+
+#[test]
+fn p12f675_has_gpio_and_shared_ram() {
+    use crate::vm::pic14::reg::{OPTION_REG, STATUS};
+    use crate::vm::pic14::{Pic14, Pin};
+
+    let mut vm = Pic14::with_device(&P12F675, &[0; 1024 * 2]);
+    assert_eq!(
+        P12F675.register_names(RegisterFileAddr(0x05)),
+        ["gpio", "trisio"]
+    );
+    assert_eq!(vm.config_word(), 0x31FF);
+
+    vm.register.at(RegisterFileAddr(0x20)).write(0x42);
+    // GP0 as output, pull-up only on GP1, all digital
+    vm.register.special.status_mut().insert(STATUS::RP0);
+    assert_eq!(vm.register.at(RegisterFileAddr(0x20)).read(), 0x42);
+    vm.register.at(RegisterFileAddr(0x05)).write(0b0011_1110);
+    vm.register.at(RegisterFileAddr(0x15)).write(0b0000_0010);
+    vm.register.at(RegisterFileAddr(0x1F)).write(0);
+    vm.register.special.status_mut().remove(STATUS::RP0);
+    vm.register.special.option_reg_mut().0 &= !OPTION_REG::GPPU;
+
+    vm.register.at(RegisterFileAddr(0x05)).write(0b0000_0001);
+    assert_eq!(vm.pin_output(Pin::gp(0)), Some(true));
+    assert_eq!(vm.pin_output(Pin::gp(1)), None);
+    assert_eq!(vm.register.at(RegisterFileAddr(0x05)).read(), 0b0000_0011);
+
+    let mut image = vec![0; CONFIG_WORD_ADDR as usize * 2 + 2];
+    image[CONFIG_WORD_ADDR as usize * 2..].copy_from_slice(&0x3FD4u16.to_le_bytes());
+    let config = P12F675.config_word_in(&image).unwrap();
+    vm.set_config_word(config);
+    assert_eq!(vm.config_word() & P12F675_CONFIG::FOSC.bits(), 0b100);
+}
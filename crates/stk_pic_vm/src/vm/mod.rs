@@ -1 +1,7 @@
+pub mod cancel;
+pub mod cosim;
+pub mod device;
 pub mod p16f88;
+pub mod pic14;
+pub mod pic18;
+pub mod worker;
@@ -0,0 +1,3100 @@
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::expr::Expr;
+use crate::inst::{
+    BitOrientedInstruction, BitOrientedOperation, ByteOrientedInstruction, ByteOrientedOperation,
+    ControlInstruction, Destination, Instruction, LiteralOrientedInstruction,
+    LiteralOrientedOperation, ProgramAddr, RegisterFileAddr,
+};
+use crate::savefile::{Format, SaveFileError};
+use crate::vm::cancel::CancelToken;
+use crate::vm::device::{self, AdcLayout, Device, Slot};
+use crate::vm::p16f88::FLASH_BYTES;
+use crate::vm::pic14::reg::Register;
+
+// datasheets:
+//   - https://ww1.microchip.com/downloads/aemDocuments/documents/MCU08/ProductDocuments/DataSheets/30487D.pdf
+//   - https://ww1.microchip.com/downloads/en/DeviceDoc/31029a.pdf
+
+/// mid-range (14-bit instruction) PIC core. the [`Device`] decides the flash size and which
+/// registers appear where in the register file; everything else is shared.
+///
+/// the register file holds every SFR any supported device has. those the device doesn't map
+/// keep their reset value, so e.g. the A/D converter never runs on a PIC16F84A.
+#[derive(Clone)]
+pub struct Pic14 {
+    pub w: u8,
+    pub pc: u16,
+    pub flash: Box<[u8]>,
+    /// return addresses, no more than the device's [`Device::stack_depth`]
+    pub call_stack: Vec<u16>,
+    pub register: reg::Registers,
+    /// halted by SLEEP until an interrupt source wakes it up
+    pub sleeping: bool,
+    device: &'static Device,
+    /// with the condition that has to hold to stop there, if any
+    breakpoints: BTreeMap<ProgramAddr, Option<Expr>>,
+    /// breakpoint we have just stopped at. stepping again executes it instead of stopping twice.
+    stopped_at: Option<ProgramAddr>,
+    /// instruction the ticker is called for. `None` while sleeping or entering an interrupt
+    executing: Option<ProgramAddr>,
+    /// oscillator frequency, used to convert durations into cycles
+    clock_hz: u64,
+    /// decoded instruction per word, with the opcode it was decoded from. an entry whose opcode
+    /// no longer matches `flash` (self-write, restore, direct edits) is decoded again.
+    decoded: Box<[Option<(u16, Instruction)>]>,
+    /// voltage on AN0..AN6, set by the host
+    analog: [f32; 7],
+    /// instruction cycles until the running A/D conversion finishes
+    adc_remaining: Option<u64>,
+    /// configuration word (the first one on devices with two)
+    config: u16,
+    reserved_access: ReservedAccess,
+    stack_behavior: StackBehavior,
+    uninitialized_reads: Check,
+    /// GPRs written since power-on, by index. empty while `uninitialized_reads` is off
+    initialized: Vec<bool>,
+    /// `None` when the watchdog isn't emulated
+    watchdog: Option<Watchdog>,
+    timer0: Timer0,
+    /// data EEPROM, as many bytes as the device has
+    eeprom: Box<[u8]>,
+    /// where the EEPROM is kept between runs, written after every write to it
+    eeprom_file: Option<PathBuf>,
+    /// the write in progress
+    eeprom_write: Option<EepromWrite>,
+    /// how far into the 0x55, 0xAA sequence on EECON2 that unlocks a write the firmware is
+    eeprom_unlock: u8,
+    /// found while executing an instruction that carries on regardless, reported after it
+    fault: Option<VmError>,
+}
+
+/// supply voltage, also the A/D converter reference
+pub const VDD: f32 = 5.0;
+
+/// one instruction cycle takes 4 oscillator clocks
+pub const CLOCKS_PER_CYCLE: u64 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum VmError {
+    #[error("pc {pc:#06x} is outside of the program memory")]
+    PcOutOfRange { pc: u16 },
+
+    #[error("couldn't decode {code:#06x} at {pc:#06x} into instruction")]
+    InvalidInstruction { pc: u16, code: u16 },
+
+    #[error("callstack overflow at {pc:#06x}")]
+    CallStackOverflow { pc: u16 },
+
+    #[error("callstack underflow at {pc:#06x}: callstack has no return address")]
+    CallStackUnderflow { pc: u16 },
+
+    #[error("attempted to write on the reserved register {name} at {pc:#06x}")]
+    ReservedRegisterWrite { pc: u16, name: &'static str },
+
+    #[error("read of uninitialized gpr[{index}] at {pc:#06x}")]
+    UninitializedRead { pc: u16, index: u16 },
+}
+
+/// the kinds of reset, which firmware tells apart by STATUS.TO/PD and PCON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reset {
+    #[default]
+    PowerOn,
+    /// MCLR pulled low while running
+    Mclr,
+    /// the watchdog timer ran out while running
+    Watchdog,
+    /// VDD dropped below the brown-out threshold
+    BrownOut,
+}
+
+/// what accesses to the reserved locations of the register file do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReservedAccess {
+    /// writes stop with [`VmError::ReservedRegisterWrite`], reads warn and give 0
+    #[default]
+    Report,
+    /// any access panics, to fail a test right where it happens
+    Panic,
+}
+
+/// what a push onto the full call stack does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StackBehavior {
+    /// stop with [`VmError::CallStackOverflow`]
+    #[default]
+    Strict,
+    /// drop the oldest return address, as the hardware does. popping the empty stack still stops
+    /// with [`VmError::CallStackUnderflow`]; the stale address the hardware would return to
+    /// isn't kept.
+    Wrap,
+}
+
+/// how to treat something suspicious that the hardware lets through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Check {
+    #[default]
+    Off,
+    /// `tracing::warn!` and carry on
+    Warn,
+    /// stop with a [`VmError`] after the instruction
+    Error,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Watchdog {
+    /// instruction cycles since it was last cleared
+    elapsed: u64,
+    /// the PIC16F88 prescales its clock by WDTCON.WDTPS. the others have a fixed base period
+    wdtcon: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Timer0 {
+    /// clocks counted towards the next increment while the prescaler is assigned to Timer0
+    prescaler: u64,
+    /// instruction cycles left without increments after a write to TMR0
+    inhibit: u64,
+    /// TMR0 was written by the instruction just executed
+    written: bool,
+    /// level of T0CKI when it was last looked at, to count its edges
+    t0cki: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EepromWrite {
+    addr: usize,
+    data: u8,
+    /// instruction cycles until it's done
+    remaining: u64,
+}
+
+/// typical time an EEPROM write takes, the same on all the devices
+const EEPROM_WRITE_NS: u64 = 4_000_000;
+
+/// nominal period of the watchdogs without WDTCON, before the postscaler
+const WATCHDOG_BASE_NS: u64 = 18_000_000;
+
+/// one tick of the PIC16F88's 31.25 kHz watchdog clock, before WDTCON.WDTPS
+const WATCHDOG_TICK_NS: u64 = 32_000;
+
+/// settings for a new [`Pic14`]. the defaults are what [`Pic14::new`] gives: a PIC16F88 at
+/// 20 MHz after power-on, without a watchdog, stopping on reserved writes and stack overflows.
+#[derive(Debug, Clone)]
+pub struct Pic14Builder {
+    device: &'static Device,
+    flash: Vec<u8>,
+    clock_hz: u64,
+    reset: Reset,
+    watchdog: bool,
+    reserved_access: ReservedAccess,
+    stack_behavior: StackBehavior,
+    uninitialized_reads: Check,
+    eeprom: Vec<u8>,
+    eeprom_file: Option<PathBuf>,
+}
+
+impl Default for Pic14Builder {
+    fn default() -> Self {
+        Self {
+            device: &device::P16F88,
+            flash: vec![],
+            clock_hz: 20_000_000,
+            reset: Reset::PowerOn,
+            watchdog: false,
+            reserved_access: ReservedAccess::Report,
+            stack_behavior: StackBehavior::Strict,
+            uninitialized_reads: Check::Off,
+            eeprom: vec![],
+            eeprom_file: None,
+        }
+    }
+}
+
+impl Pic14Builder {
+    pub fn device(mut self, device: &'static Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// program memory, padded with zeros (NOPs) to the size of the device. all zeros if not given
+    pub fn flash(mut self, flash: &[u8]) -> Self {
+        self.flash = flash.to_vec();
+        self
+    }
+
+    pub fn clock_hz(mut self, hz: u64) -> Self {
+        assert!(hz > 0);
+        self.clock_hz = hz;
+        self
+    }
+
+    /// the reset the vm comes out of
+    pub fn reset(mut self, reset: Reset) -> Self {
+        self.reset = reset;
+        self
+    }
+
+    /// runs the watchdog timer, as if the configuration word enabled it. its period follows the
+    /// nominal timings and the prescaler settings.
+    pub fn watchdog(mut self, enabled: bool) -> Self {
+        self.watchdog = enabled;
+        self
+    }
+
+    pub fn reserved_access(mut self, policy: ReservedAccess) -> Self {
+        self.reserved_access = policy;
+        self
+    }
+
+    pub fn stack_behavior(mut self, behavior: StackBehavior) -> Self {
+        self.stack_behavior = behavior;
+        self
+    }
+
+    /// reads of GPRs that haven't been written since power-on. their content is unknown on
+    /// hardware, here they read 0
+    pub fn uninitialized_reads(mut self, check: Check) -> Self {
+        self.uninitialized_reads = check;
+        self
+    }
+
+    /// initial contents of the data EEPROM, e.g. [`MemoryImage::eeprom`]. the rest is erased
+    /// (0xFF).
+    ///
+    /// [`MemoryImage::eeprom`]: crate::hex::MemoryImage::eeprom
+    pub fn eeprom(mut self, eeprom: &[u8]) -> Self {
+        self.eeprom = eeprom.to_vec();
+        self
+    }
+
+    /// keeps the data EEPROM in `path`, one byte per byte, so that what the firmware writes is
+    /// there on the next run. once the file exists it replaces [`Self::eeprom`]; it's created on
+    /// the first write.
+    pub fn eeprom_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.eeprom_file = Some(path.into());
+        self
+    }
+
+    pub fn build(self) -> Pic14 {
+        let device = self.device;
+        assert!(
+            self.flash.len() <= device.flash_bytes,
+            "{} has {} bytes of flash",
+            device.name,
+            device.flash_bytes
+        );
+        let mut flash = self.flash;
+        flash.resize(device.flash_bytes, 0);
+        let register = reg::Registers::new(device);
+        let initialized = match self.uninitialized_reads {
+            Check::Off => vec![],
+            Check::Warn | Check::Error => vec![false; register.gpr.len()],
+        };
+        let wdtcon = device
+            .map
+            .iter()
+            .flatten()
+            .any(|slot| *slot == Slot::Special(reg::Sfr::WDTCON));
+        let mut eeprom = self.eeprom;
+        eeprom.resize(device.eeprom_bytes, 0xFF);
+        if let Some(path) = &self.eeprom_file {
+            match std::fs::read(path) {
+                Ok(saved) if saved.len() == device.eeprom_bytes => eeprom = saved,
+                Ok(saved) => tracing::warn!(
+                    "{}: expected {} bytes of EEPROM, found {}. starting without it",
+                    path.display(),
+                    device.eeprom_bytes,
+                    saved.len()
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("{}: {e}. starting without it", path.display()),
+            }
+        }
+
+        let mut vm = Pic14 {
+            w: 0,
+            pc: 0,
+            flash: flash.into(),
+            call_stack: Vec::with_capacity(device.stack_depth),
+            register,
+            sleeping: false,
+            device,
+            breakpoints: BTreeMap::new(),
+            stopped_at: None,
+            executing: None,
+            clock_hz: self.clock_hz,
+            decoded: vec![None; device.flash_bytes / 2].into_boxed_slice(),
+            analog: [0.0; 7],
+            adc_remaining: None,
+            config: device.config_mask,
+            reserved_access: self.reserved_access,
+            stack_behavior: self.stack_behavior,
+            uninitialized_reads: self.uninitialized_reads,
+            initialized,
+            watchdog: self.watchdog.then_some(Watchdog { elapsed: 0, wdtcon }),
+            timer0: Timer0::default(),
+            eeprom: eeprom.into(),
+            eeprom_file: self.eeprom_file,
+            eeprom_write: None,
+            eeprom_unlock: 0,
+            fault: None,
+        };
+        vm.reset(self.reset);
+        vm
+    }
+}
+
+/// machine state saved by [`Pic14::snapshot`]. breakpoints are debugger state and not included.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub w: u8,
+    pub pc: u16,
+    pub flash: Vec<u8>,
+    pub call_stack: Vec<u16>,
+    pub special: reg::SpecialPurposeRegisters,
+    pub gpr: Vec<u8>,
+    pub sleeping: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum SnapshotError {
+    #[error("expected {expected} bytes of {what}, found {found}")]
+    SizeMismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("callstack has {depth} entries, more than the device has")]
+    CallStackTooDeep { depth: usize },
+}
+
+impl Snapshot {
+    pub const FORMAT: Format = Format { name: "snapshot", migrations: &[] };
+
+    /// reads a snapshot written by [`Self::save`] by this or an earlier version
+    pub fn load(r: impl Read) -> Result<Self, SaveFileError> {
+        Self::FORMAT.load(r)
+    }
+
+    pub fn save(&self, w: impl Write) -> Result<(), SaveFileError> {
+        Self::FORMAT.save(w, self)
+    }
+}
+
+/// what [`Pic14::state_hash`] covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashScope {
+    /// W, PC, call stack, registers and the sleep flag
+    Cpu,
+    /// [`HashScope::Cpu`] plus the levels driven onto the input pins from outside
+    Full,
+}
+
+/// FNV-1a. unlike `DefaultHasher`, stays the same across rust versions and targets
+/// so hashes can be stored and compared later.
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+}
+
+/// result of [`Pic14::run_for_cycles`], [`Pic14::run_for`] and [`Pic14::run_until`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// cycles actually executed. can exceed the budget by one, as a 2-cycle instruction is not
+    /// split.
+    pub cycles: u64,
+    pub exit: RunExit,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RunExit {
+    /// the cycle budget has run out
+    Budget,
+    /// the `run_until` condition became true
+    Condition,
+    Stopped(Stopped),
+    /// the [`CancelToken`] was cancelled or ran out of host time
+    Cancelled,
+    /// the vm is left as it was before the failing instruction
+    Error(VmError),
+}
+
+/// A/D converter settings, see [`AdcLayout`]
+struct AdcControl {
+    /// GO/DONE bit of ADCON0
+    go: u8,
+    channel: usize,
+    /// conversion clock select, ADCS2:0
+    adcs: u8,
+    right_justified: bool,
+}
+
+/// counts cycles on the way to the user's ticker
+struct Counting<'a, T> {
+    inner: &'a mut T,
+    cycles: u64,
+}
+
+impl<T: Ticker> Ticker for Counting<'_, T> {
+    fn tick(&mut self, vm: &Pic14, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.inner.tick(vm, cycles);
+    }
+
+    fn on_read(&mut self, access: MemoryAccess) {
+        self.inner.on_read(access);
+    }
+
+    fn on_write(&mut self, access: MemoryAccess) {
+        self.inner.on_write(access);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Stopped {
+    Breakpoint(ProgramAddr),
+    /// [`Pic14::step_over`] or [`Pic14::step_out`] has finished
+    Stepped,
+    /// [`Pic14::run_to`] has reached its address
+    Reached(ProgramAddr),
+}
+
+/// register file access made by an instruction. STATUS flag updates are not reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// address of the instruction
+    pub pc: u16,
+    /// address in the instruction, without the bank
+    pub addr: RegisterFileAddr,
+    /// bank selected by STATUS.RP1:RP0
+    pub bank: u8,
+    /// value read back before the access
+    pub old: u8,
+    /// value read back after the access. same as `old` for reads
+    pub new: u8,
+}
+
+pub trait Ticker {
+    /// called after each instruction
+    fn tick(&mut self, vm: &Pic14, cycles: u8);
+
+    /// called when an instruction reads the register file, before its [`Self::tick`]
+    fn on_read(&mut self, _access: MemoryAccess) {}
+
+    /// called when an instruction writes the register file, before its [`Self::tick`]
+    fn on_write(&mut self, _access: MemoryAccess) {}
+}
+
+/// for when nothing needs to be observed
+impl Ticker for () {
+    fn tick(&mut self, _vm: &Pic14, _cycles: u8) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PortId {
+    A,
+    B,
+    /// GPIO of the 8-pin devices
+    Gpio,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Pin {
+    pub port: PortId,
+    pub bit: u8,
+}
+impl std::fmt::Debug for Pin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.port {
+            PortId::Gpio => write!(f, "GP{}", self.bit),
+            _ => write!(f, "R{:?}{}", self.port, self.bit),
+        }
+    }
+}
+/// `RA0`, `RB7` or `GP5`, as [`Debug`] prints them. case doesn't matter
+impl std::str::FromStr for Pin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let upper = s.to_ascii_uppercase();
+        let (port, bits, bit) = if let Some(bit) = upper.strip_prefix("RA") {
+            (PortId::A, 8, bit)
+        } else if let Some(bit) = upper.strip_prefix("RB") {
+            (PortId::B, 8, bit)
+        } else if let Some(bit) = upper.strip_prefix("GP") {
+            (PortId::Gpio, 6, bit)
+        } else {
+            return Err(format!("invalid pin `{s}`"));
+        };
+        match bit.parse::<u8>() {
+            Ok(bit) if bit < bits => Ok(Self { port, bit }),
+            _ => Err(format!("invalid pin `{s}`")),
+        }
+    }
+}
+
+impl Pin {
+    pub fn ra(bit: u8) -> Self {
+        assert!(bit < 8);
+        Self { port: PortId::A, bit }
+    }
+    pub fn rb(bit: u8) -> Self {
+        assert!(bit < 8);
+        Self { port: PortId::B, bit }
+    }
+    pub fn gp(bit: u8) -> Self {
+        assert!(bit < 6);
+        Self { port: PortId::Gpio, bit }
+    }
+
+    /// A/D channel (ANx) on this pin
+    pub fn analog_channel(&self) -> Option<u8> {
+        match (self.port, self.bit) {
+            (PortId::A, 0..=4) => Some(self.bit),
+            (PortId::B, 6 | 7) => Some(self.bit - 1),
+            (PortId::Gpio, 0..=2) => Some(self.bit),
+            (PortId::Gpio, 4) => Some(3),
+            _ => None,
+        }
+    }
+}
+
+impl Pic14 {
+    /// a PIC16F88 running `flash`. see [`Self::builder`] for the other devices and settings.
+    #[allow(clippy::new_without_default)]
+    pub fn new(flash: [u8; FLASH_BYTES]) -> Self {
+        Self::builder().flash(&flash).build()
+    }
+
+    /// `flash` must be exactly [`Device::flash_bytes`] long
+    pub fn with_device(device: &'static Device, flash: &[u8]) -> Self {
+        assert_eq!(
+            flash.len(),
+            device.flash_bytes,
+            "{} has {} bytes of flash",
+            device.name,
+            device.flash_bytes
+        );
+        Self::builder().device(device).flash(flash).build()
+    }
+
+    pub fn builder() -> Pic14Builder {
+        Pic14Builder::default()
+    }
+
+    /// resets the core as `kind` does: the pc, the call stack and the SFRs go back to their reset
+    /// values, and STATUS.TO/PD and PCON record which reset it was. GPRs keep their contents and
+    /// the pins keep the levels driven onto them from outside.
+    pub fn reset(&mut self, kind: Reset) {
+        use reg::{PCON, STATUS};
+
+        let before = std::mem::take(&mut self.register.special);
+        let sp = &mut self.register.special;
+        sp.porta_mut().input = before.porta().input;
+        sp.porta_mut().driven = before.porta().driven;
+        sp.portb_mut().input = before.portb().input;
+        sp.portb_mut().driven = before.portb().driven;
+        sp.gpio_mut().input = before.gpio().input;
+        sp.gpio_mut().driven = before.gpio().driven;
+
+        let (status, pcon) = (before.status(), before.pcon().0);
+        let (to, pd, pcon) = match kind {
+            Reset::PowerOn => (true, true, 0),
+            Reset::BrownOut => (true, true, PCON::POR),
+            Reset::Mclr => (
+                status.contains(STATUS::TO),
+                status.contains(STATUS::PD),
+                pcon,
+            ),
+            Reset::Watchdog => (false, true, pcon),
+        };
+        sp.status_mut().set(STATUS::TO, to);
+        sp.status_mut().set(STATUS::PD, pd);
+        sp.pcon_mut().0 = pcon;
+
+        self.pc = 0;
+        self.call_stack.clear();
+        self.sleeping = false;
+        self.stopped_at = None;
+        self.executing = None;
+        self.adc_remaining = None;
+        self.fault = None;
+        if let Some(wdt) = &mut self.watchdog {
+            wdt.elapsed = 0;
+        }
+        let t0cki = self.pin_level(self.t0cki_pin());
+        self.timer0 = Timer0 { t0cki, ..Timer0::default() };
+        self.eeprom_unlock = 0;
+        if self.eeprom_write.take().is_some() {
+            self.register.special.eecon1_mut().0 |= reg::EECON1::WRERR;
+        }
+        if kind == Reset::PowerOn {
+            self.initialized.fill(false);
+        }
+    }
+
+    pub fn device(&self) -> &'static Device {
+        self.device
+    }
+
+    /// contents of the data EEPROM
+    pub fn eeprom(&self) -> &[u8] {
+        &self.eeprom
+    }
+
+    /// erased (all implemented bits set) unless [`Self::set_config_word`] says otherwise.
+    /// the core doesn't act on it; the host decides what e.g. the oscillator bits mean.
+    pub fn config_word(&self) -> u16 {
+        self.config
+    }
+
+    /// see [`Device::config_word_in`] to take it from a hex file
+    pub fn set_config_word(&mut self, word: u16) {
+        self.config = word & self.device.config_mask;
+    }
+
+    pub fn clock_hz(&self) -> u64 {
+        self.clock_hz
+    }
+
+    /// defaults to 20 MHz
+    pub fn set_clock_hz(&mut self, hz: u64) {
+        assert!(hz > 0);
+        self.clock_hz = hz;
+    }
+
+    /// replaces a conditional breakpoint at `addr`
+    pub fn add_breakpoint(&mut self, addr: ProgramAddr) {
+        self.breakpoints.insert(addr, None);
+    }
+
+    /// stops at `addr` only when `condition` holds there
+    pub fn add_breakpoint_if(&mut self, addr: ProgramAddr, condition: Expr) {
+        self.breakpoints.insert(addr, Some(condition));
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: ProgramAddr) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// conditional ones included
+    pub fn breakpoints(&self) -> impl Iterator<Item = ProgramAddr> + '_ {
+        self.breakpoints.keys().copied()
+    }
+
+    /// `None` if the breakpoint at `addr` is unconditional or there is none
+    pub fn breakpoint_condition(&self, addr: ProgramAddr) -> Option<&Expr> {
+        self.breakpoints.get(&addr)?.as_ref()
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// address of the instruction whose cycles are being reported to the [`Ticker`].
+    /// `None` for cycles spent sleeping or vectoring to the interrupt handler.
+    pub fn executing(&self) -> Option<ProgramAddr> {
+        self.executing
+    }
+
+    /// register at `addr` of the linear register file (bank * 0x80 + offset), read without side
+    /// effects, for debuggers. addresses past the last bank read as 0.
+    pub fn peek(&self, addr: u16) -> u8 {
+        let slot = self
+            .device
+            .map
+            .get(addr as usize / 0x80)
+            .map(|bank| bank[addr as usize % 0x80]);
+        match slot {
+            Some(Slot::Special(sfr)) => self.register.special.get(sfr).read(),
+            Some(Slot::Gpr(index)) => self.register.gpr[index as usize].read(),
+            None => 0,
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            w: self.w,
+            pc: self.pc,
+            flash: self.flash.to_vec(),
+            call_stack: self.call_stack.to_vec(),
+            special: self.register.special.clone(),
+            gpr: self.register.gpr.iter().map(|r| r.0).collect(),
+            sleeping: self.sleeping,
+        }
+    }
+
+    /// 64-bit digest of the machine state, for cheap equivalence checks.
+    /// flash and breakpoints are not included.
+    pub fn state_hash(&self, scope: HashScope) -> u64 {
+        let mut h = Fnv1a(0xCBF2_9CE4_8422_2325);
+        h.write_u8(self.w);
+        h.write(&self.pc.to_le_bytes());
+        h.write_u8(self.call_stack.len() as u8);
+        for ret in &self.call_stack {
+            h.write(&ret.to_le_bytes());
+        }
+        self.register.special.hash(&mut h);
+        for r in &self.register.gpr {
+            h.write_u8(r.0);
+        }
+        h.write_u8(self.sleeping as u8);
+        if scope == HashScope::Full {
+            let sp = &self.register.special;
+            for (input, driven) in [
+                (sp.porta().input, sp.porta().driven),
+                (sp.portb().input, sp.portb().driven),
+                (sp.gpio().input, sp.gpio().driven),
+            ] {
+                h.write_u8(input);
+                h.write_u8(driven);
+            }
+        }
+        h.finish()
+    }
+
+    /// brings the machine back to `snapshot`. breakpoints are kept.
+    /// on error, the vm is left untouched.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), SnapshotError> {
+        let size = |what, expected, found| {
+            if found == expected {
+                Ok(())
+            } else {
+                Err(SnapshotError::SizeMismatch { what, expected, found })
+            }
+        };
+        size("flash", self.flash.len(), snapshot.flash.len())?;
+        size("gpr", self.register.gpr.len(), snapshot.gpr.len())?;
+        if snapshot.call_stack.len() > self.device.stack_depth {
+            let depth = snapshot.call_stack.len();
+            return Err(SnapshotError::CallStackTooDeep { depth });
+        }
+
+        self.w = snapshot.w;
+        self.pc = snapshot.pc;
+        self.flash.copy_from_slice(&snapshot.flash);
+        self.call_stack = snapshot.call_stack;
+        self.register.special = snapshot.special;
+        for (r, v) in self.register.gpr.iter_mut().zip(snapshot.gpr) {
+            r.0 = v;
+        }
+        self.sleeping = snapshot.sleeping;
+        self.stopped_at = None;
+        self.executing = None;
+        self.adc_remaining = None;
+        // the snapshot doesn't say which GPRs were written. trust it
+        self.initialized.fill(true);
+        if let Some(wdt) = &mut self.watchdog {
+            wdt.elapsed = 0;
+        }
+        self.fault = None;
+        Ok(())
+    }
+
+    /// the I/O pins of the device: GP0..5 on the 8-pin devices, RA0..7 and RB0..7 otherwise
+    pub fn pins(&self) -> Vec<Pin> {
+        if self.has_gpio() {
+            (0..6).map(Pin::gp).collect()
+        } else {
+            (0..8).map(Pin::ra).chain((0..8).map(Pin::rb)).collect()
+        }
+    }
+
+    /// the 8-pin devices have GPIO instead of PORTA and PORTB
+    fn has_gpio(&self) -> bool {
+        self.device
+            .map
+            .iter()
+            .flatten()
+            .any(|slot| *slot == Slot::Special(reg::Sfr::GPIO))
+    }
+
+    /// the pin Timer0 counts the edges of in counter mode
+    fn t0cki_pin(&self) -> Pin {
+        if self.has_gpio() {
+            Pin::gp(2)
+        } else {
+            Pin::ra(4)
+        }
+    }
+
+    /// level driven onto `pin` by this MCU, or `None` if the pin is configured as an input or
+    /// is an open-drain output left floating.
+    pub fn pin_output(&self, pin: Pin) -> Option<bool> {
+        let (latch, tris) = match pin.port {
+            PortId::A => (
+                self.register.special.porta().latch,
+                self.register.special.trisa().0,
+            ),
+            PortId::B => (
+                self.register.special.portb().latch,
+                self.register.special.trisb().0,
+            ),
+            PortId::Gpio => (
+                self.register.special.gpio().latch,
+                self.register.special.trisio().0,
+            ),
+        };
+        let mask = 1 << pin.bit;
+        let open_drain = match pin.port {
+            PortId::A => self.device.porta_open_drain,
+            _ => 0,
+        };
+        if open_drain & latch & mask != 0 {
+            return None;
+        }
+        (tris & mask == 0).then_some(latch & mask != 0)
+    }
+
+    /// level on `pin`: what this MCU drives, or for an input what the outside drives or the weak
+    /// pull-up holds it at. the firmware reads pins configured as analog as 0 instead.
+    pub fn pin_level(&self, pin: Pin) -> bool {
+        let port = match pin.port {
+            PortId::A => self.register.special.porta().level(),
+            PortId::B => self.register.special.portb().level(),
+            PortId::Gpio => self.register.special.gpio().level(),
+        };
+        port & 1 << pin.bit != 0
+    }
+
+    /// drives `pin` from outside. only visible to the firmware while the pin is an input.
+    pub fn set_pin_input(&mut self, pin: Pin, level: bool) {
+        let (input, driven) = self.pin_inputs_mut(pin.port);
+        let mask = 1 << pin.bit;
+        *driven |= mask;
+        if level {
+            *input |= mask;
+        } else {
+            *input &= !mask;
+        }
+        self.update_t0cki();
+    }
+
+    /// stops driving `pin` from outside. the firmware reads it high if the weak pull-up is
+    /// enabled (RBPU cleared on PORTB, GPPU cleared and the WPU bit set on GPIO), low otherwise.
+    pub fn release_pin_input(&mut self, pin: Pin) {
+        let (input, driven) = self.pin_inputs_mut(pin.port);
+        let mask = 1 << pin.bit;
+        *driven &= !mask;
+        *input &= !mask;
+        self.update_t0cki();
+    }
+
+    fn pin_inputs_mut(&mut self, port: PortId) -> (&mut u8, &mut u8) {
+        match port {
+            PortId::A => {
+                let p = self.register.special.porta_mut();
+                (&mut p.input, &mut p.driven)
+            }
+            PortId::B => {
+                let p = self.register.special.portb_mut();
+                (&mut p.input, &mut p.driven)
+            }
+            PortId::Gpio => {
+                let p = self.register.special.gpio_mut();
+                (&mut p.input, &mut p.driven)
+            }
+        }
+    }
+
+    /// voltage applied to A/D channel `channel` (see [`Pin::analog_channel`]).
+    /// clamped to 0..[`VDD`].
+    pub fn set_analog_input(&mut self, channel: u8, volts: f32) {
+        self.analog[channel as usize] = volts.clamp(0.0, VDD);
+    }
+
+    /// whether an enabled interrupt source has its flag set, regardless of GIE.
+    /// this is what wakes the device up from SLEEP.
+    pub fn interrupt_pending(&self) -> bool {
+        use reg::INTCON;
+
+        let sp = &self.register.special;
+        let intcon = sp.intcon().0;
+        // TMR0IE/INTE/RBIE sit 3 bits above TMR0IF/INTF/RBIF
+        let core = (intcon >> 3) & intcon & (INTCON::TMR0IF | INTCON::INTF | INTCON::RBIF) != 0;
+        let peripheral = intcon & INTCON::PEIE != 0
+            && (sp.pie1().0 & sp.pir1().0 != 0 || sp.pie2().0 & sp.pir2().0 != 0);
+        // the PIC16F84A has EEIE in place of PEIE, and EEIF in EECON1
+        let (eeif_in, eeif) = self.device.eeif;
+        let eeprom =
+            eeif_in == reg::Sfr::EECON1 && intcon & INTCON::PEIE != 0 && sp.eecon1().0 & eeif != 0;
+        core || peripheral || eeprom
+    }
+
+    /// executes one instruction, or vectors to the interrupt handler if an interrupt is pending.
+    /// returns `Some` without executing anything when the pc hits a breakpoint.
+    /// on error, the vm is left as it was before the instruction.
+    pub fn step(&mut self, ticker: &mut impl Ticker) -> Result<Option<Stopped>, VmError> {
+        let addr = ProgramAddr(self.pc);
+        let hit = match self.breakpoints.get(&addr) {
+            Some(condition) => condition.as_ref().map_or(true, |x| x.holds(self)),
+            None => false,
+        };
+        if self.stopped_at.take() != Some(addr) && hit {
+            self.stopped_at = Some(addr);
+            return Ok(Some(Stopped::Breakpoint(addr)));
+        }
+
+        self.step_inner(ticker)?;
+        Ok(None)
+    }
+
+    /// steps until the vm stops.
+    pub fn run(&mut self, ticker: &mut impl Ticker) -> Result<Stopped, VmError> {
+        loop {
+            if let Some(stopped) = self.step(ticker)? {
+                return Ok(stopped);
+            }
+        }
+    }
+
+    /// steps one instruction. when that enters a subroutine (call or interrupt), keeps going
+    /// until it returns.
+    pub fn step_over(&mut self, ticker: &mut impl Ticker) -> Result<Stopped, VmError> {
+        let depth = self.call_stack.len();
+        if let Some(stopped) = self.step(ticker)? {
+            return Ok(stopped);
+        }
+        self.run_while(ticker, |vm| vm.call_stack.len() > depth)
+    }
+
+    /// runs until the current subroutine returns. at the top level, this is [`Self::run`].
+    pub fn step_out(&mut self, ticker: &mut impl Ticker) -> Result<Stopped, VmError> {
+        let Some(depth) = self.call_stack.len().checked_sub(1) else {
+            return self.run(ticker);
+        };
+        self.run_while(ticker, |vm| vm.call_stack.len() > depth)
+    }
+
+    /// runs until the pc reaches `addr`, like a breakpoint that goes away once hit.
+    /// executes at least one instruction, so this can be used to finish a loop.
+    pub fn run_to(
+        &mut self,
+        addr: ProgramAddr,
+        ticker: &mut impl Ticker,
+    ) -> Result<Stopped, VmError> {
+        if let Some(stopped) = self.step(ticker)? {
+            return Ok(stopped);
+        }
+        match self.run_while(ticker, |vm| vm.pc != addr.0)? {
+            Stopped::Stepped => Ok(Stopped::Reached(addr)),
+            stopped => Ok(stopped),
+        }
+    }
+
+    /// runs at least `cycles` instruction cycles, or until the vm stops.
+    pub fn run_for_cycles(&mut self, cycles: u64, ticker: &mut impl Ticker) -> Run {
+        self.run_with(Some(cycles), |_| false, None, ticker)
+    }
+
+    /// [`Self::run_for_cycles`] for the cycles `duration` takes at [`Self::clock_hz`].
+    pub fn run_for(&mut self, duration: Duration, ticker: &mut impl Ticker) -> Run {
+        self.run_for_cycles(self.cycles_in(duration), ticker)
+    }
+
+    /// instruction cycles `duration` takes at [`Self::clock_hz`]
+    pub fn cycles_in(&self, duration: Duration) -> u64 {
+        let clocks = duration.as_nanos() * self.clock_hz as u128 / 1_000_000_000;
+        (clocks / CLOCKS_PER_CYCLE as u128)
+            .try_into()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// steps until `cond` returns true, or until the vm stops. `cond` is checked before every
+    /// step, so nothing runs if it already holds.
+    pub fn run_until(&mut self, cond: impl FnMut(&Self) -> bool, ticker: &mut impl Ticker) -> Run {
+        self.run_with(None, cond, None, ticker)
+    }
+
+    /// [`Self::run_until`] that also stops after `cycles`, like [`Self::run_for_cycles`].
+    pub fn run_until_within(
+        &mut self,
+        cycles: u64,
+        cond: impl FnMut(&Self) -> bool,
+        ticker: &mut impl Ticker,
+    ) -> Run {
+        self.run_with(Some(cycles), cond, None, ticker)
+    }
+
+    /// [`Self::run_until`] that also gives up when `cancel` is cancelled.
+    pub fn run_until_cancellable(
+        &mut self,
+        cond: impl FnMut(&Self) -> bool,
+        cancel: &CancelToken,
+        ticker: &mut impl Ticker,
+    ) -> Run {
+        self.run_with(None, cond, Some(cancel), ticker)
+    }
+
+    pub(crate) fn run_with(
+        &mut self,
+        budget: Option<u64>,
+        mut cond: impl FnMut(&Self) -> bool,
+        cancel: Option<&CancelToken>,
+        ticker: &mut impl Ticker,
+    ) -> Run {
+        let mut ticker = Counting { inner: ticker, cycles: 0 };
+        let mut next_check = 0;
+        let exit = loop {
+            if let Some(cancel) = cancel {
+                if ticker.cycles >= next_check {
+                    if cancel.is_cancelled() {
+                        break RunExit::Cancelled;
+                    }
+                    next_check = ticker.cycles + CancelToken::CHECK_INTERVAL;
+                }
+            }
+            if budget.is_some_and(|b| ticker.cycles >= b) {
+                break RunExit::Budget;
+            }
+            if cond(self) {
+                break RunExit::Condition;
+            }
+            match self.step(&mut ticker) {
+                Ok(None) => {}
+                Ok(Some(stopped)) => break RunExit::Stopped(stopped),
+                Err(e) => break RunExit::Error(e),
+            }
+        };
+        Run { cycles: ticker.cycles, exit }
+    }
+
+    fn run_while(
+        &mut self,
+        ticker: &mut impl Ticker,
+        cond: impl Fn(&Self) -> bool,
+    ) -> Result<Stopped, VmError> {
+        while cond(self) {
+            if let Some(stopped) = self.step(ticker)? {
+                return Ok(stopped);
+            }
+        }
+        Ok(Stopped::Stepped)
+    }
+
+    fn step_inner(&mut self, ticker: &mut impl Ticker) -> Result<(), VmError> {
+        let mut ticker = Counting { inner: ticker, cycles: 0 };
+        let stepped = self.step_cpu(&mut ticker);
+        let fault = self.fault.take();
+        stepped?;
+        self.update_adc(ticker.cycles);
+        self.update_eeprom(ticker.cycles);
+        self.update_timer0(ticker.cycles);
+        self.update_t0cki();
+        self.update_watchdog(ticker.cycles);
+        fault.map_or(Ok(()), Err)
+    }
+
+    /// instruction cycles from clearing the watchdog to its timeout, at the nominal period
+    fn watchdog_period(&self, wdt: Watchdog) -> u64 {
+        use reg::{OPTION_REG, WDTCON};
+
+        let option = self.register.special.option_reg().0;
+        // the postscaler is shared with TMR0
+        let postscale = if option & OPTION_REG::PSA != 0 {
+            1 << (option & OPTION_REG::PS)
+        } else {
+            1
+        };
+        let nanos = if wdt.wdtcon {
+            let wdtps = (self.register.special.wdtcon().0 & WDTCON::WDTPS) >> 1;
+            // 1:32 up to 1:65536. the rest are reserved
+            WATCHDOG_TICK_NS << (5 + wdtps.min(11))
+        } else {
+            WATCHDOG_BASE_NS
+        };
+        let clocks = (nanos * postscale) as u128 * self.clock_hz as u128 / 1_000_000_000;
+        (clocks / CLOCKS_PER_CYCLE as u128).max(1) as u64
+    }
+
+    /// follows the 0x55, 0xAA sequence that unlocks a write
+    fn eecon2_written(&mut self, _old: u8) {
+        self.eeprom_unlock = match (self.eeprom_unlock, self.register.special.eecon2().0) {
+            (_, 0x55) => 1,
+            (1, 0xAA) => 2,
+            _ => 0,
+        };
+    }
+
+    /// starts a read or write of the data EEPROM
+    fn eecon1_written(&mut self, old: u8) {
+        use reg::EECON1;
+
+        let sp = &mut self.register.special;
+        // RD and WR can be set but not cleared
+        let eecon1 = sp.eecon1().0 | (old & (EECON1::RD | EECON1::WR));
+        sp.eecon1_mut().0 = eecon1;
+        // reads and writes of program memory aren't emulated
+        if eecon1 & EECON1::EEPGD != 0 {
+            return;
+        }
+        let addr = sp.eeadr().0 as usize % self.eeprom.len();
+        if eecon1 & EECON1::RD != 0 {
+            sp.eedata_mut().0 = self.eeprom[addr];
+            sp.eecon1_mut().0 &= !EECON1::RD;
+        }
+        if eecon1 & EECON1::WR != 0 && old & EECON1::WR == 0 {
+            let unlocked = std::mem::take(&mut self.eeprom_unlock) == 2;
+            if unlocked && eecon1 & EECON1::WREN != 0 {
+                let data = sp.eedata().0;
+                let remaining = self.cycles_in(Duration::from_nanos(EEPROM_WRITE_NS));
+                self.eeprom_write = Some(EepromWrite { addr, data, remaining });
+            } else {
+                sp.eecon1_mut().0 &= !EECON1::WR;
+            }
+        }
+    }
+
+    fn update_eeprom(&mut self, cycles: u64) {
+        let Some(write) = &mut self.eeprom_write else {
+            return;
+        };
+        write.remaining = write.remaining.saturating_sub(cycles);
+        if write.remaining > 0 {
+            return;
+        }
+        let write = self.eeprom_write.take().unwrap();
+        self.eeprom[write.addr] = write.data;
+        let (eeif_in, eeif) = self.device.eeif;
+        let sp = &mut self.register.special;
+        sp.eecon1_mut().0 &= !reg::EECON1::WR;
+        sp.get_mut(eeif_in).write_with(&|x| x | eeif);
+        if let Some(path) = &self.eeprom_file {
+            if let Err(e) = std::fs::write(path, &self.eeprom) {
+                tracing::warn!("{}: {e}", path.display());
+            }
+        }
+    }
+
+    /// a write also clears the prescaler
+    fn tmr0_written(&mut self, _old: u8) {
+        self.timer0.prescaler = 0;
+        self.timer0.written = true;
+    }
+
+    /// the receive FIFO holds one byte here, so reading it empties the FIFO
+    fn rcreg_read(&mut self) {
+        self.register.special.pir1_mut().0 &= !reg::PIR1::RCIF;
+    }
+
+    /// counts the instruction clock in timer mode (OPTION_REG.T0CS clear)
+    fn update_timer0(&mut self, cycles: u64) {
+        use reg::OPTION_REG;
+
+        // the cycles of the write itself don't count, nor do the next two
+        if std::mem::take(&mut self.timer0.written) {
+            self.timer0.inhibit = 2;
+            return;
+        }
+        let inhibited = cycles.min(self.timer0.inhibit);
+        self.timer0.inhibit -= inhibited;
+        let option = self.register.special.option_reg().0;
+        if option & OPTION_REG::T0CS == 0 && !self.sleeping {
+            self.count_timer0(cycles - inhibited);
+        }
+    }
+
+    /// counts an edge of T0CKI in counter mode (OPTION_REG.T0CS set), rising or falling as
+    /// OPTION_REG.T0SE selects. the pin may be driven from outside or by the firmware itself.
+    fn update_t0cki(&mut self) {
+        use reg::OPTION_REG;
+
+        let level = self.pin_level(self.t0cki_pin());
+        if level == self.timer0.t0cki {
+            return;
+        }
+        self.timer0.t0cki = level;
+        let option = self.register.special.option_reg().0;
+        let rising = option & OPTION_REG::T0SE == 0;
+        if option & OPTION_REG::T0CS != 0 && level == rising && !self.sleeping {
+            self.count_timer0(1);
+        }
+    }
+
+    /// counts `clocks` through the prescaler, or straight into TMR0 while the watchdog has it.
+    /// sets INTCON.TMR0IF when TMR0 overflows
+    fn count_timer0(&mut self, clocks: u64) {
+        use reg::{INTCON, OPTION_REG};
+
+        let option = self.register.special.option_reg().0;
+        let increments = if option & OPTION_REG::PSA != 0 {
+            self.timer0.prescaler = 0;
+            clocks
+        } else {
+            let rate = 2 << (option & OPTION_REG::PS);
+            let clocks = self.timer0.prescaler + clocks;
+            self.timer0.prescaler = clocks % rate;
+            clocks / rate
+        };
+        if increments == 0 {
+            return;
+        }
+        let sp = &mut self.register.special;
+        let count = sp.tmr0().0 as u64 + increments;
+        sp.tmr0_mut().0 = count as u8;
+        if count > 0xFF {
+            sp.intcon_mut().0 |= INTCON::TMR0IF;
+        }
+    }
+
+    fn update_watchdog(&mut self, cycles: u64) {
+        let Some(mut wdt) = self.watchdog else {
+            return;
+        };
+        wdt.elapsed += cycles;
+        if wdt.elapsed < self.watchdog_period(wdt) {
+            self.watchdog = Some(wdt);
+            return;
+        }
+        wdt.elapsed = 0;
+        self.watchdog = Some(wdt);
+
+        if self.sleeping {
+            // wakes up and carries on after the SLEEP
+            self.sleeping = false;
+            self.register
+                .special()
+                .status_mut()
+                .set(reg::STATUS::TO, false);
+        } else {
+            tracing::debug!("watchdog timeout at {:#06x}", self.pc);
+            self.reset(Reset::Watchdog);
+        }
+    }
+
+    /// A/D converter control bits, from wherever the device keeps them
+    fn adc_control(&self) -> AdcControl {
+        use reg::{ADCON0, ADCON1, ANSEL};
+
+        let sp = &self.register.special;
+        let adcon0 = sp.adcon0().0;
+        match self.device.adc {
+            AdcLayout::Adcon1 => AdcControl {
+                go: ADCON0::GO,
+                channel: ((adcon0 & ADCON0::CHS) >> 3) as usize,
+                adcs: (adcon0 >> 6) | ((sp.adcon1().0 & ADCON1::ADCS2) >> 4),
+                right_justified: sp.adcon1().0 & ADCON1::ADFM != 0,
+            },
+            AdcLayout::Ansel => AdcControl {
+                go: ADCON0::GO_12F,
+                channel: ((adcon0 & ADCON0::CHS_12F) >> 2) as usize,
+                adcs: (sp.ansel().0 & ANSEL::ADCS) >> 4,
+                right_justified: adcon0 & ADCON0::ADFM_12F != 0,
+            },
+        }
+    }
+
+    /// instruction cycles one A/D conversion (11 TAD) takes with the clock selected by ADCS
+    fn adc_conversion_cycles(&self, adcs: u8) -> u64 {
+        let tad_clocks = match adcs {
+            0b000 => 2,
+            0b001 => 8,
+            0b010 => 32,
+            0b100 => 4,
+            0b101 => 16,
+            0b110 => 64,
+            // internal RC: TAD is typically 4 us
+            _ => (self.clock_hz * 4 / 1_000_000).max(1),
+        };
+        (11 * tad_clocks).div_ceil(CLOCKS_PER_CYCLE)
+    }
+
+    fn update_adc(&mut self, cycles: u64) {
+        use reg::ADCON0;
+
+        let adcon0 = self.register.special.adcon0().0;
+        let control = self.adc_control();
+        if adcon0 & ADCON0::ADON == 0 || adcon0 & control.go == 0 {
+            self.adc_remaining = None;
+            return;
+        }
+        let remaining = match self.adc_remaining {
+            Some(x) => x,
+            None => self.adc_conversion_cycles(control.adcs),
+        }
+        .saturating_sub(cycles);
+        if remaining > 0 {
+            self.adc_remaining = Some(remaining);
+            return;
+        }
+        self.adc_remaining = None;
+
+        let volts = self.analog.get(control.channel).copied().unwrap_or(0.0);
+        let result = (volts / VDD * 1023.0).round() as u16;
+        let sp = &mut self.register.special;
+        if control.right_justified {
+            sp.adresh_mut().0 = (result >> 8) as u8;
+            sp.adresl_mut().0 = result as u8;
+        } else {
+            sp.adresh_mut().0 = (result >> 2) as u8;
+            sp.adresl_mut().0 = (result << 6) as u8;
+        }
+        sp.adcon0_mut().0 &= !control.go;
+        sp.pir1_mut().0 |= reg::PIR1::ADIF;
+    }
+
+    fn step_cpu(&mut self, ticker: &mut impl Ticker) -> Result<(), VmError> {
+        let pending = self.interrupt_pending();
+        self.executing = None;
+        if self.sleeping {
+            if !pending {
+                ticker.tick(self, 1);
+                return Ok(());
+            }
+            self.sleeping = false;
+        }
+
+        let intcon = self.register.special.intcon().0;
+        if pending && intcon & reg::INTCON::GIE != 0 {
+            self.push(self.pc)?;
+            self.register.special.intcon_mut().0 &= !reg::INTCON::GIE;
+            self.pc = 0x0004;
+            ticker.tick(self, 2);
+            return Ok(());
+        }
+
+        let pc = self.pc;
+        let at = pc as usize * 2;
+        let (Some(&a), Some(&b)) = (self.flash.get(at), self.flash.get(at + 1)) else {
+            return Err(VmError::PcOutOfRange { pc });
+        };
+        let bytecode = ((b as u16) << 8) | (a as u16);
+        let inst = self.decode(pc, bytecode)?;
+        self.exec(inst, ticker)
+    }
+
+    fn decode(&mut self, pc: u16, code: u16) -> Result<Instruction, VmError> {
+        let slot = &mut self.decoded[pc as usize];
+        match *slot {
+            Some((cached, inst)) if cached == code => Ok(inst),
+            _ => {
+                let inst =
+                    Instruction::from_code(code).ok_or(VmError::InvalidInstruction { pc, code })?;
+                *slot = Some((code, inst));
+                Ok(inst)
+            }
+        }
+    }
+
+    /// [`Self::step`], but panics on error.
+    #[track_caller]
+    pub fn step_or_panic(&mut self, ticker: &mut impl Ticker) -> Option<Stopped> {
+        match self.step(ticker) {
+            Ok(stopped) => stopped,
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    fn push(&mut self, ret: u16) -> Result<(), VmError> {
+        if self.call_stack.len() == self.device.stack_depth {
+            if self.stack_behavior != StackBehavior::Wrap {
+                return Err(VmError::CallStackOverflow { pc: self.pc });
+            }
+            self.call_stack.remove(0);
+        }
+        self.call_stack.push(ret);
+        Ok(())
+    }
+
+    /// where `f` is in the bank selected now
+    fn slot(&self, f: RegisterFileAddr) -> Slot {
+        let bank = (self.register.special.status().bits() & 0b0110_0000) >> 5;
+        self.device.map[bank as usize][f.0 as usize]
+    }
+
+    fn check_writable(&mut self, f: RegisterFileAddr) -> Result<(), VmError> {
+        let pc = self.pc;
+        match (self.register.at(f).reserved(), self.reserved_access) {
+            (None, _) => Ok(()),
+            (Some(name), ReservedAccess::Report) => {
+                Err(VmError::ReservedRegisterWrite { pc, name })
+            }
+            (Some(name), ReservedAccess::Panic) => {
+                panic!("attempted to write on the reserved register {name} at {pc:#06x}")
+            }
+        }
+    }
+
+    /// applies the reserved access and uninitialized read policies to a read of `f`
+    fn check_read(&mut self, f: RegisterFileAddr) {
+        let pc = self.pc;
+        match self.slot(f) {
+            Slot::Special(_) => {
+                if self.reserved_access == ReservedAccess::Panic {
+                    if let Some(name) = self.register.at(f).reserved() {
+                        panic!("attempted to read the reserved register {name} at {pc:#06x}");
+                    }
+                }
+            }
+            Slot::Gpr(index) => {
+                if self.initialized.get(index as usize) != Some(&false) {
+                    return;
+                }
+                let e = VmError::UninitializedRead { pc, index };
+                match self.uninitialized_reads {
+                    Check::Off => {}
+                    Check::Warn => tracing::warn!("{e}"),
+                    Check::Error => {
+                        self.fault.get_or_insert(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// `a + b`, with C and DC the carries out of bits 7 and 3
+    fn add(&mut self, a: u8, b: u8) -> u8 {
+        let (ret, carry) = a.overflowing_add(b);
+        let st = self.register.special().status_mut();
+        st.set(reg::STATUS::Z, ret == 0);
+        st.set(reg::STATUS::C, carry);
+        st.set(reg::STATUS::DC, (a & 0x0F) + (b & 0x0F) > 0x0F);
+        ret
+    }
+
+    /// `a - b`. C and DC are set when there is *no* borrow out of bits 7 and 3
+    fn sub(&mut self, a: u8, b: u8) -> u8 {
+        let ret = a.wrapping_sub(b);
+        let st = self.register.special().status_mut();
+        st.set(reg::STATUS::Z, ret == 0);
+        st.set(reg::STATUS::C, a >= b);
+        st.set(reg::STATUS::DC, a & 0x0F >= b & 0x0F);
+        ret
+    }
+
+    pub fn exec(&mut self, inst: Instruction, ticker: &mut impl Ticker) -> Result<(), VmError> {
+        self.executing = Some(ProgramAddr(self.pc));
+
+        match inst {
+            Instruction::ByteOriented(x) => self.exec_byte(x, ticker),
+            Instruction::BitOriented(x) => self.exec_bit(x, ticker),
+            Instruction::LiteralOriented(x) => self.exec_literal(x, ticker),
+            Instruction::Control(x) => self.exec_control(x, ticker),
+        }
+    }
+
+    fn access(&mut self, f: RegisterFileAddr, old: u8, new: u8) -> MemoryAccess {
+        let bank = (self.register.special.status().bits() & 0b0110_0000) >> 5;
+        MemoryAccess { pc: self.pc, addr: f, bank, old, new }
+    }
+
+    fn read_f(&mut self, f: RegisterFileAddr, ticker: &mut impl Ticker) -> u8 {
+        self.check_read(f);
+        let v = self.register.at(f).read();
+        if let Slot::Special(sfr) = self.slot(f) {
+            if let Some(on_read) = sfr.on_read() {
+                on_read(self);
+            }
+        }
+        ticker.on_read(self.access(f, v, v));
+        v
+    }
+
+    fn write_f(&mut self, f: RegisterFileAddr, v: u8, ticker: &mut impl Ticker) {
+        let old = self.register.at(f).read();
+        // the write may switch banks (STATUS), so take the bank before it
+        let mut access = self.access(f, old, old);
+        let slot = self.slot(f);
+        if let Slot::Gpr(index) = slot {
+            if let Some(initialized) = self.initialized.get_mut(index as usize) {
+                *initialized = true;
+            }
+        }
+        self.register.at(f).write(v);
+        if let Slot::Special(sfr) = slot {
+            if let Some(on_write) = sfr.on_write() {
+                on_write(self, old);
+            }
+        }
+        access.new = self.register.at(f).read();
+        ticker.on_write(access);
+    }
+
+    fn set_z(&mut self, v: u8) -> u8 {
+        self.register
+            .special()
+            .status_mut()
+            .set(reg::STATUS::Z, v == 0);
+        v
+    }
+
+    /// computes the result from the value of f, updating STATUS
+    fn byte_handler(op: ByteOrientedOperation) -> fn(&mut Self, u8) -> u8 {
+        use ByteOrientedOperation::*;
+
+        match op {
+            AddWf => |vm, x| vm.add(vm.w, x),
+            AndWf => |vm, x| vm.set_z(vm.w & x),
+            // read: datasheets[1] P20
+            ComplementF => |vm, x| vm.set_z(!x),
+            DecrementF => |vm, x| vm.set_z(x.wrapping_sub(1)),
+            DecrementFSkipIfZ => |_, x| x.wrapping_sub(1),
+            IncrementF => |vm, x| vm.set_z(x.wrapping_add(1)),
+            IncrementFSkipIfZ => |_, x| x.wrapping_add(1),
+            OrWf => |vm, x| vm.set_z(vm.w | x),
+            MoveF => |vm, x| vm.set_z(x),
+            RotateLeftFThroughCarry => |vm, x| {
+                let status = vm.register.special().status_mut();
+                let ret = (x << 1) | status.contains(reg::STATUS::C) as u8;
+                status.set(reg::STATUS::C, (x & 0b1000_0000) != 0);
+                ret
+            },
+            RotateRightFThroughCarry => |vm, x| {
+                let status = vm.register.special().status_mut();
+                let ret = (x >> 1) | ((status.contains(reg::STATUS::C) as u8) << 7);
+                status.set(reg::STATUS::C, (x & 0b0000_0001) != 0);
+                ret
+            },
+            SubtractWfromF => |vm, x| vm.sub(x, vm.w),
+            SwapF => |_, x| x.rotate_left(4),
+            XorWwithF => |vm, x| vm.set_z(vm.w ^ x),
+        }
+    }
+
+    fn exec_byte(
+        &mut self,
+        inst: ByteOrientedInstruction,
+        ticker: &mut impl Ticker,
+    ) -> Result<(), VmError> {
+        use ByteOrientedOperation::*;
+
+        let ByteOrientedInstruction { op, f, dest } = inst;
+        // before the handler touches STATUS, so that errors leave the vm as it was
+        if dest == Destination::F {
+            self.check_writable(f)?;
+        }
+        let x = self.read_f(f, ticker);
+        let ret = Self::byte_handler(op)(self, x);
+        match dest {
+            Destination::W => self.w = ret,
+            Destination::F => self.write_f(f, ret, ticker),
+        }
+
+        let skip = matches!(op, DecrementFSkipIfZ | IncrementFSkipIfZ) && ret == 0;
+        self.pc += if skip { 2 } else { 1 };
+        ticker.tick(self, Instruction::ByteOriented(inst).cycle_cost(skip));
+        Ok(())
+    }
+
+    fn exec_bit(
+        &mut self,
+        inst: BitOrientedInstruction,
+        ticker: &mut impl Ticker,
+    ) -> Result<(), VmError> {
+        use BitOrientedOperation::*;
+
+        let BitOrientedInstruction { op, b, f } = inst;
+        let mask = 0b0000_0001 << b.0;
+        let skip = match op {
+            BitClearF | BitSetF => {
+                self.check_writable(f)?;
+                // read-modify-write
+                let x = self.read_f(f, ticker);
+                let x = if op == BitSetF { x | mask } else { x & !mask };
+                self.write_f(f, x, ticker);
+                false
+            }
+            SkipIfFBitClear => (self.read_f(f, ticker) & mask) == 0,
+            SkipIfFBitSet => (self.read_f(f, ticker) & mask) != 0,
+        };
+        self.pc += if skip { 2 } else { 1 };
+        ticker.tick(self, Instruction::BitOriented(inst).cycle_cost(skip));
+        Ok(())
+    }
+
+    /// computes the new W from k, updating STATUS
+    fn literal_handler(op: LiteralOrientedOperation) -> fn(&mut Self, u8) -> u8 {
+        use LiteralOrientedOperation::*;
+
+        match op {
+            SubtractWFromLiteral => |vm, k| vm.sub(k, vm.w),
+            XorLiteralWithW => |vm, k| vm.set_z(vm.w ^ k),
+            OrLiteralWithW => |vm, k| vm.set_z(vm.w | k),
+            MoveLiteralToW | ReturnWithLiteralInW => |_, k| k,
+            AddLiteralToW => |vm, k| vm.add(vm.w, k),
+            AndLiteralWithW => |vm, k| vm.set_z(vm.w & k),
+        }
+    }
+
+    fn exec_literal(
+        &mut self,
+        inst: LiteralOrientedInstruction,
+        ticker: &mut impl Ticker,
+    ) -> Result<(), VmError> {
+        if inst.op == LiteralOrientedOperation::ReturnWithLiteralInW {
+            let ret = self
+                .call_stack
+                .pop()
+                .ok_or(VmError::CallStackUnderflow { pc: self.pc })?;
+            self.w = inst.k;
+            self.pc = ret;
+        } else {
+            self.w = Self::literal_handler(inst.op)(self, inst.k);
+            self.pc += 1;
+        }
+        ticker.tick(self, Instruction::LiteralOriented(inst).cycle_cost(false));
+        Ok(())
+    }
+
+    fn exec_control(
+        &mut self,
+        inst: ControlInstruction,
+        ticker: &mut impl Ticker,
+    ) -> Result<(), VmError> {
+        use ControlInstruction::*;
+
+        match inst {
+            Noop => self.pc += 1,
+            ClearWatchDogTimer => {
+                // without a watchdog it's a NOP, as with the watchdog disabled
+                if let Some(wdt) = &mut self.watchdog {
+                    wdt.elapsed = 0;
+                    let st = self.register.special().status_mut();
+                    st.set(reg::STATUS::TO, true);
+                    st.set(reg::STATUS::PD, true);
+                }
+                self.pc += 1;
+            }
+            Sleep => {
+                let st = self.register.special().status_mut();
+                st.set(reg::STATUS::TO, true);
+                st.set(reg::STATUS::PD, false);
+                if let Some(wdt) = &mut self.watchdog {
+                    wdt.elapsed = 0;
+                }
+                self.sleeping = true;
+                self.pc += 1;
+            }
+            Return | ReturnFromInterrupt => {
+                self.pc = self
+                    .call_stack
+                    .pop()
+                    .ok_or(VmError::CallStackUnderflow { pc: self.pc })?;
+                if inst == ReturnFromInterrupt {
+                    self.register.special.intcon_mut().0 |= reg::INTCON::GIE;
+                }
+            }
+            ClearF { f } => {
+                self.check_writable(f)?;
+                self.write_f(f, 0, ticker);
+                self.set_z(0);
+                self.pc += 1;
+            }
+            ClearW => {
+                self.w = self.set_z(0);
+                self.pc += 1;
+            }
+            MoveWtoF { f } => {
+                self.check_writable(f)?;
+                self.write_f(f, self.w, ticker);
+                self.pc += 1;
+            }
+            Goto { addr } => {
+                self.pc = addr.0;
+                self.pc |= ((self.register.special.pclath().read() & 0b0001_1000) as u16) << 8;
+            }
+            Call { addr } => {
+                // read: datasheets[0] P25
+                self.push(self.pc + 1)?;
+                // pclath: 0b0001_1xxx_0000_0000
+                // pc:     0b0000_0111_1111_1111
+                self.pc = addr.0;
+                self.pc |= ((self.register.special.pclath().read() & 0b0001_1000) as u16) << 8;
+            }
+        }
+        ticker.tick(self, Instruction::Control(inst).cycle_cost(false));
+        Ok(())
+    }
+}
+
+pub mod reg {
+    #![allow(dead_code)]
+
+    use std::hash::{Hash, Hasher};
+
+    use concat_idents::concat_idents;
+    use serde::{Deserialize, Serialize};
+
+    use crate::inst::RegisterFileAddr;
+    use crate::vm::device::{Device, RegisterMap, Slot};
+
+    pub trait Register {
+        fn read(&self) -> u8;
+        fn write(&mut self, v: u8);
+
+        /// name of the register if it is reserved and must not be written
+        fn reserved(&self) -> Option<&'static str> {
+            None
+        }
+
+        /// name of the register if it only stores what is written, without the behavior of the
+        /// hardware behind it
+        fn stub(&self) -> Option<&'static str> {
+            None
+        }
+
+        // using dyn to preserve object-safety
+        fn write_with(&mut self, f: &dyn Fn(u8) -> u8) {
+            self.write(f(self.read()))
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct Registers {
+        pub special: SpecialPurposeRegisters,
+        /// as many as the device has
+        pub gpr: Box<[GeneralPurposeRegister]>,
+        map: &'static RegisterMap,
+        /// ANSEL is mapped and makes pins analog
+        ansel: bool,
+        porta_open_drain: u8,
+    }
+
+    #[derive(Clone)]
+    pub struct GeneralPurposeRegister(pub u8);
+
+    /// side effect of an instruction reading an SFR, run after the read
+    pub(super) type ReadEffect = fn(&mut super::Pic14);
+    /// side effect of an instruction writing an SFR, run after the write with the value it held
+    /// before
+    pub(super) type WriteEffect = fn(&mut super::Pic14, u8);
+
+    // the handlers on read and write are methods of `Pic14`, see `ReadEffect` and `WriteEffect`.
+    // the others only store the byte
+    special_registers! {
+        // name    field   gen_struct   impl   init        unimpl      unstable on reset on read    on write
+        IADDR      iaddr       y        unimpl 0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        UNIMPL     unimpl      y        unimpl 0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        RESERV     reserv      y        unimpl 0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        TMR0       tmr0        y        none   0b0000_0000 0b0000_0000 0b1111_1111       -          tmr0_written
+        PCL        pcl         y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        STATUS     status      n        none   0b0001_1000 0b0000_0000 0b0000_0111       -          -
+        FSR        fsr         y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        PORTA      porta       n        none   0b0000_0000 0b0000_0000 0b1110_0000       -          -
+        PORTB      portb       n        none   0b0000_0000 0b0000_0000 0b0011_1111       -          -
+        PCLATH     pclath      y        stub   0b0000_0000 0b1110_0000 0b0000_0000       -          -
+        INTCON     intcon      y        stub   0b0000_0000 0b0000_0000 0b0000_0001       -          -
+        PIR1       pir1        y        stub   0b0000_0000 0b1000_0000 0b0000_0000       -          -
+        PIR2       pir2        y        stub   0b0000_0000 0b0010_1111 0b0000_0000       -          -
+        TMR1L      tmr1l       y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        TMR1H      tmr1h       y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        T1CON      t1con       y        stub   0b0000_0000 0b1000_0000 0b0000_0000       -          -
+        TMR2       tmr2        y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        T2CON      t2con       y        stub   0b0000_0000 0b1000_0000 0b0000_0000       -          -
+        SSPBUF     sspbuf      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        SSPCON     sspcon      y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        CCPR1L     ccpr1l      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        CCPR1H     ccpr1h      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        CCP1CON    ccp1con     y        stub   0b0000_0000 0b1100_0000 0b0000_0000       -          -
+        RCSTA      rcsta       y        stub   0b0000_0000 0b0000_0000 0b0000_0001       -          -
+        TXREG      txreg       y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        RCREG      rcreg       y        stub   0b0000_0000 0b0000_0000 0b0000_0000       rcreg_read -
+        ADRESH     adresh      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        ADCON0     adcon0      y        stub   0b0000_0000 0b0000_0010 0b0000_0000       -          -
+        OPTION_REG option_reg  y        stub   0b1111_1111 0b0000_0000 0b0000_0000       -          -
+        TRISA      trisa       y        stub   0b1111_1111 0b0000_0000 0b0000_0000       -          -
+        TRISB      trisb       y        stub   0b1111_1111 0b0000_0000 0b0000_0000       -          -
+        PIE1       pie1        y        stub   0b0000_0000 0b1000_0000 0b0000_0000       -          -
+        PIE2       pie2        y        stub   0b0000_0000 0b0010_1111 0b0000_0000       -          -
+        PCON       pcon        y        stub   0b0000_0000 0b1111_1100 0b0000_0000       -          - // NOTE: 0b0000_0001 depends on condition
+        OSCCON     osccon      y        stub   0b0000_0000 0b1000_0000 0b0000_0000       -          -
+        OSCTUNE    osctune     y        stub   0b0000_0000 0b1100_0000 0b0000_0000       -          -
+        PR2        pr2         y        stub   0b1111_1111 0b0000_0000 0b0000_0000       -          -
+        SSPADD     sspadd      y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        SSPSTAT    sspstat     y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        TXSTA      txsta       y        stub   0b0000_0010 0b0000_1000 0b0000_0000       -          -
+        SPBRG      spbrg       y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        ANSEL      ansel       y        stub   0b0111_1111 0b1000_0000 0b0000_0000       -          -
+        CMCON      cmcon       y        stub   0b0000_0111 0b0000_0000 0b0000_0000       -          -
+        CVRCON     cvrcon      y        stub   0b0000_0000 0b0001_0000 0b0000_0000       -          -
+        WDTCON     wdtcon      y        stub   0b0000_1000 0b1110_0000 0b0000_0000       -          -
+        ADRESL     adresl      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        ADCON1     adcon1      y        stub   0b0000_0000 0b0000_1111 0b0000_0000       -          -
+        EEDATA     eedata      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        EEADR      eeadr       y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        EEDATH     eedath      y        stub   0b0000_0000 0b1100_0000 0b0011_1111       -          -
+        EEADRH     eeadrh      y        stub   0b0000_0000 0b1111_1000 0b0000_0111       -          -
+        EECON1     eecon1      y        stub   0b0000_0000 0b0110_0000 0b1001_1000       -          eecon1_written
+        EECON2     eecon2      y        stub   0b0000_0000 0b1111_1111 0b0000_0000       -          eecon2_written
+        // PIC16F877A only. ports C-E are plain latches for now
+        PORTC      portc       y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        PORTD      portd       y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        PORTE      porte       y        stub   0b0000_0000 0b1111_1000 0b0000_0111       -          -
+        TRISC      trisc       y        stub   0b1111_1111 0b0000_0000 0b0000_0000       -          -
+        TRISD      trisd       y        stub   0b1111_1111 0b0000_0000 0b0000_0000       -          -
+        TRISE      trise       y        stub   0b0000_0111 0b0000_1000 0b0000_0000       -          -
+        CCPR2L     ccpr2l      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        CCPR2H     ccpr2h      y        stub   0b0000_0000 0b0000_0000 0b1111_1111       -          -
+        CCP2CON    ccp2con     y        stub   0b0000_0000 0b1100_0000 0b0000_0000       -          -
+        SSPCON2    sspcon2     y        stub   0b0000_0000 0b0000_0000 0b0000_0000       -          -
+        // PIC12F675 only. it shares CMCON, ANSEL and the rest with the others
+        GPIO       gpio        n        none   0b0000_0000 0b1100_0000 0b0011_1111       -          -
+        TRISIO     trisio      y        stub   0b0011_1111 0b1100_0000 0b0000_0000       -          -
+        WPU        wpu         y        stub   0b0011_0111 0b1100_1000 0b0000_0000       -          -
+        IOC        ioc         y        stub   0b0000_0000 0b1100_0000 0b0000_0000       -          -
+        OSCCAL     osccal      y        stub   0b1000_0000 0b0000_0011 0b0000_0000       -          -
+        VRCON      vrcon       y        stub   0b0000_0000 0b0101_0000 0b0000_0000       -          -
+    }
+
+    impl Registers {
+        pub fn new(device: &'static Device) -> Self {
+            let map = device.map;
+            Self {
+                special: SpecialPurposeRegisters::new(),
+                gpr: (0..device.gprs)
+                    .map(|_| GeneralPurposeRegister::new())
+                    .collect(),
+                map,
+                ansel: map
+                    .iter()
+                    .flatten()
+                    .any(|x| *x == Slot::Special(Sfr::ANSEL)),
+                porta_open_drain: device.porta_open_drain,
+            }
+        }
+
+        pub fn at(&mut self, addr: RegisterFileAddr) -> &mut dyn Register {
+            self.special.porta.tris = self.special.trisa.0;
+            self.special.porta.open_drain = self.porta_open_drain;
+            self.special.portb.tris = self.special.trisb.0;
+            self.special.portb.pull_up = if self.special.option_reg.0 & OPTION_REG::RBPU == 0 {
+                0xFF
+            } else {
+                0
+            };
+            self.special.gpio.tris = self.special.trisio.0;
+            self.special.gpio.pull_up = if self.special.option_reg.0 & OPTION_REG::GPPU == 0 {
+                self.special.wpu.0
+            } else {
+                0
+            };
+            // ANS0-4 are RA0-4 and ANS5-6 RB6-7, or ANS0-3 GP0-2 and GP4 on the PIC12F675
+            let ansel = if self.ansel { self.special.ansel.0 } else { 0 };
+            self.special.porta.analog = ansel & 0b1_1111;
+            self.special.portb.analog = (ansel & 0b110_0000) << 1;
+            self.special.gpio.analog = (ansel & 0b111) | (ansel & 0b1000) << 1;
+
+            assert!(addr.0 < 0x80, "addr out of bounds");
+            let bank = (self.special.status_mut().read() & 0b0110_0000) >> 5;
+            match self.map[bank as usize][addr.0 as usize] {
+                Slot::Special(sfr) => self.special.get_mut(sfr),
+                Slot::Gpr(index) => &mut self.gpr[index as usize],
+            }
+        }
+
+        pub fn special(&mut self) -> &mut SpecialPurposeRegisters {
+            &mut self.special
+        }
+    }
+
+    impl Default for GeneralPurposeRegister {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl GeneralPurposeRegister {
+        pub fn new() -> Self {
+            Self(0)
+        }
+    }
+
+    impl Register for GeneralPurposeRegister {
+        fn read(&self) -> u8 {
+            // TODO: check for uninitilized?
+            self.0
+        }
+
+        fn write(&mut self, v: u8) {
+            self.0 = v;
+        }
+    }
+
+    macro_rules! special_registers {
+        (
+            $($name:ident $lowername:ident $gen_struct:ident $stub_ty:ident $initial_value:literal $unimplemented_mask:literal $unknown_mask:literal $on_read:tt $on_write:tt)+
+        ) => {
+            $(
+                special_registers!(@struct $name $gen_struct $unimplemented_mask $initial_value);
+                special_registers!(@genstub $name $stub_ty);
+
+                impl Default for $name {
+                    fn default() -> Self {
+                        Self::new()
+                    }
+                }
+
+            )+
+
+            /// names a special purpose register, for [`RegisterMap`]s
+            #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            pub enum Sfr {
+                $($name,)+
+            }
+
+            impl Sfr {
+                pub fn name(self) -> &'static str {
+                    match self {
+                        $(Sfr::$name => stringify!($lowername),)+
+                    }
+                }
+
+                /// what an instruction reading it does besides taking the value
+                pub(super) fn on_read(self) -> Option<ReadEffect> {
+                    match self {
+                        $(Sfr::$name => special_registers!(@effect $on_read),)+
+                    }
+                }
+
+                /// what an instruction writing it does besides storing the value
+                pub(super) fn on_write(self) -> Option<WriteEffect> {
+                    match self {
+                        $(Sfr::$name => special_registers!(@effect $on_write),)+
+                    }
+                }
+            }
+
+            #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+            pub struct SpecialPurposeRegisters {
+                $($lowername: $name,)+
+            }
+
+            impl Default for SpecialPurposeRegisters {
+                fn default() -> Self {
+                    Self::new()
+                }
+            }
+
+            impl SpecialPurposeRegisters {
+                pub fn new() -> Self {
+                    Self {
+                        $($lowername: $name::new(),)+
+                    }
+                }
+
+                pub fn get(&self, sfr: Sfr) -> &dyn Register {
+                    match sfr {
+                        $(Sfr::$name => &self.$lowername,)+
+                    }
+                }
+
+                pub fn get_mut(&mut self, sfr: Sfr) -> &mut dyn Register {
+                    match sfr {
+                        $(Sfr::$name => &mut self.$lowername,)+
+                    }
+                }
+
+                $(
+                    concat_idents! { mut_fn_name = $lowername, _mut {
+                        pub fn mut_fn_name(&mut self) -> &mut $name {
+                            &mut self.$lowername
+                        }
+                        pub fn $lowername(&self) -> &$name {
+                            &self.$lowername
+                        }
+                    }}
+                )+
+            }
+        };
+
+        (@struct $name:ident y $unimplemented_mask:literal $initial_value:literal) => {
+            #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+            #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+            pub struct $name(pub u8);
+
+            impl $name {
+                const UNIMPLEMENTED: u8 = $unimplemented_mask;
+                pub fn new() -> Self {
+                    Self($initial_value)
+                }
+            }
+        };
+
+        (@struct $name:ident n $unimplemented_mask:literal $initial_value:literal) => {
+            impl $name {
+                const UNIMPLEMENTED: u8 = $unimplemented_mask;
+                const INITIAL_VALUE: u8 = $initial_value;
+            }
+        };
+
+        (@effect -) => { None };
+
+        (@effect $handler:ident) => { Some(super::Pic14::$handler) };
+
+        (@genstub stub) => { };
+
+        (@genstub $name:ident stub) => {
+            impl Register for $name {
+                fn read(&self) -> u8 {
+                    self.0
+                }
+
+                fn write(&mut self, v: u8) {
+                    self.0 = v;
+                }
+
+                fn stub(&self) -> Option<&'static str> {
+                    Some(stringify!($name))
+                }
+            }
+        };
+
+        (@genstub $name:ident none) => { };
+
+        (@genstub $name:ident unimpl) => {
+            impl Register for $name {
+                fn read(&self) -> u8 {
+                    tracing::warn!("{}: tried to read the reserved register!: reading 0", stringify!($name));
+                    0
+                }
+
+                fn write(&mut self, _v: u8) {
+                    panic!("{}: attempted to write on the reserved register", stringify!($name));
+                }
+
+                fn reserved(&self) -> Option<&'static str> {
+                    Some(stringify!($name))
+                }
+            }
+        };
+    }
+
+    macro_rules! io_port {
+        ($name:ident) => {
+            /// I/O port. writes go to the output latch, reads return the pin levels.
+            #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+            pub struct $name {
+                pub latch: u8,
+                /// levels driven onto the pins from outside
+                pub input: u8,
+                /// pins that something outside is driving. the others are floating
+                pub driven: u8,
+                /// copy of the corresponding TRIS register, refreshed on every register access
+                tris: u8,
+                /// pins with an enabled weak pull-up, refreshed like `tris`
+                pull_up: u8,
+                /// pins configured as analog, refreshed like `tris`. they read 0
+                analog: u8,
+                /// outputs that only pull low, refreshed like `tris`
+                open_drain: u8,
+            }
+
+            impl $name {
+                fn new() -> Self {
+                    Self {
+                        latch: Self::INITIAL_VALUE,
+                        input: 0,
+                        driven: 0,
+                        tris: 0xFF,
+                        pull_up: 0,
+                        analog: 0,
+                        open_drain: 0,
+                    }
+                }
+
+                /// levels on the pins, including the analog ones
+                pub fn level(&self) -> u8 {
+                    // floating inputs read low unless pulled up
+                    let outside = self.input | (self.pull_up & !self.driven);
+                    let driving = !self.tris & !(self.open_drain & self.latch);
+                    (self.latch & driving) | (outside & !driving)
+                }
+            }
+
+            /// only the latch. `input` and `driven` come from outside, `tris` and `pull_up`
+            /// mirror other registers.
+            impl Hash for $name {
+                fn hash<H: Hasher>(&self, state: &mut H) {
+                    self.latch.hash(state);
+                }
+            }
+
+            impl Register for $name {
+                fn read(&self) -> u8 {
+                    self.level() & !self.analog
+                }
+
+                fn write(&mut self, v: u8) {
+                    self.latch = v;
+                }
+            }
+        };
+    }
+
+    io_port!(PORTA);
+    io_port!(PORTB);
+    io_port!(GPIO);
+
+    // counted by `Pic14::update_timer0` and `Pic14::update_t0cki`
+    impl Register for TMR0 {
+        fn read(&self) -> u8 {
+            self.0
+        }
+
+        fn write(&mut self, v: u8) {
+            self.0 = v;
+        }
+    }
+
+    impl INTCON {
+        pub const GIE: u8 = 1 << 7;
+        pub const PEIE: u8 = 1 << 6;
+        pub const TMR0IE: u8 = 1 << 5;
+        pub const INTE: u8 = 1 << 4;
+        pub const RBIE: u8 = 1 << 3;
+        pub const TMR0IF: u8 = 1 << 2;
+        pub const INTF: u8 = 1 << 1;
+        pub const RBIF: u8 = 1 << 0;
+    }
+
+    /// bits of PIE1 and PIR1
+    impl PIR1 {
+        pub const ADIF: u8 = 1 << 6;
+        pub const RCIF: u8 = 1 << 5;
+        pub const TXIF: u8 = 1 << 4;
+        pub const SSPIF: u8 = 1 << 3;
+        pub const CCP1IF: u8 = 1 << 2;
+        pub const TMR2IF: u8 = 1 << 1;
+        pub const TMR1IF: u8 = 1 << 0;
+    }
+
+    impl ADCON0 {
+        /// channel select
+        pub const CHS: u8 = 0b0011_1000;
+        /// set to start a conversion, cleared by hardware when it is done
+        pub const GO: u8 = 1 << 2;
+        pub const ADON: u8 = 1 << 0;
+
+        // the PIC12F675 lays it out differently, see `AdcLayout::Ansel`
+        pub const ADFM_12F: u8 = 1 << 7;
+        pub const CHS_12F: u8 = 0b0000_1100;
+        pub const GO_12F: u8 = 1 << 1;
+    }
+
+    impl ANSEL {
+        /// A/D conversion clock select, PIC12F675 only
+        pub const ADCS: u8 = 0b0111_0000;
+    }
+
+    impl OPTION_REG {
+        /// PORTB pull-up enable, active low
+        pub const RBPU: u8 = 1 << 7;
+        /// the same bit on the PIC12F675, where it gates the pull-ups enabled in WPU
+        pub const GPPU: u8 = 1 << 7;
+        /// TMR0 counts edges on T0CKI instead of the instruction clock
+        pub const T0CS: u8 = 1 << 5;
+        /// T0CKI counts falling edges instead of rising ones
+        pub const T0SE: u8 = 1 << 4;
+        /// prescaler assigned to the watchdog instead of TMR0
+        pub const PSA: u8 = 1 << 3;
+        /// prescaler rate, 1:2^(PS+1) for TMR0 and 1:2^PS for the watchdog
+        pub const PS: u8 = 0b0000_0111;
+    }
+
+    impl EECON1 {
+        /// program memory instead of the data EEPROM, not on the PIC16F84A and PIC12F675
+        pub const EEPGD: u8 = 1 << 7;
+        /// a write was cut short by a reset
+        pub const WRERR: u8 = 1 << 3;
+        pub const WREN: u8 = 1 << 2;
+        pub const WR: u8 = 1 << 1;
+        pub const RD: u8 = 1 << 0;
+    }
+
+    impl WDTCON {
+        /// watchdog prescaler, 1:32 << WDTPS
+        pub const WDTPS: u8 = 0b0001_1110;
+    }
+
+    impl PCON {
+        /// cleared by a power-on reset
+        pub const POR: u8 = 1 << 1;
+        /// cleared by a brown-out reset
+        pub const BOR: u8 = 1 << 0;
+    }
+
+    impl ADCON1 {
+        /// right-justified result
+        pub const ADFM: u8 = 1 << 7;
+        pub const ADCS2: u8 = 1 << 6;
+    }
+
+    /// bits of PIE2 and PIR2
+    impl PIR2 {
+        pub const OSFIF: u8 = 1 << 7;
+        pub const CMIF: u8 = 1 << 6;
+        pub const EEIF: u8 = 1 << 4;
+    }
+
+    bitflags::bitflags! {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        pub struct STATUS: u8 {
+            const IRP = 1 << 7;
+            const RP1 = 1 << 6;
+            const RP0 = 1 << 5;
+            const TO  = 1 << 4;
+            const PD  = 1 << 3;
+            const Z   = 1 << 2;
+            const DC  = 1 << 1;
+            const C   = 1 << 0;
+        }
+    }
+
+    impl STATUS {
+        fn new() -> Self {
+            Self::from_bits(Self::INITIAL_VALUE).unwrap()
+        }
+    }
+    impl Register for STATUS {
+        fn read(&self) -> u8 {
+            self.bits()
+        }
+
+        fn write(&mut self, v: u8) {
+            *self = Self::from_bits(v).unwrap();
+        }
+    }
+
+    use special_registers;
+}
+
+#[cfg(test)]
+use crate::vm::p16f88::P16F88;
+
+#[cfg(test)]
+struct NullTicker;
+#[cfg(test)]
+impl Ticker for NullTicker {
+    fn tick(&mut self, _vm: &P16F88, _cycles: u8) {}
+}
+
+/// vm running NOPs from 0x0010 with the given INTCON/PIE1/PIR1
+#[cfg(test)]
+fn interrupt_test_vm(intcon: u8, pie1: u8, pir1: u8) -> P16F88 {
+    let mut vm = P16F88::new([0; 7168]);
+    vm.pc = 0x0010;
+    vm.register.special.intcon_mut().0 = intcon;
+    vm.register.special.pie1_mut().0 = pie1;
+    vm.register.special.pir1_mut().0 = pir1;
+    vm
+}
+
+#[test]
+fn peripheral_interrupt_vectors_only_when_enabled() {
+    use reg::{INTCON, PIR1};
+
+    let all = INTCON::GIE | INTCON::PEIE;
+    #[rustfmt::skip]
+    let cases = [
+        // intcon               pie1          pir1                         vectors
+        (all,                   PIR1::TMR1IF, PIR1::TMR1IF,                true),
+        (all,                   PIR1::RCIF,   PIR1::RCIF | PIR1::TMR1IF,   true),
+        (all,                   PIR1::TMR1IF, PIR1::RCIF,                  false),
+        (all,                   0,            0xFF,                        false),
+        (INTCON::GIE,           PIR1::TMR1IF, PIR1::TMR1IF,                false),
+        (INTCON::PEIE,          PIR1::TMR1IF, PIR1::TMR1IF,                false),
+    ];
+
+    for (intcon, pie1, pir1, vectors) in cases {
+        let mut vm = interrupt_test_vm(intcon, pie1, pir1);
+        vm.step(&mut NullTicker).unwrap();
+        if vectors {
+            assert_eq!(vm.pc, 0x0004, "{intcon:#010b} {pie1:#010b} {pir1:#010b}");
+            assert_eq!(vm.call_stack.as_slice(), &[0x0010]);
+            assert_eq!(vm.register.special.intcon().0 & INTCON::GIE, 0);
+        } else {
+            assert_eq!(vm.pc, 0x0011, "{intcon:#010b} {pie1:#010b} {pir1:#010b}");
+            assert!(vm.call_stack.is_empty());
+        }
+    }
+}
+
+#[test]
+fn peripheral2_interrupt_is_gated_by_pie2() {
+    use reg::{INTCON, PIR2};
+
+    let mut vm = interrupt_test_vm(INTCON::GIE | INTCON::PEIE, 0, 0);
+    vm.register.special.pir2_mut().0 = PIR2::EEIF;
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.pc, 0x0011);
+
+    vm.register.special.pie2_mut().0 = PIR2::EEIF;
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.pc, 0x0004);
+}
+
+#[test]
+fn retfie_returns_and_reenables_interrupts() {
+    use reg::{INTCON, PIR1};
+
+    let mut vm = interrupt_test_vm(INTCON::GIE | INTCON::PEIE, PIR1::ADIF, PIR1::ADIF);
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.pc, 0x0004);
+
+    // handler clears the flag and returns
+    vm.register.special.pir1_mut().0 = 0;
+    vm.exec(
+        Instruction::Control(ControlInstruction::ReturnFromInterrupt),
+        &mut NullTicker,
+    )
+    .unwrap();
+    assert_eq!(vm.pc, 0x0010);
+    assert_ne!(vm.register.special.intcon().0 & INTCON::GIE, 0);
+
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.pc, 0x0011);
+}
+
+#[test]
+fn masked_peripheral_does_not_wake_from_sleep() {
+    use reg::{INTCON, PIR1};
+
+    let mut vm = interrupt_test_vm(INTCON::PEIE, 0, 0);
+    vm.exec(
+        Instruction::Control(ControlInstruction::Sleep),
+        &mut NullTicker,
+    )
+    .unwrap();
+    assert!(vm.sleeping);
+
+    vm.register.special.pir1_mut().0 = PIR1::TMR1IF;
+    vm.step(&mut NullTicker).unwrap();
+    assert!(vm.sleeping);
+    assert_eq!(vm.pc, 0x0011);
+
+    // GIE is clear, so waking up continues after SLEEP instead of vectoring
+    vm.register.special.pie1_mut().0 = PIR1::TMR1IF;
+    vm.step(&mut NullTicker).unwrap();
+    assert!(!vm.sleeping);
+    assert_eq!(vm.pc, 0x0012);
+}
+
+#[test]
+fn breakpoint_stops_once_then_resumes() {
+    let mut vm = P16F88::new([0; 7168]);
+    vm.add_breakpoint(ProgramAddr(0x0003));
+
+    assert_eq!(
+        vm.run(&mut NullTicker),
+        Ok(Stopped::Breakpoint(ProgramAddr(0x0003)))
+    );
+    assert_eq!(vm.pc, 0x0003);
+
+    assert_eq!(vm.step(&mut NullTicker), Ok(None));
+    assert_eq!(vm.pc, 0x0004);
+
+    vm.pc = 0x0003;
+    assert_eq!(
+        vm.step(&mut NullTicker),
+        Ok(Some(Stopped::Breakpoint(ProgramAddr(0x0003))))
+    );
+
+    vm.remove_breakpoint(ProgramAddr(0x0003));
+    vm.pc = 0x0003;
+    assert_eq!(vm.step(&mut NullTicker), Ok(None));
+}
+
+#[test]
+fn conditional_breakpoint_stops_when_it_holds() {
+    use crate::symbols::SymbolTable;
+
+    let flash = flash_of(&[
+        0x0AA0, // 0x0000: incf 0x20, f
+        0x2800, // 0x0001: goto 0x0000
+    ]);
+    let mut vm = P16F88::builder().flash(&flash).build();
+    let condition = Expr::parse("[0x20] == 3", &SymbolTable::new(), vm.device()).unwrap();
+    vm.add_breakpoint_if(ProgramAddr(0x0001), condition.clone());
+    assert_eq!(
+        vm.breakpoint_condition(ProgramAddr(0x0001)),
+        Some(&condition)
+    );
+    assert_eq!(
+        vm.run(&mut NullTicker),
+        Ok(Stopped::Breakpoint(ProgramAddr(0x0001)))
+    );
+    assert_eq!(vm.peek(0x20), 3);
+
+    vm.add_breakpoint(ProgramAddr(0x0001));
+    assert_eq!(vm.breakpoint_condition(ProgramAddr(0x0001)), None);
+    vm.step(&mut NullTicker).unwrap();
+    vm.run(&mut NullTicker).unwrap();
+    assert_eq!(vm.peek(0x20), 4);
+}
+
+#[test]
+fn step_over_out_and_run_to() {
+    let words: [u16; 6] = [
+        0b10_0000_0000_0100, // 0x0000: call 0x0004
+        0b00_0000_0000_0000, // 0x0001: nop
+        0b10_1000_0000_0010, // 0x0002: goto 0x0002
+        0b00_0000_0000_0000, // 0x0003: nop
+        0b00_0000_0000_0000, // 0x0004: nop
+        0b00_0000_0000_1000, // 0x0005: return
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    let mut vm = P16F88::new(flash);
+
+    assert_eq!(vm.step_over(&mut NullTicker), Ok(Stopped::Stepped));
+    assert_eq!((vm.pc, vm.call_stack.len()), (0x0001, 0));
+
+    vm.pc = 0x0000;
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.pc, 0x0004);
+    assert_eq!(vm.step_out(&mut NullTicker), Ok(Stopped::Stepped));
+    assert_eq!((vm.pc, vm.call_stack.len()), (0x0001, 0));
+
+    vm.pc = 0x0000;
+    assert_eq!(
+        vm.run_to(ProgramAddr(0x0005), &mut NullTicker),
+        Ok(Stopped::Reached(ProgramAddr(0x0005)))
+    );
+    assert_eq!(vm.call_stack.len(), 1);
+
+    // breakpoints inside the callee still stop step-over
+    vm.pc = 0x0000;
+    vm.call_stack.clear();
+    vm.add_breakpoint(ProgramAddr(0x0004));
+    assert_eq!(
+        vm.step_over(&mut NullTicker),
+        Ok(Stopped::Breakpoint(ProgramAddr(0x0004)))
+    );
+}
+
+#[test]
+fn run_for_and_until() {
+    // 0x0000: goto 0x0000
+    let mut flash = [0; 7168];
+    flash[..2].copy_from_slice(&0b10_1000_0000_0000u16.to_le_bytes());
+    let mut vm = P16F88::new(flash);
+
+    let run = vm.run_for_cycles(5, &mut ());
+    assert_eq!(run, Run { cycles: 6, exit: RunExit::Budget });
+
+    // 20 MHz: 1 us is 5 cycles
+    let run = vm.run_for(Duration::from_micros(1), &mut ());
+    assert_eq!(run.cycles, 6);
+
+    vm.add_breakpoint(ProgramAddr(0x0000));
+    let run = vm.run_for_cycles(100, &mut ());
+    assert_eq!(
+        run.exit,
+        RunExit::Stopped(Stopped::Breakpoint(ProgramAddr(0x0000)))
+    );
+
+    let mut vm = P16F88::new([0; 7168]);
+    let run = vm.run_until(|vm| vm.pc() == 3, &mut ());
+    assert_eq!(run, Run { cycles: 3, exit: RunExit::Condition });
+    assert_eq!(vm.run_until(|vm| vm.pc() == 3, &mut ()).cycles, 0);
+}
+
+#[test]
+fn cancel_token_interrupts_runs() {
+    let mut vm = P16F88::new([0; 7168]);
+    let never = |_: &P16F88| false;
+
+    let token = CancelToken::new();
+    let run = vm.run_until_cancellable(|vm| vm.pc() == 3, &token, &mut ());
+    assert_eq!(run.exit, RunExit::Condition);
+
+    token.clone().cancel();
+    let run = vm.run_until_cancellable(never, &token, &mut ());
+    assert_eq!(run, Run { cycles: 0, exit: RunExit::Cancelled });
+
+    let token = CancelToken::new().with_budget(Duration::ZERO);
+    let run = vm.run_until_cancellable(never, &token, &mut ());
+    assert_eq!(run, Run { cycles: 0, exit: RunExit::Cancelled });
+}
+
+#[test]
+fn decode_cache_follows_flash_writes() {
+    let mut vm = P16F88::new([0; 7168]);
+    vm.step(&mut ()).unwrap();
+    assert_eq!(vm.pc, 0x0001);
+
+    // 0x0000: movlw 0x42
+    vm.flash[..2].copy_from_slice(&0b11_0000_0100_0010u16.to_le_bytes());
+    vm.pc = 0x0000;
+    vm.step(&mut ()).unwrap();
+    assert_eq!(vm.w, 0x42);
+}
+
+#[test]
+fn decf_decrements() {
+    // 0x0000: decf 0x20, f
+    let mut flash = [0; 7168];
+    flash[..2].copy_from_slice(&0b00_0011_1010_0000u16.to_le_bytes());
+    let mut vm = P16F88::new(flash);
+    vm.register.gpr[0].0 = 1;
+
+    vm.step(&mut ()).unwrap();
+    assert_eq!(vm.register.gpr[0].0, 0);
+    assert!(vm.register.special.status().contains(reg::STATUS::Z));
+}
+
+#[test]
+fn arithmetic_and_logic_flags() {
+    use reg::STATUS;
+
+    const C: STATUS = STATUS::C;
+    const DC: STATUS = STATUS::DC;
+    const Z: STATUS = STATUS::Z;
+    const NONE: STATUS = STATUS::empty();
+
+    /// W and the flags after running `code` with W = `w` and gpr[0] (0x20) = `f`
+    fn exec(code: u16, w: u8, f: u8) -> (u8, STATUS) {
+        let mut vm = P16F88::new([0; 7168]);
+        vm.w = w;
+        vm.register.gpr[0].0 = f;
+        // flags the instruction leaves alone would show up as set
+        vm.register.special.status_mut().insert(C | DC | Z);
+        vm.exec(Instruction::from_code(code).unwrap(), &mut ())
+            .unwrap();
+        (vm.w, *vm.register.special.status() & (C | DC | Z))
+    }
+
+    #[rustfmt::skip]
+    let adds = [
+        // a     b     a + b  flags
+        (0x00, 0x00, 0x00, Z),
+        (0x0F, 0x01, 0x10, DC),
+        (0x08, 0x08, 0x10, DC),
+        (0xF0, 0x0F, 0xFF, NONE),
+        (0x7F, 0x7F, 0xFE, DC),
+        (0x80, 0x80, 0x00, C | Z),
+        (0xFF, 0x01, 0x00, C | DC | Z),
+        (0xFF, 0xFF, 0xFE, C | DC),
+    ];
+    for (a, b, sum, flags) in adds {
+        // addwf 0x20, w
+        assert_eq!(
+            exec(0x0720, a, b),
+            (sum, flags),
+            "addwf {a:#04x} + {b:#04x}"
+        );
+        // addlw b
+        assert_eq!(
+            exec(0x3E00 | b as u16, a, 0),
+            (sum, flags),
+            "addlw {a:#04x} + {b:#04x}"
+        );
+    }
+
+    // C and DC are set when there is no borrow
+    #[rustfmt::skip]
+    let subs = [
+        // a     W     a - W  flags
+        (0x00, 0x00, 0x00, C | DC | Z),
+        (0x05, 0x00, 0x05, C | DC),
+        (0x05, 0x05, 0x00, C | DC | Z),
+        (0x02, 0x01, 0x01, C | DC),
+        (0x02, 0x03, 0xFF, NONE),
+        (0x10, 0x01, 0x0F, C),
+        (0x01, 0x10, 0xF1, DC),
+        (0x00, 0xFF, 0x01, NONE),
+        (0xFF, 0xFF, 0x00, C | DC | Z),
+    ];
+    for (a, w, diff, flags) in subs {
+        // subwf 0x20, w
+        assert_eq!(
+            exec(0x0220, w, a),
+            (diff, flags),
+            "subwf {a:#04x} - {w:#04x}"
+        );
+        // sublw a
+        assert_eq!(
+            exec(0x3C00 | a as u16, w, 0),
+            (diff, flags),
+            "sublw {a:#04x} - {w:#04x}"
+        );
+    }
+
+    // only Z, C and DC are left as they were
+    #[rustfmt::skip]
+    let logic = [
+        // code                         W     f     W after  flags
+        (0x3900 | 0xF0, /* andlw */    0x0F, 0x00, 0x00,    C | DC | Z),
+        (0x3900 | 0xF0, /* andlw */    0x1F, 0x00, 0x10,    C | DC),
+        (0x3800,        /* iorlw 0 */  0x00, 0x00, 0x00,    C | DC | Z),
+        (0x3A00 | 0xFF, /* xorlw */    0xFF, 0x00, 0x00,    C | DC | Z),
+        (0x3A00 | 0xFF, /* xorlw */    0x0F, 0x00, 0xF0,    C | DC),
+        (0x3000,        /* movlw 0 */  0x55, 0x00, 0x00,    C | DC | Z),
+        (0x0520,        /* andwf */    0xF0, 0x0F, 0x00,    C | DC | Z),
+        (0x0420,        /* iorwf */    0x00, 0x01, 0x01,    C | DC),
+        (0x0620,        /* xorwf */    0x5A, 0x5A, 0x00,    C | DC | Z),
+        (0x0920,        /* comf */     0x00, 0xFF, 0x00,    C | DC | Z),
+        (0x0A20,        /* incf */     0x00, 0xFF, 0x00,    C | DC | Z),
+        (0x0320,        /* decf */     0x00, 0x00, 0xFF,    C | DC),
+        (0x0820,        /* movf */     0x55, 0x00, 0x00,    C | DC | Z),
+        (0x0E20,        /* swapf */    0x00, 0x00, 0x00,    C | DC | Z),
+        (0x0D20,        /* rlf */      0x00, 0x80, 0x01,    C | DC | Z),
+        (0x0C20,        /* rrf */      0x00, 0x02, 0x81,    DC | Z),
+    ];
+    for (code, w, f, after, flags) in logic {
+        assert_eq!(exec(code, w, f), (after, flags), "{code:#06x}");
+    }
+}
+
+#[test]
+fn memory_hooks_see_accesses() {
+    #[derive(Default)]
+    struct Log(Vec<(&'static str, MemoryAccess)>);
+    impl Ticker for Log {
+        fn tick(&mut self, _vm: &P16F88, _cycles: u8) {}
+        fn on_read(&mut self, access: MemoryAccess) {
+            self.0.push(("r", access));
+        }
+        fn on_write(&mut self, access: MemoryAccess) {
+            self.0.push(("w", access));
+        }
+    }
+
+    let words: [u16; 3] = [
+        0b11_0000_0000_0101, // 0x0000: movlw 5
+        0b00_0000_1010_0000, // 0x0001: movwf 0x20
+        0b00_1010_1010_0000, // 0x0002: incf 0x20, f
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    let mut vm = P16F88::new(flash);
+    let mut log = Log::default();
+    vm.run_for_cycles(3, &mut log);
+
+    let at = |pc, old, new| MemoryAccess {
+        pc,
+        addr: RegisterFileAddr(0x20),
+        bank: 0,
+        old,
+        new,
+    };
+    assert_eq!(
+        log.0,
+        [("w", at(1, 0, 5)), ("r", at(2, 5, 5)), ("w", at(2, 5, 6))]
+    );
+}
+
+#[test]
+fn adc_converts_selected_channel() {
+    use reg::{ADCON0, ADCON1, PIR1};
+
+    let mut vm = P16F88::new([0; 7168]);
+    vm.set_analog_input(Pin::rb(6).analog_channel().unwrap(), 2.5);
+    // AN5, Fosc/2
+    vm.register.special.adcon1_mut().0 = ADCON1::ADFM;
+    vm.register.special.adcon0_mut().0 = (5 << 3) | ADCON0::GO | ADCON0::ADON;
+
+    // 11 TAD = 22 clocks = 6 cycles
+    vm.run_for_cycles(5, &mut ());
+    assert_ne!(vm.register.special.adcon0().0 & ADCON0::GO, 0);
+    vm.step(&mut ()).unwrap();
+    assert_eq!(vm.register.special.adcon0().0 & ADCON0::GO, 0);
+    assert_ne!(vm.register.special.pir1().0 & PIR1::ADIF, 0);
+
+    let sp = &vm.register.special;
+    assert_eq!(((sp.adresh().0 as u16) << 8) | sp.adresl().0 as u16, 512);
+}
+
+#[test]
+fn portb_weak_pull_ups() {
+    use reg::OPTION_REG;
+
+    let mut vm = P16F88::new([0; 7168]);
+    vm.set_pin_input(Pin::rb(1), false);
+    vm.set_pin_input(Pin::ra(1), false);
+    vm.release_pin_input(Pin::ra(1));
+    let read = |vm: &mut P16F88, addr| vm.register.at(RegisterFileAddr(addr)).read();
+
+    // floating pins read low while the pull-ups are off (RBPU set at reset)
+    assert_eq!(read(&mut vm, 0x06), 0b0000_0000);
+
+    vm.register.special.option_reg_mut().0 &= !OPTION_REG::RBPU;
+    // RB1 is still driven low; PORTA has no pull-ups. RB6 and RB7 are analog out of reset
+    assert_eq!(read(&mut vm, 0x06), 0b0011_1101);
+    assert_eq!(read(&mut vm, 0x05), 0b0000_0000);
+
+    vm.release_pin_input(Pin::rb(1));
+    vm.register.special.ansel_mut().0 = 0;
+    assert_eq!(read(&mut vm, 0x06), 0b1111_1111);
+}
+
+#[test]
+fn analog_pins_read_0_and_ra4_is_open_drain() {
+    use crate::vm::device::P16F84A;
+
+    let read = |vm: &mut Pic14, addr| vm.register.at(RegisterFileAddr(addr)).read();
+
+    let mut vm = P16F88::new([0; 7168]);
+    vm.set_pin_input(Pin::ra(0), true);
+    vm.set_pin_input(Pin::rb(7), true);
+    vm.set_pin_input(Pin::rb(5), true);
+    assert_eq!(read(&mut vm, 0x05), 0b0000_0000);
+    assert_eq!(read(&mut vm, 0x06), 0b0010_0000);
+    assert!(vm.pin_level(Pin::ra(0)));
+    // analog outputs still drive the pin, the firmware just can't read it back
+    vm.register.special.trisa_mut().0 = 0b1111_1101;
+    vm.register.special.porta_mut().latch = 0b0000_0010;
+    assert_eq!(read(&mut vm, 0x05), 0b0000_0000);
+    assert_eq!(vm.pin_output(Pin::ra(1)), Some(true));
+    vm.register.special.ansel_mut().0 = 0;
+    assert_eq!(read(&mut vm, 0x05), 0b0000_0011);
+    assert_eq!(read(&mut vm, 0x06), 0b1010_0000);
+
+    // RA4 only pulls low, the pull-up is outside
+    let mut vm = Pic14::with_device(&P16F84A, &[0; 1024 * 2]);
+    vm.register.special.trisa_mut().0 = 0b0000_0000;
+    vm.register.special.porta_mut().latch = 0b0001_1000;
+    assert_eq!(vm.pin_output(Pin::ra(3)), Some(true));
+    assert_eq!(vm.pin_output(Pin::ra(4)), None);
+    assert_eq!(read(&mut vm, 0x05), 0b0000_1000);
+    vm.set_pin_input(Pin::ra(4), true);
+    assert_eq!(read(&mut vm, 0x05), 0b0001_1000);
+    assert!(vm.pin_level(Pin::ra(4)));
+    vm.register.special.porta_mut().latch = 0b0000_1000;
+    assert_eq!(vm.pin_output(Pin::ra(4)), Some(false));
+    assert_eq!(read(&mut vm, 0x05), 0b0000_1000);
+}
+
+#[test]
+fn snapshot_roundtrip() {
+    use reg::STATUS;
+
+    let mut vm = interrupt_test_vm(0, 0, 0);
+    vm.w = 0x42;
+    vm.call_stack.push(0x0123);
+    vm.register.gpr[5].0 = 0xAB;
+    vm.register
+        .special
+        .status_mut()
+        .insert(STATUS::Z | STATUS::RP0);
+    vm.register.special.porta_mut().input = 0b0001_0000;
+
+    let mut json = vec![];
+    vm.snapshot().save(&mut json).unwrap();
+    let mut restored = P16F88::new([0; 7168]);
+    restored
+        .restore(Snapshot::load(json.as_slice()).unwrap())
+        .unwrap();
+    assert_eq!(restored.snapshot(), vm.snapshot());
+
+    // written before snapshots were versioned
+    let unversioned = serde_json::to_vec(&vm.snapshot()).unwrap();
+    assert_eq!(
+        Snapshot::load(unversioned.as_slice()).unwrap(),
+        vm.snapshot()
+    );
+
+    let mut broken = vm.snapshot();
+    broken.call_stack = vec![0; 9];
+    assert_eq!(
+        restored.restore(broken),
+        Err(SnapshotError::CallStackTooDeep { depth: 9 })
+    );
+    assert_eq!(restored.w, 0x42);
+}
+
+#[test]
+fn state_hash_tracks_state() {
+    let vm = P16F88::new([0; 7168]);
+    let base = vm.state_hash(HashScope::Full);
+    assert_eq!(P16F88::new([0xFF; 7168]).state_hash(HashScope::Full), base);
+
+    let mut gpr = P16F88::new([0; 7168]);
+    gpr.register.gpr[100].0 = 1;
+    assert_ne!(gpr.state_hash(HashScope::Full), base);
+
+    // port inputs only count for the full scope
+    let mut input = P16F88::new([0; 7168]);
+    input.set_pin_input(Pin::rb(0), true);
+    assert_eq!(
+        input.state_hash(HashScope::Cpu),
+        vm.state_hash(HashScope::Cpu)
+    );
+    assert_ne!(input.state_hash(HashScope::Full), base);
+}
+
+/// assembles `words` from 0x0000
+#[cfg(test)]
+fn flash_of(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+#[test]
+fn builder_stack_behavior() {
+    // 0x0000: call 0x0000
+    let flash = flash_of(&[0x2000]);
+    let mut strict = P16F88::builder().flash(&flash).build();
+    let mut wrap = P16F88::builder()
+        .flash(&flash)
+        .stack_behavior(StackBehavior::Wrap)
+        .build();
+    for _ in 0..8 {
+        strict.step(&mut NullTicker).unwrap();
+    }
+    assert_eq!(
+        strict.step(&mut NullTicker),
+        Err(VmError::CallStackOverflow { pc: 0 })
+    );
+    for _ in 0..20 {
+        wrap.step(&mut NullTicker).unwrap();
+    }
+    assert_eq!(wrap.call_stack.as_slice(), &[0x0001; 8]);
+}
+
+#[test]
+fn builder_checks_uninitialized_reads() {
+    let flash = flash_of(&[
+        0x0821, // movf 0x21, w
+        0x00A0, // movwf 0x20
+        0x0820, // movf 0x20, w
+    ]);
+    let mut vm = P16F88::builder()
+        .flash(&flash)
+        .uninitialized_reads(Check::Error)
+        .build();
+    let Slot::Gpr(index) = vm.device().map[0][0x21] else {
+        unreachable!()
+    };
+    assert_eq!(
+        vm.step(&mut NullTicker),
+        Err(VmError::UninitializedRead { pc: 0, index })
+    );
+    // the instruction has completed
+    assert_eq!(vm.pc, 1);
+    vm.step(&mut NullTicker).unwrap();
+    vm.step(&mut NullTicker).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "reserved register")]
+fn builder_panics_on_reserved_access() {
+    let flash = flash_of(&[
+        0x1683, // bsf STATUS, RP0
+        0x1703, // bsf STATUS, RP1
+        0x018E, // clrf 0x0E (reserved in bank 3)
+    ]);
+    let mut vm = P16F88::builder()
+        .flash(&flash)
+        .reserved_access(ReservedAccess::Panic)
+        .build();
+    for _ in 0..3 {
+        let _ = vm.step(&mut NullTicker);
+    }
+}
+
+#[test]
+fn watchdog_resets_unless_cleared() {
+    use reg::{OPTION_REG, PCON, STATUS};
+
+    let run = |words: &[u16]| {
+        let mut vm = P16F88::builder()
+            .flash(&flash_of(words))
+            // 1 us per cycle. the watchdog runs out after 16.384 ms by default
+            .clock_hz(4_000_000)
+            .watchdog(true)
+            .build();
+        // the postscaler comes up assigned to the watchdog at 1:128
+        vm.register.special.option_reg_mut().0 &= !OPTION_REG::PSA;
+        vm.run_for_cycles(16_380, &mut NullTicker);
+        assert!(vm.register.special.status().contains(STATUS::TO));
+        vm.run_for_cycles(10, &mut NullTicker);
+        vm.register.special.status().contains(STATUS::TO)
+    };
+    // 0x0000: goto 0x0000
+    assert!(!run(&[0x2800]));
+    // 0x0000: clrwdt, 0x0001: goto 0x0000
+    assert!(run(&[0x0064, 0x2800]));
+
+    let vm = P16F88::builder().reset(Reset::BrownOut).build();
+    assert_eq!(vm.register.special.pcon().0, PCON::POR);
+    let vm = P16F88::builder().reset(Reset::Watchdog).build();
+    assert!(!vm.register.special.status().contains(STATUS::TO));
+}
+
+#[test]
+fn peeks_any_bank() {
+    let flash = flash_of(&[
+        0x3042, // movlw 0x42
+        0x1683, // bsf STATUS, RP0
+        0x00A0, // movwf 0x20 (0xA0 in bank 1)
+        0x0086, // movwf TRISB
+    ]);
+    let mut vm = P16F88::builder().flash(&flash).build();
+    for _ in 0..4 {
+        vm.step(&mut NullTicker).unwrap();
+    }
+    assert_eq!(vm.peek(0xA0), 0x42);
+    assert_eq!(vm.peek(0x20), 0);
+    assert_eq!(vm.peek(0x86), 0x42);
+    assert_eq!(vm.peek(0x03) & 0x20, 0x20);
+    assert_eq!(vm.peek(0x200), 0);
+}
+
+#[test]
+fn timer0_counts_t0cki_edges_and_cycles() {
+    use reg::{INTCON, OPTION_REG};
+
+    let mut vm = P16F88::new([0; 7168]);
+    let tmr0 = |vm: &P16F88| vm.register.special.tmr0().0;
+    let pulse = |vm: &mut P16F88, n| {
+        for _ in 0..n {
+            vm.set_pin_input(Pin::ra(4), true);
+            vm.set_pin_input(Pin::ra(4), false);
+        }
+    };
+
+    // counter mode on falling edges out of reset, the prescaler with the watchdog
+    pulse(&mut vm, 3);
+    vm.run_for_cycles(10, &mut NullTicker);
+    assert_eq!(tmr0(&vm), 3);
+
+    // rising edges, the last one left high
+    vm.register.special.option_reg_mut().0 &= !OPTION_REG::T0SE;
+    pulse(&mut vm, 2);
+    vm.set_pin_input(Pin::ra(4), true);
+    assert_eq!(tmr0(&vm), 6);
+
+    // 1:4
+    vm.set_pin_input(Pin::ra(4), false);
+    vm.register.special.option_reg_mut().0 &= !(OPTION_REG::PSA | OPTION_REG::PS);
+    vm.register.special.option_reg_mut().0 |= 1;
+    pulse(&mut vm, 9);
+    assert_eq!(tmr0(&vm), 8);
+
+    vm.register.special.option_reg_mut().0 |= OPTION_REG::PSA;
+    vm.register.special.tmr0_mut().0 = 0xFF;
+    assert_eq!(vm.register.special.intcon().0 & INTCON::TMR0IF, 0);
+    pulse(&mut vm, 1);
+    assert_eq!(tmr0(&vm), 0);
+    assert_ne!(vm.register.special.intcon().0 & INTCON::TMR0IF, 0);
+
+    // timer mode, not counting the write and the two cycles after it
+    let flash = flash_of(&[
+        0x0181, // clrf TMR0
+    ]);
+    let mut vm = P16F88::builder().flash(&flash).build();
+    vm.register.special.option_reg_mut().0 &= !OPTION_REG::T0CS;
+    vm.register.special.tmr0_mut().0 = 0x80;
+    vm.run_for_cycles(5, &mut NullTicker);
+    assert_eq!(tmr0(&vm), 2);
+    pulse(&mut vm, 3);
+    vm.run_for_cycles(10, &mut NullTicker);
+    assert_eq!(tmr0(&vm), 12);
+}
+
+#[test]
+fn eeprom_writes_survive_in_a_file() {
+    use reg::{EECON1, PIR2};
+
+    let path = std::env::temp_dir().join(format!("stk-eeprom-{}.bin", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let write = flash_of(&[
+        0x1703, // bsf STATUS, RP1
+        0x3005, // movlw 0x05
+        0x008D, // movwf EEADR
+        0x3042, // movlw 0x42
+        0x008C, // movwf EEDATA
+        0x1683, // bsf STATUS, RP0
+        0x138C, // bcf EECON1, EEPGD
+        0x150C, // bsf EECON1, WREN
+        0x3055, // movlw 0x55
+        0x008D, // movwf EECON2
+        0x30AA, // movlw 0xAA
+        0x008D, // movwf EECON2
+        0x148C, // bsf EECON1, WR
+        0x280D, // goto $
+    ]);
+    let mut vm = P16F88::builder()
+        .flash(&write)
+        .eeprom(&[1, 2, 3])
+        .eeprom_file(&path)
+        .build();
+    assert_eq!(vm.eeprom()[..4], [1, 2, 3, 0xFF]);
+    for _ in 0..13 {
+        vm.step(&mut NullTicker).unwrap();
+    }
+    assert_ne!(vm.register.special.eecon1().0 & EECON1::WR, 0);
+    assert_eq!(vm.eeprom()[5], 0xFF);
+    assert!(!path.exists());
+    vm.run_for(Duration::from_millis(5), &mut NullTicker);
+    assert_eq!(vm.register.special.eecon1().0 & EECON1::WR, 0);
+    assert_ne!(vm.register.special.pir2().0 & PIR2::EEIF, 0);
+    assert_eq!(vm.eeprom()[5], 0x42);
+    assert_eq!(std::fs::read(&path).unwrap(), vm.eeprom());
+
+    // without the unlock sequence WR doesn't stick
+    vm.register.special.eecon1_mut().0 = EECON1::WREN;
+    vm.pc = 0x000C;
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.register.special.eecon1().0 & EECON1::WR, 0);
+
+    let read = flash_of(&[
+        0x1703, // bsf STATUS, RP1
+        0x3005, // movlw 0x05
+        0x008D, // movwf EEADR
+        0x1683, // bsf STATUS, RP0
+        0x138C, // bcf EECON1, EEPGD
+        0x140C, // bsf EECON1, RD
+        0x1283, // bcf STATUS, RP0
+        0x080C, // movf EEDATA, w
+    ]);
+    let mut vm = P16F88::builder()
+        .flash(&read)
+        .eeprom(&[9; 4])
+        .eeprom_file(&path)
+        .build();
+    for _ in 0..8 {
+        vm.step(&mut NullTicker).unwrap();
+    }
+    assert_eq!(vm.w, 0x42);
+    assert_eq!(vm.eeprom()[..4], [1, 2, 3, 0xFF]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reading_rcreg_clears_rcif() {
+    use reg::PIR1;
+
+    let flash = flash_of(&[
+        0x080C, // movf PIR1, w
+        0x081A, // movf RCREG, w
+    ]);
+    let mut vm = P16F88::builder().flash(&flash).build();
+    vm.register.special.rcreg_mut().0 = 0x41;
+    vm.register.special.pir1_mut().0 = PIR1::RCIF;
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.w, PIR1::RCIF);
+    assert_eq!(vm.register.special.pir1().0, PIR1::RCIF);
+    vm.step(&mut NullTicker).unwrap();
+    assert_eq!(vm.w, 0x41);
+    assert_eq!(vm.register.special.pir1().0, 0);
+}
@@ -0,0 +1,753 @@
+//! PIC18 core. it shares nothing with [`Pic14`](super::pic14::Pic14) but the ideas: the
+//! instructions are 16 bits wide ([`crate::inst18`]), the 4K data memory is banked through
+//! BSR and an access bank, there is a hardware multiplier and a 31-level return stack.
+//!
+//! only the core is modeled. peripherals are plain memory, so firmware can configure them but
+//! nothing happens.
+
+use arrayvec::ArrayVec;
+
+use crate::inst::Destination;
+use crate::inst18::{
+    BitOperation, ByteOperation, Condition, FileOperation, Instruction, LiteralOperation, Ram,
+    TableOperation, TablePointer,
+};
+
+// datasheets:
+//   - PIC18F2420/2520/4420/4520: https://ww1.microchip.com/downloads/en/DeviceDoc/39631E.pdf
+
+/// addresses of the core SFRs in the data memory
+pub mod sfr {
+    pub const TOSU: u16 = 0xFFF;
+    pub const TOSH: u16 = 0xFFE;
+    pub const TOSL: u16 = 0xFFD;
+    pub const STKPTR: u16 = 0xFFC;
+    pub const PCLATU: u16 = 0xFFB;
+    pub const PCLATH: u16 = 0xFFA;
+    pub const PCL: u16 = 0xFF9;
+    pub const TBLPTRU: u16 = 0xFF8;
+    pub const TBLPTRH: u16 = 0xFF7;
+    pub const TBLPTRL: u16 = 0xFF6;
+    pub const TABLAT: u16 = 0xFF5;
+    pub const PRODH: u16 = 0xFF4;
+    pub const PRODL: u16 = 0xFF3;
+    pub const INTCON: u16 = 0xFF2;
+    pub const INDF0: u16 = 0xFEF;
+    pub const FSR0L: u16 = 0xFE9;
+    pub const WREG: u16 = 0xFE8;
+    pub const INDF1: u16 = 0xFE7;
+    pub const FSR1L: u16 = 0xFE1;
+    pub const BSR: u16 = 0xFE0;
+    pub const INDF2: u16 = 0xFDF;
+    pub const FSR2L: u16 = 0xFD9;
+    pub const STATUS: u16 = 0xFD8;
+    pub const PIR1: u16 = 0xF9E;
+    pub const PIE1: u16 = 0xF9D;
+}
+
+bitflags::bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct STATUS: u8 {
+        const N  = 1 << 4;
+        const OV = 1 << 3;
+        const Z  = 1 << 2;
+        const DC = 1 << 1;
+        const C  = 1 << 0;
+    }
+}
+
+/// bits of INTCON. with IPEN clear (the reset state) GIE and PEIE work as on the mid-range.
+pub struct INTCON;
+
+impl INTCON {
+    pub const GIE: u8 = 1 << 7;
+    pub const PEIE: u8 = 1 << 6;
+    pub const TMR0IE: u8 = 1 << 5;
+    pub const INT0IE: u8 = 1 << 4;
+    pub const RBIE: u8 = 1 << 3;
+    pub const TMR0IF: u8 = 1 << 2;
+    pub const INT0IF: u8 = 1 << 1;
+    pub const RBIF: u8 = 1 << 0;
+}
+
+/// f below this in the access bank is GPR of bank 0, above it the SFRs of bank 15
+pub const ACCESS_SPLIT: u8 = 0x80;
+
+/// levels of the hardware return stack
+pub const STACK_DEPTH: usize = 31;
+
+/// the program counter and table pointer are 21 bits wide
+const ADDR_MASK: u32 = 0x1F_FFFF;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum VmError {
+    #[error("pc {pc:#08x} is outside of the program memory")]
+    PcOutOfRange { pc: u32 },
+
+    #[error("couldn't decode {code:#06x} at {pc:#08x} into instruction")]
+    InvalidInstruction { pc: u32, code: u16 },
+
+    #[error("stack overflow at {pc:#08x}")]
+    StackOverflow { pc: u32 },
+
+    #[error("stack underflow at {pc:#08x}: stack has no return address")]
+    StackUnderflow { pc: u32 },
+}
+
+pub struct Pic18 {
+    pub w: u8,
+    /// byte address of the next instruction, always even
+    pub pc: u32,
+    pub flash: Box<[u8]>,
+    pub stack: ArrayVec<u32, STACK_DEPTH>,
+    /// the whole data memory, SFRs included. W and the registers with side effects (PCL,
+    /// INDFn and friends, TOS, STKPTR) are handled by [`Self::read`] and [`Self::write`].
+    pub data: Box<[u8; 4096]>,
+    /// halted by SLEEP until an interrupt source wakes it up
+    pub sleeping: bool,
+    /// W, STATUS and BSR saved on interrupts and fast calls
+    shadow: [u8; 3],
+}
+
+impl Pic18 {
+    /// `flash` is the program memory image, at most 2 MiB
+    pub fn new(flash: &[u8]) -> Self {
+        assert!(flash.len() % 2 == 0 && flash.len() <= ADDR_MASK as usize + 1);
+        Self {
+            w: 0,
+            pc: 0,
+            flash: flash.into(),
+            stack: ArrayVec::new(),
+            data: Box::new([0; 4096]),
+            sleeping: false,
+            shadow: [0; 3],
+        }
+    }
+
+    pub fn status(&self) -> STATUS {
+        STATUS::from_bits_truncate(self.data[sfr::STATUS as usize])
+    }
+
+    fn set_flag(&mut self, flag: STATUS, value: bool) {
+        let mut st = self.status();
+        st.set(flag, value);
+        self.data[sfr::STATUS as usize] = st.bits();
+    }
+
+    pub fn bsr(&self) -> u8 {
+        self.data[sfr::BSR as usize] & 0x0F
+    }
+
+    /// PRODH:PRODL
+    pub fn product(&self) -> u16 {
+        u16::from_le_bytes([
+            self.data[sfr::PRODL as usize],
+            self.data[sfr::PRODH as usize],
+        ])
+    }
+
+    fn fsr(&self, n: u16) -> u16 {
+        let at = (sfr::FSR0L - 8 * n) as usize;
+        u16::from_le_bytes([self.data[at], self.data[at + 1]]) & 0x0FFF
+    }
+
+    fn set_fsr(&mut self, n: u16, v: u16) {
+        let at = (sfr::FSR0L - 8 * n) as usize;
+        let [lo, hi] = (v & 0x0FFF).to_le_bytes();
+        self.data[at] = lo;
+        self.data[at + 1] = hi;
+    }
+
+    fn tblptr(&self) -> u32 {
+        u32::from_le_bytes([
+            self.data[sfr::TBLPTRL as usize],
+            self.data[sfr::TBLPTRH as usize],
+            self.data[sfr::TBLPTRU as usize],
+            0,
+        ])
+    }
+
+    fn set_tblptr(&mut self, v: u32) {
+        let [l, h, u, _] = (v & ADDR_MASK).to_le_bytes();
+        self.data[sfr::TBLPTRL as usize] = l;
+        self.data[sfr::TBLPTRH as usize] = h;
+        self.data[sfr::TBLPTRU as usize] = u;
+    }
+
+    /// data memory address `f` refers to
+    pub fn address(&self, f: u8, ram: Ram) -> u16 {
+        match ram {
+            Ram::Access if f < ACCESS_SPLIT => f as u16,
+            Ram::Access => 0xF00 | f as u16,
+            Ram::Banked => ((self.bsr() as u16) << 8) | f as u16,
+        }
+    }
+
+    /// resolves INDFn, POSTINCn, POSTDECn, PREINCn and PLUSWn to the address they point at,
+    /// updating FSRn. `None` for any other address.
+    fn indirect(&mut self, addr: u16) -> Option<u16> {
+        if !(sfr::FSR2L + 3..=sfr::INDF0).contains(&addr) {
+            return None;
+        }
+        let n = (sfr::INDF0 - addr) / 8;
+        let fsr = self.fsr(n);
+        match sfr::INDF0 - 8 * n - addr {
+            // INDFn
+            0 => Some(fsr),
+            // POSTINCn
+            1 => {
+                self.set_fsr(n, fsr.wrapping_add(1));
+                Some(fsr)
+            }
+            // POSTDECn
+            2 => {
+                self.set_fsr(n, fsr.wrapping_sub(1));
+                Some(fsr)
+            }
+            // PREINCn
+            3 => {
+                self.set_fsr(n, fsr.wrapping_add(1));
+                Some(fsr.wrapping_add(1) & 0x0FFF)
+            }
+            // PLUSWn
+            4 => Some(fsr.wrapping_add_signed(self.w as i8 as i16) & 0x0FFF),
+            _ => None,
+        }
+    }
+
+    pub fn read(&mut self, addr: u16) -> u8 {
+        let addr = match self.indirect(addr) {
+            // indirect access to the indirect registers themselves reads 0
+            Some(target) if self.is_indirect(target) => return 0,
+            Some(target) => target,
+            None => addr,
+        };
+        match addr {
+            sfr::WREG => self.w,
+            sfr::PCL => {
+                let [l, h, u, _] = self.pc.to_le_bytes();
+                self.data[sfr::PCLATH as usize] = h;
+                self.data[sfr::PCLATU as usize] = u;
+                l
+            }
+            sfr::STKPTR => self.stack.len() as u8,
+            sfr::TOSL | sfr::TOSH | sfr::TOSU => {
+                let tos = self.stack.last().copied().unwrap_or(0);
+                tos.to_le_bytes()[(addr - sfr::TOSL) as usize]
+            }
+            _ => self.data[addr as usize],
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, v: u8) {
+        let addr = match self.indirect(addr) {
+            Some(target) if self.is_indirect(target) => return,
+            Some(target) => target,
+            None => addr,
+        };
+        match addr {
+            sfr::WREG => self.w = v,
+            sfr::PCL => {
+                self.pc = u32::from_le_bytes([
+                    v & !1,
+                    self.data[sfr::PCLATH as usize],
+                    self.data[sfr::PCLATU as usize] & 0x1F,
+                    0,
+                ]);
+            }
+            // the depth is ours to track
+            sfr::STKPTR => {}
+            sfr::TOSL | sfr::TOSH | sfr::TOSU => {
+                if let Some(tos) = self.stack.last_mut() {
+                    let mut bytes = tos.to_le_bytes();
+                    bytes[(addr - sfr::TOSL) as usize] = v;
+                    *tos = u32::from_le_bytes(bytes) & ADDR_MASK;
+                }
+            }
+            _ => self.data[addr as usize] = v,
+        }
+    }
+
+    fn is_indirect(&self, addr: u16) -> bool {
+        (sfr::FSR2L + 3..=sfr::INDF0).contains(&addr) && (sfr::INDF0 - addr) % 8 <= 4
+    }
+
+    /// whether an enabled interrupt source has its flag set, regardless of GIE
+    pub fn interrupt_pending(&self) -> bool {
+        let intcon = self.data[sfr::INTCON as usize];
+        let core = (intcon >> 3) & intcon & (INTCON::TMR0IF | INTCON::INT0IF | INTCON::RBIF) != 0;
+        let peripheral = intcon & INTCON::PEIE != 0
+            && self.data[sfr::PIE1 as usize] & self.data[sfr::PIR1 as usize] != 0;
+        core || peripheral
+    }
+
+    /// the RESET instruction. the GPRs keep their contents
+    pub fn reset(&mut self) {
+        self.w = 0;
+        self.pc = 0;
+        self.stack.clear();
+        self.sleeping = false;
+        self.shadow = [0; 3];
+        self.data[0xF80..].fill(0);
+    }
+
+    fn word_at(&self, pc: u32) -> Option<u16> {
+        let at = pc as usize;
+        let bytes = self.flash.get(at..at + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// `pc` is the instruction pushing, for the error
+    fn push(&mut self, ret: u32, pc: u32) -> Result<(), VmError> {
+        self.stack
+            .try_push(ret)
+            .map_err(|_| VmError::StackOverflow { pc })
+    }
+
+    fn pop(&mut self, pc: u32) -> Result<u32, VmError> {
+        self.stack.pop().ok_or(VmError::StackUnderflow { pc })
+    }
+
+    fn save_shadow(&mut self) {
+        self.shadow = [
+            self.w,
+            self.data[sfr::STATUS as usize],
+            self.data[sfr::BSR as usize],
+        ];
+    }
+
+    fn restore_shadow(&mut self) {
+        let [w, status, bsr] = self.shadow;
+        self.w = w;
+        self.data[sfr::STATUS as usize] = status;
+        self.data[sfr::BSR as usize] = bsr;
+    }
+
+    /// executes one instruction, or vectors to the interrupt handler if an interrupt is pending.
+    /// returns the instruction cycles it took. on error, the vm is left as it was before the
+    /// instruction.
+    pub fn step(&mut self) -> Result<u8, VmError> {
+        let pending = self.interrupt_pending();
+        if self.sleeping {
+            if !pending {
+                return Ok(1);
+            }
+            self.sleeping = false;
+        }
+
+        if pending && self.data[sfr::INTCON as usize] & INTCON::GIE != 0 {
+            self.push(self.pc, self.pc)?;
+            self.save_shadow();
+            self.data[sfr::INTCON as usize] &= !INTCON::GIE;
+            self.pc = 0x0008;
+            return Ok(2);
+        }
+
+        let pc = self.pc;
+        let code = self.word_at(pc).ok_or(VmError::PcOutOfRange { pc })?;
+        let next = self.word_at(pc + 2).unwrap_or(0);
+        let inst =
+            Instruction::from_code(code, next).ok_or(VmError::InvalidInstruction { pc, code })?;
+
+        // the pc already points at the next instruction while this one runs, like on hardware
+        self.pc = (pc + 2 * inst.words() as u32) & ADDR_MASK;
+        let taken = match self.exec(inst, pc) {
+            Ok(taken) => taken,
+            Err(e) => {
+                self.pc = pc;
+                return Err(e);
+            }
+        };
+        let mut cycles = inst.cycle_cost(taken);
+        if taken && Self::skips(inst) {
+            // skipping a two-word instruction takes one more cycle
+            let skipped = self.word_at(self.pc).unwrap_or(0);
+            let after = self.word_at(self.pc + 2).unwrap_or(0);
+            let words = Instruction::from_code(skipped, after).map_or(1, |i| i.words());
+            self.pc = (self.pc + 2 * words as u32) & ADDR_MASK;
+            cycles += words - 1;
+        }
+        Ok(cycles)
+    }
+
+    fn skips(inst: Instruction) -> bool {
+        use ByteOperation::*;
+
+        matches!(
+            inst,
+            Instruction::Byte {
+                op: DecrementFSkipIfZ
+                    | DecrementFSkipIfNotZ
+                    | IncrementFSkipIfZ
+                    | IncrementFSkipIfNotZ,
+                ..
+            } | Instruction::File {
+                op: FileOperation::CompareSkipIfEqual
+                    | FileOperation::CompareSkipIfGreater
+                    | FileOperation::CompareSkipIfLess
+                    | FileOperation::TestFSkipIfZ,
+                ..
+            } | Instruction::Bit {
+                op: BitOperation::SkipIfFBitClear | BitOperation::SkipIfFBitSet,
+                ..
+            }
+        )
+    }
+
+    /// a + b + carry, setting C, DC, Z, OV and N
+    fn add(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let wide = a as u16 + b as u16 + carry as u16;
+        let ret = wide as u8;
+        let mut st = self.status();
+        st.set(STATUS::C, wide > 0xFF);
+        st.set(STATUS::DC, (a & 0x0F) + (b & 0x0F) + carry as u8 > 0x0F);
+        st.set(STATUS::OV, (a ^ ret) & (b ^ ret) & 0x80 != 0);
+        st.set(STATUS::Z, ret == 0);
+        st.set(STATUS::N, ret & 0x80 != 0);
+        self.data[sfr::STATUS as usize] = st.bits();
+        ret
+    }
+
+    /// sets Z and N from `v`
+    fn set_zn(&mut self, v: u8) -> u8 {
+        self.set_flag(STATUS::Z, v == 0);
+        self.set_flag(STATUS::N, v & 0x80 != 0);
+        v
+    }
+
+    fn multiply(&mut self, a: u8, b: u8) {
+        let [lo, hi] = (a as u16 * b as u16).to_le_bytes();
+        self.data[sfr::PRODL as usize] = lo;
+        self.data[sfr::PRODH as usize] = hi;
+    }
+
+    /// `offset` words from the current pc
+    fn relative(&self, offset: i16) -> u32 {
+        (self.pc as i32 + 2 * offset as i32) as u32 & ADDR_MASK
+    }
+
+    /// runs `inst` at `pc`, with `self.pc` already past it. returns whether a branch was taken or
+    /// the next instruction is to be skipped.
+    fn exec(&mut self, inst: Instruction, pc: u32) -> Result<bool, VmError> {
+        match inst {
+            Instruction::Byte { op, f, dest, ram } => {
+                let addr = self.address(f, ram);
+                let x = self.read(addr);
+                let ret = self.exec_byte(op, x);
+                match dest {
+                    Destination::W => self.w = ret,
+                    Destination::F => self.write(addr, ret),
+                }
+                Ok(match op {
+                    ByteOperation::DecrementFSkipIfZ | ByteOperation::IncrementFSkipIfZ => ret == 0,
+                    ByteOperation::DecrementFSkipIfNotZ | ByteOperation::IncrementFSkipIfNotZ => {
+                        ret != 0
+                    }
+                    _ => false,
+                })
+            }
+            Instruction::File { op, f, ram } => Ok(self.exec_file(op, self.address(f, ram))),
+            Instruction::Bit { op, f, b, ram } => {
+                let addr = self.address(f, ram);
+                let mask = 1 << b;
+                let x = self.read(addr);
+                Ok(match op {
+                    BitOperation::BitClearF => {
+                        self.write(addr, x & !mask);
+                        false
+                    }
+                    BitOperation::BitSetF => {
+                        self.write(addr, x | mask);
+                        false
+                    }
+                    BitOperation::BitToggleF => {
+                        self.write(addr, x ^ mask);
+                        false
+                    }
+                    BitOperation::SkipIfFBitClear => x & mask == 0,
+                    BitOperation::SkipIfFBitSet => x & mask != 0,
+                })
+            }
+            Instruction::Literal { op, k } => {
+                self.exec_literal(op, k, pc)?;
+                Ok(false)
+            }
+            Instruction::MoveLiteralToBsr { k } => {
+                self.data[sfr::BSR as usize] = k & 0x0F;
+                Ok(false)
+            }
+            Instruction::MoveFf { src, dst } => {
+                let v = self.read(src);
+                self.write(dst, v);
+                Ok(false)
+            }
+            Instruction::LoadFsr { fsr, k } => {
+                self.set_fsr(fsr as u16, k);
+                Ok(false)
+            }
+            Instruction::Branch { cond, offset } => {
+                let st = self.status();
+                let taken = match cond {
+                    Condition::Zero => st.contains(STATUS::Z),
+                    Condition::NotZero => !st.contains(STATUS::Z),
+                    Condition::Carry => st.contains(STATUS::C),
+                    Condition::NotCarry => !st.contains(STATUS::C),
+                    Condition::Overflow => st.contains(STATUS::OV),
+                    Condition::NotOverflow => !st.contains(STATUS::OV),
+                    Condition::Negative => st.contains(STATUS::N),
+                    Condition::NotNegative => !st.contains(STATUS::N),
+                };
+                if taken {
+                    self.pc = self.relative(offset as i16);
+                }
+                Ok(taken)
+            }
+            Instruction::BranchAlways { offset } => {
+                self.pc = self.relative(offset);
+                Ok(false)
+            }
+            Instruction::RelativeCall { offset } => {
+                self.push(self.pc, pc)?;
+                self.pc = self.relative(offset);
+                Ok(false)
+            }
+            Instruction::Call { addr, fast } => {
+                self.push(self.pc, pc)?;
+                if fast {
+                    self.save_shadow();
+                }
+                self.pc = (addr * 2) & ADDR_MASK;
+                Ok(false)
+            }
+            Instruction::Goto { addr } => {
+                self.pc = (addr * 2) & ADDR_MASK;
+                Ok(false)
+            }
+            Instruction::Return { fast } | Instruction::ReturnFromInterrupt { fast } => {
+                self.pc = self.pop(pc)?;
+                if fast {
+                    self.restore_shadow();
+                }
+                if matches!(inst, Instruction::ReturnFromInterrupt { .. }) {
+                    self.data[sfr::INTCON as usize] |= INTCON::GIE;
+                }
+                Ok(false)
+            }
+            Instruction::Table { op } => {
+                self.exec_table(op);
+                Ok(false)
+            }
+            Instruction::DecimalAdjustW => {
+                let st = self.status();
+                let mut w = self.w as u16;
+                if w & 0x0F > 9 || st.contains(STATUS::DC) {
+                    w += 0x06;
+                }
+                if w > 0x9F || st.contains(STATUS::C) {
+                    w += 0x60;
+                }
+                self.w = w as u8;
+                self.set_flag(STATUS::C, w > 0xFF || st.contains(STATUS::C));
+                Ok(false)
+            }
+            Instruction::Push => {
+                self.push(self.pc, pc)?;
+                Ok(false)
+            }
+            Instruction::Pop => {
+                self.pop(pc)?;
+                Ok(false)
+            }
+            Instruction::Reset => {
+                self.reset();
+                Ok(false)
+            }
+            Instruction::Sleep => {
+                self.sleeping = true;
+                Ok(false)
+            }
+            Instruction::ClearWatchDogTimer | Instruction::Noop => Ok(false),
+        }
+    }
+
+    /// computes the result from the value of f, updating STATUS
+    fn exec_byte(&mut self, op: ByteOperation, x: u8) -> u8 {
+        use ByteOperation::*;
+
+        let carry = self.status().contains(STATUS::C);
+        match op {
+            AddWf => self.add(self.w, x, false),
+            AddWfWithCarry => self.add(self.w, x, carry),
+            AndWf => self.set_zn(self.w & x),
+            ComplementF => self.set_zn(!x),
+            DecrementF => self.add(x, 0xFF, false),
+            DecrementFSkipIfZ | DecrementFSkipIfNotZ => x.wrapping_sub(1),
+            IncrementF => self.add(x, 1, false),
+            IncrementFSkipIfZ | IncrementFSkipIfNotZ => x.wrapping_add(1),
+            OrWf => self.set_zn(self.w | x),
+            MoveF => self.set_zn(x),
+            RotateLeftFThroughCarry => {
+                self.set_flag(STATUS::C, x & 0x80 != 0);
+                self.set_zn((x << 1) | carry as u8)
+            }
+            RotateLeftF => self.set_zn(x.rotate_left(1)),
+            RotateRightFThroughCarry => {
+                self.set_flag(STATUS::C, x & 0x01 != 0);
+                self.set_zn((x >> 1) | ((carry as u8) << 7))
+            }
+            RotateRightF => self.set_zn(x.rotate_right(1)),
+            // a - b - !C is a + !b + C
+            SubtractFFromWWithBorrow => self.add(self.w, !x, carry),
+            SubtractWfromF => self.add(x, !self.w, true),
+            SubtractWfromFWithBorrow => self.add(x, !self.w, carry),
+            SwapF => x.rotate_left(4),
+            XorWwithF => self.set_zn(self.w ^ x),
+        }
+    }
+
+    /// returns whether to skip the next instruction
+    fn exec_file(&mut self, op: FileOperation, addr: u16) -> bool {
+        use FileOperation::*;
+
+        match op {
+            ClearF => {
+                self.write(addr, 0);
+                self.set_flag(STATUS::Z, true);
+            }
+            CompareSkipIfEqual => return self.read(addr) == self.w,
+            CompareSkipIfGreater => return self.read(addr) > self.w,
+            CompareSkipIfLess => return self.read(addr) < self.w,
+            MoveWtoF => self.write(addr, self.w),
+            MultiplyWwithF => {
+                let x = self.read(addr);
+                self.multiply(self.w, x);
+            }
+            NegateF => {
+                let x = self.read(addr);
+                let ret = self.add(!x, 0, true);
+                self.write(addr, ret);
+            }
+            SetF => self.write(addr, 0xFF),
+            TestFSkipIfZ => return self.read(addr) == 0,
+        }
+        false
+    }
+
+    fn exec_literal(&mut self, op: LiteralOperation, k: u8, pc: u32) -> Result<(), VmError> {
+        use LiteralOperation::*;
+
+        match op {
+            AddLiteralToW => self.w = self.add(self.w, k, false),
+            AndLiteralWithW => self.w = self.set_zn(self.w & k),
+            OrLiteralWithW => self.w = self.set_zn(self.w | k),
+            XorLiteralWithW => self.w = self.set_zn(self.w ^ k),
+            MoveLiteralToW => self.w = k,
+            MultiplyLiteralWithW => self.multiply(self.w, k),
+            SubtractWFromLiteral => self.w = self.add(k, !self.w, true),
+            ReturnWithLiteralInW => {
+                self.pc = self.pop(pc)?;
+                self.w = k;
+            }
+        }
+        Ok(())
+    }
+
+    /// TBLRD reads the program memory into TABLAT. TBLWT only moves the pointer: the holding
+    /// registers and the flash write sequence aren't modeled.
+    fn exec_table(&mut self, op: TableOperation) {
+        let ptr = self.tblptr();
+        let at = match op.pointer {
+            TablePointer::PreIncrement => ptr + 1,
+            _ => ptr,
+        } & ADDR_MASK;
+        if !op.write {
+            self.data[sfr::TABLAT as usize] = self.flash.get(at as usize).copied().unwrap_or(0);
+        }
+        match op.pointer {
+            TablePointer::Unchanged => {}
+            TablePointer::PostIncrement | TablePointer::PreIncrement => self.set_tblptr(ptr + 1),
+            TablePointer::PostDecrement => self.set_tblptr(ptr.wrapping_sub(1)),
+        }
+    }
+}
+
+/// assembles `words` into a flash image big enough for the tests
+#[cfg(test)]
+fn test_vm(words: &[u16]) -> Pic18 {
+    let mut flash = vec![0; 0x400];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    Pic18::new(&flash)
+}
+
+#[test]
+fn multiplies_and_uses_banks() {
+    let mut vm = test_vm(&[
+        0x0E0C, // movlw 12
+        0x0D0B, // mullw 11
+        0x0102, // movlb 2
+        0x6F10, // movwf 0x10, b   (0x210)
+        0x6E10, // movwf 0x10, a   (0x010)
+        0xC210, // movff 0x210, 0x011
+        0xF011, 0x2B10, // incf 0x10, f, b
+    ]);
+    for _ in 0..7 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.product(), 132);
+    assert_eq!(
+        (vm.data[0x210], vm.data[0x010], vm.data[0x011]),
+        (13, 12, 12)
+    );
+    assert_eq!(vm.pc, 16);
+    assert!(!vm.status().contains(STATUS::Z));
+}
+
+#[test]
+fn skips_over_two_word_instructions() {
+    let mut vm = test_vm(&[
+        0x6A20, // clrf 0x20, a
+        0x6620, // tstfsz 0x20, a
+        0xEF10, // goto 0x010
+        0xF000, 0x0E01, // movlw 1
+    ]);
+    vm.step().unwrap();
+    assert_eq!(vm.step().unwrap(), 3);
+    assert_eq!(vm.pc, 8);
+    vm.step().unwrap();
+    assert_eq!(vm.w, 1);
+}
+
+#[test]
+fn stack_has_31_levels() {
+    // 0x0000: rcall 0x0000
+    let mut vm = test_vm(&[0xDFFF]);
+    for _ in 0..STACK_DEPTH {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.read(sfr::STKPTR), 31);
+    assert_eq!(vm.step(), Err(VmError::StackOverflow { pc: 0 }));
+    assert_eq!(vm.pc, 0);
+}
+
+#[test]
+fn reads_through_fsrs_and_the_table_pointer() {
+    let mut vm = test_vm(&[
+        0xEE01, // lfsr 0, 0x120
+        0xF020, 0x0E05, // movlw 5
+        0x6EEE, // movwf POSTINC0
+        0x6EEE, // movwf POSTINC0
+        0x0009, // tblrd*+
+        0x0009, // tblrd*+
+    ]);
+    for _ in 0..6 {
+        vm.step().unwrap();
+    }
+    assert_eq!((vm.data[0x120], vm.data[0x121]), (5, 5));
+    assert_eq!(vm.fsr(0), 0x122);
+    // the high byte of `lfsr`'s first word
+    assert_eq!(vm.data[sfr::TABLAT as usize], 0xEE);
+    assert_eq!(vm.tblptr(), 2);
+}
@@ -0,0 +1,230 @@
+//! a vm on a thread of its own, for UIs that shouldn't wait on the simulation. [`Worker::spawn`]
+//! moves the vm there, [`Worker::send`] pauses, steps and drives its pins, and [`Worker::latest`]
+//! has what it last reported.
+
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::inst::ProgramAddr;
+use crate::vm::pic14::{Pic14, Pin, Reset, RunExit, Snapshot, Ticker};
+
+/// instruction cycles run between looking for commands
+const SLICE_CYCLES: u64 = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// runs until paused, a breakpoint or an error
+    Run,
+    Pause,
+    /// pauses and runs one instruction
+    Step,
+    SetPin(Pin, bool),
+    ReleasePin(Pin),
+    /// volts on an analog channel
+    SetAnalog(u8, f32),
+    AddBreakpoint(ProgramAddr),
+    RemoveBreakpoint(ProgramAddr),
+    Reset(Reset),
+}
+
+/// sent after every command, when the vm stops by itself, and every `interval` while it runs
+#[derive(Debug, Clone)]
+pub struct State {
+    pub snapshot: Snapshot,
+    /// instruction cycles run since the worker started
+    pub cycles: u64,
+    pub running: bool,
+    /// why it last stopped by itself, [`RunExit::Stopped`] or [`RunExit::Error`]. cleared by
+    /// [`Command::Run`] and [`Command::Step`]
+    pub exit: Option<RunExit>,
+}
+
+pub struct Worker<T> {
+    commands: Sender<Command>,
+    states: Receiver<State>,
+    latest: Option<State>,
+    thread: JoinHandle<(Pic14, T)>,
+}
+
+impl<T: Ticker + Send + 'static> Worker<T> {
+    /// starts paused, with a state for how the vm is now
+    pub fn spawn(vm: Pic14, ticker: T, interval: Duration) -> Self {
+        let (commands, command_rx) = mpsc::channel();
+        let (state_tx, states) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let mut worker = Thread {
+                vm,
+                ticker: Counting { inner: ticker, cycles: 0 },
+                running: false,
+                exit: None,
+                states: state_tx,
+                reported: Instant::now(),
+            };
+            worker.work(command_rx, interval);
+            (worker.vm, worker.ticker.inner)
+        });
+        Self { commands, states, latest: None, thread }
+    }
+}
+
+impl<T> Worker<T> {
+    /// false if the worker is gone, which only happens when it panicked
+    pub fn send(&self, command: Command) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    /// the most recent state, without waiting
+    pub fn latest(&mut self) -> Option<&State> {
+        while let Ok(state) = self.states.try_recv() {
+            self.latest = Some(state);
+        }
+        self.latest.as_ref()
+    }
+
+    /// waits for the state after the last one seen, e.g. the one a command has sent
+    pub fn next(&mut self, timeout: Duration) -> Option<&State> {
+        self.latest = Some(self.states.recv_timeout(timeout).ok()?);
+        self.latest.as_ref()
+    }
+
+    /// stops the thread and hands the vm and ticker back
+    pub fn join(self) -> (Pic14, T) {
+        drop(self.commands);
+        self.thread
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+}
+
+struct Counting<T> {
+    inner: T,
+    cycles: u64,
+}
+
+impl<T: Ticker> Ticker for Counting<T> {
+    fn tick(&mut self, vm: &Pic14, cycles: u8) {
+        self.cycles += cycles as u64;
+        self.inner.tick(vm, cycles);
+    }
+}
+
+struct Thread<T> {
+    vm: Pic14,
+    ticker: Counting<T>,
+    running: bool,
+    exit: Option<RunExit>,
+    states: Sender<State>,
+    reported: Instant,
+}
+
+impl<T: Ticker> Thread<T> {
+    fn work(&mut self, commands: Receiver<Command>, interval: Duration) {
+        self.report();
+        loop {
+            let command = match self.running {
+                true => match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return,
+                },
+                false => match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return,
+                },
+            };
+            if let Some(command) = command {
+                self.command(command);
+                self.report();
+            }
+
+            if self.running {
+                let run = self.vm.run_for_cycles(SLICE_CYCLES, &mut self.ticker);
+                if run.exit != RunExit::Budget {
+                    self.running = false;
+                    self.exit = Some(run.exit);
+                    self.report();
+                } else if self.reported.elapsed() >= interval {
+                    self.report();
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, command: Command) {
+        let vm = &mut self.vm;
+        match command {
+            Command::Run => {
+                self.running = true;
+                self.exit = None;
+            }
+            Command::Pause => self.running = false,
+            Command::Step => {
+                self.running = false;
+                self.exit = match vm.step(&mut self.ticker) {
+                    Ok(None) => None,
+                    Ok(Some(stopped)) => Some(RunExit::Stopped(stopped)),
+                    Err(e) => Some(RunExit::Error(e)),
+                };
+            }
+            Command::SetPin(pin, level) => vm.set_pin_input(pin, level),
+            Command::ReleasePin(pin) => vm.release_pin_input(pin),
+            Command::SetAnalog(channel, volts) => vm.set_analog_input(channel, volts),
+            Command::AddBreakpoint(addr) => vm.add_breakpoint(addr),
+            Command::RemoveBreakpoint(addr) => vm.remove_breakpoint(addr),
+            Command::Reset(kind) => vm.reset(kind),
+        }
+    }
+
+    fn report(&mut self) {
+        self.reported = Instant::now();
+        // nobody listening is fine, the owner can still join
+        let _ = self.states.send(State {
+            snapshot: self.vm.snapshot(),
+            cycles: self.ticker.cycles,
+            running: self.running,
+            exit: self.exit.clone(),
+        });
+    }
+}
+
+#[test]
+fn runs_and_steps_on_its_own_thread() {
+    use crate::vm::pic14::Stopped;
+
+    fn send<T: Send>() {}
+    send::<Pic14>();
+
+    let words: [u16; 2] = [
+        0b00_0000_0000_0000, // 0x0000: nop
+        0b10_1000_0000_0000, // 0x0001: goto 0x0000
+    ];
+    let mut flash = [0; 7168];
+    for (i, w) in words.iter().enumerate() {
+        flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+    }
+    let mut worker = Worker::spawn(Pic14::new(flash), (), Duration::from_millis(10));
+    let timeout = Duration::from_secs(5);
+    assert_eq!(worker.next(timeout).unwrap().snapshot.pc, 0);
+
+    worker.send(Command::Step);
+    let state = worker.next(timeout).unwrap();
+    assert_eq!((state.snapshot.pc, state.cycles), (1, 1));
+
+    worker.send(Command::AddBreakpoint(ProgramAddr(0)));
+    worker.send(Command::Run);
+    let stopped = Some(RunExit::Stopped(Stopped::Breakpoint(ProgramAddr(0))));
+    while worker.next(timeout).unwrap().exit != stopped {}
+    let state = worker.latest().unwrap();
+    assert!(!state.running);
+    assert_eq!((state.snapshot.pc, state.cycles), (0, 3));
+
+    worker.send(Command::RemoveBreakpoint(ProgramAddr(0)));
+    worker.send(Command::Run);
+    while worker.next(timeout).unwrap().cycles < 100_000 {}
+    worker.send(Command::Pause);
+    while worker.next(timeout).unwrap().running {}
+
+    let (vm, ()) = worker.join();
+    assert!(vm.pc() <= 1);
+}
@@ -0,0 +1,215 @@
+//! pulse width and period measured off a digital signal, for PWM and RC servo outputs
+//!
+//! the analyzer watches one line, following the level it's given and the time going on. a pulse
+//! runs from a rising edge to the next one: high for its width, low for the rest of its period.
+//! it's recorded once the next rising edge closes it, into a history of the latest pulses that
+//! the statistics are taken over. a floating line reads as low.
+
+use std::collections::VecDeque;
+
+/// one period of the signal, from a rising edge to the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pulse {
+    /// time of the rising edge
+    pub start_ns: u64,
+    pub width_ns: u64,
+    pub period_ns: u64,
+}
+
+impl Pulse {
+    /// high time over period, 0.0 to 1.0
+    pub fn duty(&self) -> f64 {
+        self.width_ns as f64 / self.period_ns as f64
+    }
+
+    pub fn frequency_hz(&self) -> f64 {
+        1e9 / self.period_ns as f64
+    }
+
+    /// the angle a hobby servo turns to for this width, 1 ms as 0 and 2 ms as 180 degrees. not
+    /// clamped, so pulses out of the range give angles out of it
+    pub fn servo_angle(&self) -> f64 {
+        (self.width_ns as f64 - 1_000_000.0) / 1_000_000.0 * 180.0
+    }
+}
+
+/// min, mean and max of one quantity over the history
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    fn of(xs: impl Iterator<Item = f64>) -> Option<Self> {
+        let (mut min, mut max, mut sum, mut n) = (f64::INFINITY, f64::NEG_INFINITY, 0.0, 0);
+        for x in xs {
+            min = min.min(x);
+            max = max.max(x);
+            sum += x;
+            n += 1;
+        }
+        (n > 0).then(|| Self { min, mean: sum / n as f64, max })
+    }
+
+    /// max minus min
+    pub fn jitter(&self) -> f64 {
+        self.max - self.min
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub pulses: usize,
+    pub width_ns: Summary,
+    pub period_ns: Summary,
+    pub duty: Summary,
+}
+
+#[derive(Debug, Clone)]
+pub struct Analyzer {
+    now_ns: u64,
+    level: bool,
+    /// time of the last rising edge
+    rise_ns: Option<u64>,
+    /// high time of the pulse from that edge, once it has fallen
+    width_ns: Option<u64>,
+    pulses: VecDeque<Pulse>,
+    history: usize,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer {
+    /// the pulses kept by default
+    pub const DEFAULT_HISTORY: usize = 256;
+
+    pub fn new() -> Self {
+        Self::with_history(Self::DEFAULT_HISTORY)
+    }
+
+    /// keeping the latest `history` pulses
+    pub fn with_history(history: usize) -> Self {
+        assert!(history > 0);
+        Self {
+            now_ns: 0,
+            level: false,
+            rise_ns: None,
+            width_ns: None,
+            pulses: VecDeque::with_capacity(history),
+            history,
+        }
+    }
+
+    /// the level on the line from now on, `None` if it floats
+    pub fn input(&mut self, level: Option<bool>) {
+        let level = level == Some(true);
+        if level == self.level {
+            return;
+        }
+        self.level = level;
+        if !level {
+            self.width_ns = self.rise_ns.map(|rise| self.now_ns - rise);
+            return;
+        }
+        if let (Some(start_ns), Some(width_ns)) = (self.rise_ns, self.width_ns) {
+            if self.pulses.len() == self.history {
+                self.pulses.pop_front();
+            }
+            let period_ns = self.now_ns - start_ns;
+            self.pulses
+                .push_back(Pulse { start_ns, width_ns, period_ns });
+        }
+        self.rise_ns = Some(self.now_ns);
+        self.width_ns = None;
+    }
+
+    pub fn simulate(&mut self, ns: u64) {
+        self.now_ns += ns;
+    }
+
+    pub fn now_ns(&self) -> u64 {
+        self.now_ns
+    }
+
+    pub fn level(&self) -> bool {
+        self.level
+    }
+
+    /// the pulses kept, oldest first
+    pub fn pulses(&self) -> impl Iterator<Item = &Pulse> + '_ {
+        self.pulses.iter()
+    }
+
+    pub fn latest(&self) -> Option<&Pulse> {
+        self.pulses.back()
+    }
+
+    /// time since the last edge. long with no pulses coming means the line is held at
+    /// [`Self::level`]
+    pub fn steady_ns(&self) -> u64 {
+        let rise = self.rise_ns.unwrap_or(0);
+        let fall = self.width_ns.map_or(rise, |w| rise + w);
+        self.now_ns - rise.max(fall)
+    }
+
+    /// over the pulses kept. `None` before the first pulse is closed
+    pub fn stats(&self) -> Option<Stats> {
+        let pulses = || self.pulses.iter();
+        Some(Stats {
+            pulses: self.pulses.len(),
+            width_ns: Summary::of(pulses().map(|p| p.width_ns as f64))?,
+            period_ns: Summary::of(pulses().map(|p| p.period_ns as f64))?,
+            duty: Summary::of(pulses().map(Pulse::duty))?,
+        })
+    }
+
+    /// forgets the pulses, e.g. after the firmware changes the duty
+    pub fn clear(&mut self) {
+        self.pulses.clear();
+    }
+}
+
+#[test]
+fn measures_pwm_and_servo_pulses() {
+    const US: u64 = 1_000;
+
+    // 1 kHz, 25% then 50%
+    let mut probe = Analyzer::with_history(4);
+    assert_eq!(probe.stats(), None);
+    for width in [250, 250, 500, 500, 500, 500] {
+        probe.input(Some(true));
+        probe.simulate(width * US);
+        probe.input(None);
+        probe.simulate((1000 - width) * US);
+    }
+    probe.input(Some(true));
+    let stats = probe.stats().unwrap();
+    assert_eq!(stats.pulses, 4);
+    assert_eq!(stats.duty.mean, 0.5);
+    assert_eq!(stats.period_ns.jitter(), 0.0);
+    assert_eq!(probe.latest().unwrap().frequency_hz(), 1000.0);
+    assert_eq!(probe.pulses().next().unwrap().start_ns, 2000 * US);
+
+    // a servo at 50 Hz, 1.5 ms then 2 ms
+    probe.clear();
+    for width in [1500, 2000] {
+        probe.input(Some(true));
+        probe.simulate(width * US);
+        probe.input(Some(false));
+        probe.simulate(20_000 * US - width * US);
+    }
+    probe.input(Some(true));
+    let angles: Vec<_> = probe.pulses().map(Pulse::servo_angle).collect();
+    assert_eq!(angles, [90.0, 180.0]);
+
+    // held high
+    probe.simulate(50_000 * US);
+    assert!(probe.level());
+    assert_eq!(probe.steady_ns(), 50_000 * US);
+}
@@ -0,0 +1,192 @@
+//! multi-digit 7-segment display driven by multiplexing
+//!
+//! the digits share the segment lines a..g and dp, and each has a common line of its own. with a
+//! common cathode a segment of a digit is on while its segment line is high and the common is
+//! low, and the other way around with a common anode. firmware lights one digit at a time, so
+//! the display integrates on-time over a refresh period and reports the fraction as the
+//! brightness the eye would perceive.
+
+/// a..g, then dp
+pub const SEGMENTS: usize = 8;
+
+/// brightness (0.0 to 1.0) of every segment over one refresh period, indexed by
+/// `[digit][segment]` with the digits from the left
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Frame(pub Vec<[f32; SEGMENTS]>);
+
+impl Frame {
+    pub fn brightness(&self, digit: usize, segment: usize) -> f32 {
+        self.0[digit][segment]
+    }
+
+    /// per digit, bit `n` is set if segment `n` is at least `threshold` bright
+    pub fn lit(&self, threshold: f32) -> Vec<u8> {
+        self.0
+            .iter()
+            .map(|digit| {
+                digit
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, b)| **b >= threshold)
+                    .fold(0, |acc, (s, _)| acc | (1 << s))
+            })
+            .collect()
+    }
+
+    /// what the digits read as, e.g. `"12.34"`. a pattern that isn't a hex digit, `-` or blank is
+    /// `?`
+    pub fn text(&self, threshold: f32) -> String {
+        let mut text = String::new();
+        for segments in self.lit(threshold) {
+            text.push(character(segments & 0x7f));
+            if segments & 0x80 != 0 {
+                text.push('.');
+            }
+        }
+        text
+    }
+}
+
+/// the character segments a..g in bits 0..6 show
+fn character(segments: u8) -> char {
+    const PATTERNS: [(u8, char); 18] = [
+        (0x00, ' '),
+        (0x3f, '0'),
+        (0x06, '1'),
+        (0x5b, '2'),
+        (0x4f, '3'),
+        (0x66, '4'),
+        (0x6d, '5'),
+        (0x7d, '6'),
+        (0x07, '7'),
+        (0x7f, '8'),
+        (0x6f, '9'),
+        (0x77, 'A'),
+        (0x7c, 'b'),
+        (0x39, 'C'),
+        (0x5e, 'd'),
+        (0x79, 'E'),
+        (0x71, 'F'),
+        (0x40, '-'),
+    ];
+    PATTERNS
+        .iter()
+        .find(|x| x.0 == segments)
+        .map_or('?', |x| x.1)
+}
+
+#[derive(Debug, Clone)]
+pub struct SevenSegment {
+    segments: [Option<bool>; SEGMENTS],
+    commons: Vec<Option<bool>>,
+    common_anode: bool,
+    period_ns: u64,
+    /// time into the current period
+    elapsed_ns: u64,
+    on_ns: Vec<[u64; SEGMENTS]>,
+    /// last completed period
+    frame: Frame,
+}
+
+impl SevenSegment {
+    /// 50 Hz. scanning faster than this looks steady to the eye
+    pub const DEFAULT_PERIOD_NS: u64 = 20_000_000;
+
+    /// `digits` digits with common cathodes
+    pub fn new(digits: usize) -> Self {
+        Self::with_period(digits, Self::DEFAULT_PERIOD_NS)
+    }
+
+    pub fn with_period(digits: usize, period_ns: u64) -> Self {
+        assert!(digits > 0 && period_ns > 0);
+        Self {
+            segments: [None; SEGMENTS],
+            commons: vec![None; digits],
+            common_anode: false,
+            period_ns,
+            elapsed_ns: 0,
+            on_ns: vec![[0; SEGMENTS]; digits],
+            frame: Frame(vec![[0.0; SEGMENTS]; digits]),
+        }
+    }
+
+    /// segments on while their line is low and the common high
+    pub fn common_anode(mut self, on: bool) -> Self {
+        self.common_anode = on;
+        self
+    }
+
+    pub fn digits(&self) -> usize {
+        self.commons.len()
+    }
+
+    /// `None` if the segment line is floating
+    pub fn set_segment(&mut self, segment: usize, level: Option<bool>) {
+        self.segments[segment] = level;
+    }
+
+    /// `None` if the common line of the digit is floating
+    pub fn set_common(&mut self, digit: usize, level: Option<bool>) {
+        self.commons[digit] = level;
+    }
+
+    pub fn is_on(&self, digit: usize, segment: usize) -> bool {
+        let on = !self.common_anode;
+        self.segments[segment] == Some(on) && self.commons[digit] == Some(!on)
+    }
+
+    /// advances time with the current segment and common levels
+    pub fn simulate(&mut self, ns: u64) {
+        let mut remain = ns;
+        while remain > 0 {
+            let slice = remain.min(self.period_ns - self.elapsed_ns);
+            for d in 0..self.digits() {
+                for s in 0..SEGMENTS {
+                    if self.is_on(d, s) {
+                        self.on_ns[d][s] += slice;
+                    }
+                }
+            }
+            self.elapsed_ns += slice;
+            remain -= slice;
+
+            if self.elapsed_ns == self.period_ns {
+                let period = self.period_ns as f32;
+                let digits = self.on_ns.iter().map(|x| x.map(|on| on as f32 / period));
+                self.frame = Frame(digits.collect());
+                self.on_ns.fill([0; SEGMENTS]);
+                self.elapsed_ns = 0;
+            }
+        }
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+}
+
+#[test]
+fn scanned_digits() {
+    let mut display = SevenSegment::new(4).common_anode(true);
+    let slot = SevenSegment::DEFAULT_PERIOD_NS / 4;
+    // "12.3" and the last digit dark
+    let patterns = [0x06, 0x5b | 0x80, 0x4f, 0x00];
+    for _ in 0..2 {
+        for (d, pattern) in patterns.iter().enumerate() {
+            for i in 0..4 {
+                display.set_common(i, Some(i == d));
+            }
+            for s in 0..SEGMENTS {
+                display.set_segment(s, Some(pattern >> s & 1 == 0));
+            }
+            display.simulate(slot);
+        }
+    }
+
+    let frame = display.frame();
+    assert_eq!(frame.brightness(0, 1), 0.25);
+    assert_eq!(frame.brightness(0, 0), 0.0);
+    assert_eq!(frame.lit(0.2), [0x06, 0xdb, 0x4f, 0x00]);
+    assert_eq!(frame.text(0.2), "12.3 ");
+    assert_eq!(frame.text(0.5), "    ");
+}
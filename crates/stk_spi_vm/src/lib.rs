@@ -0,0 +1,345 @@
+//! SPI bus shared by the device models, watched at the pin level
+//!
+//! the master drives SCK and MOSI and a chip select per device, active low. the selected devices
+//! shift bits in and out in their own [`Mode`] and see whole bytes through [`SpiDevice`]. MISO is
+//! driven by the selected devices, ANDed if there are several, and floats with none selected.
+//!
+//! every selection of a device, from CS going low to it going high again, is a [`Transaction`].
+//! the bus logs them with the bytes both ways for traces and exports to take with
+//! [`SpiBus::take_transactions`].
+//!
+//! testbenches without an MCU drive the bus as the master with [`SpiBus::select`],
+//! [`SpiBus::transfer`] and [`SpiBus::deselect`].
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// clock polarity (CPOL, bit 1) and phase (CPHA, bit 0) as in the usual mode numbers. in mode 0
+/// SCK idles low and bits are sampled on its rising edges
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Mode(u8);
+
+impl Mode {
+    pub const MODE0: Mode = Mode(0);
+    pub const MODE1: Mode = Mode(1);
+    pub const MODE2: Mode = Mode(2);
+    pub const MODE3: Mode = Mode(3);
+
+    /// `None` past mode 3
+    pub fn new(mode: u8) -> Option<Self> {
+        (mode < 4).then_some(Mode(mode))
+    }
+
+    /// the level SCK idles at
+    pub fn cpol(self) -> bool {
+        self.0 & 2 != 0
+    }
+
+    /// whether bits are sampled on the trailing edge, rather than the leading one
+    pub fn cpha(self) -> bool {
+        self.0 & 1 != 0
+    }
+}
+
+/// a chip on the bus, seen a byte at a time
+pub trait SpiDevice {
+    fn mode(&self) -> Mode {
+        Mode::MODE0
+    }
+
+    /// CS went low
+    fn select(&mut self) {}
+
+    /// CS went high
+    fn deselect(&mut self) {}
+
+    /// the next byte to shift out, MSB first. asked for on selection and after every byte
+    fn load(&mut self) -> u8;
+
+    /// a byte shifted in from the master
+    fn receive(&mut self, byte: u8);
+}
+
+/// for keeping a handle to the device's state from outside
+impl<T: SpiDevice> SpiDevice for Rc<RefCell<T>> {
+    fn mode(&self) -> Mode {
+        self.borrow().mode()
+    }
+
+    fn select(&mut self) {
+        self.borrow_mut().select()
+    }
+
+    fn deselect(&mut self) {
+        self.borrow_mut().deselect()
+    }
+
+    fn load(&mut self) -> u8 {
+        self.borrow_mut().load()
+    }
+
+    fn receive(&mut self, byte: u8) {
+        self.borrow_mut().receive(byte)
+    }
+}
+
+/// the bytes of one selection of a device
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Transaction {
+    /// the index [`SpiBus::attach`] gave the device
+    pub device: usize,
+    pub mosi: Vec<u8>,
+    pub miso: Vec<u8>,
+}
+
+/// a device and where it is in shifting a byte
+struct Slot {
+    device: Box<dyn SpiDevice>,
+    selected: bool,
+    /// shifting in, MSB first
+    input: u8,
+    bits_in: u8,
+    /// shifting out
+    output: u8,
+    /// the bit of `output` on MISO, from the MSB
+    shown: u8,
+    /// with CPHA, the first leading edge of a byte shows bit 7 rather than moving on
+    fresh: bool,
+    /// of the current selection
+    transaction: Transaction,
+}
+
+impl Slot {
+    fn miso(&self) -> bool {
+        self.output << self.shown & 0x80 != 0
+    }
+
+    fn sample(&mut self, mosi: bool) {
+        self.input = self.input << 1 | mosi as u8;
+        self.bits_in += 1;
+        if self.bits_in == 8 {
+            self.device.receive(self.input);
+            self.transaction.mosi.push(self.input);
+            self.transaction.miso.push(self.output);
+            self.bits_in = 0;
+        }
+    }
+
+    fn shift(&mut self) {
+        if std::mem::take(&mut self.fresh) {
+            return;
+        }
+        self.shown += 1;
+        if self.shown == 8 {
+            self.load();
+            // this edge is the first one of the new byte
+            self.fresh = false;
+        }
+    }
+
+    fn load(&mut self) {
+        self.output = self.device.load();
+        self.shown = 0;
+        self.fresh = self.device.mode().cpha();
+    }
+}
+
+pub struct SpiBus {
+    slots: Vec<Slot>,
+    sck: bool,
+    mosi: bool,
+    transactions: Vec<Transaction>,
+}
+
+impl std::fmt::Debug for SpiBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpiBus")
+            .field("devices", &self.slots.len())
+            .field("sck", &self.sck)
+            .field("transactions", &self.transactions)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for SpiBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpiBus {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![],
+            sck: false,
+            mosi: false,
+            transactions: vec![],
+        }
+    }
+
+    /// puts `device` on the bus with a chip select of its own. returns its index, for
+    /// [`Self::set_select`]
+    pub fn attach(&mut self, device: impl SpiDevice + 'static) -> usize {
+        self.slots.push(Slot {
+            device: Box::new(device),
+            selected: false,
+            input: 0,
+            bits_in: 0,
+            output: 0xff,
+            shown: 0,
+            fresh: false,
+            transaction: Transaction::default(),
+        });
+        self.slots.len() - 1
+    }
+
+    pub fn devices(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// level of the chip select of `device`
+    pub fn set_select(&mut self, device: usize, cs: bool) {
+        let slot = &mut self.slots[device];
+        if slot.selected != cs {
+            return;
+        }
+        slot.selected = !cs;
+        if slot.selected {
+            slot.device.select();
+            slot.input = 0;
+            slot.bits_in = 0;
+            slot.load();
+            slot.transaction = Transaction { device, ..Transaction::default() };
+        } else {
+            slot.device.deselect();
+            let transaction = std::mem::take(&mut slot.transaction);
+            self.transactions.push(transaction);
+        }
+    }
+
+    /// levels of SCK and MOSI
+    pub fn update(&mut self, sck: bool, mosi: bool) {
+        let sck_before = self.sck;
+        self.sck = sck;
+        self.mosi = mosi;
+        if sck == sck_before {
+            return;
+        }
+        for slot in self.slots.iter_mut().filter(|x| x.selected) {
+            let mode = slot.device.mode();
+            let leading = sck != mode.cpol();
+            if leading != mode.cpha() {
+                slot.sample(mosi);
+            } else {
+                slot.shift();
+            }
+        }
+    }
+
+    /// the level on MISO, `None` while no device is selected
+    pub fn miso(&self) -> Option<bool> {
+        let mut selected = self.slots.iter().filter(|x| x.selected).peekable();
+        selected.peek()?;
+        Some(selected.all(Slot::miso))
+    }
+
+    /// the transactions finished since the last call, oldest first
+    pub fn take_transactions(&mut self) -> Vec<Transaction> {
+        std::mem::take(&mut self.transactions)
+    }
+
+    /// selects `device` as the master, with SCK at the idle level of its mode
+    pub fn select(&mut self, device: usize) {
+        let idle = self.slots[device].device.mode().cpol();
+        self.update(idle, self.mosi);
+        self.set_select(device, false);
+    }
+
+    pub fn deselect(&mut self, device: usize) {
+        self.set_select(device, true);
+    }
+
+    /// shifts a byte out and one in as the master, in the mode of the first selected device
+    pub fn transfer(&mut self, byte: u8) -> u8 {
+        let mode = self
+            .slots
+            .iter()
+            .find(|x| x.selected)
+            .map_or(Mode::MODE0, |x| x.device.mode());
+        let idle = mode.cpol();
+        let mut read = 0;
+        for i in (0..8).rev() {
+            let bit = byte >> i & 1 != 0;
+            // with CPHA the bit goes out on the leading edge and is sampled on the trailing one
+            if mode.cpha() {
+                self.update(!idle, bit);
+                self.update(idle, bit);
+            } else {
+                self.update(self.sck, bit);
+                self.update(!idle, bit);
+            }
+            read = read << 1 | self.miso().unwrap_or(true) as u8;
+            if !mode.cpha() {
+                self.update(idle, bit);
+            }
+        }
+        read
+    }
+}
+
+#[test]
+fn transfers_in_every_mode() {
+    /// answers every byte with the last one plus one
+    struct Echo {
+        mode: Mode,
+        last: u8,
+        received: Vec<u8>,
+    }
+    impl SpiDevice for Echo {
+        fn mode(&self) -> Mode {
+            self.mode
+        }
+        fn load(&mut self) -> u8 {
+            self.last.wrapping_add(1)
+        }
+        fn receive(&mut self, byte: u8) {
+            self.last = byte;
+            self.received.push(byte);
+        }
+    }
+
+    let mut bus = SpiBus::new();
+    let echoes: Vec<_> = (0..4)
+        .map(|m| {
+            let echo = Rc::new(RefCell::new(Echo {
+                mode: Mode::new(m).unwrap(),
+                last: 0x10 * m,
+                received: vec![],
+            }));
+            bus.attach(echo.clone());
+            echo
+        })
+        .collect();
+    assert_eq!(bus.miso(), None);
+
+    for (device, echo) in echoes.iter().enumerate() {
+        bus.select(device);
+        let read = [0xa5, 0x3c].map(|x| bus.transfer(x));
+        bus.deselect(device);
+        let m = device as u8;
+        assert_eq!(read, [0x10 * m + 1, 0xa6], "mode {m}");
+        assert_eq!(echo.borrow().received, [0xa5, 0x3c], "mode {m}");
+    }
+
+    let log = bus.take_transactions();
+    assert_eq!(log.len(), 4);
+    assert_eq!(
+        log[2],
+        Transaction {
+            device: 2,
+            mosi: vec![0xa5, 0x3c],
+            miso: vec![0x21, 0xa6]
+        }
+    );
+    assert!(bus.take_transactions().is_empty());
+}
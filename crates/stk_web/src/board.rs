@@ -0,0 +1,483 @@
+//! 回路全体のシミュレーション
+//!
+//! MCU のようにクロックで動く部品 ([`CircuitComponent::clock_hz`]) は、どの部品が次に動くかを
+//! 正確な時刻順に決めて 1 回ずつ動かし、そのたびに出力をネットに伝搬させる。
+//! クロックの違う部品どうしでも、信号は相手が次に動くときには届いている。
+//!
+//! それ以外の部品は全体を細かい時間 ([`SLICE_NS`]) に区切り、区切りごとにまとめて進める。
+//! どちらも区切り方は固定なので、結果は [`crate::transport`] のステップと同じく決定的になる。
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::transport::Simulate;
+use crate::{CircuitComponent, CircuitComponentAdapter, Percent, Pos, Rect};
+
+/// 電源電圧。デジタルの出力はアナログの入力からは 0V か VDD に見える
+pub const VDD: f64 = 5.0;
+
+/// クロックのない部品との間で信号が伝わるまでの最大の遅れ
+pub const SLICE_NS: u64 = 10_000;
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// ポートどうしがこの距離 (%) より近ければつながっているとみなす
+const CONNECT_DISTANCE: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortRef {
+    pub component: usize,
+    pub port: usize,
+}
+
+/// ユーザーが引いた配線。ポートの位置に関係なく、両端を同じネットにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wire {
+    pub from: PortRef,
+    pub to: PortRef,
+}
+
+#[derive(Debug, Default)]
+pub struct Net {
+    pub ports: Vec<PortRef>,
+    /// ユーザーがつけた名前。なければ N1, N2… と自動でつける
+    pub name: String,
+}
+
+#[derive(Default)]
+pub struct Board {
+    pub components: Vec<CircuitComponentAdapter>,
+    /// 部品のラベル。`components` と同じ順
+    pub labels: Vec<String>,
+    /// ユーザーがつけたネットの名前。ネットは毎回作り直すので、そのネットにあるポートで覚えておく
+    pub net_names: Vec<(PortRef, String)>,
+    /// 配線。ポートで覚えているので、部品を動かしてもついていく
+    pub wires: Vec<Wire>,
+    /// シミュレーション開始からの時間
+    elapsed_ns: u64,
+    /// クロックで動く部品が次に動く時刻 (自分のクロックで数えたもの)。`components` と同じ順で、
+    /// まだ動いていない部品は `None`
+    next_tick: Vec<Option<u64>>,
+}
+
+/// `ticks / hz` 秒の時刻。割り算せずに比べるので、クロックが違っても正確に前後が決まる
+#[derive(Debug, Clone, Copy)]
+struct Time {
+    ticks: u64,
+    hz: u64,
+}
+
+impl Ord for Time {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let a = self.ticks as u128 * other.hz as u128;
+        let b = other.ticks as u128 * self.hz as u128;
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Time {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Time {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Time {}
+
+impl Board {
+    /// 重なっているポートと、配線でつながったポートを同じネットにまとめる
+    pub fn netlist(&self) -> Vec<Net> {
+        let ports = self
+            .components
+            .iter()
+            .enumerate()
+            .flat_map(|(component, c)| {
+                c.ports()
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(port, p)| (PortRef { component, port }, p.pos))
+            })
+            .collect::<Vec<_>>();
+
+        // union-find
+        let mut parent = (0..ports.len()).collect::<Vec<_>>();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        for a in 0..ports.len() {
+            for b in (a + 1)..ports.len() {
+                if ports[a].0.component == ports[b].0.component {
+                    continue;
+                }
+                let area = Rect::from_center(ports[a].1, Percent::new(CONNECT_DISTANCE * 2.0));
+                if area.contains(ports[b].1) {
+                    let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                    parent[ra] = rb;
+                }
+            }
+        }
+        for wire in &self.wires {
+            let index = |port: PortRef| ports.iter().position(|(p, _)| *p == port);
+            if let (Some(a), Some(b)) = (index(wire.from), index(wire.to)) {
+                let (ra, rb) = (root(&mut parent, a), root(&mut parent, b));
+                parent[ra] = rb;
+            }
+        }
+
+        let mut nets: Vec<(usize, Net)> = vec![];
+        for (i, (port, _)) in ports.iter().enumerate() {
+            let r = root(&mut parent, i);
+            match nets.iter_mut().find(|(x, _)| *x == r) {
+                Some((_, net)) => net.ports.push(*port),
+                None => nets.push((r, Net { ports: vec![*port], name: String::new() })),
+            }
+        }
+
+        let mut nets = nets
+            .into_iter()
+            .map(|(_, net)| net)
+            .filter(|net| net.ports.len() > 1)
+            .collect::<Vec<_>>();
+        name_nets(&mut nets, &self.net_names);
+        nets
+    }
+
+    /// `from` と `to` を配線でつなぐ。同じポートどうしや、向きだけ違うものも含めてもうある配線は
+    /// 足さない
+    pub fn connect(&mut self, from: PortRef, to: PortRef) {
+        let exists = self
+            .wires
+            .iter()
+            .any(|w| (w.from, w.to) == (from, to) || (w.from, w.to) == (to, from));
+        if from != to && !exists {
+            self.wires.push(Wire { from, to });
+        }
+    }
+
+    /// `port` の今の位置
+    pub fn port_pos(&self, port: PortRef) -> Pos {
+        self.components[port.component].ports()[port.port].pos
+    }
+
+    /// `port` のあるネットに名前をつける。空なら自動の名前に戻す
+    pub fn rename_net(&mut self, port: PortRef, name: String) {
+        let nets = self.netlist();
+        let same_net = nets
+            .iter()
+            .find(|net| net.ports.contains(&port))
+            .map_or(vec![port], |net| net.ports.clone());
+        self.net_names.retain(|(p, _)| !same_net.contains(p));
+        if !name.is_empty() {
+            self.net_names.push((port, name));
+        }
+    }
+
+    /// `prefix` に番号をつけた、まだ使われていないラベル
+    pub fn next_label(&self, prefix: &str) -> String {
+        (1..)
+            .map(|i| format!("{prefix}{i}"))
+            .find(|label| !self.labels.contains(label))
+            .unwrap()
+    }
+
+    /// クロックで動く部品を、`until_ns` より前に動く分だけ時刻順に動かす
+    fn run_clocked(&mut self, until_ns: u64, nets: &[Net], nets_of: &[Vec<usize>]) {
+        let until = Time { ticks: until_ns, hz: NS_PER_SEC };
+        let now_ns = self.elapsed_ns;
+        self.next_tick.resize(self.components.len(), None);
+
+        // 同じ時刻なら番号の小さい部品から
+        let mut queue = BinaryHeap::new();
+        for (i, c) in self.components.iter().enumerate() {
+            let Some(hz) = c.clock_hz() else {
+                continue;
+            };
+            // 途中から追加された部品は今の時刻から動き始める
+            let ticks = *self.next_tick[i].get_or_insert_with(|| {
+                (now_ns as u128 * hz as u128).div_ceil(NS_PER_SEC as u128) as u64
+            });
+            queue.push(Reverse((Time { ticks, hz }, i)));
+        }
+
+        while let Some(Reverse((at, i))) = queue.pop() {
+            if at >= until {
+                break;
+            }
+            let ticks = at.ticks + self.components[i].tick().max(1);
+            self.next_tick[i] = Some(ticks);
+            self.propagate(nets_of[i].iter().map(|n| &nets[*n]));
+            queue.push(Reverse((Time { ticks, hz: at.hz }, i)));
+        }
+    }
+
+    fn propagate<'a>(&mut self, nets: impl IntoIterator<Item = &'a Net>) {
+        for net in nets {
+            let mut level = None;
+            let mut conflict = false;
+            for p in &net.ports {
+                if let Some(out) = self.components[p.component].output(p.port) {
+                    conflict |= level.is_some_and(|l| l != out);
+                    level = Some(out);
+                }
+            }
+            // 複数の出力がぶつかっているときは不定とする
+            let level = if conflict { None } else { level };
+
+            // アナログの出力があればそちらを優先する
+            let mut volts = None;
+            let mut analog_conflict = false;
+            for p in &net.ports {
+                if let Some(v) = self.components[p.component].analog_output(p.port) {
+                    analog_conflict |= volts.is_some_and(|x| x != v);
+                    volts = Some(v);
+                }
+            }
+            let volts = if analog_conflict {
+                None
+            } else {
+                volts.or(level.map(|l| if l { VDD } else { 0.0 }))
+            };
+
+            for p in &net.ports {
+                self.components[p.component].input(p.port, level);
+                self.components[p.component].analog_input(p.port, volts);
+            }
+        }
+    }
+}
+
+/// ユーザーがつけた名前がなければ、N1 から順に他と被らない名前をつける
+fn name_nets(nets: &mut [Net], names: &[(PortRef, String)]) {
+    for net in nets.iter_mut() {
+        if let Some((_, name)) = names.iter().find(|(p, _)| net.ports.contains(p)) {
+            net.name = name.clone();
+        }
+    }
+    let mut auto = (1..).map(|i| format!("N{i}"));
+    for i in 0..nets.len() {
+        if nets[i].name.is_empty() {
+            let name = auto
+                .by_ref()
+                .find(|name| nets.iter().all(|n| &n.name != name))
+                .unwrap();
+            nets[i].name = name;
+        }
+    }
+}
+
+impl Simulate for Board {
+    fn simulate(&mut self, ns: u64) {
+        let nets = self.netlist();
+        // 部品ごとに、その部品のポートがあるネット
+        let nets_of = (0..self.components.len())
+            .map(|c| {
+                (0..nets.len())
+                    .filter(|n| nets[*n].ports.iter().any(|p| p.component == c))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let end = self.elapsed_ns + ns;
+        while self.elapsed_ns < end {
+            let slice_end = end.min(self.elapsed_ns + SLICE_NS);
+            self.propagate(&nets);
+            self.run_clocked(slice_end, &nets, &nets_of);
+            for c in &mut self.components {
+                if c.clock_hz().is_none() {
+                    c.simulate(slice_end - self.elapsed_ns);
+                }
+            }
+            self.elapsed_ns = slice_end;
+        }
+    }
+}
+
+#[test]
+fn unnamed_nets_get_automatic_names() {
+    let port = |component, port| PortRef { component, port };
+    let net = |ports| Net { ports, name: String::new() };
+    let mut nets = [
+        net(vec![port(0, 0), port(1, 0)]),
+        net(vec![port(0, 1), port(2, 0)]),
+        net(vec![port(0, 2), port(3, 0)]),
+    ];
+    // ユーザーがつけた名前と被る自動の名前は飛ばす
+    let names = [(port(2, 0), "N1".to_owned())];
+    name_nets(&mut nets, &names);
+    let names = nets.iter().map(|n| n.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["N2", "N1", "N3"]);
+}
+
+#[test]
+fn wires_join_ports_wherever_they_are() {
+    use crate::Movable;
+
+    let mut board = Board::default();
+    for (x, y) in [(0.0, 0.0), (40.0, 0.0), (0.0, 40.0)] {
+        let mut led = crate::Led::new();
+        led.move_(Pos::new(x, y));
+        board.components.push(CircuitComponentAdapter::new(led));
+    }
+    let port = |component| PortRef { component, port: 0 };
+    assert!(board.netlist().is_empty());
+
+    board.connect(port(0), port(1));
+    // 同じ配線や、同じポートどうしは増やさない
+    board.connect(port(1), port(0));
+    board.connect(port(2), port(2));
+    assert_eq!(board.wires.len(), 1);
+    board.connect(port(1), port(2));
+    let nets = board.netlist();
+    assert_eq!(nets.len(), 1);
+    assert_eq!(nets[0].ports, [port(0), port(1), port(2)]);
+
+    // 部品を動かしても配線はついていく
+    board.components[2].move_(Pos::new(60.0, 60.0));
+    assert_eq!(board.netlist()[0].ports.len(), 3);
+    assert_eq!(board.port_pos(port(2)), board.components[2].ports()[0].pos);
+}
+
+/// (部品, 動いた時刻, そのときの入力)
+#[cfg(test)]
+type TickLog = std::rc::Rc<std::cell::RefCell<Vec<(usize, Time, Option<bool>)>>>;
+
+/// テスト用の、決まったクロックで動く部品。動くたびに時刻と入力を `log` に残す
+#[cfg(test)]
+struct Clocked {
+    id: usize,
+    hz: u64,
+    /// 1 回ごとに進めるクロック数。順に繰り返す
+    pattern: &'static [u64],
+    ticks: u64,
+    count: usize,
+    /// この回数動いたら出力を high にする
+    rise_at: Option<usize>,
+    input: Option<bool>,
+    log: TickLog,
+}
+
+#[cfg(test)]
+impl crate::Drawable for Clocked {
+    fn draw(&self, _ctx: &crate::Renderer) {}
+}
+
+#[cfg(test)]
+impl crate::Movable for Clocked {
+    fn rect(&self) -> Rect {
+        Rect::FULL
+    }
+
+    fn move_(&mut self, _pos: crate::Pos) {}
+}
+
+#[cfg(test)]
+impl CircuitComponent for Clocked {
+    fn ports(&self) -> Vec<crate::Port> {
+        // 全部品で同じネットになる
+        vec![crate::Port { pos: crate::Pos::CENTER }]
+    }
+
+    fn clock_hz(&self) -> Option<u64> {
+        Some(self.hz)
+    }
+
+    fn tick(&mut self) -> u64 {
+        let at = Time { ticks: self.ticks, hz: self.hz };
+        self.log.borrow_mut().push((self.id, at, self.input));
+        let clocks = self.pattern[self.count % self.pattern.len()];
+        self.ticks += clocks;
+        self.count += 1;
+        clocks
+    }
+
+    fn output(&self, _port: usize) -> Option<bool> {
+        self.rise_at.map(|n| self.count >= n)
+    }
+
+    fn input(&mut self, _port: usize, level: Option<bool>) {
+        self.input = level;
+    }
+}
+
+#[cfg(test)]
+fn clocked_board(parts: &[(u64, &'static [u64], Option<usize>)]) -> (Board, TickLog) {
+    let log = TickLog::default();
+    let mut board = Board::default();
+    for (id, &(hz, pattern, rise_at)) in parts.iter().enumerate() {
+        board.components.push(CircuitComponentAdapter::new(Clocked {
+            id,
+            hz,
+            pattern,
+            ticks: 0,
+            count: 0,
+            rise_at,
+            input: None,
+            log: std::rc::Rc::clone(&log),
+        }));
+    }
+    (board, log)
+}
+
+#[test]
+fn clocked_parts_run_in_time_order() {
+    // 20MHz と、2 サイクル命令の混ざる 4MHz の MCU、32.768kHz の RTC
+    let parts: [(u64, &'static [u64], _); 3] = [
+        (20_000_000, &[4], None),
+        (4_000_000, &[4, 8], None),
+        (32_768, &[1], None),
+    ];
+    let (mut board, log) = clocked_board(&parts);
+    board.simulate(333_333);
+    board.simulate(666_667);
+
+    let log = log.borrow();
+    let count = |id| log.iter().filter(|(i, _, _)| *i == id).count();
+    // 1ms の間に動く回数: 5000 命令、4 + 8 クロックで 2 命令、32.768 クロック
+    assert_eq!((count(0), count(1), count(2)), (5000, 667, 33));
+    assert!(log.windows(2).all(|w| w[0].1 <= w[1].1));
+
+    // 区切り方によらず同じ順になる
+    let (mut once, log_once) = clocked_board(&parts);
+    once.simulate(1_000_000);
+    assert_eq!(
+        log.iter()
+            .map(|(i, t, _)| (*i, t.ticks))
+            .collect::<Vec<_>>(),
+        log_once
+            .borrow()
+            .iter()
+            .map(|(i, t, _)| (*i, t.ticks))
+            .collect::<Vec<_>>(),
+    );
+}
+
+#[test]
+fn signals_cross_clock_domains() {
+    // 20MHz の部品が 10 回目 (1.8us) に出力を上げると、4MHz の部品は 2us に動くときに見る
+    let (mut board, log) = clocked_board(&[(20_000_000, &[4], Some(10)), (4_000_000, &[4], None)]);
+    board.simulate(5_000);
+
+    let log = log.borrow();
+    let seen = log
+        .iter()
+        .filter(|(i, _, _)| *i == 1)
+        .find(|(_, _, input)| *input == Some(true))
+        .unwrap();
+    assert_eq!(seen.1, Time { ticks: 8, hz: 4_000_000 });
+    let before = log
+        .iter()
+        .filter(|(i, _, _)| *i == 1)
+        .find(|(_, t, _)| *t == Time { ticks: 4, hz: 4_000_000 })
+        .unwrap();
+    assert_eq!(before.2, Some(false));
+}
@@ -0,0 +1,47 @@
+use std::collections::VecDeque;
+
+use stk_diag::{Diagnostic, DiagnosticSink, Severity};
+
+use crate::{Drawable, Percent, Pos, Renderer, TextAlign};
+
+/// 保持しておく件数
+const CAPACITY: usize = 100;
+
+/// 画面に表示する件数
+const VISIBLE: usize = 5;
+
+/// 画面左上に出るログ
+#[derive(Default)]
+pub struct DiagnosticLog {
+    entries: VecDeque<Diagnostic>,
+}
+
+impl DiagnosticSink for DiagnosticLog {
+    fn emit(&mut self, diagnostic: Diagnostic) {
+        match diagnostic.severity {
+            Severity::Warning => tracing::warn!("{diagnostic}"),
+            Severity::Error => tracing::error!("{diagnostic}"),
+            _ => tracing::info!("{diagnostic}"),
+        }
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(diagnostic);
+    }
+}
+
+impl Drawable for DiagnosticLog {
+    fn draw(&self, ctx: &Renderer) {
+        ctx.set_text_align(TextAlign::TopLeft);
+        ctx.set_font_size(Percent::new(2.0));
+        let skip = self.entries.len().saturating_sub(VISIBLE);
+        for (i, d) in self.entries.iter().skip(skip).enumerate() {
+            let color = match d.severity {
+                Severity::Warning => "orange",
+                Severity::Error => "red",
+                _ => "gray",
+            };
+            ctx.filled_text(&d.to_string(), Pos::new(1.0, 1.0 + 3.0 * i as f64), color);
+        }
+    }
+}
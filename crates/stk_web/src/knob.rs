@@ -0,0 +1,150 @@
+//! つまみで値を変えられるアナログ部品。出力した電圧はマイコンの A/D 変換器に入る。
+
+use std::borrow::Cow;
+
+use crate::board::VDD;
+use crate::{
+    CircuitComponent, Drawable, MouseEventType, Movable, Percent, Port, Pos, Rect, Renderer, Size,
+    TextAlign,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum KnobKind {
+    /// 両端を VDD と GND につないだ可変抵抗。ワイパーの電圧を出す
+    Potentiometer,
+    /// 10kΩ の抵抗と分圧した NTC サーミスタ (10kΩ@25℃, B=3950)。つまみで温度を変える
+    Thermistor,
+}
+
+pub struct Knob {
+    rect: Rect,
+    kind: KnobKind,
+    /// つまみの位置 (0.0 から 1.0)
+    value: f64,
+}
+
+impl Knob {
+    /// サーミスタのつまみで変えられる温度の範囲 (℃)
+    const TEMP_RANGE: (f64, f64) = (-20.0, 80.0);
+
+    pub fn new(kind: KnobKind) -> Self {
+        Self {
+            rect: Rect { pos: Pos::CENTER, size: Size::new(12.0, 16.0) },
+            kind,
+            value: 0.5,
+        }
+    }
+
+    fn celsius(&self) -> f64 {
+        let (lo, hi) = Self::TEMP_RANGE;
+        lo + (hi - lo) * self.value
+    }
+
+    fn volts(&self) -> f64 {
+        match self.kind {
+            KnobKind::Potentiometer => VDD * self.value,
+            KnobKind::Thermistor => {
+                const R0: f64 = 10_000.0;
+                const T0: f64 = 298.15;
+                const B: f64 = 3950.0;
+                let t = self.celsius() + 273.15;
+                let r = R0 * (B * (1.0 / t - 1.0 / T0)).exp();
+                // VDD - 10kΩ - 出力 - サーミスタ - GND
+                VDD * r / (r + R0)
+            }
+        }
+    }
+
+    /// スライダーの溝。部品内の位置
+    fn track() -> Rect {
+        Rect::new(10.0, 65.0, 80.0, 15.0)
+    }
+}
+
+impl Movable for Knob {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn move_(&mut self, pos: Pos) {
+        self.rect.pos = pos;
+    }
+}
+
+impl CircuitComponent for Knob {
+    fn ports(&self) -> Vec<Port> {
+        vec![Port {
+            pos: Rect::FULL.map_in(self.rect, Pos::new(100.0, 30.0)),
+        }]
+    }
+
+    fn designator(&self) -> &'static str {
+        match self.kind {
+            KnobKind::Potentiometer => "VR",
+            KnobKind::Thermistor => "TH",
+        }
+    }
+
+    fn output(&self, _port: usize) -> Option<bool> {
+        Some(self.volts() > VDD / 2.0)
+    }
+
+    fn analog_output(&self, _port: usize) -> Option<f64> {
+        Some(self.volts())
+    }
+}
+
+impl Drawable for Knob {
+    fn on_mouse_event(&mut self, _ctx: &Renderer, pos: Pos, ty: MouseEventType) {
+        let MouseEventType::Click = ty else {
+            return;
+        };
+        let track = Self::track();
+        let start = Rect::FULL.map_in(self.rect, track.pos);
+        let end = Rect::FULL.map_in(
+            self.rect,
+            track.pos + Pos { x: track.size.w, y: track.size.h },
+        );
+        let area = Rect {
+            pos: start,
+            size: Size { w: end.x - start.x, h: end.y - start.y },
+        };
+        if area.contains(pos) {
+            self.value = ((pos.x - start.x).value() / area.size.w.value()).clamp(0.0, 1.0);
+        }
+    }
+
+    fn draw(&self, ctx: &Renderer) {
+        ctx.rect(self.rect, Cow::from("white"), Cow::from("black"));
+
+        let ctx = ctx.subcanbas(self.rect);
+        let (name, reading) = match self.kind {
+            KnobKind::Potentiometer => ("POT", format!("{:.0}%", self.value * 100.0)),
+            KnobKind::Thermistor => ("NTC", format!("{:.1}℃", self.celsius())),
+        };
+        ctx.set_text_align(TextAlign::Center);
+        ctx.set_font_size(Percent::new(12.0));
+        ctx.filled_text(name, Pos::new(45.0, 20.0), "black");
+        ctx.set_font_size(Percent::new(9.0));
+        ctx.filled_text(&reading, Pos::new(45.0, 40.0), "black");
+        ctx.filled_text(
+            &format!("{:.2}V", self.volts()),
+            Pos::new(45.0, 52.0),
+            "gray",
+        );
+
+        let track = Self::track();
+        ctx.rect(track, Cow::from("#dddddd"), Cow::from("black"));
+        let x = track.pos.x.value() + track.size.w.value() * self.value;
+        ctx.rect(
+            Rect::new(
+                x - 3.0,
+                track.pos.y.value() - 5.0,
+                6.0,
+                track.size.h.value() + 10.0,
+            ),
+            Cow::from("gray"),
+            Cow::from("black"),
+        );
+    }
+}
@@ -0,0 +1,106 @@
+//! 8x8 LED マトリクス。ダイナミック点灯の明るさは [`stk_led_matrix_vm`] が計算する。
+
+use std::borrow::Cow;
+
+use stk_led_matrix_vm::SIZE;
+
+use crate::{CircuitComponent, Drawable, Movable, Percent, Port, Pos, Rect, Renderer, Size};
+
+pub struct LedMatrix {
+    rect: Rect,
+    matrix: stk_led_matrix_vm::LedMatrix,
+}
+
+impl LedMatrix {
+    pub fn new() -> Self {
+        Self {
+            rect: Rect { pos: Pos::CENTER, size: Size::new(20.0, 35.0) },
+            matrix: stk_led_matrix_vm::LedMatrix::new(),
+        }
+    }
+
+    /// 部品内で LED を並べる領域
+    fn grid() -> Rect {
+        Rect::new(10.0, 0.0, 90.0, 90.0)
+    }
+
+    /// `i` 番目の行 (列) の中心。[`Self::grid`] 内の位置
+    fn center(i: usize) -> f64 {
+        (i as f64 + 0.5) * 100.0 / SIZE as f64
+    }
+
+    /// ポート 0..8 は左端の行 (アノード)、8..16 は下端の列 (カソード)
+    fn port_pos(port: usize) -> Pos {
+        if port < SIZE {
+            let y = Rect::FULL
+                .map_in(Self::grid(), Pos::new(0.0, Self::center(port)))
+                .y;
+            Pos { x: Percent::ZERO, y }
+        } else {
+            let x = Rect::FULL
+                .map_in(Self::grid(), Pos::new(Self::center(port - SIZE), 0.0))
+                .x;
+            Pos { x, y: Percent::FULL }
+        }
+    }
+}
+
+impl Movable for LedMatrix {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn move_(&mut self, pos: Pos) {
+        self.rect.pos = pos;
+    }
+}
+
+impl CircuitComponent for LedMatrix {
+    fn ports(&self) -> Vec<Port> {
+        (0..SIZE * 2)
+            .map(|i| Port {
+                pos: Rect::FULL.map_in(self.rect, Self::port_pos(i)),
+            })
+            .collect()
+    }
+
+    fn designator(&self) -> &'static str {
+        "DS"
+    }
+
+    fn simulate(&mut self, ns: u64) {
+        self.matrix.simulate(ns);
+    }
+
+    fn input(&mut self, port: usize, level: Option<bool>) {
+        if port < SIZE {
+            self.matrix.set_row(port, level);
+        } else {
+            self.matrix.set_col(port - SIZE, level);
+        }
+    }
+}
+
+impl Drawable for LedMatrix {
+    fn draw(&self, ctx: &Renderer) {
+        ctx.rect(self.rect, Cow::from("#222222"), Cow::from("black"));
+
+        let ctx = ctx.subcanbas(self.rect).subcanbas(Self::grid());
+        let frame = self.matrix.frame();
+        let pitch = 100.0 / SIZE as f64;
+        for r in 0..SIZE {
+            for c in 0..SIZE {
+                let cell = Rect::new(
+                    c as f64 * pitch + pitch * 0.15,
+                    r as f64 * pitch + pitch * 0.15,
+                    pitch * 0.7,
+                    pitch * 0.7,
+                );
+                // 消えている LED も見えるように暗い赤を下地にする
+                let b = frame.brightness(r, c).clamp(0.0, 1.0);
+                ctx.rect(cell, Cow::from("#440000"), None);
+                ctx.rect(cell, Cow::from(format!("rgba(255, 40, 40, {b})")), None);
+            }
+        }
+    }
+}
@@ -1,3 +1,14 @@
+mod board;
+mod diag;
+mod knob;
+mod led_matrix;
+mod mcu;
+mod motor;
+mod perf;
+mod scope;
+mod seven_segment;
+mod transport;
+
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::future::Future;
@@ -21,6 +32,16 @@ use web_sys::{
     ResizeObserverEntry,
 };
 
+use crate::board::{Board, PortRef};
+use crate::diag::DiagnosticLog;
+use crate::knob::{Knob, KnobKind};
+use crate::led_matrix::LedMatrix;
+use crate::motor::Motor;
+use crate::perf::FrameStats;
+use crate::scope::Scope;
+use crate::seven_segment::SevenSegment;
+use crate::transport::{Simulate, Transport};
+
 fn main() {
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
 
@@ -74,7 +95,8 @@ impl Drop for ResizeObserver {
 
 struct RequestAnimationFrameFuture {
     raf_instance: Option<AnimationFrame>,
-    ready: Rc<RefCell<Option<()>>>,
+    /// rAF に渡されたタイムスタンプ (ms)
+    ready: Rc<RefCell<Option<f64>>>,
 }
 impl RequestAnimationFrameFuture {
     fn new() -> Self {
@@ -85,17 +107,17 @@ impl RequestAnimationFrameFuture {
     }
 }
 impl Future for RequestAnimationFrameFuture {
-    type Output = ();
+    type Output = f64;
 
     fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
         match this.ready.take() {
-            Some(_) => Poll::Ready(()),
+            Some(timestamp) => Poll::Ready(timestamp),
             None => {
                 let ready = Rc::clone(&this.ready);
                 let waker = ctx.waker().to_owned();
-                let instance = request_animation_frame(move |_delta| {
-                    *ready.borrow_mut() = Some(());
+                let instance = request_animation_frame(move |timestamp| {
+                    *ready.borrow_mut() = Some(timestamp);
                     waker.wake();
                 });
                 this.raf_instance = Some(instance);
@@ -125,7 +147,26 @@ impl RenderLoop {
         let ctx = canvas.get_context("2d").unwrap().unwrap();
         let ctx: CanvasRenderingContext2d = ctx.dyn_into().unwrap();
 
-        let app = Rc::new(RefCell::new(App { ctx, main_scene: MainScene::new() }));
+        let search = gloo::utils::window()
+            .location()
+            .search()
+            .unwrap_or_default();
+        let embed = has_query_flag(&search, "embed");
+        let perf = has_query_flag(&search, "perf").then(FrameStats::new);
+        if embed {
+            // iframe いっぱいに広げる
+            let style = canvas.style();
+            style.set_property("width", "100vw").unwrap();
+            style.set_property("height", "100vh").unwrap();
+            style.set_property("border", "none").unwrap();
+        }
+
+        let app = Rc::new(RefCell::new(App {
+            ctx,
+            main_scene: MainScene::new(embed),
+            transport: Transport::new(),
+            perf,
+        }));
 
         let _resize_observer = ResizeObserver::new({
             let app = Rc::clone(&app);
@@ -143,6 +184,7 @@ impl RenderLoop {
         {
             use MouseEventType::*;
             me.listen("click", |app, ev| app.on_mouse_event(ev, Click));
+            me.listen("dblclick", |app, ev| app.on_mouse_event(ev, DoubleClick));
             me.listen("mouseup", |app, ev| app.on_mouse_event(ev, Up));
             me.listen("mousedown", |app, ev| app.on_mouse_event(ev, Down));
             me.listen("mousemove", |app, ev| app.on_mouse_event(ev, Move));
@@ -153,23 +195,45 @@ impl RenderLoop {
 
     async fn run(&mut self) {
         loop {
-            self.app.borrow_mut().render();
-            RequestAnimationFrameFuture::new().await;
+            let now = RequestAnimationFrameFuture::new().await;
+            let mut app = self.app.borrow_mut();
+            app.on_frame(now);
+            app.render();
         }
     }
 }
 
+/// `?embed` のようなクエリパラメータが付いているか。`search` は `location.search`
+fn has_query_flag(search: &str, key: &str) -> bool {
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .any(|kv| kv.split('=').next() == Some(key))
+}
+
+#[test]
+fn query_flag_test() {
+    assert!(has_query_flag("?embed", "embed"));
+    assert!(has_query_flag("?a=1&embed=1", "embed"));
+    assert!(!has_query_flag("?embedded=1", "embed"));
+    assert!(!has_query_flag("", "embed"));
+}
+
 #[derive(Clone, Copy, Debug)]
 enum MouseEventType {
     Up,
     Down,
     Click,
+    DoubleClick,
     Move,
 }
 
 struct App {
     ctx: CanvasRenderingContext2d,
     main_scene: MainScene,
+    transport: Transport,
+    /// `?perf` のときだけ計測する
+    perf: Option<FrameStats>,
 }
 
 impl App {
@@ -197,19 +261,50 @@ impl App {
         self.main_scene.on_mouse_event(&self.ctx, pos, ty);
     }
 
+    fn on_frame(&mut self, now_ms: f64) {
+        let start = perf::now_ms();
+        self.transport.set_playing(self.main_scene.playing);
+        self.transport
+            .on_frame(now_ms, &mut self.main_scene.circuit);
+        self.main_scene.elapsed_ns = self.transport.elapsed_ns();
+        if let Some(stats) = &mut self.perf {
+            stats.record_simulate(perf::now_ms() - start);
+        }
+    }
+
     fn render(&mut self) {
+        let start = perf::now_ms();
         self.main_scene.render(&self.ctx);
+        if let Some(stats) = &mut self.perf {
+            stats.record_render(perf::now_ms() - start);
+            stats.draw(&self.main_scene.renderer(&self.ctx));
+        }
     }
 }
 
 struct MainScene {
     i: usize,
+    /// シミュレーション上の経過時間
+    elapsed_ns: u64,
+    /// 再生・一時停止。埋め込みでも触れる唯一の操作
+    playing: bool,
+    play_button: Button,
     circuit: Circuit,
 }
 
 impl MainScene {
-    fn new() -> Self {
-        Self { i: 0, circuit: Circuit::new() }
+    /// `embed`: ブログなどに埋め込まれている。回路の編集をさせない
+    fn new(embed: bool) -> Self {
+        Self {
+            i: 0,
+            elapsed_ns: 0,
+            playing: true,
+            play_button: Button {
+                rect: Rect::new(28.0, 90.0, 10.0, 10.0),
+                text: Cow::from("Pause"),
+            },
+            circuit: Circuit::new(!embed),
+        }
     }
 
     fn renderer(&self, ctx: &CanvasRenderingContext2d) -> Renderer {
@@ -236,6 +331,13 @@ impl MainScene {
         let pos = Renderer::new(ctx).to_abs_pos(pos); // dirty...
         let ctx = self.renderer(ctx);
         let pos = ctx.to_rel_pos(pos);
+        if let MouseEventType::Click = ty {
+            if self.play_button.rect.contains(pos) {
+                self.playing = !self.playing;
+                self.play_button.text = Cow::from(if self.playing { "Pause" } else { "Play" });
+                return;
+            }
+        }
         self.circuit.on_mouse_event(&ctx, pos, ty);
     }
 
@@ -255,11 +357,12 @@ impl MainScene {
         Text {
             pos: Pos::new(0.0, 100.0),
             align: TextAlign::BottomLeft,
-            text: format!("f: {}", self.i).into(),
+            text: format!("f: {}, t: {}ms", self.i, self.elapsed_ns / 1_000_000).into(),
             size: Percent::new(2.0),
         }
         .draw(&ctx);
 
+        self.play_button.draw(&ctx);
         self.circuit.draw(&ctx);
     }
 }
@@ -365,52 +468,6 @@ impl Renderer {
         }
     }
 
-    fn debug(&self) {
-        self.rect(
-            Rect {
-                pos: Pos::ZERO,
-                size: Size { w: Percent::new(100.0), h: Percent::new(100.0) },
-            },
-            None,
-            Cow::from("blue"),
-        );
-        self.line(
-            Percent::new(0.1),
-            Pos::new(0.0, 0.0),
-            Pos::new(100.0, 100.0),
-            Cow::from("blue"),
-        );
-        self.line(
-            Percent::new(0.1),
-            Pos::new(100.0, 0.0),
-            Pos::new(0.0, 100.0),
-            Cow::from("blue"),
-        );
-        self.set_text_align(TextAlign::TopLeft);
-        self.set_font_size(Percent::new(2.0));
-        self.filled_text(
-            &format!(
-                "{}x{}->{}x{}",
-                self.canvas_size.w as u32,
-                self.canvas_size.h as u32,
-                self.size.w as u32,
-                self.size.h as u32
-            ),
-            Pos::ZERO,
-            "black",
-        );
-    }
-
-    fn translate(&self, pos: Pos) -> Self {
-        let pos = self.to_abs_pos(pos);
-        Self {
-            offset: pos,
-            size: self.size,
-            canvas_size: self.canvas_size,
-            ctx: self.ctx.clone(),
-        }
-    }
-
     fn subcanbas(&self, rect: Rect) -> Self {
         let abs_rect = self.to_abs_rect(rect);
         Self {
@@ -461,14 +518,6 @@ impl Renderer {
         self.ctx.set_text_align(align);
     }
 
-    fn measure_text(&self, text: &str) -> Size {
-        let measured = self.ctx.measure_text(text).unwrap();
-        self.to_rel_size(AbsoluteSize {
-            w: measured.width(),
-            h: measured.actual_bounding_box_descent() - measured.actual_bounding_box_ascent(),
-        })
-    }
-
     fn filled_text(&self, text: &str, pos: Pos, fill_style: impl Into<Cow<'static, str>>) {
         let pos = self.to_abs_pos(pos);
         self.ctx
@@ -602,9 +651,6 @@ impl Pos {
     fn new(x: f64, y: f64) -> Pos {
         Pos { x: Percent::new(x), y: Percent::new(y) }
     }
-    fn replace_y(self, y: Percent) -> Pos {
-        Pos { x: self.x, y }
-    }
     fn rotate(self, sheta: f64) -> Pos {
         use std::f64::consts::PI;
         let rad = sheta / 180.0 * PI;
@@ -720,7 +766,7 @@ impl MovementController {
     }
 }
 impl Drawable for MovementController {
-    fn on_mouse_event(&mut self, ctx: &Renderer, pos: Pos, ty: MouseEventType) {
+    fn on_mouse_event(&mut self, _ctx: &Renderer, pos: Pos, ty: MouseEventType) {
         let overlap = self.entries.iter_mut().find(|x| {
             // let pos = ctx.to_abs_pos(pos);
             // let ctx = ctx.translate(x.base);
@@ -761,7 +807,7 @@ impl Drawable for MovementController {
                     entry.selected = None;
                 }
             }
-            MouseEventType::Click => {}
+            MouseEventType::Click | MouseEventType::DoubleClick => {}
         }
     }
 
@@ -822,12 +868,40 @@ struct Port {
 
 trait CircuitComponent: Movable {
     fn ports(&self) -> Vec<Port>;
+    /// 自動でつけるラベルの頭 (LED なら D1, D2… の D)
+    fn designator(&self) -> &'static str {
+        "X"
+    }
+    /// [`Simulate::simulate`] と同じく、固定幅の時間で呼ばれる
+    fn simulate(&mut self, _ns: u64) {}
+    /// 自分のクロックで動く部品の周波数 (Hz)。`Some` なら [`Self::simulate`] の代わりに、
+    /// そのクロックの刻みで [`Self::tick`] が呼ばれる
+    fn clock_hz(&self) -> Option<u64> {
+        None
+    }
+    /// 1 回分動いて、次に呼ばれるまでのクロック数 (1 以上) を返す
+    fn tick(&mut self) -> u64 {
+        1
+    }
+    /// `port` に出力している値。出力していなければ `None`
+    fn output(&self, _port: usize) -> Option<bool> {
+        None
+    }
+    /// `port` がつながっているネットの値。不定なら `None`
+    fn input(&mut self, _port: usize, _level: Option<bool>) {}
+    /// `port` に出力している電圧。アナログの出力でなければ `None`
+    fn analog_output(&self, _port: usize) -> Option<f64> {
+        None
+    }
+    /// `port` がつながっているネットの電圧。不定なら `None`
+    fn analog_input(&mut self, _port: usize, _volts: Option<f64>) {}
 }
 
 #[derive(Clone, Copy)]
 struct Led {
     rect: Rect,
     port: Port,
+    lit: bool,
 }
 
 impl Led {
@@ -836,6 +910,7 @@ impl Led {
         Self {
             rect,
             port: Port { pos: Rect::FULL.map_in(rect, Pos::new(3.0, 50.0)) },
+            lit: false,
         }
     }
 }
@@ -855,6 +930,14 @@ impl CircuitComponent for Led {
     fn ports(&self) -> Vec<Port> {
         vec![self.port]
     }
+
+    fn designator(&self) -> &'static str {
+        "D"
+    }
+
+    fn input(&mut self, _port: usize, level: Option<bool>) {
+        self.lit = level == Some(true);
+    }
 }
 
 impl Drawable for Led {
@@ -866,6 +949,10 @@ impl Drawable for Led {
         let w = Percent::new(1.0);
         let c = 50.0;
 
+        if self.lit {
+            ctx.rect(Rect::FULL, Cow::from("#ffdddd"), None);
+        }
+
         let start = Pos::new(3.0, 50.0);
         let end = Pos::new(90.0, 50.0);
 
@@ -928,21 +1015,148 @@ impl Drawable for Led {
 
 struct Circuit {
     led_add_button: Button,
+    mcu_add_button: Button,
+    matrix_add_button: Button,
+    motor_add_button: Button,
+    segment_add_button: Button,
+    scope_add_button: Button,
+    wire_button: Button,
+    pot_add_button: Button,
+    ntc_add_button: Button,
     movement: MovementController,
-    components: Vec<CircuitComponentAdapter>,
+    board: Board,
+    /// 非同期に読み込まれて、まだ回路に追加されていない部品
+    pending: Rc<RefCell<Vec<CircuitComponentAdapter>>>,
+    diag: Rc<RefCell<DiagnosticLog>>,
+    /// false なら部品の追加・移動・名前の変更をさせない
+    editable: bool,
+    /// 配線モード。部品は動かさず、ポートを 2 つ順にクリックすると配線する
+    wiring: bool,
+    /// 配線モードで先にクリックされたポート
+    wire_from: Option<PortRef>,
+    /// 引きかけの配線の先
+    cursor: Pos,
 }
 
 impl Circuit {
-    fn new() -> Self {
+    fn new(editable: bool) -> Self {
         Self {
             led_add_button: Button {
                 rect: Rect::new(40.0, 90.0, 10.0, 10.0),
                 text: Cow::from("LED"),
             },
+            mcu_add_button: Button {
+                rect: Rect::new(52.0, 90.0, 10.0, 10.0),
+                text: Cow::from("MCU"),
+            },
+            matrix_add_button: Button {
+                rect: Rect::new(64.0, 90.0, 10.0, 10.0),
+                text: Cow::from("8x8"),
+            },
+            motor_add_button: Button {
+                rect: Rect::new(76.0, 90.0, 10.0, 10.0),
+                text: Cow::from("Motor"),
+            },
+            segment_add_button: Button {
+                rect: Rect::new(16.0, 90.0, 10.0, 10.0),
+                text: Cow::from("7seg"),
+            },
+            scope_add_button: Button {
+                rect: Rect::new(88.0, 79.0, 11.0, 10.0),
+                text: Cow::from("Scope"),
+            },
+            wire_button: Button {
+                rect: Rect::new(76.0, 79.0, 10.0, 10.0),
+                text: Cow::from("Wire"),
+            },
+            pot_add_button: Button {
+                rect: Rect::new(88.0, 90.0, 5.0, 10.0),
+                text: Cow::from("POT"),
+            },
+            ntc_add_button: Button {
+                rect: Rect::new(94.0, 90.0, 5.0, 10.0),
+                text: Cow::from("NTC"),
+            },
             movement: MovementController::default(),
-            components: vec![],
+            board: Board::default(),
+            pending: Rc::new(RefCell::new(vec![])),
+            diag: Rc::new(RefCell::new(DiagnosticLog::default())),
+            editable,
+            wiring: false,
+            wire_from: None,
+            cursor: Pos::ZERO,
+        }
+    }
+
+    fn push(&mut self, c: CircuitComponentAdapter) {
+        let label = self.board.next_label(c.designator());
+        self.movement.push(c.clone());
+        self.board.components.push(c);
+        self.board.labels.push(label);
+    }
+
+    fn port_at(&self, pos: Pos) -> Option<PortRef> {
+        self.board
+            .components
+            .iter()
+            .enumerate()
+            .find_map(|(component, c)| {
+                let port = c
+                    .ports()
+                    .iter()
+                    .position(|p| Self::port_rect(p.pos).contains(pos))?;
+                Some(PortRef { component, port })
+            })
+    }
+
+    /// 配線モードのクリック。1 つ目のポートから 2 つ目のポートへ配線し、ポート以外なら引きかけの
+    /// 配線をやめる
+    fn wire_at(&mut self, pos: Pos) {
+        let Some(port) = self.port_at(pos) else {
+            self.wire_from = None;
+            return;
+        };
+        match self.wire_from.take() {
+            Some(from) => self.board.connect(from, port),
+            None => self.wire_from = Some(port),
         }
     }
+
+    /// ダブルクリックされたポートのネット、なければ部品の名前を変える
+    fn rename_at(&mut self, pos: Pos) {
+        if let Some(port) = self.port_at(pos) {
+            let nets = self.board.netlist();
+            let current = nets
+                .iter()
+                .find(|net| net.ports.contains(&port))
+                .map_or("", |net| net.name.as_str());
+            if let Some(name) = gloo::dialogs::prompt("ネットの名前 (空なら自動)", Some(current))
+            {
+                self.board.rename_net(port, name.trim().to_owned());
+            }
+            return;
+        }
+
+        let Some(i) = self
+            .board
+            .components
+            .iter()
+            .rposition(|c| c.rect().contains(pos))
+        else {
+            return;
+        };
+        let current = self.board.labels[i].clone();
+        if let Some(label) = gloo::dialogs::prompt("部品のラベル", Some(&current)) {
+            let label = label.trim();
+            if !label.is_empty() {
+                self.board.labels[i] = label.to_owned();
+            }
+        }
+    }
+
+    fn port_rect(pos: Pos) -> Rect {
+        Rect::from_center(pos, Percent::new(2.0)).a16_9_to_a1_1()
+    }
 }
 
 #[derive(Clone)]
@@ -957,6 +1171,10 @@ impl Drawable for CircuitComponentAdapter {
     fn draw(&self, ctx: &Renderer) {
         self.0.borrow().draw(ctx)
     }
+
+    fn on_mouse_event(&mut self, ctx: &Renderer, pos: Pos, ty: MouseEventType) {
+        self.0.borrow_mut().on_mouse_event(ctx, pos, ty)
+    }
 }
 impl Movable for CircuitComponentAdapter {
     fn rect(&self) -> Rect {
@@ -971,39 +1189,161 @@ impl CircuitComponent for CircuitComponentAdapter {
     fn ports(&self) -> Vec<Port> {
         self.0.borrow().ports()
     }
+
+    fn designator(&self) -> &'static str {
+        self.0.borrow().designator()
+    }
+
+    fn simulate(&mut self, ns: u64) {
+        self.0.borrow_mut().simulate(ns)
+    }
+
+    fn clock_hz(&self) -> Option<u64> {
+        self.0.borrow().clock_hz()
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.0.borrow_mut().tick()
+    }
+
+    fn output(&self, port: usize) -> Option<bool> {
+        self.0.borrow().output(port)
+    }
+
+    fn input(&mut self, port: usize, level: Option<bool>) {
+        self.0.borrow_mut().input(port, level)
+    }
+
+    fn analog_output(&self, port: usize) -> Option<f64> {
+        self.0.borrow().analog_output(port)
+    }
+
+    fn analog_input(&mut self, port: usize, volts: Option<f64>) {
+        self.0.borrow_mut().analog_input(port, volts)
+    }
+}
+
+impl Simulate for Circuit {
+    fn simulate(&mut self, ns: u64) {
+        let pending = std::mem::take(&mut *self.pending.borrow_mut());
+        for c in pending {
+            self.push(c);
+        }
+
+        self.board.simulate(ns);
+    }
 }
 
 impl Drawable for Circuit {
     fn on_mouse_event(&mut self, ctx: &Renderer, pos: Pos, ty: MouseEventType) {
-        self.movement.on_mouse_event(ctx, pos, ty);
-        for c in &mut self.components {
+        if self.editable && !self.wiring {
+            self.movement.on_mouse_event(ctx, pos, ty);
+        }
+        // つまみなどの部品自体の操作は埋め込みでもできる
+        for c in &mut self.board.components {
             c.on_mouse_event(ctx, pos, ty);
         }
+        if !self.editable {
+            return;
+        }
 
+        if let MouseEventType::Move = ty {
+            self.cursor = pos;
+        }
         if let MouseEventType::Click = ty {
+            if self.wire_button.rect.contains(pos) {
+                self.wiring = !self.wiring;
+                self.wire_from = None;
+                self.wire_button.text = Cow::from(if self.wiring { "Done" } else { "Wire" });
+                return;
+            }
+            if self.wiring {
+                self.wire_at(pos);
+            }
             if self.led_add_button.rect.contains(pos) {
-                let led = CircuitComponentAdapter::new(Led::new());
-                self.movement.push(led.clone());
-                self.components.push(led);
+                self.push(CircuitComponentAdapter::new(Led::new()));
+            }
+            if self.mcu_add_button.rect.contains(pos) {
+                mcu::add_from_file(Rc::clone(&self.pending), Rc::clone(&self.diag));
+            }
+            if self.matrix_add_button.rect.contains(pos) {
+                self.push(CircuitComponentAdapter::new(LedMatrix::new()));
+            }
+            if self.motor_add_button.rect.contains(pos) {
+                self.push(CircuitComponentAdapter::new(Motor::new()));
+            }
+            if self.segment_add_button.rect.contains(pos) {
+                self.push(CircuitComponentAdapter::new(SevenSegment::new()));
             }
+            if self.scope_add_button.rect.contains(pos) {
+                self.push(CircuitComponentAdapter::new(Scope::new()));
+            }
+            if self.pot_add_button.rect.contains(pos) {
+                let knob = Knob::new(KnobKind::Potentiometer);
+                self.push(CircuitComponentAdapter::new(knob));
+            }
+            if self.ntc_add_button.rect.contains(pos) {
+                let knob = Knob::new(KnobKind::Thermistor);
+                self.push(CircuitComponentAdapter::new(knob));
+            }
+        }
+        if let MouseEventType::DoubleClick = ty {
+            self.rename_at(pos);
         }
     }
 
     fn draw(&self, ctx: &Renderer) {
+        self.diag.borrow().draw(ctx);
         self.movement.draw(ctx);
-        self.led_add_button.draw(ctx);
+        if self.editable {
+            self.led_add_button.draw(ctx);
+            self.mcu_add_button.draw(ctx);
+            self.matrix_add_button.draw(ctx);
+            self.motor_add_button.draw(ctx);
+            self.segment_add_button.draw(ctx);
+            self.scope_add_button.draw(ctx);
+            self.wire_button.draw(ctx);
+            self.pot_add_button.draw(ctx);
+            self.ntc_add_button.draw(ctx);
+        }
 
-        for comp in &self.components {
+        for (comp, label) in self.board.components.iter().zip(&self.board.labels) {
             comp.draw(ctx);
 
             ctx.set_line_width(Percent::new(0.2));
             let ports = comp.ports();
             for p in ports {
-                ctx.rect(
-                    Rect::from_center(p.pos, Percent::new(2.0)).a16_9_to_a1_1(),
-                    Cow::from("white"),
-                    Cow::from("red"),
-                );
+                ctx.rect(Self::port_rect(p.pos), Cow::from("white"), Cow::from("red"));
+            }
+
+            // 部品の左上に出す
+            ctx.set_text_align(TextAlign::BottomLeft);
+            ctx.set_font_size(Percent::new(2.0));
+            ctx.filled_text(label, comp.rect().pos, Cow::from("black"));
+        }
+
+        // 配線はポートの今の位置どうしを結ぶ
+        for wire in &self.board.wires {
+            let (from, to) = (self.board.port_pos(wire.from), self.board.port_pos(wire.to));
+            ctx.line(Percent::new(0.3), from, to, "darkgreen");
+        }
+        if let Some(from) = self.wire_from {
+            let _restore = ctx.dotted_line();
+            ctx.line(
+                Percent::new(0.3),
+                self.board.port_pos(from),
+                self.cursor,
+                "darkgreen",
+            );
+        }
+
+        // ネットの名前はポートの右下に出す
+        ctx.set_text_align(TextAlign::TopLeft);
+        ctx.set_font_size(Percent::new(1.5));
+        for net in self.board.netlist() {
+            for p in &net.ports {
+                let pos = self.board.port_pos(*p);
+                ctx.filled_text(&net.name, pos + Pos::new(1.0, 1.0), Cow::from("blue"));
             }
         }
     }
@@ -0,0 +1,267 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo::events::EventListener;
+use gloo::utils::document;
+use stk_diag::{Diagnostic, DiagnosticSink};
+use stk_pic_vm::hex::{decode_intel_hex, MemoryImage};
+use stk_pic_vm::sim::Simulation;
+use stk_pic_vm::vm::p16f88::{Pin, PortId, RunExit, CLOCKS_PER_CYCLE, FLASH_BYTES, P16F88};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+
+use crate::diag::DiagnosticLog;
+use crate::{
+    CircuitComponent, CircuitComponentAdapter, Drawable, Movable, Percent, Port, Pos, Rect,
+    Renderer, Size, TextAlign,
+};
+
+/// PIC16F88 (DIP-18) のピン配置。電源ピンは `None`
+const PINOUT: [Option<Pin>; 18] = {
+    const fn ra(bit: u8) -> Option<Pin> {
+        Some(Pin { port: PortId::A, bit })
+    }
+    const fn rb(bit: u8) -> Option<Pin> {
+        Some(Pin { port: PortId::B, bit })
+    }
+    [
+        ra(2),
+        ra(3),
+        ra(4),
+        ra(5),
+        None,
+        rb(0),
+        rb(1),
+        rb(2),
+        rb(3), //
+        rb(4),
+        rb(5),
+        rb(6),
+        rb(7),
+        None,
+        ra(6),
+        ra(7),
+        ra(0),
+        ra(1),
+    ]
+};
+
+pub struct Mcu {
+    rect: Rect,
+    name: String,
+    /// MCU 1 つだけのシミュレーション。実行中にエラーが起きたら一時停止したままになる
+    sim: Simulation,
+    diag: Rc<RefCell<DiagnosticLog>>,
+}
+
+impl Mcu {
+    fn new(
+        name: String,
+        memory: MemoryImage,
+        clock_hz: u64,
+        diag: Rc<RefCell<DiagnosticLog>>,
+    ) -> Self {
+        if memory.program_len() > FLASH_BYTES {
+            diag.borrow_mut().emit(Diagnostic::warning(
+                &name,
+                "program is too large; truncating",
+            ));
+        }
+        let flash = memory.program_flash(FLASH_BYTES);
+
+        let mut vm = P16F88::new(flash.try_into().unwrap());
+        vm.set_clock_hz(clock_hz);
+        let mut sim = Simulation::new();
+        sim.add_mcu(vm);
+
+        Self {
+            rect: Rect {
+                pos: Pos::new(30.0, 30.0),
+                size: Size::new(12.0, 40.0),
+            },
+            name,
+            sim,
+            diag,
+        }
+    }
+
+    /// I/O ピンの (ピン番号 - 1, ピン)
+    fn io_pins() -> impl Iterator<Item = (usize, Pin)> {
+        PINOUT
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.map(|p| (i, p)))
+    }
+
+    /// ピン番号 - 1 から、部品内での位置を求める
+    fn pin_pos(index: usize) -> Pos {
+        let half = PINOUT.len() / 2;
+        let pitch = 100.0 / half as f64;
+        if index < half {
+            Pos::new(0.0, pitch * (index as f64 + 0.5))
+        } else {
+            Pos::new(100.0, pitch * ((PINOUT.len() - 1 - index) as f64 + 0.5))
+        }
+    }
+
+    fn vm(&self) -> &P16F88 {
+        self.sim.mcus().mcu(0)
+    }
+
+    fn vm_mut(&mut self) -> &mut P16F88 {
+        self.sim.mcus_mut().mcu_mut(0)
+    }
+
+    fn pin(port: usize) -> Pin {
+        Self::io_pins().nth(port).expect("port out of range").1
+    }
+}
+
+impl Movable for Mcu {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn move_(&mut self, pos: Pos) {
+        self.rect.pos = pos;
+    }
+}
+
+impl CircuitComponent for Mcu {
+    fn ports(&self) -> Vec<Port> {
+        Self::io_pins()
+            .map(|(i, _)| Port {
+                pos: Rect::FULL.map_in(self.rect, Self::pin_pos(i)),
+            })
+            .collect()
+    }
+
+    fn designator(&self) -> &'static str {
+        "U"
+    }
+
+    fn clock_hz(&self) -> Option<u64> {
+        Some(self.vm().clock_hz())
+    }
+
+    /// 1 命令実行する。止まっていても 1 命令サイクル分の時間は進める
+    fn tick(&mut self) -> u64 {
+        let before = self.sim.mcus().cycles(0);
+        if let Err(halted) = self.sim.step(&mut ()) {
+            if let RunExit::Error(e) = halted.exit {
+                self.diag.borrow_mut().emit(
+                    Diagnostic::error(&self.name, e.to_string())
+                        .with_cycle(before)
+                        .with_pc(self.vm().pc()),
+                );
+            }
+        }
+        let cycles = self.sim.mcus().cycles(0) - before;
+        cycles.max(1) * CLOCKS_PER_CYCLE
+    }
+
+    fn output(&self, port: usize) -> Option<bool> {
+        self.vm().pin_output(Self::pin(port))
+    }
+
+    fn input(&mut self, port: usize, level: Option<bool>) {
+        // TODO: 何もつながっていないピンの扱い
+        match level {
+            Some(level) => self.vm_mut().set_pin_input(Self::pin(port), level),
+            None => self.vm_mut().release_pin_input(Self::pin(port)),
+        }
+    }
+
+    fn analog_input(&mut self, port: usize, volts: Option<f64>) {
+        if let Some(channel) = Self::pin(port).analog_channel() {
+            self.vm_mut()
+                .set_analog_input(channel, volts.unwrap_or(0.0) as f32);
+        }
+    }
+}
+
+impl Drawable for Mcu {
+    fn draw(&self, ctx: &Renderer) {
+        ctx.rect(self.rect, Cow::from("white"), Cow::from("black"));
+
+        let ctx = ctx.subcanbas(self.rect);
+        for (i, pin) in Self::io_pins() {
+            let pos = Self::pin_pos(i);
+            let label_x = if pos.x.value() < 50.0 { 15.0 } else { 85.0 };
+            ctx.set_text_align(TextAlign::Center);
+            ctx.set_font_size(Percent::new(4.0));
+            ctx.filled_text(
+                &format!("{pin:?}"),
+                Pos::new(label_x, pos.y.value()),
+                "black",
+            );
+        }
+
+        ctx.set_text_align(TextAlign::Center);
+        ctx.set_font_to_fit(&self.name, Percent::new(40.0));
+        ctx.filled_text(&self.name, Pos::CENTER, "black");
+        ctx.set_font_size(Percent::new(4.0));
+        ctx.filled_text(
+            &format!("{}MHz", self.vm().clock_hz() as f64 / 1_000_000.0),
+            Pos::new(50.0, 60.0),
+            "gray",
+        );
+        // エラーの詳細はログに出している
+        if self.sim.is_paused() {
+            ctx.filled_text("halted", Pos::new(50.0, 70.0), "red");
+        }
+    }
+}
+
+/// HEX ファイルとクロック周波数をユーザーに選んでもらい、読み込めたら `pending` に積む
+pub fn add_from_file(
+    pending: Rc<RefCell<Vec<CircuitComponentAdapter>>>,
+    diag: Rc<RefCell<DiagnosticLog>>,
+) {
+    let input: HtmlInputElement = document()
+        .create_element("input")
+        .unwrap()
+        .dyn_into()
+        .unwrap();
+    input.set_type("file");
+    input.set_accept(".hex");
+
+    EventListener::once(&input, "change", {
+        let input = input.clone();
+        move |_| {
+            let Some(file) = input.files().and_then(|x| x.get(0)) else {
+                return;
+            };
+            let clock = gloo::dialogs::prompt("clock (Hz)", Some("20000000"));
+            let Some(clock_hz) = clock.and_then(|x| x.trim().parse::<u64>().ok()) else {
+                gloo::dialogs::alert("invalid clock");
+                return;
+            };
+
+            spawn_local(async move {
+                let file = gloo::file::File::from(file);
+                let bytes = match gloo::file::futures::read_as_bytes(&file).await {
+                    Ok(x) => x,
+                    Err(e) => {
+                        gloo::dialogs::alert(&format!("failed to read {}: {e}", file.name()));
+                        return;
+                    }
+                };
+                let memory = match decode_intel_hex(bytes.as_slice()) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        gloo::dialogs::alert(&format!("failed to decode {}: {e}", file.name()));
+                        return;
+                    }
+                };
+                let mcu = Mcu::new(file.name(), memory, clock_hz, diag);
+                pending.borrow_mut().push(CircuitComponentAdapter::new(mcu));
+            });
+        }
+    })
+    .forget();
+
+    input.click();
+}
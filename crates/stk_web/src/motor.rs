@@ -0,0 +1,91 @@
+//! H ブリッジつきの DC モーター。回転の計算は [`stk_dc_motor_vm`] がする。
+
+use std::borrow::Cow;
+
+use stk_dc_motor_vm::DcMotor;
+
+use crate::{
+    CircuitComponent, Drawable, Movable, Percent, Port, Pos, Rect, Renderer, Size, TextAlign,
+};
+
+pub struct Motor {
+    rect: Rect,
+    motor: DcMotor,
+    /// H ブリッジの入力 A, B
+    inputs: [Option<bool>; 2],
+}
+
+impl Motor {
+    pub fn new() -> Self {
+        Self {
+            rect: Rect { pos: Pos::CENTER, size: Size::new(12.0, 20.0) },
+            motor: DcMotor::new(),
+            inputs: [None; 2],
+        }
+    }
+
+    fn port_pos(port: usize) -> Pos {
+        Pos::new(0.0, [30.0, 70.0][port])
+    }
+}
+
+impl Movable for Motor {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn move_(&mut self, pos: Pos) {
+        self.rect.pos = pos;
+    }
+}
+
+impl CircuitComponent for Motor {
+    fn ports(&self) -> Vec<Port> {
+        (0..2)
+            .map(|i| Port {
+                pos: Rect::FULL.map_in(self.rect, Self::port_pos(i)),
+            })
+            .collect()
+    }
+
+    fn designator(&self) -> &'static str {
+        "M"
+    }
+
+    fn simulate(&mut self, ns: u64) {
+        self.motor.simulate(ns);
+    }
+
+    fn input(&mut self, port: usize, level: Option<bool>) {
+        self.inputs[port] = level;
+        self.motor.set_inputs(self.inputs[0], self.inputs[1]);
+    }
+}
+
+impl Drawable for Motor {
+    fn draw(&self, ctx: &Renderer) {
+        ctx.rect(self.rect, Cow::from("white"), Cow::from("black"));
+
+        let ctx = ctx.subcanbas(self.rect);
+        ctx.set_text_align(TextAlign::Center);
+        ctx.set_font_size(Percent::new(8.0));
+        ctx.filled_text("A", Pos::new(12.0, 30.0), "black");
+        ctx.filled_text("B", Pos::new(12.0, 70.0), "black");
+
+        // 羽根の代わりに、軸の角度に合わせて回る十字を描く
+        let center = Pos::new(55.0, 45.0);
+        for i in 0..4 {
+            let arm = Pos::new(30.0, 0.0).rotate(self.motor.angle() + i as f64 * 90.0);
+            // 部品は縦長なので、縦方向を縮めて円に見せる
+            let arm = Pos::new(arm.x.value(), arm.y.value() * 0.6);
+            ctx.line(Percent::new(1.0), center, center + arm, "black");
+        }
+
+        ctx.set_font_size(Percent::new(6.0));
+        ctx.filled_text(
+            &format!("{:.0} rpm", self.motor.rpm()),
+            Pos::new(55.0, 90.0),
+            "gray",
+        );
+    }
+}
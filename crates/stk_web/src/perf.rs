@@ -0,0 +1,97 @@
+//! フレームごとの処理時間の計測
+//!
+//! `?perf` を付けて開くと有効になり、描画とシミュレーションにかかった時間の分布を画面右上に出す。
+//! 作り直しの前後で遅くなっていないかを数字で比べるためのもの。
+
+use hdrhistogram::Histogram;
+
+use crate::{Drawable, Percent, Pos, Renderer, TextAlign};
+
+/// これより長い記録は丸める (μs)
+const MAX_US: u64 = 10_000_000;
+
+/// ページを開いてからの時間 (ms)
+pub fn now_ms() -> f64 {
+    gloo::utils::window()
+        .performance()
+        .expect("performance is not available")
+        .now()
+}
+
+pub struct FrameStats {
+    /// 1 フレームの描画にかかった時間 (μs)
+    render: Histogram<u64>,
+    /// 1 フレーム分のシミュレーションにかかった時間 (μs)
+    simulate: Histogram<u64>,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        let histogram = || Histogram::new_with_bounds(1, MAX_US, 3).unwrap();
+        Self { render: histogram(), simulate: histogram() }
+    }
+
+    pub fn record_render(&mut self, ms: f64) {
+        self.render.saturating_record(to_us(ms));
+    }
+
+    pub fn record_simulate(&mut self, ms: f64) {
+        self.simulate.saturating_record(to_us(ms));
+    }
+
+    /// 1 行に 1 つずつ。記録がなければ空
+    pub fn report(&self) -> Vec<String> {
+        [("render", &self.render), ("simulate", &self.simulate)]
+            .into_iter()
+            .filter(|(_, h)| !h.is_empty())
+            .map(|(name, h)| {
+                let ms = |us: u64| us as f64 / 1000.0;
+                format!(
+                    "{name}: p50 {:.1}ms, p99 {:.1}ms, max {:.1}ms ({} frames)",
+                    ms(h.value_at_quantile(0.5)),
+                    ms(h.value_at_quantile(0.99)),
+                    ms(h.max()),
+                    h.len(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn to_us(ms: f64) -> u64 {
+    (ms * 1000.0).round().max(1.0) as u64
+}
+
+impl Drawable for FrameStats {
+    fn draw(&self, ctx: &Renderer) {
+        ctx.set_text_align(TextAlign::TopLeft);
+        ctx.set_font_size(Percent::new(2.0));
+        for (i, line) in self.report().iter().enumerate() {
+            ctx.filled_text(line, Pos::new(65.0, 1.0 + 3.0 * i as f64), "gray");
+        }
+    }
+}
+
+#[test]
+fn report_percentiles() {
+    let mut stats = FrameStats::new();
+    assert!(stats.report().is_empty());
+
+    for i in 1..=100 {
+        stats.record_render(i as f64 / 10.0);
+    }
+    stats.record_simulate(0.0);
+    assert_eq!(
+        stats.report(),
+        [
+            "render: p50 5.0ms, p99 9.9ms, max 10.0ms (100 frames)",
+            "simulate: p50 0.0ms, p99 0.0ms, max 0.0ms (1 frames)",
+        ]
+    );
+}
@@ -0,0 +1,123 @@
+//! 1 チャンネルのオシロスコープ。パルス幅・周期の測定は [`stk_pwm_vm`] がして、最新の数周期の
+//! 波形と測定値を表示する。
+
+use std::borrow::Cow;
+
+use stk_pwm_vm::Analyzer;
+
+use crate::{
+    CircuitComponent, Drawable, Movable, Percent, Port, Pos, Rect, Renderer, Size, TextAlign,
+};
+
+/// 画面に並べる周期の数
+const PERIODS: u64 = 3;
+
+pub struct Scope {
+    rect: Rect,
+    analyzer: Analyzer,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self {
+            rect: Rect { pos: Pos::CENTER, size: Size::new(30.0, 20.0) },
+            analyzer: Analyzer::new(),
+        }
+    }
+
+    /// 最新の [`PERIODS`] 周期ぶんの (時刻, レベル) の変化点。時刻は画面の左端から
+    fn edges(&self) -> Option<(u64, Vec<(u64, bool)>)> {
+        let latest = self.analyzer.latest()?;
+        let end = latest.start_ns + latest.period_ns;
+        let span = PERIODS * latest.period_ns;
+        let from = end.saturating_sub(span);
+        let mut edges = vec![(0, false)];
+        for p in self
+            .analyzer
+            .pulses()
+            .filter(|p| p.start_ns + p.period_ns > from)
+        {
+            edges.push((p.start_ns.saturating_sub(from), true));
+            edges.push(((p.start_ns + p.width_ns).saturating_sub(from), false));
+        }
+        Some((span, edges))
+    }
+}
+
+impl Movable for Scope {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn move_(&mut self, pos: Pos) {
+        self.rect.pos = pos;
+    }
+}
+
+impl CircuitComponent for Scope {
+    fn ports(&self) -> Vec<Port> {
+        vec![Port {
+            pos: Rect::FULL.map_in(self.rect, Pos::new(0.0, 50.0)),
+        }]
+    }
+
+    fn designator(&self) -> &'static str {
+        "OSC"
+    }
+
+    fn simulate(&mut self, ns: u64) {
+        self.analyzer.simulate(ns);
+    }
+
+    fn input(&mut self, _port: usize, level: Option<bool>) {
+        self.analyzer.input(level);
+    }
+}
+
+impl Drawable for Scope {
+    fn draw(&self, ctx: &Renderer) {
+        ctx.rect(self.rect, Cow::from("#002200"), Cow::from("black"));
+
+        let ctx = ctx.subcanbas(self.rect);
+        let (high, low) = (20.0, 60.0);
+        if let Some((span, edges)) = self.edges() {
+            let x = |t: u64| Percent::new(5.0 + 90.0 * t as f64 / span as f64);
+            let y = |level: bool| Percent::new(if level { high } else { low });
+            for (i, &(t, level)) in edges.iter().enumerate() {
+                // 次の変化点か右端まで水平線を引き、変化点では縦線でつなぐ
+                let next = edges.get(i + 1).map_or(span, |e| e.0);
+                let a = Pos { x: x(t), y: y(level) };
+                let b = Pos { x: x(next), y: y(level) };
+                ctx.line(Percent::new(1.0), a, b, "lime");
+                if i > 0 {
+                    let c = Pos { x: x(t), y: y(!level) };
+                    ctx.line(Percent::new(1.0), c, a, "lime");
+                }
+            }
+        } else {
+            // まだ周期が測れていないので、今のレベルをそのまま描く
+            let level = if self.analyzer.level() { high } else { low };
+            ctx.line(
+                Percent::new(1.0),
+                Pos::new(5.0, level),
+                Pos::new(95.0, level),
+                "lime",
+            );
+        }
+
+        ctx.set_text_align(TextAlign::Center);
+        ctx.set_font_size(Percent::new(12.0));
+        let text = match self.analyzer.stats() {
+            Some(stats) => {
+                let hz = 1e9 / stats.period_ns.mean;
+                let width_us = stats.width_ns.mean / 1000.0;
+                format!(
+                    "{:.1}% {hz:.0} Hz {width_us:.0} us",
+                    stats.duty.mean * 100.0
+                )
+            }
+            None => "--".to_string(),
+        };
+        ctx.filled_text(&text, Pos::new(50.0, 85.0), "lime");
+    }
+}
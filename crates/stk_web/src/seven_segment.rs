@@ -0,0 +1,105 @@
+//! 4 桁の 7 セグメント LED (カソードコモン)。ダイナミック点灯の明るさは [`stk_seven_segment_vm`]
+//! が計算する。
+
+use std::borrow::Cow;
+
+use stk_seven_segment_vm::SEGMENTS;
+
+use crate::{CircuitComponent, Drawable, Movable, Percent, Port, Pos, Rect, Renderer, Size};
+
+const DIGITS: usize = 4;
+
+pub struct SevenSegment {
+    rect: Rect,
+    display: stk_seven_segment_vm::SevenSegment,
+}
+
+impl SevenSegment {
+    pub fn new() -> Self {
+        Self {
+            rect: Rect { pos: Pos::CENTER, size: Size::new(30.0, 20.0) },
+            display: stk_seven_segment_vm::SevenSegment::new(DIGITS),
+        }
+    }
+
+    /// ポート 0..8 は上端のセグメント a..g, dp、8..12 は下端の各桁のコモン
+    fn port_pos(port: usize) -> Pos {
+        if port < SEGMENTS {
+            let x = (port as f64 + 0.5) * 100.0 / SEGMENTS as f64;
+            Pos { x: Percent::new(x), y: Percent::ZERO }
+        } else {
+            let x = (port - SEGMENTS) as f64 * 25.0 + 12.5;
+            Pos { x: Percent::new(x), y: Percent::FULL }
+        }
+    }
+
+    /// 1 桁の中でのセグメント a..g, dp の位置
+    fn segment_rect(segment: usize) -> Rect {
+        match segment {
+            0 => Rect::new(20.0, 10.0, 50.0, 8.0),
+            1 => Rect::new(70.0, 14.0, 10.0, 34.0),
+            2 => Rect::new(70.0, 52.0, 10.0, 34.0),
+            3 => Rect::new(20.0, 82.0, 50.0, 8.0),
+            4 => Rect::new(10.0, 52.0, 10.0, 34.0),
+            5 => Rect::new(10.0, 14.0, 10.0, 34.0),
+            6 => Rect::new(20.0, 46.0, 50.0, 8.0),
+            _ => Rect::new(84.0, 82.0, 8.0, 8.0),
+        }
+    }
+}
+
+impl Movable for SevenSegment {
+    fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    fn move_(&mut self, pos: Pos) {
+        self.rect.pos = pos;
+    }
+}
+
+impl CircuitComponent for SevenSegment {
+    fn ports(&self) -> Vec<Port> {
+        (0..SEGMENTS + DIGITS)
+            .map(|i| Port {
+                pos: Rect::FULL.map_in(self.rect, Self::port_pos(i)),
+            })
+            .collect()
+    }
+
+    fn designator(&self) -> &'static str {
+        "DS"
+    }
+
+    fn simulate(&mut self, ns: u64) {
+        self.display.simulate(ns);
+    }
+
+    fn input(&mut self, port: usize, level: Option<bool>) {
+        if port < SEGMENTS {
+            self.display.set_segment(port, level);
+        } else {
+            self.display.set_common(port - SEGMENTS, level);
+        }
+    }
+}
+
+impl Drawable for SevenSegment {
+    fn draw(&self, ctx: &Renderer) {
+        ctx.rect(self.rect, Cow::from("#222222"), Cow::from("black"));
+
+        let ctx = ctx.subcanbas(self.rect);
+        let frame = self.display.frame();
+        let pitch = 100.0 / DIGITS as f64;
+        for d in 0..DIGITS {
+            let digit = ctx.subcanbas(Rect::new(d as f64 * pitch, 10.0, pitch, 80.0));
+            for s in 0..SEGMENTS {
+                let cell = Self::segment_rect(s);
+                // 消えているセグメントも見えるように暗い赤を下地にする
+                let b = frame.brightness(d, s).clamp(0.0, 1.0);
+                digit.rect(cell, Cow::from("#440000"), None);
+                digit.rect(cell, Cow::from(format!("rgba(255, 40, 40, {b})")), None);
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! シミュレーションの進行管理
+//!
+//! rAF の呼ばれる間隔はモニタのリフレッシュレートや負荷によって変わるので、
+//! その差分をそのままシミュレーションに渡すと結果がフレームレート依存になってしまう。
+//! ここでは経過時間を貯めておいて、常に同じ大きさ ([`STEP_NS`]) の塊で進める。
+
+/// 1 ステップで進めるシミュレーション上の時間
+pub const STEP_NS: u64 = 1_000_000;
+
+/// 1 フレームで実行する最大ステップ数。
+/// これを超える遅れは切り捨てる (タブが裏に回った後などに固まらないように)
+const MAX_STEPS_PER_FRAME: u32 = 50;
+
+/// シミュレーションで進められるもの
+pub trait Simulate {
+    /// シミュレーション上の時間を `ns` だけ進める。
+    /// 常に [`STEP_NS`] で呼ばれる。
+    fn simulate(&mut self, ns: u64);
+}
+
+#[derive(Debug)]
+pub struct FixedTimestep {
+    /// 前回のフレームのタイムスタンプ (ms)
+    last: Option<f64>,
+    /// まだ消化していないシミュレーション時間 (ns)
+    accumulated: f64,
+}
+
+impl FixedTimestep {
+    pub fn new() -> Self {
+        Self { last: None, accumulated: 0.0 }
+    }
+
+    /// rAF のタイムスタンプを受け取り、このフレームで実行すべきステップ数を返す
+    pub fn advance(&mut self, now_ms: f64) -> u32 {
+        let Some(last) = self.last.replace(now_ms) else {
+            return 0;
+        };
+
+        let elapsed_ms = (now_ms - last).max(0.0);
+        self.accumulated += elapsed_ms * 1_000_000.0;
+
+        let steps = (self.accumulated / STEP_NS as f64).floor();
+        if steps > MAX_STEPS_PER_FRAME as f64 {
+            let dropped = steps - MAX_STEPS_PER_FRAME as f64;
+            tracing::warn!("simulation is falling behind; dropping {dropped} steps");
+            self.accumulated = 0.0;
+            return MAX_STEPS_PER_FRAME;
+        }
+
+        self.accumulated -= steps * STEP_NS as f64;
+        steps as u32
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct Transport {
+    timestep: FixedTimestep,
+    /// これまでに実行したステップ数
+    steps: u64,
+    playing: bool,
+}
+
+impl Transport {
+    /// 再生中の状態で作る
+    pub fn new() -> Self {
+        Self {
+            timestep: FixedTimestep::new(),
+            steps: 0,
+            playing: true,
+        }
+    }
+
+    /// 止めている間の時間はシミュレーションに渡さない
+    pub fn set_playing(&mut self, playing: bool) {
+        if self.playing != playing {
+            self.timestep = FixedTimestep::new();
+        }
+        self.playing = playing;
+    }
+
+    /// シミュレーション開始からの経過時間 (ns)
+    pub fn elapsed_ns(&self) -> u64 {
+        self.steps * STEP_NS
+    }
+
+    /// フレームごとに呼ぶ
+    pub fn on_frame(&mut self, now_ms: f64, target: &mut impl Simulate) {
+        if !self.playing {
+            return;
+        }
+        for _ in 0..self.timestep.advance(now_ms) {
+            target.simulate(STEP_NS);
+            self.steps += 1;
+        }
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn fixed_timestep_is_frame_rate_independent() {
+    let run = |frame_ms: f64| {
+        let mut t = FixedTimestep::new();
+        let mut steps = 0;
+        let mut now = 0.0;
+        while now <= 1000.0 {
+            steps += t.advance(now);
+            now += frame_ms;
+        }
+        steps
+    };
+
+    // フレームレートが違っても 1 秒で同じだけ進む
+    assert_eq!(run(1000.0 / 64.0), 1000);
+    assert_eq!(run(1000.0 / 125.0), 1000);
+}
+
+#[test]
+fn paused_time_is_not_simulated() {
+    struct Counter(u64);
+    impl Simulate for Counter {
+        fn simulate(&mut self, ns: u64) {
+            self.0 += ns;
+        }
+    }
+
+    let mut t = Transport::new();
+    let mut c = Counter(0);
+    t.on_frame(0.0, &mut c);
+    t.on_frame(10.0, &mut c);
+    t.set_playing(false);
+    t.on_frame(500.0, &mut c);
+    t.set_playing(true);
+    t.on_frame(1000.0, &mut c);
+    t.on_frame(1010.0, &mut c);
+
+    assert_eq!(c.0, 20 * STEP_NS);
+    assert_eq!(t.elapsed_ns(), c.0);
+}
@@ -0,0 +1,113 @@
+//! on-disk cache of pass outputs, keyed by the hash of the pass inputs. in watch builds most
+//! files don't change between runs, so most passes can be skipped.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use crate::sys::fs;
+
+pub struct Cache {
+    /// `None` when disabled by `--no-cache`
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    /// least recently used entries are removed above this
+    pub const MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+    pub async fn open(dir: &Path, enabled: bool) -> Result<Self> {
+        if !enabled {
+            return Ok(Self { dir: None });
+        }
+        fs::mkdir_all(dir).await?;
+        Ok(Self { dir: Some(dir.to_owned()) })
+    }
+
+    /// cache key of `pass` run on `inputs`. includes the crate version so that changes to the
+    /// passes invalidate old entries on release.
+    pub fn key(pass: &str, inputs: &[&[u8]]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update(pass);
+        for input in inputs {
+            hasher.update((input.len() as u64).to_le_bytes());
+            hasher.update(input);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Vec<Vec<u8>>> {
+        let path = self.dir.as_ref()?.join(key);
+        let bytes = fs::read_file(&path).await.ok()?;
+        // bump the mtime so that pruning sees this entry as recently used
+        fs::touch(&path).await.ok()?;
+        decode(&bytes)
+    }
+
+    pub async fn put(&self, key: &str, outputs: &[&[u8]]) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        fs::write_file(&dir.join(key), &encode(outputs)).await
+    }
+
+    /// removes the least recently used entries until the cache fits in [`Self::MAX_BYTES`]
+    pub async fn prune(&self) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        let mut entries = vec![];
+        for path in fs::read_dir(dir).await? {
+            let stat = fs::stat(&path).await?;
+            entries.push((stat.modified_ms, stat.size, path));
+        }
+        entries.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut total = 0;
+        for (_, size, path) in entries {
+            total += size;
+            if total > Self::MAX_BYTES {
+                fs::rimraf(&path).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// each part as its length (u64 LE) followed by its bytes
+fn encode(parts: &[&[u8]]) -> Vec<u8> {
+    let mut ret = vec![];
+    for part in parts {
+        ret.extend((part.len() as u64).to_le_bytes());
+        ret.extend(*part);
+    }
+    ret
+}
+
+fn decode(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut parts = vec![];
+    while !bytes.is_empty() {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let (len, rest) = bytes.split_at(8);
+        let len = usize::try_from(u64::from_le_bytes(len.try_into().unwrap())).ok()?;
+        if rest.len() < len {
+            return None;
+        }
+        let (part, rest) = rest.split_at(len);
+        parts.push(part.to_vec());
+        bytes = rest;
+    }
+    Some(parts)
+}
+
+#[test]
+fn encode_roundtrip() {
+    let parts: [&[u8]; 3] = [b"wasm", b"", b"js"];
+    assert_eq!(decode(&encode(&parts)).unwrap(), parts);
+    // truncated entries are treated as misses
+    assert_eq!(decode(&encode(&parts)[..10]), None);
+}
@@ -2,7 +2,9 @@
 #![feature(let_chains)]
 #![feature(box_patterns)]
 
+mod cache;
 mod opt_js;
+mod par;
 mod symbol;
 mod sys;
 
@@ -22,6 +24,7 @@ use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::JsValue;
 use web_sys::console;
 
+use crate::cache::Cache;
 use crate::sys::{brotli, fs};
 
 #[wasm_bindgen(start)]
@@ -32,6 +35,7 @@ async fn main() {
 
 static ORIGINAL_DIR: Lazy<&Path> = Lazy::new(|| Path::new("../../stk_web/dist"));
 static MINIFIED_DIR: Lazy<&Path> = Lazy::new(|| Path::new("../../stk_web/dist-minified"));
+static CACHE_DIR: Lazy<&Path> = Lazy::new(|| Path::new("../cache"));
 
 struct ProcessStats {
     origin_size: usize,
@@ -54,14 +58,19 @@ impl TrackedFile {
         Ok(Self { content, path, original_len })
     }
 
-    async fn minify_str<F>(&mut self, minifier: F) -> Result<()>
+    async fn minify_str<F>(&mut self, cache: &Cache, pass: &str, minifier: F) -> Result<()>
     where
         F: FnOnce(String) -> Pin<Box<dyn Future<Output = Result<String>>>>,
     {
+        let key = Cache::key(pass, &[&self.content]);
+        if let Some([cached]) = cache.get(&key).await.as_deref() {
+            self.content = cached.clone();
+            return Ok(());
+        }
         let input = String::from_utf8(self.content.clone())?;
         let updated = minifier(input).await?;
         self.content = updated.into_bytes();
-        Ok(())
+        cache.put(&key, &[&self.content]).await
     }
 
     async fn finish(self) -> Result<ProcessStats> {
@@ -104,6 +113,8 @@ async fn start() {
 
     fs::rimraf(*MINIFIED_DIR).await.unwrap();
     fs::mkdir(*MINIFIED_DIR).await.unwrap();
+    let use_cache = !sys::args().iter().any(|x| x == "--no-cache");
+    let cache = Cache::open(*CACHE_DIR, use_cache).await.unwrap();
 
     let mut file_paths = fs::read_dir(*ORIGINAL_DIR).await.unwrap();
 
@@ -152,21 +163,62 @@ async fn start() {
     // minify
     let minify_html = ac!(|x: String| { sys::minifier::html(&x).await });
     let minify_css = ac!(|x: String| { sys::minifier::css(&x).await });
-    let minify_js = ac!(|x: String| { sys::minifier::js(&opt_js::optimize_js(x)).await });
+    let minify_js = ac!(|x: String| { sys::minifier::js(&x).await });
+
+    // symbol minification rewrites the js glue, so it goes before the js passes
+    for target in &mut targets {
+        if let ProcessTarget::WasmBindgen { js, wasm } = target {
+            let key = Cache::key("symbol", &[&wasm.content, &js.content]);
+            if let Some([w, j]) = cache.get(&key).await.as_deref() {
+                (wasm.content, js.content) = (w.clone(), j.clone());
+            } else {
+                symbol::minify_symbol(&mut wasm.content, &mut js.content).await;
+                cache
+                    .put(&key, &[&wasm.content, &js.content])
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    // optimize_js doesn't touch js land, so all the files can go through it at once
+    let js_files = targets.iter_mut().filter_map(|target| match target {
+        ProcessTarget::Individual(i) if i.path.extension().unwrap() == "js" => Some(i),
+        ProcessTarget::WasmBindgen { js, .. } => Some(js),
+        ProcessTarget::Individual(_) => None,
+    });
+    let mut misses = vec![];
+    for f in js_files {
+        let key = Cache::key("optimize_js", &[&f.content]);
+        match cache.get(&key).await.as_deref() {
+            Some([cached]) => f.content = cached.clone(),
+            _ => misses.push((key, f)),
+        }
+    }
+    let sources = misses
+        .iter()
+        .map(|(_, f)| String::from_utf8(f.content.clone()).unwrap())
+        .collect();
+    let optimized = par::map(sources, opt_js::optimize_js);
+    for ((key, f), js) in misses.into_iter().zip(optimized) {
+        f.content = js.into_bytes();
+        cache.put(&key, &[&f.content]).await.unwrap();
+    }
+
     for target in &mut targets {
         match target {
             ProcessTarget::Individual(i) => match i.path.extension().unwrap().to_str().unwrap() {
-                "html" => i.minify_str(&minify_html).await.unwrap(),
-                "css" => i.minify_str(&minify_css).await.unwrap(),
-                "js" => i.minify_str(&minify_js).await.unwrap(),
+                "html" => i.minify_str(&cache, "html", &minify_html).await.unwrap(),
+                "css" => i.minify_str(&cache, "css", &minify_css).await.unwrap(),
+                "js" => i.minify_str(&cache, "terser", &minify_js).await.unwrap(),
                 _ => {}
             },
-            ProcessTarget::WasmBindgen { js, wasm } => {
-                symbol::minify_symbol(&mut wasm.content, &mut js.content).await;
-                js.minify_str(&minify_js).await.unwrap();
+            ProcessTarget::WasmBindgen { js, .. } => {
+                js.minify_str(&cache, "terser", &minify_js).await.unwrap()
             }
         }
     }
+    cache.prune().await.unwrap();
 
     // finalize and show result
     let mut files = vec![];
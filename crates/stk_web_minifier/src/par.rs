@@ -0,0 +1,21 @@
+//! parallel helpers. without the `parallel` feature everything runs on the calling thread.
+//!
+//! results always come back in input order so the output doesn't depend on scheduling.
+
+/// `items.into_iter().map(f).collect()`, spread over the rayon pool when enabled.
+pub fn map<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync + Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::iter::{IntoParallelIterator, ParallelIterator};
+        items.into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.into_iter().map(f).collect()
+    }
+}
@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use wasm_encoder::{ConstExpr, ElementSegment};
 
+use crate::par;
+
 fn map_element_items<'a>(
     items: wasmparser::ElementItems,
     functions: &'a mut Vec<u32>,
@@ -37,6 +39,24 @@ fn map_element_kind<'a>(
     }
 }
 
+/// copies a code section entry as is
+fn reencode_function(bytes: &[u8]) -> wasm_encoder::Function {
+    let mut function = wasm_encoder::Function::new([]);
+
+    pub struct Function {
+        bytes: Vec<u8>,
+    }
+    unsafe {
+        (*(&function as *const _ as *const Function as *mut Function))
+            .bytes
+            .clear();
+    }
+    assert_eq!(function.byte_len(), 0);
+
+    function.raw(bytes.iter().copied());
+    function
+}
+
 pub async fn minify_symbol(wasm: &mut Vec<u8>, js: &mut Vec<u8>) {
     let parser = wasmparser::Parser::new(0);
 
@@ -49,7 +69,7 @@ pub async fn minify_symbol(wasm: &mut Vec<u8>, js: &mut Vec<u8>) {
     let mut export_ident = MinifiedIdent::new();
 
     let mut code_section_remaining = 0;
-    let mut code_section_encoder = None;
+    let mut code_section_bodies = None;
 
     for payload in parser.parse_all(wasm) {
         let payload = payload.unwrap();
@@ -192,34 +212,23 @@ pub async fn minify_symbol(wasm: &mut Vec<u8>, js: &mut Vec<u8>) {
             wasmparser::Payload::CodeSectionStart { count, .. } => {
                 assert_eq!(code_section_remaining, 0);
                 code_section_remaining = count;
-                code_section_encoder = Some(wasm_encoder::CodeSection::new());
+                code_section_bodies = Some(Vec::with_capacity(count as usize));
             }
 
             wasmparser::Payload::CodeSectionEntry(f) => {
                 let mut reader = f.get_binary_reader();
                 let bytes = reader.read_bytes(reader.bytes_remaining()).unwrap();
+                code_section_bodies.as_mut().unwrap().push(bytes);
 
-                let mut function = wasm_encoder::Function::new([]);
-
-                pub struct Function {
-                    bytes: Vec<u8>,
-                }
-                unsafe {
-                    (*(&function as *const _ as *const Function as *mut Function))
-                        .bytes
-                        .clear();
-                }
-                assert_eq!(function.byte_len(), 0);
-
-                function.raw(bytes.iter().copied());
-
-                let encoder = code_section_encoder.as_mut().unwrap();
-                encoder.function(&function);
-
+                // functions are independent, re-encode them all at once at the end of the section
                 code_section_remaining -= 1;
                 if code_section_remaining == 0 {
-                    module.section(encoder);
-                    code_section_encoder = None;
+                    let bodies = code_section_bodies.take().unwrap();
+                    let mut encoder = wasm_encoder::CodeSection::new();
+                    for function in par::map(bodies, reencode_function) {
+                        encoder.function(&function);
+                    }
+                    module.section(&encoder);
                 }
             }
 
@@ -242,7 +251,7 @@ pub async fn minify_symbol(wasm: &mut Vec<u8>, js: &mut Vec<u8>) {
         }
     }
 
-    assert!(code_section_encoder.is_none());
+    assert!(code_section_bodies.is_none());
 
     let new_wasm = module.finish();
     let mut js_string = String::from_utf8(js.clone()).unwrap();
@@ -1,7 +1,7 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::Result;
-use js_sys::{Array, Object, Uint8Array};
+use js_sys::{Array, Date, Object, Reflect, Uint8Array};
 use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::{JsCast, JsValue};
 
@@ -38,6 +38,61 @@ pub async fn mkdir(path: &Path) -> Result<()> {
     Ok(())
 }
 
+pub async fn mkdir_all(path: &Path) -> Result<()> {
+    #[wasm_bindgen(module = "fs/promises")]
+    extern "C" {
+        #[wasm_bindgen(catch)]
+        async fn mkdir(path: &str, options: &Object) -> Result<(), JsValue>;
+    }
+
+    mkdir(path.to_str().unwrap(), &object! { recursive: true })
+        .await
+        .map_err(JsError)?;
+    Ok(())
+}
+
+pub struct Stat {
+    pub size: u64,
+    /// last modification, in milliseconds since the epoch
+    pub modified_ms: f64,
+}
+
+pub async fn stat(path: &Path) -> Result<Stat> {
+    #[wasm_bindgen(module = "fs/promises")]
+    extern "C" {
+        #[wasm_bindgen(catch)]
+        async fn stat(path: &str) -> Result<JsValue, JsValue>;
+    }
+
+    let stat = stat(path.to_str().unwrap()).await.map_err(JsError)?;
+    let number = |key| {
+        Reflect::get(&stat, &JsValue::from(key))
+            .expect("Stats should have the key")
+            .as_f64()
+            .expect("Stats field should be number")
+    };
+
+    Ok(Stat {
+        size: number("size") as u64,
+        modified_ms: number("mtimeMs"),
+    })
+}
+
+/// sets the modification time to now
+pub async fn touch(path: &Path) -> Result<()> {
+    #[wasm_bindgen(module = "fs/promises")]
+    extern "C" {
+        #[wasm_bindgen(catch)]
+        async fn utimes(path: &str, atime: &Date, mtime: &Date) -> Result<(), JsValue>;
+    }
+
+    let now = Date::new_0();
+    utimes(path.to_str().unwrap(), &now, &now)
+        .await
+        .map_err(JsError)?;
+    Ok(())
+}
+
 pub async fn read_file(path: &Path) -> Result<Vec<u8>> {
     #[wasm_bindgen(module = "fs/promises")]
     extern "C" {
@@ -1,8 +1,21 @@
 pub mod brotli;
 pub mod fs;
 pub mod minifier;
+use js_sys::{Array, Reflect};
 use wasm_bindgen::JsValue;
 
+/// `process.argv` without the node binary and the script path
+pub fn args() -> Vec<String> {
+    let process = Reflect::get(&js_sys::global(), &JsValue::from("process"))
+        .expect("should be running on node");
+    let argv = Reflect::get(&process, &JsValue::from("argv")).expect("process should have argv");
+    Array::from(&argv)
+        .iter()
+        .skip(2)
+        .map(|x| x.as_string().expect("argv should contain only strings"))
+        .collect()
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 struct JsError(JsValue);